@@ -0,0 +1,51 @@
+#!/usr/bin/env bash
+# Plays a short game between two native peers and checks that they end up in
+# the same state, see src/smoke_test.rs. Needs `matchbox_server` on the PATH,
+# free to listen on its default port, and a display (run under `xvfb-run` on
+# headless machines).
+set -euo pipefail
+
+cd "$(dirname "$0")/.."
+
+TIMEOUT_SECS="${SMOKE_TEST_TIMEOUT_SECS:-120}"
+HOST_TARGET="$(rustc -vV | sed -n 's/^host: //p')"
+WORK_DIR="$(mktemp -d)"
+
+cleanup() {
+    kill $(jobs -p) 2>/dev/null || true
+    rm -rf "$WORK_DIR"
+}
+trap cleanup EXIT
+
+cargo build --release --features native --target "$HOST_TARGET"
+GAME="target/$HOST_TARGET/release/web_ghost"
+
+matchbox_server >"$WORK_DIR/server.log" 2>&1 &
+sleep 1
+
+ROOM="smoke_test_$$"
+for peer in 1 2; do
+    WEB_GHOST_SMOKE_TEST="$ROOM" \
+        WEB_GHOST_DATA_DIR="$WORK_DIR/peer$peer" \
+        timeout "$TIMEOUT_SECS" "$GAME" >"$WORK_DIR/peer$peer.log" 2>&1 &
+done
+
+status=0
+for peer in 1 2; do
+    wait -n || status=1
+done
+
+checksum() {
+    grep '^SMOKE_TEST_CHECKSUM ' "$WORK_DIR/peer$1.log" || true
+}
+first="$(checksum 1)"
+second="$(checksum 2)"
+if [ "$status" -ne 0 ] || [ -z "$first" ] || [ "$first" != "$second" ]; then
+    echo "Smoke test failed: '$first' vs '$second'"
+    for peer in 1 2; do
+        echo "--- peer $peer ---"
+        tail -n 50 "$WORK_DIR/peer$peer.log"
+    done
+    exit 1
+fi
+echo "Smoke test passed: $first"
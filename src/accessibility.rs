@@ -0,0 +1,57 @@
+use crate::components::IsLocal;
+use bevy::prelude::*;
+use bevy_egui::{
+    egui::{CollapsingHeader, Slider, Ui},
+    EguiSettings,
+};
+use serde::{Deserialize, Serialize};
+
+/// Makes the menus readable on small high-DPI screens by scaling all of egui.
+/// The scale is a property of the local player, kept per tab in cookies like
+/// [`crate::components::UserInfo`]. Labels drawn over the world already
+/// account for egui's scale factor.
+pub struct AccessibilityPlugin;
+
+impl Plugin for AccessibilityPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(apply_ui_scale);
+    }
+}
+
+const MIN_UI_SCALE: f32 = 0.75;
+const MAX_UI_SCALE: f32 = 2.5;
+/// Coarse steps, since the slider itself moves as the scale changes
+const UI_SCALE_STEP: f64 = 0.25;
+
+#[derive(Component, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(default)]
+pub struct AccessibilitySettings {
+    pub ui_scale: f32,
+}
+
+impl Default for AccessibilitySettings {
+    fn default() -> Self {
+        Self { ui_scale: 1. }
+    }
+}
+
+fn apply_ui_scale(
+    mut egui_settings: ResMut<EguiSettings>,
+    settings: Query<&AccessibilitySettings, (Changed<AccessibilitySettings>, With<IsLocal>)>,
+) {
+    if let Some(settings) = settings.iter().next() {
+        egui_settings.scale_factor = settings.ui_scale.clamp(MIN_UI_SCALE, MAX_UI_SCALE) as f64;
+    }
+}
+
+/// The lobby's "Accessibility" section
+pub fn accessibility_ui(ui: &mut Ui, settings: &mut AccessibilitySettings) {
+    CollapsingHeader::new("Accessibility").show(ui, |ui| {
+        ui.add(
+            Slider::new(&mut settings.ui_scale, MIN_UI_SCALE..=MAX_UI_SCALE)
+                .step_by(UI_SCALE_STEP)
+                .text("UI scale"),
+        );
+        ui.weak("Tab moves between controls, Enter toggles ready");
+    });
+}
@@ -0,0 +1,347 @@
+#[cfg(feature = "presentation")]
+use crate::{
+    components::IsLocal, fixed_timestep::FixedTimestep, lobby::PracticeMode, GameState,
+    LocalPlayerHandle, MAX_PREDICTION_FRAMES,
+};
+use crate::{pickups::PickupKind, rng::SimFrame};
+use bevy::prelude::*;
+#[cfg(feature = "presentation")]
+use bevy_egui::{
+    egui::{Align2, Area, CollapsingHeader, Frame, Grid, ProgressBar, RichText, Ui},
+    EguiContexts,
+};
+#[cfg(feature = "presentation")]
+use serde::Deserialize;
+use serde::Serialize;
+
+/// Awards achievements for things the local player did in games. The
+/// simulation queues [`GameplayEvent`]s as they happen, but a rollback can
+/// undo them, so they're only passed on as [`ConfirmedGameplayEvent`]s once
+/// their frame can't be resimulated anymore. Counting only those means a kill
+/// that got rolled back is never counted, and a resimulated one is counted
+/// once. Progress is kept per tab in cookies like
+/// [`crate::components::PlayerStats`], and practice games don't count.
+#[cfg(feature = "presentation")]
+pub struct AchievementsPlugin;
+
+#[cfg(feature = "presentation")]
+impl Plugin for AchievementsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GameplayEvents>()
+            .init_resource::<AchievementToasts>()
+            .add_event::<ConfirmedGameplayEvent>()
+            .add_system(confirm_gameplay_events.in_set(OnUpdate(GameState::InGame)))
+            .add_system(
+                track_achievements
+                    .after(confirm_gameplay_events)
+                    .run_if(not(resource_exists::<PracticeMode>()))
+                    .in_set(OnUpdate(GameState::InGame)),
+            )
+            .add_system(toasts_ui)
+            .add_system(clear_gameplay_events.in_schedule(OnExit(GameState::InGame)));
+    }
+}
+
+/// Something that happened in the simulation
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GameplayEvent {
+    /// One pull of the trigger, however many bullets it fired
+    Shot {
+        shooter: usize,
+    },
+    Hit {
+        shooter: usize,
+        victim: usize,
+        damage: i32,
+    },
+    Kill {
+        shooter: usize,
+        victim: usize,
+    },
+    /// A death nobody shot, e.g. in the zone or on a hazard
+    Death {
+        victim: usize,
+    },
+    Pickup {
+        player: usize,
+        kind: PickupKind,
+    },
+    RoundEnded {
+        winner: Option<usize>,
+        /// The winner didn't take any damage
+        flawless: bool,
+        /// Set on the last round, if the match has a single winner
+        match_winner: Option<usize>,
+    },
+}
+
+#[cfg(feature = "presentation")]
+/// A [`GameplayEvent`] whose frame can't be rolled back anymore
+#[derive(Clone, Copy, Debug)]
+pub struct ConfirmedGameplayEvent {
+    pub frame: u32,
+    pub event: GameplayEvent,
+}
+
+/// Events of frames that may still be rolled back
+#[derive(Resource, Default)]
+pub struct GameplayEvents {
+    pending: Vec<(u32, GameplayEvent)>,
+}
+
+impl GameplayEvents {
+    pub fn push(&mut self, frame: &SimFrame, event: GameplayEvent) {
+        self.pending.push((frame.0, event));
+    }
+}
+
+/// Drops what earlier simulations of the current frame and later ones
+/// queued, since they're being simulated again. Runs in the rollback schedule
+/// before anything that queues events.
+pub fn forget_resimulated_events(frame: Res<SimFrame>, mut events: ResMut<GameplayEvents>) {
+    events
+        .pending
+        .retain(|(event_frame, _)| *event_frame < frame.0);
+}
+
+#[cfg(feature = "presentation")]
+pub fn confirm_gameplay_events(
+    frame: Res<SimFrame>,
+    mut events: ResMut<GameplayEvents>,
+    mut confirmed: EventWriter<ConfirmedGameplayEvent>,
+) {
+    let oldest_unconfirmed = frame.0.saturating_sub(MAX_PREDICTION_FRAMES);
+    events.pending.retain(|(event_frame, event)| {
+        if *event_frame > oldest_unconfirmed {
+            return true;
+        }
+        confirmed.send(ConfirmedGameplayEvent {
+            frame: *event_frame,
+            event: *event,
+        });
+        false
+    });
+}
+
+#[cfg(feature = "presentation")]
+/// Events still pending when the game ends were never confirmed
+fn clear_gameplay_events(mut events: ResMut<GameplayEvents>) {
+    events.pending.clear();
+}
+
+#[cfg(feature = "presentation")]
+/// Counts the achievements are judged by, and the ones already unlocked
+#[derive(Serialize, Deserialize, Default, Clone, PartialEq, Debug, Component)]
+pub struct AchievementProgress {
+    pub kills: u32,
+    pub round_wins: u32,
+    pub flawless_wins: u32,
+    pub match_wins: u32,
+    pub best_round_kills: u32,
+    /// Ids of unlocked achievements
+    pub unlocked: Vec<String>,
+}
+
+#[cfg(feature = "presentation")]
+#[derive(Clone, Copy)]
+enum Counter {
+    Kills,
+    RoundWins,
+    FlawlessWins,
+    MatchWins,
+    BestRoundKills,
+}
+
+#[cfg(feature = "presentation")]
+impl AchievementProgress {
+    fn count(&self, counter: Counter) -> u32 {
+        match counter {
+            Counter::Kills => self.kills,
+            Counter::RoundWins => self.round_wins,
+            Counter::FlawlessWins => self.flawless_wins,
+            Counter::MatchWins => self.match_wins,
+            Counter::BestRoundKills => self.best_round_kills,
+        }
+    }
+
+    fn is_unlocked(&self, achievement: &Achievement) -> bool {
+        self.unlocked.iter().any(|id| id == achievement.id)
+    }
+}
+
+#[cfg(feature = "presentation")]
+struct Achievement {
+    /// Stored in [`AchievementProgress`], so it must not change
+    id: &'static str,
+    name: &'static str,
+    description: &'static str,
+    counter: Counter,
+    goal: u32,
+}
+
+#[cfg(feature = "presentation")]
+const ACHIEVEMENTS: [Achievement; 7] = [
+    Achievement {
+        id: "first_blood",
+        name: "First Blood",
+        description: "Get a kill",
+        counter: Counter::Kills,
+        goal: 1,
+    },
+    Achievement {
+        id: "sharpshooter",
+        name: "Sharpshooter",
+        description: "Get 50 kills",
+        counter: Counter::Kills,
+        goal: 50,
+    },
+    Achievement {
+        id: "double_trouble",
+        name: "Double Trouble",
+        description: "Get 2 kills in one round",
+        counter: Counter::BestRoundKills,
+        goal: 2,
+    },
+    Achievement {
+        id: "last_one_standing",
+        name: "Last One Standing",
+        description: "Win a round",
+        counter: Counter::RoundWins,
+        goal: 1,
+    },
+    Achievement {
+        id: "untouchable",
+        name: "Untouchable",
+        description: "Win a round without taking damage",
+        counter: Counter::FlawlessWins,
+        goal: 1,
+    },
+    Achievement {
+        id: "champion",
+        name: "Champion",
+        description: "Win a match",
+        counter: Counter::MatchWins,
+        goal: 1,
+    },
+    Achievement {
+        id: "veteran",
+        name: "Veteran",
+        description: "Win 25 rounds",
+        counter: Counter::RoundWins,
+        goal: 25,
+    },
+];
+
+#[cfg(feature = "presentation")]
+/// Seconds an unlock notification stays on screen
+const TOAST_SECS: f32 = 4.;
+
+#[cfg(feature = "presentation")]
+/// Names of recently unlocked achievements, with seconds left on screen
+#[derive(Resource, Default)]
+struct AchievementToasts(Vec<(&'static str, f32)>);
+
+#[cfg(feature = "presentation")]
+fn track_achievements(
+    mut events: EventReader<ConfirmedGameplayEvent>,
+    local_handle: Option<Res<LocalPlayerHandle>>,
+    mut progress: Query<&mut AchievementProgress, With<IsLocal>>,
+    mut toasts: ResMut<AchievementToasts>,
+    mut round_kills: Local<u32>,
+) {
+    let (Some(local_handle), Ok(mut progress)) = (local_handle, progress.get_single_mut()) else {
+        events.clear();
+        return;
+    };
+    let local = local_handle.0;
+    for event in events.iter() {
+        match event.event {
+            GameplayEvent::Kill { shooter, victim } if shooter == local && victim != local => {
+                progress.kills += 1;
+                *round_kills += 1;
+                progress.best_round_kills = progress.best_round_kills.max(*round_kills);
+            }
+            GameplayEvent::RoundEnded {
+                winner,
+                flawless,
+                match_winner,
+            } => {
+                *round_kills = 0;
+                if winner == Some(local) {
+                    progress.round_wins += 1;
+                    progress.flawless_wins += flawless as u32;
+                }
+                if match_winner == Some(local) {
+                    progress.match_wins += 1;
+                }
+            }
+            _ => {}
+        }
+    }
+    for achievement in ACHIEVEMENTS.iter() {
+        if !progress.is_unlocked(achievement)
+            && progress.count(achievement.counter) >= achievement.goal
+        {
+            info!("Unlocked achievement {}", achievement.id);
+            progress.unlocked.push(achievement.id.to_string());
+            toasts.0.push((achievement.name, TOAST_SECS));
+        }
+    }
+}
+
+#[cfg(feature = "presentation")]
+fn toasts_ui(
+    mut contexts: EguiContexts,
+    timestep: Res<FixedTimestep>,
+    mut toasts: ResMut<AchievementToasts>,
+) {
+    if toasts.0.is_empty() {
+        return;
+    }
+    let delta = timestep.real_delta_seconds();
+    toasts.0.retain_mut(|(_, secs_left)| {
+        *secs_left -= delta;
+        *secs_left > 0.
+    });
+    Area::new("achievement_toasts")
+        .anchor(Align2::RIGHT_TOP, [-10., 10.])
+        .interactable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            for (name, _) in toasts.0.iter() {
+                Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.label(RichText::new("Achievement unlocked").small());
+                    ui.label(RichText::new(*name).strong());
+                });
+            }
+        });
+}
+
+/// The lobby's "Achievements" section
+#[cfg(feature = "presentation")]
+pub fn achievements_ui(ui: &mut Ui, progress: &AchievementProgress) {
+    let unlocked = ACHIEVEMENTS
+        .iter()
+        .filter(|achievement| progress.is_unlocked(achievement))
+        .count();
+    CollapsingHeader::new(format!("Achievements ({unlocked}/{})", ACHIEVEMENTS.len())).show(
+        ui,
+        |ui| {
+            Grid::new("achievements").show(ui, |ui| {
+                for achievement in ACHIEVEMENTS.iter() {
+                    ui.label(achievement.name)
+                        .on_hover_text(achievement.description);
+                    if progress.is_unlocked(achievement) {
+                        ui.label("✔");
+                    } else {
+                        let count = progress.count(achievement.counter).min(achievement.goal);
+                        ui.add(
+                            ProgressBar::new(count as f32 / achievement.goal as f32)
+                                .text(format!("{count}/{}", achievement.goal)),
+                        );
+                    }
+                    ui.end_row();
+                }
+            });
+        },
+    );
+}
@@ -0,0 +1,133 @@
+use crate::{
+    components::{Player, Position},
+    presentation_clock::PresentationClock,
+    weapons::Ammo,
+    GameState, MoveDir,
+};
+use bevy::prelude::*;
+
+/// Animates player sprites from their sprite sheet. Runs outside the rollback
+/// schedule and only reads simulation state, so it can't affect determinism.
+pub struct SpriteAnimationPlugin;
+
+impl Plugin for SpriteAnimationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(animate_players.in_set(OnUpdate(GameState::InGame)));
+    }
+}
+
+/// Frames per animation in `player_sheet.png`. Must match the atlas layout
+/// in `ImageAssets`.
+const PLAYER_SHEET_COLUMNS: usize = 4;
+
+/// Rows of the player sprite sheet, one per animation
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum PlayerAnimation {
+    #[default]
+    Idle,
+    Walk,
+    Shoot,
+}
+
+impl PlayerAnimation {
+    fn row(self) -> usize {
+        match self {
+            Self::Idle => 0,
+            Self::Walk => 1,
+            Self::Shoot => 2,
+        }
+    }
+
+    fn frames_per_second(self) -> f32 {
+        match self {
+            Self::Idle => 4.,
+            Self::Walk => 10.,
+            Self::Shoot => 16.,
+        }
+    }
+
+    /// Whether the animation holds its last frame instead of looping
+    fn holds_last_frame(self) -> bool {
+        self == Self::Shoot
+    }
+}
+
+/// Which animation a [`TextureAtlasSprite`] plays and how far along it is
+#[derive(Component, Default)]
+pub struct AnimatedSprite {
+    animation: PlayerAnimation,
+    frame: usize,
+    elapsed: f32,
+    last_position: Option<IVec2>,
+}
+
+impl AnimatedSprite {
+    fn play(&mut self, animation: PlayerAnimation) {
+        if self.animation != animation {
+            *self = Self {
+                animation,
+                last_position: self.last_position,
+                ..default()
+            };
+        }
+    }
+
+    fn advance(&mut self, seconds: f32) {
+        self.elapsed += seconds;
+        let frame_time = 1. / self.animation.frames_per_second();
+        while self.elapsed >= frame_time {
+            self.elapsed -= frame_time;
+            self.frame = if self.animation.holds_last_frame() {
+                (self.frame + 1).min(PLAYER_SHEET_COLUMNS - 1)
+            } else {
+                (self.frame + 1) % PLAYER_SHEET_COLUMNS
+            };
+        }
+    }
+
+    fn atlas_index(&self) -> usize {
+        self.animation.row() * PLAYER_SHEET_COLUMNS + self.frame
+    }
+}
+
+/// Shoot plays until a player releases fire after a shot, walk while they move, idle
+/// otherwise. Sprites face the way the player last moved.
+fn animate_players(
+    clock: Res<PresentationClock>,
+    mut players: Query<
+        (
+            &mut AnimatedSprite,
+            &mut TextureAtlasSprite,
+            &Position,
+            &MoveDir,
+            &Ammo,
+        ),
+        With<Player>,
+    >,
+) {
+    for (mut animated, mut sprite, position, move_dir, ammo) in players.iter_mut() {
+        let moved = animated
+            .last_position
+            .replace(position.0)
+            .map_or(false, |last| last != position.0);
+        let animation = if !ammo.trigger_ready {
+            PlayerAnimation::Shoot
+        } else if moved {
+            PlayerAnimation::Walk
+        } else {
+            PlayerAnimation::Idle
+        };
+        animated.play(animation);
+        animated.advance(clock.delta_seconds());
+        let index = animated.atlas_index();
+        if sprite.index != index {
+            sprite.index = index;
+        }
+        if move_dir.0.x != 0 {
+            let flip_x = move_dir.0.x < 0;
+            if sprite.flip_x != flip_x {
+                sprite.flip_x = flip_x;
+            }
+        }
+    }
+}
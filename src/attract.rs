@@ -0,0 +1,117 @@
+use crate::{
+    components::{IsLocal, IsReady, UserInfo},
+    lobby::PracticeMode,
+    rounds::RoundState,
+    GameState,
+};
+use bevy::{input::touch::Touches, prelude::*};
+use bevy_egui::{
+    egui::{Align2, Area, RichText},
+    EguiContexts,
+};
+
+/// Plays a bot-only game behind the lobby once it has been left idle for a
+/// while, for kiosk-style showcases. The game is the real simulation, run as
+/// an offline sync test session like practice, with every player a
+/// [`crate::bots::Bot`]. Any input returns to the lobby; a finished match
+/// starts the next one.
+pub struct AttractPlugin;
+
+impl Plugin for AttractPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<IdleTimer>()
+            .add_system(track_idle_time.in_set(OnUpdate(GameState::Matchmaking)))
+            .add_systems(
+                (leave_demo, attract_overlay_ui)
+                    .in_set(OnUpdate(GameState::InGame))
+                    .distributive_run_if(resource_exists::<AttractMode>()),
+            )
+            .add_system(stop_demo.in_schedule(OnExit(GameState::InGame)));
+    }
+}
+
+const IDLE_SECONDS_BEFORE_DEMO: f32 = 60.;
+pub const DEMO_BOT_COUNT: usize = 4;
+
+/// Present while the game being played is the attract mode's demo
+#[derive(Resource)]
+pub struct AttractMode;
+
+#[derive(Resource, Default)]
+struct IdleTimer {
+    idle_seconds: f32,
+}
+
+/// Who plays the bot with `handle` in the demo
+pub fn demo_bot_info(handle: usize) -> UserInfo {
+    let hue = 360. * handle as f32 / DEMO_BOT_COUNT as f32;
+    let [r, g, b, _] = Color::hsl(hue, 0.8, 0.5).as_rgba_f32();
+    UserInfo {
+        name: format!("Bot {}", handle + 1),
+        color: [r, g, b].map(|channel| (channel * 255.) as u8),
+        avatar: handle as u8,
+        cosmetics: default(),
+        class: default(),
+    }
+}
+
+fn any_input(keys: &Input<KeyCode>, mouse: &Input<MouseButton>, touches: &Touches) -> bool {
+    keys.get_just_pressed().next().is_some()
+        || mouse.get_just_pressed().next().is_some()
+        || touches.iter_just_pressed().next().is_some()
+}
+
+fn track_idle_time(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut idle: ResMut<IdleTimer>,
+    keys: Res<Input<KeyCode>>,
+    mouse: Res<Input<MouseButton>>,
+    touches: Res<Touches>,
+    local_ready: Query<&IsReady, With<IsLocal>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let ready = local_ready.get_single().map_or(false, |r| r.0);
+    if any_input(&keys, &mouse, &touches) || ready || local_ready.is_empty() {
+        idle.idle_seconds = 0.;
+        return;
+    }
+    idle.idle_seconds += time.delta_seconds();
+    if idle.idle_seconds > IDLE_SECONDS_BEFORE_DEMO {
+        info!("Lobby idle, starting attract mode");
+        commands.insert_resource(AttractMode);
+        commands.insert_resource(PracticeMode);
+        next_state.set(GameState::InGame);
+    }
+}
+
+/// Back to the lobby on any input. Once the match is over the lobby is still
+/// idle, so the next demo starts right away.
+fn leave_demo(
+    mut idle: ResMut<IdleTimer>,
+    keys: Res<Input<KeyCode>>,
+    mouse: Res<Input<MouseButton>>,
+    touches: Res<Touches>,
+    round: Res<RoundState>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if any_input(&keys, &mouse, &touches) {
+        info!("Leaving attract mode");
+        idle.idle_seconds = 0.;
+        next_state.set(GameState::Matchmaking);
+    } else if round.match_over {
+        next_state.set(GameState::Matchmaking);
+    }
+}
+
+fn attract_overlay_ui(mut contexts: EguiContexts) {
+    Area::new("attract_overlay")
+        .anchor(Align2::CENTER_BOTTOM, [0., -40.])
+        .show(contexts.ctx_mut(), |ui| {
+            ui.label(RichText::new("Press any key to return").heading());
+        });
+}
+
+fn stop_demo(mut commands: Commands) {
+    commands.remove_resource::<AttractMode>();
+}
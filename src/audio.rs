@@ -0,0 +1,133 @@
+use crate::rng::SimFrame;
+#[cfg(feature = "presentation")]
+use crate::{components::IsReady, ghost::GhostView, GameState, MAX_PREDICTION_FRAMES};
+use bevy::prelude::*;
+#[cfg(feature = "presentation")]
+use bevy::utils::HashSet;
+#[cfg(feature = "presentation")]
+use bevy_asset_loader::prelude::*;
+
+/// Plays sound effects. Simulation systems don't play sounds themselves since
+/// GGRS resimulates frames during rollbacks; they queue [`SoundEvent`]s
+/// instead, and each event is played once no matter how often its frame is
+/// simulated. Played events are also sent as bevy events for other feedback,
+/// like gamepad rumble.
+#[cfg(feature = "presentation")]
+pub struct AudioPlugin;
+
+#[cfg(feature = "presentation")]
+impl Plugin for AudioPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SoundQueue>()
+            .add_event::<SoundEvent>()
+            .add_system(play_queued_sounds.run_if(resource_exists::<SoundAssets>()))
+            .add_system(
+                play_ready_sound
+                    .run_if(resource_exists::<SoundAssets>())
+                    .in_set(OnUpdate(GameState::Matchmaking)),
+            )
+            .add_system(clear_sound_queue.in_schedule(OnExit(GameState::InGame)));
+    }
+}
+
+/// Ghosts hear the world slowed down and quieter, as if from far away
+#[cfg(feature = "presentation")]
+const GHOST_PLAYBACK: PlaybackSettings = PlaybackSettings::ONCE.with_volume(0.5).with_speed(0.7);
+
+#[cfg(feature = "presentation")]
+#[derive(AssetCollection, Resource)]
+pub struct SoundAssets {
+    #[asset(path = "sounds/fire.wav")]
+    fire: Handle<AudioSource>,
+    #[asset(path = "sounds/hit.wav")]
+    hit: Handle<AudioSource>,
+    #[asset(path = "sounds/death.wav")]
+    death: Handle<AudioSource>,
+    #[asset(path = "sounds/ready.wav")]
+    ready: Handle<AudioSource>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Sound {
+    Fire,
+    Hit,
+    Death,
+}
+
+/// A sound caused by the simulation. Resimulating a frame queues an equal
+/// event, which is how replays are recognized.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct SoundEvent {
+    pub frame: u32,
+    pub sound: Sound,
+    /// Handle of the player the sound belongs to
+    pub source: usize,
+}
+
+#[derive(Resource, Default)]
+pub struct SoundQueue {
+    queued: Vec<SoundEvent>,
+    /// Events already played that could still be simulated again
+    #[cfg(feature = "presentation")]
+    played: HashSet<SoundEvent>,
+}
+
+impl SoundQueue {
+    pub fn push(&mut self, frame: &SimFrame, sound: Sound, source: usize) {
+        self.queued.push(SoundEvent {
+            frame: frame.0,
+            sound,
+            source,
+        });
+    }
+}
+
+#[cfg(feature = "presentation")]
+fn play_queued_sounds(
+    audio: Res<Audio>,
+    sounds: Res<SoundAssets>,
+    frame: Res<SimFrame>,
+    mut queue: ResMut<SoundQueue>,
+    mut played_events: EventWriter<SoundEvent>,
+    ghost_view: Option<Res<GhostView>>,
+) {
+    if queue.queued.is_empty() {
+        return;
+    }
+    let SoundQueue { queued, played } = &mut *queue;
+    for event in queued.drain(..) {
+        if !played.insert(event) {
+            continue;
+        }
+        let handle = match event.sound {
+            Sound::Fire => &sounds.fire,
+            Sound::Hit => &sounds.hit,
+            Sound::Death => &sounds.death,
+        };
+        if ghost_view.is_some() {
+            audio.play_with_settings(handle.clone(), GHOST_PLAYBACK);
+        } else {
+            audio.play(handle.clone());
+        }
+        played_events.send(event);
+    }
+    // Frames older than the prediction window are never simulated again
+    let oldest = frame.0.saturating_sub(MAX_PREDICTION_FRAMES);
+    played.retain(|event| event.frame >= oldest);
+}
+
+#[cfg(feature = "presentation")]
+fn play_ready_sound(
+    audio: Res<Audio>,
+    sounds: Res<SoundAssets>,
+    ready: Query<&IsReady, Changed<IsReady>>,
+) {
+    if ready.iter().any(|ready| ready.0) {
+        audio.play(sounds.ready.clone());
+    }
+}
+
+#[cfg(feature = "presentation")]
+fn clear_sound_queue(mut queue: ResMut<SoundQueue>) {
+    *queue = SoundQueue::default();
+}
@@ -0,0 +1,67 @@
+use crate::{components::UserInfo, player::PLAYER_WIDTH_RF};
+use bevy::{prelude::*, sprite::MaterialMesh2dBundle};
+use bevy_egui::egui::{ComboBox, Ui};
+
+/// Avatars are emblems drawn on top of the player's square, in a color that
+/// contrasts with the player color. Index 0 is a plain square.
+pub const AVATAR_NAMES: [&str; 6] = ["Plain", "Dot", "Triangle", "Diamond", "Hexagon", "Pentagon"];
+
+const EMBLEM_SIZE_RF: f32 = PLAYER_WIDTH_RF * 0.3;
+/// Offset above the player sprite, small enough to stay within the player's
+/// slot in the players layer. Emblems are children of the player, so they
+/// follow its z rather than having a draw layer of their own.
+const EMBLEM_Z_OFFSET: f32 = 0.001;
+
+fn emblem_mesh(avatar: u8) -> Option<Mesh> {
+    Some(match avatar {
+        1 => shape::Circle::new(EMBLEM_SIZE_RF).into(),
+        2 => shape::RegularPolygon::new(EMBLEM_SIZE_RF, 3).into(),
+        3 => shape::RegularPolygon::new(EMBLEM_SIZE_RF, 4).into(),
+        4 => shape::RegularPolygon::new(EMBLEM_SIZE_RF, 6).into(),
+        5 => shape::RegularPolygon::new(EMBLEM_SIZE_RF, 5).into(),
+        _ => return None,
+    })
+}
+
+/// Black or white, whichever stands out more against `color`
+fn contrasting(color: Color) -> Color {
+    let [r, g, b, _] = color.as_rgba_f32();
+    if 0.299 * r + 0.587 * g + 0.114 * b > 0.5 {
+        Color::BLACK
+    } else {
+        Color::WHITE
+    }
+}
+
+/// Adds the emblem for `info`'s avatar as a child of the player `entity`
+pub fn spawn_avatar_emblem(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<ColorMaterial>,
+    entity: Entity,
+    info: &UserInfo,
+) {
+    let Some(mesh) = emblem_mesh(info.avatar) else {
+        return;
+    };
+    let emblem = commands
+        .spawn(MaterialMesh2dBundle {
+            mesh: meshes.add(mesh).into(),
+            material: materials.add(ColorMaterial::from(contrasting(info.sprite_color()))),
+            transform: Transform::from_xyz(0., 0., EMBLEM_Z_OFFSET),
+            ..default()
+        })
+        .id();
+    commands.entity(entity).add_child(emblem);
+}
+
+/// Lobby picker for [`UserInfo::avatar`]
+pub fn avatar_picker(ui: &mut Ui, avatar: &mut u8) {
+    ComboBox::from_id_source("avatar")
+        .selected_text(AVATAR_NAMES[*avatar as usize % AVATAR_NAMES.len()])
+        .show_ui(ui, |ui| {
+            for (index, name) in AVATAR_NAMES.iter().enumerate() {
+                ui.selectable_value(avatar, index as u8, *name);
+            }
+        });
+}
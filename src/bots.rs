@@ -0,0 +1,74 @@
+use crate::{
+    bullet::BULLET_RADIUS_SI,
+    components::{Health, Player, Position},
+    input::{encode_input, PlayerInput},
+    maps::CurrentMap,
+    IVec2Ext, F2I,
+};
+use bevy::{ecs::system::SystemParam, prelude::*};
+
+/// Marks a player driven by [`BotBrains`] instead of a person. Bots are local
+/// players of the lobby host, so only the host runs their AI and everyone
+/// else receives their inputs through GGRS like any remote player's.
+#[derive(Component)]
+pub struct Bot;
+
+/// Bots back off when closer than this to their target
+const BOT_MIN_DISTANCE_SI: i32 = 3 * F2I;
+/// Bots close in when farther than this from their target
+const BOT_MAX_DISTANCE_SI: i32 = 6 * F2I;
+/// Bots only fire at targets this close
+const BOT_FIRE_RANGE_SI: i32 = 10 * F2I;
+
+/// Decides what bots do from the simulation state alone, so a bot reacts the
+/// same way to the same situation
+#[derive(SystemParam)]
+pub struct BotBrains<'w, 's> {
+    bots: Query<'w, 's, &'static Player, With<Bot>>,
+    players: Query<'w, 's, (&'static Player, &'static Position, &'static Health)>,
+    map: Res<'w, CurrentMap>,
+}
+
+impl BotBrains<'_, '_> {
+    /// Input for the bot playing `handle`, or `None` if no bot does
+    pub fn input(&self, handle: usize) -> Option<PlayerInput> {
+        self.bots.iter().find(|bot| bot.handle == handle)?;
+        let alive = |health: &Health| health.0 > 0;
+        let Some((_, position, _)) = self
+            .players
+            .iter()
+            .find(|(player, _, health)| player.handle == handle && alive(health))
+        else {
+            return Some(PlayerInput::default());
+        };
+        // Seek the nearest living player, the lowest handle breaking ties
+        let Some((_, target, _)) = self
+            .players
+            .iter()
+            .filter(|(player, _, health)| player.handle != handle && alive(health))
+            .min_by_key(|(player, target, _)| {
+                ((target.0 - position.0).norm_sq_wide(), player.handle)
+            })
+        else {
+            return Some(PlayerInput::default());
+        };
+
+        let offset = target.0 - position.0;
+        let distance_sq = offset.norm_sq_wide();
+        let within = |distance: i32| distance_sq < distance as i64 * distance as i64;
+        let direction = if within(BOT_MIN_DISTANCE_SI) {
+            -offset
+        } else if within(BOT_MAX_DISTANCE_SI) {
+            IVec2::ZERO
+        } else {
+            offset
+        };
+        let fire = within(BOT_FIRE_RANGE_SI) && self.in_sight(position.0, target.0);
+        Some(encode_input(direction, fire, Some(offset)))
+    }
+
+    /// Whether a bullet from `from` would reach `to` without hitting a wall
+    fn in_sight(&self, from: IVec2, to: IVec2) -> bool {
+        self.map.sweep(from, to, BULLET_RADIUS_SI).is_none()
+    }
+}
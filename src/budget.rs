@@ -0,0 +1,110 @@
+use bevy::prelude::*;
+
+/// Rollback entities alive at once. Every one of them is in each snapshot and
+/// restored by each rollback, so chaotic matches are held below this.
+pub const MAX_ROLLBACK_ENTITIES: usize = 128;
+/// Bullets each player adds to the budget
+const BULLETS_PER_PLAYER: usize = 16;
+/// Bullets alive at once in a full room
+const MAX_BULLETS: usize = 96;
+/// Bullets spawned with every game and reused for every shot, enough for the
+/// largest budget
+pub const BULLET_POOL_SIZE: usize = MAX_BULLETS;
+/// Particles on screen at once, across all effects
+#[cfg(feature = "presentation")]
+pub const MAX_PARTICLES: usize = 256;
+/// Particles left in the budget below which effects are cut down to their
+/// most important parts
+#[cfg(feature = "presentation")]
+pub const PARTICLE_DEGRADE_THRESHOLD: usize = MAX_PARTICLES / 4;
+
+/// Most bullets alive at once. Grows with the player count, but slower than
+/// it once the room is crowded, and leaves the rest of the rollback budget to
+/// the `other_rollback_entities` already alive.
+pub fn bullet_budget(player_count: usize, other_rollback_entities: usize) -> usize {
+    (BULLETS_PER_PLAYER * player_count)
+        .min(MAX_BULLETS)
+        .min(MAX_ROLLBACK_ENTITIES.saturating_sub(other_rollback_entities))
+}
+
+/// A bullet as far as [`expiry_order`] is concerned
+pub struct BudgetedBullet {
+    pub entity: Entity,
+    pub lifetime: u32,
+    pub shooter: usize,
+    pub position: IVec2,
+}
+
+/// Order in which bullets are expired early to make room for new shots once
+/// the budget is full: the oldest, i.e. closest to expiring anyway, first.
+/// Ties are broken on simulation state alone, never on entity ids, so every
+/// peer expires the same bullets.
+pub fn expiry_order(bullets: impl Iterator<Item = BudgetedBullet>) -> Vec<Entity> {
+    let mut bullets = bullets.collect::<Vec<_>>();
+    bullets.sort_by_key(|bullet| {
+        (
+            bullet.lifetime,
+            bullet.shooter,
+            bullet.position.x,
+            bullet.position.y,
+        )
+    });
+    bullets.into_iter().map(|bullet| bullet.entity).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bullet(index: u32, lifetime: u32, shooter: usize, position: IVec2) -> BudgetedBullet {
+        BudgetedBullet {
+            entity: Entity::from_raw(index),
+            lifetime,
+            shooter,
+            position,
+        }
+    }
+
+    #[test]
+    fn budget_grows_with_players_up_to_the_pool() {
+        assert_eq!(bullet_budget(1, 0), BULLETS_PER_PLAYER);
+        assert_eq!(bullet_budget(2, 0), 2 * BULLETS_PER_PLAYER);
+        assert_eq!(bullet_budget(8, 0), MAX_BULLETS);
+        assert!(bullet_budget(8, 0) <= BULLET_POOL_SIZE);
+    }
+
+    #[test]
+    fn budget_leaves_room_for_other_rollback_entities() {
+        assert_eq!(bullet_budget(8, MAX_ROLLBACK_ENTITIES - 10), 10);
+        assert_eq!(bullet_budget(8, MAX_ROLLBACK_ENTITIES + 10), 0);
+    }
+
+    #[test]
+    fn oldest_bullets_expire_first() {
+        let order = expiry_order(
+            [
+                bullet(0, 30, 0, IVec2::ZERO),
+                bullet(1, 10, 0, IVec2::ZERO),
+                bullet(2, 20, 0, IVec2::ZERO),
+            ]
+            .into_iter(),
+        );
+        assert_eq!(order, [1, 2, 0].map(Entity::from_raw));
+    }
+
+    #[test]
+    fn ties_are_broken_on_state_not_entities() {
+        let bullets = || {
+            [
+                bullet(0, 10, 1, IVec2::new(0, 0)),
+                bullet(1, 10, 0, IVec2::new(5, 0)),
+                bullet(2, 10, 0, IVec2::new(-5, 3)),
+                bullet(3, 10, 0, IVec2::new(-5, -3)),
+            ]
+        };
+        let expected = [3, 2, 1, 0].map(Entity::from_raw);
+        assert_eq!(expiry_order(bullets().into_iter()), expected);
+        // Peers may have spawned the same bullets in a different order
+        assert_eq!(expiry_order(bullets().into_iter().rev()), expected);
+    }
+}
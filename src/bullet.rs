@@ -0,0 +1,105 @@
+use crate::F2I;
+#[cfg(feature = "presentation")]
+use crate::{
+    components::{Active, Bullet, MoveDir, Position, Radius},
+    layers::DrawLayer,
+    simulation::SimulationTimingSet,
+    sprite_atlas::{GameSprite, SpriteAtlas},
+    GameState, IVec2Ext, I2F,
+};
+use bevy::prelude::*;
+#[cfg(feature = "presentation")]
+use bevy_ggrs::GGRSSchedule;
+
+/// Bullet sprites, shown while their pooled bullet is in flight
+#[cfg(feature = "presentation")]
+pub struct BulletPlugin;
+
+#[cfg(feature = "presentation")]
+impl Plugin for BulletPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(add_bullet_sprites.in_set(OnUpdate(GameState::InGame)))
+            .add_system(orient_bullets.in_set(OnUpdate(GameState::InGame)))
+            .add_system(
+                show_active_bullets
+                    .in_schedule(GGRSSchedule)
+                    .in_base_set(SimulationTimingSet::End),
+            );
+    }
+}
+
+pub const BULLET_RADIUS_SI: i32 = 5 * F2I / 100;
+
+/// Sprites for the pooled bullets, hidden until the simulation fires them
+#[cfg(feature = "presentation")]
+fn add_bullet_sprites(
+    mut commands: Commands,
+    sprites: Res<SpriteAtlas>,
+    bullets: Query<Entity, Added<Bullet>>,
+) {
+    for entity in bullets.iter() {
+        commands.entity(entity).insert((
+            DrawLayer::Bullets,
+            SpriteSheetBundle {
+                visibility: Visibility::Hidden,
+                ..sprites.bundle(
+                    GameSprite::Bullet,
+                    Color::WHITE,
+                    Vec2::ZERO,
+                    Transform::from_xyz(0., 0., DrawLayer::Bullets.z()),
+                )
+            },
+        ));
+    }
+}
+
+/// Shows bullets as they're fired, sized and turned for their shot, and hides
+/// them again once they're back in the pool. Last in the frame, after every
+/// system that fires or expires bullets.
+#[cfg(feature = "presentation")]
+fn show_active_bullets(
+    mut bullets: Query<
+        (
+            &Active,
+            &Position,
+            &MoveDir,
+            &Radius,
+            &mut TextureAtlasSprite,
+            &mut Transform,
+            &mut Visibility,
+        ),
+        (With<Bullet>, Changed<Active>),
+    >,
+) {
+    for (active, position, dir, radius, mut sprite, mut transform, mut visibility) in
+        bullets.iter_mut()
+    {
+        if !active.0 {
+            *visibility = Visibility::Hidden;
+            continue;
+        }
+        let width = (radius.0 * 2) as f32 * I2F;
+        sprite.custom_size = Some(Vec2::new(width * 3., width));
+        transform.translation = position.0.i2f().extend(transform.translation.z);
+        transform.rotation = bullet_rotation(dir.0);
+        *visibility = Visibility::Inherited;
+    }
+}
+
+/// Turns bullets that ricocheted to face their new direction. Outside the
+/// rollback schedule, which only touches the simulation's own components.
+#[cfg(feature = "presentation")]
+fn orient_bullets(
+    mut bullets: Query<(&Active, &MoveDir, &mut Transform), (With<Bullet>, Changed<MoveDir>)>,
+) {
+    for (active, dir, mut transform) in bullets.iter_mut() {
+        if active.0 {
+            transform.rotation = bullet_rotation(dir.0);
+        }
+    }
+}
+
+#[cfg(feature = "presentation")]
+fn bullet_rotation(dir: IVec2) -> Quat {
+    Quat::from_rotation_arc_2d(Vec2::X, dir.i2f().normalize())
+}
@@ -0,0 +1,234 @@
+use crate::{
+    attract::AttractMode,
+    components::{Health, Player, Position, UserInfo},
+    fixed_timestep::FixedTimestep,
+    ghost::{Ghost, GhostView},
+    key_bindings::{Action, KeyBindings},
+    lobby::Spectating,
+    lobby_settings::LobbySettings,
+    presentation_clock::{PresentationClock, Smoothing},
+    GameState, LocalPlayerHandle, I2F,
+};
+use bevy::{
+    prelude::*,
+    render::camera::{CameraUpdateSystem, ScalingMode},
+    transform::TransformSystem,
+};
+use bevy_egui::{
+    egui::{Align2, Area},
+    EguiContexts,
+};
+
+/// The game camera. It follows the local player, or their ghost while dead,
+/// and is kept from showing more than a sliver of the void past the map's
+/// edge. The limit depends on how much the view covers, so it's applied after
+/// bevy updates the projection for this frame's window size and zoom.
+/// Spectators and dead players can press Tab to follow someone else or pan
+/// freely instead, see [`CameraMode`].
+pub struct CameraPlugin;
+
+impl Plugin for CameraPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CameraMode>()
+            .add_system(spawn_camera.in_schedule(OnExit(GameState::AssetLoading)))
+            .add_systems(
+                (
+                    cycle_camera_mode,
+                    camera_follow.after(cycle_camera_mode),
+                    pan_free_camera.after(cycle_camera_mode),
+                    camera_mode_ui,
+                )
+                    .in_set(OnUpdate(GameState::InGame)),
+            )
+            .add_system(reset_camera_mode.in_schedule(OnExit(GameState::InGame)))
+            .add_system(
+                clamp_camera_to_map
+                    .in_base_set(CoreSet::PostUpdate)
+                    .after(CameraUpdateSystem)
+                    .before(TransformSystem::TransformPropagate)
+                    .run_if(in_state(GameState::InGame)),
+            );
+    }
+}
+
+/// How far past the map's edge the camera may show
+const CAMERA_MARGIN_RF: f32 = 1.;
+/// Panning speed of the free camera in world units per second
+const FREE_CAMERA_SPEED_RF: f32 = 12.;
+
+/// What the camera looks at. Anything but [`CameraMode::Own`] is only
+/// available to spectators and dead players.
+#[derive(Resource, Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CameraMode {
+    /// The local player or their ghost, or the first player for spectators
+    #[default]
+    Own,
+    /// The player with this handle
+    Following(usize),
+    /// Wherever the movement keys pan it
+    Free,
+}
+
+fn can_look_around(spectating: &Option<Res<Spectating>>, ghost: &Option<Res<GhostView>>) -> bool {
+    spectating.is_some() || ghost.is_some()
+}
+
+/// Tab steps through the living players in handle order, then the free
+/// camera, then back to the usual view. Coming back to life resets it.
+fn cycle_camera_mode(
+    mut mode: ResMut<CameraMode>,
+    keys: Res<Input<KeyCode>>,
+    spectating: Option<Res<Spectating>>,
+    ghost: Option<Res<GhostView>>,
+    players: Query<(&Player, &Health)>,
+) {
+    if !can_look_around(&spectating, &ghost) {
+        if *mode != CameraMode::Own {
+            *mode = CameraMode::Own;
+        }
+        return;
+    }
+    if !keys.just_pressed(KeyCode::Tab) {
+        return;
+    }
+    let mut living = players
+        .iter()
+        .filter(|(_, health)| health.0 > 0)
+        .map(|(player, _)| player.handle)
+        .collect::<Vec<_>>();
+    living.sort();
+    let after = match *mode {
+        CameraMode::Own => None,
+        CameraMode::Following(handle) => Some(handle),
+        CameraMode::Free => {
+            *mode = CameraMode::Own;
+            return;
+        }
+    };
+    *mode = living
+        .into_iter()
+        .find(|handle| after.map_or(true, |after| *handle > after))
+        .map_or(CameraMode::Free, CameraMode::Following);
+}
+
+fn pan_free_camera(
+    mode: Res<CameraMode>,
+    keys: Res<Input<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    timestep: Res<FixedTimestep>,
+    mut cameras: Query<&mut Transform, With<Camera>>,
+) {
+    if *mode != CameraMode::Free {
+        return;
+    }
+    let axis = |negative, positive| {
+        bindings.pressed(&keys, positive) as i32 as f32
+            - bindings.pressed(&keys, negative) as i32 as f32
+    };
+    let direction = Vec2::new(
+        axis(Action::Left, Action::Right),
+        axis(Action::Down, Action::Up),
+    );
+    let delta =
+        direction.normalize_or_zero() * FREE_CAMERA_SPEED_RF * timestep.real_delta_seconds();
+    for mut transform in cameras.iter_mut() {
+        transform.translation += delta.extend(0.);
+    }
+}
+
+/// Says whose view is shown and how to change it, while it can be changed
+fn camera_mode_ui(
+    mut contexts: EguiContexts,
+    mode: Res<CameraMode>,
+    spectating: Option<Res<Spectating>>,
+    ghost: Option<Res<GhostView>>,
+    players: Query<(&Player, Option<&UserInfo>)>,
+) {
+    if !can_look_around(&spectating, &ghost) {
+        return;
+    }
+    let name = |handle: usize| {
+        players
+            .iter()
+            .find(|(player, _)| player.handle == handle)
+            .and_then(|(_, info)| info.map(|info| info.name.clone()))
+            .unwrap_or_else(|| format!("Player {handle}"))
+    };
+    let text = match *mode {
+        CameraMode::Own if spectating.is_some() => format!("Watching {}", name(0)),
+        CameraMode::Own => "Watching your ghost".to_string(),
+        CameraMode::Following(handle) => format!("Watching {}", name(handle)),
+        CameraMode::Free => "Free camera, move to pan".to_string(),
+    };
+    Area::new("camera_mode")
+        .anchor(Align2::CENTER_BOTTOM, [0., -120.])
+        .show(contexts.ctx_mut(), |ui| {
+            ui.label(format!("{text} · Tab to switch"));
+        });
+}
+
+fn reset_camera_mode(mut mode: ResMut<CameraMode>) {
+    *mode = CameraMode::Own;
+}
+
+fn spawn_camera(mut commands: Commands) {
+    let mut camera_bundle = Camera2dBundle::default();
+    camera_bundle.projection.scaling_mode = ScalingMode::FixedVertical(10.);
+    commands.spawn(camera_bundle);
+}
+
+pub fn camera_follow(
+    player_handle: Option<Res<LocalPlayerHandle>>,
+    spectating: Option<Res<Spectating>>,
+    attract: Option<Res<AttractMode>>,
+    player_query: Query<(&Player, &Position)>,
+    ghosts: Query<&Transform, With<Ghost>>,
+    mut camera_query: Query<&mut Transform, (With<Camera>, Without<Player>, Without<Ghost>)>,
+    clock: Res<PresentationClock>,
+    mode: Res<CameraMode>,
+    mut smoothing: Local<Smoothing>,
+) {
+    // Dead players follow their ghost around instead
+    let mut ghost = ghosts.get_single().ok();
+    let player_handle = match (*mode, player_handle) {
+        (CameraMode::Free, _) => return,
+        (CameraMode::Following(handle), _) => {
+            ghost = None;
+            handle
+        }
+        (CameraMode::Own, Some(handle)) => handle.0,
+        (CameraMode::Own, None) if spectating.is_some() || attract.is_some() => 0,
+        (CameraMode::Own, None) => return, // Session hasn't started yet
+    };
+    for (player, player_position) in player_query.iter() {
+        if player.handle != player_handle {
+            continue;
+        }
+
+        // Follows the simulated position rather than the player's smoothed
+        // transform, which is only updated after this runs
+        let target = ghost.map_or(player_position.0.as_vec2() * I2F, |ghost| {
+            ghost.translation.truncate()
+        });
+        let pos = smoothing.follow(target, &clock);
+
+        for mut transform in camera_query.iter_mut() {
+            transform.translation.x = pos.x;
+            transform.translation.y = pos.y;
+        }
+    }
+}
+
+/// Stops the view at the map's edge, and centers maps smaller than the view
+pub fn clamp_camera_to_map(
+    settings: Res<LobbySettings>,
+    mut cameras: Query<(&mut Transform, &OrthographicProjection), With<Camera>>,
+) {
+    let map_half = settings.map_size_si() as f32 * I2F / 2. + CAMERA_MARGIN_RF;
+    for (mut transform, projection) in cameras.iter_mut() {
+        let limit = (Vec2::splat(map_half) - projection.area.size() / 2.).max(Vec2::ZERO);
+        let pos = transform.translation.truncate().clamp(-limit, limit);
+        transform.translation.x = pos.x;
+        transform.translation.y = pos.y;
+    }
+}
@@ -0,0 +1,92 @@
+use crate::{bullet::BULLET_RADIUS_SI, PLAYER_MAX_HEALTH};
+use bevy::prelude::*;
+#[cfg(feature = "presentation")]
+use bevy_egui::egui::{ComboBox, Ui};
+use serde::{Deserialize, Serialize};
+
+/// What a player plays as, picked in the lobby. Classes only differ in their
+/// [`ClassStats`], so every class goes through the same movement and combat
+/// systems and new ones just need stats. Players carry their class as a
+/// rollback component, so a resumed save keeps the classes it was made with.
+#[derive(
+    Component,
+    Reflect,
+    FromReflect,
+    Serialize,
+    Deserialize,
+    Default,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Debug,
+)]
+pub enum PlayerClass {
+    #[default]
+    Soldier,
+    Scout,
+    Heavy,
+}
+
+pub struct ClassStats {
+    /// Percentage of the lobby's player speed
+    pub speed_percent: i32,
+    pub max_health: i32,
+    pub bullet_radius_si: i32,
+}
+
+impl PlayerClass {
+    pub const ALL: [PlayerClass; 3] =
+        [PlayerClass::Soldier, PlayerClass::Scout, PlayerClass::Heavy];
+
+    pub fn stats(self) -> &'static ClassStats {
+        match self {
+            PlayerClass::Soldier => &ClassStats {
+                speed_percent: 100,
+                max_health: PLAYER_MAX_HEALTH,
+                bullet_radius_si: BULLET_RADIUS_SI,
+            },
+            PlayerClass::Scout => &ClassStats {
+                speed_percent: 125,
+                max_health: PLAYER_MAX_HEALTH * 3 / 4,
+                bullet_radius_si: BULLET_RADIUS_SI,
+            },
+            PlayerClass::Heavy => &ClassStats {
+                speed_percent: 80,
+                max_health: PLAYER_MAX_HEALTH * 3 / 2,
+                bullet_radius_si: BULLET_RADIUS_SI * 8 / 5,
+            },
+        }
+    }
+
+    #[cfg(feature = "presentation")]
+    fn name(self) -> &'static str {
+        match self {
+            PlayerClass::Soldier => "Soldier",
+            PlayerClass::Scout => "Scout",
+            PlayerClass::Heavy => "Heavy",
+        }
+    }
+
+    #[cfg(feature = "presentation")]
+    fn description(self) -> &'static str {
+        match self {
+            PlayerClass::Soldier => "All-rounder",
+            PlayerClass::Scout => "Faster, but less health",
+            PlayerClass::Heavy => "Slower, with more health and bigger bullets",
+        }
+    }
+}
+
+/// Lobby picker for [`crate::components::UserInfo::class`]
+#[cfg(feature = "presentation")]
+pub fn class_picker(ui: &mut Ui, class: &mut PlayerClass) {
+    ComboBox::from_id_source("class")
+        .selected_text(class.name())
+        .show_ui(ui, |ui| {
+            for option in PlayerClass::ALL {
+                ui.selectable_value(class, option, option.name())
+                    .on_hover_text(option.description());
+            }
+        });
+}
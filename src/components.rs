@@ -1,6 +1,16 @@
 // use crate::fixed_point::{Fixed, Vec2Fixed};
+use crate::classes::PlayerClass;
+#[cfg(any(feature = "presentation", test))]
+use crate::match_config::MatchConfig;
+#[cfg(feature = "presentation")]
+use crate::{
+    match_config::Fnv1a,
+    wire_format::{Bincode, WireFormat},
+};
 use bevy::prelude::*;
+#[cfg(any(feature = "presentation", test))]
 use bevy_matchbox::prelude::PeerId;
+#[cfg(any(feature = "presentation", test))]
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
@@ -12,6 +22,11 @@ pub struct Player {
 #[derive(Component, Reflect, Default)]
 pub struct Bullet;
 
+/// Whether a bullet is in flight. Bullets come from a pool spawned with the
+/// game, so firing and expiring only switch this on and off.
+#[derive(Component, Reflect, Default, Clone, Copy, Debug)]
+pub struct Active(pub bool);
+
 #[derive(Component, Reflect, Default)]
 pub struct Character;
 
@@ -21,27 +36,212 @@ pub struct MoveDir(pub IVec2);
 #[derive(Component, Reflect, Default, Clone, Copy, Debug)]
 pub struct Position(pub IVec2);
 
+/// Knockback in simulation units per frame, added to a player's movement and
+/// decaying every frame
+#[derive(Component, Reflect, Default, Clone, Copy, Debug)]
+pub struct Velocity(pub IVec2);
+
+/// Frames left of reduced movement speed after firing
+#[derive(Component, Reflect, Default, Clone, Copy, Debug)]
+pub struct FireSlowdown(pub u32);
+
+/// The latest bullet hit on a player. `frame` is the [`SimFrame`] of the hit,
+/// or 0 if the player hasn't been hit yet.
+///
+/// [`SimFrame`]: crate::rng::SimFrame
+#[derive(Component, Reflect, Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct LastHit {
+    pub shooter: usize,
+    pub frame: u32,
+}
+
+/// Handle of the player who fired a bullet
+#[derive(Component, Reflect, Default, Clone, Copy, Debug)]
+pub struct Shooter(pub usize);
+
+/// Frames left before the bullet expires
+#[derive(Component, Reflect, Default, Clone, Copy, Debug)]
+pub struct Lifetime(pub u32);
+
+/// Times a bullet has ricocheted off the map's edge
+#[derive(Component, Reflect, Default, Clone, Copy, Debug)]
+pub struct Bounces(pub u8);
+
+/// Where a bullet's move this frame starts, for sweeping it against walls,
+/// players and other bullets: where it was before the move, or the point it
+/// bounced off the map's edge
+#[derive(Component, Reflect, Default, Clone, Copy, Debug)]
+pub struct SweepStart(pub IVec2);
+
 #[derive(Component, Reflect, Default, Serialize, Deserialize, Clone, Debug)]
 pub struct TabId(pub String);
 
+#[cfg(feature = "presentation")]
 #[derive(Component)]
 pub struct IsLocal;
+#[cfg(any(feature = "presentation", test))]
 #[derive(Component, Debug)]
 pub struct MatchBoxPeerId(pub PeerId);
 
+#[cfg(any(feature = "presentation", test))]
 #[derive(Component, Default, Clone, PartialEq, Debug)]
 pub struct IsReady(pub bool);
 
+/// The build a peer is running, as announced when it connected
+#[cfg(any(feature = "presentation", test))]
+#[derive(Component, Clone, PartialEq, Eq, Debug)]
+pub struct PeerVersion {
+    pub build_hash: String,
+    pub protocol: u32,
+}
+
+/// Bump whenever P2P messages or snapshots change in a way older builds can't read
+#[cfg(any(feature = "presentation", test))]
+pub const PROTOCOL_VERSION: u32 = 43;
+/// Identifies this build. Release builds set `WEB_GHOST_BUILD_HASH` to the
+/// commit they were built from.
+#[cfg(any(feature = "presentation", test))]
+pub const BUILD_HASH: &str = match option_env!("WEB_GHOST_BUILD_HASH") {
+    Some(hash) => hash,
+    None => env!("CARGO_PKG_VERSION"),
+};
+
+#[cfg(feature = "presentation")]
+impl PeerVersion {
+    /// Whether the peer runs the same build as us. Mixed builds desync or fail
+    /// to read each other's snapshots.
+    pub fn matches_local(&self) -> bool {
+        self.protocol == PROTOCOL_VERSION && self.build_hash == BUILD_HASH
+    }
+}
+
+/// What a player wants to do when a save is available. Everyone has to agree
+/// before the game starts.
+#[cfg(any(feature = "presentation", test))]
+#[derive(Component, Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StartChoice {
+    #[default]
+    ResumeSave,
+    NewGame,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Component)]
 pub struct UserInfo {
     pub name: String,
+    /// sRGB player color
+    #[serde(default = "default_player_color")]
+    pub color: [u8; 3],
+    /// Index into `avatar::AVATAR_NAMES`
+    #[serde(default)]
+    pub avatar: u8,
+    #[serde(default)]
+    pub cosmetics: Cosmetics,
+    #[serde(default)]
+    pub class: PlayerClass,
+}
+
+/// Lifetime totals of the local player, kept per tab in cookies like
+/// [`UserInfo`]
+#[cfg(feature = "presentation")]
+#[derive(Debug, Serialize, Deserialize, Default, Clone, PartialEq, Component)]
+pub struct PlayerStats {
+    pub kills: u32,
+    pub deaths: u32,
+    pub games_played: u32,
+    /// Rounds won
+    pub wins: u32,
 }
 
+/// Which cosmetic of each kind a player shows, as indices into the tables of
+/// [`crate::cosmetics`]. 0 is the default look.
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Cosmetics {
+    pub trail: u8,
+    pub bullets: u8,
+    pub grid: u8,
+}
+
+pub fn default_player_color() -> [u8; 3] {
+    [0, 120, 255]
+}
+
+impl Default for UserInfo {
+    fn default() -> Self {
+        Self {
+            name: "New User".to_string(),
+            color: default_player_color(),
+            avatar: 0,
+            cosmetics: default(),
+            class: default(),
+        }
+    }
+}
+
+impl UserInfo {
+    #[cfg(feature = "presentation")]
+    pub fn sprite_color(&self) -> Color {
+        let [r, g, b] = self.color;
+        Color::rgb_u8(r, g, b)
+    }
+}
+
+#[cfg(any(feature = "presentation", test))]
 #[derive(Serialize, Deserialize, Clone, Component, Debug, Resource)]
 pub struct GameSaveData {
     pub snapshot: String,
     pub timestamp: DateTime<Utc>,
+    pub config: MatchConfig,
+    /// Save format version the snapshot was written by, so it can be
+    /// migrated before loading
+    pub save_version: u32,
 }
 
-#[derive(Component)]
+#[cfg(feature = "presentation")]
+impl GameSaveData {
+    /// Identifies the save, so peers can agree on one and check they got
+    /// exactly that one. Always hashed as [`Bincode`], whatever format P2P
+    /// messages are sent in, so builds that picked another format still
+    /// agree.
+    pub fn hash(&self) -> Result<u64, String> {
+        let mut hasher = Fnv1a::default();
+        hasher.write(&Bincode::encode(self)?);
+        Ok(hasher.finish())
+    }
+
+    pub fn offer(&self) -> Result<SaveOffer, String> {
+        Ok(SaveOffer {
+            hash: self.hash()?,
+            timestamp: self.timestamp,
+            config: self.config.clone(),
+        })
+    }
+}
+
+/// What a peer tells the lobby about its save, without the save itself. The
+/// bytes are only sent to peers who ask for them.
+#[cfg(any(feature = "presentation", test))]
+#[derive(Serialize, Deserialize, Clone, Component, PartialEq, Debug)]
+pub struct SaveOffer {
+    pub hash: u64,
+    pub timestamp: DateTime<Utc>,
+    /// For telling whether the save can be resumed before it arrived
+    pub config: MatchConfig,
+}
+
+#[derive(Component, Reflect, Default, Clone, Copy, Debug)]
 pub struct Radius(pub i32);
+
+#[derive(Component, Reflect, Default, Clone, Copy, Debug)]
+pub struct Health(pub i32);
+
+#[derive(Component, Reflect, Default, Clone, Copy, Debug)]
+pub struct Damage(pub i32);
+
+/// Frames left during which the entity can't take damage
+#[derive(Component, Reflect, Default, Clone, Copy, Debug)]
+pub struct Invulnerable(pub u32);
+
+/// World-space health bar following the player entity it belongs to
+#[cfg(feature = "presentation")]
+#[derive(Component)]
+pub struct HealthBar(pub Entity);
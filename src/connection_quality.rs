@@ -0,0 +1,201 @@
+use crate::{
+    components::{MatchBoxPeerId, Player},
+    lobby::SocketExt,
+    net::{read_messages, Messages, P2PMessage},
+    save::kill_game,
+    GameState, GgrsConfig,
+};
+use bevy::{prelude::*, utils::HashMap};
+use bevy_egui::egui::{Color32, Ui};
+use bevy_ggrs::Session;
+use bevy_matchbox::prelude::*;
+use chrono::Utc;
+
+/// Measures the round trip to every peer, so the lobby and the bottom bar can
+/// show how good each connection is. In the lobby that's from
+/// [`P2PMessage::Ping`]s on the reliable channel; during a game GGRS already
+/// measures it and the pings stop. Pongs also carry the peer's wall clock,
+/// giving an estimate of how far its clock is off from ours.
+pub struct ConnectionQualityPlugin;
+
+impl Plugin for ConnectionQualityPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PeerPings>()
+            .add_system(receive_pings.after(read_messages).before(kill_game))
+            .add_system(send_pings.in_set(OnUpdate(GameState::Matchmaking)))
+            .add_system(read_ggrs_pings.in_set(OnUpdate(GameState::InGame)));
+    }
+}
+
+const PING_INTERVAL_SECS: f64 = 2.;
+/// A peer that hasn't answered for this long counts as a bad connection
+const PING_TIMEOUT_SECS: f64 = 3. * PING_INTERVAL_SECS;
+/// Round trips up to this are good
+const GOOD_PING_MS: u32 = 80;
+/// Round trips up to this are fair, anything longer is poor
+const FAIR_PING_MS: u32 = 160;
+/// Clock offsets further off than a day come from a broken or lying peer and
+/// are ignored
+const MAX_CLOCK_OFFSET_MS: i64 = 24 * 60 * 60 * 1000;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Quality {
+    Good,
+    Fair,
+    Poor,
+}
+
+impl Quality {
+    fn color(self) -> Color32 {
+        match self {
+            Quality::Good => Color32::from_rgb(80, 200, 90),
+            Quality::Fair => Color32::from_rgb(230, 190, 50),
+            Quality::Poor => Color32::from_rgb(220, 70, 60),
+        }
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct PeerPings {
+    /// The latest round trip to each peer in milliseconds, and when it was
+    /// measured
+    pings: HashMap<PeerId, (u32, f64)>,
+    /// How far each peer's wall clock is ahead of ours in milliseconds, and
+    /// the round trip of the pong it was estimated from
+    clock_offsets: HashMap<PeerId, (i64, u32)>,
+    last_sent: f64,
+}
+
+impl PeerPings {
+    fn record(&mut self, peer_id: PeerId, ping_ms: u32, now: f64) {
+        self.pings.insert(peer_id, (ping_ms, now));
+    }
+
+    /// Like NTP, assumes the pong was answered halfway through the round
+    /// trip. Estimates from shorter round trips can be off by less, so only
+    /// those replace the offset.
+    fn record_clock(&mut self, peer_id: PeerId, ping_ms: u32, peer_wall_clock_ms: i64) {
+        let answered_at = Utc::now().timestamp_millis() - ping_ms as i64 / 2;
+        let Some(offset_ms) = peer_wall_clock_ms
+            .checked_sub(answered_at)
+            .filter(|offset_ms| (-MAX_CLOCK_OFFSET_MS..=MAX_CLOCK_OFFSET_MS).contains(offset_ms))
+        else {
+            warn!("Ignoring implausible wall clock {peer_wall_clock_ms} from {peer_id:?}");
+            return;
+        };
+        let closest = self
+            .clock_offsets
+            .get(&peer_id)
+            .map_or(true, |&(_, best_ms)| ping_ms <= best_ms);
+        if closest {
+            self.clock_offsets.insert(peer_id, (offset_ms, ping_ms));
+        }
+    }
+
+    /// How far `peer_id`'s wall clock is ahead of ours in milliseconds, or
+    /// nothing before its first pong
+    pub fn clock_offset_ms(&self, peer_id: PeerId) -> Option<i64> {
+        self.clock_offsets
+            .get(&peer_id)
+            .map(|&(offset_ms, _)| offset_ms)
+    }
+
+    /// The last round trip to `peer_id` and how good that is, or nothing
+    /// before the first one's measured
+    pub fn quality(&self, peer_id: PeerId, time: &Time) -> Option<(u32, Quality)> {
+        let &(ping_ms, measured) = self.pings.get(&peer_id)?;
+        let quality = if time.elapsed_seconds_f64() - measured > PING_TIMEOUT_SECS {
+            Quality::Poor
+        } else if ping_ms <= GOOD_PING_MS {
+            Quality::Good
+        } else if ping_ms <= FAIR_PING_MS {
+            Quality::Fair
+        } else {
+            Quality::Poor
+        };
+        Some((ping_ms, quality))
+    }
+}
+
+/// A colored dot for the connection to `peer_id`, with the round trip on
+/// hover
+pub fn quality_icon(ui: &mut Ui, pings: &PeerPings, time: &Time, peer_id: PeerId) {
+    match pings.quality(peer_id, time) {
+        Some((ping_ms, quality)) => ui
+            .colored_label(quality.color(), "●")
+            .on_hover_text(format!("{ping_ms} ms")),
+        None => ui.weak("●").on_hover_text("Measuring connection"),
+    };
+}
+
+fn send_pings(
+    mut socket: ResMut<MatchboxSocket<MultipleChannels>>,
+    mut pings: ResMut<PeerPings>,
+    time: Res<Time>,
+) {
+    let now = time.elapsed_seconds_f64();
+    if now - pings.last_sent < PING_INTERVAL_SECS {
+        return;
+    }
+    pings.last_sent = now;
+    for peer_id in socket.connected_peers().collect::<Vec<_>>() {
+        socket.send_p2p_message(&peer_id, P2PMessage::Ping(now));
+    }
+}
+
+/// Answers pings and times pongs. Runs in every state, since pings sent from
+/// the lobby can arrive once the game started, where any other message would
+/// make [`kill_game`] restart the session.
+fn receive_pings(
+    mut messages: ResMut<Messages>,
+    mut socket: Option<ResMut<MatchboxSocket<MultipleChannels>>>,
+    mut pings: ResMut<PeerPings>,
+    time: Res<Time>,
+) {
+    let now = time.elapsed_seconds_f64();
+    messages
+        .0
+        .retain(|(peer_id, packet)| match P2PMessage::decode(packet) {
+            Ok(P2PMessage::Ping(sent)) => {
+                if let Some(socket) = socket.as_mut() {
+                    let wall_clock_ms = Utc::now().timestamp_millis();
+                    socket.send_p2p_message(
+                        peer_id,
+                        P2PMessage::Pong {
+                            sent,
+                            wall_clock_ms,
+                        },
+                    );
+                }
+                false
+            }
+            Ok(P2PMessage::Pong {
+                sent,
+                wall_clock_ms,
+            }) => {
+                let ping_ms = ((now - sent).max(0.) * 1000.) as u32;
+                pings.record(*peer_id, ping_ms, now);
+                pings.record_clock(*peer_id, ping_ms, wall_clock_ms);
+                false
+            }
+            _ => true,
+        });
+}
+
+fn read_ggrs_pings(
+    session: Option<Res<Session<GgrsConfig>>>,
+    mut pings: ResMut<PeerPings>,
+    time: Res<Time>,
+    players: Query<(&Player, &MatchBoxPeerId)>,
+) {
+    let Some(Session::P2PSession(session)) = session.as_deref() else {
+        return;
+    };
+    let now = time.elapsed_seconds_f64();
+    for (player, peer_id) in players.iter() {
+        // Fails for the local player, and for peers before the first stats
+        if let Ok(stats) = session.network_stats(player.handle) {
+            pings.record(peer_id.0, stats.ping as u32, now);
+        }
+    }
+}
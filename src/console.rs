@@ -0,0 +1,600 @@
+use crate::{
+    classes::PlayerClass,
+    components::{
+        Active, Bounces, Bullet, Damage, GameSaveData, Health, IsLocal, Lifetime, MatchBoxPeerId,
+        MoveDir, Player, Position, Radius, Shooter, TabId,
+    },
+    debug_overlay::DebugOverlay,
+    input::DIRECTION_SCALE,
+    lobby::{PracticeBots, PracticeMode},
+    lobby_settings::{lobby_host, LobbySettings},
+    match_config::Fnv1a,
+    net_sim::{NetSimWindow, SimulatedConditions},
+    net_stats::NetStatsOverlay,
+    rng::{advance_sim_frame, SimFrame},
+    room::Room,
+    sandbox::{saved_tab_ids, Sandbox},
+    save::{serialize_game, store_save},
+    save_format::compare_formats,
+    save_storage,
+    simulation::{apply_damage, fire_bullets, move_players, ring_out},
+    snapshot_diff::SnapshotDiff,
+    storage::{KeyValueStores, StorageArea},
+    weapons::{Ammo, BulletSpeed, Weapon},
+    GameState, GgrsConfig, LocalPlayerHandle, F2I,
+};
+use bevy::prelude::*;
+use bevy_egui::{
+    egui::{self, Key, ScrollArea, TextEdit, TopBottomPanel},
+    EguiContexts,
+};
+use bevy_ggrs::{GGRSSchedule, GGRSStage, Rollback};
+
+/// Drop-down console for manual testing, opened with the backtick key. Only
+/// added in debug builds. Commands that change the game don't touch the world
+/// directly, as a rollback would undo that: they're queued as
+/// [`ConsoleEffects`] for the next frame and applied in the GGRS schedule,
+/// like inputs. Peers don't get each other's effects, so those commands are
+/// only allowed in practice, where every player is local. Lobby settings are
+/// changed through the host's settings instead, which reach every peer.
+pub struct ConsolePlugin;
+
+impl Plugin for ConsolePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Console>()
+            .init_resource::<ConsoleEffects>()
+            .add_system(toggle_console)
+            .add_system(console_ui.after(toggle_console))
+            .add_system(run_console_commands.after(console_ui))
+            .add_system(
+                apply_console_effects
+                    .after(advance_sim_frame)
+                    .before(ring_out)
+                    .before(move_players)
+                    .before(fire_bullets)
+                    .before(apply_damage)
+                    .in_schedule(GGRSSchedule),
+            )
+            .add_system(clear_console_effects.in_schedule(OnExit(GameState::InGame)));
+    }
+}
+
+const MAX_LOG_LINES: usize = 200;
+
+#[derive(Resource, Default)]
+struct Console {
+    open: bool,
+    input: String,
+    log: Vec<String>,
+    /// Submitted lines waiting for world access
+    pending: Vec<String>,
+    /// Text to put on the clipboard on the next UI update
+    clipboard: Option<String>,
+}
+
+impl Console {
+    fn print(&mut self, line: impl Into<String>) {
+        self.log.push(line.into());
+        if self.log.len() > MAX_LOG_LINES {
+            self.log.drain(..self.log.len() - MAX_LOG_LINES);
+        }
+    }
+}
+
+type CommandResult = Result<String, String>;
+
+/// A change to the local player or the game, made by a console command
+#[derive(Clone, Copy, Debug)]
+enum ConsoleEffect {
+    FireBullet,
+    GiveWeapon(Weapon),
+    SetHealth(i32),
+    SetSpeed(i32),
+    Teleport(IVec2),
+}
+
+/// Effects by the [`SimFrame`] they're applied on. They're kept until the
+/// game ends, so resimulating a frame applies its effects again.
+#[derive(Resource, Default)]
+struct ConsoleEffects(Vec<(u32, ConsoleEffect)>);
+
+struct ConsoleCommand {
+    name: &'static str,
+    usage: &'static str,
+    help: &'static str,
+    run: fn(&mut World, &[&str]) -> CommandResult,
+}
+
+const COMMANDS: [ConsoleCommand; 13] = [
+    ConsoleCommand {
+        name: "help",
+        usage: "help",
+        help: "list commands",
+        run: help,
+    },
+    ConsoleCommand {
+        name: "spawn_bot",
+        usage: "spawn_bot",
+        help: "add an idle bot to the next practice game",
+        run: spawn_bot,
+    },
+    ConsoleCommand {
+        name: "spawn_bullet",
+        usage: "spawn_bullet",
+        help: "fire one of the local player's bullets, ignoring the cooldown",
+        run: spawn_bullet,
+    },
+    ConsoleCommand {
+        name: "give_weapon",
+        usage: "give_weapon <blaster|shotgun|repeater>",
+        help: "switch the local player's weapon",
+        run: give_weapon,
+    },
+    ConsoleCommand {
+        name: "set_health",
+        usage: "set_health <health>",
+        help: "set the local player's health",
+        run: set_health,
+    },
+    ConsoleCommand {
+        name: "set_speed",
+        usage: "set_speed <percent>",
+        help: "set the player speed, in practice or as the lobby host in the lobby",
+        run: set_speed,
+    },
+    ConsoleCommand {
+        name: "teleport",
+        usage: "teleport <x> <y>",
+        help: "move the local player, in world units",
+        run: teleport,
+    },
+    ConsoleCommand {
+        name: "overlay",
+        usage: "overlay <debug|net_stats|net_sim|snapshot_diff>",
+        help: "toggle a debug overlay",
+        run: toggle_overlay,
+    },
+    ConsoleCommand {
+        name: "desync_check",
+        usage: "desync_check",
+        help: "print a checksum of the current state, to compare between peers",
+        run: desync_check,
+    },
+    ConsoleCommand {
+        name: "save",
+        usage: "save",
+        help: "store the running game as this tab's save for the room",
+        run: save,
+    },
+    ConsoleCommand {
+        name: "load",
+        usage: "load",
+        help: "start a practice game from this tab's save for the room, from the lobby",
+        run: load,
+    },
+    ConsoleCommand {
+        name: "dump_snapshot",
+        usage: "dump_snapshot",
+        help: "log the current snapshot and copy it to the clipboard",
+        run: dump_snapshot,
+    },
+    ConsoleCommand {
+        name: "save_formats",
+        usage: "save_formats",
+        help: "compare the size and speed of save encodings on the local save",
+        run: save_formats,
+    },
+];
+
+/// Commands whose name starts with the first word of `input`
+fn completions(input: &str) -> Vec<&'static ConsoleCommand> {
+    let Some(prefix) = input.split_whitespace().next() else {
+        return Vec::new();
+    };
+    if input.trim_start().contains(' ') {
+        return Vec::new();
+    }
+    COMMANDS
+        .iter()
+        .filter(|command| command.name.starts_with(prefix))
+        .collect()
+}
+
+fn run_command(world: &mut World, line: &str) -> CommandResult {
+    let words = line.split_whitespace().collect::<Vec<_>>();
+    let Some((name, args)) = words.split_first() else {
+        return Ok(String::new());
+    };
+    let command = COMMANDS
+        .iter()
+        .find(|command| command.name == *name)
+        .ok_or_else(|| format!("Unknown command {name:?}, try \"help\""))?;
+    (command.run)(world, args).map_err(|error| format!("{error}\nusage: {}", command.usage))
+}
+
+fn toggle_console(keys: Res<Input<KeyCode>>, mut console: ResMut<Console>) {
+    if keys.just_pressed(KeyCode::Grave) {
+        console.open = !console.open;
+    }
+}
+
+fn console_ui(mut contexts: EguiContexts, mut console: ResMut<Console>) {
+    let ctx = contexts.ctx_mut();
+    if let Some(text) = console.clipboard.take() {
+        ctx.output_mut(|output| output.copied_text = text);
+    }
+    if !console.open {
+        return;
+    }
+    TopBottomPanel::top("console").show(ctx, |ui| {
+        ScrollArea::vertical()
+            .max_height(200.)
+            .stick_to_bottom(true)
+            .show(ui, |ui| {
+                for line in console.log.iter() {
+                    ui.monospace(line);
+                }
+            });
+        let console = &mut *console;
+        // The key that opened the console shouldn't end up in the input
+        console.input.retain(|c| c != '`');
+        let response = ui.add(
+            TextEdit::singleline(&mut console.input)
+                .font(egui::TextStyle::Monospace)
+                .desired_width(f32::INFINITY)
+                .lock_focus(true)
+                .hint_text("command, Tab to complete"),
+        );
+        response.request_focus();
+        let matches = completions(&console.input);
+        if ui.input(|i| i.key_pressed(Key::Tab)) {
+            if let [command] = matches[..] {
+                console.input = format!("{} ", command.name);
+            }
+        }
+        if ui.input(|i| i.key_pressed(Key::Enter)) && !console.input.trim().is_empty() {
+            let line = std::mem::take(&mut console.input);
+            console.print(format!("> {line}"));
+            console.pending.push(line);
+        } else if !matches.is_empty() {
+            ui.horizontal_wrapped(|ui| {
+                for command in matches {
+                    ui.weak(command.usage);
+                }
+            });
+        }
+    });
+}
+
+fn run_console_commands(world: &mut World) {
+    let pending = std::mem::take(&mut world.resource_mut::<Console>().pending);
+    for line in pending {
+        let output = match run_command(world, &line) {
+            Ok(output) => output,
+            Err(error) => error,
+        };
+        let mut console = world.resource_mut::<Console>();
+        for line in output.lines() {
+            console.print(line);
+        }
+    }
+}
+
+/// Rejects commands that change the simulation outside of practice, where
+/// they'd desync the other peers
+fn require_practice(world: &World) -> Result<(), String> {
+    if world.contains_resource::<PracticeMode>() {
+        Ok(())
+    } else {
+        Err("Only available in practice games".to_string())
+    }
+}
+
+/// Queues `effect` for the next simulated frame, returning that frame
+fn queue_effect(world: &mut World, effect: ConsoleEffect) -> Result<u32, String> {
+    require_practice(world)?;
+    local_player(world)?;
+    let frame = world.resource::<SimFrame>().0.wrapping_add(1);
+    world
+        .resource_mut::<ConsoleEffects>()
+        .0
+        .push((frame, effect));
+    Ok(frame)
+}
+
+fn apply_console_effects(world: &mut World) {
+    let frame = world.resource::<SimFrame>().0;
+    let due = world
+        .resource::<ConsoleEffects>()
+        .0
+        .iter()
+        .filter(|(at, _)| *at == frame)
+        .map(|(_, effect)| *effect)
+        .collect::<Vec<_>>();
+    for effect in due {
+        if let Err(error) = apply_effect(world, effect) {
+            warn!("Console effect {effect:?} failed on frame {frame}: {error}");
+        }
+    }
+}
+
+fn apply_effect(world: &mut World, effect: ConsoleEffect) -> Result<(), String> {
+    let player = local_player(world)?;
+    match effect {
+        ConsoleEffect::FireBullet => fire_bullet(world, player)?,
+        ConsoleEffect::GiveWeapon(weapon) => {
+            world
+                .entity_mut(player)
+                .insert((weapon, Ammo::full(weapon)));
+        }
+        ConsoleEffect::SetHealth(health) => {
+            world.entity_mut(player).insert(Health(health));
+        }
+        ConsoleEffect::SetSpeed(percent) => {
+            let mut settings = world.resource_mut::<LobbySettings>();
+            *settings = LobbySettings {
+                player_speed_percent: percent,
+                ..settings.clone()
+            }
+            .sanitized();
+        }
+        ConsoleEffect::Teleport(position) => {
+            world.entity_mut(player).insert(Position(position));
+        }
+    }
+    Ok(())
+}
+
+fn clear_console_effects(mut effects: ResMut<ConsoleEffects>) {
+    effects.0.clear();
+}
+
+fn local_player(world: &mut World) -> Result<Entity, String> {
+    let handle = world
+        .get_resource::<LocalPlayerHandle>()
+        .ok_or("No game running")?
+        .0;
+    world
+        .query::<(Entity, &Player)>()
+        .iter(world)
+        .find(|(_, player)| player.handle == handle)
+        .map(|(entity, _)| entity)
+        .ok_or_else(|| "Local player not found".to_string())
+}
+
+fn parse<T: std::str::FromStr>(args: &[&str], index: usize) -> Result<T, String> {
+    let arg = args.get(index).ok_or("Missing argument")?;
+    arg.parse().map_err(|_| format!("Invalid argument {arg:?}"))
+}
+
+fn help(_: &mut World, _: &[&str]) -> CommandResult {
+    Ok(COMMANDS
+        .iter()
+        .map(|command| format!("{:<36} {}", command.usage, command.help))
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+fn spawn_bot(world: &mut World, _: &[&str]) -> CommandResult {
+    let mut bots = world.resource_mut::<PracticeBots>();
+    bots.0 += 1;
+    Ok(format!(
+        "The next practice game will have {} bot(s)",
+        bots.0
+    ))
+}
+
+fn spawn_bullet(world: &mut World, _: &[&str]) -> CommandResult {
+    let frame = queue_effect(world, ConsoleEffect::FireBullet)?;
+    Ok(format!("Firing a bullet on frame {frame}"))
+}
+
+/// Activates the free pooled bullet with the lowest rollback id, like
+/// `fire_bullets` does
+fn fire_bullet(world: &mut World, player: Entity) -> Result<(), String> {
+    let (handle, position, dir, radius, weapon, class) = world
+        .query::<(&Player, &Position, &MoveDir, &Radius, &Weapon, &PlayerClass)>()
+        .get(world, player)
+        .map(|(player, position, dir, radius, weapon, class)| {
+            (player.handle, position.0, dir.0, radius.0, *weapon, *class)
+        })
+        .map_err(|_| "Local player not spawned yet".to_string())?;
+    let bullet = world
+        .query_filtered::<(Entity, &Rollback, &Active), With<Bullet>>()
+        .iter(world)
+        .filter(|(_, _, active)| !active.0)
+        .map(|(entity, rollback, _)| (rollback.id(), entity))
+        .min()
+        .map(|(_, entity)| entity)
+        .ok_or("No free bullets in the pool")?;
+    let stats = weapon.stats();
+    let bullet_radius = class.stats().bullet_radius_si;
+    world.entity_mut(bullet).insert((
+        Active(true),
+        Position(position + (dir * (bullet_radius + radius)) / DIRECTION_SCALE),
+        MoveDir(dir),
+        Radius(bullet_radius),
+        Lifetime(stats.lifetime_frames),
+        Bounces(0),
+        Damage(stats.damage),
+        BulletSpeed(stats.bullet_speed_percent),
+        Shooter(handle),
+    ));
+    Ok(())
+}
+
+fn give_weapon(world: &mut World, args: &[&str]) -> CommandResult {
+    let name = args.first().ok_or("Missing weapon")?;
+    let weapon = Weapon::ALL
+        .into_iter()
+        .find(|weapon| weapon.name().eq_ignore_ascii_case(name))
+        .ok_or_else(|| format!("Unknown weapon {name:?}"))?;
+    let frame = queue_effect(world, ConsoleEffect::GiveWeapon(weapon))?;
+    Ok(format!("Switching to {weapon:?} on frame {frame}"))
+}
+
+fn set_health(world: &mut World, args: &[&str]) -> CommandResult {
+    let health = parse::<i32>(args, 0)?;
+    let frame = queue_effect(world, ConsoleEffect::SetHealth(health))?;
+    Ok(format!("Setting health to {health} on frame {frame}"))
+}
+
+/// In the lobby the host's settings are broadcast when they change, so this
+/// reaches every peer. Mid-game only practice allows it, as an effect.
+fn set_speed(world: &mut World, args: &[&str]) -> CommandResult {
+    let percent = parse::<i32>(args, 0)?;
+    if world.contains_resource::<LocalPlayerHandle>() {
+        let frame = queue_effect(world, ConsoleEffect::SetSpeed(percent))?;
+        return Ok(format!(
+            "Setting player speed to {percent}% on frame {frame}"
+        ));
+    }
+    let local = world
+        .query_filtered::<&MatchBoxPeerId, With<IsLocal>>()
+        .get_single(world)
+        .map(|peer_id| peer_id.0)
+        .map_err(|_| "Not connected to a room".to_string())?;
+    let host = lobby_host(world.query::<&MatchBoxPeerId>().iter(world));
+    if host != Some(local) {
+        return Err("Only the lobby host can change settings".to_string());
+    }
+    let mut settings = world.resource_mut::<LobbySettings>();
+    let edited = LobbySettings {
+        player_speed_percent: percent,
+        ..settings.clone()
+    }
+    .sanitized();
+    let speed = edited.player_speed_percent;
+    *settings = edited;
+    Ok(format!("Player speed set to {speed}%"))
+}
+
+fn teleport(world: &mut World, args: &[&str]) -> CommandResult {
+    let (x, y) = (parse::<f32>(args, 0)?, parse::<f32>(args, 1)?);
+    let position = IVec2::new((x * F2I as f32) as i32, (y * F2I as f32) as i32);
+    let frame = queue_effect(world, ConsoleEffect::Teleport(position))?;
+    Ok(format!("Teleporting to {x}, {y} on frame {frame}"))
+}
+
+fn toggle_overlay(world: &mut World, args: &[&str]) -> CommandResult {
+    let visible = match *args.first().ok_or("Missing overlay")? {
+        "debug" => {
+            let mut overlay = world.resource_mut::<DebugOverlay>();
+            overlay.visible = !overlay.visible;
+            overlay.visible
+        }
+        "net_stats" => {
+            let mut overlay = world.resource_mut::<NetStatsOverlay>();
+            overlay.visible = !overlay.visible;
+            overlay.visible
+        }
+        "net_sim" => {
+            world.init_resource::<SimulatedConditions>();
+            let mut window = world.resource_mut::<NetSimWindow>();
+            window.open = !window.open;
+            window.open
+        }
+        "snapshot_diff" => {
+            let mut diff = world.resource_mut::<SnapshotDiff>();
+            diff.open = !diff.open;
+            diff.open
+        }
+        other => return Err(format!("Unknown overlay {other:?}")),
+    };
+    Ok(if visible { "Shown" } else { "Hidden" }.to_string())
+}
+
+fn serialized_snapshot(world: &World) -> Result<String, String> {
+    if !world.contains_resource::<LocalPlayerHandle>() {
+        return Err("No game running".to_string());
+    }
+    Ok(world
+        .get_resource::<GGRSStage<GgrsConfig>>()
+        .ok_or("No rollback stage")?
+        .get_serialized_snapshot(world))
+}
+
+/// GGRS only compares checksums every few frames and doesn't expose them, so
+/// this hashes the snapshot instead. Run it on each peer while paused between
+/// rounds, when the frame counters match.
+fn desync_check(world: &mut World, _: &[&str]) -> CommandResult {
+    let snapshot = serialized_snapshot(world)?;
+    let mut hasher = Fnv1a::default();
+    hasher.write(snapshot.as_bytes());
+    let frame = world.resource::<SimFrame>().0;
+    let message = format!("Frame {frame}: checksum {:016x}", hasher.finish());
+    info!("{message}");
+    Ok(message)
+}
+
+/// Only reads the game and stores the save in this tab, so it works in any
+/// game without affecting the peers
+fn save(world: &mut World, _: &[&str]) -> CommandResult {
+    serialized_snapshot(world)?;
+    let save = serialize_game(world);
+    store_save(world, &save);
+    Ok(format!("Saved frame {}", world.resource::<SimFrame>().0))
+}
+
+/// Loading into a running game would be undone by the next rollback, so this
+/// starts a practice game from the save instead, the way the time travel
+/// window does, with the save loaded before the first frame
+fn load(world: &mut World, _: &[&str]) -> CommandResult {
+    if world.resource::<State<GameState>>().0 != GameState::Matchmaking {
+        return Err("Only available in the lobby".to_string());
+    }
+    let room = world.resource::<Room>().0.clone();
+    let tab_id = world
+        .query_filtered::<&TabId, With<IsLocal>>()
+        .get_single(world)
+        .map(|tab_id| tab_id.0.clone())
+        .map_err(|_| "No local tab id".to_string())?;
+    let stores = world.resource::<KeyValueStores>();
+    let save = save_storage::load(stores.area(StorageArea::Local), &room, &tab_id)
+        .ok_or("No save stored for this room")?;
+    let tab_ids = saved_tab_ids(&save.snapshot);
+    if !tab_ids.contains(&tab_id) {
+        return Err("The save has no player of this tab".to_string());
+    }
+    let timestamp = save.timestamp;
+    world.insert_resource(Sandbox {
+        save,
+        tab_ids,
+        own_tab_id: tab_id,
+        bots: false,
+    });
+    world.insert_resource(PracticeMode);
+    world
+        .resource_mut::<NextState<GameState>>()
+        .set(GameState::InGame);
+    Ok(format!("Loading the save from {timestamp}"))
+}
+
+fn dump_snapshot(world: &mut World, _: &[&str]) -> CommandResult {
+    let snapshot = serialized_snapshot(world)?;
+    info!("Snapshot: {snapshot}");
+    let message = format!("Logged and copied {} bytes", snapshot.len());
+    world.resource_mut::<Console>().clipboard = Some(snapshot);
+    Ok(message)
+}
+
+fn save_formats(world: &mut World, _: &[&str]) -> CommandResult {
+    let save = world
+        .query_filtered::<&GameSaveData, With<IsLocal>>()
+        .get_single(world)
+        .map_err(|_| "No game save to measure".to_string())?
+        .clone();
+    let lines = compare_formats(&save)
+        .into_iter()
+        .map(|report| {
+            format!(
+                "{}: {} bytes, encode {:.2} ms, decode {:.2} ms",
+                report.name, report.bytes, report.encode_ms, report.decode_ms
+            )
+        })
+        .collect::<Vec<_>>();
+    for line in &lines {
+        info!("{line}");
+    }
+    Ok(lines.join("\n"))
+}
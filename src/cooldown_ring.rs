@@ -0,0 +1,122 @@
+use crate::{
+    components::{IsLocal, Player},
+    layers::DrawLayer,
+    player::PLAYER_WIDTH_RF,
+    GameState,
+};
+use bevy::prelude::*;
+use std::{f32::consts::TAU, marker::PhantomData};
+
+/// A rollback component that tracks an ability cooldown
+pub trait Cooldown: Component {
+    /// Fraction of the cooldown still to go, or `None` once the ability is ready
+    fn remaining_fraction(&self) -> Option<f32>;
+}
+
+#[derive(Resource)]
+pub struct CooldownRingSettings {
+    pub enabled: bool,
+}
+
+impl Default for CooldownRingSettings {
+    fn default() -> Self {
+        Self { enabled: true }
+    }
+}
+
+const RING_SEGMENTS: usize = 24;
+const RING_SEGMENT_SIZE_RF: f32 = 0.06;
+const RING_SPACING_RF: f32 = 0.1;
+
+#[derive(Component)]
+struct RingSegment<T> {
+    index: usize,
+    marker: PhantomData<T>,
+}
+
+#[derive(Resource)]
+struct RingStyle<T> {
+    color: Color,
+    radius: f32,
+    marker: PhantomData<T>,
+}
+
+/// Draws a segmented ring around the local player showing the cooldown in
+/// `T`. Rings with a higher `ring` index are drawn further out.
+pub fn add_cooldown_ring<T: Cooldown>(app: &mut App, color: Color, ring: u32) {
+    app.init_resource::<CooldownRingSettings>()
+        .insert_resource(RingStyle::<T> {
+            color,
+            radius: PLAYER_WIDTH_RF * 0.75 + ring as f32 * RING_SPACING_RF,
+            marker: PhantomData,
+        })
+        .add_system(spawn_ring_segments::<T>.in_schedule(OnEnter(GameState::InGame)))
+        .add_system(update_ring_segments::<T>.in_set(OnUpdate(GameState::InGame)))
+        .add_system(despawn_ring_segments::<T>.in_schedule(OnExit(GameState::InGame)));
+}
+
+fn spawn_ring_segments<T: Cooldown>(
+    mut commands: Commands,
+    style: Res<RingStyle<T>>,
+    segments: Query<&RingSegment<T>>,
+) {
+    if !segments.is_empty() {
+        return;
+    }
+    for index in 0..RING_SEGMENTS {
+        commands.spawn((
+            RingSegment::<T> {
+                index,
+                marker: PhantomData,
+            },
+            DrawLayer::UiWorld,
+            SpriteBundle {
+                sprite: Sprite {
+                    color: style.color,
+                    custom_size: Some(Vec2::splat(RING_SEGMENT_SIZE_RF)),
+                    ..default()
+                },
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+        ));
+    }
+}
+
+fn update_ring_segments<T: Cooldown>(
+    settings: Res<CooldownRingSettings>,
+    style: Res<RingStyle<T>>,
+    local_player: Query<(&T, &Transform), (With<Player>, With<IsLocal>)>,
+    mut segments: Query<(&RingSegment<T>, &mut Transform, &mut Visibility), Without<Player>>,
+) {
+    let Ok((cooldown, player_transform)) = local_player.get_single() else {
+        return;
+    };
+    let remaining = cooldown
+        .remaining_fraction()
+        .filter(|_| settings.enabled)
+        .unwrap_or(0.);
+    let lit_segments = (remaining * RING_SEGMENTS as f32).ceil() as usize;
+    for (segment, mut transform, mut visibility) in segments.iter_mut() {
+        // Start at 12 o'clock and drain clockwise
+        let angle = TAU / 4. - TAU * segment.index as f32 / RING_SEGMENTS as f32;
+        let offset = Vec2::from_angle(angle) * style.radius;
+        transform.translation.x = player_transform.translation.x + offset.x;
+        transform.translation.y = player_transform.translation.y + offset.y;
+        transform.rotation = Quat::from_rotation_z(angle);
+        *visibility = if segment.index < lit_segments {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+    }
+}
+
+fn despawn_ring_segments<T: Cooldown>(
+    mut commands: Commands,
+    segments: Query<Entity, With<RingSegment<T>>>,
+) {
+    for entity in segments.iter() {
+        commands.entity(entity).despawn();
+    }
+}
@@ -0,0 +1,354 @@
+use crate::{
+    components::{Bullet, Cosmetics, Health, IsLocal, Player, PlayerStats, Shooter, UserInfo},
+    game::GridTheme,
+    layers::DrawLayer,
+    lobby::PracticeMode,
+    presentation_clock::PresentationClock,
+    GameState,
+};
+use bevy::{prelude::*, utils::HashMap};
+use bevy_egui::egui::{CollapsingHeader, ComboBox, Grid, SelectableLabel, Ui};
+use chrono::{Datelike, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Purely visual extras players unlock through local achievements: a trail
+/// behind their square, the color of their bullets and the look of the grid.
+/// Picks are part of [`UserInfo`], so everyone sees a player's trail and
+/// bullets; the grid theme only changes the picking player's own view.
+/// Unlocks are kept per tab in cookies like [`PlayerStats`]. Seasonal
+/// cosmetics are unlocked by playing during their month.
+pub struct CosmeticsPlugin;
+
+impl Plugin for CosmeticsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(unlock_cosmetics)
+            .add_system(apply_grid_theme)
+            .add_systems(
+                (color_bullets, spawn_trails, fade_trails).in_set(OnUpdate(GameState::InGame)),
+            )
+            .add_system(clear_trails.in_schedule(OnExit(GameState::InGame)));
+    }
+}
+
+/// Ids of the cosmetics the local player has unlocked
+#[derive(Serialize, Deserialize, Default, Clone, PartialEq, Debug, Component)]
+pub struct CosmeticUnlocks(pub Vec<String>);
+
+enum Unlock {
+    Free,
+    Wins(u32),
+    Kills(u32),
+    GamesPlayed(u32),
+    /// Playing a game during this month (1-12)
+    Season(u32),
+}
+
+impl Unlock {
+    fn reached(&self, stats: &PlayerStats) -> bool {
+        match *self {
+            Unlock::Free => true,
+            Unlock::Wins(wins) => stats.wins >= wins,
+            Unlock::Kills(kills) => stats.kills >= kills,
+            Unlock::GamesPlayed(games) => stats.games_played >= games,
+            Unlock::Season(month) => stats.games_played > 0 && Utc::now().month() == month,
+        }
+    }
+
+    fn requirement(&self) -> String {
+        match *self {
+            Unlock::Free => String::new(),
+            Unlock::Wins(1) => "Win a round".to_string(),
+            Unlock::Wins(wins) => format!("Win {wins} rounds"),
+            Unlock::Kills(kills) => format!("Get {kills} kills"),
+            Unlock::GamesPlayed(games) => format!("Play {games} games"),
+            Unlock::Season(month) => format!("Play a game in {}", MONTH_NAMES[month as usize - 1]),
+        }
+    }
+}
+
+const MONTH_NAMES: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+struct Cosmetic {
+    /// Stored in [`CosmeticUnlocks`], so it must not change
+    id: &'static str,
+    name: &'static str,
+    /// sRGB
+    color: [u8; 3],
+    unlock: Unlock,
+}
+
+impl Cosmetic {
+    fn color(&self) -> Color {
+        let [r, g, b] = self.color;
+        Color::rgb_u8(r, g, b)
+    }
+
+    fn is_unlocked(&self, unlocks: Option<&CosmeticUnlocks>) -> bool {
+        matches!(self.unlock, Unlock::Free)
+            || unlocks.map_or(false, |unlocks| unlocks.0.iter().any(|id| id == self.id))
+    }
+}
+
+/// The first trail draws nothing
+const TRAILS: [Cosmetic; 4] = [
+    Cosmetic {
+        id: "trail_none",
+        name: "None",
+        color: [0, 0, 0],
+        unlock: Unlock::Free,
+    },
+    Cosmetic {
+        id: "trail_victor",
+        name: "Victor",
+        color: [255, 210, 60],
+        unlock: Unlock::Wins(1),
+    },
+    Cosmetic {
+        id: "trail_hunter",
+        name: "Hunter",
+        color: [220, 40, 40],
+        unlock: Unlock::Kills(100),
+    },
+    Cosmetic {
+        id: "trail_pumpkin",
+        name: "Pumpkin",
+        color: [255, 130, 20],
+        unlock: Unlock::Season(10),
+    },
+];
+
+const BULLET_COLORS: [Cosmetic; 4] = [
+    Cosmetic {
+        id: "bullets_white",
+        name: "White",
+        color: [255, 255, 255],
+        unlock: Unlock::Free,
+    },
+    Cosmetic {
+        id: "bullets_gold",
+        name: "Gold",
+        color: [255, 200, 40],
+        unlock: Unlock::Wins(1),
+    },
+    Cosmetic {
+        id: "bullets_toxic",
+        name: "Toxic",
+        color: [120, 255, 60],
+        unlock: Unlock::Kills(100),
+    },
+    Cosmetic {
+        id: "bullets_frost",
+        name: "Frost",
+        color: [150, 220, 255],
+        unlock: Unlock::Season(12),
+    },
+];
+
+const GRID_THEMES: [Cosmetic; 3] = [
+    Cosmetic {
+        id: "grid_classic",
+        name: "Classic",
+        color: [69, 69, 69],
+        unlock: Unlock::Free,
+    },
+    Cosmetic {
+        id: "grid_neon",
+        name: "Neon",
+        color: [40, 90, 110],
+        unlock: Unlock::GamesPlayed(10),
+    },
+    Cosmetic {
+        id: "grid_blood",
+        name: "Blood",
+        color: [100, 30, 30],
+        unlock: Unlock::Kills(100),
+    },
+];
+
+/// The cosmetic at `index` in `table`, or the default one for indices that
+/// don't exist in this build
+fn pick(table: &[Cosmetic], index: u8) -> &Cosmetic {
+    table.get(index as usize).unwrap_or(&table[0])
+}
+
+fn all_cosmetics() -> impl Iterator<Item = &'static Cosmetic> {
+    TRAILS
+        .iter()
+        .chain(BULLET_COLORS.iter())
+        .chain(GRID_THEMES.iter())
+}
+
+/// Unlocks whatever the local player's stats have earned. Unlocks are kept
+/// even if the stats are lost later.
+fn unlock_cosmetics(
+    mut local_player: Query<
+        (&PlayerStats, &mut CosmeticUnlocks),
+        (With<IsLocal>, Changed<PlayerStats>),
+    >,
+    practice: Option<Res<PracticeMode>>,
+) {
+    if practice.is_some() {
+        return;
+    }
+    for (stats, mut unlocks) in local_player.iter_mut() {
+        let new = all_cosmetics()
+            .filter(|cosmetic| !matches!(cosmetic.unlock, Unlock::Free))
+            .filter(|cosmetic| !unlocks.0.iter().any(|id| id == cosmetic.id))
+            .filter(|cosmetic| cosmetic.unlock.reached(stats))
+            .collect::<Vec<_>>();
+        for cosmetic in new {
+            info!("Unlocked cosmetic {}", cosmetic.id);
+            unlocks.0.push(cosmetic.id.to_string());
+        }
+    }
+}
+
+fn apply_grid_theme(
+    local_player: Query<&UserInfo, (With<IsLocal>, Changed<UserInfo>)>,
+    mut theme: ResMut<GridTheme>,
+) {
+    if let Ok(info) = local_player.get_single() {
+        let color = pick(&GRID_THEMES, info.cosmetics.grid).color();
+        if theme.line_color != color {
+            theme.line_color = color;
+        }
+    }
+}
+
+/// Bullets are fired white in the rollback schedule, which must not depend
+/// on cosmetics, and colored here afterwards. Firing reassigns the shooter of
+/// the pooled bullet it reuses.
+fn color_bullets(
+    mut bullets: Query<(&Shooter, &mut TextureAtlasSprite), (With<Bullet>, Changed<Shooter>)>,
+    players: Query<(&Player, &UserInfo)>,
+) {
+    for (shooter, mut sprite) in bullets.iter_mut() {
+        if let Some((_, info)) = players
+            .iter()
+            .find(|(player, _)| player.handle == shooter.0)
+        {
+            sprite.color = pick(&BULLET_COLORS, info.cosmetics.bullets).color();
+        }
+    }
+}
+
+/// Seconds a trail dot stays visible
+const TRAIL_SECS: f32 = 0.4;
+/// Distance a player moves before the next dot is dropped
+const TRAIL_SPACING: f32 = 0.15;
+const TRAIL_DOT_SIZE: f32 = 0.2;
+
+#[derive(Component)]
+struct TrailDot {
+    secs_left: f32,
+}
+
+fn spawn_trails(
+    mut commands: Commands,
+    players: Query<(&Player, &UserInfo, &Health, &Transform)>,
+    mut last_dots: Local<HashMap<usize, Vec2>>,
+) {
+    for (player, info, health, transform) in players.iter() {
+        if info.cosmetics.trail == 0 || health.0 <= 0 {
+            continue;
+        }
+        let position = transform.translation.truncate();
+        let last = last_dots.entry(player.handle).or_insert(position);
+        if last.distance(position) < TRAIL_SPACING {
+            continue;
+        }
+        *last = position;
+        commands.spawn((
+            TrailDot {
+                secs_left: TRAIL_SECS,
+            },
+            DrawLayer::Vfx,
+            SpriteBundle {
+                sprite: Sprite {
+                    color: pick(&TRAILS, info.cosmetics.trail).color(),
+                    custom_size: Some(Vec2::splat(TRAIL_DOT_SIZE)),
+                    ..default()
+                },
+                transform: Transform::from_translation(position.extend(DrawLayer::Vfx.z())),
+                ..default()
+            },
+        ));
+    }
+}
+
+fn fade_trails(
+    mut commands: Commands,
+    clock: Res<PresentationClock>,
+    mut dots: Query<(Entity, &mut TrailDot, &mut Sprite)>,
+) {
+    let delta = clock.delta_seconds();
+    for (entity, mut dot, mut sprite) in dots.iter_mut() {
+        dot.secs_left -= delta;
+        if dot.secs_left <= 0. {
+            commands.entity(entity).despawn();
+        } else {
+            sprite.color.set_a(dot.secs_left / TRAIL_SECS);
+        }
+    }
+}
+
+fn clear_trails(mut commands: Commands, dots: Query<Entity, With<TrailDot>>) {
+    for entity in dots.iter() {
+        commands.entity(entity).despawn();
+    }
+}
+
+/// The lobby's "Cosmetics" section. Locked cosmetics are listed with what it
+/// takes to unlock them.
+pub fn cosmetics_ui(ui: &mut Ui, cosmetics: &mut Cosmetics, unlocks: Option<&CosmeticUnlocks>) {
+    CollapsingHeader::new("Cosmetics").show(ui, |ui| {
+        Grid::new("cosmetics").show(ui, |ui| {
+            for (label, table, index) in [
+                ("Trail", &TRAILS[..], &mut cosmetics.trail),
+                ("Bullets", &BULLET_COLORS[..], &mut cosmetics.bullets),
+                ("Grid", &GRID_THEMES[..], &mut cosmetics.grid),
+            ] {
+                ui.label(label);
+                cosmetic_picker(ui, label, table, index, unlocks);
+                ui.end_row();
+            }
+        });
+    });
+}
+
+fn cosmetic_picker(
+    ui: &mut Ui,
+    id: &str,
+    table: &[Cosmetic],
+    index: &mut u8,
+    unlocks: Option<&CosmeticUnlocks>,
+) {
+    ComboBox::from_id_source(("cosmetic", id))
+        .selected_text(pick(table, *index).name)
+        .show_ui(ui, |ui| {
+            for (i, cosmetic) in table.iter().enumerate() {
+                let unlocked = cosmetic.is_unlocked(unlocks);
+                let label = SelectableLabel::new(*index as usize == i, cosmetic.name);
+                if ui
+                    .add_enabled(unlocked, label)
+                    .on_disabled_hover_text(cosmetic.unlock.requirement())
+                    .clicked()
+                {
+                    *index = i as u8;
+                }
+            }
+        });
+}
@@ -0,0 +1,70 @@
+use crate::GameState;
+use bevy::prelude::*;
+use bevy_egui::{
+    egui::{Align2, Area, RichText},
+    EguiContexts,
+};
+use chrono::{DateTime, Duration, Utc};
+
+/// Gives everyone a moment between the last player readying up and the game
+/// starting. The lobby host picks the start time and sends it to everyone as
+/// [`crate::net::P2PMessage::StartAt`], and cancels it the same way if anyone
+/// un-readies before then. Ready states are locked for the final moments, so
+/// nobody can flip theirs while the others are already starting.
+pub struct CountdownPlugin;
+
+impl Plugin for CountdownPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(
+            countdown_ui
+                .in_set(OnUpdate(GameState::Matchmaking))
+                .run_if(resource_exists::<StartCountdown>()),
+        )
+        .add_system(clear_countdown.in_schedule(OnExit(GameState::Matchmaking)));
+    }
+}
+
+const START_COUNTDOWN_SECS: i64 = 3;
+/// Ready states can't be changed once the start is this close
+const LOCK_MILLIS: i64 = 1000;
+
+/// Present while the game is about to start
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct StartCountdown {
+    pub at: DateTime<Utc>,
+}
+
+impl StartCountdown {
+    pub fn from_now() -> Self {
+        Self {
+            at: Utc::now() + Duration::seconds(START_COUNTDOWN_SECS),
+        }
+    }
+
+    pub fn is_over(&self) -> bool {
+        Utc::now() >= self.at
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.at - Utc::now() < Duration::milliseconds(LOCK_MILLIS)
+    }
+}
+
+fn clear_countdown(mut commands: Commands) {
+    commands.remove_resource::<StartCountdown>();
+}
+
+fn countdown_ui(mut contexts: EguiContexts, countdown: Res<StartCountdown>) {
+    let millis_left = (countdown.at - Utc::now()).num_milliseconds();
+    let text = if millis_left > 0 {
+        format!("Starting in {}…", (millis_left + 999) / 1000)
+    } else {
+        "Starting…".to_string()
+    };
+    Area::new("start_countdown")
+        .anchor(Align2::CENTER_CENTER, [0., 0.])
+        .interactable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.label(RichText::new(text).size(48.).strong());
+        });
+}
@@ -0,0 +1,73 @@
+use crate::{
+    components::{Health, Player},
+    weapons::MoveSpread,
+    GameState, LocalPlayerHandle,
+};
+use bevy::{prelude::*, window::PrimaryWindow};
+use bevy_egui::{
+    egui::{self, Color32, LayerId, Order, Stroke},
+    EguiContexts, EguiSettings,
+};
+
+/// A crosshair at the mouse cursor whose arms spread apart while the local
+/// player's shots are thrown off by moving, and close in again as they stand
+/// still, see [`MoveSpread`]
+pub struct CrosshairPlugin;
+
+impl Plugin for CrosshairPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(draw_crosshair.in_set(OnUpdate(GameState::InGame)));
+    }
+}
+
+/// Gap between the center and each arm when aim is perfect, in points
+const CROSSHAIR_GAP: f32 = 4.;
+/// Extra gap at the full movement spread
+const CROSSHAIR_SPREAD_GAP: f32 = 14.;
+const CROSSHAIR_ARM_LENGTH: f32 = 6.;
+const CROSSHAIR_COLOR: Color32 = Color32::from_rgba_premultiplied(230, 230, 230, 220);
+
+fn draw_crosshair(
+    mut contexts: EguiContexts,
+    egui_settings: Res<EguiSettings>,
+    local_handle: Option<Res<LocalPlayerHandle>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    players: Query<(&Player, &Health, &MoveSpread)>,
+) {
+    let Some(local_handle) = local_handle else {
+        return;
+    };
+    let Some((_, _, spread)) = players
+        .iter()
+        .find(|(player, health, _)| player.handle == local_handle.0 && health.0 > 0)
+    else {
+        return;
+    };
+    let Ok(window) = windows.get_single() else {
+        return;
+    };
+    let Some(cursor) = window.cursor_position() else {
+        return;
+    };
+    let scale = egui_settings.scale_factor as f32;
+    // Window y points up, egui's down
+    let center = egui::pos2(cursor.x / scale, (window.height() - cursor.y) / scale);
+    let gap = CROSSHAIR_GAP + spread.fraction() * CROSSHAIR_SPREAD_GAP;
+    let painter = contexts
+        .ctx_mut()
+        .layer_painter(LayerId::new(Order::Foreground, egui::Id::new("crosshair")));
+    for direction in [
+        egui::vec2(1., 0.),
+        egui::vec2(-1., 0.),
+        egui::vec2(0., 1.),
+        egui::vec2(0., -1.),
+    ] {
+        painter.line_segment(
+            [
+                center + direction * gap,
+                center + direction * (gap + CROSSHAIR_ARM_LENGTH),
+            ],
+            Stroke::new(1.5, CROSSHAIR_COLOR),
+        );
+    }
+}
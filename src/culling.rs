@@ -0,0 +1,44 @@
+use crate::{components::Position, GameState, IVec2Ext};
+use bevy::{prelude::*, render::view::VisibilitySystems};
+
+/// Keeps sprites well outside the camera's view from being drawn, so big
+/// matches full of bullets and pickups don't cost the browser draw work for
+/// things nobody can see. It only ever hides, after bevy computed what's
+/// visible for the frame, so it never fights the systems that show and hide
+/// sprites for gameplay reasons.
+pub struct CullingPlugin;
+
+impl Plugin for CullingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(
+            cull_offscreen
+                .in_base_set(CoreSet::PostUpdate)
+                .after(VisibilitySystems::CheckVisibility)
+                .run_if(in_state(GameState::InGame)),
+        );
+    }
+}
+
+/// How far past the edge of the view sprites are still drawn, so nothing
+/// large pops in at the edge
+const CULL_MARGIN_RF: f32 = 2.;
+
+fn cull_offscreen(
+    cameras: Query<(&GlobalTransform, &OrthographicProjection), With<Camera>>,
+    mut entities: Query<(&Position, &mut ComputedVisibility)>,
+) {
+    let Ok((camera, projection)) = cameras.get_single() else {
+        return;
+    };
+    let center = camera.translation().truncate();
+    let half_size = projection.area.size() / 2. + Vec2::splat(CULL_MARGIN_RF);
+    for (position, mut visibility) in entities.iter_mut() {
+        if !visibility.is_visible() {
+            continue;
+        }
+        let offset = (position.0.i2f() - center).abs();
+        if offset.x > half_size.x || offset.y > half_size.y {
+            *visibility = ComputedVisibility::HIDDEN;
+        }
+    }
+}
@@ -0,0 +1,67 @@
+#[cfg(feature = "presentation")]
+use crate::cooldown_ring::Cooldown;
+use crate::{
+    components::{Health, Player},
+    input::{dash, direction},
+    rng::advance_sim_frame,
+    simulation::move_players,
+    GgrsConfig,
+};
+use bevy::prelude::*;
+use bevy_ggrs::{GGRSSchedule, PlayerInputs};
+
+/// A short burst of speed in the direction the player is moving, on a
+/// cooldown so it's a dodge rather than a way to get around
+pub struct DashPlugin;
+
+impl Plugin for DashPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(
+            start_dash
+                .after(advance_sim_frame)
+                .before(move_players)
+                .in_schedule(GGRSSchedule),
+        );
+    }
+}
+
+pub const DASH_FRAMES: u32 = 8;
+/// Frames from the start of a dash until the next one, the dash included
+pub const DASH_COOLDOWN_FRAMES: u32 = 90;
+/// Movement speed while dashing, as a percentage of the usual speed
+pub const DASH_SPEED_PERCENT: i32 = 300;
+
+/// Frames left until the player can dash again
+#[derive(Component, Reflect, Default, Clone, Copy, Debug)]
+pub struct DashCooldown(pub u32);
+
+impl DashCooldown {
+    /// Whether the player is in the first [`DASH_FRAMES`] of the cooldown
+    pub fn is_dashing(&self) -> bool {
+        self.0 > DASH_COOLDOWN_FRAMES - DASH_FRAMES
+    }
+}
+
+#[cfg(feature = "presentation")]
+impl Cooldown for DashCooldown {
+    fn remaining_fraction(&self) -> Option<f32> {
+        (self.0 > 0).then(|| self.0 as f32 / DASH_COOLDOWN_FRAMES as f32)
+    }
+}
+
+fn start_dash(
+    inputs: Res<PlayerInputs<GgrsConfig>>,
+    mut players: Query<(&Player, &Health, &mut DashCooldown)>,
+) {
+    for (player, health, mut cooldown) in players.iter_mut() {
+        if cooldown.0 > 0 {
+            cooldown.0 -= 1;
+            continue;
+        }
+        let (input, _) = inputs[player.handle];
+        // Dashing on the spot would only waste the cooldown
+        if health.0 > 0 && dash(input) && direction(input) != IVec2::ZERO {
+            cooldown.0 = DASH_COOLDOWN_FRAMES;
+        }
+    }
+}
@@ -0,0 +1,216 @@
+use crate::{
+    components::{Active, Bullet, HealthBar, Player},
+    launch_config::LaunchConfig,
+    simulation::SimulationTimingSet,
+};
+use bevy::{
+    diagnostic::{
+        Diagnostic, DiagnosticId, Diagnostics, EntityCountDiagnosticsPlugin,
+        FrameTimeDiagnosticsPlugin,
+    },
+    prelude::*,
+    utils::Instant,
+};
+use bevy_egui::{
+    egui::{self, Grid},
+    EguiContexts,
+};
+use bevy_ggrs::GGRSSchedule;
+
+/// Entity, archetype and timing stats for perf tuning. Toggled with F3, or
+/// shown from the start by launch links with `overlays=debug`.
+pub struct DebugOverlayPlugin;
+
+impl Plugin for DebugOverlayPlugin {
+    fn build(&self, app: &mut App) {
+        let visible = app.world.resource::<LaunchConfig>().debug_overlay;
+        app.add_plugin(FrameTimeDiagnosticsPlugin)
+            .add_plugin(EntityCountDiagnosticsPlugin)
+            .insert_resource(DebugOverlay {
+                visible,
+                ..default()
+            })
+            .init_resource::<SimulationTimer>()
+            .add_startup_system(setup_diagnostics)
+            .add_system(begin_simulation_timing.in_base_set(CoreSet::First))
+            .add_system(
+                start_simulation_step
+                    .in_schedule(GGRSSchedule)
+                    .in_base_set(SimulationTimingSet::Start),
+            )
+            .add_system(
+                end_simulation_step
+                    .in_schedule(GGRSSchedule)
+                    .in_base_set(SimulationTimingSet::End),
+            )
+            .add_system(toggle_debug_overlay)
+            .add_system(collect_world_stats.after(toggle_debug_overlay))
+            .add_system(debug_overlay_ui.after(collect_world_stats));
+    }
+}
+
+/// Time spent in the rollback schedule per frame, including resimulated frames
+pub const SIMULATION_TIME: DiagnosticId =
+    DiagnosticId::from_u128(0x5d2c_7f37_95a4_4bd6_9a1e_0c4b_11f3_9e02);
+
+const MAX_LISTED_ARCHETYPES: usize = 12;
+
+fn setup_diagnostics(mut diagnostics: ResMut<Diagnostics>) {
+    diagnostics.add(Diagnostic::new(SIMULATION_TIME, "simulation_time_ms", 120));
+}
+
+#[derive(Resource, Default)]
+pub struct DebugOverlay {
+    pub visible: bool,
+    stats: WorldStats,
+}
+
+#[derive(Default)]
+struct WorldStats {
+    entities: usize,
+    players: usize,
+    bullets: usize,
+    health_bars: usize,
+    archetypes: Vec<ArchetypeStats>,
+    estimated_bytes: usize,
+}
+
+struct ArchetypeStats {
+    components: String,
+    entities: usize,
+}
+
+#[derive(Resource, Default)]
+struct SimulationTimer {
+    step_started: Option<Instant>,
+    frame_total_ms: f64,
+}
+
+fn begin_simulation_timing(mut timer: ResMut<SimulationTimer>, mut diagnostics: ResMut<Diagnostics>) {
+    diagnostics.add_measurement(SIMULATION_TIME, || timer.frame_total_ms);
+    timer.frame_total_ms = 0.;
+}
+
+fn start_simulation_step(mut timer: ResMut<SimulationTimer>) {
+    timer.step_started = Some(Instant::now());
+}
+
+fn end_simulation_step(mut timer: ResMut<SimulationTimer>) {
+    if let Some(started) = timer.step_started.take() {
+        timer.frame_total_ms += started.elapsed().as_secs_f64() * 1000.;
+    }
+}
+
+fn toggle_debug_overlay(keys: Res<Input<KeyCode>>, mut overlay: ResMut<DebugOverlay>) {
+    if keys.just_pressed(KeyCode::F3) {
+        overlay.visible = !overlay.visible;
+    }
+}
+
+fn collect_world_stats(world: &mut World) {
+    if !world.resource::<DebugOverlay>().visible {
+        return;
+    }
+    let players = world.query::<&Player>().iter(world).len();
+    let bullets = world
+        .query_filtered::<&Active, With<Bullet>>()
+        .iter(world)
+        .filter(|active| active.0)
+        .count();
+    let health_bars = world.query::<&HealthBar>().iter(world).len();
+
+    let components = world.components();
+    let mut estimated_bytes = 0;
+    let mut archetypes = world
+        .archetypes()
+        .iter()
+        .filter(|archetype| !archetype.is_empty())
+        .map(|archetype| {
+            let mut names = Vec::new();
+            let mut row_bytes = 0;
+            for info in archetype.components().filter_map(|id| components.get_info(id)) {
+                row_bytes += info.layout().size();
+                let name = info.name();
+                names.push(name.rsplit("::").next().unwrap_or(name).to_string());
+            }
+            estimated_bytes += row_bytes * archetype.len();
+            names.sort();
+            ArchetypeStats {
+                components: names.join(", "),
+                entities: archetype.len(),
+            }
+        })
+        .collect::<Vec<_>>();
+    archetypes.sort_by(|a, b| b.entities.cmp(&a.entities));
+    archetypes.truncate(MAX_LISTED_ARCHETYPES);
+
+    let entities = world.entities().len() as usize;
+    world.resource_mut::<DebugOverlay>().stats = WorldStats {
+        entities,
+        players,
+        bullets,
+        health_bars,
+        archetypes,
+        estimated_bytes,
+    };
+}
+
+fn debug_overlay_ui(
+    mut contexts: EguiContexts,
+    overlay: Res<DebugOverlay>,
+    diagnostics: Res<Diagnostics>,
+) {
+    if !overlay.visible {
+        return;
+    }
+    let smoothed = |id| {
+        diagnostics
+            .get(id)
+            .and_then(|d| d.smoothed())
+            .unwrap_or_default()
+    };
+    let stats = &overlay.stats;
+    egui::Window::new("Debug stats")
+        .default_pos([10., 10.])
+        .show(contexts.ctx_mut(), |ui| {
+            Grid::new("debug_stats_timing").show(ui, |ui| {
+                ui.label("FPS");
+                ui.label(format!("{:.0}", smoothed(FrameTimeDiagnosticsPlugin::FPS)));
+                ui.end_row();
+                ui.label("Frame time");
+                ui.label(format!(
+                    "{:.2} ms",
+                    smoothed(FrameTimeDiagnosticsPlugin::FRAME_TIME)
+                ));
+                ui.end_row();
+                ui.label("Simulation time");
+                ui.label(format!("{:.2} ms", smoothed(SIMULATION_TIME)));
+                ui.end_row();
+            });
+            ui.separator();
+            Grid::new("debug_stats_entities").show(ui, |ui| {
+                for (label, count) in [
+                    ("Entities", stats.entities),
+                    ("Players", stats.players),
+                    ("Bullets", stats.bullets),
+                    ("Health bars", stats.health_bars),
+                ] {
+                    ui.label(label);
+                    ui.label(count.to_string());
+                    ui.end_row();
+                }
+                ui.label("Component data");
+                ui.label(format!("~{:.1} KiB", stats.estimated_bytes as f64 / 1024.));
+                ui.end_row();
+            });
+            ui.separator();
+            ui.label("Largest archetypes");
+            Grid::new("debug_stats_archetypes").striped(true).show(ui, |ui| {
+                for archetype in stats.archetypes.iter() {
+                    ui.label(archetype.entities.to_string());
+                    ui.label(&archetype.components);
+                    ui.end_row();
+                }
+            });
+        });
+}
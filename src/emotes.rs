@@ -0,0 +1,195 @@
+use crate::{
+    components::{Health, IsLocal, MatchBoxPeerId, Player, Position},
+    fixed_timestep::FixedTimestep,
+    key_bindings::{Action, KeyBindings},
+    lobby::{SocketExt, Spectating},
+    lobby_settings::LobbySettings,
+    net::{read_messages, Messages, P2PMessage},
+    player::PLAYER_WIDTH_RF,
+    save::kill_game,
+    vision::Vision,
+    GameState,
+};
+use bevy::{prelude::*, transform::TransformSystem, utils::HashMap};
+use bevy_egui::{
+    egui::{self, Align2, Area, FontId, LayerId, Order, RichText},
+    EguiContexts, EguiSet, EguiSettings,
+};
+use bevy_matchbox::prelude::*;
+use std::f32::consts::TAU;
+
+/// Holding the emote key opens a wheel of emotes. The one picked floats over
+/// the sender's character for a moment on every peer. Emotes go out as
+/// [`P2PMessage::Emote`] on the reliable channel and never enter the
+/// simulation, so they can't desync anything.
+pub struct EmotesPlugin;
+
+impl Plugin for EmotesPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Emotes>()
+            .add_systems(
+                (
+                    receive_emotes.after(read_messages).before(kill_game),
+                    emote_wheel,
+                )
+                    .in_set(OnUpdate(GameState::InGame)),
+            )
+            .add_system(
+                draw_emotes
+                    .in_base_set(CoreSet::PostUpdate)
+                    .after(TransformSystem::TransformPropagate)
+                    .before(EguiSet::ProcessOutput)
+                    .run_if(in_state(GameState::InGame)),
+            )
+            .add_system(clear_emotes.in_schedule(OnExit(GameState::InGame)));
+    }
+}
+
+/// What [`P2PMessage::Emote`] indexes into
+pub const EMOTES: [&str; 6] = ["👍", "👋", "😂", "😮", "😡", "❤"];
+
+const EMOTE_SECS: f32 = 2.5;
+/// Least time between two emotes from the local player
+const EMOTE_COOLDOWN_SECS: f32 = 1.;
+/// Distance of the wheel's buttons from the middle of the screen, in points
+const WHEEL_RADIUS: f32 = 70.;
+const WHEEL_ICON_SIZE: f32 = 28.;
+/// Height of the emote's bottom edge above the player's center, above the
+/// name tag
+const EMOTE_OFFSET_RF: f32 = PLAYER_WIDTH_RF * 1.7;
+const EMOTE_FONT_SIZE: f32 = 26.;
+
+#[derive(Resource, Default)]
+struct Emotes {
+    /// The emote shown over each peer's character and seconds left on screen
+    shown: HashMap<PeerId, (u8, f32)>,
+    /// Seconds until the local player can emote again
+    cooldown: f32,
+}
+
+impl Emotes {
+    fn show(&mut self, peer_id: PeerId, emote: u8) {
+        if (emote as usize) < EMOTES.len() {
+            self.shown.insert(peer_id, (emote, EMOTE_SECS));
+        }
+    }
+}
+
+/// Takes emotes out of the mid-game messages, which would otherwise make
+/// [`kill_game`] restart the session
+fn receive_emotes(mut messages: ResMut<Messages>, mut emotes: ResMut<Emotes>) {
+    messages
+        .0
+        .retain(|(peer_id, packet)| match P2PMessage::decode(packet) {
+            Ok(P2PMessage::Emote(emote)) => {
+                emotes.show(*peer_id, emote);
+                false
+            }
+            _ => true,
+        });
+}
+
+/// The wheel stays open while the emote key is held, and a click on one of
+/// its buttons sends that emote
+fn emote_wheel(
+    mut contexts: EguiContexts,
+    timestep: Res<FixedTimestep>,
+    keys: Res<Input<KeyCode>>,
+    mut emotes: ResMut<Emotes>,
+    mut socket: Option<ResMut<MatchboxSocket<MultipleChannels>>>,
+    spectating: Option<Res<Spectating>>,
+    local_player: Query<(&MatchBoxPeerId, &KeyBindings), (With<IsLocal>, With<Player>)>,
+) {
+    emotes.cooldown = (emotes.cooldown - timestep.real_delta_seconds()).max(0.);
+    let Ok((local_peer_id, bindings)) = local_player.get_single() else {
+        return;
+    };
+    if spectating.is_some() || !bindings.pressed(&keys, Action::Emote) {
+        return;
+    }
+    let ctx = contexts.ctx_mut();
+    let center = ctx.screen_rect().center();
+    let mut chosen = None;
+    for (index, icon) in EMOTES.iter().enumerate() {
+        let angle = index as f32 / EMOTES.len() as f32 * TAU;
+        Area::new(("emote_wheel", index))
+            .fixed_pos(center + WHEEL_RADIUS * egui::vec2(angle.sin(), -angle.cos()))
+            .pivot(Align2::CENTER_CENTER)
+            .show(ctx, |ui| {
+                let button = egui::Button::new(RichText::new(*icon).size(WHEEL_ICON_SIZE));
+                if ui.add_enabled(emotes.cooldown == 0., button).clicked() {
+                    chosen = Some(index as u8);
+                }
+            });
+    }
+    let Some(emote) = chosen else {
+        return;
+    };
+    emotes.cooldown = EMOTE_COOLDOWN_SECS;
+    emotes.show(local_peer_id.0, emote);
+    if let Some(socket) = socket.as_mut() {
+        for peer_id in socket.connected_peers().collect::<Vec<_>>() {
+            socket.send_p2p_message(&peer_id, P2PMessage::Emote(emote));
+        }
+    }
+}
+
+/// Painted with egui at the players' projected screen positions, like the
+/// name tags
+fn draw_emotes(
+    mut contexts: EguiContexts,
+    timestep: Res<FixedTimestep>,
+    mut emotes: ResMut<Emotes>,
+    egui_settings: Res<EguiSettings>,
+    vision: Res<Vision>,
+    settings: Res<LobbySettings>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    players: Query<(&MatchBoxPeerId, &Health, &Position, &GlobalTransform), With<Player>>,
+) {
+    let delta = timestep.real_delta_seconds();
+    emotes.shown.retain(|_, (_, secs_left)| {
+        *secs_left -= delta;
+        *secs_left > 0.
+    });
+    if emotes.shown.is_empty() {
+        return;
+    }
+    let Some((camera, camera_transform)) = cameras.iter().find(|(camera, _)| camera.is_active)
+    else {
+        return;
+    };
+    let Some(viewport_size) = camera.logical_viewport_size() else {
+        return;
+    };
+    let scale = egui_settings.scale_factor as f32;
+    let painter = contexts
+        .ctx_mut()
+        .layer_painter(LayerId::new(Order::Background, egui::Id::new("emotes")));
+    for (peer_id, health, position, transform) in players.iter() {
+        let Some(&(emote, secs_left)) = emotes.shown.get(&peer_id.0) else {
+            continue;
+        };
+        if health.0 <= 0 || !vision.can_see(position.0, &settings) {
+            continue;
+        }
+        let anchor = transform.translation() + Vec3::Y * EMOTE_OFFSET_RF;
+        let Some(position) = camera.world_to_viewport(camera_transform, anchor) else {
+            continue;
+        };
+        // Viewport y points up, egui's down
+        let position = egui::pos2(position.x / scale, (viewport_size.y - position.y) / scale);
+        // Fades out over its last half second
+        let alpha = (secs_left / 0.5).min(1.);
+        painter.text(
+            position,
+            Align2::CENTER_BOTTOM,
+            EMOTES[emote as usize],
+            FontId::proportional(EMOTE_FONT_SIZE),
+            egui::Color32::WHITE.linear_multiply(alpha),
+        );
+    }
+}
+
+fn clear_emotes(mut emotes: ResMut<Emotes>) {
+    *emotes = default();
+}
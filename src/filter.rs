@@ -0,0 +1,124 @@
+use crate::{components::UserInfo, GameState};
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    utils::{BoxedFuture, HashSet},
+};
+use bevy_asset_loader::prelude::*;
+use serde::Deserialize;
+use std::borrow::Cow;
+
+/// Display-side word filter applied to text coming from other players
+pub struct FilterPlugin;
+
+impl Plugin for FilterPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<Blocklist>()
+            .init_asset_loader::<BlocklistLoader>()
+            .init_resource::<WordFilter>()
+            .add_system(load_blocklist.in_schedule(OnExit(GameState::AssetLoading)));
+    }
+}
+
+#[derive(AssetCollection, Resource)]
+pub struct FilterAssets {
+    #[asset(path = "default.blocklist.ron")]
+    blocklist: Handle<Blocklist>,
+}
+
+/// List of words to censor, loaded from a `*.blocklist.ron` asset
+#[derive(Deserialize, TypeUuid, Debug)]
+#[uuid = "5c1b8b55-35a4-4d58-9e0e-7bb0f0fd3b1e"]
+pub struct Blocklist {
+    pub words: Vec<String>,
+}
+
+#[derive(Default)]
+struct BlocklistLoader;
+
+impl AssetLoader for BlocklistLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let blocklist = ron::de::from_bytes::<Blocklist>(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(blocklist));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["blocklist.ron"]
+    }
+}
+
+#[derive(Resource)]
+pub struct WordFilter {
+    pub enabled: bool,
+    words: HashSet<String>,
+}
+
+impl Default for WordFilter {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            words: default(),
+        }
+    }
+}
+
+impl WordFilter {
+    /// Replaces every blocked word in `text` with asterisks. Matching is
+    /// case-insensitive and only considers whole words.
+    pub fn censor<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        if !self.enabled || self.words.is_empty() {
+            return Cow::Borrowed(text);
+        }
+        let mut censored = String::with_capacity(text.len());
+        let mut changed = false;
+        let mut word_start = None;
+        for (index, c) in text.char_indices().chain(std::iter::once((text.len(), ' '))) {
+            match (c.is_alphanumeric(), word_start) {
+                (true, None) => word_start = Some(index),
+                (false, Some(start)) => {
+                    let word = &text[start..index];
+                    if self.words.contains(&word.to_lowercase()) {
+                        censored.extend(word.chars().map(|_| '*'));
+                        changed = true;
+                    } else {
+                        censored.push_str(word);
+                    }
+                    word_start = None;
+                }
+                _ => {}
+            }
+            if word_start.is_none() && index < text.len() {
+                censored.push(c);
+            }
+        }
+        if changed {
+            Cow::Owned(censored)
+        } else {
+            Cow::Borrowed(text)
+        }
+    }
+}
+
+/// The name to show for a player, with blocked words censored
+pub fn display_name<'a>(filter: &WordFilter, info: &'a UserInfo) -> Cow<'a, str> {
+    filter.censor(&info.name)
+}
+
+fn load_blocklist(
+    mut filter: ResMut<WordFilter>,
+    assets: Res<FilterAssets>,
+    blocklists: Res<Assets<Blocklist>>,
+) {
+    if let Some(blocklist) = blocklists.get(&assets.blocklist) {
+        filter.words = blocklist.words.iter().map(|w| w.to_lowercase()).collect();
+        info!("Loaded {} blocked words", filter.words.len());
+    }
+}
@@ -0,0 +1,116 @@
+use crate::F2I;
+use bevy::prelude::*;
+
+/// Angle steps in a full turn. Angles are counter-clockwise from +x.
+///
+/// Float `sin` and `cos` may round differently between platforms, which
+/// would desync peers, so the simulation turns angles into directions through
+/// a lookup table instead. Results are in its I20F12 fixed-point format, i.e.
+/// scaled by [`F2I`].
+pub const TURN: i32 = 512;
+const QUARTER_TURN: i32 = TURN / 4;
+
+/// Steps of [`TURN`] per step of `PlayerInput::aim`
+pub const AIM_STEP: i32 = TURN / 256;
+
+/// `sin` of every step of the first quarter turn, both ends included
+const SIN_TABLE: [i32; QUARTER_TURN as usize + 1] = [
+    0, 50, 101, 151, 201, 251, 301, 351, 401, 451, 501, 551, //
+    601, 651, 700, 750, 799, 848, 897, 946, 995, 1044, 1092, 1141, //
+    1189, 1237, 1285, 1332, 1380, 1427, 1474, 1521, 1567, 1614, 1660, 1706, //
+    1751, 1797, 1842, 1886, 1931, 1975, 2019, 2062, 2106, 2149, 2191, 2234, //
+    2276, 2317, 2359, 2399, 2440, 2480, 2520, 2559, 2598, 2637, 2675, 2713, //
+    2751, 2788, 2824, 2861, 2896, 2932, 2967, 3001, 3035, 3068, 3102, 3134, //
+    3166, 3198, 3229, 3260, 3290, 3320, 3349, 3378, 3406, 3433, 3461, 3487, //
+    3513, 3539, 3564, 3588, 3612, 3636, 3659, 3681, 3703, 3724, 3745, 3765, //
+    3784, 3803, 3822, 3839, 3857, 3873, 3889, 3905, 3920, 3934, 3948, 3961, //
+    3973, 3985, 3996, 4007, 4017, 4027, 4036, 4044, 4052, 4059, 4065, 4071, //
+    4076, 4081, 4085, 4088, 4091, 4093, 4095, 4096, 4096,
+];
+
+/// `sin(angle)`, scaled by [`F2I`]
+pub fn sin(angle: i32) -> i32 {
+    let angle = angle.rem_euclid(TURN);
+    let (step, sign) = match angle / QUARTER_TURN {
+        0 => (angle, 1),
+        1 => (2 * QUARTER_TURN - angle, 1),
+        2 => (angle - 2 * QUARTER_TURN, -1),
+        _ => (TURN - angle, -1),
+    };
+    sign * SIN_TABLE[step as usize]
+}
+
+/// `cos(angle)`, scaled by [`F2I`]
+pub fn cos(angle: i32) -> i32 {
+    sin(angle + QUARTER_TURN)
+}
+
+/// Vector of length `scale` pointing at `angle`
+pub fn direction(angle: i32, scale: i32) -> IVec2 {
+    rotate(IVec2::new(scale, 0), angle)
+}
+
+/// `v` turned counter-clockwise by `angle`
+pub fn rotate(v: IVec2, angle: i32) -> IVec2 {
+    let (sin, cos) = (sin(angle) as i64, cos(angle) as i64);
+    let (x, y) = (v.x as i64, v.y as i64);
+    IVec2::new(
+        div_round(x * cos - y * sin, F2I as i64) as i32,
+        div_round(x * sin + y * cos, F2I as i64) as i32,
+    )
+}
+
+/// Division rounding halves away from zero, like `f32::round`
+fn div_round(numerator: i64, denominator: i64) -> i64 {
+    (numerator + numerator.signum() * denominator / 2) / denominator
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::IVec2Ext;
+
+    #[test]
+    fn quarter_turns_are_exact() {
+        for (quarter, (sin_value, cos_value)) in [(0, F2I), (F2I, 0), (0, -F2I), (-F2I, 0)]
+            .into_iter()
+            .enumerate()
+        {
+            let angle = quarter as i32 * QUARTER_TURN;
+            assert_eq!((sin(angle), cos(angle)), (sin_value, cos_value), "{angle}");
+        }
+    }
+
+    #[test]
+    fn angles_wrap_around_a_full_turn() {
+        for angle in -TURN..TURN {
+            assert_eq!(sin(angle), sin(angle + TURN), "{angle}");
+            assert_eq!(sin(-angle), -sin(angle), "{angle}");
+        }
+    }
+
+    #[test]
+    fn rotate_turns_counter_clockwise() {
+        let v = IVec2::new(3 * F2I, F2I);
+        assert_eq!(rotate(v, 0), v);
+        assert_eq!(rotate(v, QUARTER_TURN), IVec2::new(-F2I, 3 * F2I));
+        assert_eq!(rotate(v, 2 * QUARTER_TURN), -v);
+        assert_eq!(rotate(v, -QUARTER_TURN), IVec2::new(F2I, -3 * F2I));
+    }
+
+    #[test]
+    fn directions_keep_their_length() {
+        for angle in 0..TURN {
+            let length = direction(angle, F2I).norm().unwrap();
+            assert!((length - F2I).abs() <= 2, "{angle}: {length}");
+        }
+    }
+
+    #[test]
+    fn rounding_is_symmetric() {
+        assert_eq!(div_round(7, 2), 4);
+        assert_eq!(div_round(-7, 2), -4);
+        assert_eq!(div_round(5, 3), 2);
+        assert_eq!(div_round(-5, 3), -2);
+    }
+}
@@ -0,0 +1,126 @@
+use crate::GgrsConfig;
+use bevy::{
+    prelude::*,
+    time::{TimeSystem, TimeUpdateStrategy},
+    utils::{Duration, Instant},
+};
+use bevy_ggrs::{GGRSStage, Session};
+
+/// Runs the simulation at exactly its tick rate, whatever the display's
+/// refresh rate. The GGRS stage runs a tick for every tick interval of
+/// [`Time`] that passed, so frames a hair shorter or longer than the interval
+/// ran no tick on one frame and two on the next, sampling input unevenly.
+/// During a game this keeps its own accumulator of real time instead, works
+/// out how many whole ticks are due each frame and advances [`Time`] by
+/// exactly that much, so the stage runs exactly those ticks. Cosmetic systems
+/// and UI timers follow real time through
+/// [`crate::presentation_clock::PresentationClock`] or
+/// [`FixedTimestep::real_delta_seconds`] either way. Like GGRS's own `run_slow`, ticks are stretched a little while
+/// we're ahead of the peers, so both agree on how many are due.
+pub struct FixedTimestepPlugin;
+
+impl Plugin for FixedTimestepPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<FixedTimestep>().add_system(
+            advance_timestep
+                .before(TimeSystem)
+                .in_base_set(CoreSet::First),
+        );
+    }
+}
+
+/// Most ticks run in one frame. A longer stall, e.g. in a background tab, is
+/// dropped here and caught up with by [`crate::tab_visibility`] instead of
+/// freezing the tab in one huge frame.
+const MAX_TICKS_PER_FRAME: f64 = 8.;
+/// How much longer ticks are while running slow, the same as GGRS's
+const RUN_SLOW_FACTOR: f64 = 1.1;
+
+#[derive(Resource)]
+pub struct FixedTimestep {
+    /// Ticks per second the simulation runs at
+    rate: u32,
+    /// Rate last set on the GGRS stage
+    stage_rate: Option<u32>,
+    /// Real time not yet handed to the simulation
+    accumulator: f64,
+    last_frame: Option<Instant>,
+    /// Real seconds since the previous frame
+    real_delta: f32,
+    /// Whether a session is running on the accumulator
+    running: bool,
+}
+
+impl Default for FixedTimestep {
+    fn default() -> Self {
+        Self {
+            rate: 60,
+            stage_rate: None,
+            accumulator: 0.,
+            last_frame: None,
+            real_delta: 0.,
+            running: false,
+        }
+    }
+}
+
+impl FixedTimestep {
+    /// Sets the ticks per second, taking effect from the next frame
+    pub fn set_rate(&mut self, rate: u32) {
+        self.rate = rate.max(1);
+    }
+
+    /// Seconds since the previous frame, which [`Time`] no longer tells
+    /// during a game
+    pub fn real_delta_seconds(&self) -> f32 {
+        self.real_delta
+    }
+}
+
+fn advance_timestep(
+    mut timestep: ResMut<FixedTimestep>,
+    mut strategy: ResMut<TimeUpdateStrategy>,
+    mut stage: ResMut<GGRSStage<GgrsConfig>>,
+    session: Option<Res<Session<GgrsConfig>>>,
+) {
+    let now = Instant::now();
+    let real = timestep
+        .last_frame
+        .replace(now)
+        .map_or(0., |last| now.duration_since(last).as_secs_f64());
+    timestep.real_delta = real as f32;
+    if timestep.stage_rate != Some(timestep.rate) {
+        stage.set_update_frequency(timestep.rate as usize);
+        timestep.stage_rate = Some(timestep.rate);
+    }
+
+    let Some(session) = session else {
+        if timestep.running {
+            *strategy = TimeUpdateStrategy::Automatic;
+            timestep.accumulator = 0.;
+            timestep.running = false;
+        }
+        return;
+    };
+    let run_slow = match &*session {
+        Session::P2PSession(session) => session.frames_ahead() > 0,
+        _ => false,
+    };
+    let mut tick = 1. / timestep.rate as f64;
+    if run_slow {
+        tick *= RUN_SLOW_FACTOR;
+    }
+    let accumulator = (timestep.accumulator + real).min(MAX_TICKS_PER_FRAME * tick);
+    let ticks = (accumulator / tick).floor();
+    timestep.accumulator = accumulator - ticks * tick;
+    let mut handed = ticks * tick;
+    if !timestep.running {
+        // The stage starts a session with next to nothing accumulated. Half a
+        // tick more keeps its leftover clear of the boundary, where rounding
+        // could make it run one tick too few on one frame and one too many on
+        // the next.
+        handed += tick / 2.;
+        timestep.running = true;
+    }
+    *strategy = TimeUpdateStrategy::ManualDuration(Duration::from_secs_f64(handed));
+}
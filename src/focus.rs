@@ -0,0 +1,64 @@
+use crate::GameState;
+use bevy::{input::InputSystem, prelude::*, window::WindowFocused};
+use bevy_egui::{
+    egui::{Align2, Area, RichText},
+    EguiContexts,
+};
+
+/// Keeps keys from getting stuck when the window loses focus mid-press: the
+/// browser never tells us about the key-up, so the player would run into a
+/// wall until the key is pressed again. Local input is paused until the
+/// window has focus again.
+pub struct FocusPlugin;
+
+impl Plugin for FocusPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<WindowFocus>()
+            .add_system(
+                track_window_focus
+                    .in_base_set(CoreSet::PreUpdate)
+                    .after(InputSystem),
+            )
+            .add_system(input_paused_ui.in_set(OnUpdate(GameState::InGame)));
+    }
+}
+
+/// Whether the window has focus, i.e. local input can be trusted
+#[derive(Resource)]
+pub struct WindowFocus(pub bool);
+
+impl Default for WindowFocus {
+    fn default() -> Self {
+        Self(true)
+    }
+}
+
+/// Releases every key and button on both focus changes. Keys still held
+/// when focus returns are picked up again by the browser's key repeat.
+fn track_window_focus(
+    mut events: EventReader<WindowFocused>,
+    mut focus: ResMut<WindowFocus>,
+    mut keys: ResMut<Input<KeyCode>>,
+    mut mouse_buttons: ResMut<Input<MouseButton>>,
+) {
+    for event in events.iter() {
+        if event.focused == focus.0 {
+            continue;
+        }
+        info!("Window focus changed, focused: {}", event.focused);
+        focus.0 = event.focused;
+        keys.release_all();
+        mouse_buttons.release_all();
+    }
+}
+
+fn input_paused_ui(mut contexts: EguiContexts, focus: Res<WindowFocus>) {
+    if focus.0 {
+        return;
+    }
+    Area::new("input_paused")
+        .anchor(Align2::CENTER_BOTTOM, [0., -80.])
+        .show(contexts.ctx_mut(), |ui| {
+            ui.label(RichText::new("Input paused — click to focus").weak());
+        });
+}
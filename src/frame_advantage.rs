@@ -0,0 +1,137 @@
+use crate::{
+    fixed_timestep::FixedTimestep,
+    lobby_settings::LobbySettings,
+    session_events::{session_events, GgrsSessionEvent},
+    tab_visibility::{TabVisibility, CATCH_UP_RATE_PERCENT},
+    GameState, GgrsConfig,
+};
+use bevy::prelude::*;
+use bevy_egui::{
+    egui::{Align2, Area, RichText},
+    EguiContexts,
+};
+use bevy_ggrs::{ggrs::GGRSEvent, Session};
+
+/// Slows the simulation down while the local peer runs ahead of the others,
+/// so a faster machine stops mispredicting its way through the slower ones'
+/// inputs. A wait recommendation from GGRS halves the rate just long enough
+/// to skip the frames it asks for, and a steady lead in GGRS's frame
+/// advantage estimate trims the rate a little until it's gone. A subtle
+/// indicator shows while the game is held back for more than a moment. The
+/// other way round, a tab back from the background speeds the simulation up
+/// to catch up, see [`crate::tab_visibility`].
+pub struct FrameAdvantagePlugin;
+
+impl Plugin for FrameAdvantagePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<FrameAdvantage>()
+            .add_systems(
+                (throttle.after(session_events), syncing_ui.after(throttle))
+                    .in_set(OnUpdate(GameState::InGame)),
+            )
+            .add_system(reset_frame_advantage.in_schedule(OnExit(GameState::InGame)));
+    }
+}
+
+/// Average lead over the slowest peer above which the simulation slows down
+const MAX_FRAMES_AHEAD: f32 = 1.5;
+/// Rate the simulation runs at while trimming a steady lead, in percent of
+/// the tick rate
+const THROTTLED_RATE_PERCENT: u32 = 90;
+/// Weight of each new sample in the running average. GGRS's estimate jumps
+/// with every packet, so single samples aren't worth reacting to.
+const SAMPLE_WEIGHT: f32 = 0.05;
+/// How long the simulation must be held back before the indicator shows
+const INDICATOR_DELAY_SECS: f64 = 0.5;
+
+#[derive(Resource, Default)]
+pub struct FrameAdvantage {
+    /// Running average of how many frames the local simulation is ahead of
+    /// the slowest peer
+    pub frames_ahead: f32,
+    /// Simulation rate currently set, if not the tick rate
+    throttled_rate: Option<u32>,
+    throttled_since: Option<f64>,
+    /// End of the half rate stretch following a wait recommendation
+    skip_until: Option<f64>,
+}
+
+pub fn throttle(
+    time: Res<Time>,
+    mut events: EventReader<GgrsSessionEvent>,
+    session: Option<Res<Session<GgrsConfig>>>,
+    settings: Res<LobbySettings>,
+    mut timestep: ResMut<FixedTimestep>,
+    mut advantage: ResMut<FrameAdvantage>,
+    visibility: Res<TabVisibility>,
+) {
+    let now = time.elapsed_seconds_f64();
+    let tick_rate = settings.tick_rate;
+    for GgrsSessionEvent(event) in events.iter() {
+        if let GGRSEvent::WaitRecommendation { skip_frames } = *event {
+            if advantage.skip_until.is_none() && skip_frames > 0 {
+                debug!("Ahead of peers, skipping {skip_frames} frames");
+                // Half rate for twice the time falls behind by exactly
+                // `skip_frames`
+                advantage.skip_until = Some(now + 2. * skip_frames as f64 / tick_rate as f64);
+            }
+        }
+    }
+    if advantage.skip_until.map_or(false, |until| now >= until) {
+        advantage.skip_until = None;
+    }
+    if let Some(Session::P2PSession(session)) = session.as_deref() {
+        let sample = session.frames_ahead() as f32;
+        advantage.frames_ahead += (sample - advantage.frames_ahead) * SAMPLE_WEIGHT;
+    }
+
+    let rate = if visibility.is_catching_up() {
+        Some(tick_rate * CATCH_UP_RATE_PERCENT / 100)
+    } else if advantage.skip_until.is_some() {
+        Some(tick_rate / 2)
+    } else if advantage.frames_ahead > MAX_FRAMES_AHEAD {
+        Some(tick_rate * THROTTLED_RATE_PERCENT / 100)
+    } else {
+        None
+    };
+    if rate != advantage.throttled_rate {
+        timestep.set_rate(rate.unwrap_or(tick_rate));
+        advantage.throttled_rate = rate;
+        advantage.throttled_since = rate.and(advantage.throttled_since.or(Some(now)));
+    }
+}
+
+fn syncing_ui(
+    mut contexts: EguiContexts,
+    time: Res<Time>,
+    advantage: Res<FrameAdvantage>,
+    visibility: Res<TabVisibility>,
+) {
+    // Catching up has an overlay of its own
+    let Some(since) = advantage
+        .throttled_since
+        .filter(|_| !visibility.is_catching_up())
+    else {
+        return;
+    };
+    if time.elapsed_seconds_f64() - since < INDICATOR_DELAY_SECS {
+        return;
+    }
+    Area::new("syncing")
+        .anchor(Align2::RIGHT_BOTTOM, [-10., -10.])
+        .interactable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.label(RichText::new("syncing…").weak().small());
+        });
+}
+
+fn reset_frame_advantage(
+    settings: Res<LobbySettings>,
+    mut timestep: ResMut<FixedTimestep>,
+    mut advantage: ResMut<FrameAdvantage>,
+) {
+    if advantage.throttled_rate.is_some() {
+        timestep.set_rate(settings.tick_rate);
+    }
+    *advantage = default();
+}
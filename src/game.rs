@@ -0,0 +1,247 @@
+use crate::{
+    audio::SoundAssets,
+    components::{HealthBar, Position},
+    filter::FilterAssets,
+    hot_seat::HotSeat,
+    layers::DrawLayer,
+    lobby::{GameStartConfig, PracticeMode, Spectating},
+    lobby_settings::LobbySettings,
+    maps::{apply_map, select_map, CurrentMap, MapAsset, MapAssets, MapWall},
+    match_config::MatchConfig,
+    net::DesyncDetected,
+    save::load_snapshot,
+    simulation::{collide_players, move_bullet},
+    tilemap::quad_mesh,
+    GameState, GgrsConfig, I2F,
+};
+use bevy::{
+    prelude::*,
+    sprite::{MaterialMesh2dBundle, Mesh2dHandle},
+};
+use bevy_asset_loader::prelude::*;
+use bevy_ggrs::{GGRSSchedule, Rollback};
+
+/// The stage every game is played on: loads the assets, puts up the map,
+/// floor and background grid when a game starts, keeps sprites on their simulated
+/// positions, and tears the session down when the game ends
+pub struct GamePlugin;
+
+impl Plugin for GamePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_loading_state(
+            LoadingState::new(GameState::AssetLoading).continue_to_state(GameState::RoomSelect),
+        )
+        .add_collection_to_loading_state::<_, ImageAssets>(GameState::AssetLoading)
+        .add_collection_to_loading_state::<_, FilterAssets>(GameState::AssetLoading)
+        .add_collection_to_loading_state::<_, SoundAssets>(GameState::AssetLoading)
+        .add_collection_to_loading_state::<_, MapAssets>(GameState::AssetLoading)
+        .insert_resource(ClearColor(Color::rgb(0.53, 0.53, 0.53)))
+        .init_resource::<GridTheme>()
+        .add_system(setup.in_schedule(OnExit(GameState::AssetLoading)))
+        .add_system(select_lobby_map.in_schedule(OnExit(GameState::Matchmaking)))
+        .add_systems(
+            (
+                resize_grid.after(load_snapshot),
+                load_map.after(load_snapshot),
+            )
+                .in_schedule(OnEnter(GameState::InGame)),
+        )
+        .add_system(cleanup_session.in_schedule(OnExit(GameState::InGame)))
+        .add_system(
+            set_translations_to_positions
+                .after(collide_players)
+                .after(move_bullet)
+                .in_schedule(GGRSSchedule),
+        )
+        .add_system(recolor_grid);
+    }
+}
+
+#[derive(AssetCollection, Resource)]
+pub struct ImageAssets {
+    #[asset(path = "bullet.png")]
+    pub bullet: Handle<Image>,
+    #[asset(texture_atlas(tile_size_x = 32., tile_size_y = 32., columns = 4, rows = 3))]
+    #[asset(path = "player_sheet.png")]
+    pub player_sheet: Handle<TextureAtlas>,
+}
+
+/// Look of the background grid
+#[derive(Resource)]
+pub struct GridTheme {
+    pub line_color: Color,
+    pub line_width: f32,
+}
+
+impl Default for GridTheme {
+    fn default() -> Self {
+        Self {
+            line_color: Color::rgb(0.27, 0.27, 0.27),
+            line_width: 0.05,
+        }
+    }
+}
+
+#[derive(Component)]
+struct Grid;
+
+#[derive(Component)]
+struct Floor;
+
+const FLOOR_COLOR: Color = Color::rgb(0.58, 0.58, 0.58);
+
+fn setup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    theme: Res<GridTheme>,
+    settings: Res<LobbySettings>,
+) {
+    commands.spawn((
+        Grid,
+        DrawLayer::Grid,
+        MaterialMesh2dBundle {
+            mesh: meshes
+                .add(grid_mesh(settings.map_size, theme.line_width))
+                .into(),
+            material: materials.add(ColorMaterial::from(theme.line_color)),
+            ..default()
+        },
+    ));
+    commands.spawn((
+        Floor,
+        DrawLayer::Background,
+        MaterialMesh2dBundle {
+            mesh: meshes.add(floor_mesh(settings.map_size)).into(),
+            material: materials.add(ColorMaterial::from(FLOOR_COLOR)),
+            ..default()
+        },
+    ));
+}
+
+/// Picks the lobby's map before the game's players, pickups and hazards are
+/// placed on it. Its walls only go up in [`load_map`].
+fn select_lobby_map(
+    mut current: ResMut<CurrentMap>,
+    settings: Res<LobbySettings>,
+    map_assets: Res<MapAssets>,
+    maps: Res<Assets<MapAsset>>,
+) {
+    select_map(&mut current, &settings, &map_assets, &maps);
+}
+
+/// Puts up the walls of the game's map: the lobby's for a new game, the
+/// snapshot's for a resumed one, whose settings are only known once it's
+/// loaded. The only system spawning walls on entering a game, since walls
+/// spawned through commands aren't visible to a second one in the same
+/// schedule.
+fn load_map(
+    mut commands: Commands,
+    mut current: ResMut<CurrentMap>,
+    walls: Query<Entity, With<MapWall>>,
+    settings: Res<LobbySettings>,
+    map_assets: Res<MapAssets>,
+    maps: Res<Assets<MapAsset>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    apply_map(
+        &mut commands,
+        &mut current,
+        &walls,
+        &settings,
+        &map_assets,
+        &maps,
+        &mut meshes,
+        &mut materials,
+    );
+}
+
+/// Matches the grid and floor to the map size of the game being started,
+/// which may have come from the lobby settings or a resumed snapshot
+fn resize_grid(
+    settings: Res<LobbySettings>,
+    theme: Res<GridTheme>,
+    grid: Query<&Mesh2dHandle, With<Grid>>,
+    floor: Query<&Mesh2dHandle, With<Floor>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    for handle in grid.iter() {
+        if let Some(mesh) = meshes.get_mut(&handle.0) {
+            *mesh = grid_mesh(settings.map_size, theme.line_width);
+        }
+    }
+    for handle in floor.iter() {
+        if let Some(mesh) = meshes.get_mut(&handle.0) {
+            *mesh = floor_mesh(settings.map_size);
+        }
+    }
+}
+
+/// Applies changes to the grid theme, such as a newly picked cosmetic
+fn recolor_grid(
+    theme: Res<GridTheme>,
+    grid: Query<&Handle<ColorMaterial>, With<Grid>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    if !theme.is_changed() {
+        return;
+    }
+    for handle in grid.iter() {
+        if let Some(material) = materials.get_mut(handle) {
+            material.color = theme.line_color;
+        }
+    }
+}
+
+/// Builds all grid lines of a `size` x `size` map, centered on the origin, as
+/// one mesh of axis-aligned quads
+fn grid_mesh(size: i32, line_width: f32) -> Mesh {
+    let half_size = size as f32 / 2.;
+    let half_width = line_width / 2.;
+    quad_mesh((0..=size).flat_map(|i| {
+        let offset = i as f32 - half_size;
+        [
+            // Horizontal line
+            (
+                Vec2::new(-half_size, offset - half_width),
+                Vec2::new(half_size, offset + half_width),
+            ),
+            // Vertical line
+            (
+                Vec2::new(offset - half_width, -half_size),
+                Vec2::new(offset + half_width, half_size),
+            ),
+        ]
+    }))
+}
+
+/// The playable area, which the grid is drawn over
+fn floor_mesh(size: i32) -> Mesh {
+    let half_size = size as f32 / 2.;
+    quad_mesh([(Vec2::splat(-half_size), Vec2::splat(half_size))])
+}
+
+fn set_translations_to_positions(mut entities: Query<(&mut Transform, &Position)>) {
+    for (mut transform, position) in entities.iter_mut() {
+        transform.translation.x = position.0.x as f32 * I2F;
+        transform.translation.y = position.0.y as f32 * I2F;
+    }
+}
+
+fn cleanup_session(
+    mut commands: Commands,
+    rollback_entities: Query<Entity, Or<(With<Rollback>, With<HealthBar>, With<MapWall>)>>,
+) {
+    commands.remove_resource::<bevy_ggrs::Session<GgrsConfig>>();
+    commands.remove_resource::<DesyncDetected>();
+    commands.remove_resource::<PracticeMode>();
+    commands.remove_resource::<HotSeat>();
+    commands.remove_resource::<GameStartConfig>();
+    commands.remove_resource::<MatchConfig>();
+    commands.remove_resource::<Spectating>();
+    for entity in rollback_entities.iter() {
+        // Recursive to take avatar emblems along with their players
+        commands.entity(entity).despawn_recursive();
+    }
+}
@@ -0,0 +1,339 @@
+#[cfg(feature = "presentation")]
+use bevy_egui::egui::{ComboBox, Slider, Ui};
+
+/// A rule a game mode can expose to the lobby host
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Rule {
+    /// Round wins that end the match, 0 for no limit
+    ScoreLimit,
+    /// Rounds played before the match ends, 0 for no limit
+    RoundCount,
+    /// There are no teams, so this decides whether players can be hit by
+    /// their own bullets
+    FriendlyFire,
+    /// Seconds between the end of a round and everyone respawning
+    RespawnDelay,
+    /// Seconds into a round before the play area starts shrinking, 0 for
+    /// never
+    ZoneDelay,
+    /// Percentage of the default push a bullet gives the player it hits, 0
+    /// for none
+    Knockback,
+    /// Whether players knocked past the edge of the map are out of the round
+    RingOut,
+    /// Seconds a player has to hold the hill alone to win the round
+    CaptureSecs,
+    /// Seconds between the hill's moves, 0 for never
+    HillMoveSecs,
+}
+
+/// Only read by the lobby's form, which headless builds leave out
+#[cfg_attr(not(feature = "presentation"), allow(dead_code))]
+pub enum RuleKind {
+    Toggle,
+    Range {
+        min: i32,
+        max: i32,
+        suffix: &'static str,
+        /// Shown instead of the value when it's `min`, for rules where the
+        /// minimum turns them off
+        min_label: Option<&'static str>,
+    },
+}
+
+/// How the lobby shows and validates one rule of a mode
+pub struct RuleSchema {
+    pub rule: Rule,
+    /// Only shown by the lobby
+    #[cfg_attr(not(feature = "presentation"), allow(dead_code))]
+    pub label: &'static str,
+    /// Only checked and shown by the lobby
+    #[cfg_attr(not(feature = "presentation"), allow(dead_code))]
+    pub kind: RuleKind,
+    pub default: i32,
+}
+
+#[cfg(feature = "presentation")]
+impl RuleSchema {
+    fn validate(&self, value: i32) -> i32 {
+        match self.kind {
+            RuleKind::Toggle => (value != 0) as i32,
+            RuleKind::Range { min, max, .. } => value.clamp(min, max),
+        }
+    }
+}
+
+/// A game mode and the rules the host can configure for it. The lobby builds
+/// its form from `rules`, so adding a mode here is enough to make it
+/// configurable.
+pub struct GameMode {
+    /// Stable name, sent to peers and stored in saves
+    pub id: &'static str,
+    /// Only shown by the lobby
+    #[cfg_attr(not(feature = "presentation"), allow(dead_code))]
+    pub name: &'static str,
+    pub rules: &'static [RuleSchema],
+}
+
+pub const DEFAULT_MODE: &str = "last_standing";
+
+pub static MODES: [GameMode; 3] = [
+    GameMode {
+        id: DEFAULT_MODE,
+        name: "Last standing",
+        rules: &[
+            RuleSchema {
+                rule: Rule::ScoreLimit,
+                label: "score limit",
+                kind: RuleKind::Range {
+                    min: 0,
+                    max: 20,
+                    suffix: " wins",
+                    min_label: Some("no limit"),
+                },
+                default: 0,
+            },
+            RuleSchema {
+                rule: Rule::RoundCount,
+                label: "rounds",
+                kind: RuleKind::Range {
+                    min: 0,
+                    max: 30,
+                    suffix: "",
+                    min_label: Some("no limit"),
+                },
+                default: 0,
+            },
+            RuleSchema {
+                rule: Rule::FriendlyFire,
+                label: "Friendly fire",
+                kind: RuleKind::Toggle,
+                default: 1,
+            },
+            RuleSchema {
+                rule: Rule::RespawnDelay,
+                label: "respawn delay",
+                kind: RuleKind::Range {
+                    min: 1,
+                    max: 10,
+                    suffix: " s",
+                    min_label: None,
+                },
+                default: 3,
+            },
+            RuleSchema {
+                rule: Rule::ZoneDelay,
+                label: "zone closes after",
+                kind: RuleKind::Range {
+                    min: 0,
+                    max: 300,
+                    suffix: " s",
+                    min_label: Some("never"),
+                },
+                default: 0,
+            },
+            RuleSchema {
+                rule: Rule::Knockback,
+                label: "knockback",
+                kind: KNOCKBACK_RANGE,
+                default: 0,
+            },
+        ],
+    },
+    GameMode {
+        id: "sumo",
+        name: "Sumo",
+        rules: &[
+            RuleSchema {
+                rule: Rule::ScoreLimit,
+                label: "score limit",
+                kind: RuleKind::Range {
+                    min: 0,
+                    max: 20,
+                    suffix: " wins",
+                    min_label: Some("no limit"),
+                },
+                default: 5,
+            },
+            RuleSchema {
+                rule: Rule::FriendlyFire,
+                label: "Friendly fire",
+                kind: RuleKind::Toggle,
+                default: 0,
+            },
+            RuleSchema {
+                rule: Rule::RespawnDelay,
+                label: "respawn delay",
+                kind: RuleKind::Range {
+                    min: 1,
+                    max: 10,
+                    suffix: " s",
+                    min_label: None,
+                },
+                default: 2,
+            },
+            RuleSchema {
+                rule: Rule::Knockback,
+                label: "knockback",
+                kind: KNOCKBACK_RANGE,
+                default: 200,
+            },
+            RuleSchema {
+                rule: Rule::RingOut,
+                label: "Ring out",
+                kind: RuleKind::Toggle,
+                default: 1,
+            },
+        ],
+    },
+    GameMode {
+        id: "king_of_the_hill",
+        name: "King of the hill",
+        rules: &[
+            RuleSchema {
+                rule: Rule::ScoreLimit,
+                label: "score limit",
+                kind: RuleKind::Range {
+                    min: 0,
+                    max: 20,
+                    suffix: " wins",
+                    min_label: Some("no limit"),
+                },
+                default: 3,
+            },
+            RuleSchema {
+                rule: Rule::CaptureSecs,
+                label: "capture time",
+                kind: RuleKind::Range {
+                    min: 5,
+                    max: 120,
+                    suffix: " s",
+                    min_label: None,
+                },
+                default: 20,
+            },
+            RuleSchema {
+                rule: Rule::HillMoveSecs,
+                label: "hill moves every",
+                kind: RuleKind::Range {
+                    min: 0,
+                    max: 120,
+                    suffix: " s",
+                    min_label: Some("never"),
+                },
+                default: 30,
+            },
+            RuleSchema {
+                rule: Rule::FriendlyFire,
+                label: "Friendly fire",
+                kind: RuleKind::Toggle,
+                default: 0,
+            },
+            RuleSchema {
+                rule: Rule::RespawnDelay,
+                label: "respawn delay",
+                kind: RuleKind::Range {
+                    min: 1,
+                    max: 10,
+                    suffix: " s",
+                    min_label: None,
+                },
+                default: 3,
+            },
+        ],
+    },
+];
+
+const KNOCKBACK_RANGE: RuleKind = RuleKind::Range {
+    min: 0,
+    max: 400,
+    suffix: "%",
+    min_label: Some("off"),
+};
+
+impl GameMode {
+    pub fn find(id: &str) -> Option<&'static GameMode> {
+        MODES.iter().find(|mode| mode.id == id)
+    }
+
+    pub fn default_rules(&self) -> Vec<i32> {
+        self.rules.iter().map(|schema| schema.default).collect()
+    }
+
+    /// `values` with missing rules defaulted, extra ones dropped and the rest
+    /// clamped to what the schema allows
+    #[cfg(feature = "presentation")]
+    pub fn validated(&self, values: Vec<i32>) -> Vec<i32> {
+        self.rules
+            .iter()
+            .enumerate()
+            .map(|(index, schema)| {
+                values
+                    .get(index)
+                    .map_or(schema.default, |value| schema.validate(*value))
+            })
+            .collect()
+    }
+
+    /// The value of `rule` in `values`, or `None` if the mode doesn't have it
+    pub fn rule(&self, values: &[i32], rule: Rule) -> Option<i32> {
+        let index = self.rules.iter().position(|schema| schema.rule == rule)?;
+        Some(
+            values
+                .get(index)
+                .copied()
+                .unwrap_or(self.rules[index].default),
+        )
+    }
+}
+
+/// Mode picker followed by a form for the picked mode's rules, generated from
+/// its schema. Switching modes resets the rules to the new mode's defaults.
+#[cfg(feature = "presentation")]
+pub fn mode_form(ui: &mut Ui, mode_id: &mut String, values: &mut Vec<i32>) {
+    let current = GameMode::find(mode_id);
+    ComboBox::from_label("mode")
+        .selected_text(current.map_or(mode_id.as_str(), |mode| mode.name))
+        .show_ui(ui, |ui| {
+            for mode in MODES.iter() {
+                if ui
+                    .selectable_label(*mode_id == mode.id, mode.name)
+                    .clicked()
+                    && *mode_id != mode.id
+                {
+                    *mode_id = mode.id.to_string();
+                    *values = mode.default_rules();
+                }
+            }
+        });
+    let Some(mode) = GameMode::find(mode_id) else {
+        return;
+    };
+    *values = mode.validated(std::mem::take(values));
+    for (schema, value) in mode.rules.iter().zip(values.iter_mut()) {
+        match schema.kind {
+            RuleKind::Toggle => {
+                let mut enabled = *value != 0;
+                ui.checkbox(&mut enabled, schema.label);
+                *value = enabled as i32;
+            }
+            RuleKind::Range {
+                min,
+                max,
+                suffix,
+                min_label,
+            } => {
+                ui.horizontal(|ui| {
+                    ui.add(
+                        Slider::new(value, min..=max)
+                            .suffix(suffix)
+                            .text(schema.label),
+                    );
+                    if let Some(label) = min_label.filter(|_| *value == min) {
+                        ui.weak(label);
+                    }
+                });
+            }
+        }
+    }
+}
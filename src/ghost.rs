@@ -0,0 +1,127 @@
+use crate::{
+    components::{Health, Player, UserInfo},
+    game::ImageAssets,
+    input::{direction, LocalControls, PlayerInput, DIRECTION_SCALE},
+    layers::DrawLayer,
+    lobby_settings::LobbySettings,
+    player::PLAYER_WIDTH_RF,
+    presentation_clock::PresentationClock,
+    GameState, LocalPlayerHandle, I2F,
+};
+use bevy::prelude::*;
+use bevy_egui::{
+    egui::{Color32, LayerId},
+    EguiContexts,
+};
+
+/// While the local player is dead they see the world as a ghost: washed-out
+/// colors, muffled sounds and a translucent body they can drift around in
+/// until they respawn. The ghost is purely cosmetic and never enters the
+/// simulation.
+pub struct GhostPlugin;
+
+impl Plugin for GhostPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            (
+                update_ghost,
+                drift_ghost.after(update_ghost),
+                draw_ghost_tint,
+            )
+                .in_set(OnUpdate(GameState::InGame)),
+        )
+        .add_system(remove_ghost.in_schedule(OnExit(GameState::InGame)));
+    }
+}
+
+/// Drift speed in world units per second, well below walking speed
+const GHOST_SPEED_RF: f32 = 3.;
+const GHOST_ALPHA: f32 = 0.35;
+/// Gray laid over the world to wash out its colors
+const GHOST_TINT: Color32 = Color32::from_rgba_premultiplied(60, 60, 68, 110);
+
+/// The local player's body while they're dead
+#[derive(Component)]
+pub struct Ghost;
+
+/// Present while the local player is a ghost
+#[derive(Resource)]
+pub struct GhostView;
+
+/// Spawns a ghost where the local player died and removes it on respawn
+fn update_ghost(
+    mut commands: Commands,
+    local_handle: Option<Res<LocalPlayerHandle>>,
+    players: Query<(&Player, &Health, &Transform, Option<&UserInfo>)>,
+    ghosts: Query<Entity, With<Ghost>>,
+    images: Res<ImageAssets>,
+) {
+    let Some(local_handle) = local_handle else {
+        return;
+    };
+    let Some((_, health, transform, info)) = players
+        .iter()
+        .find(|(player, ..)| player.handle == local_handle.0)
+    else {
+        return;
+    };
+    let dead = health.0 <= 0;
+    if dead && ghosts.is_empty() {
+        commands.insert_resource(GhostView);
+        commands.spawn((
+            Ghost,
+            DrawLayer::Vfx,
+            SpriteSheetBundle {
+                transform: *transform,
+                sprite: TextureAtlasSprite {
+                    color: info
+                        .cloned()
+                        .unwrap_or_default()
+                        .sprite_color()
+                        .with_a(GHOST_ALPHA),
+                    custom_size: Some(Vec2::splat(PLAYER_WIDTH_RF)),
+                    ..default()
+                },
+                texture_atlas: images.player_sheet.clone(),
+                ..default()
+            },
+        ));
+    } else if !dead && !ghosts.is_empty() {
+        remove_ghost(commands, ghosts);
+    }
+}
+
+fn drift_ghost(
+    clock: Res<PresentationClock>,
+    controls: LocalControls,
+    settings: Res<LobbySettings>,
+    mut ghosts: Query<&mut Transform, With<Ghost>>,
+) {
+    let input = PlayerInput {
+        buttons: controls.held_buttons(),
+        ..default()
+    };
+    let velocity = direction(input).as_vec2() / DIRECTION_SCALE as f32 * GHOST_SPEED_RF;
+    let limit = settings.map_size_si() as f32 * I2F / 2.;
+    for mut transform in ghosts.iter_mut() {
+        let position = transform.translation.truncate() + velocity * clock.delta_seconds();
+        let position = position.clamp(Vec2::splat(-limit), Vec2::splat(limit));
+        transform.translation = position.extend(transform.translation.z);
+    }
+}
+
+fn draw_ghost_tint(mut contexts: EguiContexts, ghost_view: Option<Res<GhostView>>) {
+    if ghost_view.is_none() {
+        return;
+    }
+    let ctx = contexts.ctx_mut();
+    ctx.layer_painter(LayerId::background())
+        .rect_filled(ctx.screen_rect(), 0., GHOST_TINT);
+}
+
+fn remove_ghost(mut commands: Commands, ghosts: Query<Entity, With<Ghost>>) {
+    for ghost in ghosts.iter() {
+        commands.entity(ghost).despawn();
+    }
+    commands.remove_resource::<GhostView>();
+}
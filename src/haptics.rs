@@ -0,0 +1,131 @@
+use crate::{
+    audio::{Sound, SoundEvent},
+    classes::PlayerClass,
+    components::{Health, Player},
+    GameState, LocalPlayerHandle,
+};
+use bevy::prelude::*;
+use js_sys::{Function, Object, Reflect};
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::window;
+
+/// Rumbles gamepads when the local player fires or gets hurt. Uses the
+/// browser's `vibrationActuator`, which not every browser or gamepad has;
+/// without it this does nothing.
+pub struct HapticsPlugin;
+
+impl Plugin for HapticsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<HapticsSettings>()
+            .add_systems((rumble_on_fire, rumble_on_damage).in_set(OnUpdate(GameState::InGame)));
+    }
+}
+
+const FIRE_RUMBLE_STRENGTH: f32 = 0.2;
+const FIRE_RUMBLE_MS: f64 = 60.;
+/// Strength of the lightest hit. Harder hits rumble up to full strength.
+const MIN_DAMAGE_RUMBLE_STRENGTH: f32 = 0.3;
+const DAMAGE_RUMBLE_MS: f64 = 200.;
+
+#[derive(Resource)]
+pub struct HapticsSettings {
+    pub enabled: bool,
+    /// Multiplies every rumble's strength, 0 to 1
+    pub strength: f32,
+}
+
+impl Default for HapticsSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            strength: 1.,
+        }
+    }
+}
+
+impl HapticsSettings {
+    fn rumble(&self, strength: f32, duration_ms: f64) {
+        if self.enabled && self.strength > 0. {
+            rumble_gamepads((strength * self.strength).clamp(0., 1.), duration_ms);
+        }
+    }
+}
+
+fn rumble_on_fire(
+    mut sounds: EventReader<SoundEvent>,
+    local_handle: Option<Res<LocalPlayerHandle>>,
+    settings: Res<HapticsSettings>,
+    gamepads: Res<Gamepads>,
+) {
+    let fired = local_handle.map_or(false, |handle| {
+        sounds
+            .iter()
+            .any(|event| event.sound == Sound::Fire && event.source == handle.0)
+    });
+    if fired && gamepads.iter().next().is_some() {
+        settings.rumble(FIRE_RUMBLE_STRENGTH, FIRE_RUMBLE_MS);
+    }
+}
+
+/// Rumbles harder the more health the local player just lost. Healing, e.g.
+/// on respawn, only updates the baseline.
+fn rumble_on_damage(
+    players: Query<(&Player, &Health, &PlayerClass)>,
+    local_handle: Option<Res<LocalPlayerHandle>>,
+    settings: Res<HapticsSettings>,
+    gamepads: Res<Gamepads>,
+    mut last_health: Local<Option<i32>>,
+) {
+    let Some(local_handle) = local_handle else {
+        return;
+    };
+    let Some((_, health, class)) = players.iter().find(|(p, ..)| p.handle == local_handle.0) else {
+        return;
+    };
+    let lost = last_health
+        .replace(health.0)
+        .map_or(0, |last| last - health.0);
+    if lost > 0 && gamepads.iter().next().is_some() {
+        let fraction = lost as f32 / class.stats().max_health as f32;
+        let strength =
+            MIN_DAMAGE_RUMBLE_STRENGTH + (1. - MIN_DAMAGE_RUMBLE_STRENGTH) * fraction.min(1.);
+        settings.rumble(strength, DAMAGE_RUMBLE_MS);
+    }
+}
+
+/// Plays a dual-rumble effect on every connected gamepad that supports one.
+/// `vibrationActuator.playEffect` isn't in web_sys yet, so it's looked up
+/// dynamically.
+fn rumble_gamepads(strength: f32, duration_ms: f64) {
+    if cfg!(feature = "native") {
+        return;
+    }
+    let Some(gamepads) = window().and_then(|w| w.navigator().get_gamepads().ok()) else {
+        return;
+    };
+    let params = Object::new();
+    for (key, value) in [
+        ("duration", duration_ms),
+        ("strongMagnitude", strength as f64),
+        ("weakMagnitude", strength as f64),
+    ] {
+        let _ = Reflect::set(&params, &key.into(), &value.into());
+    }
+    for gamepad in gamepads.iter().filter(|gamepad| gamepad.is_object()) {
+        let Ok(actuator) = Reflect::get(&gamepad, &"vibrationActuator".into()) else {
+            continue;
+        };
+        if !actuator.is_object() {
+            continue;
+        }
+        let play_effect = Reflect::get(&actuator, &"playEffect".into())
+            .ok()
+            .and_then(|f| f.dyn_into::<Function>().ok());
+        if let Some(play_effect) = play_effect {
+            let effect = JsValue::from_str("dual-rumble");
+            if let Err(error) = play_effect.call2(&actuator, &effect, &params) {
+                debug!("Gamepad rumble failed: {error:?}");
+            }
+        }
+    }
+}
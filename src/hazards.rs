@@ -0,0 +1,204 @@
+use crate::{
+    achievements::{GameplayEvent, GameplayEvents},
+    audio::{Sound, SoundQueue},
+    components::{Health, Invulnerable, Player, Position, Radius},
+    lobby_settings::LobbySettings,
+    maps::CurrentMap,
+    portals::teleport_players,
+    rng::{advance_sim_frame, SimFrame},
+    rounds::round_in_progress,
+    simulation::{fire_bullets, spawn_bullet_pool},
+    spatial::SpatialGrid,
+    vfx::{Effect, VfxQueue},
+    GameState, IVec2Ext, F2I,
+};
+#[cfg(feature = "presentation")]
+use crate::{
+    layers::DrawLayer,
+    sprite_atlas::{GameSprite, SpriteAtlas},
+    I2F,
+};
+use bevy::prelude::*;
+use bevy_ggrs::{GGRSSchedule, Rollback, RollbackIdProvider};
+use serde::Deserialize;
+
+/// Ghosts patrolling paths from the map data, killing any player they touch.
+/// They're rollback entities whose positions follow from the frame number
+/// alone, so every peer and every resimulation puts them in the same place.
+pub struct HazardsPlugin;
+
+impl Plugin for HazardsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(
+            spawn_hazards
+                .after(spawn_bullet_pool)
+                .in_schedule(OnEnter(GameState::InGame)),
+        )
+        .add_systems(
+            (
+                move_hazards.after(advance_sim_frame),
+                touch_hazards
+                    .after(move_hazards)
+                    .after(teleport_players)
+                    .before(fire_bullets)
+                    .run_if(round_in_progress),
+            )
+                .in_schedule(GGRSSchedule),
+        );
+        #[cfg(feature = "presentation")]
+        app.add_system(add_hazard_sprites.in_set(OnUpdate(GameState::InGame)));
+    }
+}
+
+const HAZARD_RADIUS_SI: i32 = 5 * F2I / 10;
+/// Distance a hazard moves each frame
+const HAZARD_SPEED_SI: i32 = 6 * F2I / 100;
+
+/// A patrol path, in whole world units in the map asset. Hazards walk from
+/// waypoint to waypoint and from the last one back to the first.
+#[derive(Deserialize, Clone, Debug)]
+pub struct HazardPath {
+    pub waypoints: Vec<IVec2>,
+}
+
+impl HazardPath {
+    /// Frames spent going from each waypoint to the next
+    fn leg_frames(&self) -> impl Iterator<Item = u32> + '_ {
+        let next = self.waypoints.iter().cycle().skip(1);
+        self.waypoints.iter().zip(next).map(|(from, to)| {
+            let length = (*to - *from).norm().unwrap_or(i32::MAX);
+            (length / HAZARD_SPEED_SI).max(1) as u32
+        })
+    }
+
+    /// Where a hazard on this path is `frame` frames into the game, in
+    /// integer math only
+    fn position(&self, frame: u32) -> IVec2 {
+        let Some(&start) = self.waypoints.first() else {
+            return IVec2::ZERO;
+        };
+        let loop_frames = self.leg_frames().sum::<u32>();
+        if loop_frames == 0 || self.waypoints.len() < 2 {
+            return start;
+        }
+        let mut frame = frame % loop_frames;
+        for (index, frames) in self.leg_frames().enumerate() {
+            if frame < frames {
+                let from = self.waypoints[index];
+                let to = self.waypoints[(index + 1) % self.waypoints.len()];
+                let lerp = |from: i32, to: i32| {
+                    from + ((to - from) as i64 * frame as i64 / frames as i64) as i32
+                };
+                return IVec2::new(lerp(from.x, to.x), lerp(from.y, to.y));
+            }
+            frame -= frames;
+        }
+        start
+    }
+}
+
+/// Which of the [`CurrentMap`]'s hazard paths this hazard patrols
+#[derive(Component, Reflect, Default, Clone, Copy, Debug)]
+pub struct Hazard(pub usize);
+
+/// After the bullet pool, so rollback ids come out the same on every peer
+pub fn spawn_hazards(
+    mut commands: Commands,
+    mut rip: ResMut<RollbackIdProvider>,
+    map: Res<CurrentMap>,
+    frame: Res<SimFrame>,
+) {
+    for (index, path) in map.hazards().iter().enumerate() {
+        commands.spawn((
+            Hazard(index),
+            Rollback::new(rip.next_id()),
+            Position(path.position(frame.0)),
+            Radius(HAZARD_RADIUS_SI),
+        ));
+    }
+}
+
+pub fn move_hazards(
+    map: Res<CurrentMap>,
+    frame: Res<SimFrame>,
+    mut hazards: Query<(&Hazard, &mut Position)>,
+) {
+    for (hazard, mut position) in hazards.iter_mut() {
+        if let Some(path) = map.hazards().get(hazard.0) {
+            position.0 = path.position(frame.0);
+        }
+    }
+}
+
+pub fn touch_hazards(
+    settings: Res<LobbySettings>,
+    frame: Res<SimFrame>,
+    grid: Res<SpatialGrid>,
+    hazards: Query<(&Position, &Radius), With<Hazard>>,
+    mut players: Query<(&Player, &Position, &Radius, &mut Health, &Invulnerable), Without<Hazard>>,
+    mut sounds: ResMut<SoundQueue>,
+    mut vfx: ResMut<VfxQueue>,
+    mut events: ResMut<GameplayEvents>,
+) {
+    // Handle order, so sounds and effects queue identically on every peer
+    let mut players = players.iter_mut().collect::<Vec<_>>();
+    players.sort_by_key(|(player, ..)| player.handle);
+    for (player, position, radius, mut health, invulnerable) in players {
+        if health.0 <= 0 || invulnerable.0 > 0 {
+            continue;
+        }
+        let touched = grid
+            .query_circle(position.0, radius.0)
+            .into_iter()
+            .any(|entity| {
+                let Ok((hazard_position, hazard_radius)) = hazards.get(entity) else {
+                    return false;
+                };
+                let reach = (radius.0 + hazard_radius.0) as i64;
+                let hazard_position = settings.nearest_image(hazard_position.0, position.0);
+                (position.0 - hazard_position).norm_sq_wide() < reach * reach
+            });
+        if !touched {
+            continue;
+        }
+        health.0 = 0;
+        sounds.push(&frame, Sound::Death, player.handle);
+        events.push(
+            &frame,
+            GameplayEvent::Death {
+                victim: player.handle,
+            },
+        );
+        vfx.push(
+            &frame,
+            Effect::DeathBurst,
+            player.handle,
+            position.0,
+            IVec2::ZERO,
+        );
+    }
+}
+
+#[cfg(feature = "presentation")]
+const HAZARD_COLOR: Color = Color::rgba(0.85, 0.9, 1., 0.6);
+
+/// Rollbacks and loaded saves may spawn hazards again, so their sprites are
+/// added here like the pickups'
+#[cfg(feature = "presentation")]
+fn add_hazard_sprites(
+    mut commands: Commands,
+    sprites: Res<SpriteAtlas>,
+    hazards: Query<(Entity, &Position, &Radius), Added<Hazard>>,
+) {
+    for (entity, position, radius) in hazards.iter() {
+        commands.entity(entity).insert((
+            DrawLayer::Hazards,
+            sprites.bundle(
+                GameSprite::Solid,
+                HAZARD_COLOR,
+                Vec2::splat(radius.0 as f32 * I2F * 2.),
+                Transform::from_translation(position.0.i2f().extend(DrawLayer::Hazards.z())),
+            ),
+        ));
+    }
+}
@@ -0,0 +1,434 @@
+use crate::{
+    achievements::GameplayEvents,
+    audio::SoundQueue,
+    bots::{Bot, BotBrains},
+    components::{Health, Player, Position},
+    game_modes::DEFAULT_MODE,
+    input::{encode_input, PlayerInput},
+    lobby_settings::MAX_PLAYERS,
+    match_config::{balance_hash, MatchConfig},
+    rng::{SimFrame, SimRng},
+    simulation::{ggrs_plugin, SimulationPlugin, SimulationTimingSet},
+    vfx::VfxQueue,
+    GameState, GgrsConfig, MAX_PREDICTION_FRAMES,
+};
+use bevy::{log::LogPlugin, prelude::*, time::TimeUpdateStrategy};
+use bevy_ggrs::{
+    ggrs::{self, PlayerHandle, PlayerType},
+    ggrs_stage::GGRSStage,
+    GGRSSchedule,
+};
+use serde::Deserialize;
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    time::Duration,
+};
+
+/// Plays a match between bots without a window, renderer or sound, as fast
+/// as the machine allows, then prints a hash of the simulation state. It's a
+/// GGRS sync test, so every frame is also rolled back and simulated again,
+/// and any nondeterminism fails the run. The match is then played a second
+/// time, and the run fails unless both end on byte-identical snapshots, which
+/// also catches state that differs between runs but not between rollbacks.
+/// The same seed gives the same hash on every run, so builds can be compared.
+/// Players, seed and length are read from `WEB_GHOST_HEADLESS_PLAYERS`,
+/// `_SEED` and `_FRAMES`, and `_SCRIPT` names an [`InputScript`] to play
+/// instead of the bots. The same check runs as part of
+/// `cargo test --no-default-features --features headless`.
+pub fn run() {
+    let config = HeadlessConfig::from_env();
+    let script = match &config.script {
+        Some(path) => InputScript::load(path),
+        None => InputScript::default(),
+    };
+    let outcome = match play_twice(&config, &script, true) {
+        Ok(outcome) => outcome,
+        Err(error) => {
+            error!("Headless: {error}");
+            std::process::exit(1);
+        }
+    };
+    // Read by scripts, so printed rather than logged
+    println!(
+        "HEADLESS_CHECKSUM {} {:016x}",
+        config.frames, outcome.checksum
+    );
+}
+
+/// Plays the match twice, failing unless both runs end on byte-identical
+/// snapshots
+fn play_twice(config: &HeadlessConfig, script: &InputScript, log: bool) -> Result<Outcome, String> {
+    let first = play(config, script, log);
+    let second = play(config, script, false);
+    match divergence(&first.snapshot, &second.snapshot) {
+        Some(divergence) => Err(format!("the runs diverged at {divergence}")),
+        None => Ok(first),
+    }
+}
+
+/// Plays the match once. Time advances by one frame per update instead of
+/// following the clock, so every run simulates the same frames in the same
+/// updates and stops on the same one.
+fn play(config: &HeadlessConfig, script: &InputScript, log: bool) -> Outcome {
+    let mut app = App::new();
+
+    ggrs_plugin()
+        .with_input_system(scripted_input)
+        .build(&mut app);
+    app.world
+        .resource_mut::<GGRSStage<GgrsConfig>>()
+        .set_update_frequency(FPS);
+
+    app.add_plugins(MinimalPlugins);
+    // Logging can only be set up once per process
+    if log {
+        app.add_plugin(LogPlugin::default());
+    }
+    app.add_state::<GameState>()
+        .add_plugin(SimulationPlugin)
+        .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f64(
+            1. / FPS as f64,
+        )))
+        .insert_resource(MatchConfig {
+            seed: config.seed,
+            mode: DEFAULT_MODE.to_string(),
+            balance_hash: balance_hash(),
+        })
+        .insert_resource(NextState(Some(GameState::InGame)))
+        .insert_resource(config.clone())
+        .insert_resource(script.clone())
+        .init_resource::<Checksum>()
+        .add_startup_system(spawn_players)
+        .add_system(start_session.in_schedule(OnEnter(GameState::InGame)))
+        .add_system(
+            record_checksum
+                .in_schedule(GGRSSchedule)
+                .in_base_set(SimulationTimingSet::End),
+        )
+        .add_systems((discard_feedback, finish).in_set(OnUpdate(GameState::InGame)));
+    while !app.world.contains_resource::<Outcome>() {
+        app.update();
+    }
+    app.world.remove_resource::<Outcome>().unwrap()
+}
+
+/// Simulation frames per second of game time
+const FPS: usize = 60;
+/// Frames GGRS rolls back and resimulates every frame, by default
+const CHECK_DISTANCE: usize = 2;
+/// Bytes of snapshot shown on each side of where two runs diverged
+const EXCERPT_BYTES: usize = 60;
+
+#[derive(Resource, Clone, Debug)]
+struct HeadlessConfig {
+    players: usize,
+    seed: u64,
+    /// Frame the state is hashed at
+    frames: u32,
+    /// Frames GGRS rolls back and resimulates every frame, none with 0
+    check_distance: usize,
+    /// Path of the [`InputScript`] to play
+    script: Option<String>,
+}
+
+impl HeadlessConfig {
+    fn from_env() -> Self {
+        fn var<T: std::str::FromStr>(name: &str, default: T) -> T {
+            std::env::var(name)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(default)
+        }
+        Self {
+            players: var("WEB_GHOST_HEADLESS_PLAYERS", 4).clamp(1, MAX_PLAYERS),
+            seed: var("WEB_GHOST_HEADLESS_SEED", 1),
+            frames: var("WEB_GHOST_HEADLESS_FRAMES", 3600),
+            check_distance: CHECK_DISTANCE,
+            script: std::env::var("WEB_GHOST_HEADLESS_SCRIPT").ok(),
+        }
+    }
+}
+
+/// Inputs to play instead of the bots, read from a RON file: a list of
+/// steps per player, each held for its number of frames, starting over once
+/// the last one is done. Players without steps are left to the bots.
+///
+/// ```ron
+/// [
+///     [(frames: 30, direction: (1, 0)), (frames: 10, fire: true, aim: Some((0, 1)))],
+///     [],
+/// ]
+/// ```
+#[derive(Resource, Deserialize, Clone, Default)]
+struct InputScript(Vec<Vec<ScriptStep>>);
+
+#[derive(Deserialize, Clone)]
+struct ScriptStep {
+    frames: u32,
+    /// Moves along whichever of the 8 directions is closest
+    #[serde(default)]
+    direction: (i32, i32),
+    #[serde(default)]
+    fire: bool,
+    #[serde(default)]
+    aim: Option<(i32, i32)>,
+}
+
+impl InputScript {
+    fn load(path: &str) -> Self {
+        let text = std::fs::read_to_string(path)
+            .unwrap_or_else(|error| panic!("failed to read input script {path}: {error}"));
+        ron::from_str(&text)
+            .unwrap_or_else(|error| panic!("failed to parse input script {path}: {error}"))
+    }
+
+    fn is_scripted(&self, handle: usize) -> bool {
+        self.0.get(handle).map_or(false, |steps| !steps.is_empty())
+    }
+
+    fn input(&self, handle: usize, frame: u32) -> Option<PlayerInput> {
+        let steps = self.0.get(handle)?;
+        let length: u32 = steps.iter().map(|step| step.frames).sum();
+        if length == 0 {
+            return None;
+        }
+        let mut left = frame % length;
+        let step = steps.iter().find(|step| {
+            let current = left < step.frames;
+            left = left.saturating_sub(step.frames);
+            current
+        })?;
+        Some(encode_input(
+            step.direction.into(),
+            step.fire,
+            step.aim.map(IVec2::from),
+        ))
+    }
+}
+
+/// State hash of the checksum frame, from its latest simulation
+#[derive(Resource, Default)]
+struct Checksum(Option<u64>);
+
+/// How a run ended, inserted once the checksum frame can't be rolled back
+#[derive(Resource)]
+struct Outcome {
+    checksum: u64,
+    /// Serialized rollback state of the last frame simulated
+    snapshot: String,
+    /// Where the players ended up on that frame, by handle
+    #[cfg(test)]
+    positions: Vec<IVec2>,
+}
+
+fn spawn_players(mut commands: Commands, config: Res<HeadlessConfig>, script: Res<InputScript>) {
+    info!("Running headless: {config:?}");
+    for handle in 0..config.players {
+        let mut player = commands.spawn(Player { handle });
+        if !script.is_scripted(handle) {
+            player.insert(Bot);
+        }
+    }
+}
+
+/// Started along with the players getting their components, so no frame is
+/// simulated without them and ends the round on the spot
+fn start_session(mut commands: Commands, config: Res<HeadlessConfig>) {
+    let mut session_builder = ggrs::SessionBuilder::<GgrsConfig>::new()
+        .with_num_players(config.players)
+        .with_check_distance(config.check_distance);
+    for handle in 0..config.players {
+        session_builder = session_builder
+            .add_player(PlayerType::Local, handle)
+            .expect("failed to add player");
+    }
+    let session = session_builder
+        .start_synctest_session()
+        .expect("failed to start session");
+    commands.insert_resource(bevy_ggrs::Session::SyncTestSession(session));
+}
+
+fn scripted_input(
+    handle: In<PlayerHandle>,
+    script: Res<InputScript>,
+    frame: Res<SimFrame>,
+    bots: BotBrains,
+) -> PlayerInput {
+    script
+        .input(handle.0, frame.0)
+        .or_else(|| bots.input(handle.0))
+        .unwrap_or_default()
+}
+
+/// Nothing plays sounds, spawns effects or tracks achievements here
+pub fn discard_feedback(
+    mut sounds: ResMut<SoundQueue>,
+    mut vfx: ResMut<VfxQueue>,
+    mut events: ResMut<GameplayEvents>,
+) {
+    *sounds = default();
+    *vfx = default();
+    *events = default();
+}
+
+/// Rollbacks simulate the frame again, so the last hash recorded is the one
+/// of the confirmed frame
+fn record_checksum(
+    frame: Res<SimFrame>,
+    rng: Res<SimRng>,
+    config: Res<HeadlessConfig>,
+    players: Query<(&Player, &Position, &Health)>,
+    mut checksum: ResMut<Checksum>,
+) {
+    if frame.0 != config.frames {
+        return;
+    }
+    let mut players = players
+        .iter()
+        .map(|(player, position, health)| (player.handle, position.0.to_array(), health.0))
+        .collect::<Vec<_>>();
+    players.sort();
+    let mut hasher = DefaultHasher::new();
+    players.hash(&mut hasher);
+    rng.state().hash(&mut hasher);
+    checksum.0 = Some(hasher.finish());
+}
+
+fn finish(world: &mut World) {
+    let frames = world.resource::<HeadlessConfig>().frames;
+    // Past the prediction window, the checksum frame can't be rolled back
+    if world.resource::<SimFrame>().0 <= frames + MAX_PREDICTION_FRAMES {
+        return;
+    }
+    let Some(checksum) = world.resource::<Checksum>().0 else {
+        panic!("frame {frames} was never simulated");
+    };
+    let snapshot = world
+        .resource::<GGRSStage<GgrsConfig>>()
+        .get_serialized_snapshot(world);
+    #[cfg(test)]
+    let positions = player_positions(world);
+    world.insert_resource(Outcome {
+        checksum,
+        snapshot,
+        #[cfg(test)]
+        positions,
+    });
+}
+
+#[cfg(test)]
+fn player_positions(world: &mut World) -> Vec<IVec2> {
+    let mut players = world
+        .query::<(&Player, &Position)>()
+        .iter(world)
+        .map(|(player, position)| (player.handle, position.0))
+        .collect::<Vec<_>>();
+    players.sort_by_key(|(handle, _)| *handle);
+    players.into_iter().map(|(_, position)| position).collect()
+}
+
+/// Where two snapshots of what should be the same state first differ, with
+/// the bytes around it, if they do
+pub fn divergence(a: &str, b: &str) -> Option<String> {
+    let at = first_difference(a, b)?;
+    Some(format!(
+        "byte {at} of their snapshots:\n{}\n{}",
+        excerpt(a, at),
+        excerpt(b, at)
+    ))
+}
+
+/// Where `a` and `b` first differ, if they do
+fn first_difference(a: &str, b: &str) -> Option<usize> {
+    a.bytes()
+        .zip(b.bytes())
+        .position(|(a, b)| a != b)
+        .or_else(|| (a.len() != b.len()).then_some(a.len().min(b.len())))
+}
+
+fn excerpt(snapshot: &str, at: usize) -> String {
+    let bytes = snapshot.as_bytes();
+    let start = at.saturating_sub(EXCERPT_BYTES);
+    let end = (at + EXCERPT_BYTES).min(bytes.len());
+    String::from_utf8_lossy(&bytes[start..end]).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{player::spawn_position, MAP_SIZE_SI};
+
+    /// Long enough for bots to fight and rounds to end, short enough to keep
+    /// the tests quick
+    const TEST_FRAMES: u32 = 900;
+
+    fn config(players: usize, seed: u64) -> HeadlessConfig {
+        HeadlessConfig {
+            players,
+            seed,
+            frames: TEST_FRAMES,
+            check_distance: CHECK_DISTANCE,
+            script: None,
+        }
+    }
+
+    #[test]
+    fn bot_matches_are_deterministic() {
+        for (players, seed) in [(1, 1), (2, 5), (4, 9)] {
+            if let Err(error) = play_twice(&config(players, seed), &InputScript::default(), false) {
+                panic!("{players} players with seed {seed}: {error}");
+            }
+        }
+    }
+
+    #[test]
+    fn scripted_match_is_deterministic() {
+        let script = InputScript::load(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/scripts/headless_script.ron"
+        ));
+        if let Err(error) = play_twice(&config(3, 1), &script, false) {
+            panic!("{error}");
+        }
+    }
+
+    /// Recoil goes through velocity, which carries over between frames, so
+    /// it's what rollbacks are most likely to get wrong
+    #[test]
+    fn recoil_is_the_same_with_and_without_resimulation() {
+        // Firing away from the middle of the map pushes the player towards it
+        let script = InputScript(vec![vec![ScriptStep {
+            frames: 1,
+            direction: (0, 0),
+            fire: true,
+            aim: Some((-1, 0)),
+        }]]);
+        let resimulated = HeadlessConfig {
+            frames: 120,
+            ..config(1, 1)
+        };
+        let straight = HeadlessConfig {
+            check_distance: 0,
+            ..resimulated.clone()
+        };
+        let [resimulated, straight] =
+            [resimulated, straight].map(|config| play(&config, &script, false));
+        assert_eq!(resimulated.positions, straight.positions);
+        if let Some(divergence) = divergence(&resimulated.snapshot, &straight.snapshot) {
+            panic!("resimulating diverged at {divergence}");
+        }
+        let (spawn, _) = spawn_position(0, MAP_SIZE_SI);
+        assert!(
+            resimulated.positions[0].x > spawn.x,
+            "recoil didn't move the player from {spawn}"
+        );
+    }
+
+    #[test]
+    fn first_difference_finds_divergence_and_length_mismatch() {
+        assert_eq!(first_difference("abc", "abc"), None);
+        assert_eq!(first_difference("abc", "abd"), Some(2));
+        assert_eq!(first_difference("abc", "ab"), Some(2));
+    }
+}
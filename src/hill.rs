@@ -0,0 +1,221 @@
+#[cfg(feature = "presentation")]
+use crate::components::UserInfo;
+use crate::{
+    components::{Health, Player, Position},
+    game_modes::Rule,
+    lobby_settings::LobbySettings,
+    maps::CurrentMap,
+    pickups::spawn_pickups,
+    portals::teleport_players,
+    rng::SimRng,
+    rounds::{update_round, RoundState},
+    simulation::{apply_damage, collide_players},
+    zone::update_zone,
+    GameState, IVec2Ext, F2I,
+};
+#[cfg(feature = "presentation")]
+use crate::{
+    layers::DrawLayer,
+    sprite_atlas::{GameSprite, SpriteAtlas},
+    I2F,
+};
+use bevy::prelude::*;
+#[cfg(feature = "presentation")]
+use bevy_egui::{
+    egui::{Align2, Area, ProgressBar},
+    EguiContexts,
+};
+use bevy_ggrs::GGRSSchedule;
+
+/// King of the hill: in modes with a capture time, a circle on the map
+/// fills up a player's capture progress on every frame they stand in it
+/// alone, and the first to fill it wins the round. It moves to a random spot
+/// every so often. Like the zone, it's a rollback resource.
+pub struct HillPlugin;
+
+impl Plugin for HillPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<HillState>()
+            .add_system(
+                update_hill
+                    .after(collide_players)
+                    .after(teleport_players)
+                    .after(apply_damage)
+                    .after(update_zone)
+                    .after(spawn_pickups)
+                    .before(update_round)
+                    .in_schedule(GGRSSchedule),
+            )
+            .add_system(reset_hill.in_schedule(OnExit(GameState::InGame)));
+        #[cfg(feature = "presentation")]
+        app.add_system(draw_hill.in_set(OnUpdate(GameState::InGame)))
+            .add_system(hill_ui.in_set(OnUpdate(GameState::InGame)))
+            .add_system(despawn_hill.in_schedule(OnExit(GameState::InGame)));
+    }
+}
+
+const HILL_RADIUS_SI: i32 = 2 * F2I;
+
+#[derive(Resource, Reflect, Default, Clone, Debug)]
+#[reflect(Resource)]
+pub struct HillState {
+    /// Whether the hill is on the map. It's placed when a round starts.
+    pub placed: bool,
+    pub center: IVec2,
+    /// Frames until the hill moves, unless the mode keeps it in place
+    pub moves_in: u32,
+    /// Frames each player held the hill alone this round, by handle
+    pub progress: Vec<u32>,
+}
+
+impl HillState {
+    /// The player whose capture progress is complete, ending the round
+    pub fn capturer(&self, settings: &LobbySettings) -> Option<usize> {
+        let needed = capture_frames(settings)?;
+        self.progress.iter().position(|frames| *frames >= needed)
+    }
+
+    fn contains(&self, settings: &LobbySettings, position: IVec2) -> bool {
+        let position = settings.nearest_image(position, self.center);
+        (position - self.center).norm_sq_wide() <= (HILL_RADIUS_SI as i64).pow(2)
+    }
+}
+
+/// Frames a player has to hold the hill for, in modes that have one
+fn capture_frames(settings: &LobbySettings) -> Option<u32> {
+    let seconds = settings.rule(Rule::CaptureSecs)?;
+    Some(seconds.max(1) as u32 * settings.tick_rate)
+}
+
+/// Frames between the hill's moves, if it moves at all
+fn move_frames(settings: &LobbySettings) -> Option<u32> {
+    let seconds = settings.rule(Rule::HillMoveSecs).unwrap_or(0);
+    (seconds > 0).then(|| seconds as u32 * settings.tick_rate)
+}
+
+/// Picks a spot the hill fits on, out of the walls
+fn random_center(settings: &LobbySettings, map: &CurrentMap, rng: &mut SimRng) -> IVec2 {
+    let limit = (settings.half_map_size_si() - HILL_RADIUS_SI).max(1);
+    let center = IVec2::new(
+        rng.range_i32(-limit, limit + 1),
+        rng.range_i32(-limit, limit + 1),
+    );
+    map.push_out_of_walls(center, HILL_RADIUS_SI)
+}
+
+fn update_hill(
+    mut hill: ResMut<HillState>,
+    round: Res<RoundState>,
+    settings: Res<LobbySettings>,
+    map: Res<CurrentMap>,
+    mut rng: ResMut<SimRng>,
+    players: Query<(&Player, &Position, &Health)>,
+) {
+    if capture_frames(&settings).is_none() {
+        return;
+    }
+    if !round.in_progress() {
+        if hill.placed {
+            *hill = HillState::default();
+        }
+        return;
+    }
+    let move_frames = move_frames(&settings);
+    if !hill.placed || (move_frames.is_some() && hill.moves_in == 0) {
+        hill.center = random_center(&settings, &map, &mut rng);
+        hill.moves_in = move_frames.unwrap_or(0);
+        hill.placed = true;
+    }
+    hill.moves_in = hill.moves_in.saturating_sub(1);
+
+    let mut holders = players
+        .iter()
+        .filter(|(_, position, health)| health.0 > 0 && hill.contains(&settings, position.0))
+        .map(|(player, ..)| player.handle);
+    // Contested while more than one player stands in it
+    if let (Some(holder), None) = (holders.next(), holders.next()) {
+        if hill.progress.len() <= holder {
+            hill.progress.resize(holder + 1, 0);
+        }
+        hill.progress[holder] += 1;
+    }
+}
+
+fn reset_hill(mut hill: ResMut<HillState>) {
+    *hill = HillState::default();
+}
+
+#[cfg(feature = "presentation")]
+const HILL_COLOR: Color = Color::rgba(1., 0.85, 0.2, 0.25);
+
+#[cfg(feature = "presentation")]
+#[derive(Component)]
+struct HillMarker;
+
+#[cfg(feature = "presentation")]
+fn draw_hill(
+    mut commands: Commands,
+    hill: Res<HillState>,
+    sprites: Res<SpriteAtlas>,
+    mut markers: Query<(&mut Transform, &mut Visibility), With<HillMarker>>,
+) {
+    let Ok((mut transform, mut visibility)) = markers.get_single_mut() else {
+        if hill.placed {
+            commands.spawn((
+                HillMarker,
+                DrawLayer::Zone,
+                sprites.bundle(
+                    GameSprite::Bullet,
+                    HILL_COLOR,
+                    Vec2::splat(2. * HILL_RADIUS_SI as f32 * I2F),
+                    Transform::from_translation(hill.center.i2f().extend(DrawLayer::Zone.z())),
+                ),
+            ));
+        }
+        return;
+    };
+    *visibility = if hill.placed {
+        Visibility::Inherited
+    } else {
+        Visibility::Hidden
+    };
+    transform.translation = hill.center.i2f().extend(transform.translation.z);
+}
+
+#[cfg(feature = "presentation")]
+fn despawn_hill(mut commands: Commands, markers: Query<Entity, With<HillMarker>>) {
+    for entity in markers.iter() {
+        commands.entity(entity).despawn();
+    }
+}
+
+/// Everyone's capture progress at the top of the screen
+#[cfg(feature = "presentation")]
+fn hill_ui(
+    mut contexts: EguiContexts,
+    hill: Res<HillState>,
+    settings: Res<LobbySettings>,
+    players: Query<(&Player, Option<&UserInfo>)>,
+) {
+    let Some(needed) = capture_frames(&settings) else {
+        return;
+    };
+    if !hill.placed {
+        return;
+    }
+    let mut players = players.iter().collect::<Vec<_>>();
+    players.sort_by_key(|(player, _)| player.handle);
+    Area::new("hill_progress")
+        .anchor(Align2::CENTER_TOP, [0., 40.])
+        .show(contexts.ctx_mut(), |ui| {
+            ui.set_width(200.);
+            for (player, info) in players {
+                let frames = hill.progress.get(player.handle).copied().unwrap_or(0);
+                let name = info.map_or_else(
+                    || format!("Player {}", player.handle),
+                    |info| info.name.clone(),
+                );
+                ui.add(ProgressBar::new(frames as f32 / needed as f32).text(name));
+            }
+        });
+}
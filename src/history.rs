@@ -0,0 +1,199 @@
+use crate::{
+    attract::AttractMode,
+    components::{Health, IsLocal, Player, UserInfo},
+    filter::WordFilter,
+    lobby::PracticeMode,
+    lobby_settings::LobbySettings,
+    storage::{KeyValueStore, KeyValueStores, StorageArea},
+    wire_format::{Ron, TextFormat},
+    GameState,
+};
+use bevy::prelude::*;
+use bevy_egui::{
+    egui::{self, Grid, ScrollArea},
+    EguiContexts,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Keeps summaries of finished matches in local storage and shows them in a
+/// lobby window
+pub struct HistoryPlugin;
+
+impl Plugin for HistoryPlugin {
+    fn build(&self, app: &mut App) {
+        let stores = KeyValueStores::get();
+        app.insert_resource(MatchHistory::load(stores.area(StorageArea::Local)))
+            .init_resource::<HistoryWindow>()
+            .add_system(
+                start_match_record
+                    .run_if(not(resource_exists::<AttractMode>()))
+                    .in_schedule(OnEnter(GameState::InGame)),
+            )
+            .add_system(finish_match_record.in_schedule(OnExit(GameState::InGame)))
+            .add_system(history_ui.in_set(OnUpdate(GameState::Matchmaking)));
+    }
+}
+
+const HISTORY_STORAGE_KEY: &str = "match_history";
+const MAX_HISTORY_ENTRIES: usize = 50;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MatchSummary {
+    pub started: DateTime<Utc>,
+    pub duration_secs: f64,
+    /// Name of the `MapAsset` the match was played on
+    pub map: String,
+    pub practice: bool,
+    pub players: Vec<PlayerSummary>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PlayerSummary {
+    pub name: String,
+    pub health: i32,
+    pub local: bool,
+}
+
+#[derive(Resource, Default, Debug)]
+pub struct MatchHistory(pub Vec<MatchSummary>);
+
+impl MatchHistory {
+    fn load(local: &dyn KeyValueStore) -> Self {
+        local
+            .get(HISTORY_STORAGE_KEY)
+            .and_then(|value| Ron::from_text(&value).ok())
+            .map(Self)
+            .unwrap_or_default()
+    }
+
+    fn save(&self, local: &dyn KeyValueStore) {
+        let stored = Ron::to_text(&self.0).and_then(|value| local.set(HISTORY_STORAGE_KEY, &value));
+        if let Err(e) = stored {
+            warn!("Failed to save match history: {e}");
+        }
+    }
+}
+
+#[derive(Resource, Default)]
+struct HistoryWindow {
+    open: bool,
+}
+
+#[derive(Resource)]
+struct MatchInProgress {
+    started: DateTime<Utc>,
+    started_secs: f64,
+}
+
+fn start_match_record(mut commands: Commands, time: Res<Time>) {
+    commands.insert_resource(MatchInProgress {
+        started: Utc::now(),
+        started_secs: time.elapsed_seconds_f64(),
+    });
+}
+
+fn finish_match_record(
+    mut commands: Commands,
+    time: Res<Time>,
+    in_progress: Option<Res<MatchInProgress>>,
+    settings: Res<LobbySettings>,
+    practice: Option<Res<PracticeMode>>,
+    mut history: ResMut<MatchHistory>,
+    stores: Res<KeyValueStores>,
+    players: Query<(&Player, Option<&UserInfo>, Option<&Health>, Option<&IsLocal>)>,
+) {
+    let Some(in_progress) = in_progress else {
+        return;
+    };
+    commands.remove_resource::<MatchInProgress>();
+    let mut players = players.iter().collect::<Vec<_>>();
+    players.sort_by_key(|(player, ..)| player.handle);
+    history.0.insert(
+        0,
+        MatchSummary {
+            started: in_progress.started,
+            duration_secs: time.elapsed_seconds_f64() - in_progress.started_secs,
+            map: settings.map.clone(),
+            practice: practice.is_some(),
+            players: players
+                .into_iter()
+                .map(|(_, info, health, local)| PlayerSummary {
+                    name: info.map(|i| i.name.clone()).unwrap_or_default(),
+                    health: health.map_or(0, |h| h.0),
+                    local: local.is_some(),
+                })
+                .collect(),
+        },
+    );
+    history.0.truncate(MAX_HISTORY_ENTRIES);
+    history.save(stores.area(StorageArea::Local));
+}
+
+fn format_duration(secs: f64) -> String {
+    let secs = secs.max(0.) as u64;
+    format!("{}:{:02}", secs / 60, secs % 60)
+}
+
+fn history_ui(
+    mut contexts: EguiContexts,
+    mut window: ResMut<HistoryWindow>,
+    history: Res<MatchHistory>,
+    word_filter: Res<WordFilter>,
+) {
+    let ctx = contexts.ctx_mut();
+    egui::TopBottomPanel::bottom("history_button_panel").show(ctx, |ui| {
+        if ui.button("Match history").clicked() {
+            window.open = !window.open;
+        }
+    });
+    let mut open = window.open;
+    egui::Window::new("Match history")
+        .open(&mut open)
+        .show(ctx, |ui| {
+            let survived = history
+                .0
+                .iter()
+                .filter(|m| m.players.iter().any(|p| p.local && p.health > 0))
+                .count();
+            let total_secs = history.0.iter().map(|m| m.duration_secs).sum::<f64>();
+            ui.label(format!(
+                "{} matches, survived {survived}, {} played",
+                history.0.len(),
+                format_duration(total_secs)
+            ));
+            ui.separator();
+            ScrollArea::vertical().show(ui, |ui| {
+                for (index, summary) in history.0.iter().enumerate() {
+                    ui.collapsing(
+                        format!(
+                            "{} - {} ({}){}",
+                            summary.started.format("%Y-%m-%d %H:%M"),
+                            summary.map,
+                            format_duration(summary.duration_secs),
+                            if summary.practice { " practice" } else { "" },
+                        ),
+                        |ui| {
+                            Grid::new(("history_players", index)).show(ui, |ui| {
+                                for player in summary.players.iter() {
+                                    let name = word_filter.censor(&player.name);
+                                    if player.local {
+                                        ui.strong(&*name);
+                                    } else {
+                                        ui.label(&*name);
+                                    }
+                                    ui.label(if player.health > 0 {
+                                        format!("{} hp", player.health)
+                                    } else {
+                                        "dead".to_string()
+                                    });
+                                    ui.end_row();
+                                }
+                            });
+                        },
+                    );
+                }
+            });
+        });
+    window.open = open;
+}
@@ -0,0 +1,116 @@
+use crate::{
+    components::{LastHit, Player, Position},
+    presentation_clock::PresentationClock,
+    rng::SimFrame,
+    GameState, LocalPlayerHandle, MAX_PREDICTION_FRAMES,
+};
+use bevy::prelude::*;
+use bevy_egui::{
+    egui::{self, Color32, LayerId, Order, Shape, Stroke},
+    EguiContexts,
+};
+
+/// Flashes a marker at the screen edge pointing toward whoever shot the local
+/// player. Hits are only shown once they're older than the rollback window,
+/// so mispredicted hits never flash.
+pub struct HitIndicatorPlugin;
+
+impl Plugin for HitIndicatorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<HitIndicators>()
+            .add_systems(
+                (track_hits, draw_hit_indicators.after(track_hits))
+                    .in_set(OnUpdate(GameState::InGame)),
+            )
+            .add_system(clear_hit_indicators.in_schedule(OnExit(GameState::InGame)));
+    }
+}
+
+const INDICATOR_SECS: f32 = 1.;
+/// Distance of indicators from the screen edge, in points
+const INDICATOR_MARGIN: f32 = 40.;
+const INDICATOR_SIZE: f32 = 18.;
+
+#[derive(Resource, Default)]
+struct HitIndicators {
+    /// Last hit that was shown, so it isn't shown twice
+    shown: Option<LastHit>,
+    /// Screen-space directions toward attackers, with seconds left on screen
+    active: Vec<(Vec2, f32)>,
+}
+
+fn track_hits(
+    clock: Res<PresentationClock>,
+    frame: Res<SimFrame>,
+    local_handle: Option<Res<LocalPlayerHandle>>,
+    players: Query<(&Player, &Position, &LastHit)>,
+    mut indicators: ResMut<HitIndicators>,
+) {
+    let delta = clock.delta_seconds();
+    indicators.active.retain_mut(|(_, secs_left)| {
+        *secs_left -= delta;
+        *secs_left > 0.
+    });
+
+    let Some(local_handle) = local_handle else {
+        return;
+    };
+    let Some((_, position, last_hit)) = players
+        .iter()
+        .find(|(player, ..)| player.handle == local_handle.0)
+    else {
+        return;
+    };
+    if last_hit.frame == 0 || indicators.shown == Some(*last_hit) {
+        return;
+    }
+    // Until it's older than the prediction window a rollback may still
+    // replace or undo the hit
+    if frame.0 < last_hit.frame + MAX_PREDICTION_FRAMES {
+        return;
+    }
+    indicators.shown = Some(*last_hit);
+    let attacker = players
+        .iter()
+        .find(|(player, ..)| player.handle == last_hit.shooter && player.handle != local_handle.0);
+    if let Some((_, attacker_position, _)) = attacker {
+        let direction = (attacker_position.0 - position.0).as_vec2();
+        if direction != Vec2::ZERO {
+            // Screen y points down
+            let direction = Vec2::new(direction.x, -direction.y).normalize();
+            indicators.active.push((direction, INDICATOR_SECS));
+        }
+    }
+}
+
+fn draw_hit_indicators(mut contexts: EguiContexts, indicators: Res<HitIndicators>) {
+    if indicators.active.is_empty() {
+        return;
+    }
+    let ctx = contexts.ctx_mut();
+    let screen = ctx.screen_rect();
+    let center = screen.center();
+    let radius = screen.width().min(screen.height()) / 2. - INDICATOR_MARGIN;
+    let painter = ctx.layer_painter(LayerId::new(Order::Foreground, egui::Id::new("hits")));
+    for (direction, secs_left) in indicators.active.iter() {
+        let alpha = (secs_left / INDICATOR_SECS * 255.) as u8;
+        let color = Color32::from_rgba_unmultiplied(230, 40, 40, alpha);
+        let dir = egui::vec2(direction.x, direction.y);
+        let side = egui::vec2(-dir.y, dir.x);
+        let tip = center + dir * (radius + INDICATOR_SIZE);
+        let base = center + dir * radius;
+        painter.add(Shape::convex_polygon(
+            vec![
+                tip,
+                base + side * INDICATOR_SIZE,
+                base - side * INDICATOR_SIZE,
+            ],
+            color,
+            Stroke::NONE,
+        ));
+    }
+}
+
+fn clear_hit_indicators(mut indicators: ResMut<HitIndicators>) {
+    *indicators = HitIndicators::default();
+}
@@ -0,0 +1,94 @@
+use crate::{
+    camera::camera_follow,
+    components::{Health, Player, Position},
+    fixed_timestep::FixedTimestep,
+    lobby_settings::LobbySettings,
+    presentation_clock::{PresentationClock, Smoothing},
+    GameState, IVec2Ext,
+};
+use bevy::prelude::*;
+
+/// Two players on one keyboard in an offline practice game. The second seat
+/// is a local GGRS player like the practice bots, but reads its own half of
+/// the keyboard (see [`crate::key_bindings::KeyBindings::hot_seat`]) instead
+/// of standing still. Both share one camera, which frames whoever is alive
+/// and zooms out as they move apart.
+pub struct HotSeatPlugin;
+
+impl Plugin for HotSeatPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(
+            frame_hot_seat_players
+                .after(camera_follow)
+                .in_set(OnUpdate(GameState::InGame))
+                .run_if(resource_exists::<HotSeat>()),
+        )
+        .add_system(reset_zoom.in_schedule(OnExit(GameState::InGame)));
+    }
+}
+
+/// Present while a hot-seat practice game is being set up or played
+#[derive(Resource)]
+pub struct HotSeat;
+
+/// Handle of the second player at the keyboard
+pub const HOT_SEAT_HANDLE: usize = 1;
+
+/// Space kept around the players at the edge of the view, in world units
+const FRAME_MARGIN_RF: f32 = 3.;
+/// The camera never zooms out further than this, so players stay readable
+const MAX_ZOOM_OUT: f32 = 2.5;
+/// How quickly the zoom eases towards what frames both players, per second
+const ZOOM_RATE: f32 = 4.;
+
+/// Centers the camera between the living hot-seat players and zooms out just
+/// enough to keep both in view. Runs after the usual follow camera, which
+/// only knows about the first player.
+fn frame_hot_seat_players(
+    settings: Res<LobbySettings>,
+    clock: Res<PresentationClock>,
+    timestep: Res<FixedTimestep>,
+    players: Query<(&Player, &Position, &Health)>,
+    mut cameras: Query<(&mut Transform, &mut OrthographicProjection), With<Camera>>,
+    mut smoothing: Local<Smoothing>,
+) {
+    let mut seated = players
+        .iter()
+        .filter(|(player, _, health)| player.handle <= HOT_SEAT_HANDLE && health.0 > 0)
+        .map(|(_, position, _)| position.0)
+        .collect::<Vec<_>>();
+    let Some(&first) = seated.first() else {
+        return;
+    };
+    // On wrapping maps, frame the closest copy of the other player
+    for position in seated.iter_mut() {
+        *position = settings.nearest_image(*position, first);
+    }
+    let min = seated
+        .iter()
+        .fold(first, |min, position| min.min(*position));
+    let max = seated
+        .iter()
+        .fold(first, |max, position| max.max(*position));
+    let center = smoothing.follow((min + max).i2f() / 2., &clock);
+    let needed = (max - min).i2f() + Vec2::splat(FRAME_MARGIN_RF * 2.);
+
+    let ease = 1. - (-ZOOM_RATE * timestep.real_delta_seconds()).exp();
+    for (mut transform, mut projection) in cameras.iter_mut() {
+        transform.translation.x = center.x;
+        transform.translation.y = center.y;
+        // The area is last frame's, at last frame's zoom
+        let unzoomed = projection.area.size() / projection.scale;
+        if unzoomed.min_element() <= 0. {
+            continue;
+        }
+        let target = (needed / unzoomed).max_element().clamp(1., MAX_ZOOM_OUT);
+        projection.scale += (target - projection.scale) * ease;
+    }
+}
+
+fn reset_zoom(mut projections: Query<&mut OrthographicProjection, With<Camera>>) {
+    for mut projection in projections.iter_mut() {
+        projection.scale = 1.;
+    }
+}
@@ -1,7 +1,22 @@
 use bevy::prelude::*;
+#[cfg(feature = "presentation")]
+use bevy::{ecs::system::SystemParam, window::PrimaryWindow};
+#[cfg(feature = "presentation")]
 use bevy_ggrs::ggrs::PlayerHandle;
+use bytemuck::{Pod, Zeroable};
 
-use crate::IVec2Ext;
+#[cfg(feature = "presentation")]
+use crate::{
+    bots::BotBrains,
+    components::{IsLocal, Player},
+    focus::WindowFocus,
+    hot_seat::{HotSeat, HOT_SEAT_HANDLE},
+    key_bindings::{Action, KeyBindings},
+    pause::PauseVote,
+    touch::TouchControls,
+    GameState, LocalPlayerHandle,
+};
+use crate::{fixed_point, IVec2Ext};
 
 // use crate::fixed_point::{Fix, Vec2Fixed};
 
@@ -10,32 +25,388 @@ const INPUT_DOWN: u8 = 1 << 1;
 const INPUT_LEFT: u8 = 1 << 2;
 const INPUT_RIGHT: u8 = 1 << 3;
 const INPUT_FIRE: u8 = 1 << 4;
+/// Set when `PlayerInput::aim` holds a valid angle
+const INPUT_AIM: u8 = 1 << 5;
+const INPUT_DASH: u8 = 1 << 6;
+const INPUT_SWITCH: u8 = 1 << 7;
 
-pub fn input(_: In<PlayerHandle>, keys: Res<Input<KeyCode>>) -> u8 {
-    let mut input = 0u8;
+/// Buttons latched by [`InputBuffer`] between two sampled frames
+#[cfg(feature = "presentation")]
+const BUFFERED_BUTTONS: u8 = INPUT_UP | INPUT_DOWN | INPUT_LEFT | INPUT_RIGHT | INPUT_FIRE;
+
+/// Button set by each key binding that's held
+#[cfg(feature = "presentation")]
+const ACTION_BUTTONS: [(Action, u8); 7] = [
+    (Action::Up, INPUT_UP),
+    (Action::Down, INPUT_DOWN),
+    (Action::Left, INPUT_LEFT),
+    (Action::Right, INPUT_RIGHT),
+    (Action::Fire, INPUT_FIRE),
+    (Action::Dash, INPUT_DASH),
+    (Action::SwitchWeapon, INPUT_SWITCH),
+];
+
+/// Set in `PlayerInput::flags` once the player's peer agreed to pause
+#[cfg(feature = "presentation")]
+const FLAG_PAUSE: u8 = 1 << 0;
+/// Set in `PlayerInput::flags` while the reload button is held. The buttons
+/// byte is full.
+const FLAG_RELOAD: u8 = 1 << 1;
+
+/// Everything one player sends to GGRS each frame
+#[repr(C)]
+#[derive(Copy, Clone, PartialEq, Eq, Pod, Zeroable, Debug, Default)]
+pub struct PlayerInput {
+    pub buttons: u8,
+    /// Aim angle counter-clockwise from +x, in 1/256ths of a full turn
+    pub aim: u8,
+    pub flags: u8,
+}
+
+/// How far the virtual joystick must be pushed along an axis to count as a
+/// key press. Roughly sin(22.5°), so the stick maps onto 8 directions.
+#[cfg(feature = "presentation")]
+const TOUCH_AXIS_THRESHOLD: f32 = 0.38;
+/// Same as [`TOUCH_AXIS_THRESHOLD`], for gamepad sticks
+#[cfg(feature = "presentation")]
+const STICK_AXIS_THRESHOLD: f32 = 0.38;
+/// How far the right stick must be pushed before it takes over aiming
+#[cfg(feature = "presentation")]
+const STICK_AIM_THRESHOLD: f32 = 0.5;
+
+/// Latches short presses between the frames GGRS samples local input on.
+/// Rendering and simulation run at different rates, so a fire tap or a quick
+/// direction tap can start and end between two frames and would never reach
+/// the simulation. Each latched press goes into the next sampled frame,
+/// which is never resimulated with different local input. Only buffers while
+/// in a game, so menu clicks and key presses never reach it.
+#[cfg(feature = "presentation")]
+pub struct InputBufferPlugin;
+
+#[cfg(feature = "presentation")]
+impl Plugin for InputBufferPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<InputBuffer>()
+            .add_system(clear_input_buffer.in_schedule(OnEnter(GameState::InGame)))
+            .add_system(buffer_input.in_set(OnUpdate(GameState::InGame)));
+    }
+}
+
+/// Buttons pressed since local input was last sampled
+#[cfg(feature = "presentation")]
+#[derive(Resource, Default)]
+pub struct InputBuffer {
+    latched: u8,
+    reload: bool,
+}
+
+#[cfg(feature = "presentation")]
+impl InputBuffer {
+    /// Adds the latched buttons to `held` and clears them. A latched
+    /// direction is dropped if the same axis is held, so tapping one way
+    /// while holding the other doesn't cancel out the movement.
+    fn take(&mut self, held: u8) -> u8 {
+        let mut latched = std::mem::take(&mut self.latched);
+        for axis in [INPUT_UP | INPUT_DOWN, INPUT_LEFT | INPUT_RIGHT] {
+            if held & axis != 0 {
+                latched &= !axis;
+            }
+        }
+        held | latched
+    }
+}
+
+/// Presses made in menus or before the game don't carry into its first frame
+#[cfg(feature = "presentation")]
+fn clear_input_buffer(mut buffer: ResMut<InputBuffer>) {
+    *buffer = default();
+}
+
+#[cfg(feature = "presentation")]
+fn buffer_input(controls: LocalControls, focus: Res<WindowFocus>, mut buffer: ResMut<InputBuffer>) {
+    if !focus.0 {
+        *buffer = default();
+        return;
+    }
+    buffer.latched |= (controls.held_buttons() | controls.tapped_buttons()) & BUFFERED_BUTTONS;
+    buffer.reload |= controls.reload();
+}
+
+/// Every local input device, except the mouse which aims relative to the
+/// player's sprite
+#[cfg(feature = "presentation")]
+#[derive(SystemParam)]
+pub struct LocalControls<'w, 's> {
+    keys: Res<'w, Input<KeyCode>>,
+    bindings: Query<'w, 's, &'static KeyBindings, With<IsLocal>>,
+    touch: Res<'w, TouchControls>,
+    gamepads: Res<'w, Gamepads>,
+    gamepad_buttons: Res<'w, Input<GamepadButton>>,
+    gamepad_axes: Res<'w, Axis<GamepadAxis>>,
+    hot_seat: Option<Res<'w, HotSeat>>,
+}
+
+#[cfg(feature = "presentation")]
+impl LocalControls<'_, '_> {
+    /// The local player's own bindings, or the left half of the keyboard in
+    /// hot-seat games
+    fn bindings(&self) -> KeyBindings {
+        if self.hot_seat.is_some() {
+            let [bindings, _] = KeyBindings::hot_seat();
+            return bindings;
+        }
+        self.bindings.get_single().cloned().unwrap_or_default()
+    }
+
+    /// Movement and fire buttons held on any device
+    pub fn held_buttons(&self) -> u8 {
+        let mut input = 0u8;
+        let touch = &self.touch;
+        if touch.enabled {
+            // Screen space has y pointing down
+            if touch.direction.y < -TOUCH_AXIS_THRESHOLD {
+                input |= INPUT_UP;
+            }
+            if touch.direction.y > TOUCH_AXIS_THRESHOLD {
+                input |= INPUT_DOWN;
+            }
+            if touch.direction.x < -TOUCH_AXIS_THRESHOLD {
+                input |= INPUT_LEFT;
+            }
+            if touch.direction.x > TOUCH_AXIS_THRESHOLD {
+                input |= INPUT_RIGHT;
+            }
+            if touch.fire {
+                input |= INPUT_FIRE;
+            }
+        }
+
+        let bindings = self.bindings();
+        for (action, button) in ACTION_BUTTONS {
+            if bindings.pressed(&self.keys, action) {
+                input |= button;
+            }
+        }
+
+        for gamepad in self.gamepads.iter() {
+            input |= gamepad_input(gamepad, &self.gamepad_buttons, &self.gamepad_axes).0;
+        }
+        input
+    }
+
+    /// Whether reload is held on any device. The west button reloads on
+    /// gamepads.
+    pub fn reload(&self) -> bool {
+        let bindings = self.bindings();
+        bindings.pressed(&self.keys, Action::Reload)
+            || bindings.just_pressed(&self.keys, Action::Reload)
+            || self.gamepads.iter().any(|gamepad| {
+                self.gamepad_buttons
+                    .pressed(GamepadButton::new(gamepad, GamepadButtonType::West))
+            })
+    }
+
+    /// Keys pressed and released again within this update, which
+    /// [`Self::held_buttons`] misses
+    fn tapped_buttons(&self) -> u8 {
+        let bindings = self.bindings();
+        let mut input = 0u8;
+        for (action, button) in [
+            (Action::Up, INPUT_UP),
+            (Action::Down, INPUT_DOWN),
+            (Action::Left, INPUT_LEFT),
+            (Action::Right, INPUT_RIGHT),
+            (Action::Fire, INPUT_FIRE),
+        ] {
+            if bindings.just_pressed(&self.keys, action) {
+                input |= button;
+            }
+        }
+        input
+    }
+
+    /// Buttons of the second hot-seat player held or pressed this update, and
+    /// whether they're reloading. Their presses aren't latched between
+    /// sampled frames, so catching taps within one update has to do.
+    fn second_player_buttons(&self) -> (u8, bool) {
+        let [_, bindings] = KeyBindings::hot_seat();
+        let held = |action| {
+            bindings.pressed(&self.keys, action) || bindings.just_pressed(&self.keys, action)
+        };
+        let buttons = ACTION_BUTTONS
+            .into_iter()
+            .filter(|(action, _)| held(*action))
+            .fold(0, |input, (_, button)| input | button);
+        (buttons, held(Action::Reload))
+    }
+
+    /// Direction a gamepad's right stick aims in, if any is pushed far enough
+    fn stick_aim(&self) -> Option<Vec2> {
+        self.gamepads
+            .iter()
+            .find_map(|gamepad| gamepad_input(gamepad, &self.gamepad_buttons, &self.gamepad_axes).1)
+    }
+}
 
-    if keys.any_pressed([KeyCode::Up, KeyCode::W]) {
+#[cfg(feature = "presentation")]
+pub fn input(
+    handle: In<PlayerHandle>,
+    controls: LocalControls,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    players: Query<(&Player, &Transform)>,
+    local_handle: Option<Res<LocalPlayerHandle>>,
+    bots: BotBrains,
+    focus: Res<WindowFocus>,
+    pause_vote: Res<PauseVote>,
+    mut buffer: ResMut<InputBuffer>,
+) -> PlayerInput {
+    let second_player = controls.hot_seat.is_some() && handle.0 == HOT_SEAT_HANDLE;
+    if let Some(input) = bots.input(handle.0) {
+        return input;
+    }
+    // Other local players are practice targets, which stand still
+    if !second_player && local_handle.map_or(false, |local| local.0 != handle.0) {
+        return PlayerInput::default();
+    }
+    let mut flags = if pause_vote.agreed() { FLAG_PAUSE } else { 0 };
+    if !focus.0 {
+        return PlayerInput { flags, ..default() };
+    }
+    // No mouse for the second player, they aim where they move
+    if second_player {
+        let (buttons, reload) = controls.second_player_buttons();
+        if reload {
+            flags |= FLAG_RELOAD;
+        }
+        return PlayerInput {
+            buttons,
+            aim: 0,
+            flags,
+        };
+    }
+    if std::mem::take(&mut buffer.reload) || controls.reload() {
+        flags |= FLAG_RELOAD;
+    }
+    let mut input = buffer.take(controls.held_buttons());
+    let mut aim = 0u8;
+
+    // The stick takes over from the mouse while it's pushed
+    if let Some(aim_vector) = controls
+        .stick_aim()
+        .or_else(|| mouse_aim(handle.0, &windows, &cameras, &players))
+    {
+        input |= INPUT_AIM;
+        aim = quantize_angle(aim_vector);
+    }
+
+    PlayerInput {
+        buttons: input,
+        aim,
+        flags,
+    }
+}
+
+/// Buttons held on `gamepad`, and its right stick direction if it's aiming.
+/// The left stick and d-pad move, the south button and right trigger fire,
+/// the east button and left trigger dash.
+#[cfg(feature = "presentation")]
+fn gamepad_input(
+    gamepad: Gamepad,
+    buttons: &Input<GamepadButton>,
+    axes: &Axis<GamepadAxis>,
+) -> (u8, Option<Vec2>) {
+    let button = |button_type| buttons.pressed(GamepadButton::new(gamepad, button_type));
+    let axis = |axis_type| axes.get(GamepadAxis::new(gamepad, axis_type)).unwrap_or(0.);
+    let mut input = 0u8;
+    let stick = Vec2::new(
+        axis(GamepadAxisType::LeftStickX),
+        axis(GamepadAxisType::LeftStickY),
+    );
+    if stick.y > STICK_AXIS_THRESHOLD || button(GamepadButtonType::DPadUp) {
         input |= INPUT_UP;
     }
-    if keys.any_pressed([KeyCode::Down, KeyCode::S]) {
+    if stick.y < -STICK_AXIS_THRESHOLD || button(GamepadButtonType::DPadDown) {
         input |= INPUT_DOWN;
     }
-    if keys.any_pressed([KeyCode::Left, KeyCode::A]) {
-        input |= INPUT_LEFT
+    if stick.x < -STICK_AXIS_THRESHOLD || button(GamepadButtonType::DPadLeft) {
+        input |= INPUT_LEFT;
     }
-    if keys.any_pressed([KeyCode::Right, KeyCode::D]) {
+    if stick.x > STICK_AXIS_THRESHOLD || button(GamepadButtonType::DPadRight) {
         input |= INPUT_RIGHT;
     }
-    if keys.any_pressed([KeyCode::Space, KeyCode::Return]) {
+    if button(GamepadButtonType::South) || button(GamepadButtonType::RightTrigger2) {
         input |= INPUT_FIRE;
     }
+    if button(GamepadButtonType::East) || button(GamepadButtonType::LeftTrigger2) {
+        input |= INPUT_DASH;
+    }
+    if button(GamepadButtonType::North) {
+        input |= INPUT_SWITCH;
+    }
+    let aim = Vec2::new(
+        axis(GamepadAxisType::RightStickX),
+        axis(GamepadAxisType::RightStickY),
+    );
+    (input, (aim.length() > STICK_AIM_THRESHOLD).then_some(aim))
+}
+
+/// World-space vector from the player to the mouse cursor, if it's over the window
+#[cfg(feature = "presentation")]
+fn mouse_aim(
+    handle: PlayerHandle,
+    windows: &Query<&Window, With<PrimaryWindow>>,
+    cameras: &Query<(&Camera, &GlobalTransform)>,
+    players: &Query<(&Player, &Transform)>,
+) -> Option<Vec2> {
+    let cursor = windows.get_single().ok()?.cursor_position()?;
+    let (camera, camera_transform) = cameras.get_single().ok()?;
+    let cursor = camera.viewport_to_world_2d(camera_transform, cursor)?;
+    let (_, player_transform) = players.iter().find(|(p, _)| p.handle == handle)?;
+    let aim = cursor - player_transform.translation.truncate();
+    (aim != Vec2::ZERO).then_some(aim)
+}
+
+/// Input moving along whichever of the 8 directions is closest to
+/// `direction`, and aiming along `aim` if given
+pub fn encode_input(direction: IVec2, fire: bool, aim: Option<IVec2>) -> PlayerInput {
+    let mut buttons = 0u8;
+    // Only press an axis if it's at least half as far along as the other
+    if direction.y * 2 > direction.x.abs() {
+        buttons |= INPUT_UP;
+    }
+    if -direction.y * 2 > direction.x.abs() {
+        buttons |= INPUT_DOWN;
+    }
+    if direction.x * 2 > direction.y.abs() {
+        buttons |= INPUT_RIGHT;
+    }
+    if -direction.x * 2 > direction.y.abs() {
+        buttons |= INPUT_LEFT;
+    }
+    if fire {
+        buttons |= INPUT_FIRE;
+    }
+    let aim = aim.filter(|aim| *aim != IVec2::ZERO).map(|aim| {
+        buttons |= INPUT_AIM;
+        quantize_angle(aim.as_vec2())
+    });
+    PlayerInput {
+        buttons,
+        aim: aim.unwrap_or(0),
+        flags: 0,
+    }
+}
 
-    input
+fn quantize_angle(v: Vec2) -> u8 {
+    let turns = v.y.atan2(v.x) / std::f32::consts::TAU;
+    (turns.rem_euclid(1.) * 256.).round() as i32 as u8
 }
 
 pub const DIRECTION_SCALE: i32 = 100;
 
-pub fn direction(input: u8) -> IVec2 {
+pub fn direction(input: PlayerInput) -> IVec2 {
+    let input = input.buttons;
     let mut direction = IVec2::ZERO;
     if input & INPUT_UP != 0 {
         direction.y += DIRECTION_SCALE;
@@ -52,6 +423,35 @@ pub fn direction(input: u8) -> IVec2 {
     direction.normalize_or_zero_at_scale(DIRECTION_SCALE)
 }
 
-pub fn fire(input: u8) -> bool {
-    input & INPUT_FIRE != 0
+pub fn fire(input: PlayerInput) -> bool {
+    input.buttons & INPUT_FIRE != 0
+}
+
+pub fn dash(input: PlayerInput) -> bool {
+    input.buttons & INPUT_DASH != 0
+}
+
+pub fn switch_weapon(input: PlayerInput) -> bool {
+    input.buttons & INPUT_SWITCH != 0
+}
+
+pub fn reload(input: PlayerInput) -> bool {
+    input.flags & FLAG_RELOAD != 0
+}
+
+/// Whether the player's peer is part of an agreed pause
+#[cfg(feature = "presentation")]
+pub fn pause(input: PlayerInput) -> bool {
+    input.flags & FLAG_PAUSE != 0
+}
+
+/// Aim direction at [`DIRECTION_SCALE`], if the player is aiming
+pub fn aim_direction(input: PlayerInput) -> Option<IVec2> {
+    if input.buttons & INPUT_AIM == 0 {
+        return None;
+    }
+    Some(fixed_point::direction(
+        input.aim as i32 * fixed_point::AIM_STEP,
+        DIRECTION_SCALE,
+    ))
 }
@@ -0,0 +1,172 @@
+use crate::{
+    camera::clamp_camera_to_map,
+    fixed_timestep::FixedTimestep,
+    lobby_settings::LobbySettings,
+    presentation_clock::PresentationClock,
+    vfx::{Effect, EffectShown},
+    vision::Vision,
+    GameState, IVec2Ext, LocalPlayerHandle, F2I,
+};
+use bevy::{prelude::*, transform::TransformSystem};
+
+/// Screen shake and hit-stop. Kills and hits shake the camera, harder the
+/// closer to the view they are, and the local player getting hit or a kill
+/// nearby briefly slows cosmetic time down. Both are driven by
+/// [`EffectShown`], so a resimulated frame never shakes twice, and neither
+/// touches the simulation: hit-stop slows the [`PresentationClock`], not
+/// GGRS. Can be turned off with [`JuiceSettings`].
+pub struct JuicePlugin;
+
+impl Plugin for JuicePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<JuiceSettings>()
+            .init_resource::<CameraShake>()
+            .add_system(
+                unshake_camera
+                    .in_base_set(CoreSet::PreUpdate)
+                    .run_if(in_state(GameState::InGame)),
+            )
+            .add_systems(
+                (react_to_effects, shake_camera.after(react_to_effects))
+                    .in_base_set(CoreSet::PostUpdate)
+                    .after(clamp_camera_to_map)
+                    .before(TransformSystem::TransformPropagate)
+                    .distributive_run_if(in_state(GameState::InGame)),
+            )
+            .add_system(reset_shake.in_schedule(OnExit(GameState::InGame)));
+    }
+}
+
+/// Trauma added by a kill right at the center of the view
+const KILL_TRAUMA: f32 = 0.6;
+/// Trauma added by a hit at the center of the view, at any damage
+const HIT_TRAUMA: f32 = 0.25;
+/// Effects this far from the center of the view don't shake it at all
+const SHAKE_RANGE_RF: f32 = 12.;
+/// Camera offset at full trauma, in world units
+const MAX_SHAKE_RF: f32 = 0.35;
+/// Trauma lost per second
+const TRAUMA_DECAY: f32 = 1.8;
+/// How fast the shake wobbles, per second
+const SHAKE_FREQUENCY: f32 = 30.;
+const HIT_STOP_SECS: f32 = 0.06;
+const KILL_STOP_SECS: f32 = 0.1;
+/// Cosmetic time runs this fast during a hit-stop
+const HIT_STOP_TIME_SCALE: f32 = 0.1;
+/// Kills further away than this don't cause a hit-stop
+const KILL_STOP_RANGE_RF: f32 = 5.;
+
+#[derive(Resource)]
+pub struct JuiceSettings {
+    pub enabled: bool,
+}
+
+impl Default for JuiceSettings {
+    fn default() -> Self {
+        Self { enabled: true }
+    }
+}
+
+#[derive(Resource, Default)]
+struct CameraShake {
+    /// 0 to 1. The shake grows with its square, so small bumps stay subtle.
+    trauma: f32,
+    /// Offset added to the camera this frame, taken off again before the
+    /// camera is moved next frame
+    applied: Vec2,
+    /// Real seconds the shake has been going, for its wobble
+    secs: f32,
+}
+
+fn react_to_effects(
+    mut shown: EventReader<EffectShown>,
+    settings: Res<JuiceSettings>,
+    lobby_settings: Res<LobbySettings>,
+    vision: Res<Vision>,
+    local_handle: Option<Res<LocalPlayerHandle>>,
+    cameras: Query<&Transform, With<Camera>>,
+    mut shake: ResMut<CameraShake>,
+    mut clock: ResMut<PresentationClock>,
+) {
+    if !settings.enabled {
+        shown.clear();
+        return;
+    }
+    let Ok(camera) = cameras.get_single() else {
+        return;
+    };
+    let center = camera.translation.truncate();
+    for EffectShown(event) in shown.iter() {
+        // Shaking for what happens in the fog would give it away
+        if !vision.can_see(event.position, &lobby_settings) {
+            continue;
+        }
+        // On wrapping maps, the closest copy of the effect is the one in view
+        let distance = lobby_settings
+            .nearest_image(event.position, (center * F2I as f32).as_ivec2())
+            .i2f()
+            .distance(center);
+        let closeness = (1. - distance / SHAKE_RANGE_RF).max(0.);
+        let is_local = local_handle
+            .as_ref()
+            .map_or(false, |handle| handle.0 == event.source);
+        match event.effect {
+            Effect::DeathBurst => {
+                shake.trauma += KILL_TRAUMA * closeness;
+                if distance < KILL_STOP_RANGE_RF {
+                    clock.slow_down(KILL_STOP_SECS, HIT_STOP_TIME_SCALE);
+                }
+            }
+            Effect::Hit(_) => {
+                shake.trauma += HIT_TRAUMA * closeness;
+                if is_local {
+                    clock.slow_down(HIT_STOP_SECS, HIT_STOP_TIME_SCALE);
+                }
+            }
+            Effect::MuzzleFlash | Effect::BulletTrail | Effect::Shootdown => {}
+        }
+    }
+    shake.trauma = shake.trauma.min(1.);
+}
+
+/// Wobbles the camera around wherever it was put this frame. Runs after the
+/// camera is clamped to the map, so a shake can peek a little past the edge.
+fn shake_camera(
+    timestep: Res<FixedTimestep>,
+    mut shake: ResMut<CameraShake>,
+    mut cameras: Query<&mut Transform, With<Camera>>,
+) {
+    if shake.trauma <= 0. {
+        return;
+    }
+    // Goes by real time, so a hit-stop doesn't draw the shake out
+    let delta = timestep.real_delta_seconds();
+    shake.trauma = (shake.trauma - TRAUMA_DECAY * delta).max(0.);
+    shake.secs += delta;
+    let phase = shake.secs * SHAKE_FREQUENCY;
+    let direction = Vec2::new(phase.sin(), (phase * 1.3 + 1.).sin());
+    let offset = direction * shake.trauma.powi(2) * MAX_SHAKE_RF;
+    for mut transform in cameras.iter_mut() {
+        transform.translation += offset.extend(0.);
+    }
+    shake.applied = offset;
+}
+
+/// The follow systems set the camera's position from scratch, but the free
+/// camera only moves it, so the shake has to come off first
+fn unshake_camera(
+    mut shake: ResMut<CameraShake>,
+    mut cameras: Query<&mut Transform, With<Camera>>,
+) {
+    let applied = std::mem::take(&mut shake.applied);
+    if applied == Vec2::ZERO {
+        return;
+    }
+    for mut transform in cameras.iter_mut() {
+        transform.translation -= applied.extend(0.);
+    }
+}
+
+fn reset_shake(mut shake: ResMut<CameraShake>) {
+    *shake = CameraShake::default();
+}
@@ -0,0 +1,245 @@
+use crate::{components::IsLocal, GameState};
+use bevy::prelude::*;
+use bevy_egui::egui::{CollapsingHeader, Grid, Ui};
+use serde::{Deserialize, Serialize};
+
+/// Lets players rebind the keyboard controls from the lobby. Bindings are a
+/// property of the local player, kept per tab in cookies like
+/// [`crate::components::UserInfo`]. Rebinding captures the next key pressed.
+pub struct KeyBindingsPlugin;
+
+impl Plugin for KeyBindingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<KeyCapture>()
+            .add_system(capture_key.in_set(OnUpdate(GameState::Matchmaking)))
+            .add_system(cancel_capture.in_schedule(OnExit(GameState::Matchmaking)));
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Action {
+    Up,
+    Down,
+    Left,
+    Right,
+    Fire,
+    Dash,
+    SwitchWeapon,
+    Reload,
+    Emote,
+}
+
+impl Action {
+    const ALL: [Self; 9] = [
+        Self::Up,
+        Self::Down,
+        Self::Left,
+        Self::Right,
+        Self::Fire,
+        Self::Dash,
+        Self::SwitchWeapon,
+        Self::Reload,
+        Self::Emote,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            Self::Up => "Up",
+            Self::Down => "Down",
+            Self::Left => "Left",
+            Self::Right => "Right",
+            Self::Fire => "Fire",
+            Self::Dash => "Dash",
+            Self::SwitchWeapon => "Switch weapon",
+            Self::Reload => "Reload",
+            Self::Emote => "Emote",
+        }
+    }
+}
+
+/// Keys bound to each action, as a primary and an alternative
+type Binding = [Option<KeyCode>; 2];
+
+#[derive(Component, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(default)]
+pub struct KeyBindings {
+    up: Binding,
+    down: Binding,
+    left: Binding,
+    right: Binding,
+    fire: Binding,
+    dash: Binding,
+    switch_weapon: Binding,
+    reload: Binding,
+    emote: Binding,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self {
+            up: [Some(KeyCode::W), Some(KeyCode::Up)],
+            down: [Some(KeyCode::S), Some(KeyCode::Down)],
+            left: [Some(KeyCode::A), Some(KeyCode::Left)],
+            right: [Some(KeyCode::D), Some(KeyCode::Right)],
+            fire: [Some(KeyCode::Space), Some(KeyCode::Return)],
+            dash: [Some(KeyCode::LShift), Some(KeyCode::RShift)],
+            switch_weapon: [Some(KeyCode::Q), None],
+            reload: [Some(KeyCode::R), None],
+            emote: [Some(KeyCode::T), None],
+        }
+    }
+}
+
+impl KeyBindings {
+    /// The keyboard split in two for [`crate::hot_seat`]: WASD and space on
+    /// the left, the arrows and enter on the right. Fixed rather than
+    /// rebindable, so neither half can steal the other's keys.
+    pub fn hot_seat() -> [Self; 2] {
+        [
+            Self {
+                up: [Some(KeyCode::W), None],
+                down: [Some(KeyCode::S), None],
+                left: [Some(KeyCode::A), None],
+                right: [Some(KeyCode::D), None],
+                fire: [Some(KeyCode::Space), None],
+                dash: [Some(KeyCode::LShift), None],
+                switch_weapon: [Some(KeyCode::Q), None],
+                reload: [Some(KeyCode::R), None],
+                emote: [Some(KeyCode::T), None],
+            },
+            Self {
+                up: [Some(KeyCode::Up), None],
+                down: [Some(KeyCode::Down), None],
+                left: [Some(KeyCode::Left), None],
+                right: [Some(KeyCode::Right), None],
+                fire: [Some(KeyCode::Return), None],
+                dash: [Some(KeyCode::RShift), None],
+                switch_weapon: [Some(KeyCode::Slash), None],
+                reload: [Some(KeyCode::Period), None],
+                emote: [None, None],
+            },
+        ]
+    }
+
+    fn binding(&self, action: Action) -> &Binding {
+        match action {
+            Action::Up => &self.up,
+            Action::Down => &self.down,
+            Action::Left => &self.left,
+            Action::Right => &self.right,
+            Action::Fire => &self.fire,
+            Action::Dash => &self.dash,
+            Action::SwitchWeapon => &self.switch_weapon,
+            Action::Reload => &self.reload,
+            Action::Emote => &self.emote,
+        }
+    }
+
+    fn binding_mut(&mut self, action: Action) -> &mut Binding {
+        match action {
+            Action::Up => &mut self.up,
+            Action::Down => &mut self.down,
+            Action::Left => &mut self.left,
+            Action::Right => &mut self.right,
+            Action::Fire => &mut self.fire,
+            Action::Dash => &mut self.dash,
+            Action::SwitchWeapon => &mut self.switch_weapon,
+            Action::Reload => &mut self.reload,
+            Action::Emote => &mut self.emote,
+        }
+    }
+
+    pub fn pressed(&self, keys: &Input<KeyCode>, action: Action) -> bool {
+        self.binding(action)
+            .iter()
+            .flatten()
+            .any(|&key| keys.pressed(key))
+    }
+
+    pub fn just_pressed(&self, keys: &Input<KeyCode>, action: Action) -> bool {
+        self.binding(action)
+            .iter()
+            .flatten()
+            .any(|&key| keys.just_pressed(key))
+    }
+
+    /// Binds `key` to `slot` of `action`, taking it away from wherever else it
+    /// was bound so one key never does two things
+    fn bind(&mut self, action: Action, slot: usize, key: KeyCode) {
+        for other in Action::ALL {
+            for bound in self.binding_mut(other) {
+                if *bound == Some(key) {
+                    *bound = None;
+                }
+            }
+        }
+        self.binding_mut(action)[slot] = Some(key);
+    }
+}
+
+/// The binding waiting for a key press, if any
+#[derive(Resource, Default)]
+pub struct KeyCapture(Option<(Action, usize)>);
+
+impl KeyCapture {
+    pub fn is_capturing(&self) -> bool {
+        self.0.is_some()
+    }
+}
+
+/// Escape cancels a capture, backspace clears the binding
+pub fn capture_key(
+    keys: Res<Input<KeyCode>>,
+    mut capture: ResMut<KeyCapture>,
+    mut bindings: Query<&mut KeyBindings, With<IsLocal>>,
+) {
+    let Some((action, slot)) = capture.0 else {
+        return;
+    };
+    let Some(&key) = keys.get_just_pressed().next() else {
+        return;
+    };
+    capture.0 = None;
+    let Ok(mut bindings) = bindings.get_single_mut() else {
+        return;
+    };
+    match key {
+        KeyCode::Escape => {}
+        KeyCode::Back => bindings.binding_mut(action)[slot] = None,
+        key => bindings.bind(action, slot, key),
+    }
+}
+
+fn cancel_capture(mut capture: ResMut<KeyCapture>) {
+    capture.0 = None;
+}
+
+/// The lobby's "Controls" section
+pub fn key_bindings_ui(ui: &mut Ui, bindings: &mut KeyBindings, capture: &mut KeyCapture) {
+    CollapsingHeader::new("Controls").show(ui, |ui| {
+        Grid::new("key_bindings").show(ui, |ui| {
+            for action in Action::ALL {
+                ui.label(action.label());
+                for slot in 0..2 {
+                    let text = if capture.0 == Some((action, slot)) {
+                        "Press a key...".to_string()
+                    } else {
+                        bindings.binding(action)[slot]
+                            .map_or("-".to_string(), |key| format!("{key:?}"))
+                    };
+                    if ui.button(text).clicked() {
+                        capture.0 = Some((action, slot));
+                    }
+                }
+                ui.end_row();
+            }
+        });
+        if capture.0.is_some() {
+            ui.weak("Escape cancels, backspace unbinds");
+        }
+        if ui.button("Reset to defaults").clicked() {
+            capture.0 = None;
+            *bindings = KeyBindings::default();
+        }
+    });
+}
@@ -0,0 +1,248 @@
+use crate::{
+    components::{IsLocal, MatchBoxPeerId, TabId},
+    lobby::{AutoResume, SaveTransfers, SocketExt},
+    lobby_events::{LobbyEvent, LobbyEventLog},
+    lobby_settings::lobby_host,
+    net::{Messages, P2PMessage},
+    quick_play::QuickPlay,
+    room::Room,
+    GameState,
+};
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+use bevy_egui::{
+    egui::{Align2, Window},
+    EguiContexts,
+};
+use bevy_matchbox::prelude::*;
+
+/// Lets the lobby host remove players. Kicks go out as [`P2PMessage::Kick`],
+/// only accepted from the host: the kicked peer leaves the room and everyone
+/// else drops them. The host also bans them from the room for the rest of the
+/// session, kicking them again as soon as they're back. Peers that never said
+/// who they are can't be kicked by tab, so the host can stop waiting for them
+/// instead with [`P2PMessage::StopWaiting`], which works the same way.
+pub struct KickPlugin;
+
+impl Plugin for KickPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<KickPlayer>()
+            .add_event::<ApplyKick>()
+            .add_event::<StopWaiting>()
+            .add_event::<ApplyStopWaiting>()
+            .init_resource::<BanList>()
+            .init_resource::<DroppedPeers>()
+            .add_systems(
+                (
+                    kick_players,
+                    enforce_bans.before(kick_players),
+                    apply_kicks.after(kick_players).after(apply_stop_waiting),
+                    stop_waiting,
+                    apply_stop_waiting.after(stop_waiting),
+                )
+                    .in_set(OnUpdate(GameState::Matchmaking)),
+            )
+            .add_system(
+                kicked_ui
+                    .in_set(OnUpdate(GameState::RoomSelect))
+                    .run_if(resource_exists::<Kicked>()),
+            );
+    }
+}
+
+/// Sent by the host's kick button
+pub struct KickPlayer(pub TabId);
+
+/// A kick to carry out locally, from the host's [`P2PMessage::Kick`] or our
+/// own [`KickPlayer`]
+pub struct ApplyKick(pub TabId);
+
+/// Sent by the host to start without a peer that's holding up the game
+pub struct StopWaiting(pub PeerId);
+
+/// Drops a peer locally, from the host's [`P2PMessage::StopWaiting`] or our own
+/// [`StopWaiting`]
+pub struct ApplyStopWaiting(pub PeerId);
+
+/// Peers nobody waits for anymore, whose messages are ignored. Peer ids are
+/// new with every connection, so a dropped player can come back by
+/// reconnecting.
+#[derive(Resource, Default)]
+pub struct DroppedPeers(HashSet<PeerId>);
+
+impl DroppedPeers {
+    pub fn contains(&self, peer_id: &PeerId) -> bool {
+        self.0.contains(peer_id)
+    }
+}
+
+/// Tabs the local host kicked, per room
+#[derive(Resource, Default)]
+struct BanList(HashMap<String, HashSet<String>>);
+
+/// Present on the room selection screen after being kicked, until the player
+/// acknowledges it
+#[derive(Resource)]
+pub struct Kicked {
+    room: String,
+}
+
+fn kick_players(
+    mut events: EventReader<KickPlayer>,
+    mut socket: ResMut<MatchboxSocket<MultipleChannels>>,
+    mut bans: ResMut<BanList>,
+    room: Res<Room>,
+    peers: Query<&MatchBoxPeerId>,
+    mut apply: EventWriter<ApplyKick>,
+) {
+    let is_host = socket.id().is_some() && lobby_host(peers.iter()) == socket.id();
+    for KickPlayer(tab_id) in events.iter() {
+        if !is_host {
+            warn!("Only the host can kick players");
+            continue;
+        }
+        info!("Kicking {tab_id:?}");
+        bans.0
+            .entry(room.0.clone())
+            .or_default()
+            .insert(tab_id.0.clone());
+        for peer_id in socket.connected_peers().collect::<Vec<_>>().iter() {
+            socket.send_p2p_message(peer_id, P2PMessage::Kick(tab_id.clone()));
+        }
+        apply.send(ApplyKick(tab_id.clone()));
+    }
+}
+
+/// Kicks banned players again as soon as they tell us who they are
+fn enforce_bans(
+    socket: Res<MatchboxSocket<MultipleChannels>>,
+    bans: Res<BanList>,
+    room: Res<Room>,
+    peers: Query<&MatchBoxPeerId>,
+    new_tabs: Query<&TabId, (Added<TabId>, Without<IsLocal>)>,
+    mut kicks: EventWriter<KickPlayer>,
+) {
+    if socket.id().is_none() || lobby_host(peers.iter()) != socket.id() {
+        return;
+    }
+    let Some(banned) = bans.0.get(&room.0) else {
+        return;
+    };
+    for tab_id in new_tabs.iter().filter(|tab_id| banned.contains(&tab_id.0)) {
+        info!("{tab_id:?} is banned from this room");
+        kicks.send(KickPlayer(tab_id.clone()));
+    }
+}
+
+fn apply_kicks(
+    mut commands: Commands,
+    mut events: EventReader<ApplyKick>,
+    players: Query<(Entity, &MatchBoxPeerId, Option<&TabId>, Option<&IsLocal>)>,
+    mut log: ResMut<LobbyEventLog>,
+    time: Res<Time>,
+    mut messages: ResMut<Messages>,
+    mut transfers: ResMut<SaveTransfers>,
+    room: Res<Room>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    for ApplyKick(tab_id) in events.iter() {
+        let kicked = players
+            .iter()
+            .filter(|(.., tab, _)| tab.map_or(false, |tab| tab.0 == tab_id.0));
+        for (entity, peer_id, _, is_local) in kicked {
+            if is_local.is_none() {
+                log.record(
+                    &time,
+                    peer_id.0,
+                    LobbyEvent::Left,
+                    &mut commands.entity(entity),
+                );
+                continue;
+            }
+            info!("Kicked from room {}", room.0);
+            commands.remove_resource::<MatchboxSocket<MultipleChannels>>();
+            commands.remove_resource::<QuickPlay>();
+            commands.remove_resource::<AutoResume>();
+            messages.0.clear();
+            transfers.clear();
+            // The local player is recreated from cookies when joining again
+            for (entity, ..) in players.iter() {
+                commands.entity(entity).despawn_recursive();
+            }
+            commands.insert_resource(Kicked {
+                room: room.0.clone(),
+            });
+            next_state.set(GameState::RoomSelect);
+            return;
+        }
+    }
+}
+
+fn stop_waiting(
+    mut events: EventReader<StopWaiting>,
+    mut socket: ResMut<MatchboxSocket<MultipleChannels>>,
+    peers: Query<&MatchBoxPeerId>,
+    mut apply: EventWriter<ApplyStopWaiting>,
+) {
+    let is_host = socket.id().is_some() && lobby_host(peers.iter()) == socket.id();
+    for StopWaiting(peer_id) in events.iter() {
+        if !is_host {
+            warn!("Only the host can stop waiting for players");
+            continue;
+        }
+        info!("Not waiting for {peer_id:?} anymore");
+        for other in socket.connected_peers().collect::<Vec<_>>().iter() {
+            socket.send_p2p_message(other, P2PMessage::StopWaiting(*peer_id));
+        }
+        apply.send(ApplyStopWaiting(*peer_id));
+    }
+}
+
+/// Forgets the dropped peer. If that's us, the others went on without us, so
+/// we leave like after a kick.
+fn apply_stop_waiting(
+    mut commands: Commands,
+    mut events: EventReader<ApplyStopWaiting>,
+    mut dropped: ResMut<DroppedPeers>,
+    players: Query<(Entity, &MatchBoxPeerId, Option<&TabId>, Option<&IsLocal>)>,
+    mut log: ResMut<LobbyEventLog>,
+    time: Res<Time>,
+    mut transfers: ResMut<SaveTransfers>,
+    mut kicks: EventWriter<ApplyKick>,
+) {
+    for ApplyStopWaiting(peer_id) in events.iter() {
+        let player = players.iter().find(|(_, id, ..)| id.0 == *peer_id);
+        if let Some((.., Some(tab_id), Some(_))) = player {
+            kicks.send(ApplyKick(tab_id.clone()));
+            continue;
+        }
+        dropped.0.insert(*peer_id);
+        transfers.remove(peer_id);
+        if let Some((entity, ..)) = player {
+            log.record(
+                &time,
+                *peer_id,
+                LobbyEvent::Left,
+                &mut commands.entity(entity),
+            );
+        }
+    }
+}
+
+fn kicked_ui(mut commands: Commands, mut contexts: EguiContexts, kicked: Res<Kicked>) {
+    Window::new("Kicked")
+        .anchor(Align2::CENTER_CENTER, [0., 0.])
+        .collapsible(false)
+        .resizable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.label(format!(
+                "The host removed you from the room \"{}\".",
+                kicked.room
+            ));
+            if ui.button("Back to room selection").clicked() {
+                commands.remove_resource::<Kicked>();
+            }
+        });
+}
@@ -0,0 +1,120 @@
+use crate::{
+    lobby::MAX_NAME_LENGTH,
+    low_power::LowPowerPreference,
+    room::{is_valid_room_name, Room},
+};
+use bevy::prelude::*;
+
+/// Reads the page's query parameters once at startup, so a link can set up
+/// the whole app for a playtest or a bug report, e.g.
+/// `?room=playtest&name=Sam&overlays=debug,net_stats`. Native builds take the
+/// same parameters, written as a query string, from `WEB_GHOST_LAUNCH`.
+///
+/// Must be added before the plugins that read the [`LaunchConfig`].
+pub struct LaunchConfigPlugin;
+
+impl Plugin for LaunchConfigPlugin {
+    fn build(&self, app: &mut App) {
+        let config = LaunchConfig::from_page_url();
+        info!("Launch config: {config:?}");
+        app.insert_resource(config);
+    }
+}
+
+#[cfg(feature = "native")]
+const LAUNCH_VAR: &str = "WEB_GHOST_LAUNCH";
+
+/// Settings from the launch link. Options that only apply once are taken out
+/// when used, so leaving a game doesn't apply them again.
+#[derive(Resource, Default, Debug, Clone)]
+pub struct LaunchConfig {
+    /// `room`: joined right away instead of showing the room select screen
+    pub room: Option<Room>,
+    /// `server`: matchbox server url, instead of the one picked from the page
+    pub server: Option<String>,
+    /// `name`: player name, which also skips onboarding on a first visit
+    pub name: Option<String>,
+    /// `synctest`: starts a practice game, which runs GGRS's sync test
+    pub synctest: bool,
+    /// `overlays`: comma separated overlays to show from the start, out of
+    /// `debug` and `net_stats`
+    pub debug_overlay: bool,
+    pub net_stats: bool,
+    /// `low_power`: `on`, `off` or `auto`
+    pub low_power: Option<LowPowerPreference>,
+    /// `save_wait`: seconds the lobby host waits on a missing save before
+    /// offering to start without it
+    pub save_wait_secs: Option<u32>,
+}
+
+impl LaunchConfig {
+    #[cfg(not(feature = "native"))]
+    fn from_page_url() -> Self {
+        use web_sys::{window, UrlSearchParams};
+
+        let params = window()
+            .and_then(|w| w.location().search().ok())
+            .and_then(|search| UrlSearchParams::new_with_str(&search).ok());
+        Self::from_params(|key| params.as_ref()?.get(key))
+    }
+
+    #[cfg(feature = "native")]
+    fn from_page_url() -> Self {
+        let query = std::env::var(LAUNCH_VAR).unwrap_or_default();
+        Self::from_params(|key| {
+            query.split('&').find_map(|pair| {
+                let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+                (name == key).then(|| value.to_string())
+            })
+        })
+    }
+
+    /// Flags count as set when present without a value, e.g. `?synctest`
+    fn from_params(get: impl Fn(&str) -> Option<String>) -> Self {
+        let flag = |key| get(key).map_or(false, |value| value != "0" && value != "false");
+        let overlays = get("overlays").unwrap_or_default();
+        let overlays = overlays.split(',').map(str::trim).collect::<Vec<_>>();
+        for overlay in &overlays {
+            if !["", "debug", "net_stats"].contains(overlay) {
+                warn!("Unknown overlay {overlay:?} in launch link");
+            }
+        }
+        let room = get("room").filter(|name| {
+            let valid = is_valid_room_name(name);
+            if !valid {
+                warn!("Ignoring invalid room {name:?} in launch link");
+            }
+            valid
+        });
+        let low_power = get("low_power").and_then(|value| match value.as_str() {
+            "on" => Some(LowPowerPreference::On),
+            "off" => Some(LowPowerPreference::Off),
+            "auto" => Some(LowPowerPreference::Auto),
+            other => {
+                warn!("Unknown low power mode {other:?} in launch link");
+                None
+            }
+        });
+        let save_wait_secs = get("save_wait").and_then(|value| {
+            let secs = value.parse().ok();
+            if secs.is_none() {
+                warn!("Invalid save wait {value:?} in launch link");
+            }
+            secs
+        });
+        Self {
+            room: room.map(Room),
+            server: get("server")
+                .map(|url| url.trim_end_matches('/').to_string())
+                .filter(|url| !url.is_empty()),
+            name: get("name")
+                .map(|name| name.trim().chars().take(MAX_NAME_LENGTH).collect())
+                .filter(|name: &String| !name.is_empty()),
+            synctest: flag("synctest"),
+            debug_overlay: overlays.contains(&"debug"),
+            net_stats: overlays.contains(&"net_stats"),
+            low_power,
+            save_wait_secs,
+        }
+    }
+}
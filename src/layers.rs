@@ -0,0 +1,83 @@
+use bevy::{prelude::*, transform::TransformSystem};
+use bevy_ggrs::Rollback;
+
+/// Assigns sprite z values from named draw layers instead of magic numbers
+pub struct LayersPlugin;
+
+impl Plugin for LayersPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(
+            apply_draw_layers
+                .in_base_set(CoreSet::PostUpdate)
+                .before(TransformSystem::TransformPropagate),
+        );
+        #[cfg(debug_assertions)]
+        app.add_system(
+            check_layer_collisions
+                .in_base_set(CoreSet::PostUpdate)
+                .after(apply_draw_layers),
+        );
+    }
+}
+
+/// Depth of the z band owned by each layer
+const LAYER_DEPTH: f32 = 10.;
+/// Number of distinct sub-positions within a layer before ordering wraps
+const LAYER_SLOTS: u32 = 1024;
+
+/// Draw layers from back to front
+#[derive(Component, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DrawLayer {
+    Background,
+    Grid,
+    Walls,
+    /// The edge of the shrinking zone
+    Zone,
+    Portals,
+    Corpses,
+    Pickups,
+    /// Patrolling ghosts
+    Hazards,
+    Players,
+    Bullets,
+    Vfx,
+    /// World-space UI such as health bars and cooldown rings
+    UiWorld,
+}
+
+impl DrawLayer {
+    pub fn z(self) -> f32 {
+        self as u8 as f32 * LAYER_DEPTH
+    }
+
+    /// z for an entity drawn `order` steps above the back of this layer, so
+    /// that e.g. newer bullets draw over older ones instead of z-fighting
+    pub fn z_with_order(self, order: u32) -> f32 {
+        let slot = (order % LAYER_SLOTS) as f32 / LAYER_SLOTS as f32;
+        self.z() + slot * LAYER_DEPTH * 0.9
+    }
+}
+
+fn apply_draw_layers(mut entities: Query<(&DrawLayer, &mut Transform, Option<&Rollback>)>) {
+    for (layer, mut transform, rollback) in entities.iter_mut() {
+        let z = layer.z_with_order(rollback.map_or(0, |r| r.id()));
+        if transform.translation.z != z {
+            transform.translation.z = z;
+        }
+    }
+}
+
+/// Warns about sprites whose z was set by hand to a value inside another
+/// layer's band, which usually means a forgotten magic number
+#[cfg(debug_assertions)]
+fn check_layer_collisions(
+    sprites: Query<(Entity, &GlobalTransform), (With<Sprite>, Without<DrawLayer>)>,
+    mut warned: Local<bevy::utils::HashSet<Entity>>,
+) {
+    for (entity, transform) in sprites.iter() {
+        let z = transform.translation().z;
+        if z > DrawLayer::Background.z() && warned.insert(entity) {
+            warn!("{entity:?} has z {z} but no DrawLayer, it may collide with layered sprites");
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use crate::{
+    components::{IsLocal, IsReady},
+    lobby::SocketExt,
+    net::{Messages, P2PMessage},
+    quick_play::QuickPlay,
+    GameState,
+};
+use bevy::prelude::*;
+use bevy_matchbox::prelude::*;
+
+/// Leaves a running game on purpose. The others are told on the reliable
+/// channel, so they can resume without the leaver right away instead of
+/// waiting for GGRS to notice the peer is gone.
+pub struct LeavePlugin;
+
+impl Plugin for LeavePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<LeaveGame>()
+            .add_system(leave_game.in_set(OnUpdate(GameState::InGame)))
+            .add_system(
+                close_socket
+                    .in_set(OnUpdate(GameState::RoomSelect))
+                    .run_if(resource_exists::<ClosingSocket>()),
+            )
+            .add_system(stop_closing_socket.in_schedule(OnExit(GameState::RoomSelect)));
+    }
+}
+
+/// Gives the [`P2PMessage::Leaving`] messages time to go out before the
+/// socket closes
+const CLOSE_DELAY_SECS: f64 = 0.5;
+
+/// Sent to leave the running game and go back to room selection
+pub struct LeaveGame;
+
+/// Present while the socket of a game we left waits to be closed
+#[derive(Resource)]
+struct ClosingSocket {
+    at: f64,
+}
+
+fn leave_game(
+    mut commands: Commands,
+    mut events: EventReader<LeaveGame>,
+    time: Res<Time>,
+    mut socket: Option<ResMut<MatchboxSocket<MultipleChannels>>>,
+    mut local_ready: Query<&mut IsReady, With<IsLocal>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if events.iter().count() == 0 {
+        return;
+    }
+    info!("Leaving the game");
+    if let Some(socket) = socket.as_mut() {
+        let peers = socket.connected_peers().collect::<Vec<_>>();
+        for peer_id in &peers {
+            socket.send_p2p_message(peer_id, P2PMessage::Leaving);
+        }
+    }
+    for mut ready in local_ready.iter_mut() {
+        ready.0 = false;
+    }
+    commands.remove_resource::<QuickPlay>();
+    commands.insert_resource(ClosingSocket {
+        at: time.elapsed_seconds_f64() + CLOSE_DELAY_SECS,
+    });
+    next_state.set(GameState::RoomSelect);
+}
+
+fn close_socket(
+    mut commands: Commands,
+    time: Res<Time>,
+    closing: Res<ClosingSocket>,
+    mut messages: ResMut<Messages>,
+) {
+    if time.elapsed_seconds_f64() < closing.at {
+        return;
+    }
+    commands.remove_resource::<MatchboxSocket<MultipleChannels>>();
+    commands.remove_resource::<ClosingSocket>();
+    messages.0.clear();
+}
+
+/// Joining another room before the delay is up replaces the socket anyway
+fn stop_closing_socket(mut commands: Commands) {
+    commands.remove_resource::<ClosingSocket>();
+}
@@ -1,41 +1,125 @@
+#[cfg(debug_assertions)]
+use crate::net_sim::{SimulatedConditions, SimulatedSocket};
 use crate::{
-    components::{IsLocal, IsReady, MatchBoxPeerId, Player, TabId, UserInfo},
-    kill_game, GameSaveData, GameState, GgrsConfig, LocalPlayerHandle, Messages, P2PMessage,
+    accessibility::{accessibility_ui, AccessibilitySettings},
+    achievements::{achievements_ui, AchievementProgress},
+    attract::{demo_bot_info, AttractMode, DEMO_BOT_COUNT},
+    avatar::avatar_picker,
+    bots::Bot,
+    classes::class_picker,
+    components::{
+        IsLocal, IsReady, MatchBoxPeerId, PeerVersion, Player, PlayerStats, SaveOffer, StartChoice,
+        TabId, UserInfo, BUILD_HASH, PROTOCOL_VERSION,
+    },
+    connection_quality::PeerPings,
+    cosmetics::{cosmetics_ui, CosmeticUnlocks},
+    countdown::StartCountdown,
+    filter::{display_name, WordFilter},
+    fixed_timestep::FixedTimestep,
+    haptics::HapticsSettings,
+    hot_seat::{HotSeat, HOT_SEAT_HANDLE},
+    juice::JuiceSettings,
+    key_bindings::{capture_key, key_bindings_ui, KeyBindings, KeyCapture},
+    kick::{ApplyKick, ApplyStopWaiting, DroppedPeers, StopWaiting},
+    launch_config::LaunchConfig,
+    lobby_events::{LobbyEvent, LobbyEventLog},
+    lobby_settings::{
+        lobby_host, seat_peers, seat_players, ConnectionSettings, LobbySettings, MAX_PLAYERS,
+    },
+    lobby_state::{LobbyState, ReceivedLobbyStates, SentLobbyState},
+    low_power::{low_power_ui, LowPowerSettings},
+    match_config::MatchConfig,
+    net::{Messages, P2PMessage},
+    overlay::OverlaySettings,
+    peer_names::PeerNames,
+    persistence::{read_from, write_to},
+    player_list::{PlayerList, PlayerRow},
+    quick_play::QuickPlay,
+    room::Room,
+    sandbox::Sandbox,
+    save::kill_game,
+    save_format, save_storage,
+    stats::stats_ui,
+    status::StatusEvent,
+    storage::{KeyValueStore, KeyValueStores, StorageArea},
+    GameSaveData, GameState, GgrsConfig, LocalPlayerHandle,
 };
 use bevy::prelude::*;
 use bevy_egui::{
-    egui::{Align, Layout, SidePanel, TextEdit, Ui},
+    egui::{Align, Align2, Checkbox, Layout, ProgressBar, SidePanel, Slider, TextEdit, Ui, Window},
     EguiContexts,
 };
-use bevy_ggrs::ggrs::{self, PlayerType};
+use bevy_ggrs::ggrs::{self, DesyncDetection, PlayerType};
 use bevy_matchbox::{
     prelude::{MultipleChannels, PeerId, PeerState},
     MatchboxSocket,
 };
+use chrono::Duration;
 use serde::{Deserialize, Serialize};
 use std::{collections::HashMap, fmt::Debug};
-use wasm_cookies::CookieOptions;
-use web_sys::window;
+
+pub const MAX_NAME_LENGTH: usize = 20;
 
 pub struct LobbyPlugin;
 
 impl Plugin for LobbyPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system(receive_from_peers.after(update_peers).after(kill_game))
+        app.add_event::<DiscardSaves>()
+            .init_resource::<SaveTransfers>()
+            .init_resource::<PracticeBots>()
+            .init_resource::<LobbyEventLog>()
+            .init_resource::<StalledPeers>()
+            .init_resource::<SentLobbyState>()
+            .init_resource::<ReceivedLobbyStates>()
+            .add_event::<SaveRequested>()
+            .add_event::<LobbyStateRequested>()
+            .add_system(receive_from_peers.after(update_peers).after(kill_game))
             .add_systems(
                 (
                     update_peers,
                     set_local_metadata,
+                    apply_launch_name.before(broadcast_my_info_changes),
+                    start_launch_practice,
                     trigger_game_start,
-                    ui,
+                    toggle_ready_on_enter.before(lobby_ui).before(capture_key),
+                    lobby_ui,
                     check_waiting_on,
-                    broadcast_my_info_changes.after(update_peers).after(ui),
+                    resolve_tab_id_collisions.after(receive_from_peers),
+                    track_stalled_peers
+                        .after(check_waiting_on)
+                        .after(receive_from_peers),
+                    stalled_start_ui.after(track_stalled_peers),
+                    discard_saves.after(lobby_ui).after(receive_from_peers),
+                    broadcast_my_info_changes
+                        .after(update_peers)
+                        .after(lobby_ui),
+                    ready_to_resume
+                        .before(broadcast_my_info_changes)
+                        .run_if(resource_exists::<AutoResume>()),
                 )
                     .in_set(OnUpdate(GameState::Matchmaking)),
             )
+            // Agreeing on the save to resume
             .add_systems(
                 (
-                    find_best_game_save.after(trigger_game_start),
+                    offer_local_save.after(set_local_metadata),
+                    choose_save
+                        .after(receive_from_peers)
+                        .after(stalled_start_ui),
+                    fetch_chosen_save.after(receive_from_peers),
+                    send_requested_saves.after(receive_from_peers),
+                    send_requested_lobby_state.after(receive_from_peers),
+                    request_missed_lobby_states.after(receive_from_peers),
+                )
+                    .in_set(OnUpdate(GameState::Matchmaking)),
+            )
+            .add_systems(
+                (
+                    take_chosen_save
+                        .after(trigger_game_start)
+                        .run_if(not(resource_exists::<PracticeMode>())),
+                    finish_auto_resume,
+                    forget_start_agreement.after(take_chosen_save),
                     launch_session
                         .after(update_peers)
                         .after(check_waiting_on)
@@ -45,6 +129,12 @@ impl Plugin for LobbyPlugin {
                     .in_schedule(OnExit(GameState::Matchmaking)),
             );
         add_local_property::<UserInfo>(app);
+        add_local_property::<PlayerStats>(app);
+        add_local_property::<CosmeticUnlocks>(app);
+        add_local_property::<AchievementProgress>(app);
+        add_local_property::<KeyBindings>(app);
+        add_local_property::<AccessibilitySettings>(app);
+        add_local_property::<LowPowerSettings>(app);
     }
 }
 
@@ -53,59 +143,120 @@ fn add_local_property<
 >(
     app: &mut App,
 ) {
-    app.add_system(update_local_property::<T>.run_if(in_state(GameState::Matchmaking)))
+    // Saved in every state, so properties that change in game are kept
+    app.add_system(update_local_property::<T>)
         .add_system(set_local_property::<T>.run_if(in_state(GameState::Matchmaking)));
 }
 
+/// Session storage key of the tab's [`TabId`]
+const TAB_ID_KEY: &str = "tab_id";
+
 fn set_local_metadata(
     mut commands: Commands,
     socket: Res<MatchboxSocket<MultipleChannels>>,
     local_players: Query<With<IsLocal>>,
     mut stored_gamesave: Option<Res<GameSaveData>>,
+    room: Res<Room>,
+    stores: Res<KeyValueStores>,
 ) {
     if local_players.is_empty() {
         if let Some(peer_id) = socket.id() {
             let peer_id_string = peer_id.0.to_string();
-            let window = window().unwrap();
-
-            let storage = window.session_storage().unwrap().unwrap();
-            const TAB_ID_KEY: &str = "tab_id";
-            let tab_id = if let Ok(Some(value)) = storage.get_item(TAB_ID_KEY) {
+            let session = stores.area(StorageArea::Session);
+            let tab_id = if let Some(value) = session.get(TAB_ID_KEY) {
                 value
             } else {
                 info!("{TAB_ID_KEY} not found, setting to {peer_id_string}");
-                storage.set_item(TAB_ID_KEY, &peer_id_string).unwrap();
+                if let Err(e) = session.set(TAB_ID_KEY, &peer_id_string) {
+                    warn!("Failed to store {TAB_ID_KEY}: {e}");
+                }
                 peer_id_string
             };
             let mut entity_commands = commands.spawn((
                 MatchBoxPeerId(peer_id),
                 IsLocal,
-                TabId(tab_id),
+                TabId(tab_id.clone()),
                 IsReady(false),
+                StartChoice::default(),
             ));
             if let Some(gamesave) = stored_gamesave.take() {
                 entity_commands.insert(gamesave.to_owned());
+            } else if let Some(gamesave) =
+                save_storage::load(stores.area(StorageArea::Local), &room.0, &tab_id)
+            {
+                info!("Restored game save from local storage");
+                entity_commands.insert(gamesave);
             }
         }
     }
 }
 
-fn get_cookie_map<T: for<'de> Deserialize<'de>>(key: &str) -> HashMap<String, T> {
-    wasm_cookies::get(key)
-        .map(|map_result| map_result.unwrap())
-        .map(|map| ron::from_str::<HashMap<String, T>>(&map).unwrap())
-        .unwrap_or_default()
+/// Renames the local player as asked by the launch link, once per visit
+fn apply_launch_name(
+    mut launch: ResMut<LaunchConfig>,
+    mut local: Query<&mut UserInfo, (With<IsLocal>, Added<UserInfo>)>,
+) {
+    let Ok(mut info) = local.get_single_mut() else {
+        return;
+    };
+    if let Some(name) = launch.name.take() {
+        info.name = name;
+    }
+}
+
+/// Starts a practice game for launch links asking for a sync test, once the
+/// local player is set up
+fn start_launch_practice(
+    mut commands: Commands,
+    mut launch: ResMut<LaunchConfig>,
+    local: Query<(), (With<IsLocal>, With<UserInfo>)>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if !launch.synctest || local.is_empty() {
+        return;
+    }
+    launch.synctest = false;
+    info!("Starting practice session from the launch link");
+    commands.insert_resource(PracticeMode);
+    next_state.set(GameState::InGame);
+}
+
+/// Whether any tab has stored a `T` in `cookies` before, i.e. this isn't a
+/// first visit
+pub fn has_stored_property<T: for<'de> Deserialize<'de>>(cookies: &dyn KeyValueStore) -> bool {
+    !get_cookie_map::<T>(cookies, std::any::type_name::<T>()).is_empty()
+}
+
+/// Stores `value` as the fallback that tabs without their own `T` start from
+pub fn store_default_property<T: for<'de> Deserialize<'de> + Serialize>(
+    cookies: &dyn KeyValueStore,
+    value: T,
+) {
+    let key = std::any::type_name::<T>();
+    let mut map = get_cookie_map::<T>(cookies, key);
+    map.insert(String::new(), value);
+    write_to(cookies, key, &map);
+}
+
+/// Values of a property by tab id, with the fallback under the empty key
+fn get_cookie_map<T: for<'de> Deserialize<'de>>(
+    cookies: &dyn KeyValueStore,
+    key: &str,
+) -> HashMap<String, T> {
+    read_from(cookies, key).unwrap_or_default()
 }
 
 fn set_local_property<T>(
     mut commands: Commands,
     entity: Query<(Entity, &TabId), (Without<T>, With<IsLocal>)>,
+    stores: Res<KeyValueStores>,
 ) where
     for<'de> T: Deserialize<'de> + Default + Serialize + Debug + Clone + Component,
 {
     if let Some((entity, tab_id)) = entity.iter().next() {
+        let cookies = stores.area(StorageArea::Cookies);
         let key = std::any::type_name::<T>();
-        let mut map = get_cookie_map::<T>(key);
+        let mut map = get_cookie_map::<T>(cookies, key);
         let value = if let Some(value) = map.get(&tab_id.0) {
             info!("{key} found in cookies: {value:?}");
             value.clone()
@@ -117,63 +268,211 @@ fn set_local_property<T>(
                 .unwrap_or_default();
             info!("{key} not found in cookies, setting to {value:?}");
             map.insert(tab_id.0.clone(), value.clone());
-            wasm_cookies::set(
-                key,
-                &ron::to_string(&map).unwrap(),
-                &CookieOptions::default(),
-            );
+            write_to(cookies, key, &map);
             value
         };
         commands.entity(entity).insert(value);
     }
 }
 
-fn update_local_property<T>(property: Query<(&T, &TabId), (Changed<T>, With<IsLocal>)>)
-where
+fn update_local_property<T>(
+    property: Query<(&T, &TabId), (Changed<T>, With<IsLocal>)>,
+    stores: Res<KeyValueStores>,
+) where
     for<'de> T: Deserialize<'de> + Serialize + Clone + Component,
 {
     if let Some((property, tab_id)) = property.iter().next() {
+        let cookies = stores.area(StorageArea::Cookies);
         let key = std::any::type_name::<T>();
-        let mut map = get_cookie_map::<T>(key);
+        let mut map = get_cookie_map::<T>(cookies, key);
         map.insert(tab_id.0.clone(), property.clone());
-        wasm_cookies::set(
-            key,
-            &ron::to_string(&map).unwrap(),
-            &CookieOptions::default(),
-        );
+        write_to(cookies, key, &map);
     }
 }
 
+/// Sends peers what changed about the local player, as a patch of only the
+/// changed fields, and the settings if we're the host
 fn broadcast_my_info_changes(
     mut socket: ResMut<MatchboxSocket<MultipleChannels>>,
-    my_info: Query<&UserInfo, (With<IsLocal>, Changed<UserInfo>)>,
-    my_ready: Query<&IsReady, (With<IsLocal>, Changed<IsReady>)>,
+    my_state: Query<
+        (&IsReady, &StartChoice, &UserInfo),
+        (
+            With<IsLocal>,
+            Or<(Changed<IsReady>, Changed<StartChoice>, Changed<UserInfo>)>,
+        ),
+    >,
+    mut sent: ResMut<SentLobbyState>,
+    settings: Res<LobbySettings>,
+    peers: Query<&MatchBoxPeerId>,
 ) {
-    for message in [
-        my_info
-            .get_single()
-            .map(|x| P2PMessage::UserInfo(x.clone())),
-        my_ready.get_single().map(|x| P2PMessage::Ready(x.0)),
-    ]
-    .iter()
-    .flatten()
-    {
+    let is_host = socket.id().is_some() && lobby_host(peers.iter()) == socket.id();
+    let settings_message =
+        (is_host && settings.is_changed()).then(|| P2PMessage::Settings(settings.clone()));
+    let state_message = my_state
+        .get_single()
+        .ok()
+        .and_then(|(ready, choice, info)| sent.update(LobbyState::new(ready, choice, info)))
+        .map(P2PMessage::LobbyState);
+    for message in state_message.iter().chain(settings_message.as_ref()) {
         for peer_id in socket.connected_peers().collect::<Vec<_>>().iter() {
             socket.send_p2p_message(peer_id, message.clone());
         }
     }
 }
 
-trait SocketExt {
+pub trait SocketExt {
     fn send_p2p_message(&mut self, peer_id: &PeerId, message: P2PMessage);
+    /// Sends `gamesave` encoded by [`save_format::encode`] and split into
+    /// [`SAVE_CHUNK_SIZE`] chunks
+    fn send_game_save(&mut self, peer_id: &PeerId, gamesave: &GameSaveData);
 }
 
+/// Largest slice of a compressed save sent in one message, comfortably under
+/// the WebRTC message size limit
+const SAVE_CHUNK_SIZE: usize = 15 * 1024;
+
 impl SocketExt for MatchboxSocket<MultipleChannels> {
     fn send_p2p_message(&mut self, peer_id: &PeerId, message: P2PMessage) {
-        self.channel(1).send(
-            bincode::serialize(&message).unwrap().into_boxed_slice(),
-            *peer_id,
-        );
+        let bytes = match message.encode() {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                error!("Failed to serialize {message:?}: {e}");
+                return;
+            }
+        };
+        self.channel(1).send(bytes.into_boxed_slice(), *peer_id);
+    }
+
+    fn send_game_save(&mut self, peer_id: &PeerId, gamesave: &GameSaveData) {
+        let encoded = save_format::encode(gamesave);
+        let total = encoded.chunks(SAVE_CHUNK_SIZE).len() as u32;
+        for (index, bytes) in encoded.chunks(SAVE_CHUNK_SIZE).enumerate() {
+            self.send_p2p_message(
+                peer_id,
+                P2PMessage::SaveChunk {
+                    index: index as u32,
+                    total,
+                    bytes: bytes.to_vec(),
+                },
+            );
+        }
+    }
+}
+
+/// A save being received from a peer, one chunk at a time. The reliable
+/// channel keeps chunks in order, so they are simply appended.
+struct SaveTransfer {
+    total: u32,
+    chunks: Vec<Vec<u8>>,
+    /// When the first chunk arrived, in seconds since startup
+    started_secs: f64,
+    /// When the latest chunk arrived, to tell stalled transfers from slow ones
+    last_chunk_secs: f64,
+    /// Bytes per second measured over the chunks after the first, which
+    /// only started the clock
+    throughput: Option<f64>,
+}
+
+impl SaveTransfer {
+    fn new(total: u32, now: f64) -> Self {
+        Self {
+            total,
+            chunks: Vec::new(),
+            started_secs: now,
+            last_chunk_secs: now,
+            throughput: None,
+        }
+    }
+
+    fn received(&self) -> u32 {
+        self.chunks.len() as u32
+    }
+
+    fn push(&mut self, bytes: Vec<u8>, now: f64) {
+        self.chunks.push(bytes);
+        self.last_chunk_secs = now;
+        let elapsed = now - self.started_secs;
+        if self.chunks.len() > 1 && elapsed > 0. {
+            let measured = self.chunks[1..].iter().map(Vec::len).sum::<usize>();
+            self.throughput = Some(measured as f64 / elapsed);
+        }
+    }
+
+    /// Rough size of the whole save. Every chunk but the last is full size.
+    fn estimated_bytes(&self) -> usize {
+        self.total as usize * SAVE_CHUNK_SIZE
+    }
+
+    /// Seconds until the rest arrives at the measured throughput
+    fn eta_secs(&self) -> Option<f64> {
+        let received = self.chunks.iter().map(Vec::len).sum::<usize>();
+        let remaining = self.estimated_bytes().saturating_sub(received);
+        self.throughput
+            .map(|throughput| remaining as f64 / throughput)
+    }
+
+    /// Progress line for the lobby, with the estimate once there is one
+    fn status(&self) -> String {
+        let size_kb = self.estimated_bytes() / 1024;
+        match (self.throughput, self.eta_secs()) {
+            (Some(throughput), Some(eta)) => format!(
+                "{}/{} of ~{size_kb} KB, {:.0} KB/s, ~{:.0} s left",
+                self.received(),
+                self.total,
+                throughput / 1024.,
+                eta.ceil()
+            ),
+            _ => format!("{}/{} of ~{size_kb} KB", self.received(), self.total),
+        }
+    }
+
+    /// The received save, if it's the one `offer` promised
+    fn decode(&self, offer: Option<&SaveOffer>) -> Option<GameSaveData> {
+        let Some(save) = save_format::decode(&self.chunks.concat()) else {
+            warn!("Failed to decode game save");
+            return None;
+        };
+        match (offer, save.hash()) {
+            (Some(offer), Ok(hash)) if offer.hash == hash => Some(save),
+            (_, Err(e)) => {
+                warn!("Failed to hash received game save: {e}");
+                None
+            }
+            _ => {
+                warn!("Received game save doesn't match the offer");
+                None
+            }
+        }
+    }
+}
+
+/// Saves still being received, by sender
+#[derive(Resource, Default)]
+pub struct SaveTransfers(HashMap<PeerId, SaveTransfer>);
+
+/// Forgets everything the lobby knows about the other peers. It's keyed by
+/// their peer ids, which are gone once the socket is replaced, and a stale
+/// save transfer would hold up the game start forever.
+pub fn forget_peers(commands: &mut Commands) {
+    commands.insert_resource(SaveTransfers::default());
+    commands.insert_resource(StalledPeers::default());
+    commands.insert_resource(DroppedPeers::default());
+    commands.insert_resource(ReceivedLobbyStates::default());
+}
+
+impl SaveTransfers {
+    /// Drops unfinished transfers, e.g. when leaving the room
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+
+    /// Drops `peer_id`'s unfinished transfer, if any
+    pub fn remove(&mut self, peer_id: &PeerId) {
+        self.0.remove(peer_id);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
     }
 }
 
@@ -190,56 +489,311 @@ fn maybe_mutate<T: Clone + PartialEq + Debug>(
     }
 }
 
-fn ui(
+/// Enter toggles ready while no widget has keyboard focus, so the lobby can
+/// be played without a mouse. Runs before the panel is drawn, while focus is
+/// still last frame's, so the Enter that finishes editing the name doesn't
+/// count. Neither does one being bound to an action.
+fn toggle_ready_on_enter(
     mut contexts: EguiContexts,
-    mut local_info: Query<(&mut UserInfo, &mut IsReady), With<IsLocal>>,
-    other_players: Query<(&UserInfo, &IsReady), Without<IsLocal>>,
-    waiting_on: Option<Res<WaitingOn>>,
+    keys: Res<Input<KeyCode>>,
+    key_capture: Res<KeyCapture>,
+    countdown: Option<Res<StartCountdown>>,
+    mut local_ready: Query<&mut IsReady, With<IsLocal>>,
+) {
+    let enter = keys.any_just_pressed([KeyCode::Return, KeyCode::NumpadEnter]);
+    if !enter || key_capture.is_capturing() {
+        return;
+    }
+    let unfocused = contexts
+        .ctx_mut()
+        .memory(|memory| memory.focus().is_none() && !memory.any_popup_open());
+    let locked = countdown.map_or(false, |countdown| countdown.is_locked());
+    if !unfocused || locked {
+        return;
+    }
+    for mut ready in local_ready.iter_mut() {
+        ready.0 = !ready.0;
+    }
+}
+
+/// The lobby's side panel. Drawn before the match settings window, so Tab
+/// moves focus through the panel first.
+pub fn lobby_ui(
+    mut contexts: EguiContexts,
+    mut local_info: Query<
+        (
+            &mut UserInfo,
+            &mut IsReady,
+            &mut StartChoice,
+            &MatchBoxPeerId,
+            Option<&PlayerStats>,
+            Option<&CosmeticUnlocks>,
+            Option<&AchievementProgress>,
+            Option<&mut KeyBindings>,
+            Option<&mut AccessibilitySettings>,
+            Option<&mut LowPowerSettings>,
+        ),
+        With<IsLocal>,
+    >,
+    other_players: Query<
+        (
+            &MatchBoxPeerId,
+            &UserInfo,
+            &IsReady,
+            &StartChoice,
+            Option<&TabId>,
+            Option<&PeerVersion>,
+        ),
+        Without<IsLocal>,
+    >,
+    (chosen, offers): (Option<Res<ChosenSave>>, Query<&SaveOffer>),
+    (waiting_on, countdown): (Option<Res<WaitingOn>>, Option<Res<StartCountdown>>),
+    mut word_filter: ResMut<WordFilter>,
+    mut overlay_settings: ResMut<OverlaySettings>,
+    mut commands: Commands,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut player_list: PlayerList,
+    (mut haptics, mut juice, mut key_capture): (
+        ResMut<HapticsSettings>,
+        ResMut<JuiceSettings>,
+        ResMut<KeyCapture>,
+    ),
+    (transfers, room): (Res<SaveTransfers>, Res<Room>),
+    log: Res<LobbyEventLog>,
+    mut discard: EventWriter<DiscardSaves>,
+    mut confirming_discard: Local<bool>,
 ) {
     if local_info.is_empty() {
         return;
     }
     SidePanel::left("left_panel").show(contexts.ctx_mut(), |ui| {
         ui.heading("Lobby");
+        ui.label(format!("Room: {}", word_filter.censor(&room.0)));
         ui.separator();
-        let (mut my_info, mut ready) = local_info.single_mut();
+        let (
+            mut my_info,
+            mut ready,
+            mut choice,
+            my_peer_id,
+            stats,
+            unlocks,
+            achievements,
+            key_bindings,
+            accessibility,
+            low_power,
+        ) = local_info.single_mut();
+        let is_host = lobby_host(
+            other_players
+                .iter()
+                .map(|(peer_id, ..)| peer_id)
+                .chain([my_peer_id]),
+        ) == Some(my_peer_id.0);
+        let (_, spectators) = seat_peers(
+            other_players
+                .iter()
+                .map(|(peer_id, ..)| peer_id.0)
+                .chain([my_peer_id.0]),
+        );
+        if !spectators.is_empty() {
+            ui.colored_label(
+                ui.visuals().warn_fg_color,
+                format!(
+                    "The room is full: only {MAX_PLAYERS} can play, {} will spectate",
+                    spectators.len()
+                ),
+            );
+            if spectators.contains(&my_peer_id.0) {
+                ui.label("You will spectate the next game");
+            }
+        }
         ui.horizontal(|ui| {
             ui.label("Name:");
-            maybe_mutate(ui, &mut my_info, |ui, UserInfo { name }| {
-                ui.add(TextEdit::singleline(name).clip_text(false));
-                const MAX_NAME_LENGTH: usize = 20;
-                if name.len() > MAX_NAME_LENGTH {
-                    name.truncate(MAX_NAME_LENGTH);
-                }
+            maybe_mutate(
+                ui,
+                &mut my_info,
+                |ui,
+                 UserInfo {
+                     name,
+                     color,
+                     avatar,
+                     ..
+                 }| {
+                    ui.color_edit_button_srgb(color);
+                    avatar_picker(ui, avatar);
+                    ui.add(TextEdit::singleline(name).clip_text(false));
+                    if name.chars().count() > MAX_NAME_LENGTH {
+                        *name = name.chars().take(MAX_NAME_LENGTH).collect();
+                    }
+                },
+            );
+        });
+        let locked = countdown.map_or(false, |countdown| countdown.is_locked());
+        // Like the ready state, the class can't change in the last moments before the start
+        ui.horizontal(|ui| {
+            ui.label("Class:");
+            maybe_mutate(ui, &mut my_info, |ui, info| {
+                ui.add_enabled_ui(!locked, |ui| class_picker(ui, &mut info.class));
             });
         });
         maybe_mutate(ui, &mut ready, |ui, ready| {
-            ui.checkbox(&mut ready.0, "I'm ready");
+            ui.add_enabled(!locked, Checkbox::new(&mut ready.0, "I'm ready"))
+                .on_hover_text("Or press Enter");
+        });
+        let chosen_save = chosen_offer(chosen.as_deref(), offers.iter());
+        if let Some(chosen_save) = chosen_save {
+            ui.group(|ui| {
+                maybe_mutate(ui, &mut choice, |ui, choice| {
+                    ui.set_enabled(!locked);
+                    ui.radio_value(
+                        choice,
+                        StartChoice::ResumeSave,
+                        format!(
+                            "Resume save from {}",
+                            chosen_save.timestamp.format("%Y-%m-%d %H:%M")
+                        ),
+                    );
+                    ui.radio_value(choice, StartChoice::NewGame, "Start a new game");
+                });
+                if let Some(reason) = chosen_save.config.incompatibility() {
+                    ui.colored_label(
+                        ui.visuals().error_fg_color,
+                        format!("Can't resume: {reason}. Start a new game instead."),
+                    );
+                }
+                if other_players
+                    .iter()
+                    .any(|(_, _, _, other, ..)| other != &*choice)
+                {
+                    ui.colored_label(
+                        ui.visuals().warn_fg_color,
+                        "Everyone must pick the same option to start",
+                    );
+                }
+                if !is_host {
+                    ui.weak("Only the host can discard the save for everyone");
+                } else if *confirming_discard {
+                    ui.label("Discard every player's save for good?");
+                    ui.horizontal(|ui| {
+                        if ui.button("Discard").clicked() {
+                            discard.send(DiscardSaves);
+                            *confirming_discard = false;
+                        }
+                        if ui.button("Cancel").clicked() {
+                            *confirming_discard = false;
+                        }
+                    });
+                } else if ui.button("Discard save for everyone").clicked() {
+                    *confirming_discard = true;
+                }
+            });
+        }
+        if ui.button("Practice offline").clicked() {
+            info!("Starting practice session");
+            commands.insert_resource(PracticeMode);
+            next_state.set(GameState::InGame);
+        }
+        if ui
+            .button("Hot-seat (2 players)")
+            .on_hover_text("WASD and space against the arrows and enter, on one keyboard")
+            .clicked()
+        {
+            info!("Starting hot-seat session");
+            commands.insert_resource(PracticeMode);
+            commands.insert_resource(HotSeat);
+            next_state.set(GameState::InGame);
+        }
+        ui.checkbox(&mut word_filter.enabled, "Filter offensive words");
+        ui.checkbox(
+            &mut overlay_settings.enabled,
+            "Share match data with page overlays",
+        );
+        if let Some(mut low_power) = low_power {
+            maybe_mutate(ui, &mut low_power, low_power_ui);
+        }
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut haptics.enabled, "Gamepad rumble");
+            ui.add_enabled(
+                haptics.enabled,
+                Slider::new(&mut haptics.strength, 0.0..=1.).show_value(false),
+            );
         });
+        ui.checkbox(&mut juice.enabled, "Screen shake and hit-stop");
+        maybe_mutate(ui, &mut my_info, |ui, info| {
+            cosmetics_ui(ui, &mut info.cosmetics, unlocks);
+        });
+        if let Some(mut key_bindings) = key_bindings {
+            maybe_mutate(ui, &mut key_bindings, |ui, bindings| {
+                key_bindings_ui(ui, bindings, &mut key_capture);
+            });
+        }
+        if let Some(mut accessibility) = accessibility {
+            maybe_mutate(ui, &mut accessibility, accessibility_ui);
+        }
+        if let Some(stats) = stats {
+            stats_ui(ui, stats);
+        }
+        if let Some(achievements) = achievements {
+            achievements_ui(ui, achievements);
+        }
+        if ui
+            .button(format!("Copy lobby log ({} events)", log.event_count()))
+            .on_hover_text("For bug reports about the lobby handshake")
+            .clicked()
+        {
+            ui.output_mut(|output| output.copied_text = log.export());
+        }
 
+        if other_players
+            .iter()
+            .any(|(.., version)| version.map_or(false, |v| !v.matches_local()))
+        {
+            ui.colored_label(
+                ui.visuals().error_fg_color,
+                "Some players run a different version of the game. \
+                 Everyone needs to refresh before the game can start.",
+            );
+        }
+
+        let rows = other_players
+            .iter()
+            .enumerate()
+            .map(
+                |(index, (peer_id, info, ready, choice, tab_id, version))| PlayerRow {
+                    index,
+                    peer_id: peer_id.0,
+                    info,
+                    ready: ready.0,
+                    choice: *choice,
+                    tab_id,
+                    different_version: version.map_or(false, |v| !v.matches_local()),
+                    spectator: spectators.contains(&peer_id.0),
+                    waiting: waiting_on
+                        .as_ref()
+                        .map_or(false, |waiting_on| waiting_on.0.contains(&peer_id.0)),
+                },
+            )
+            .collect::<Vec<_>>();
         ui.group(|ui| {
-            ui.heading("Other Players");
-            ui.separator();
-            for (index, (info, ready)) in other_players.iter().enumerate() {
-                ui.horizontal(|ui| {
-                    ui.label(if ready.0 { "☑" } else { "☐" });
-                    ui.label(format!("{index}: {}", info.name));
-                });
-            }
+            player_list.show(ui, &rows, &word_filter, chosen_save.is_some(), is_host);
         });
 
-        if let Some(waiting_on) = waiting_on {
-            if !waiting_on.0.is_empty() {
-                ui.with_layout(Layout::bottom_up(Align::Min), |ui| {
-                    ui.group(|ui| {
-                        for peer_id in waiting_on.0.iter() {
-                            ui.label(format!("{}...", peer_id.0.to_string().get(..8).unwrap()));
-                        }
-                        ui.separator();
-                        ui.label("Waiting on peers to rejoin:");
-                    });
+        if !transfers.0.is_empty() {
+            ui.with_layout(Layout::bottom_up(Align::Min), |ui| {
+                ui.group(|ui| {
+                    for (peer_id, transfer) in transfers.0.iter() {
+                        ui.add(
+                            ProgressBar::new(transfer.received() as f32 / transfer.total as f32)
+                                .text(format!(
+                                    "{}... {}",
+                                    short_peer_id(*peer_id),
+                                    transfer.status()
+                                )),
+                        );
+                    }
+                    ui.separator();
+                    ui.label("Receiving saves, the game can start once they're in:");
                 });
-            }
+            });
         }
     });
 }
@@ -247,30 +801,76 @@ fn ui(
 fn update_peers(
     mut commands: Commands,
     mut socket: ResMut<MatchboxSocket<MultipleChannels>>,
-    my_info: Query<(&TabId, &IsReady, &UserInfo, Option<&GameSaveData>), With<IsLocal>>,
+    my_info: Query<
+        (
+            &TabId,
+            &IsReady,
+            &StartChoice,
+            &UserInfo,
+            Option<&GameSaveData>,
+        ),
+        With<IsLocal>,
+    >,
     player_peer_ids: Query<(Entity, &MatchBoxPeerId)>,
+    mut transfers: ResMut<SaveTransfers>,
+    settings: Res<LobbySettings>,
+    mut log: ResMut<LobbyEventLog>,
+    time: Res<Time>,
+    auto_resume: Option<Res<AutoResume>>,
+    sent: Res<SentLobbyState>,
+    mut received: ResMut<ReceivedLobbyStates>,
 ) {
-    let Ok((tab_id, ready, user_info, gamesave)) = my_info.get_single() else {
+    let Ok((tab_id, ready, choice, user_info, gamesave)) = my_info.get_single() else {
         return;
     };
+    let is_host = socket.id().is_some()
+        && lobby_host(player_peer_ids.iter().map(|(_, id)| id)) == socket.id();
     for (peer_id, peer_state) in socket.update_peers() {
         match peer_state {
             PeerState::Connected => {
                 info!("Peer joined: {:?}", peer_id);
-                commands.spawn((MatchBoxPeerId(peer_id), IsReady(false)));
+                let mut entity = commands.spawn(MatchBoxPeerId(peer_id));
+                log.record(&time, peer_id, LobbyEvent::Joined, &mut entity);
+                socket.send_p2p_message(
+                    &peer_id,
+                    P2PMessage::Version {
+                        build_hash: BUILD_HASH.to_string(),
+                        protocol: PROTOCOL_VERSION,
+                    },
+                );
                 socket.send_p2p_message(&peer_id, P2PMessage::TabId(tab_id.clone()));
-                socket.send_p2p_message(&peer_id, P2PMessage::Ready(ready.0));
+                let offer = gamesave.and_then(|gamesave| match gamesave.offer() {
+                    Ok(offer) => Some(offer),
+                    Err(e) => {
+                        warn!("Failed to offer game save: {e}");
+                        None
+                    }
+                });
                 socket.send_p2p_message(
                     &peer_id,
-                    P2PMessage::GameSave(gamesave.map(|g| g.to_owned())),
+                    offer.map_or(P2PMessage::NoGameSave, P2PMessage::SaveOffer),
                 );
-                socket.send_p2p_message(&peer_id, P2PMessage::UserInfo(user_info.clone()));
+                let state = LobbyState::new(ready, choice, user_info);
+                socket.send_p2p_message(&peer_id, P2PMessage::LobbyState(sent.full(&state)));
+                if is_host {
+                    socket.send_p2p_message(&peer_id, P2PMessage::Settings(settings.clone()));
+                }
+                if auto_resume.is_some() {
+                    socket.send_p2p_message(&peer_id, P2PMessage::JoinRunningGame);
+                }
             }
             PeerState::Disconnected => {
                 info!("Peer left: {:?}", peer_id);
+                transfers.0.remove(&peer_id);
+                received.forget(peer_id);
                 if let Some((entity, ..)) = player_peer_ids.iter().find(|(.., id)| id.0 == peer_id)
                 {
-                    commands.entity(entity).despawn();
+                    log.record(
+                        &time,
+                        peer_id,
+                        LobbyEvent::Left,
+                        &mut commands.entity(entity),
+                    );
                 }
             }
         }
@@ -279,36 +879,204 @@ fn update_peers(
 
 fn receive_from_peers(
     mut commands: Commands,
-    player_peer_ids: Query<(Entity, &MatchBoxPeerId)>,
+    player_peer_ids: Query<(Entity, &MatchBoxPeerId, Option<&SaveOffer>)>,
     mut messages: ResMut<Messages>,
+    mut transfers: ResMut<SaveTransfers>,
+    mut settings: ResMut<LobbySettings>,
+    mut log: ResMut<LobbyEventLog>,
+    time: Res<Time>,
+    mut quick_play: Option<ResMut<QuickPlay>>,
+    mut discard: EventWriter<DiscardSaves>,
+    (mut kicks, mut stop_waiting): (EventWriter<ApplyKick>, EventWriter<ApplyStopWaiting>),
+    mut peer_names: ResMut<PeerNames>,
+    dropped: Res<DroppedPeers>,
+    mut save_requests: EventWriter<SaveRequested>,
+    (mut lobby_states, mut state_requests): (
+        ResMut<ReceivedLobbyStates>,
+        EventWriter<LobbyStateRequested>,
+    ),
 ) {
+    let host = lobby_host(player_peer_ids.iter().map(|(_, id, _)| id));
     messages.0.retain(|(peer_id, packet)| {
-        if let Some(entity) = player_peer_ids
-            .iter()
-            .find(|(_, id)| id.0 == *peer_id)
-            .map(|(entity, ..)| entity)
+        if dropped.contains(peer_id) {
+            false
+        } else if let Some((entity, _, offer)) =
+            player_peer_ids.iter().find(|(_, id, _)| id.0 == *peer_id)
         {
-            if let Ok(p2p_message) = bincode::deserialize::<P2PMessage>(packet) {
-                let mut entity_commands = commands.entity(entity);
+            if let Ok(p2p_message) = P2PMessage::decode(packet) {
                 trace!("Received P2PMessage: {:?}", p2p_message);
-                match p2p_message {
-                    P2PMessage::TabId(tab_id) => {
-                        entity_commands.insert(tab_id);
+                let event = match p2p_message {
+                    P2PMessage::Version {
+                        build_hash,
+                        protocol,
+                    } => {
+                        let version = PeerVersion {
+                            build_hash,
+                            protocol,
+                        };
+                        if !version.matches_local() {
+                            warn!(
+                                "Peer {peer_id:?} runs build {} (protocol {}), we run {BUILD_HASH} \
+                                 (protocol {PROTOCOL_VERSION})",
+                                version.build_hash, version.protocol
+                            );
+                        }
+                        Some(LobbyEvent::Version(version))
+                    }
+                    P2PMessage::TabId(tab_id) => Some(LobbyEvent::TabId(tab_id)),
+                    P2PMessage::SaveChunk {
+                        index,
+                        total,
+                        bytes,
+                    } => {
+                        if index == 0 {
+                            transfers.0.insert(
+                                *peer_id,
+                                SaveTransfer::new(total, time.elapsed_seconds_f64()),
+                            );
+                        }
+                        match transfers.0.get_mut(peer_id) {
+                            Some(transfer) if transfer.received() == index => {
+                                transfer.push(bytes, time.elapsed_seconds_f64());
+                                if transfer.received() == transfer.total {
+                                    let game_save = transfer.decode(offer);
+                                    transfers.0.remove(peer_id);
+                                    game_save.map(LobbyEvent::GameSave)
+                                } else {
+                                    None
+                                }
+                            }
+                            _ => {
+                                warn!("Received game save chunk {index} out of order");
+                                transfers.0.remove(peer_id);
+                                None
+                            }
+                        }
+                    }
+                    P2PMessage::Settings(host_settings) => {
+                        if host == Some(*peer_id) {
+                            settings.set_if_neq(host_settings.sanitized());
+                        } else {
+                            warn!("Ignoring settings from {peer_id:?}, who isn't the host");
+                        }
+                        None
+                    }
+                    P2PMessage::QuickPlay(assignment) => {
+                        match quick_play.as_mut() {
+                            Some(quick_play) if host == Some(*peer_id) => quick_play.assign(
+                                assignment,
+                                Vec::new(),
+                                time.elapsed_seconds_f64(),
+                            ),
+                            _ => warn!("Ignoring quick play assignment from {peer_id:?}"),
+                        }
+                        None
+                    }
+                    P2PMessage::JoinRunningGame => {
+                        info!("{peer_id:?} is in a running game, joining it");
+                        commands.init_resource::<AutoResume>();
+                        None
+                    }
+                    P2PMessage::DiscardSaves => {
+                        if host == Some(*peer_id) {
+                            info!("The host discarded everyone's saves");
+                            discard.send(DiscardSaves);
+                            transfers.0.remove(peer_id);
+                            Some(LobbyEvent::SaveOffer(None))
+                        } else {
+                            warn!("Ignoring save discard from {peer_id:?}, who isn't the host");
+                            None
+                        }
+                    }
+                    P2PMessage::StartAt(start_at) => {
+                        match start_at {
+                            _ if host != Some(*peer_id) => {
+                                warn!("Ignoring start from {peer_id:?}, who isn't the host")
+                            }
+                            Some(at) => commands.insert_resource(StartCountdown { at }),
+                            None => {
+                                info!("The host cancelled the start");
+                                commands.remove_resource::<StartCountdown>();
+                            }
+                        }
+                        None
                     }
-                    P2PMessage::GameSave(Some(game_save)) => {
-                        entity_commands.insert(game_save);
+                    P2PMessage::Kick(tab_id) => {
+                        if host == Some(*peer_id) {
+                            info!("The host kicked {tab_id:?}");
+                            kicks.send(ApplyKick(tab_id));
+                        } else {
+                            warn!("Ignoring kick from {peer_id:?}, who isn't the host");
+                        }
+                        None
+                    }
+                    P2PMessage::StopWaiting(waiting) => {
+                        if host == Some(*peer_id) {
+                            info!("The host stopped waiting for {waiting:?}");
+                            stop_waiting.send(ApplyStopWaiting(waiting));
+                        } else {
+                            warn!("Ignoring stop waiting from {peer_id:?}, who isn't the host");
+                        }
+                        None
                     }
-                    P2PMessage::GameSave(None) => {
-                        entity_commands.remove::<GameSaveData>();
+                    P2PMessage::StartDecision(decision) => {
+                        if host == Some(*peer_id) {
+                            info!("The host decided how to start: {decision:?}");
+                            commands.insert_resource(decision);
+                        } else {
+                            warn!("Ignoring start decision from {peer_id:?}, who isn't the host");
+                        }
+                        None
                     }
-                    P2PMessage::Ready(ready) => {
-                        entity_commands.insert(IsReady(ready));
+                    P2PMessage::Roster(roster) => {
+                        peer_names.learn_roster(roster);
+                        None
                     }
-                    P2PMessage::UserInfo(user_info) => {
-                        entity_commands.insert(user_info);
+                    P2PMessage::Leaving => {
+                        info!("{peer_id:?} left the game");
+                        None
+                    }
+                    // Only sent during a game, see `pause` and `emotes`
+                    P2PMessage::PauseRequest
+                    | P2PMessage::PauseAck
+                    | P2PMessage::PauseDecline
+                    | P2PMessage::ResumeConfirm
+                    | P2PMessage::Emote(_) => None,
+                    // Taken out before this, see `connection_quality`
+                    P2PMessage::Ping(_) | P2PMessage::Pong { .. } => None,
+                    P2PMessage::SaveOffer(offer) => Some(LobbyEvent::SaveOffer(Some(offer))),
+                    P2PMessage::NoGameSave => {
+                        transfers.0.remove(peer_id);
+                        Some(LobbyEvent::SaveOffer(None))
+                    }
+                    P2PMessage::SaveChosen(hash) => {
+                        if host == Some(*peer_id) {
+                            commands.insert_resource(ChosenSave(hash));
+                        } else {
+                            warn!("Ignoring save choice from {peer_id:?}, who isn't the host");
+                        }
+                        None
                     }
+                    P2PMessage::RequestSave(hash) => {
+                        save_requests.send(SaveRequested(*peer_id, hash));
+                        None
+                    }
+                    P2PMessage::LobbyState(patch) => {
+                        for event in lobby_states.apply(*peer_id, patch) {
+                            log.record(&time, *peer_id, event, &mut commands.entity(entity));
+                        }
+                        None
+                    }
+                    P2PMessage::RequestLobbyState => {
+                        state_requests.send(LobbyStateRequested(*peer_id));
+                        None
+                    }
+                };
+                if let Some(event) = event {
+                    log.record(&time, *peer_id, event, &mut commands.entity(entity));
                 }
             } else {
+                // Most likely a peer running a different build
                 warn!("Failed to deserialize P2PMessage");
             }
             false
@@ -318,6 +1086,109 @@ fn receive_from_peers(
     });
 }
 
+/// Throws away the local save, both in memory and in storage. Sent by the
+/// host's discard button and by the host's [`P2PMessage::DiscardSaves`].
+pub struct DiscardSaves;
+
+fn discard_saves(
+    mut commands: Commands,
+    mut events: EventReader<DiscardSaves>,
+    mut socket: ResMut<MatchboxSocket<MultipleChannels>>,
+    local_player: Query<(Entity, &TabId), With<IsLocal>>,
+    peers: Query<&MatchBoxPeerId>,
+    room: Res<Room>,
+    stores: Res<KeyValueStores>,
+) {
+    if events.iter().count() == 0 {
+        return;
+    }
+    let Ok((entity, tab_id)) = local_player.get_single() else {
+        return;
+    };
+    info!("Discarding game save");
+    commands.entity(entity).remove::<GameSaveData>();
+    commands.remove_resource::<GameSaveData>();
+    save_storage::remove(stores.area(StorageArea::Local), &room.0, &tab_id.0);
+    // The host's message makes the others discard theirs, everyone else
+    // just tells the room they have no save left
+    let message = if lobby_host(peers.iter()) == socket.id() {
+        P2PMessage::DiscardSaves
+    } else {
+        P2PMessage::NoGameSave
+    };
+    for peer_id in socket.connected_peers().collect::<Vec<_>>().iter() {
+        socket.send_p2p_message(peer_id, message.clone());
+    }
+}
+
+/// Duplicating a browser tab copies its session storage, tab id included, so
+/// two peers can claim the same player in saves. Neither tab can tell which
+/// is the copy, so the one with the larger peer id gives the id up: it takes
+/// a fresh one with its peer id as suffix, drops the save that belongs to the
+/// other tab and tells everyone.
+fn resolve_tab_id_collisions(
+    mut commands: Commands,
+    mut socket: ResMut<MatchboxSocket<MultipleChannels>>,
+    mut local_player: Query<(Entity, &MatchBoxPeerId, &mut TabId), With<IsLocal>>,
+    peers: Query<(&MatchBoxPeerId, &TabId), Without<IsLocal>>,
+    stores: Res<KeyValueStores>,
+) {
+    let Ok((entity, local_peer_id, mut tab_id)) = local_player.get_single_mut() else {
+        return;
+    };
+    let Some((original, _)) = peers
+        .iter()
+        .find(|(peer_id, peer_tab_id)| peer_tab_id.0 == tab_id.0 && peer_id.0 < local_peer_id.0)
+    else {
+        return;
+    };
+    let suffix = local_peer_id.0 .0.simple().to_string();
+    let fresh = format!("{}-{}", tab_id.0, &suffix[..8]);
+    warn!(
+        "{:?} has our tab id {}, this tab was probably duplicated. Using {fresh} instead",
+        original.0, tab_id.0
+    );
+    if let Err(e) = stores.area(StorageArea::Session).set(TAB_ID_KEY, &fresh) {
+        warn!("Failed to store {TAB_ID_KEY}: {e}");
+    }
+    tab_id.0 = fresh;
+    commands.entity(entity).remove::<GameSaveData>();
+    commands.remove_resource::<GameSaveData>();
+    for peer_id in socket.connected_peers().collect::<Vec<_>>().iter() {
+        socket.send_p2p_message(peer_id, P2PMessage::TabId(tab_id.clone()));
+        socket.send_p2p_message(peer_id, P2PMessage::NoGameSave);
+    }
+}
+
+/// Present while a running game restarts because a peer joined or left it:
+/// the saved game gets resumed by whoever is in the room as soon as everyone
+/// has it
+#[derive(Resource, Default)]
+pub struct AutoResume {
+    /// Whether we readied up already, after which players may change their
+    /// mind like in any other lobby
+    readied: bool,
+}
+
+/// Readies up to resume the running game, without waiting for anyone to click
+fn ready_to_resume(
+    mut auto_resume: ResMut<AutoResume>,
+    mut local_player: Query<(&mut IsReady, &mut StartChoice), With<IsLocal>>,
+) {
+    if auto_resume.readied {
+        return;
+    }
+    if let Ok((mut ready, mut choice)) = local_player.get_single_mut() {
+        ready.0 = true;
+        *choice = StartChoice::ResumeSave;
+        auto_resume.readied = true;
+    }
+}
+
+fn finish_auto_resume(mut commands: Commands) {
+    commands.remove_resource::<AutoResume>();
+}
+
 #[derive(Resource)]
 struct WaitingOn(Vec<PeerId>);
 
@@ -325,10 +1196,12 @@ fn check_waiting_on(
     mut commands: Commands,
     socket: Res<MatchboxSocket<MultipleChannels>>,
     players_we_have_heard_from: Query<&MatchBoxPeerId, With<TabId>>,
+    dropped: Res<DroppedPeers>,
 ) {
     if let Some(our_id) = socket.id() {
         let connected_ids = socket
             .connected_peers()
+            .filter(|id| !dropped.contains(id))
             .chain(std::iter::once(our_id))
             .collect::<Vec<_>>();
         let players_we_have_heard_from = players_we_have_heard_from
@@ -345,90 +1218,780 @@ fn check_waiting_on(
     }
 }
 
+/// Seconds the host waits on a save that stopped arriving, or on a peer that
+/// never introduced itself, before offering to start without them. Launch
+/// links can change it, see [`LaunchConfig::save_wait_secs`].
+const DEFAULT_SAVE_WAIT_SECS: u32 = 20;
+
+/// Peers the game start waits on, by when they last made progress: their
+/// save's latest chunk, or since we've been waiting for them to say who they
+/// are
+#[derive(Resource, Default)]
+struct StalledPeers(HashMap<PeerId, f64>);
+
+impl StalledPeers {
+    /// The peers stuck for longer than `secs`
+    fn overdue(&self, now: f64, secs: u32) -> Vec<PeerId> {
+        let mut overdue = self
+            .0
+            .iter()
+            .filter(|(_, since)| now - **since > secs as f64)
+            .map(|(peer_id, _)| *peer_id)
+            .collect::<Vec<_>>();
+        overdue.sort();
+        overdue
+    }
+}
+
+fn track_stalled_peers(
+    time: Res<Time>,
+    transfers: Res<SaveTransfers>,
+    waiting_on: Option<Res<WaitingOn>>,
+    mut stalled: ResMut<StalledPeers>,
+) {
+    let now = time.elapsed_seconds_f64();
+    let pending = transfers
+        .0
+        .iter()
+        .map(|(peer_id, transfer)| (*peer_id, transfer.last_chunk_secs))
+        .chain(
+            waiting_on
+                .iter()
+                .flat_map(|waiting_on| waiting_on.0.iter().map(|peer_id| (*peer_id, 0.))),
+        )
+        .collect::<HashMap<_, _>>();
+    stalled.0.retain(|peer_id, _| pending.contains_key(peer_id));
+    for (peer_id, last_progress) in pending {
+        let since = stalled.0.entry(peer_id).or_insert(now);
+        *since = since.max(last_progress);
+    }
+}
+
+/// How to start once the host gave up on missing saves. Overrides everyone's
+/// [`StartChoice`].
+#[derive(Resource, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StartDecision {
+    NewGame,
+    /// Resume the host's own save, even if a missing peer had a newer one
+    HostSave,
+}
+
+/// Lets the host start without the peers holding up the game, dropping them
+/// like [`StopWaiting`] does and sending everyone a [`StartDecision`]
+fn stalled_start_ui(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut socket: ResMut<MatchboxSocket<MultipleChannels>>,
+    time: Res<Time>,
+    launch: Res<LaunchConfig>,
+    stalled: Res<StalledPeers>,
+    decision: Option<Res<StartDecision>>,
+    peers: Query<(&MatchBoxPeerId, Option<&UserInfo>)>,
+    local_save: Query<&SaveOffer, With<IsLocal>>,
+    peer_names: Res<PeerNames>,
+    word_filter: Res<WordFilter>,
+    mut stop_waiting: EventWriter<StopWaiting>,
+) {
+    let is_host =
+        socket.id().is_some() && lobby_host(peers.iter().map(|(id, _)| id)) == socket.id();
+    if !is_host || decision.is_some() {
+        return;
+    }
+    let wait_secs = launch.save_wait_secs.unwrap_or(DEFAULT_SAVE_WAIT_SECS);
+    let overdue = stalled.overdue(time.elapsed_seconds_f64(), wait_secs);
+    if overdue.is_empty() {
+        return;
+    }
+    let names = overdue
+        .iter()
+        .map(|peer_id| {
+            // Peers that never introduced themselves only have a placeholder
+            peer_names
+                .reported(*peer_id)
+                .map(|(_, name)| word_filter.censor(name).into_owned())
+                .or_else(|| {
+                    peers
+                        .iter()
+                        .find(|(id, _)| id.0 == *peer_id)
+                        .and_then(|(_, info)| info)
+                        .map(|info| display_name(&word_filter, info).into_owned())
+                })
+                .unwrap_or_else(|| format!("{}...", short_peer_id(*peer_id)))
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    let own_save = local_save
+        .get_single()
+        .ok()
+        .filter(|save| save.config.incompatibility().is_none());
+    let mut decided = None;
+    Window::new("Missing saves")
+        .anchor(Align2::CENTER_BOTTOM, [0., -10.])
+        .collapsible(false)
+        .resizable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.label(format!(
+                "Still waiting on {names} after {wait_secs} s. They may have the newest save."
+            ));
+            ui.horizontal(|ui| {
+                if ui.button("Start fresh").clicked() {
+                    decided = Some(StartDecision::NewGame);
+                }
+                if let Some(save) = own_save {
+                    if ui
+                        .button("Start from my save")
+                        .on_hover_text(format!("Saved {}", save.timestamp.format("%Y-%m-%d %H:%M")))
+                        .clicked()
+                    {
+                        decided = Some(StartDecision::HostSave);
+                    }
+                }
+            });
+        });
+    let Some(decided) = decided else {
+        return;
+    };
+    info!("Starting without {overdue:?}: {decided:?}");
+    for peer_id in overdue {
+        stop_waiting.send(StopWaiting(peer_id));
+    }
+    let mut messages = vec![P2PMessage::StartDecision(decided)];
+    if let (StartDecision::HostSave, Some(save)) = (decided, own_save) {
+        commands.insert_resource(ChosenSave(Some(save.hash)));
+        messages.insert(0, P2PMessage::SaveChosen(Some(save.hash)));
+    }
+    for peer_id in socket.connected_peers().collect::<Vec<_>>().iter() {
+        for message in messages.iter() {
+            socket.send_p2p_message(peer_id, message.clone());
+        }
+    }
+    commands.insert_resource(decided);
+}
+
+/// The save choice and start decision only hold for the lobby they were made in
+fn forget_start_agreement(mut commands: Commands) {
+    commands.remove_resource::<ChosenSave>();
+    commands.remove_resource::<StartDecision>();
+}
+
+/// Whether every player picked the same [`StartChoice`]. Only matters when
+/// there's a save to resume.
+fn start_choices_agree(choices: &Query<&StartChoice>, chosen_save: Option<&SaveOffer>) -> bool {
+    chosen_save.is_none()
+        || choices
+            .iter()
+            .all(|choice| Some(choice) == choices.iter().next())
+}
+
+/// Whether the save everyone agreed to resume was made with rules this build
+/// can't play
+fn resume_blocked(choices: &Query<&StartChoice>, chosen_save: Option<&SaveOffer>) -> bool {
+    choices.iter().next() == Some(&StartChoice::ResumeSave)
+        && chosen_save.map_or(false, |save| save.config.incompatibility().is_some())
+}
+
+/// Starts the countdown once everyone is ready, and the game when it ends.
+/// Only the host starts and cancels countdowns, the others follow.
 fn trigger_game_start(
+    mut commands: Commands,
+    mut socket: ResMut<MatchboxSocket<MultipleChannels>>,
+    peers: Query<&MatchBoxPeerId>,
+    countdown: Option<Res<StartCountdown>>,
     ready_statuses: Query<&IsReady>,
+    choices: Query<&StartChoice>,
+    chosen: Option<Res<ChosenSave>>,
+    saves: Query<(&SaveOffer, Option<&GameSaveData>)>,
+    peer_versions: Query<Option<&PeerVersion>, Without<IsLocal>>,
     local_player: Query<With<IsLocal>>,
     waiting_on: Option<Res<WaitingOn>>,
+    transfers: Res<SaveTransfers>,
+    decision: Option<Res<StartDecision>>,
     mut next_state: ResMut<NextState<GameState>>,
 ) {
-    if waiting_on.is_some()
-        && waiting_on.unwrap().0.is_empty()
+    let chosen_save = chosen_offer(chosen.as_deref(), saves.iter().map(|(offer, _)| offer));
+    // Starting fresh needs no save, everything else needs the chosen one's bytes
+    let save_ready = decision.as_deref() == Some(&StartDecision::NewGame)
+        || match chosen.as_deref() {
+            None => false,
+            Some(ChosenSave(None)) => true,
+            Some(ChosenSave(Some(hash))) => saves
+                .iter()
+                .any(|(offer, save)| offer.hash == *hash && save.is_some()),
+        };
+    let all_ready = waiting_on.map_or(false, |waiting_on| waiting_on.0.is_empty())
+        && transfers.0.is_empty()
+        && save_ready
         && !local_player.is_empty()
         && ready_statuses.iter().all(|ready| ready.0)
-    {
-        info!("All peers are ready, starting game");
+        && (decision.is_some()
+            || (start_choices_agree(&choices, chosen_save)
+                && !resume_blocked(&choices, chosen_save)))
+        && peer_versions
+            .iter()
+            .all(|version| version.map_or(false, PeerVersion::matches_local));
+    let is_host = socket.id().is_some() && lobby_host(peers.iter()) == socket.id();
+    let start_at = match (&countdown, all_ready, is_host) {
+        (None, true, true) => {
+            let countdown = StartCountdown::from_now();
+            info!("All peers are ready, starting at {}", countdown.at);
+            commands.insert_resource(countdown);
+            Some(Some(countdown.at))
+        }
+        (Some(_), false, true) => {
+            info!("Not everyone is ready anymore, cancelling the start");
+            commands.remove_resource::<StartCountdown>();
+            Some(None)
+        }
+        _ => None,
+    };
+    if let Some(start_at) = start_at {
+        for peer_id in socket.connected_peers().collect::<Vec<_>>().iter() {
+            socket.send_p2p_message(peer_id, P2PMessage::StartAt(start_at));
+        }
+    }
+    // Others may still be waiting on messages that arrived at the host, in
+    // which case they start as soon as those are in
+    if all_ready && countdown.map_or(false, |countdown| countdown.is_over()) {
+        info!("Countdown over, starting game");
         next_state.set(GameState::InGame);
     }
 }
 
-fn find_best_game_save(
+/// How the next game should start, decided unanimously in the lobby
+#[derive(Resource, Debug)]
+pub struct GameStartConfig {
+    pub resume_save: bool,
+}
+
+/// The save everyone resumes, as picked by the lobby host. Absent until the
+/// host told us.
+#[derive(Resource, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ChosenSave(pub Option<u64>);
+
+fn chosen_offer<'a>(
+    chosen: Option<&ChosenSave>,
+    mut offers: impl Iterator<Item = &'a SaveOffer>,
+) -> Option<&'a SaveOffer> {
+    let hash = chosen?.0?;
+    offers.find(|offer| offer.hash == hash)
+}
+
+/// Lets the host pick the save to resume out of everyone's offers: the most
+/// recent one, ties broken by hash. Timestamps come from each peer's own
+/// clock, so they're shifted by its estimated offset from the host's first.
+/// Only the host's pick counts, so peers never have to agree on the order
+/// themselves. Newcomers are told too.
+fn choose_save(
     mut commands: Commands,
-    game_saves: Query<(&MatchBoxPeerId, Option<&GameSaveData>)>,
-    local_player: Query<Entity, With<IsLocal>>,
+    mut socket: ResMut<MatchboxSocket<MultipleChannels>>,
+    peers: Query<&MatchBoxPeerId>,
+    newcomers: Query<(), (Added<MatchBoxPeerId>, Without<IsLocal>)>,
+    offers: Query<(&SaveOffer, Option<&MatchBoxPeerId>)>,
+    pings: Res<PeerPings>,
+    chosen: Option<Res<ChosenSave>>,
+    decision: Option<Res<StartDecision>>,
 ) {
-    // This sorting will resolve the same way on all peers
-    if let Some(best_save) = game_saves
+    // A decision to start without some peers settles the save for good
+    if socket.id().is_none() || lobby_host(peers.iter()) != socket.id() || decision.is_some() {
+        return;
+    }
+    let best = offers
         .iter()
-        .filter_map(|(id, gamesave)| gamesave.map(|gamesave| (id.0, gamesave)))
-        .reduce(|acc, x| {
-            // Prefer the most recent save, then the one from the peer with the highest peer id
-            if acc.1.timestamp > x.1.timestamp {
-                acc
-            } else if acc.1.timestamp < x.1.timestamp {
-                x
-            } else if acc.0 > x.0 {
-                acc
-            } else {
-                x
-            }
+        .max_by_key(|(offer, peer_id)| {
+            let offset_ms = peer_id
+                .and_then(|peer_id| pings.clock_offset_ms(peer_id.0))
+                .unwrap_or(0);
+            // Offsets are kept within a day, but the timestamp is the
+            // peer's own and could be anything
+            let timestamp = offer
+                .timestamp
+                .checked_sub_signed(Duration::milliseconds(offset_ms))
+                .unwrap_or(offer.timestamp);
+            (timestamp, offer.hash)
         })
-        .map(|(_, gamesave)| gamesave)
-    {
-        commands
-            .entity(local_player.single())
-            .insert(best_save.clone());
+        .map(|(offer, _)| offer.hash);
+    let changed = chosen.map_or(true, |chosen| chosen.0 != best);
+    if !changed && newcomers.is_empty() {
+        return;
+    }
+    if changed {
+        info!("Chose save {best:x?}");
+        commands.insert_resource(ChosenSave(best));
+    }
+    for peer_id in socket.connected_peers().collect::<Vec<_>>().iter() {
+        socket.send_p2p_message(peer_id, P2PMessage::SaveChosen(best));
     }
 }
 
-fn launch_session(
+/// Keeps the local player's [`SaveOffer`] in line with its save
+fn offer_local_save(
     mut commands: Commands,
+    local_player: Query<(Entity, Option<Ref<GameSaveData>>, Option<&SaveOffer>), With<IsLocal>>,
+) {
+    let Ok((entity, save, offer)) = local_player.get_single() else {
+        return;
+    };
+    match (save, offer) {
+        (Some(save), offer) if save.is_changed() || offer.is_none() => match save.offer() {
+            Ok(offer) => {
+                commands.entity(entity).insert(offer);
+            }
+            // Only warned about once, the save is retried every frame
+            Err(e) if save.is_changed() => {
+                warn!("Failed to offer game save: {e}");
+                commands.entity(entity).remove::<SaveOffer>();
+            }
+            Err(_) => {}
+        },
+        (None, Some(_)) => {
+            commands.entity(entity).remove::<SaveOffer>();
+        }
+        _ => {}
+    }
+}
+
+/// Seconds before asking the next peer that offered the chosen save for it
+const SAVE_REQUEST_RETRY_SECS: f64 = 10.;
+
+/// Asks for the chosen save unless we have it already, going through the
+/// peers that offered it in turn
+fn fetch_chosen_save(
     mut socket: ResMut<MatchboxSocket<MultipleChannels>>,
-    all_players: Query<(Entity, &MatchBoxPeerId)>,
-    local_player: Query<&MatchBoxPeerId, With<IsLocal>>,
+    time: Res<Time>,
+    chosen: Option<Res<ChosenSave>>,
+    holders: Query<(&MatchBoxPeerId, &SaveOffer, Option<&GameSaveData>)>,
+    transfers: Res<SaveTransfers>,
+    mut last_request: Local<Option<(u64, f64, usize)>>,
 ) {
-    let mut session_builder = ggrs::SessionBuilder::<GgrsConfig>::new()
-        .with_num_players(all_players.iter().len())
-        .with_input_delay(0);
-    let local_peer_id = local_player.single();
-    let mut socket_players = all_players
+    let Some(hash) = chosen.and_then(|chosen| chosen.0) else {
+        return;
+    };
+    let mut offered_by = holders
         .iter()
-        .map(|(entity, peer_id)| {
-            (
-                if peer_id.0 == local_peer_id.0 {
-                    PlayerType::Local
-                } else {
-                    PlayerType::Remote(peer_id.0)
-                },
-                entity,
-                peer_id,
-            )
-        })
+        .filter(|(_, offer, _)| offer.hash == hash)
         .collect::<Vec<_>>();
-    socket_players.sort_by_key(|(.., peer_id)| &peer_id.0);
-    for (i, (player, entity, ..)) in socket_players.into_iter().enumerate() {
-        if let PlayerType::Local = player {
-            commands.insert_resource(LocalPlayerHandle(i));
+    if offered_by.is_empty()
+        || offered_by.iter().any(|(.., save)| save.is_some())
+        || !transfers.is_empty()
+    {
+        return;
+    }
+    offered_by.sort_by_key(|(peer_id, ..)| peer_id.0);
+    let now = time.elapsed_seconds_f64();
+    let attempt = match *last_request {
+        Some((requested, at, _)) if requested == hash && now - at < SAVE_REQUEST_RETRY_SECS => {
+            return;
         }
+        Some((requested, _, attempt)) if requested == hash => attempt + 1,
+        _ => 0,
+    };
+    let (peer_id, ..) = offered_by[attempt % offered_by.len()];
+    info!("Asking {:?} for save {hash:016x}", peer_id.0);
+    socket.send_p2p_message(&peer_id.0, P2PMessage::RequestSave(hash));
+    *last_request = Some((hash, now, attempt));
+}
+
+/// A peer asking for the save with this hash
+struct SaveRequested(PeerId, u64);
+
+fn send_requested_saves(
+    mut events: EventReader<SaveRequested>,
+    mut socket: ResMut<MatchboxSocket<MultipleChannels>>,
+    local_save: Query<(&GameSaveData, &SaveOffer), With<IsLocal>>,
+) {
+    for SaveRequested(peer_id, hash) in events.iter() {
+        match local_save.get_single() {
+            Ok((save, offer)) if offer.hash == *hash => socket.send_game_save(peer_id, save),
+            _ => warn!("{peer_id:?} asked for save {hash:016x}, which we don't have"),
+        }
+    }
+}
+
+/// A peer asking for all of the local lobby state
+struct LobbyStateRequested(PeerId);
+
+fn send_requested_lobby_state(
+    mut events: EventReader<LobbyStateRequested>,
+    mut socket: ResMut<MatchboxSocket<MultipleChannels>>,
+    my_state: Query<(&IsReady, &StartChoice, &UserInfo), With<IsLocal>>,
+    sent: Res<SentLobbyState>,
+) {
+    let Ok((ready, choice, info)) = my_state.get_single() else {
+        return;
+    };
+    let state = LobbyState::new(ready, choice, info);
+    for LobbyStateRequested(peer_id) in events.iter() {
+        socket.send_p2p_message(peer_id, P2PMessage::LobbyState(sent.full(&state)));
+    }
+}
+
+fn request_missed_lobby_states(
+    mut received: ResMut<ReceivedLobbyStates>,
+    mut socket: ResMut<MatchboxSocket<MultipleChannels>>,
+) {
+    for peer_id in std::mem::take(&mut received.missed) {
+        socket.send_p2p_message(&peer_id, P2PMessage::RequestLobbyState);
+    }
+}
+
+/// Sets the next game up from the chosen save, if it gets resumed
+fn take_chosen_save(
+    mut commands: Commands,
+    game_saves: Query<(&MatchBoxPeerId, Option<&SaveOffer>, Option<&GameSaveData>)>,
+    chosen: Option<Res<ChosenSave>>,
+    local_player: Query<(Entity, &StartChoice), With<IsLocal>>,
+    settings: Res<LobbySettings>,
+    decision: Option<Res<StartDecision>>,
+) {
+    let (local_entity, choice) = local_player.single();
+    let chosen_save = chosen.and_then(|chosen| chosen.0).and_then(|hash| {
+        game_saves
+            .iter()
+            .filter(|(_, offer, _)| offer.map_or(false, |offer| offer.hash == hash))
+            .find_map(|(.., save)| save)
+    });
+    // The host chose its own save before deciding to resume it
+    let (resume_save, chosen_save) = match decision.as_deref() {
+        None => {
+            let resume_save = *choice == StartChoice::ResumeSave;
+            (resume_save, chosen_save.filter(|_| resume_save))
+        }
+        Some(StartDecision::NewGame) => (false, None),
+        Some(StartDecision::HostSave) => (chosen_save.is_some(), chosen_save),
+    };
+    info!("Starting game, resume save: {resume_save}");
+    commands.insert_resource(GameStartConfig { resume_save });
+    if let Some(chosen_save) = chosen_save {
+        commands.insert_resource(chosen_save.config.clone());
+        commands.entity(local_entity).insert(chosen_save.clone());
+    } else {
+        commands.insert_resource(MatchConfig::new(
+            game_saves.iter().map(|(id, ..)| id.0),
+            &settings.mode,
+        ));
+    }
+}
+
+/// Frames between checksum comparisons with peers
+const DESYNC_CHECK_INTERVAL: u32 = 10;
+
+/// Present while a single-player practice session is being set up or played
+#[derive(Resource)]
+pub struct PracticeMode;
+
+/// Idle players added to practice games, for testing against something that
+/// can be shot
+#[derive(Resource, Default)]
+pub struct PracticeBots(pub usize);
+
+/// Frames GGRS rolls back and resimulates every frame in practice mode to check determinism
+const PRACTICE_CHECK_DISTANCE: usize = 2;
+
+/// Practice session with a seat for every player of the sandboxed save. The
+/// others get their saved state through their tab ids when the snapshot is
+/// loaded, and are driven by bots if asked to.
+fn launch_sandbox(
+    commands: &mut Commands,
+    local_player: &Query<(Entity, &MatchBoxPeerId), With<IsLocal>>,
+    sandbox: &Sandbox,
+) -> Result<(), String> {
+    let (entity, _) = local_player
+        .get_single()
+        .map_err(|_| "no local player".to_string())?;
+    let mut session_builder = ggrs::SessionBuilder::<GgrsConfig>::new()
+        .with_num_players(sandbox.tab_ids.len())
+        .with_check_distance(PRACTICE_CHECK_DISTANCE);
+    for handle in 0..sandbox.tab_ids.len() {
+        session_builder = session_builder
+            .add_player(PlayerType::Local, handle)
+            .map_err(|e| format!("failed to add player {handle}: {e}"))?;
+    }
+    let ggrs_session = session_builder
+        .start_synctest_session()
+        .map_err(|e| e.to_string())?;
+    commands.insert_resource(sandbox.save.config.clone());
+    for (handle, tab_id) in sandbox.tab_ids.iter().enumerate() {
+        if *tab_id == sandbox.own_tab_id {
+            commands.insert_resource(LocalPlayerHandle(handle));
+            commands.entity(entity).insert(Player { handle });
+            continue;
+        }
+        let mut other = commands.spawn((
+            Player { handle },
+            TabId(tab_id.clone()),
+            UserInfo {
+                name: format!("Player {}", handle + 1),
+                ..default()
+            },
+        ));
+        if sandbox.bots {
+            other.insert(Bot);
+        }
+    }
+    commands.insert_resource(bevy_ggrs::Session::SyncTestSession(ggrs_session));
+    Ok(())
+}
+
+/// Offline session against idle bots, rolled back every frame to check
+/// determinism. In hot-seat games a second player sits at
+/// [`HOT_SEAT_HANDLE`], before the bots.
+fn launch_practice(
+    commands: &mut Commands,
+    local_player: &Query<(Entity, &MatchBoxPeerId), With<IsLocal>>,
+    bots: usize,
+    hot_seat: bool,
+    settings: &LobbySettings,
+) -> Result<(), String> {
+    let (entity, local_peer_id) = local_player
+        .get_single()
+        .map_err(|_| "no local player".to_string())?;
+    let humans = if hot_seat { 2 } else { 1 };
+    let mut session_builder = ggrs::SessionBuilder::<GgrsConfig>::new()
+        .with_num_players(humans + bots)
+        .with_check_distance(PRACTICE_CHECK_DISTANCE);
+    // Bots are local players too, the input system leaves them idle
+    for handle in 0..humans + bots {
+        session_builder = session_builder
+            .add_player(PlayerType::Local, handle)
+            .map_err(|e| format!("failed to add player {handle}: {e}"))?;
+    }
+    let ggrs_session = session_builder
+        .start_synctest_session()
+        .map_err(|e| e.to_string())?;
+    commands.insert_resource(LocalPlayerHandle(0));
+    commands.insert_resource(MatchConfig::new([local_peer_id.0], &settings.mode));
+    commands.entity(entity).insert(Player { handle: 0 });
+    if hot_seat {
+        commands.spawn((
+            Player {
+                handle: HOT_SEAT_HANDLE,
+            },
+            UserInfo {
+                name: "Player 2".to_string(),
+                color: [230, 120, 40],
+                avatar: 1,
+                cosmetics: default(),
+                class: default(),
+            },
+        ));
+    }
+    for handle in humans..humans + bots {
+        commands.spawn((
+            Player { handle },
+            UserInfo {
+                name: format!("Bot {}", handle - humans + 1),
+                color: [128, 128, 128],
+                avatar: 0,
+                cosmetics: default(),
+                class: default(),
+            },
+        ));
+    }
+    commands.insert_resource(bevy_ggrs::Session::SyncTestSession(ggrs_session));
+    Ok(())
+}
+
+/// Offline session between [`DEMO_BOT_COUNT`] bots for the attract mode,
+/// checked like practice. Nobody local plays, so there's no local handle.
+fn launch_attract(
+    commands: &mut Commands,
+    local_player: &Query<(Entity, &MatchBoxPeerId), With<IsLocal>>,
+    settings: &LobbySettings,
+) -> Result<(), String> {
+    let (_, local_peer_id) = local_player
+        .get_single()
+        .map_err(|_| "no local player".to_string())?;
+    let mut session_builder = ggrs::SessionBuilder::<GgrsConfig>::new()
+        .with_num_players(DEMO_BOT_COUNT)
+        .with_check_distance(PRACTICE_CHECK_DISTANCE);
+    for handle in 0..DEMO_BOT_COUNT {
         session_builder = session_builder
-            .add_player(player, i)
-            .expect("failed to add player");
-        commands.entity(entity).insert(Player { handle: i });
+            .add_player(PlayerType::Local, handle)
+            .map_err(|e| format!("failed to add player {handle}: {e}"))?;
+    }
+    let ggrs_session = session_builder
+        .start_synctest_session()
+        .map_err(|e| e.to_string())?;
+    commands.remove_resource::<LocalPlayerHandle>();
+    commands.insert_resource(MatchConfig::new([local_peer_id.0], &settings.mode));
+    for handle in 0..DEMO_BOT_COUNT {
+        commands.spawn((Player { handle }, Bot, demo_bot_info(handle)));
+    }
+    commands.insert_resource(bevy_ggrs::Session::SyncTestSession(ggrs_session));
+    Ok(())
+}
+
+/// Starts the GGRS session for the game about to begin. A session that can't
+/// be started sends everyone back to the lobby with an error instead of
+/// crashing the tab.
+fn launch_session(
+    mut commands: Commands,
+    mut socket: ResMut<MatchboxSocket<MultipleChannels>>,
+    all_players: Query<(Entity, &MatchBoxPeerId)>,
+    local_player: Query<(Entity, &MatchBoxPeerId), With<IsLocal>>,
+    practice: Option<Res<PracticeMode>>,
+    (hot_seat, attract): (Option<Res<HotSeat>>, Option<Res<AttractMode>>),
+    bots: Res<PracticeBots>,
+    #[cfg(debug_assertions)] net_conditions: Option<Res<SimulatedConditions>>,
+    settings: Res<LobbySettings>,
+    connection: Res<ConnectionSettings>,
+    mut timestep: ResMut<FixedTimestep>,
+    sandbox: Option<Res<Sandbox>>,
+    mut status: EventWriter<StatusEvent>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    timestep.set_rate(settings.tick_rate);
+    let launched = if let Some(sandbox) = sandbox {
+        launch_sandbox(&mut commands, &local_player, &sandbox)
+    } else if attract.is_some() {
+        launch_attract(&mut commands, &local_player, &settings)
+    } else if practice.is_some() {
+        launch_practice(
+            &mut commands,
+            &local_player,
+            bots.0,
+            hot_seat.is_some(),
+            &settings,
+        )
+    } else {
+        launch_p2p(
+            &mut commands,
+            &mut socket,
+            &all_players,
+            &local_player,
+            #[cfg(debug_assertions)]
+            net_conditions.as_deref(),
+            &settings,
+            &connection,
+        )
+    };
+    if let Err(e) = launched {
+        status.send(StatusEvent::error(format!("Couldn't start the game: {e}")));
+        next_state.set(GameState::Matchmaking);
     }
+}
+
+fn launch_p2p(
+    commands: &mut Commands,
+    socket: &mut MatchboxSocket<MultipleChannels>,
+    all_players: &Query<(Entity, &MatchBoxPeerId)>,
+    local_player: &Query<(Entity, &MatchBoxPeerId), With<IsLocal>>,
+    #[cfg(debug_assertions)] net_conditions: Option<&SimulatedConditions>,
+    settings: &LobbySettings,
+    connection: &ConnectionSettings,
+) -> Result<(), String> {
+    let (_, local_peer_id) = local_player
+        .get_single()
+        .map_err(|_| "no local player".to_string())?;
+    let (seated, spectators) = seat_peers(all_players.iter().map(|(_, id)| id.0));
+    let host = *seated.first().ok_or("no players")?;
+    let bots = settings.bots.min(MAX_PLAYERS - seated.len());
+    let num_players = seated.len() + bots;
+    let mut session_builder = ggrs::SessionBuilder::<GgrsConfig>::new()
+        .with_num_players(num_players)
+        .with_fps(settings.tick_rate as usize)
+        .map_err(|e| format!("invalid tick rate {}: {e}", settings.tick_rate))?
+        .with_input_delay(connection.input_delay)
+        .with_desync_detection_mode(DesyncDetection::On {
+            interval: DESYNC_CHECK_INTERVAL,
+        });
 
     // Move the channel out of the socket (required because GGRS takes ownership of it)
-    let channel = socket.take_channel(0).unwrap();
+    let channel = socket
+        .take_channel(0)
+        .map_err(|e| format!("no game channel: {e:?}"))?;
+    // Without the dev network simulator open this passes packets straight
+    // through
+    #[cfg(debug_assertions)]
+    let channel = SimulatedSocket::new(channel, &net_conditions.cloned().unwrap_or_default());
+
+    // Spectators need the players too, for the inputs they're sent to move
+    seat_players(
+        commands,
+        all_players
+            .iter()
+            .map(|(entity, peer_id)| (entity, peer_id.0)),
+        &seated,
+        num_players,
+    );
+    if spectators.contains(&local_peer_id.0) {
+        info!("Room is full, spectating through {host:?}");
+        let ggrs_session = session_builder.start_spectator_session(host, channel);
+        commands.remove_resource::<LocalPlayerHandle>();
+        commands.insert_resource(Spectating);
+        commands.insert_resource(bevy_ggrs::Session::SpectatorSession(ggrs_session));
+        return Ok(());
+    }
+
+    for (handle, peer_id) in seated.iter().enumerate() {
+        let player = if *peer_id == local_peer_id.0 {
+            commands.insert_resource(LocalPlayerHandle(handle));
+            PlayerType::Local
+        } else {
+            PlayerType::Remote(*peer_id)
+        };
+        session_builder = session_builder
+            .add_player(player, handle)
+            .map_err(|e| format!("failed to add player {handle}: {e}"))?;
+    }
+    // Bots take the seats after the people, and are played by the host
+    for handle in seated.len()..num_players {
+        let player = if local_peer_id.0 == host {
+            PlayerType::Local
+        } else {
+            PlayerType::Remote(host)
+        };
+        session_builder = session_builder
+            .add_player(player, handle)
+            .map_err(|e| format!("failed to add bot {handle}: {e}"))?;
+    }
+    // The host forwards everyone's inputs to the spectators
+    if local_peer_id.0 == host {
+        for (i, spectator) in spectators.into_iter().enumerate() {
+            session_builder = session_builder
+                .add_player(PlayerType::Spectator(spectator), num_players + i)
+                .map_err(|e| format!("failed to add spectator {spectator:?}: {e}"))?;
+        }
+    }
+
     let ggrs_session = session_builder
         .start_p2p_session(channel)
-        .expect("failed to start session");
+        .map_err(|e| e.to_string())?;
     commands.insert_resource(bevy_ggrs::Session::P2PSession(ggrs_session));
+    Ok(())
+}
+
+/// Present while the local peer watches a full room's game instead of playing
+#[derive(Resource)]
+pub struct Spectating;
+
+/// The start of a peer's id, for showing peers whose name isn't known yet
+fn short_peer_id(peer_id: PeerId) -> String {
+    peer_id.0.to_string().chars().take(8).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn local_properties_go_to_the_app_stores() {
+        let mut app = App::new();
+        app.insert_resource(KeyValueStores::in_memory())
+            .add_systems((
+                set_local_property::<PlayerStats>,
+                update_local_property::<PlayerStats>,
+            ));
+        let local = app.world.spawn((IsLocal, TabId("tab".to_string()))).id();
+        app.update();
+        app.world.get_mut::<PlayerStats>(local).unwrap().kills = 3;
+        app.update();
+
+        let stores = app.world.resource::<KeyValueStores>();
+        let stored = get_cookie_map::<PlayerStats>(
+            stores.area(StorageArea::Cookies),
+            std::any::type_name::<PlayerStats>(),
+        );
+        assert_eq!(stored["tab"].kills, 3);
+    }
 }
@@ -0,0 +1,158 @@
+use crate::components::{
+    GameSaveData, IsReady, PeerVersion, SaveOffer, StartChoice, TabId, UserInfo,
+};
+#[cfg(feature = "presentation")]
+use crate::placeholder::Placeholder;
+use bevy::{ecs::system::EntityCommands, prelude::*};
+use bevy_matchbox::prelude::PeerId;
+#[cfg(feature = "presentation")]
+use serde::Serialize;
+
+/// A change to a remote peer's lobby state. Peer entities are only changed by
+/// reducing these, in the order they were received, so the log explains how
+/// every peer got into its current state.
+#[derive(Clone, Debug)]
+pub enum LobbyEvent {
+    Joined,
+    #[cfg(feature = "presentation")]
+    Left,
+    Version(PeerVersion),
+    TabId(TabId),
+    Ready(bool),
+    StartChoice(StartChoice),
+    UserInfo(UserInfo),
+    /// The save the peer has, or `None` when it has none
+    SaveOffer(Option<SaveOffer>),
+    /// A fully received save, matching the peer's offer
+    #[cfg(feature = "presentation")]
+    GameSave(GameSaveData),
+}
+
+impl LobbyEvent {
+    /// Applies the event to the peer's entity. Depends on nothing but the
+    /// event, so replaying a log rebuilds the same state.
+    pub fn reduce(&self, entity: &mut EntityCommands) {
+        match self {
+            LobbyEvent::Joined => {
+                entity.insert((IsReady(false), StartChoice::default()));
+            }
+            #[cfg(feature = "presentation")]
+            LobbyEvent::Left => entity.despawn(),
+            LobbyEvent::Version(version) => {
+                entity.insert(version.clone());
+            }
+            LobbyEvent::TabId(tab_id) => {
+                entity.insert(tab_id.clone());
+            }
+            LobbyEvent::Ready(ready) => {
+                entity.insert(IsReady(*ready));
+            }
+            LobbyEvent::StartChoice(choice) => {
+                entity.insert(*choice);
+            }
+            LobbyEvent::UserInfo(user_info) => {
+                entity.insert(user_info.clone());
+                // Only the presentation hands out placeholders
+                #[cfg(feature = "presentation")]
+                entity.remove::<Placeholder>();
+            }
+            // Bytes received earlier belong to the previous offer
+            LobbyEvent::SaveOffer(Some(offer)) => {
+                entity.insert(offer.clone()).remove::<GameSaveData>();
+            }
+            LobbyEvent::SaveOffer(None) => {
+                entity.remove::<(SaveOffer, GameSaveData)>();
+            }
+            #[cfg(feature = "presentation")]
+            LobbyEvent::GameSave(game_save) => {
+                entity.insert(game_save.clone());
+            }
+        }
+    }
+
+    /// One-line description for exported logs. Saves are summarized since
+    /// their snapshots are large.
+    #[cfg(feature = "presentation")]
+    fn summary(&self) -> String {
+        match self {
+            LobbyEvent::SaveOffer(Some(offer)) => {
+                format!("SaveOffer({:016x} from {})", offer.hash, offer.timestamp)
+            }
+            LobbyEvent::GameSave(save) => {
+                format!(
+                    "GameSave(from {}, seed {})",
+                    save.timestamp, save.config.seed
+                )
+            }
+            event => format!("{event:?}"),
+        }
+    }
+}
+
+// Only read when the lobby exports the log
+#[cfg_attr(not(feature = "presentation"), allow(dead_code))]
+#[derive(Debug)]
+pub struct LoggedEvent {
+    /// Seconds since startup when the event was received
+    pub secs: f64,
+    pub peer: PeerId,
+    pub event: LobbyEvent,
+}
+
+/// Oldest events are dropped beyond this, keeping memory bounded in long
+/// lobby sessions
+const MAX_LOGGED_EVENTS: usize = 1000;
+
+/// Append-only log of every [`LobbyEvent`] received this session
+#[derive(Resource, Default)]
+pub struct LobbyEventLog(Vec<LoggedEvent>);
+
+#[cfg(feature = "presentation")]
+#[derive(Serialize)]
+struct ExportedEvent {
+    secs: f64,
+    peer: String,
+    event: String,
+}
+
+impl LobbyEventLog {
+    /// Appends `event` and applies it to the peer's entity
+    pub fn record(
+        &mut self,
+        time: &Time,
+        peer: PeerId,
+        event: LobbyEvent,
+        entity: &mut EntityCommands,
+    ) {
+        trace!("Lobby event from {peer:?}: {event:?}");
+        event.reduce(entity);
+        if self.0.len() >= MAX_LOGGED_EVENTS {
+            self.0.remove(0);
+        }
+        self.0.push(LoggedEvent {
+            secs: time.elapsed_seconds_f64(),
+            peer,
+            event,
+        });
+    }
+
+    #[cfg(feature = "presentation")]
+    pub fn event_count(&self) -> usize {
+        self.0.len()
+    }
+
+    /// The log as pretty RON, for attaching to bug reports
+    #[cfg(feature = "presentation")]
+    pub fn export(&self) -> String {
+        let events = self
+            .0
+            .iter()
+            .map(|logged| ExportedEvent {
+                secs: logged.secs,
+                peer: logged.peer.0.to_string(),
+                event: logged.event.summary(),
+            })
+            .collect::<Vec<_>>();
+        ron::ser::to_string_pretty(&events, Default::default()).unwrap()
+    }
+}
@@ -0,0 +1,449 @@
+#[cfg(any(feature = "presentation", test))]
+use crate::{
+    bots::Bot,
+    components::{Player, UserInfo},
+};
+#[cfg(feature = "presentation")]
+use crate::{
+    components::{IsLocal, MatchBoxPeerId},
+    game_modes::mode_form,
+    lobby::lobby_ui,
+    map_gen::{self, GENERATED_MAP},
+    maps::{MapAsset, MapAssets},
+    GameState,
+};
+use crate::{
+    game_modes::{GameMode, Rule, DEFAULT_MODE, MODES},
+    maps::OPEN_MAP,
+    BULLET_SPEED_SI, F2I, MAP_SIZE_RI, PLAYER_MOVE_SPEED_SI,
+};
+use bevy::prelude::*;
+#[cfg(feature = "presentation")]
+use bevy_egui::{
+    egui::{Align2, Button, ComboBox, DragValue, Slider, Window},
+    EguiContexts,
+};
+#[cfg(any(feature = "presentation", test))]
+use bevy_matchbox::prelude::PeerId;
+#[cfg(feature = "presentation")]
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+/// Match rules picked by the lobby host. The host broadcasts them to the other
+/// peers and they are a rollback resource, so every peer simulates with the
+/// same values and resumed games keep the rules they were saved with.
+#[cfg(feature = "presentation")]
+pub struct LobbySettingsPlugin;
+
+#[cfg(feature = "presentation")]
+impl Plugin for LobbySettingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LobbySettings>()
+            .init_resource::<ConnectionSettings>()
+            .add_system(
+                settings_ui
+                    .after(lobby_ui)
+                    .in_set(OnUpdate(GameState::Matchmaking)),
+            );
+    }
+}
+
+/// Most players a session seats. Bullet budgets and spawn points are tuned
+/// for this many; anyone else in the room spectates.
+pub const MAX_PLAYERS: usize = 8;
+#[cfg(feature = "presentation")]
+pub const MIN_MAP_SIZE: i32 = 11;
+#[cfg(feature = "presentation")]
+pub const MAX_MAP_SIZE: i32 = 61;
+/// Quick picks for the map size, next to the slider
+#[cfg(feature = "presentation")]
+const MAP_SIZE_PRESETS: [(&str, i32); 3] = [
+    ("Small", 21),
+    ("Medium", MAP_SIZE_RI),
+    ("Large", MAX_MAP_SIZE),
+];
+/// Most times a bullet ricochets before leaving the map
+pub const MAX_BOUNCES: u8 = 2;
+#[cfg(feature = "presentation")]
+pub const TICK_RATES: [u32; 3] = [30, 45, 60];
+#[cfg(feature = "presentation")]
+pub const MAX_INPUT_DELAY: usize = 6;
+
+#[derive(Resource, Reflect, Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+#[reflect(Resource)]
+pub struct LobbySettings {
+    /// Name of the `MapAsset` to play on
+    pub map: String,
+    /// Width and height of the map in world units. Follows the map, unless
+    /// it's resizable.
+    pub map_size: i32,
+    /// What the [`crate::map_gen`] grows the map from, when it's generated
+    pub map_seed: u32,
+    /// Percentage of the default bullet speed
+    pub bullet_speed_percent: i32,
+    /// Percentage of the default player speed
+    pub player_speed_percent: i32,
+    /// Id of the [`GameMode`] to play
+    pub mode: String,
+    /// Values of the mode's rules, in the order of its schema
+    pub rules: Vec<i32>,
+    /// Simulation frames per second. Speeds are per frame, so lower rates
+    /// also slow the game down, in exchange for fewer rollbacks.
+    pub tick_rate: u32,
+    /// AI players added to fill the match. Only as many as there are free
+    /// seats get to play.
+    pub bots: usize,
+    /// Whether players and bullets leaving the map come back in on the
+    /// opposite side, instead of stopping at the edge or expiring
+    pub wrap_around: bool,
+    /// Whether bullets ricochet off the map's edge, up to [`MAX_BOUNCES`]
+    /// times. Wrapping around takes precedence.
+    pub ricochet: bool,
+    /// Whether players only see what's near them, see [`crate::vision`]
+    pub fog_of_war: bool,
+    /// Whether players push each other away instead of walking through
+    /// each other
+    pub body_blocking: bool,
+    /// Whether bullets from different players destroy each other, see
+    /// [`crate::shootdown`]
+    pub bullet_collisions: bool,
+}
+
+/// Session parameters each player picks for their own connection
+#[cfg(feature = "presentation")]
+#[derive(Resource, Default, Clone, Debug)]
+pub struct ConnectionSettings {
+    /// Frames local inputs are held back. Higher values mean fewer
+    /// rollbacks on bad links, at the cost of responsiveness.
+    pub input_delay: usize,
+}
+
+impl Default for LobbySettings {
+    fn default() -> Self {
+        Self {
+            map: OPEN_MAP.to_string(),
+            map_size: MAP_SIZE_RI,
+            map_seed: 0,
+            bullet_speed_percent: 100,
+            player_speed_percent: 100,
+            mode: DEFAULT_MODE.to_string(),
+            rules: GameMode::find(DEFAULT_MODE).unwrap().default_rules(),
+            tick_rate: 60,
+            bots: 0,
+            wrap_around: false,
+            ricochet: false,
+            fog_of_war: false,
+            body_blocking: false,
+            bullet_collisions: false,
+        }
+    }
+}
+
+impl LobbySettings {
+    pub fn map_size_si(&self) -> i32 {
+        self.map_size * F2I
+    }
+
+    /// Half the width of the playing field, which spans `-half..=half`
+    pub fn half_map_size_si(&self) -> i32 {
+        (self.map_size_si() + 1) / 2
+    }
+
+    /// Brings a position that left a wrapping map back in from the opposite
+    /// side
+    pub fn wrap_position(&self, position: IVec2) -> IVec2 {
+        let half = self.half_map_size_si();
+        let wrap = |v: i32| (v + half).rem_euclid(2 * half) - half;
+        IVec2::new(wrap(position.x), wrap(position.y))
+    }
+
+    /// The copy of `position` closest to `near`, on a map that wraps around.
+    /// Things close to each other across an edge are only close this way.
+    pub fn nearest_image(&self, position: IVec2, near: IVec2) -> IVec2 {
+        if !self.wrap_around {
+            return position;
+        }
+        near + self.wrap_position(position - near)
+    }
+
+    pub fn bullet_speed_si(&self) -> i32 {
+        BULLET_SPEED_SI * self.bullet_speed_percent / 100
+    }
+
+    pub fn player_speed_si(&self) -> i32 {
+        PLAYER_MOVE_SPEED_SI * self.player_speed_percent / 100
+    }
+
+    pub fn game_mode(&self) -> &'static GameMode {
+        GameMode::find(&self.mode).unwrap_or(&MODES[0])
+    }
+
+    /// The value of `rule` in the current mode, if it has that rule
+    pub fn rule(&self, rule: Rule) -> Option<i32> {
+        self.game_mode().rule(&self.rules, rule)
+    }
+
+    pub fn friendly_fire(&self) -> bool {
+        self.rule(Rule::FriendlyFire)
+            .map_or(true, |value| value != 0)
+    }
+
+    /// Percentage of the default knockback bullets give, 0 in modes without
+    /// it
+    pub fn knockback_percent(&self) -> i32 {
+        self.rule(Rule::Knockback).unwrap_or(0)
+    }
+
+    /// Whether knockback past the map's edge takes players out. There's no
+    /// edge to fall off when the map wraps around.
+    pub fn ring_out(&self) -> bool {
+        !self.wrap_around && self.rule(Rule::RingOut).map_or(false, |value| value != 0)
+    }
+
+    /// Clamps values received from peers to what the simulation supports
+    #[cfg(feature = "presentation")]
+    pub fn sanitized(mut self) -> Self {
+        if GameMode::find(&self.mode).is_none() {
+            self.mode = DEFAULT_MODE.to_string();
+        }
+        self.rules = self.game_mode().validated(self.rules);
+        self.map_size = self.map_size.clamp(MIN_MAP_SIZE, MAX_MAP_SIZE);
+        self.bullet_speed_percent = self.bullet_speed_percent.clamp(50, 200);
+        self.player_speed_percent = self.player_speed_percent.clamp(50, 200);
+        if !TICK_RATES.contains(&self.tick_rate) {
+            self.tick_rate = LobbySettings::default().tick_rate;
+        }
+        self.bots = self.bots.min(MAX_PLAYERS - 1);
+        self
+    }
+}
+
+/// The peer whose settings everyone uses: the one with the lowest peer id
+#[cfg(feature = "presentation")]
+pub fn lobby_host<'a>(peers: impl Iterator<Item = &'a MatchBoxPeerId>) -> Option<PeerId> {
+    peers.map(|peer| peer.0).min()
+}
+
+/// Splits peers into those that play, in handle order, and those that
+/// spectate. Every peer computes the same split.
+#[cfg(any(feature = "presentation", test))]
+pub fn seat_peers(peers: impl Iterator<Item = PeerId>) -> (Vec<PeerId>, Vec<PeerId>) {
+    let mut seated = peers.collect::<Vec<_>>();
+    seated.sort();
+    let spectators = seated.split_off(seated.len().min(MAX_PLAYERS));
+    (seated, spectators)
+}
+
+/// Gives the entities of the `seated` peers their handles, and spawns the
+/// bots taking the seats after them up to `num_players`. Spectators seat
+/// everyone the same way, so the inputs they're sent have players to move.
+#[cfg(any(feature = "presentation", test))]
+pub fn seat_players(
+    commands: &mut Commands,
+    peers: impl Iterator<Item = (Entity, PeerId)>,
+    seated: &[PeerId],
+    num_players: usize,
+) {
+    for (entity, peer_id) in peers {
+        if let Some(handle) = seated.iter().position(|id| *id == peer_id) {
+            commands.entity(entity).insert(Player { handle });
+        }
+    }
+    for handle in seated.len()..num_players {
+        commands.spawn((
+            Player { handle },
+            Bot,
+            UserInfo {
+                name: format!("Bot {}", handle - seated.len() + 1),
+                color: [128, 128, 128],
+                avatar: 0,
+                cosmetics: default(),
+                class: default(),
+            },
+        ));
+    }
+}
+
+#[cfg(feature = "presentation")]
+fn settings_ui(
+    mut contexts: EguiContexts,
+    mut settings: ResMut<LobbySettings>,
+    mut connection: ResMut<ConnectionSettings>,
+    peers: Query<&MatchBoxPeerId>,
+    local_player: Query<&MatchBoxPeerId, With<IsLocal>>,
+    map_assets: Res<MapAssets>,
+    maps: Res<Assets<MapAsset>>,
+) {
+    let Ok(local_peer_id) = local_player.get_single() else {
+        return;
+    };
+    let is_host = lobby_host(peers.iter()) == Some(local_peer_id.0);
+    Window::new("Match settings")
+        .anchor(Align2::RIGHT_TOP, [-10., 10.])
+        .resizable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            if !is_host {
+                ui.weak("Chosen by the host");
+            }
+            ui.add_enabled_ui(is_host, |ui| {
+                // Edit a copy so the resource is only marked changed, and
+                // broadcast, when a value actually changes
+                let mut edited = settings.clone();
+                mode_form(ui, &mut edited.mode, &mut edited.rules);
+                ui.separator();
+                let generated = edited.map == GENERATED_MAP;
+                ComboBox::from_label("map")
+                    .selected_text(&edited.map)
+                    .show_ui(ui, |ui| {
+                        for map in map_assets.maps.iter().filter_map(|h| maps.get(h)) {
+                            if ui
+                                .selectable_label(edited.map == map.name, &map.name)
+                                .clicked()
+                            {
+                                edited.map = map.name.clone();
+                                edited.map_size = map.size;
+                            }
+                        }
+                        if ui.selectable_label(generated, GENERATED_MAP).clicked() && !generated {
+                            edited.map = GENERATED_MAP.to_string();
+                            edited.map_seed = new_map_seed();
+                        }
+                    });
+                if generated {
+                    ui.horizontal(|ui| {
+                        ui.label("Seed:");
+                        ui.add(DragValue::new(&mut edited.map_seed));
+                        if ui.button("New seed").clicked() {
+                            edited.map_seed = new_map_seed();
+                        }
+                    });
+                }
+                let resizable = generated
+                    || map_assets
+                        .find(&maps, &edited.map)
+                        .map_or(true, MapAsset::is_resizable);
+                ui.add_enabled_ui(resizable, |ui| {
+                    ui.horizontal(|ui| {
+                        for (name, size) in MAP_SIZE_PRESETS {
+                            ui.radio_value(&mut edited.map_size, size, name);
+                        }
+                    });
+                    ui.add(
+                        Slider::new(&mut edited.map_size, MIN_MAP_SIZE..=MAX_MAP_SIZE)
+                            .step_by(2.)
+                            .text("map size"),
+                    );
+                });
+                ui.checkbox(&mut edited.wrap_around, "Wrap around edges")
+                    .on_hover_text("Leaving the map brings you back in on the other side");
+                ui.add_enabled_ui(!edited.wrap_around, |ui| {
+                    ui.checkbox(&mut edited.ricochet, "Ricochet")
+                        .on_hover_text(format!(
+                            "Bullets bounce off the map's edge up to {MAX_BOUNCES} times"
+                        ));
+                });
+                ui.checkbox(&mut edited.fog_of_war, "Fog of war")
+                    .on_hover_text("Players only see others close to them");
+                ui.checkbox(&mut edited.body_blocking, "Body blocking")
+                    .on_hover_text("Players can't walk through each other");
+                ui.checkbox(&mut edited.bullet_collisions, "Bullet collisions")
+                    .on_hover_text("Shoot incoming bullets down with your own");
+                ui.add(
+                    Slider::new(&mut edited.bullet_speed_percent, 50..=200)
+                        .suffix("%")
+                        .text("bullet speed"),
+                );
+                ui.add(
+                    Slider::new(&mut edited.player_speed_percent, 50..=200)
+                        .suffix("%")
+                        .text("player speed"),
+                );
+                ui.horizontal(|ui| {
+                    ui.label("Tick rate:");
+                    for rate in TICK_RATES {
+                        ui.radio_value(&mut edited.tick_rate, rate, format!("{rate} Hz"));
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.label(format!("Bots: {}", edited.bots));
+                    if ui
+                        .add_enabled(edited.bots < MAX_PLAYERS - 1, Button::new("Add bot"))
+                        .clicked()
+                    {
+                        edited.bots += 1;
+                    }
+                    if ui
+                        .add_enabled(edited.bots > 0, Button::new("Remove bot"))
+                        .clicked()
+                    {
+                        edited.bots -= 1;
+                    }
+                });
+                settings.set_if_neq(edited);
+            });
+            ui.separator();
+            ui.label("Your connection");
+            ui.add(
+                Slider::new(&mut connection.input_delay, 0..=MAX_INPUT_DELAY)
+                    .text("input delay (frames)"),
+            )
+            .on_hover_text("Raise on a bad connection to trade latency for smoothness");
+        });
+}
+
+/// A fresh seed for a generated map. It only has to differ between picks, and
+/// the host's choice is sent to everyone, so the clock will do.
+#[cfg(feature = "presentation")]
+fn new_map_seed() -> u32 {
+    let now = Utc::now();
+    map_gen::mix(
+        now.timestamp() as u32,
+        &[now.timestamp_subsec_nanos() as i32],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wrap_position_leaves_the_field_alone() {
+        let settings = LobbySettings::default();
+        let half = settings.half_map_size_si();
+        for position in [IVec2::ZERO, IVec2::new(half - 1, -half), IVec2::new(-7, 13)] {
+            assert_eq!(settings.wrap_position(position), position);
+        }
+    }
+
+    #[test]
+    fn wrap_position_comes_back_in_from_the_opposite_side() {
+        let settings = LobbySettings::default();
+        let half = settings.half_map_size_si();
+        assert_eq!(
+            settings.wrap_position(IVec2::new(half + 10, 0)),
+            IVec2::new(-half + 10, 0)
+        );
+        assert_eq!(
+            settings.wrap_position(IVec2::new(0, -half - 10)),
+            IVec2::new(0, half - 10)
+        );
+        // However far out, like a bullet crossing the map in one frame
+        assert_eq!(
+            settings.wrap_position(IVec2::new(5 * half + 3, -7 * half)),
+            IVec2::new(-half + 3, -half)
+        );
+    }
+
+    #[test]
+    fn nearest_image_only_wraps_on_wrapping_maps() {
+        let mut settings = LobbySettings::default();
+        let half = settings.half_map_size_si();
+        let (position, near) = (IVec2::new(half - 5, 0), IVec2::new(-half + 5, 0));
+        assert_eq!(settings.nearest_image(position, near), position);
+        settings.wrap_around = true;
+        assert_eq!(
+            settings.nearest_image(position, near),
+            IVec2::new(-half - 5, 0)
+        );
+    }
+}
@@ -0,0 +1,195 @@
+use crate::{
+    classes::PlayerClass,
+    components::{Cosmetics, IsReady, StartChoice, UserInfo},
+    lobby_events::LobbyEvent,
+};
+use bevy::{prelude::*, utils::HashMap};
+use bevy_matchbox::prelude::PeerId;
+use serde::{Deserialize, Serialize};
+
+/// Everything a peer tells the others about itself while in the lobby. It's
+/// sent as [`LobbyStatePatch`]es carrying only the fields that changed, each
+/// numbered so a receiver notices when it missed one and asks for the whole
+/// state again.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LobbyState {
+    pub ready: bool,
+    pub start_choice: StartChoice,
+    pub user_info: UserInfo,
+}
+
+/// One part of a [`LobbyState`], with its new value
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum LobbyField {
+    Ready(bool),
+    StartChoice(StartChoice),
+    Name(String),
+    Color([u8; 3]),
+    Avatar(u8),
+    Cosmetics(Cosmetics),
+    Class(PlayerClass),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct LobbyStatePatch {
+    /// Version of the sender's state the patch applies to, or `None` when it
+    /// carries every field
+    pub base: Option<u32>,
+    /// Version of the sender's state once the patch is applied
+    pub version: u32,
+    pub fields: Vec<LobbyField>,
+}
+
+impl LobbyState {
+    pub fn new(ready: &IsReady, start_choice: &StartChoice, user_info: &UserInfo) -> Self {
+        Self {
+            ready: ready.0,
+            start_choice: *start_choice,
+            user_info: user_info.clone(),
+        }
+    }
+
+    fn fields(&self) -> Vec<LobbyField> {
+        let UserInfo {
+            name,
+            color,
+            avatar,
+            cosmetics,
+            class,
+        } = self.user_info.clone();
+        vec![
+            LobbyField::Ready(self.ready),
+            LobbyField::StartChoice(self.start_choice),
+            LobbyField::Name(name),
+            LobbyField::Color(color),
+            LobbyField::Avatar(avatar),
+            LobbyField::Cosmetics(cosmetics),
+            LobbyField::Class(class),
+        ]
+    }
+
+    /// Fields whose values differ from those in `old`. Every kind of field
+    /// appears once, so comparing whole fields compares their values.
+    #[cfg(feature = "presentation")]
+    fn changes_since(&self, old: &Self) -> Vec<LobbyField> {
+        let old = old.fields();
+        self.fields()
+            .into_iter()
+            .filter(|field| !old.contains(field))
+            .collect()
+    }
+
+    fn apply(&mut self, field: LobbyField) {
+        let info = &mut self.user_info;
+        match field {
+            LobbyField::Ready(ready) => self.ready = ready,
+            LobbyField::StartChoice(choice) => self.start_choice = choice,
+            LobbyField::Name(name) => info.name = name,
+            LobbyField::Color(color) => info.color = color,
+            LobbyField::Avatar(avatar) => info.avatar = avatar,
+            LobbyField::Cosmetics(cosmetics) => info.cosmetics = cosmetics,
+            LobbyField::Class(class) => info.class = class,
+        }
+    }
+
+    /// The lobby events that take a peer from `old` to this state, everything
+    /// for a peer not heard from before
+    fn events_since(&self, old: Option<&Self>) -> Vec<LobbyEvent> {
+        let mut events = Vec::new();
+        if old.map_or(true, |old| old.ready != self.ready) {
+            events.push(LobbyEvent::Ready(self.ready));
+        }
+        if old.map_or(true, |old| old.start_choice != self.start_choice) {
+            events.push(LobbyEvent::StartChoice(self.start_choice));
+        }
+        if old.map_or(true, |old| old.user_info != self.user_info) {
+            events.push(LobbyEvent::UserInfo(self.user_info.clone()));
+        }
+        events
+    }
+}
+
+/// The local state as last sent to peers
+#[derive(Resource, Default)]
+pub struct SentLobbyState {
+    version: u32,
+    #[cfg(feature = "presentation")]
+    state: Option<LobbyState>,
+}
+
+impl SentLobbyState {
+    /// A patch with what changed since the last one sent, if anything did
+    #[cfg(feature = "presentation")]
+    pub fn update(&mut self, state: LobbyState) -> Option<LobbyStatePatch> {
+        let (base, fields) = match &self.state {
+            Some(old) => (Some(self.version), state.changes_since(old)),
+            None => (None, state.fields()),
+        };
+        if fields.is_empty() {
+            return None;
+        }
+        self.version += 1;
+        self.state = Some(state);
+        Some(LobbyStatePatch {
+            base,
+            version: self.version,
+            fields,
+        })
+    }
+
+    /// All of `state`, for peers that just connected or missed a patch. If
+    /// `state` changed since the last patch, the next one repeats the change,
+    /// which is harmless.
+    pub fn full(&self, state: &LobbyState) -> LobbyStatePatch {
+        LobbyStatePatch {
+            base: None,
+            version: self.version,
+            fields: state.fields(),
+        }
+    }
+}
+
+/// Every remote peer's state as far as its patches were applied, by version
+#[derive(Resource, Default)]
+pub struct ReceivedLobbyStates {
+    states: HashMap<PeerId, (u32, LobbyState)>,
+    /// Peers whose patches stopped following on from each other, to ask for
+    /// their whole state
+    pub missed: Vec<PeerId>,
+}
+
+impl ReceivedLobbyStates {
+    /// Applies a peer's patch, returning the lobby events it amounts to. A
+    /// patch that doesn't apply to the last version received marks the peer
+    /// as [`ReceivedLobbyStates::missed`].
+    pub fn apply(&mut self, peer_id: PeerId, patch: LobbyStatePatch) -> Vec<LobbyEvent> {
+        let old = self.states.get(&peer_id);
+        // Outdated, e.g. the whole state asked for arriving after newer patches
+        if old.map_or(false, |(version, _)| patch.version <= *version) {
+            return Vec::new();
+        }
+        let mut state = match (patch.base, old) {
+            (None, _) => LobbyState::default(),
+            (Some(base), Some((version, state))) if base == *version => state.clone(),
+            _ => {
+                if !self.missed.contains(&peer_id) {
+                    warn!("Missed a lobby state patch from {peer_id:?}");
+                    self.missed.push(peer_id);
+                }
+                return Vec::new();
+            }
+        };
+        for field in patch.fields {
+            state.apply(field);
+        }
+        let events = state.events_since(old.map(|(_, old)| old));
+        self.states.insert(peer_id, (patch.version, state));
+        events
+    }
+
+    #[cfg(feature = "presentation")]
+    pub fn forget(&mut self, peer_id: PeerId) {
+        self.states.remove(&peer_id);
+        self.missed.retain(|missed| *missed != peer_id);
+    }
+}
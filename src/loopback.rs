@@ -0,0 +1,573 @@
+use crate::{
+    components::{
+        IsReady, MatchBoxPeerId, PeerVersion, Player, Position, StartChoice, TabId, UserInfo,
+        BUILD_HASH, PROTOCOL_VERSION,
+    },
+    game_modes::DEFAULT_MODE,
+    headless::{discard_feedback, divergence},
+    input::{encode_input, PlayerInput},
+    lobby_events::{LobbyEvent, LobbyEventLog},
+    lobby_settings::{seat_peers, seat_players, MAX_PLAYERS},
+    lobby_state::{LobbyState, LobbyStatePatch, ReceivedLobbyStates, SentLobbyState},
+    match_config::MatchConfig,
+    rng::SimFrame,
+    simulation::{ggrs_plugin, SimulationPlugin},
+    wire_format::{decode_tagged, encode_tagged},
+    GameState, GgrsConfig, MAX_PREDICTION_FRAMES,
+};
+use bevy::{prelude::*, time::TimeUpdateStrategy};
+use bevy_ggrs::{
+    ggrs::{
+        self, DesyncDetection, GGRSEvent, Message, NonBlockingSocket, PlayerHandle, PlayerType,
+    },
+    ggrs_stage::GGRSStage,
+    Session,
+};
+use bevy_matchbox::prelude::PeerId;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// Plays a match between two peers in one process, each its own `App` with
+/// its own P2P GGRS session, connected by a [`LoopbackSocket`] instead of
+/// matchbox. The peers greet each other over the socket the way the lobby
+/// does, through the lobby's event log and state patches, and only start once
+/// each has the other's version, tab and readiness. Both derive the
+/// [`MatchConfig`] from the peer ids, play up to [`SNAPSHOT_FRAME`], and must
+/// end up with identical GGRS snapshots. Unlike the sync tests in
+/// [`crate::headless`], inputs really cross between the sessions, so session
+/// start, prediction, rollbacks on late inputs and GGRS's desync detection
+/// are all exercised.
+#[test]
+fn peers_agree_after_lobby_and_play() {
+    let peers = [peer_id(1), peer_id(2)];
+    let (first, second) = LoopbackSocket::pair(peers[0], peers[1]);
+    let mut apps = [peer_app(peers, 0, first), peer_app(peers, 1, second)];
+    let mut updates = 0;
+    // A peer that got there waits for the other, which can still predict up
+    // to it from the inputs already sent
+    while !apps.iter().all(reached_snapshot_frame) {
+        assert!(
+            updates < MAX_UPDATES,
+            "the sessions stalled before frame {SNAPSHOT_FRAME}"
+        );
+        for app in apps.iter_mut().filter(|app| !reached_snapshot_frame(app)) {
+            app.update();
+        }
+        updates += 1;
+    }
+
+    for (n, app) in apps.iter().enumerate() {
+        assert_eq!(
+            app.world.resource::<SimFrame>().0,
+            SNAPSHOT_FRAME,
+            "peer {n} skipped past the snapshot frame"
+        );
+        if let Some(Desync(frame)) = app.world.get_resource::<Desync>() {
+            panic!("GGRS detected a desync at frame {frame}");
+        }
+    }
+    assert!(
+        apps[0].world.resource::<MatchConfig>() == apps[1].world.resource::<MatchConfig>(),
+        "the peers derived different match configs"
+    );
+    let [first, second] = [&apps[0], &apps[1]].map(|app| {
+        app.world
+            .resource::<GGRSStage<GgrsConfig>>()
+            .get_serialized_snapshot(&app.world)
+    });
+    if let Some(divergence) = divergence(&first, &second) {
+        panic!("the peers diverged at {divergence}");
+    }
+}
+
+/// Plays a full room: [`MAX_PLAYERS`] peers seated in a P2P session and one
+/// more spectating through the host, all seated by the lobby's
+/// [`seat_players`]. The spectator has to end up with the same players as
+/// the host, moved by the inputs the host forwards.
+#[test]
+fn spectator_sees_the_seated_players() {
+    let peers = (0..=MAX_PLAYERS as u8)
+        .map(|n| peer_id(n + 1))
+        .collect::<Vec<_>>();
+    let hub = MeshHub::default();
+    let mut apps = peers
+        .iter()
+        .map(|own| mesh_app(&peers, *own, hub.socket(*own)))
+        .collect::<Vec<_>>();
+    let (host, spectator) = (0, MAX_PLAYERS);
+    let mut updates = 0;
+    while apps[spectator].world.resource::<SimFrame>().0 < SPECTATED_FRAME {
+        assert!(
+            updates < MAX_UPDATES,
+            "the spectator stalled before frame {SPECTATED_FRAME}"
+        );
+        for app in apps.iter_mut() {
+            app.update();
+        }
+        updates += 1;
+    }
+
+    for app in apps.iter() {
+        if let Some(Desync(frame)) = app.world.get_resource::<Desync>() {
+            panic!("GGRS detected a desync at frame {frame}");
+        }
+    }
+    let [host_players, spectator_players] = [host, spectator].map(|n| seated_handles(&mut apps[n]));
+    assert_eq!(host_players.len(), MAX_PLAYERS, "the host seated too few");
+    assert_eq!(
+        spectator_players, host_players,
+        "the spectator has other players than the host"
+    );
+}
+
+/// Simulation frames per second of game time
+const FPS: usize = 60;
+/// Frame the peers compare their state at
+const SNAPSHOT_FRAME: u32 = 600;
+/// Updates of both apps before the test gives up. Sessions spend a few
+/// synchronizing, and a peer that got ahead skips some to let the other
+/// catch up.
+const MAX_UPDATES: u32 = 4 * SNAPSHOT_FRAME;
+const INPUT_DELAY: usize = 2;
+const DESYNC_CHECK_INTERVAL: u32 = 10;
+/// Frames of no input before [`SNAPSHOT_FRAME`]. Whichever peer is still
+/// predicting the other then predicts the inputs it really sent, so both
+/// hold the confirmed state of the frame.
+const IDLE_FRAMES: u32 = 2 * MAX_PREDICTION_FRAMES + INPUT_DELAY as u32;
+/// Frame the spectator has to reach
+const SPECTATED_FRAME: u32 = 120;
+/// Frames each direction of the input pattern is held for
+const FRAMES_PER_STEP: u32 = 20;
+const DIRECTIONS: [IVec2; 4] = [IVec2::X, IVec2::Y, IVec2::NEG_X, IVec2::NEG_Y];
+
+/// Peer ids as the signalling server would hand them out
+fn peer_id(n: u8) -> PeerId {
+    serde_json::from_str(&format!("\"00000000-0000-0000-0000-0000000000{n:02x}\""))
+        .expect("valid peer id")
+}
+
+/// The messages the lobby greets a newly connected peer with, in the order
+/// it sends them. `P2PMessage` carries the same in the `presentation` build,
+/// where it also holds what only the matchbox lobby needs, so here they're
+/// wrapped on their own and sent in the same wire format.
+#[derive(Serialize, Deserialize, Debug)]
+enum Greeting {
+    Version { build_hash: String, protocol: u32 },
+    TabId(TabId),
+    NoGameSave,
+    LobbyState(LobbyStatePatch),
+}
+
+/// Which peer an app plays as, and its end of the connection
+#[derive(Resource)]
+struct LoopbackPeer {
+    peers: [PeerId; 2],
+    local: PeerId,
+    socket: LoopbackSocket,
+}
+
+/// First frame GGRS found the peers disagreeing on
+#[derive(Resource)]
+struct Desync(ggrs::Frame);
+
+type Inbox<T> = Arc<Mutex<Vec<(PeerId, T)>>>;
+
+/// One end of an in-memory connection between two peers, with GGRS's packets
+/// and reliable lobby messages kept apart like matchbox's channels. There's
+/// only the other end to send to, so addresses are ignored. Clones share the
+/// same end, so the session and the lobby can each hold one.
+#[derive(Clone)]
+struct LoopbackSocket {
+    own: PeerId,
+    inbox: Inbox<Message>,
+    outbox: Inbox<Message>,
+    messages_in: Inbox<Vec<u8>>,
+    messages_out: Inbox<Vec<u8>>,
+}
+
+impl LoopbackSocket {
+    fn pair(a: PeerId, b: PeerId) -> (Self, Self) {
+        let (to_a, to_b) = (Inbox::default(), Inbox::default());
+        let (messages_to_a, messages_to_b) = (Inbox::default(), Inbox::default());
+        (
+            Self {
+                own: a,
+                inbox: to_a.clone(),
+                outbox: to_b.clone(),
+                messages_in: messages_to_a.clone(),
+                messages_out: messages_to_b.clone(),
+            },
+            Self {
+                own: b,
+                inbox: to_b,
+                outbox: to_a,
+                messages_in: messages_to_b,
+                messages_out: messages_to_a,
+            },
+        )
+    }
+
+    fn send_greeting(&self, greeting: &Greeting) {
+        let packet = encode_tagged(greeting).expect("failed to encode greeting");
+        self.messages_out.lock().unwrap().push((self.own, packet));
+    }
+
+    fn receive_greetings(&self) -> Vec<(PeerId, Greeting)> {
+        std::mem::take(&mut *self.messages_in.lock().unwrap())
+            .into_iter()
+            .map(|(peer_id, packet)| {
+                let greeting = decode_tagged(&packet).expect("failed to decode greeting");
+                (peer_id, greeting)
+            })
+            .collect()
+    }
+}
+
+impl NonBlockingSocket<PeerId> for LoopbackSocket {
+    fn send_to(&mut self, msg: &Message, _addr: &PeerId) {
+        self.outbox.lock().unwrap().push((self.own, msg.clone()));
+    }
+
+    fn receive_all_messages(&mut self) -> Vec<(PeerId, Message)> {
+        std::mem::take(&mut *self.inbox.lock().unwrap())
+    }
+}
+
+/// Every peer's inbox, for [`MeshSocket`]s to deliver to
+#[derive(Clone, Default)]
+struct MeshHub(Arc<Mutex<HashMap<PeerId, Vec<(PeerId, Message)>>>>);
+
+impl MeshHub {
+    fn socket(&self, own: PeerId) -> MeshSocket {
+        MeshSocket {
+            own,
+            hub: self.clone(),
+        }
+    }
+}
+
+/// A peer's connection to every other peer in the hub. Unlike
+/// [`LoopbackSocket`] it sends each message to its address, since a room
+/// with spectators isn't just two peers talking to each other.
+struct MeshSocket {
+    own: PeerId,
+    hub: MeshHub,
+}
+
+impl NonBlockingSocket<PeerId> for MeshSocket {
+    fn send_to(&mut self, msg: &Message, addr: &PeerId) {
+        let mut inboxes = self.hub.0.lock().unwrap();
+        inboxes
+            .entry(*addr)
+            .or_default()
+            .push((self.own, msg.clone()));
+    }
+
+    fn receive_all_messages(&mut self) -> Vec<(PeerId, Message)> {
+        let mut inboxes = self.hub.0.lock().unwrap();
+        std::mem::take(inboxes.entry(self.own).or_default())
+    }
+}
+
+/// Which peer a full room's app plays as, and its connection, taken when the
+/// session starts
+#[derive(Resource)]
+struct MeshPeer {
+    peers: Vec<PeerId>,
+    local: PeerId,
+    socket: Option<MeshSocket>,
+}
+
+/// The app of peer `local` in a room of `peers`, past the lobby: every peer
+/// already has its entity, and the players are seated before the game starts
+fn mesh_app(peers: &[PeerId], local: PeerId, socket: MeshSocket) -> App {
+    let mut app = App::new();
+
+    ggrs_plugin()
+        .with_input_system(scripted_input)
+        .build(&mut app);
+    app.world
+        .resource_mut::<GGRSStage<GgrsConfig>>()
+        .set_update_frequency(FPS);
+
+    for peer_id in peers {
+        app.world.spawn(MatchBoxPeerId(*peer_id));
+    }
+    app.add_plugins(MinimalPlugins)
+        .add_state::<GameState>()
+        .add_plugin(SimulationPlugin)
+        .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f64(
+            1. / FPS as f64,
+        )))
+        .insert_resource(NextState(Some(GameState::InGame)))
+        .insert_resource(MatchConfig::new(peers.iter().copied(), DEFAULT_MODE))
+        .insert_resource(MeshPeer {
+            peers: peers.to_vec(),
+            local,
+            socket: Some(socket),
+        })
+        .add_startup_system(seat_mesh_peers)
+        .add_system(start_mesh_session.in_schedule(OnEnter(GameState::InGame)))
+        .add_systems((discard_feedback, watch_for_desyncs).in_set(OnUpdate(GameState::InGame)));
+    app
+}
+
+/// Seats the room the way the lobby does when the game starts
+fn seat_mesh_peers(
+    mut commands: Commands,
+    peer: Res<MeshPeer>,
+    peers: Query<(Entity, &MatchBoxPeerId)>,
+) {
+    let (seated, _) = seat_peers(peer.peers.iter().copied());
+    seat_players(
+        &mut commands,
+        peers.iter().map(|(entity, peer_id)| (entity, peer_id.0)),
+        &seated,
+        seated.len(),
+    );
+}
+
+/// Starts a P2P session on the seated peers, with the host forwarding inputs
+/// to the spectators, and a spectator session on the others, like the lobby
+fn start_mesh_session(mut commands: Commands, mut peer: ResMut<MeshPeer>) {
+    let (seated, spectators) = seat_peers(peer.peers.iter().copied());
+    let host = seated[0];
+    let socket = peer.socket.take().expect("session already started");
+    let mut session_builder = ggrs::SessionBuilder::<GgrsConfig>::new()
+        .with_num_players(seated.len())
+        .with_input_delay(INPUT_DELAY)
+        .with_desync_detection_mode(DesyncDetection::On {
+            interval: DESYNC_CHECK_INTERVAL,
+        });
+    if spectators.contains(&peer.local) {
+        let session = session_builder.start_spectator_session(host, socket);
+        commands.insert_resource(Session::SpectatorSession(session));
+        return;
+    }
+    for (handle, peer_id) in seated.iter().enumerate() {
+        let player = if *peer_id == peer.local {
+            PlayerType::Local
+        } else {
+            PlayerType::Remote(*peer_id)
+        };
+        session_builder = session_builder
+            .add_player(player, handle)
+            .expect("failed to add player");
+    }
+    if peer.local == host {
+        for (i, spectator) in spectators.into_iter().enumerate() {
+            session_builder = session_builder
+                .add_player(PlayerType::Spectator(spectator), seated.len() + i)
+                .expect("failed to add spectator");
+        }
+    }
+    let session = session_builder
+        .start_p2p_session(socket)
+        .expect("failed to start session");
+    commands.insert_resource(Session::P2PSession(session));
+}
+
+/// Handles of the players the simulation set up, in order
+fn seated_handles(app: &mut App) -> Vec<usize> {
+    let mut handles = app
+        .world
+        .query_filtered::<&Player, With<Position>>()
+        .iter(&app.world)
+        .map(|player| player.handle)
+        .collect::<Vec<_>>();
+    handles.sort();
+    handles
+}
+
+/// The app of peer `local` out of `peers`, starting in the lobby. Like the
+/// headless match, time advances by one frame per update instead of
+/// following the clock.
+fn peer_app(peers: [PeerId; 2], local: usize, socket: LoopbackSocket) -> App {
+    let mut app = App::new();
+
+    ggrs_plugin()
+        .with_input_system(scripted_input)
+        .build(&mut app);
+    app.world
+        .resource_mut::<GGRSStage<GgrsConfig>>()
+        .set_update_frequency(FPS);
+
+    app.add_plugins(MinimalPlugins)
+        .add_state::<GameState>()
+        .add_plugin(SimulationPlugin)
+        .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f64(
+            1. / FPS as f64,
+        )))
+        .insert_resource(NextState(Some(GameState::Matchmaking)))
+        .insert_resource(LoopbackPeer {
+            peers,
+            local: peers[local],
+            socket,
+        })
+        .init_resource::<LobbyEventLog>()
+        .init_resource::<SentLobbyState>()
+        .init_resource::<ReceivedLobbyStates>()
+        .add_system(greet_peer.in_schedule(OnEnter(GameState::Matchmaking)))
+        .add_systems(
+            (receive_greetings, start_when_ready.after(receive_greetings))
+                .in_set(OnUpdate(GameState::Matchmaking)),
+        )
+        .add_system(start_session.in_schedule(OnEnter(GameState::InGame)))
+        .add_systems((discard_feedback, watch_for_desyncs).in_set(OnUpdate(GameState::InGame)));
+    app
+}
+
+/// What the lobby does when a peer connects: adds its entity, then tells it
+/// who we are
+fn greet_peer(
+    mut commands: Commands,
+    peer: Res<LoopbackPeer>,
+    sent: Res<SentLobbyState>,
+    mut log: ResMut<LobbyEventLog>,
+    time: Res<Time>,
+) {
+    let remote = peer.peers.into_iter().find(|id| *id != peer.local).unwrap();
+    let mut entity = commands.spawn(MatchBoxPeerId(remote));
+    log.record(&time, remote, LobbyEvent::Joined, &mut entity);
+
+    let state = LobbyState::new(&IsReady(true), &StartChoice::NewGame, &UserInfo::default());
+    for greeting in [
+        Greeting::Version {
+            build_hash: BUILD_HASH.to_string(),
+            protocol: PROTOCOL_VERSION,
+        },
+        Greeting::TabId(TabId(format!("tab-{}", peer.local.0))),
+        Greeting::NoGameSave,
+        Greeting::LobbyState(sent.full(&state)),
+    ] {
+        peer.socket.send_greeting(&greeting);
+    }
+}
+
+/// Turns greetings into lobby events on the sender's entity, as the lobby
+/// does with the same messages
+fn receive_greetings(
+    mut commands: Commands,
+    peer: Res<LoopbackPeer>,
+    peers: Query<(Entity, &MatchBoxPeerId)>,
+    mut received: ResMut<ReceivedLobbyStates>,
+    mut log: ResMut<LobbyEventLog>,
+    time: Res<Time>,
+) {
+    for (peer_id, greeting) in peer.socket.receive_greetings() {
+        let (entity, _) = peers
+            .iter()
+            .find(|(_, id)| id.0 == peer_id)
+            .expect("greeted by an unknown peer");
+        let events = match greeting {
+            Greeting::Version {
+                build_hash,
+                protocol,
+            } => {
+                let version = PeerVersion {
+                    build_hash,
+                    protocol,
+                };
+                assert!(version.matches_local(), "the peers run different builds");
+                vec![LobbyEvent::Version(version)]
+            }
+            Greeting::TabId(tab_id) => vec![LobbyEvent::TabId(tab_id)],
+            Greeting::NoGameSave => vec![LobbyEvent::SaveOffer(None)],
+            Greeting::LobbyState(patch) => received.apply(peer_id, patch),
+        };
+        for event in events {
+            log.record(&time, peer_id, event, &mut commands.entity(entity));
+        }
+    }
+    assert!(received.missed.is_empty(), "a lobby state patch was lost");
+}
+
+/// Seats the players once the other peer's version and tab are known and
+/// it's ready, like the lobby does once everyone is
+fn start_when_ready(
+    mut commands: Commands,
+    peer: Res<LoopbackPeer>,
+    others: Query<(&IsReady, &StartChoice), (With<MatchBoxPeerId>, With<PeerVersion>, With<TabId>)>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let Ok((ready, choice)) = others.get_single() else {
+        return;
+    };
+    if !ready.0 {
+        return;
+    }
+    assert_eq!(*choice, StartChoice::NewGame, "the peers chose differently");
+    for handle in 0..peer.peers.len() {
+        commands.spawn(Player { handle });
+    }
+    commands.insert_resource(MatchConfig::new(peer.peers, DEFAULT_MODE));
+    next_state.set(GameState::InGame);
+}
+
+/// Peers in handle order, the way the lobby seats them
+fn seated(peers: [PeerId; 2]) -> [PeerId; 2] {
+    let mut seated = peers;
+    seated.sort();
+    seated
+}
+
+/// Started along with the players getting their components, like the
+/// headless match's session
+fn start_session(mut commands: Commands, peer: Res<LoopbackPeer>) {
+    let mut session_builder = ggrs::SessionBuilder::<GgrsConfig>::new()
+        .with_num_players(peer.peers.len())
+        .with_input_delay(INPUT_DELAY)
+        .with_desync_detection_mode(DesyncDetection::On {
+            interval: DESYNC_CHECK_INTERVAL,
+        });
+    for (handle, peer_id) in seated(peer.peers).into_iter().enumerate() {
+        let player = if peer_id == peer.local {
+            PlayerType::Local
+        } else {
+            PlayerType::Remote(peer_id)
+        };
+        session_builder = session_builder
+            .add_player(player, handle)
+            .expect("failed to add player");
+    }
+    let session = session_builder
+        .start_p2p_session(peer.socket.clone())
+        .expect("failed to start session");
+    commands.insert_resource(Session::P2PSession(session));
+}
+
+/// Walks in a square, turning at a different time for each player, and
+/// fires every other stretch, then stands still before the snapshot frame
+fn scripted_input(handle: In<PlayerHandle>, frame: Res<SimFrame>) -> PlayerInput {
+    if frame.0 + IDLE_FRAMES >= SNAPSHOT_FRAME {
+        return encode_input(IVec2::ZERO, false, None);
+    }
+    let step = (frame.0 + handle.0 as u32 * FRAMES_PER_STEP / 2) / FRAMES_PER_STEP;
+    encode_input(
+        DIRECTIONS[step as usize % DIRECTIONS.len()],
+        step % 2 == 0,
+        None,
+    )
+}
+
+fn watch_for_desyncs(mut commands: Commands, session: Option<ResMut<Session<GgrsConfig>>>) {
+    let Some(mut session) = session else {
+        return;
+    };
+    let Session::P2PSession(session) = &mut *session else {
+        return;
+    };
+    for event in session.events() {
+        if let GGRSEvent::DesyncDetected { frame, .. } = event {
+            commands.insert_resource(Desync(frame));
+        }
+    }
+}
+
+fn reached_snapshot_frame(app: &App) -> bool {
+    app.world.resource::<SimFrame>().0 >= SNAPSHOT_FRAME
+}
@@ -0,0 +1,201 @@
+use crate::{
+    components::IsLocal, cooldown_ring::CooldownRingSettings, juice::JuiceSettings,
+    launch_config::LaunchConfig,
+};
+use bevy::{
+    prelude::*,
+    time::common_conditions::on_timer,
+    utils::Duration,
+    winit::{UpdateMode, WinitSettings},
+};
+use bevy_egui::egui::Ui;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use wasm_bindgen::{prelude::*, JsCast};
+use web_sys::{window, BatteryManager};
+
+/// Saves battery by presenting at half rate and turning off cosmetic effects:
+/// particles (see [`crate::vfx`]), screen shake and cooldown rings. GGRS
+/// catches up on the frames between updates, so the simulation and
+/// networking keep their full rate. The preference is a property of the
+/// local player, kept per tab in cookies like
+/// [`crate::accessibility::AccessibilitySettings`].
+pub struct LowPowerPlugin;
+
+impl Plugin for LowPowerPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LowPowerMode>()
+            .add_startup_system(request_battery_status)
+            .add_system(request_battery_status.run_if(on_timer(BATTERY_POLL_INTERVAL)))
+            .add_system(apply_launch_preference)
+            .add_system(update_low_power_mode.after(apply_launch_preference))
+            .add_system(apply_low_power_mode.after(update_low_power_mode));
+    }
+}
+
+const BATTERY_POLL_INTERVAL: Duration = Duration::from_secs(60);
+/// Battery level below which auto mode switches to low power while unplugged
+const LOW_BATTERY_LEVEL: f64 = 0.2;
+/// Presentation rate while in low power mode
+const LOW_POWER_FRAME_TIME: Duration = Duration::from_micros(1_000_000 / 30);
+/// How much the background is darkened in low power mode
+const BACKGROUND_DIMMING: f32 = 0.5;
+
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug, Serialize, Deserialize)]
+pub enum LowPowerPreference {
+    /// Follow the battery, where the browser reports it
+    #[default]
+    Auto,
+    On,
+    Off,
+}
+
+#[derive(Component, Serialize, Deserialize, Clone, PartialEq, Default, Debug)]
+#[serde(default)]
+pub struct LowPowerSettings {
+    pub preference: LowPowerPreference,
+}
+
+/// Whether low power mode is currently in effect
+#[derive(Resource, Default, PartialEq, Eq)]
+pub struct LowPowerMode(pub bool);
+
+#[derive(Clone, Copy)]
+struct BatteryStatus {
+    level: f64,
+    charging: bool,
+}
+
+thread_local! {
+    static BATTERY_STATUS: RefCell<Option<BatteryStatus>> = RefCell::new(None);
+}
+
+/// Asks the Battery Status API for a fresh reading. Browsers without it
+/// leave the status unknown, which auto mode treats as plugged in.
+fn request_battery_status() {
+    if cfg!(feature = "native") {
+        return;
+    }
+    let Some(promise) = window().and_then(|w| w.navigator().get_battery().ok()) else {
+        return;
+    };
+    let on_battery = Closure::once(|value: JsValue| {
+        if let Ok(battery) = value.dyn_into::<BatteryManager>() {
+            BATTERY_STATUS.with(|status| {
+                *status.borrow_mut() = Some(BatteryStatus {
+                    level: battery.level(),
+                    charging: battery.charging(),
+                })
+            });
+        }
+    });
+    let _ = promise.then(&on_battery);
+    on_battery.forget();
+}
+
+/// Sets the local player's preference as asked by the launch link, once per
+/// visit
+fn apply_launch_preference(
+    mut launch: ResMut<LaunchConfig>,
+    mut local: Query<&mut LowPowerSettings, (With<IsLocal>, Added<LowPowerSettings>)>,
+) {
+    let Ok(mut settings) = local.get_single_mut() else {
+        return;
+    };
+    if let Some(preference) = launch.low_power.take() {
+        settings.preference = preference;
+    }
+}
+
+/// Until the local player is set up, auto mode is assumed
+fn update_low_power_mode(
+    settings: Query<&LowPowerSettings, With<IsLocal>>,
+    mut mode: ResMut<LowPowerMode>,
+) {
+    let preference = settings
+        .get_single()
+        .map_or(LowPowerPreference::Auto, |settings| settings.preference);
+    let enabled = match preference {
+        LowPowerPreference::On => true,
+        LowPowerPreference::Off => false,
+        LowPowerPreference::Auto => BATTERY_STATUS.with(|status| {
+            status.borrow().map_or(false, |battery| {
+                !battery.charging && battery.level < LOW_BATTERY_LEVEL
+            })
+        }),
+    };
+    mode.set_if_neq(LowPowerMode(enabled));
+}
+
+/// What low power mode turned off, so it can be restored afterwards
+#[derive(Resource)]
+struct SuspendedEffects {
+    clear_color: Color,
+    cooldown_rings: bool,
+    screen_shake: bool,
+}
+
+fn apply_low_power_mode(
+    mut commands: Commands,
+    mode: Res<LowPowerMode>,
+    suspended: Option<Res<SuspendedEffects>>,
+    mut winit: ResMut<WinitSettings>,
+    mut clear_color: ResMut<ClearColor>,
+    mut cooldown_rings: Option<ResMut<CooldownRingSettings>>,
+    mut juice: ResMut<JuiceSettings>,
+) {
+    if !mode.is_changed() {
+        return;
+    }
+    if mode.0 {
+        if suspended.is_some() {
+            return;
+        }
+        info!("Entering low power mode");
+        commands.insert_resource(SuspendedEffects {
+            clear_color: clear_color.0,
+            cooldown_rings: cooldown_rings.as_ref().map_or(false, |rings| rings.enabled),
+            screen_shake: juice.enabled,
+        });
+        // Unlike `Reactive`, ignores raw mouse motion, which would otherwise
+        // wake the app for every movement while aiming
+        winit.focused_mode = UpdateMode::ReactiveLowPower {
+            max_wait: LOW_POWER_FRAME_TIME,
+        };
+        clear_color.0 = dim(clear_color.0);
+        if let Some(rings) = cooldown_rings.as_mut() {
+            rings.enabled = false;
+        }
+        juice.enabled = false;
+    } else if let Some(suspended) = suspended {
+        info!("Leaving low power mode");
+        winit.focused_mode = WinitSettings::default().focused_mode;
+        clear_color.0 = suspended.clear_color;
+        if let Some(rings) = cooldown_rings.as_mut() {
+            rings.enabled = suspended.cooldown_rings;
+        }
+        juice.enabled = suspended.screen_shake;
+        commands.remove_resource::<SuspendedEffects>();
+    }
+}
+
+fn dim(color: Color) -> Color {
+    let [r, g, b, a] = color.as_rgba_f32();
+    Color::rgba(
+        r * BACKGROUND_DIMMING,
+        g * BACKGROUND_DIMMING,
+        b * BACKGROUND_DIMMING,
+        a,
+    )
+}
+
+/// The lobby's low power mode choice
+pub fn low_power_ui(ui: &mut Ui, settings: &mut LowPowerSettings) {
+    ui.horizontal(|ui| {
+        ui.label("Low power mode:");
+        let preference = &mut settings.preference;
+        ui.radio_value(preference, LowPowerPreference::Auto, "Auto");
+        ui.radio_value(preference, LowPowerPreference::On, "On");
+        ui.radio_value(preference, LowPowerPreference::Off, "Off");
+    });
+}
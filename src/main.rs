@@ -1,54 +1,354 @@
 #![allow(clippy::type_complexity)]
 
+#[cfg(not(any(feature = "presentation", feature = "headless")))]
+compile_error!("enable the `presentation` feature, or `headless` to run without it");
+
 // use crate::fixed_point::Fix;
-use bevy::{prelude::*, render::camera::ScalingMode, utils::HashMap};
-use bevy_asset_loader::prelude::*;
-use bevy_egui::{
-    egui::{Align, Layout, TopBottomPanel},
-    EguiContexts, EguiPlugin,
-};
-use bevy_ggrs::{
-    ggrs::{self, GGRSEvent},
-    ggrs_stage::GGRSStage,
-    GGRSPlugin, GGRSSchedule, PlayerInputs, Rollback, RollbackIdProvider,
-};
-use bevy_matchbox::prelude::*;
-use chrono::Utc;
+#[cfg(feature = "presentation")]
+use accessibility::AccessibilityPlugin;
+#[cfg(feature = "presentation")]
+use achievements::AchievementsPlugin;
+#[cfg(feature = "presentation")]
+use animation::SpriteAnimationPlugin;
+#[cfg(feature = "presentation")]
+use attract::AttractPlugin;
+#[cfg(feature = "presentation")]
+use audio::AudioPlugin;
+use bevy::prelude::*;
+#[cfg(feature = "presentation")]
+use bevy_egui::EguiPlugin;
+use bevy_ggrs::ggrs;
+use bevy_matchbox::prelude::PeerId;
+#[cfg(feature = "presentation")]
+use bullet::BulletPlugin;
+#[cfg(feature = "presentation")]
+use camera::CameraPlugin;
+#[cfg(feature = "presentation")]
 use components::*;
+#[cfg(feature = "presentation")]
+use connection_quality::ConnectionQualityPlugin;
+#[cfg(feature = "presentation")]
+use cosmetics::CosmeticsPlugin;
+#[cfg(feature = "presentation")]
+use countdown::CountdownPlugin;
+#[cfg(feature = "presentation")]
+use crosshair::CrosshairPlugin;
+#[cfg(feature = "presentation")]
+use culling::CullingPlugin;
+#[cfg(feature = "presentation")]
+use debug_overlay::DebugOverlayPlugin;
+#[cfg(feature = "presentation")]
+use emotes::EmotesPlugin;
+#[cfg(feature = "presentation")]
+use filter::FilterPlugin;
+#[cfg(feature = "presentation")]
+use fixed_timestep::FixedTimestepPlugin;
+#[cfg(feature = "presentation")]
+use focus::FocusPlugin;
+#[cfg(feature = "presentation")]
+use frame_advantage::FrameAdvantagePlugin;
+#[cfg(feature = "presentation")]
+use game::GamePlugin;
+#[cfg(feature = "presentation")]
+use ghost::GhostPlugin;
+#[cfg(feature = "presentation")]
+use haptics::HapticsPlugin;
+#[cfg(feature = "presentation")]
+use history::HistoryPlugin;
+#[cfg(feature = "presentation")]
+use hit_indicator::HitIndicatorPlugin;
+#[cfg(feature = "presentation")]
+use hot_seat::HotSeatPlugin;
 // use fixed_point::{FixedWrapped, Vec2Fixed};
 use input::*;
+#[cfg(feature = "presentation")]
+use juice::JuicePlugin;
+#[cfg(feature = "presentation")]
+use key_bindings::KeyBindingsPlugin;
+#[cfg(feature = "presentation")]
+use kick::KickPlugin;
+#[cfg(feature = "presentation")]
+use launch_config::LaunchConfigPlugin;
+#[cfg(feature = "presentation")]
+use layers::LayersPlugin;
+#[cfg(feature = "presentation")]
+use leave::LeavePlugin;
+#[cfg(feature = "presentation")]
 use lobby::LobbyPlugin;
-use serde::{Deserialize, Serialize};
-use std::collections::VecDeque;
-
+#[cfg(feature = "presentation")]
+use lobby_settings::LobbySettingsPlugin;
+#[cfg(feature = "presentation")]
+use low_power::LowPowerPlugin;
+#[cfg(feature = "presentation")]
+use maps::MapsPlugin;
+#[cfg(feature = "presentation")]
+use match_end::MatchEndPlugin;
+#[cfg(feature = "presentation")]
+use match_log::MatchLogPlugin;
+#[cfg(feature = "presentation")]
+use match_report::MatchReportPlugin;
+#[cfg(feature = "presentation")]
+use mute::MutePlugin;
+#[cfg(feature = "presentation")]
+use name_tags::NameTagsPlugin;
+#[cfg(feature = "presentation")]
+use net::NetPlugin;
+#[cfg(feature = "presentation")]
+use net_stats::NetStatsPlugin;
+#[cfg(feature = "presentation")]
+use onboarding::OnboardingPlugin;
+#[cfg(feature = "presentation")]
+use overlay::OverlayPlugin;
+#[cfg(feature = "presentation")]
+use page_events::PageEventsPlugin;
+#[cfg(feature = "presentation")]
+use pause::PausePlugin;
+#[cfg(feature = "presentation")]
+use peer_names::PeerNamesPlugin;
+#[cfg(feature = "presentation")]
+use persistence::PersistencePlugin;
+#[cfg(feature = "presentation")]
+use placeholder::PlaceholderPlugin;
+#[cfg(feature = "presentation")]
+use player::PlayerPlugin;
+#[cfg(feature = "presentation")]
+use player_list::PlayerListPlugin;
+#[cfg(feature = "presentation")]
+use presentation_clock::PresentationClockPlugin;
+#[cfg(feature = "presentation")]
+use quick_play::QuickPlayPlugin;
+#[cfg(feature = "presentation")]
+use recent_players::RecentPlayersPlugin;
+#[cfg(feature = "presentation")]
+use reconnect::ReconnectPlugin;
+#[cfg(feature = "presentation")]
+use room::RoomSelectPlugin;
+#[cfg(feature = "presentation")]
+use save::SavePlugin;
+#[cfg(feature = "presentation")]
+use server::ServerPlugin;
+#[cfg(feature = "presentation")]
+use session_events::SessionEventsPlugin;
+use simulation::*;
+#[cfg(feature = "presentation")]
+use sprite_atlas::SpriteAtlasPlugin;
+#[cfg(feature = "presentation")]
+use stats::StatsPlugin;
+#[cfg(feature = "presentation")]
+use status::StatusPlugin;
+#[cfg(feature = "presentation")]
+use tab_visibility::TabVisibilityPlugin;
+#[cfg(feature = "presentation")]
+use touch::TouchPlugin;
+#[cfg(feature = "presentation")]
+use ui::UiPlugin;
+#[cfg(feature = "presentation")]
+use vfx::VfxPlugin;
+#[cfg(feature = "presentation")]
+use vision::VisionPlugin;
+#[cfg(feature = "presentation")]
+use wrap::WrapPlugin;
+
+#[cfg(feature = "presentation")]
+mod accessibility;
+mod achievements;
+#[cfg(feature = "presentation")]
+mod animation;
+#[cfg(feature = "presentation")]
+mod attract;
+mod audio;
+#[cfg(feature = "presentation")]
+mod avatar;
+mod bots;
+mod budget;
+mod bullet;
+#[cfg(feature = "presentation")]
+mod camera;
+mod classes;
 mod components;
+#[cfg(feature = "presentation")]
+mod connection_quality;
+#[cfg(all(debug_assertions, feature = "presentation"))]
+mod console;
+#[cfg(feature = "presentation")]
+mod cooldown_ring;
+#[cfg(feature = "presentation")]
+mod cosmetics;
+#[cfg(feature = "presentation")]
+mod countdown;
+#[cfg(feature = "presentation")]
+mod crosshair;
+#[cfg(feature = "presentation")]
+mod culling;
+mod dash;
+#[cfg(feature = "presentation")]
+mod debug_overlay;
+#[cfg(feature = "presentation")]
+mod emotes;
+#[cfg(feature = "presentation")]
+mod filter;
+mod fixed_point;
+#[cfg(feature = "presentation")]
+mod fixed_timestep;
+#[cfg(feature = "presentation")]
+mod focus;
+#[cfg(feature = "presentation")]
+mod frame_advantage;
+#[cfg(feature = "presentation")]
+mod game;
+mod game_modes;
+#[cfg(feature = "presentation")]
+mod ghost;
+#[cfg(feature = "presentation")]
+mod haptics;
+mod hazards;
+#[cfg(all(feature = "headless", not(feature = "presentation")))]
+mod headless;
+mod hill;
+#[cfg(feature = "presentation")]
+mod history;
+#[cfg(feature = "presentation")]
+mod hit_indicator;
+#[cfg(feature = "presentation")]
+mod hot_seat;
 mod input;
+#[cfg(feature = "presentation")]
+mod juice;
+#[cfg(feature = "presentation")]
+mod key_bindings;
+#[cfg(feature = "presentation")]
+mod kick;
+#[cfg(feature = "presentation")]
+mod launch_config;
+#[cfg(feature = "presentation")]
+mod layers;
+#[cfg(feature = "presentation")]
+mod leave;
+#[cfg(feature = "presentation")]
 mod lobby;
+#[cfg(any(feature = "presentation", test))]
+mod lobby_events;
+mod lobby_settings;
+#[cfg(any(feature = "presentation", test))]
+mod lobby_state;
+#[cfg(all(test, feature = "headless", not(feature = "presentation")))]
+mod loopback;
+#[cfg(feature = "presentation")]
+mod low_power;
+#[cfg(feature = "presentation")]
+mod map_gen;
+mod maps;
+mod match_config;
+#[cfg(feature = "presentation")]
+mod match_end;
+#[cfg(feature = "presentation")]
+mod match_log;
+#[cfg(feature = "presentation")]
+mod match_report;
+#[cfg(feature = "presentation")]
+mod mute;
+#[cfg(feature = "presentation")]
+mod name_tags;
+#[cfg(feature = "presentation")]
+mod net;
+#[cfg(all(debug_assertions, feature = "presentation"))]
+mod net_sim;
+#[cfg(feature = "presentation")]
+mod net_stats;
+#[cfg(feature = "presentation")]
+mod onboarding;
+#[cfg(feature = "presentation")]
+mod overlay;
+#[cfg(feature = "presentation")]
+mod page_events;
+#[cfg(feature = "presentation")]
+mod pause;
+#[cfg(feature = "presentation")]
+mod peer_names;
+#[cfg(feature = "presentation")]
+mod persistence;
+mod physics;
+mod pickups;
+#[cfg(feature = "presentation")]
+mod placeholder;
+mod player;
+#[cfg(feature = "presentation")]
+mod player_list;
+mod portals;
+#[cfg(feature = "presentation")]
+mod presentation_clock;
+#[cfg(feature = "presentation")]
+mod quick_play;
+#[cfg(feature = "presentation")]
+mod recent_players;
+#[cfg(feature = "presentation")]
+mod reconnect;
+mod rng;
+#[cfg(feature = "presentation")]
+mod room;
+mod rounds;
+#[cfg(feature = "presentation")]
+mod sandbox;
+#[cfg(feature = "presentation")]
+mod save;
+#[cfg(feature = "presentation")]
+mod save_format;
+#[cfg(feature = "presentation")]
+mod save_migration;
+#[cfg(feature = "presentation")]
+mod save_storage;
+#[cfg(feature = "presentation")]
+mod server;
+#[cfg(feature = "presentation")]
+mod session_events;
+mod shootdown;
+mod simulation;
+#[cfg(all(feature = "native", feature = "presentation"))]
+mod smoke_test;
+#[cfg(all(debug_assertions, feature = "presentation"))]
+mod snapshot_diff;
+mod spatial;
+mod spawns;
+#[cfg(feature = "presentation")]
+mod sprite_atlas;
+#[cfg(feature = "presentation")]
+mod stats;
+#[cfg(feature = "presentation")]
+mod status;
+#[cfg(feature = "presentation")]
+mod storage;
+#[cfg(feature = "presentation")]
+mod tab_visibility;
+#[cfg(feature = "presentation")]
+mod tilemap;
+#[cfg(feature = "presentation")]
+mod touch;
+#[cfg(feature = "presentation")]
+mod ui;
+mod vfx;
+#[cfg(feature = "presentation")]
+mod vision;
+mod weapons;
+#[cfg(any(feature = "presentation", test))]
+mod wire_format;
+#[cfg(feature = "presentation")]
+mod wrap;
+mod zone;
+
+/// GGRS' default prediction window. Frames this far in the past are never
+/// rolled back.
+const MAX_PREDICTION_FRAMES: u32 = 8;
 
 const F2I: i32 = 2_i32.pow(12);
 const I2F: f32 = 1.0 / F2I as f32;
 
+#[cfg(feature = "presentation")]
 fn main() {
     let mut app = App::new();
 
-    GGRSPlugin::<GgrsConfig>::new()
-        .with_input_system(input)
-        .register_rollback_component::<Position>()
-        .register_rollback_component::<BulletReady>()
-        .register_rollback_component::<MoveDir>()
-        .register_rollback_component::<TabId>()
-        .register_type_dependency::<bool>()
-        .register_type_dependency::<String>()
-        .register_type_dependency::<IVec2>()
-        .register_type_dependency::<i32>()
-        .build(&mut app);
+    ggrs_plugin().with_input_system(input).build(&mut app);
 
     app.add_state::<GameState>()
-        .add_loading_state(
-            LoadingState::new(GameState::AssetLoading).continue_to_state(GameState::Matchmaking),
-        )
-        .add_collection_to_loading_state::<_, ImageAssets>(GameState::AssetLoading)
-        .insert_resource(ClearColor(Color::rgb(0.53, 0.53, 0.53)))
         .add_plugins(DefaultPlugins.set(WindowPlugin {
             primary_window: Some(Window {
                 fit_canvas_to_parent: true,
@@ -58,348 +358,125 @@ fn main() {
             ..default()
         }))
         .add_plugin(EguiPlugin)
-        .insert_resource(ClearColor(Color::rgb(0.53, 0.53, 0.53)))
-        .add_system(setup.in_schedule(OnExit(GameState::AssetLoading)))
-        .add_system(start_matchbox_socket.in_schedule(OnEnter(GameState::Matchmaking)))
-        .add_systems(
-            (
-                insert_player_components,
-                load_snapshot.after(insert_player_components),
-                apply_loaded_components
-                    .after(insert_player_components)
-                    .after(load_snapshot),
-            )
-                .in_schedule(OnEnter(GameState::InGame)),
-        )
-        .add_system(cleanup_session.in_schedule(OnExit(GameState::InGame)))
-        .add_systems((bottom_bar_ui, camera_follow, kill_game).in_set(OnUpdate(GameState::InGame)))
-        .add_systems(
-            (
-                move_players,
-                set_translations_to_positions
-                    .after(move_players)
-                    .after(move_bullet),
-                reload_bullet,
-                fire_bullets.after(move_players).after(reload_bullet),
-                move_bullet.after(move_players).after(fire_bullets),
-                kill_players.after(move_bullet).after(move_players),
-            )
-                .in_schedule(GGRSSchedule),
-        )
+        .add_plugin(GamePlugin)
+        .add_plugin(SimulationPlugin)
+        .add_plugin(NetPlugin)
+        .add_plugin(SavePlugin)
+        .add_plugin(PlayerPlugin)
+        .add_plugin(BulletPlugin)
+        .add_plugin(UiPlugin)
+        .add_plugin(LaunchConfigPlugin)
+        .add_plugin(OnboardingPlugin)
+        .add_plugin(RoomSelectPlugin)
+        .add_plugin(QuickPlayPlugin)
+        .add_plugin(ServerPlugin)
         .add_plugin(LobbyPlugin)
-        .init_resource::<Messages>()
-        .add_system(read_messages.before(kill_game))
-        .run();
-}
-
-fn read_messages(
-    mut messages: ResMut<Messages>,
-    mut socket: Option<ResMut<MatchboxSocket<MultipleChannels>>>,
-) {
-    if let Some(socket) = socket.as_mut() {
-        messages.0.extend(socket.channel(1).receive());
+        .add_plugin(CountdownPlugin)
+        .add_plugin(LeavePlugin)
+        .add_plugin(KickPlugin)
+        .add_plugin(KeyBindingsPlugin)
+        .add_plugin(AccessibilityPlugin)
+        .add_plugin(PausePlugin)
+        .add_plugin(ReconnectPlugin)
+        .add_plugin(SessionEventsPlugin)
+        .add_plugin(FrameAdvantagePlugin)
+        .add_plugin(FixedTimestepPlugin)
+        .add_plugin(TabVisibilityPlugin)
+        .add_plugin(LobbySettingsPlugin)
+        .add_plugin(MapsPlugin)
+        .add_plugin(FilterPlugin)
+        .add_plugin(MutePlugin)
+        .add_plugin(PeerNamesPlugin)
+        .add_plugin(PlayerListPlugin)
+        .add_plugin(RecentPlayersPlugin)
+        .add_plugin(ConnectionQualityPlugin)
+        .add_plugin(PlaceholderPlugin)
+        .add_plugin(HistoryPlugin)
+        .add_plugin(HitIndicatorPlugin)
+        .add_plugin(AudioPlugin)
+        .add_plugin(HapticsPlugin)
+        .add_plugin(FocusPlugin)
+        .add_plugin(InputBufferPlugin)
+        .add_plugin(SpriteAnimationPlugin)
+        .add_plugin(CameraPlugin)
+        .add_plugin(HotSeatPlugin)
+        .add_plugin(CullingPlugin)
+        .add_plugin(CrosshairPlugin)
+        .add_plugin(GhostPlugin)
+        .add_plugin(NameTagsPlugin)
+        .add_plugin(EmotesPlugin)
+        .add_plugin(VfxPlugin)
+        .add_plugin(JuicePlugin)
+        .add_plugin(WrapPlugin)
+        .add_plugin(VisionPlugin)
+        .add_plugin(LayersPlugin)
+        .add_plugin(SpriteAtlasPlugin)
+        .add_plugin(PresentationClockPlugin)
+        .add_plugin(StatsPlugin)
+        .add_plugin(AchievementsPlugin)
+        .add_plugin(CosmeticsPlugin)
+        .add_plugin(OverlayPlugin)
+        .add_plugin(MatchReportPlugin)
+        .add_plugin(MatchEndPlugin)
+        .add_plugin(MatchLogPlugin)
+        .add_plugin(PageEventsPlugin)
+        .add_plugin(TouchPlugin)
+        .add_plugin(AttractPlugin)
+        .add_plugin(DebugOverlayPlugin)
+        .add_plugin(NetStatsPlugin)
+        .add_plugin(LowPowerPlugin)
+        .add_plugin(PersistencePlugin)
+        .add_plugin(StatusPlugin);
+    #[cfg(debug_assertions)]
+    app.add_plugin(net_sim::NetSimPlugin)
+        .add_plugin(snapshot_diff::SnapshotDiffPlugin)
+        .add_plugin(sandbox::SandboxPlugin)
+        .add_plugin(console::ConsolePlugin);
+    #[cfg(feature = "native")]
+    if let Some(smoke_test) = smoke_test::SmokeTestPlugin::from_env() {
+        app.add_plugin(smoke_test);
     }
+    app.run();
 }
 
-#[derive(Resource, Default)]
-struct Messages(VecDeque<(PeerId, Box<[u8]>)>);
-
-fn kill_game(world: &mut World) {
-    let bevy_ggrs::Session::P2PSession(session) = &mut *world
-        .get_resource_mut::<bevy_ggrs::Session<GgrsConfig>>()
-        .unwrap()
-     else {
-        return
-     };
-
-    if !session
-        .events()
-        .any(|e| matches!(e, GGRSEvent::Disconnected { .. }))
-        && world.get_resource::<Messages>().unwrap().0.is_empty()
-    {
-        return;
-    }
-
-    info!("GGRS Disconnect event detected");
-    world
-        .get_resource_mut::<NextState<GameState>>()
-        .unwrap()
-        .set(GameState::Matchmaking);
-
-    if let Ok(mut ready) = world
-        .query_filtered::<&mut IsReady, With<IsLocal>>()
-        .get_single_mut(world)
-    {
-        ready.0 = false;
-    }
-
-    let snapshot = world
-        .get_resource::<GGRSStage<GgrsConfig>>()
-        .unwrap()
-        .get_serialized_snapshot(world);
-    info!("Saving world snapshot: {snapshot}");
-    world.insert_resource(GameSaveData {
-        snapshot,
-        timestamp: Utc::now(),
-    });
-}
-
-fn load_snapshot(world: &mut World) {
-    if let Some(snapshot) = &world
-        .query_filtered::<Option<&GameSaveData>, With<IsLocal>>()
-        .get_single(world)
-        .expect("no local player found")
-        .cloned()
-    {
-        info!(
-            "Loading world snapshot from {}: {}",
-            snapshot.timestamp, snapshot.snapshot
-        );
-        world.resource_scope(|world, stage: Mut<GGRSStage<GgrsConfig>>| {
-            stage.load_serialized_snapshot(world, &snapshot.snapshot);
-        });
-    }
-}
-
-fn cleanup_session(mut commands: Commands, rollback_entities: Query<Entity, With<Rollback>>) {
-    commands.remove_resource::<bevy_ggrs::Session<GgrsConfig>>();
-    for entity in rollback_entities.iter() {
-        commands.entity(entity).despawn();
-    }
+#[cfg(not(feature = "presentation"))]
+fn main() {
+    headless::run();
 }
 
+/// Default map size. Matches use the size in [`LobbySettings`].
 const MAP_SIZE_RI: i32 = 41;
 const MAP_SIZE_SI: i32 = 41 * F2I;
-const GRID_WIDTH_RF: f32 = 0.05;
-
-fn setup(mut commands: Commands) {
-    let mut camera_bundle = Camera2dBundle::default();
-    camera_bundle.projection.scaling_mode = ScalingMode::FixedVertical(10.);
-    commands.spawn(camera_bundle);
-
-    // Horizontal lines
-    for i in 0..=MAP_SIZE_RI {
-        commands.spawn(SpriteBundle {
-            transform: Transform::from_translation(Vec3::new(
-                0.,
-                i as f32 - MAP_SIZE_RI as f32 / 2.,
-                0.,
-            )),
-            sprite: Sprite {
-                color: Color::rgb(0.27, 0.27, 0.27),
-                custom_size: Some(Vec2::new(MAP_SIZE_RI as f32, GRID_WIDTH_RF)),
-                ..default()
-            },
-            ..default()
-        });
-    }
-
-    // Vertical lines
-    for i in 0..=MAP_SIZE_RI {
-        commands.spawn(SpriteBundle {
-            transform: Transform::from_translation(Vec3::new(
-                i as f32 - MAP_SIZE_RI as f32 / 2.,
-                0.,
-                0.,
-            )),
-            sprite: Sprite {
-                color: Color::rgb(0.27, 0.27, 0.27),
-                custom_size: Some(Vec2::new(GRID_WIDTH_RF, MAP_SIZE_RI as f32)),
-                ..default()
-            },
-            ..default()
-        });
-    }
-}
-
-const PLAYER_RADIUS_SI: i32 = 5 * F2I / 10;
-const PLAYER_WIDTH_RF: f32 = PLAYER_RADIUS_SI as f32 * I2F * 2.;
-
-fn insert_player_components(
-    mut commands: Commands,
-    mut rip: ResMut<RollbackIdProvider>,
-    players: Query<(Entity, &Player)>, // This won't find any if loaded from gamestate
-) {
-    for (entity, player) in players.iter() {
-        commands.entity(entity).insert((
-            Rollback::new(rip.next_id()),
-            SpriteBundle {
-                transform: Transform::from_translation(Vec3::new(0., 0., 100.)),
-                sprite: Sprite {
-                    color: Color::rgb(0., 0.47, 1.),
-                    custom_size: Some(Vec2::new(PLAYER_WIDTH_RF, PLAYER_WIDTH_RF)),
-                    ..default()
-                },
-                ..default()
-            },
-            BulletReady(true),
-            MoveDir(-IVec2::new(1, 0) * DIRECTION_SCALE),
-            Position(IVec2::new((-8 + 2 * player.handle as i32) * F2I, 0)),
-            Radius(PLAYER_RADIUS_SI),
-        ));
-    }
-}
-
-fn apply_loaded_components(
-    mut commands: Commands,
-    new_players: Query<(Entity, &TabId), With<Player>>,
-    loaded_players: Query<(Entity, &TabId, &Position, &MoveDir, &BulletReady), Without<Player>>,
-) {
-    for (new_entity, new_id) in new_players.iter() {
-        for (_loaded_entity, loaded_id, loaded_transform, move_dir, bullet_ready) in
-            loaded_players.iter()
-        {
-            if new_id.0 == loaded_id.0 {
-                commands.entity(new_entity).insert((
-                    *loaded_transform,
-                    *move_dir,
-                    BulletReady(bullet_ready.0),
-                ));
-                break;
-            }
-        }
-    }
-    for (entity, ..) in loaded_players.iter() {
-        commands.entity(entity).despawn();
-    }
-}
-
-const PLAYER_MOVE_SPEED_SI: i32 = (13 * F2I) / 100;
-
-fn move_players(
-    inputs: Res<PlayerInputs<GgrsConfig>>,
-    mut player_query: Query<(&mut Position, &mut MoveDir, &Player)>,
-) {
-    for (mut position, mut move_dir, player) in player_query.iter_mut() {
-        let (input, _) = inputs[player.handle];
-        let direction = direction(input);
-
-        if direction == IVec2::ZERO {
-            continue;
-        }
-        move_dir.0 = direction;
-        let move_delta = (direction * PLAYER_MOVE_SPEED_SI) / DIRECTION_SCALE;
-
-        let old_pos = position.0;
-        let width = (MAP_SIZE_SI + 1) / 2;
-        let limit = IVec2::splat(width);
-
-        let new_pos = (old_pos + move_delta).clamp(-limit, limit);
-
-        position.0.x = new_pos.x;
-        position.0.y = new_pos.y;
-    }
-}
-
-fn set_translations_to_positions(mut entities: Query<(&mut Transform, &Position)>) {
-    for (mut transform, position) in entities.iter_mut() {
-        transform.translation.x = position.0.x as f32 * I2F;
-        transform.translation.y = position.0.y as f32 * I2F;
-    }
-}
-
-fn bottom_bar_ui(
-    mut contexts: EguiContexts,
-    mut players: Query<(&TabId, &UserInfo), With<IsLocal>>,
-) {
-    let (TabId(tab_id), UserInfo { name }) = players.single_mut();
-    TopBottomPanel::bottom("bottom_panel").show(contexts.ctx_mut(), |ui| {
-        ui.horizontal(|ui| {
-            ui.label(format!("Name: {name}"));
-            ui.with_layout(Layout::right_to_left(Align::Max), |ui| {
-                ui.label(format!("ID: {tab_id}"));
-            });
-        });
-    });
-}
-
-#[derive(Serialize, Deserialize, Debug, Clone)]
-enum P2PMessage {
-    TabId(TabId),
-    Ready(bool),
-    UserInfo(UserInfo),
-    GameSave(Option<GameSaveData>),
-}
-
-fn start_matchbox_socket(mut commands: Commands) {
-    // let room_url = "ws://127.0.0.1:3536/web_ghost";
-    let room_url = "wss://areyougoingserver.solve.social/web_ghost";
-    info!("connecting to matchbox server: {:?}", room_url);
-    commands.insert_resource(MatchboxSocket::from(
-        WebRtcSocketBuilder::new(room_url)
-            .add_channel(ChannelConfig::ggrs())
-            .add_reliable_channel()
-            .build(),
-    ));
-}
-
-impl Default for UserInfo {
-    fn default() -> Self {
-        Self {
-            name: "New User".to_string(),
-        }
-    }
-}
-
-#[derive(Resource, Default)]
-struct HandleMapping(HashMap<PeerId, usize>);
 
 #[derive(Debug)]
 struct GgrsConfig;
 
 impl ggrs::Config for GgrsConfig {
-    // 4-directions + fire fits easily in a single byte
-    type Input = u8;
+    // 4-directions + fire + aim angle
+    type Input = PlayerInput;
     type State = u8;
     // Matchbox' WebRtcSocket addresses are called `PeerId`s
     type Address = PeerId;
 }
 
+#[cfg(feature = "presentation")]
 #[derive(Resource)]
 struct LocalPlayerHandle(usize);
 
-fn camera_follow(
-    player_handle: Option<Res<LocalPlayerHandle>>,
-    player_query: Query<(&Player, &Transform)>,
-    mut camera_query: Query<&mut Transform, (With<Camera>, Without<Player>)>,
-) {
-    let player_handle = match player_handle {
-        Some(handle) => handle.0,
-        None => return, // Session hasn't started yet
-    };
-    for (player, player_transform) in player_query.iter() {
-        if player.handle != player_handle {
-            continue;
-        }
-
-        let pos = player_transform.translation;
-
-        for mut transform in camera_query.iter_mut() {
-            transform.translation.x = pos.x;
-            transform.translation.y = pos.y;
-        }
-    }
-}
-
-#[derive(AssetCollection, Resource)]
-struct ImageAssets {
-    #[asset(path = "bullet.png")]
-    bullet: Handle<Image>,
-}
-
 #[derive(States, Clone, Eq, PartialEq, Debug, Hash, Default)]
 enum GameState {
     #[default]
     AssetLoading,
+    RoomSelect,
     Matchmaking,
     InGame,
+    MatchEnd,
 }
 
-const BULLET_RADIUS_SI: i32 = 5 * F2I / 100;
-
 pub trait IVec2Ext {
     fn i2f(self) -> Vec2;
     fn norm_sq(self) -> Option<i32>;
+    fn norm_sq_wide(self) -> i64;
     fn norm(self) -> Option<i32>;
     fn normalize_or_zero(self) -> Self;
     fn normalize_or_zero_at_scale(self, scale: i32) -> Self;
@@ -414,9 +491,14 @@ impl IVec2Ext for IVec2 {
             .checked_mul(self.x)?
             .checked_add(self.y.checked_mul(self.y)?)
     }
+    // Squares of map-sized coordinates don't fit in an i32, so do the sum in i64
+    fn norm_sq_wide(self) -> i64 {
+        let (x, y) = (self.x as i64, self.y as i64);
+        x * x + y * y
+    }
     fn norm(self) -> Option<i32> {
         use num_integer::Roots;
-        self.norm_sq().map(|sq| sq.sqrt())
+        i32::try_from(self.norm_sq_wide().sqrt()).ok()
     }
     fn normalize_or_zero(self) -> Self {
         self.normalize_or_zero_at_scale(1)
@@ -426,85 +508,66 @@ impl IVec2Ext for IVec2 {
         if self == Self::ZERO {
             self
         } else {
-            let scaled = self * scale;
-            let scaled_norm = self.norm().unwrap();
-            Self::new(scaled.x / scaled_norm, scaled.y / scaled_norm)
+            let scaled_norm = self.norm().unwrap() as i64;
+            let (x, y) = (self.x as i64 * scale as i64, self.y as i64 * scale as i64);
+            Self::new((x / scaled_norm) as i32, (y / scaled_norm) as i32)
         }
     }
 }
 
-fn fire_bullets(
-    mut commands: Commands,
-    inputs: Res<PlayerInputs<GgrsConfig>>,
-    images: Res<ImageAssets>,
-    mut player_query: Query<(&Position, &Player, &mut BulletReady, &MoveDir, &Radius)>,
-    mut rip: ResMut<RollbackIdProvider>,
-) {
-    const BULLET_WIDTH_RF: f32 = (BULLET_RADIUS_SI * 2) as f32 * I2F;
-    for (player_transform, player, mut bullet_ready, player_move_dir, player_radius) in
-        player_query.iter_mut()
-    {
-        let (input, _) = inputs[player.handle];
-        if fire(input) && bullet_ready.0 {
-            let pos = player_transform.0
-                + (player_move_dir.0 * (BULLET_RADIUS_SI + player_radius.0)) / DIRECTION_SCALE;
-            commands.spawn((
-                Bullet,
-                *player_move_dir,
-                SpriteBundle {
-                    transform: Transform::from_translation(pos.i2f().extend(200.)).with_rotation(
-                        Quat::from_rotation_arc_2d(Vec2::X, player_move_dir.0.i2f().normalize()),
-                    ),
-                    texture: images.bullet.clone(),
-                    sprite: Sprite {
-                        custom_size: Some(Vec2::new(BULLET_WIDTH_RF * 3., BULLET_WIDTH_RF)),
-                        ..default()
-                    },
-                    ..default()
-                },
-                Rollback::new(rip.next_id()),
-                Position(pos),
-                Radius(BULLET_RADIUS_SI),
-            ));
-            bullet_ready.0 = false;
-        }
-    }
+/// Bits of headroom left before `value` overflows an i32.
+pub fn headroom_bits(value: i32) -> u32 {
+    value.unsigned_abs().leading_zeros().saturating_sub(1)
 }
 
-fn move_bullet(mut query: Query<(&mut Position, &MoveDir), With<Bullet>>) {
-    const BULLET_SPEED_SI: i32 = (35 * F2I) / 100;
-    for (mut position, dir) in query.iter_mut() {
-        position.0 += (dir.0 * BULLET_SPEED_SI) / DIRECTION_SCALE;
-    }
+/// Headroom every simulated position must keep so that multiplying it by
+/// [`DIRECTION_SCALE`] (or summing it with a similar value) can't overflow.
+const MIN_POSITION_HEADROOM_BITS: u32 = 8;
+
+/// Fires in dev builds when a simulated position gets close to the i32 limit.
+fn debug_assert_headroom(position: IVec2, what: &str) {
+    debug_assert!(
+        headroom_bits(position.x) >= MIN_POSITION_HEADROOM_BITS
+            && headroom_bits(position.y) >= MIN_POSITION_HEADROOM_BITS,
+        "{what} position {position} is approaching the i32 limit"
+    );
 }
 
-#[derive(Component, Reflect, Default)]
-pub struct BulletReady(pub bool);
+// Browser storage only exists in a page, not in a worker or node
+#[cfg(all(test, target_arch = "wasm32"))]
+wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
 
-fn reload_bullet(
-    inputs: Res<PlayerInputs<GgrsConfig>>,
-    mut query: Query<(&mut BulletReady, &Player)>,
-) {
-    for (mut can_fire, player) in query.iter_mut() {
-        let (input, _) = inputs[player.handle];
-        if !fire(input) {
-            can_fire.0 = true;
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_keeps_direction_at_scale() {
+        assert_eq!(IVec2::ZERO.normalize_or_zero_at_scale(100), IVec2::ZERO);
+        assert_eq!(
+            IVec2::new(3, 4).normalize_or_zero_at_scale(100),
+            IVec2::new(60, 80)
+        );
+        assert_eq!(IVec2::new(0, -7).normalize_or_zero(), IVec2::NEG_Y);
+        // Map-sized vectors square past the i32 limit
+        assert_eq!(
+            IVec2::new(40 * MAP_SIZE_SI, -30 * MAP_SIZE_SI).normalize_or_zero_at_scale(100),
+            IVec2::new(80, -60)
+        );
     }
-}
 
-fn kill_players(
-    mut commands: Commands,
-    player_query: Query<(Entity, &Position, &Radius), (With<Player>, Without<Bullet>)>,
-    bullet_query: Query<(&Position, &Radius), With<Bullet>>,
-) {
-    for (player, player_transform, player_radius) in player_query.iter() {
-        for (bullet_transform, bullet_radius) in bullet_query.iter() {
-            if let Some(distance) = (player_transform.0 - bullet_transform.0).norm() {
-                if distance < player_radius.0 + bullet_radius.0 {
-                    commands.entity(player).remove::<SpriteBundle>();
-                }
-            }
-        }
+    #[test]
+    fn norm_of_map_sized_vectors() {
+        let v = IVec2::new(3 * MAP_SIZE_SI, 4 * MAP_SIZE_SI);
+        assert_eq!(v.norm_sq(), None);
+        assert_eq!(v.norm(), Some(5 * MAP_SIZE_SI));
+    }
+
+    #[test]
+    fn headroom_counts_bits_below_the_sign() {
+        assert_eq!(headroom_bits(0), 31);
+        assert_eq!(headroom_bits(-1), 30);
+        assert_eq!(headroom_bits(i32::MAX), 0);
+        assert_eq!(headroom_bits(i32::MIN + 1), 0);
     }
 }
@@ -0,0 +1,207 @@
+use crate::maps::{MapAsset, WallRect};
+use bevy::prelude::*;
+
+/// Name of the map built by [`generate`] from the lobby's `map_seed`, instead
+/// of loaded from an asset
+pub const GENERATED_MAP: &str = "Generated";
+
+/// Side of a noise cell in world units. Walls are made of whole cells.
+const CELL: i32 = 2;
+/// Cells between the lattice points of the coarse noise, which shapes the
+/// blobs of wall
+const FEATURE_CELLS: i32 = 4;
+/// Noise above this, out of 255, is wall. Higher values give fewer walls.
+const WALL_THRESHOLD: u32 = 170;
+/// Cells kept open around the middle, e.g. for the hill, and around spawns
+const CLEAR_CELLS: i32 = 1;
+const SPAWNS: usize = 8;
+
+/// Scrambles `values` into one well-mixed number, the same on every peer
+pub fn mix(seed: u32, values: &[i32]) -> u32 {
+    let mut hash = seed ^ 0x9e37_79b9;
+    for value in values {
+        hash = (hash ^ *value as u32).wrapping_mul(0x85eb_ca6b);
+        hash ^= hash >> 13;
+        hash = hash.wrapping_mul(0xc2b2_ae35);
+        hash ^= hash >> 16;
+    }
+    hash
+}
+
+/// Value noise at `cell`, out of 255: random values at lattice points every
+/// `spacing` cells, blended bilinearly in between
+fn value_noise(seed: u32, cell: IVec2, spacing: i32) -> u32 {
+    let lattice = IVec2::new(cell.x.div_euclid(spacing), cell.y.div_euclid(spacing));
+    let fraction = IVec2::new(cell.x.rem_euclid(spacing), cell.y.rem_euclid(spacing));
+    let corner = |dx: i32, dy: i32| mix(seed, &[lattice.x + dx, lattice.y + dy]) & 0xff;
+    let (fx, fy) = (fraction.x as u32, fraction.y as u32);
+    let (gx, gy) = ((spacing - fraction.x) as u32, (spacing - fraction.y) as u32);
+    let sum = corner(0, 0) * gx * gy
+        + corner(1, 0) * fx * gy
+        + corner(0, 1) * gx * fy
+        + corner(1, 1) * fx * fy;
+    sum / (spacing * spacing) as u32
+}
+
+/// Coarse noise for the shape of the walls, with a finer layer for rougher
+/// edges
+fn noise(seed: u32, cell: IVec2) -> u32 {
+    let coarse = value_noise(seed, cell, FEATURE_CELLS);
+    let fine = value_noise(seed.wrapping_add(1), cell, FEATURE_CELLS / 2);
+    (coarse * 2 + fine) / 3
+}
+
+/// Which cells of a square grid are wall, row by row
+struct Grid {
+    cells: i32,
+    wall: Vec<bool>,
+}
+
+impl Grid {
+    fn index(&self, cell: IVec2) -> Option<usize> {
+        let inside = cell.cmpge(IVec2::ZERO).all() && cell.cmplt(IVec2::splat(self.cells)).all();
+        inside.then_some((cell.y * self.cells + cell.x) as usize)
+    }
+
+    fn is_wall(&self, cell: IVec2) -> bool {
+        self.index(cell).map_or(false, |index| self.wall[index])
+    }
+
+    fn set(&mut self, cell: IVec2, wall: bool) {
+        if let Some(index) = self.index(cell) {
+            self.wall[index] = wall;
+        }
+    }
+
+    fn clear_around(&mut self, center: IVec2, radius: i32) {
+        for y in -radius..=radius {
+            for x in -radius..=radius {
+                self.set(center + IVec2::new(x, y), false);
+            }
+        }
+    }
+
+    /// Opens an L-shaped path from `from` to `to`, across first
+    fn carve(&mut self, from: IVec2, to: IVec2) {
+        let step = (to - from).signum();
+        let mut cell = from;
+        while cell.x != to.x {
+            self.set(cell, false);
+            cell.x += step.x;
+        }
+        while cell.y != to.y {
+            self.set(cell, false);
+            cell.y += step.y;
+        }
+        self.set(cell, false);
+    }
+
+    /// Open cells reachable from `start`
+    fn reachable(&self, start: IVec2) -> Vec<bool> {
+        let mut reached = vec![false; self.wall.len()];
+        let mut stack = vec![start];
+        while let Some(cell) = stack.pop() {
+            let Some(index) = self.index(cell) else {
+                continue;
+            };
+            if reached[index] || self.wall[index] {
+                continue;
+            }
+            reached[index] = true;
+            for step in [IVec2::X, IVec2::NEG_X, IVec2::Y, IVec2::NEG_Y] {
+                stack.push(cell + step);
+            }
+        }
+        reached
+    }
+}
+
+/// The map grown from `seed` at `size` world units across. Walls come from
+/// integer value noise, so every peer builds the same layout from the host's
+/// seed without a map file, whatever its platform. Spawns sit on a ring
+/// around an open middle, with a path carved to each, and pockets the middle
+/// can't reach are filled in.
+pub fn generate(seed: u32, size: i32) -> MapAsset {
+    // An odd number of cells puts one right in the middle of the map
+    let cells = size / CELL;
+    let cells = if cells % 2 == 0 { cells - 1 } else { cells }.max(1);
+    let origin = -cells * CELL / 2;
+    let to_world = |cell: IVec2| IVec2::splat(origin) + cell * CELL;
+    let center = IVec2::splat(cells / 2);
+
+    let mut grid = Grid {
+        cells,
+        wall: (0..cells * cells)
+            .map(|index| noise(seed, IVec2::new(index % cells, index / cells)) > WALL_THRESHOLD)
+            .collect(),
+    };
+
+    // Spawns on a ring two thirds of the way out, starting from a side picked
+    // by the seed so layouts don't always put player 0 in the same place
+    let ring = cells / 3;
+    let diagonal = ring * 7 / 10;
+    let ring_cells = [
+        IVec2::new(ring, 0),
+        IVec2::new(diagonal, diagonal),
+        IVec2::new(0, ring),
+        IVec2::new(-diagonal, diagonal),
+        IVec2::new(-ring, 0),
+        IVec2::new(-diagonal, -diagonal),
+        IVec2::new(0, -ring),
+        IVec2::new(diagonal, -diagonal),
+    ];
+    let first = mix(seed, &[cells]) as usize % SPAWNS;
+    // Opposite spawns take turns, so small matches start apart
+    let spawn_cells =
+        [0, 4, 2, 6, 1, 5, 3, 7].map(|turn| center + ring_cells[(first + turn) % SPAWNS]);
+
+    grid.clear_around(center, CLEAR_CELLS);
+    for spawn in spawn_cells {
+        grid.clear_around(spawn, CLEAR_CELLS);
+    }
+    // Every spawn must reach the middle, and no pocket may be sealed off
+    let reached = grid.reachable(center);
+    for spawn in spawn_cells {
+        if grid.index(spawn).map_or(false, |index| !reached[index]) {
+            grid.carve(spawn, center);
+        }
+    }
+    let reached = grid.reachable(center);
+    for (wall, reached) in grid.wall.iter_mut().zip(reached) {
+        *wall |= !reached;
+    }
+
+    // Runs of wall along each row become one wall each
+    let mut walls = Vec::new();
+    for y in 0..grid.cells {
+        let mut x = 0;
+        while x < grid.cells {
+            if !grid.is_wall(IVec2::new(x, y)) {
+                x += 1;
+                continue;
+            }
+            let start = x;
+            while grid.is_wall(IVec2::new(x, y)) {
+                x += 1;
+            }
+            walls.push(WallRect {
+                min: to_world(IVec2::new(start, y)),
+                max: to_world(IVec2::new(x, y + 1)),
+            });
+        }
+    }
+
+    let half_cell = IVec2::splat(CELL / 2);
+    MapAsset {
+        name: GENERATED_MAP.to_string(),
+        size,
+        walls,
+        spawns: spawn_cells
+            .iter()
+            .map(|cell| to_world(*cell) + half_cell)
+            .collect(),
+        pickup_spawners: Vec::new(),
+        portals: Vec::new(),
+        hazards: Vec::new(),
+    }
+}
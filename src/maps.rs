@@ -0,0 +1,397 @@
+#[cfg(feature = "presentation")]
+use crate::map_gen::{self, GENERATED_MAP};
+#[cfg(any(feature = "presentation", test))]
+use crate::F2I;
+use crate::{
+    hazards::HazardPath, input::DIRECTION_SCALE, physics::sweep_circle_aabb,
+    player::spawn_position, portals::PortalPair, IVec2Ext,
+};
+#[cfg(feature = "presentation")]
+use crate::{layers::DrawLayer, lobby_settings::LobbySettings, tilemap::wall_meshes};
+use bevy::prelude::*;
+#[cfg(any(feature = "presentation", test))]
+use bevy::reflect::TypeUuid;
+#[cfg(feature = "presentation")]
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    sprite::MaterialMesh2dBundle,
+    utils::BoxedFuture,
+};
+#[cfg(feature = "presentation")]
+use bevy_asset_loader::prelude::*;
+use serde::Deserialize;
+
+/// Map layouts loaded from `*.map.ron` assets. The host picks one in the
+/// lobby and its name travels with the [`LobbySettings`], so every peer builds
+/// the same [`CurrentMap`] from its own copy of the asset. The host can also
+/// pick a [`GENERATED_MAP`], which every peer grows from the same seed.
+#[cfg(feature = "presentation")]
+pub struct MapsPlugin;
+
+#[cfg(feature = "presentation")]
+impl Plugin for MapsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<MapAsset>()
+            .init_asset_loader::<MapLoader>()
+            .init_resource::<CurrentMap>();
+    }
+}
+
+/// Name of the map without walls, whose size the host can change freely
+pub const OPEN_MAP: &str = "Open";
+#[cfg(feature = "presentation")]
+const WALL_COLOR: Color = Color::rgb(0.25, 0.25, 0.3);
+#[cfg(feature = "presentation")]
+const WALL_EDGE_COLOR: Color = Color::rgb(0.38, 0.38, 0.45);
+/// Width of the lighter outline around walls, in world units
+#[cfg(feature = "presentation")]
+const WALL_EDGE_WIDTH: f32 = 0.12;
+
+#[cfg(feature = "presentation")]
+#[derive(AssetCollection, Resource)]
+pub struct MapAssets {
+    #[asset(
+        paths("maps/open.map.ron", "maps/pillars.map.ron", "maps/crossroads.map.ron"),
+        collection(typed)
+    )]
+    pub maps: Vec<Handle<MapAsset>>,
+}
+
+#[cfg(feature = "presentation")]
+impl MapAssets {
+    pub fn find<'a>(&self, maps: &'a Assets<MapAsset>, name: &str) -> Option<&'a MapAsset> {
+        self.maps
+            .iter()
+            .filter_map(|handle| maps.get(handle))
+            .find(|map| map.name == name)
+    }
+}
+
+/// A map layout. Coordinates are in whole world units with the origin at the
+/// map's center.
+#[cfg(any(feature = "presentation", test))]
+#[derive(Deserialize, TypeUuid, Debug)]
+#[uuid = "0f3a4f1e-8e4c-4a9b-9a55-3c1f6b2d7e10"]
+pub struct MapAsset {
+    /// Shown in the lobby, and how peers refer to the map
+    pub name: String,
+    /// Width and height of the map
+    pub size: i32,
+    #[serde(default)]
+    pub walls: Vec<WallRect>,
+    /// Spawn points, used in order of player handle. Without any, players
+    /// line up across the middle.
+    #[serde(default)]
+    pub spawns: Vec<IVec2>,
+    /// Where pickups may appear. Without any, they appear anywhere.
+    #[serde(default)]
+    pub pickup_spawners: Vec<IVec2>,
+    /// Linked portals, each end taking players to the other
+    #[serde(default)]
+    pub portals: Vec<PortalPair>,
+    /// Paths patrolled by hazards, one hazard each
+    #[serde(default)]
+    pub hazards: Vec<HazardPath>,
+}
+
+#[cfg(feature = "presentation")]
+impl MapAsset {
+    /// Whether the map still works at other sizes than its own
+    pub fn is_resizable(&self) -> bool {
+        self.walls.is_empty()
+            && self.spawns.is_empty()
+            && self.pickup_spawners.is_empty()
+            && self.portals.is_empty()
+            && self.hazards.is_empty()
+    }
+}
+
+/// Axis-aligned wall between two corners
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub struct WallRect {
+    pub min: IVec2,
+    pub max: IVec2,
+}
+
+#[cfg(feature = "presentation")]
+#[derive(Default)]
+struct MapLoader;
+
+#[cfg(feature = "presentation")]
+impl AssetLoader for MapLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let map = ron::de::from_bytes::<MapAsset>(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(map));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["map.ron"]
+    }
+}
+
+/// The layout of the map being played, in simulation units. Derived from the
+/// rollback [`LobbySettings`] when a game starts and constant during it, so it
+/// doesn't need to be rolled back itself.
+#[derive(Resource, Default)]
+pub struct CurrentMap {
+    #[cfg(feature = "presentation")]
+    name: String,
+    /// Seed and size a generated map was grown from
+    #[cfg(feature = "presentation")]
+    generated: Option<(u32, i32)>,
+    walls: Vec<WallRect>,
+    spawns: Vec<IVec2>,
+    pickup_spawners: Vec<IVec2>,
+    portals: Vec<PortalPair>,
+    hazards: Vec<HazardPath>,
+}
+
+impl CurrentMap {
+    #[cfg(any(feature = "presentation", test))]
+    fn from_asset(map: &MapAsset) -> Self {
+        Self {
+            #[cfg(feature = "presentation")]
+            name: map.name.clone(),
+            #[cfg(feature = "presentation")]
+            generated: None,
+            walls: map
+                .walls
+                .iter()
+                .map(|wall| WallRect {
+                    min: wall.min.min(wall.max) * F2I,
+                    max: wall.min.max(wall.max) * F2I,
+                })
+                .collect(),
+            spawns: map.spawns.iter().map(|spawn| *spawn * F2I).collect(),
+            pickup_spawners: map.pickup_spawners.iter().map(|p| *p * F2I).collect(),
+            portals: map
+                .portals
+                .iter()
+                .map(|pair| PortalPair {
+                    a: pair.a * F2I,
+                    b: pair.b * F2I,
+                })
+                .collect(),
+            hazards: map
+                .hazards
+                .iter()
+                .map(|path| HazardPath {
+                    waypoints: path.waypoints.iter().map(|point| *point * F2I).collect(),
+                })
+                .collect(),
+        }
+    }
+
+    pub fn pickup_spawners(&self) -> &[IVec2] {
+        &self.pickup_spawners
+    }
+
+    pub fn portals(&self) -> &[PortalPair] {
+        &self.portals
+    }
+
+    pub fn hazards(&self) -> &[HazardPath] {
+        &self.hazards
+    }
+
+    /// Usual spawn point and facing of `handle`
+    pub fn spawn_position(&self, handle: usize, map_size_si: i32) -> (IVec2, IVec2) {
+        if self.spawns.is_empty() {
+            return spawn_position(handle, map_size_si);
+        }
+        let position = self.spawns[handle % self.spawns.len()];
+        // Face the middle of the map
+        let direction = match (-position).normalize_or_zero_at_scale(DIRECTION_SCALE) {
+            IVec2::ZERO => IVec2::X * DIRECTION_SCALE,
+            direction => direction,
+        };
+        (position, direction)
+    }
+
+    /// Whether a circle at `position` overlaps any wall
+    pub fn blocks(&self, position: IVec2, radius: i32) -> bool {
+        self.walls.iter().any(|wall| {
+            let offset = position - position.clamp(wall.min, wall.max);
+            offset.norm_sq_wide() < radius as i64 * radius as i64
+        })
+    }
+
+    /// When a circle moving from `from` to `to` first touches a wall, see
+    /// [`sweep_circle_aabb`]
+    pub fn sweep(&self, from: IVec2, to: IVec2, radius: i32) -> Option<i64> {
+        self.walls
+            .iter()
+            .filter_map(|wall| sweep_circle_aabb(from, to, radius, wall.min, wall.max))
+            .min()
+    }
+
+    /// Moves a circle at `position` out of every wall it overlaps, along the
+    /// shortest way out. Walls are visited in asset order, so every peer
+    /// resolves overlaps the same way.
+    pub fn push_out_of_walls(&self, mut position: IVec2, radius: i32) -> IVec2 {
+        for wall in &self.walls {
+            let closest = position.clamp(wall.min, wall.max);
+            let offset = position - closest;
+            if offset == IVec2::ZERO {
+                // The center is inside the wall, leave through the nearest side
+                let exits = [
+                    (
+                        position.x - wall.min.x,
+                        IVec2::new(wall.min.x - radius, position.y),
+                    ),
+                    (
+                        wall.max.x - position.x,
+                        IVec2::new(wall.max.x + radius, position.y),
+                    ),
+                    (
+                        position.y - wall.min.y,
+                        IVec2::new(position.x, wall.min.y - radius),
+                    ),
+                    (
+                        wall.max.y - position.y,
+                        IVec2::new(position.x, wall.max.y + radius),
+                    ),
+                ];
+                position = exits.iter().min_by_key(|(depth, _)| *depth).unwrap().1;
+            } else if offset.norm_sq_wide() < radius as i64 * radius as i64 {
+                let distance = offset.norm().unwrap_or(radius).max(1);
+                position = closest + offset * radius / distance;
+            }
+        }
+        position
+    }
+}
+
+/// Wall meshes spawned from the [`CurrentMap`]
+#[cfg(feature = "presentation")]
+#[derive(Component)]
+pub struct MapWall;
+
+/// Makes `settings.map` the [`CurrentMap`], unless it already is. Generated
+/// maps are grown again whenever their seed or size changes. Returns whether
+/// the map changed.
+#[cfg(feature = "presentation")]
+pub fn select_map(
+    current: &mut CurrentMap,
+    settings: &LobbySettings,
+    map_assets: &MapAssets,
+    maps: &Assets<MapAsset>,
+) -> bool {
+    let generated =
+        (settings.map == GENERATED_MAP).then_some((settings.map_seed, settings.map_size));
+    if current.name == settings.map && current.generated == generated {
+        return false;
+    }
+    *current = match (generated, map_assets.find(maps, &settings.map)) {
+        (Some((seed, size)), _) => CurrentMap {
+            generated,
+            ..CurrentMap::from_asset(&map_gen::generate(seed, size))
+        },
+        (None, Some(map)) => CurrentMap::from_asset(map),
+        (None, None) => {
+            warn!("Unknown map {:?}, playing without walls", settings.map);
+            CurrentMap {
+                name: settings.map.clone(),
+                ..default()
+            }
+        }
+    };
+    info!("Loaded map {:?}", current.name);
+    true
+}
+
+/// Selects `settings.map` with [`select_map`] and spawns its walls, unless
+/// they're already up. All walls are drawn as one fill mesh and one edge mesh.
+#[cfg(feature = "presentation")]
+pub fn apply_map(
+    commands: &mut Commands,
+    current: &mut CurrentMap,
+    walls: &Query<Entity, With<MapWall>>,
+    settings: &LobbySettings,
+    map_assets: &MapAssets,
+    maps: &Assets<MapAsset>,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<ColorMaterial>,
+) {
+    if !select_map(current, settings, map_assets, maps) && !walls.is_empty() {
+        return;
+    }
+    for entity in walls.iter() {
+        commands.entity(entity).despawn();
+    }
+    let (fill, edges) = wall_meshes(&current.walls, WALL_EDGE_WIDTH);
+    for (mesh, color) in [(fill, WALL_COLOR), (edges, WALL_EDGE_COLOR)] {
+        commands.spawn((
+            MapWall,
+            DrawLayer::Walls,
+            MaterialMesh2dBundle {
+                mesh: meshes.add(mesh).into(),
+                material: materials.add(ColorMaterial::from(color)),
+                ..default()
+            },
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MAP_SIZE_SI;
+
+    fn map_with_spawns(spawns: Vec<IVec2>) -> CurrentMap {
+        CurrentMap::from_asset(&MapAsset {
+            name: "test".to_string(),
+            size: 41,
+            walls: Vec::new(),
+            spawns,
+            pickup_spawners: Vec::new(),
+            portals: Vec::new(),
+            hazards: Vec::new(),
+        })
+    }
+
+    #[test]
+    fn spawn_points_face_the_middle() {
+        let map = map_with_spawns(vec![IVec2::new(-10, 0), IVec2::new(0, 10), IVec2::ZERO]);
+        assert_eq!(
+            map.spawn_position(0, MAP_SIZE_SI),
+            (IVec2::new(-10 * F2I, 0), IVec2::X * DIRECTION_SCALE)
+        );
+        assert_eq!(
+            map.spawn_position(1, MAP_SIZE_SI),
+            (IVec2::new(0, 10 * F2I), IVec2::NEG_Y * DIRECTION_SCALE)
+        );
+        // Nowhere to face from the middle itself
+        assert_eq!(
+            map.spawn_position(2, MAP_SIZE_SI),
+            (IVec2::ZERO, IVec2::X * DIRECTION_SCALE)
+        );
+    }
+
+    #[test]
+    fn spawn_points_are_reused_past_the_last() {
+        let map = map_with_spawns(vec![IVec2::new(-10, 0), IVec2::new(10, 0)]);
+        assert_eq!(
+            map.spawn_position(3, MAP_SIZE_SI),
+            map.spawn_position(1, MAP_SIZE_SI)
+        );
+    }
+
+    #[test]
+    fn maps_without_spawn_points_line_players_up() {
+        let map = map_with_spawns(Vec::new());
+        for handle in 0..4 {
+            assert_eq!(
+                map.spawn_position(handle, MAP_SIZE_SI),
+                spawn_position(handle, MAP_SIZE_SI)
+            );
+        }
+    }
+}
@@ -0,0 +1,134 @@
+#[cfg(feature = "presentation")]
+use crate::game_modes::GameMode;
+use crate::{
+    classes::PlayerClass,
+    dash::{DASH_COOLDOWN_FRAMES, DASH_FRAMES, DASH_SPEED_PERCENT},
+    pickups::{RAPID_FIRE_FRAMES, SHIELD_FRAMES, SPEED_BOOST_FRAMES},
+    rng::SimRng,
+    weapons::Weapon,
+    BULLET_SPEED_SI, MAP_SIZE_SI, PLAYER_MAX_HEALTH, PLAYER_MOVE_SPEED_SI,
+    SPAWN_INVULNERABILITY_FRAMES,
+};
+use bevy::prelude::*;
+use bevy_matchbox::prelude::PeerId;
+use serde::{Deserialize, Serialize};
+
+/// Rules a match was started with. Saved with the snapshot so a resumed game
+/// can't silently pick up different rules.
+#[derive(Resource, Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct MatchConfig {
+    /// Seed for [`SimRng`], shared by every peer
+    pub seed: u64,
+    /// Id of the [`GameMode`] the match is played in
+    pub mode: String,
+    /// Fingerprint of the gameplay constants this build simulates with
+    pub balance_hash: u64,
+}
+
+impl MatchConfig {
+    /// Config for a new `mode` match between `peers`. Every peer derives the
+    /// same seed because it only depends on the (sorted) peer ids.
+    pub fn new(peers: impl IntoIterator<Item = PeerId>, mode: &str) -> Self {
+        let mut peers = peers.into_iter().collect::<Vec<_>>();
+        peers.sort();
+        let mut hasher = Fnv1a::default();
+        for peer in peers {
+            hasher.write(peer.0.as_bytes());
+        }
+        Self {
+            seed: hasher.finish(),
+            mode: mode.to_string(),
+            balance_hash: balance_hash(),
+        }
+    }
+
+    pub fn rng(&self) -> SimRng {
+        SimRng::from_seed(self.seed)
+    }
+
+    /// Explains why a save made with `self` can't be resumed by this build
+    #[cfg(feature = "presentation")]
+    pub fn incompatibility(&self) -> Option<String> {
+        if GameMode::find(&self.mode).is_none() {
+            Some(format!(
+                "the save uses game mode \"{}\", which this build doesn't have",
+                self.mode
+            ))
+        } else if self.balance_hash != balance_hash() {
+            Some("the save was made with different gameplay balance".to_string())
+        } else {
+            None
+        }
+    }
+}
+
+/// Hashes every constant that changes the outcome of the simulation
+pub fn balance_hash() -> u64 {
+    let mut hasher = Fnv1a::default();
+    for value in [
+        MAP_SIZE_SI as i64,
+        PLAYER_MOVE_SPEED_SI as i64,
+        PLAYER_MAX_HEALTH as i64,
+        BULLET_SPEED_SI as i64,
+        SPAWN_INVULNERABILITY_FRAMES as i64,
+        SPEED_BOOST_FRAMES as i64,
+        RAPID_FIRE_FRAMES as i64,
+        SHIELD_FRAMES as i64,
+        DASH_FRAMES as i64,
+        DASH_COOLDOWN_FRAMES as i64,
+        DASH_SPEED_PERCENT as i64,
+    ] {
+        hasher.write(&value.to_le_bytes());
+    }
+    for class in PlayerClass::ALL {
+        let stats = class.stats();
+        for value in [
+            stats.speed_percent as i64,
+            stats.max_health as i64,
+            stats.bullet_radius_si as i64,
+        ] {
+            hasher.write(&value.to_le_bytes());
+        }
+    }
+    for weapon in Weapon::ALL {
+        let stats = weapon.stats();
+        for value in [
+            stats.recoil_si as i64,
+            stats.slowdown_frames as i64,
+            stats.slowdown_speed_percent as i64,
+            stats.fire_interval_frames as i64,
+            stats.automatic as i64,
+            stats.bullets_per_shot as i64,
+            stats.spread as i64,
+            stats.bullet_speed_percent as i64,
+            stats.damage as i64,
+            stats.lifetime_frames as i64,
+        ] {
+            hasher.write(&value.to_le_bytes());
+        }
+    }
+    hasher.finish()
+}
+
+/// FNV-1a, used instead of std's hasher because its output must never change
+/// between builds or platforms
+pub struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Fnv1a {
+    pub fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.0
+    }
+}
@@ -0,0 +1,224 @@
+use crate::{
+    components::{GameSaveData, IsLocal, IsReady, Player, StartChoice, TabId, UserInfo},
+    filter::WordFilter,
+    lobby_settings::LobbySettings,
+    match_log::{match_log_ui, MatchLog},
+    match_report::MatchRecorder,
+    rng::SimFrame,
+    room::Room,
+    rounds::RoundState,
+    save_storage,
+    storage::{KeyValueStores, StorageArea},
+    GameState,
+};
+use bevy::prelude::*;
+use bevy_egui::{
+    egui::{Align2, CollapsingHeader, Grid, RichText, Window},
+    EguiContexts,
+};
+
+/// Summary screen every peer moves to once a rule ends the match, with the
+/// scores, accuracy and length of the match, and the [`MatchLog`]. From there
+/// players can go for a rematch, which readies them up for a new game with
+/// whoever is still in the room, or go back to the lobby.
+pub struct MatchEndPlugin;
+
+impl Plugin for MatchEndPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(end_match.in_set(OnUpdate(GameState::InGame)))
+            .add_system(match_end_ui.in_set(OnUpdate(GameState::MatchEnd)))
+            .add_system(clear_summary.in_schedule(OnExit(GameState::MatchEnd)))
+            .add_system(
+                ready_for_rematch
+                    .in_set(OnUpdate(GameState::Matchmaking))
+                    .run_if(resource_exists::<Rematch>()),
+            );
+    }
+}
+
+/// How long the match result shows in game before the summary. Far longer
+/// than any rollback, so the result is confirmed by then.
+const MATCH_END_DELAY_SECS: f64 = 3.;
+
+struct SummaryRow {
+    handle: usize,
+    name: String,
+    rounds_won: u32,
+    kills: u32,
+    deaths: u32,
+    shots: u32,
+    hits: u32,
+}
+
+#[derive(Resource)]
+struct MatchSummary {
+    /// Winner's name, `None` for a draw
+    winner: Option<String>,
+    rounds: u32,
+    duration_secs: f64,
+    /// In handle order
+    rows: Vec<SummaryRow>,
+}
+
+/// Present from choosing a rematch until the local player is readied up
+#[derive(Resource)]
+struct Rematch;
+
+fn end_match(
+    mut commands: Commands,
+    time: Res<Time>,
+    round: Res<RoundState>,
+    recorder: Res<MatchRecorder>,
+    frame: Res<SimFrame>,
+    settings: Res<LobbySettings>,
+    room: Res<Room>,
+    stores: Res<KeyValueStores>,
+    players: Query<(&Player, Option<&UserInfo>)>,
+    mut local: Query<(Entity, &TabId, &mut IsReady), With<IsLocal>>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut over_since: Local<Option<f64>>,
+) {
+    if !round.match_over {
+        *over_since = None;
+        return;
+    }
+    let now = time.elapsed_seconds_f64();
+    if now - *over_since.get_or_insert(now) < MATCH_END_DELAY_SECS {
+        return;
+    }
+    *over_since = None;
+
+    let mut players = players.iter().collect::<Vec<_>>();
+    players.sort_by_key(|(player, _)| player.handle);
+    let name = |handle: usize| {
+        players
+            .iter()
+            .find(|(player, _)| player.handle == handle)
+            .and_then(|(_, info)| info.map(|i| i.name.clone()))
+            .unwrap_or_else(|| format!("Player {handle}"))
+    };
+    let rows = players
+        .iter()
+        .map(|(player, _)| {
+            let tally = recorder.tally(player.handle);
+            SummaryRow {
+                handle: player.handle,
+                name: name(player.handle),
+                rounds_won: round.scores.get(player.handle).copied().unwrap_or(0),
+                kills: tally.kills,
+                deaths: tally.deaths,
+                shots: tally.shots,
+                hits: tally.hits,
+            }
+        })
+        .collect();
+    commands.insert_resource(MatchSummary {
+        winner: round.leader().map(name),
+        rounds: round.round + 1,
+        duration_secs: frame.0 as f64 / settings.tick_rate as f64,
+        rows,
+    });
+    // A finished match is never resumed
+    if let Ok((entity, tab_id, mut ready)) = local.get_single_mut() {
+        ready.0 = false;
+        save_storage::remove(stores.area(StorageArea::Local), &room.0, &tab_id.0);
+        commands.entity(entity).remove::<GameSaveData>();
+    }
+    info!("Match over, showing the summary");
+    next_state.set(GameState::MatchEnd);
+}
+
+fn match_end_ui(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    summary: Option<Res<MatchSummary>>,
+    log: Res<MatchLog>,
+    word_filter: Res<WordFilter>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let Some(summary) = summary else {
+        return;
+    };
+    Window::new("Match over")
+        .anchor(Align2::CENTER_CENTER, [0., 0.])
+        .collapsible(false)
+        .resizable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            let headline = match &summary.winner {
+                Some(winner) => format!("{} wins the match!", word_filter.censor(winner)),
+                None => "The match is a draw".to_string(),
+            };
+            ui.label(RichText::new(headline).heading());
+            let secs = summary.duration_secs as u32;
+            ui.label(format!(
+                "{} rounds in {}:{:02}",
+                summary.rounds,
+                secs / 60,
+                secs % 60
+            ));
+            ui.separator();
+            Grid::new("match_summary").striped(true).show(ui, |ui| {
+                for heading in ["Player", "Rounds", "Kills", "Deaths", "Accuracy"] {
+                    ui.label(RichText::new(heading).strong());
+                }
+                ui.end_row();
+                for row in summary.rows.iter() {
+                    ui.label(&*word_filter.censor(&row.name));
+                    ui.label(row.rounds_won.to_string());
+                    ui.label(row.kills.to_string());
+                    ui.label(row.deaths.to_string());
+                    if row.shots > 0 {
+                        ui.label(format!("{}%", row.hits * 100 / row.shots))
+                            .on_hover_text(format!("{} of {} shots hit", row.hits, row.shots));
+                    } else {
+                        ui.weak("-");
+                    }
+                    ui.end_row();
+                }
+            });
+            CollapsingHeader::new(format!("Match log ({} events)", log.events().len())).show(
+                ui,
+                |ui| {
+                    match_log_ui(ui, &log, |handle| {
+                        summary
+                            .rows
+                            .iter()
+                            .find(|row| row.handle == handle)
+                            .map_or_else(
+                                || format!("Player {handle}"),
+                                |row| word_filter.censor(&row.name).into_owned(),
+                            )
+                    });
+                },
+            );
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui
+                    .button("Rematch")
+                    .on_hover_text("Ready up for a new game with everyone still here")
+                    .clicked()
+                {
+                    commands.insert_resource(Rematch);
+                    next_state.set(GameState::Matchmaking);
+                }
+                if ui.button("Back to lobby").clicked() {
+                    next_state.set(GameState::Matchmaking);
+                }
+            });
+        });
+}
+
+fn clear_summary(mut commands: Commands) {
+    commands.remove_resource::<MatchSummary>();
+}
+
+fn ready_for_rematch(
+    mut commands: Commands,
+    mut local_player: Query<(&mut IsReady, &mut StartChoice), With<IsLocal>>,
+) {
+    if let Ok((mut ready, mut choice)) = local_player.get_single_mut() {
+        ready.0 = true;
+        *choice = StartChoice::NewGame;
+        commands.remove_resource::<Rematch>();
+    }
+}
@@ -0,0 +1,161 @@
+use crate::{
+    achievements::{confirm_gameplay_events, ConfirmedGameplayEvent, GameplayEvent},
+    lobby_settings::LobbySettings,
+    GameState,
+};
+use bevy::prelude::*;
+use bevy_egui::egui::{ScrollArea, Ui};
+use serde::Serialize;
+
+/// Everything that happened in the last match, for looking back at it on the
+/// summary screen or exporting it. Built from [`ConfirmedGameplayEvent`]s
+/// only, so a rolled back shot is never logged and a resimulated one is
+/// logged once. The log is kept after the game ends and cleared when the
+/// next one starts.
+pub struct MatchLogPlugin;
+
+impl Plugin for MatchLogPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MatchLog>()
+            .add_system(reset_match_log.in_schedule(OnEnter(GameState::InGame)))
+            .add_system(
+                record_match_log
+                    .after(confirm_gameplay_events)
+                    .in_set(OnUpdate(GameState::InGame)),
+            );
+    }
+}
+
+/// Name of the exported file
+const EXPORT_FILE_NAME: &str = "match_log.ron";
+
+#[derive(Serialize, Clone, Copy, Debug)]
+pub struct LoggedEvent {
+    pub frame: u32,
+    pub secs: f64,
+    pub event: GameplayEvent,
+}
+
+#[derive(Resource, Default)]
+pub struct MatchLog {
+    events: Vec<LoggedEvent>,
+    tick_rate: u32,
+}
+
+impl MatchLog {
+    pub fn events(&self) -> &[LoggedEvent] {
+        &self.events
+    }
+
+    /// The log as pretty RON
+    pub fn export(&self) -> String {
+        ron::ser::to_string_pretty(&self.events, Default::default()).unwrap()
+    }
+}
+
+fn reset_match_log(mut log: ResMut<MatchLog>, settings: Res<LobbySettings>) {
+    *log = MatchLog {
+        events: Vec::new(),
+        tick_rate: settings.tick_rate,
+    };
+}
+
+fn record_match_log(mut log: ResMut<MatchLog>, mut events: EventReader<ConfirmedGameplayEvent>) {
+    let tick_rate = log.tick_rate.max(1);
+    for confirmed in events.iter() {
+        log.events.push(LoggedEvent {
+            frame: confirmed.frame,
+            secs: confirmed.frame as f64 / tick_rate as f64,
+            event: confirmed.event,
+        });
+    }
+}
+
+/// One line describing `event`, with players named by `name`
+fn describe(event: &GameplayEvent, name: impl Fn(usize) -> String) -> String {
+    match *event {
+        GameplayEvent::Shot { shooter } => format!("{} fired", name(shooter)),
+        GameplayEvent::Hit {
+            shooter,
+            victim,
+            damage,
+        } => format!("{} hit {} for {damage}", name(shooter), name(victim)),
+        GameplayEvent::Kill { shooter, victim } if shooter == victim => {
+            format!("{} shot themselves", name(victim))
+        }
+        GameplayEvent::Kill { shooter, victim } => {
+            format!("{} killed {}", name(shooter), name(victim))
+        }
+        GameplayEvent::Death { victim } => format!("{} died", name(victim)),
+        GameplayEvent::Pickup { player, kind } => {
+            format!("{} picked up {kind:?}", name(player))
+        }
+        GameplayEvent::RoundEnded { winner, .. } => match winner {
+            Some(winner) => format!("Round won by {}", name(winner)),
+            None => "Round ended in a draw".to_string(),
+        },
+    }
+}
+
+/// The log as a scrollable timeline, with buttons to copy or download it.
+/// Shots are left out of the timeline since there are so many of them, but
+/// they're in the export.
+pub fn match_log_ui(ui: &mut Ui, log: &MatchLog, name: impl Fn(usize) -> String) {
+    ScrollArea::vertical().max_height(160.).show(ui, |ui| {
+        for logged in log
+            .events()
+            .iter()
+            .filter(|logged| !matches!(logged.event, GameplayEvent::Shot { .. }))
+        {
+            let secs = logged.secs as u32;
+            ui.horizontal(|ui| {
+                ui.weak(format!("{}:{:02}", secs / 60, secs % 60));
+                ui.label(describe(&logged.event, &name));
+            });
+        }
+    });
+    ui.horizontal(|ui| {
+        if ui.button("Copy log").clicked() {
+            ui.output_mut(|output| output.copied_text = log.export());
+        }
+        if ui
+            .button("Download log")
+            .on_hover_text(format!("Saves {EXPORT_FILE_NAME}"))
+            .clicked()
+        {
+            if let Err(error) = save_file(EXPORT_FILE_NAME, &log.export()) {
+                warn!("Couldn't export the match log: {error}");
+            }
+        }
+    });
+}
+
+/// Hands `contents` to the browser as a download
+#[cfg(not(feature = "native"))]
+fn save_file(file_name: &str, contents: &str) -> Result<(), String> {
+    use wasm_bindgen::JsCast;
+
+    let document = web_sys::window()
+        .and_then(|window| window.document())
+        .ok_or("no document")?;
+    let link = document.create_element("a").map_err(|e| format!("{e:?}"))?;
+    let href = format!(
+        "data:text/plain;charset=utf-8,{}",
+        js_sys::encode_uri_component(contents)
+    );
+    link.set_attribute("href", &href)
+        .and_then(|_| link.set_attribute("download", file_name))
+        .map_err(|e| format!("{e:?}"))?;
+    link.dyn_into::<web_sys::HtmlElement>()
+        .map_err(|_| "not an element")?
+        .click();
+    Ok(())
+}
+
+/// Writes `contents` to the working directory
+#[cfg(feature = "native")]
+fn save_file(file_name: &str, contents: &str) -> Result<(), String> {
+    std::fs::write(file_name, contents).map_err(|e| e.to_string())?;
+    info!("Exported {file_name}");
+    Ok(())
+}
@@ -0,0 +1,259 @@
+use crate::{
+    audio::{Sound, SoundEvent},
+    components::{LastHit, Player, UserInfo},
+    lobby_settings::LobbySettings,
+    overlay::OverlaySettings,
+    page_events::dispatch,
+    rng::SimFrame,
+    room::Room,
+    rounds::{reset_rounds, RoundState},
+    GameState,
+};
+use bevy::{prelude::*, utils::HashMap};
+use chrono::Utc;
+use serde::Serialize;
+use std::cell::RefCell;
+use wasm_bindgen::prelude::*;
+
+/// Structured end-of-match results for tournament organizers: per-player
+/// kills, deaths and accuracy plus a timeline of kills and rounds. When the
+/// match ends, or the game is left, the report is sent to the page as a
+/// `webghost:matchreport` event and kept for `webGhostMatchReport()`. Like the
+/// overlay data, nothing is published unless the player shares match data.
+pub struct MatchReportPlugin;
+
+impl Plugin for MatchReportPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MatchRecorder>()
+            .add_system(reset_recorder.in_schedule(OnEnter(GameState::InGame)))
+            .add_systems(
+                (record_hits, record_rounds.after(record_hits)).in_set(OnUpdate(GameState::InGame)),
+            )
+            .add_system(
+                publish_unfinished_report
+                    .before(reset_rounds)
+                    .in_schedule(OnExit(GameState::InGame)),
+            );
+    }
+}
+
+thread_local! {
+    static LATEST_REPORT: RefCell<Option<String>> = RefCell::new(None);
+}
+
+/// Returns the report of the last match as a JSON string, or `undefined` if
+/// none was published yet.
+#[wasm_bindgen(js_name = webGhostMatchReport)]
+pub fn match_report() -> Option<String> {
+    LATEST_REPORT.with(|latest| latest.borrow().clone())
+}
+
+#[derive(Default, Clone, Copy)]
+pub struct PlayerTally {
+    pub kills: u32,
+    pub deaths: u32,
+    pub shots: u32,
+    pub hits: u32,
+    pub rounds_won: u32,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum TimelineEvent {
+    Kill {
+        secs: f64,
+        /// `None` when the shot can't be attributed
+        killer: Option<usize>,
+        victim: usize,
+    },
+    RoundEnd {
+        secs: f64,
+        round: u32,
+        winner: Option<usize>,
+    },
+}
+
+/// Tallies of the running match, by player handle. Fed from the deduplicated
+/// [`SoundEvent`]s, so resimulated frames aren't counted twice.
+#[derive(Resource, Default)]
+pub struct MatchRecorder {
+    tallies: HashMap<usize, PlayerTally>,
+    timeline: Vec<TimelineEvent>,
+    /// Whether the current round's end was recorded
+    round_recorded: bool,
+    published: bool,
+}
+
+impl MatchRecorder {
+    pub fn tally(&self, handle: usize) -> PlayerTally {
+        self.tallies.get(&handle).copied().unwrap_or_default()
+    }
+}
+
+#[derive(Serialize)]
+struct PlayerReport {
+    handle: usize,
+    name: String,
+    kills: u32,
+    deaths: u32,
+    shots: u32,
+    hits: u32,
+    /// Hits per shot, 0 for players who never fired
+    accuracy: f64,
+    rounds_won: u32,
+}
+
+#[derive(Serialize)]
+struct MatchReport<'a> {
+    room: &'a str,
+    mode: &'a str,
+    /// False if the match was left before a rule ended it
+    finished: bool,
+    ended_at: String,
+    rounds: u32,
+    players: Vec<PlayerReport>,
+    timeline: &'a [TimelineEvent],
+}
+
+fn frame_secs(frame: u32, settings: &LobbySettings) -> f64 {
+    frame as f64 / settings.tick_rate as f64
+}
+
+fn reset_recorder(mut recorder: ResMut<MatchRecorder>) {
+    *recorder = MatchRecorder::default();
+}
+
+fn record_hits(
+    mut recorder: ResMut<MatchRecorder>,
+    mut sounds: EventReader<SoundEvent>,
+    players: Query<(&Player, &LastHit)>,
+    settings: Res<LobbySettings>,
+) {
+    for event in sounds.iter() {
+        if event.sound == Sound::Fire {
+            recorder.tallies.entry(event.source).or_default().shots += 1;
+            continue;
+        }
+        // Hit and death sounds belong to the victim, whose last hit names the
+        // shooter if it happened on the same frame
+        let shooter = players
+            .iter()
+            .find(|(player, _)| player.handle == event.source)
+            .map(|(_, last_hit)| *last_hit)
+            .filter(|last_hit| last_hit.frame == event.frame)
+            .map(|last_hit| last_hit.shooter);
+        if let Some(shooter) = shooter {
+            recorder.tallies.entry(shooter).or_default().hits += 1;
+        }
+        if event.sound == Sound::Death {
+            recorder.tallies.entry(event.source).or_default().deaths += 1;
+            let killer = shooter.filter(|shooter| *shooter != event.source);
+            if let Some(killer) = killer {
+                recorder.tallies.entry(killer).or_default().kills += 1;
+            }
+            recorder.timeline.push(TimelineEvent::Kill {
+                secs: frame_secs(event.frame, &settings),
+                killer,
+                victim: event.source,
+            });
+        }
+    }
+}
+
+fn record_rounds(
+    mut recorder: ResMut<MatchRecorder>,
+    round: Res<RoundState>,
+    frame: Res<SimFrame>,
+    settings: Res<LobbySettings>,
+    share: Res<OverlaySettings>,
+    room: Res<Room>,
+    players: Query<(&Player, Option<&UserInfo>)>,
+) {
+    if round.in_progress() {
+        recorder.round_recorded = false;
+        return;
+    }
+    if recorder.round_recorded {
+        return;
+    }
+    recorder.round_recorded = true;
+    let winner = match round.survivors[..] {
+        [winner] => Some(winner),
+        _ => None,
+    };
+    if let Some(winner) = winner {
+        recorder.tallies.entry(winner).or_default().rounds_won += 1;
+    }
+    recorder.timeline.push(TimelineEvent::RoundEnd {
+        secs: frame_secs(frame.0, &settings),
+        round: round.round + 1,
+        winner,
+    });
+    if round.match_over {
+        publish(&mut recorder, &round, &settings, &share, &room, &players);
+    }
+}
+
+fn publish_unfinished_report(
+    mut recorder: ResMut<MatchRecorder>,
+    round: Res<RoundState>,
+    settings: Res<LobbySettings>,
+    share: Res<OverlaySettings>,
+    room: Res<Room>,
+    players: Query<(&Player, Option<&UserInfo>)>,
+) {
+    if !recorder.published && !recorder.timeline.is_empty() {
+        publish(&mut recorder, &round, &settings, &share, &room, &players);
+    }
+}
+
+fn publish(
+    recorder: &mut MatchRecorder,
+    round: &RoundState,
+    settings: &LobbySettings,
+    share: &OverlaySettings,
+    room: &Room,
+    players: &Query<(&Player, Option<&UserInfo>)>,
+) {
+    recorder.published = true;
+    if !share.enabled {
+        return;
+    }
+    let mut player_reports = players
+        .iter()
+        .map(|(player, info)| {
+            let tally = recorder.tally(player.handle);
+            PlayerReport {
+                handle: player.handle,
+                name: info.map(|i| i.name.clone()).unwrap_or_default(),
+                kills: tally.kills,
+                deaths: tally.deaths,
+                shots: tally.shots,
+                hits: tally.hits,
+                accuracy: if tally.shots > 0 {
+                    tally.hits as f64 / tally.shots as f64
+                } else {
+                    0.
+                },
+                rounds_won: tally.rounds_won,
+            }
+        })
+        .collect::<Vec<_>>();
+    player_reports.sort_by_key(|p| p.handle);
+    let report = MatchReport {
+        room: &room.0,
+        mode: &settings.mode,
+        finished: round.match_over,
+        ended_at: Utc::now().to_rfc3339(),
+        rounds: recorder
+            .timeline
+            .iter()
+            .filter(|event| matches!(event, TimelineEvent::RoundEnd { .. }))
+            .count() as u32,
+        players: player_reports,
+        timeline: &recorder.timeline,
+    };
+    dispatch("webghost:matchreport", &report);
+    let json = serde_json::to_string(&report).ok();
+    LATEST_REPORT.with(|latest| *latest.borrow_mut() = json);
+}
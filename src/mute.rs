@@ -0,0 +1,47 @@
+use crate::{
+    components::TabId,
+    persistence::{read_cookie, write_cookie},
+    storage::KeyValueStores,
+};
+use bevy::{prelude::*, utils::HashSet};
+
+/// Players this tab has muted, keyed by [`TabId`] so mutes survive reconnects.
+/// Muting is purely local: it hides the player's chat, emotes and name labels
+/// for us without telling them.
+pub struct MutePlugin;
+
+impl Plugin for MutePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(MutedPlayers::from_cookie())
+            .add_system(save_muted_players);
+    }
+}
+
+#[derive(Resource, Default, Debug)]
+pub struct MutedPlayers(HashSet<String>);
+
+impl MutedPlayers {
+    const COOKIE_KEY: &'static str = "muted_players";
+
+    fn from_cookie() -> Self {
+        read_cookie(Self::COOKIE_KEY).map(Self).unwrap_or_default()
+    }
+
+    pub fn is_muted(&self, tab_id: &TabId) -> bool {
+        self.0.contains(&tab_id.0)
+    }
+
+    pub fn set_muted(&mut self, tab_id: &TabId, muted: bool) {
+        if muted {
+            self.0.insert(tab_id.0.clone());
+        } else {
+            self.0.remove(&tab_id.0);
+        }
+    }
+}
+
+fn save_muted_players(muted: Res<MutedPlayers>, stores: Res<KeyValueStores>) {
+    if muted.is_changed() && !muted.is_added() {
+        write_cookie(&stores, MutedPlayers::COOKIE_KEY, &muted.0);
+    }
+}
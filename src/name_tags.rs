@@ -0,0 +1,76 @@
+use crate::{
+    components::{Health, Player, Position, UserInfo},
+    filter::{display_name, WordFilter},
+    lobby_settings::LobbySettings,
+    player::PLAYER_WIDTH_RF,
+    vision::Vision,
+    GameState,
+};
+use bevy::{prelude::*, transform::TransformSystem};
+use bevy_egui::{
+    egui::{self, Align2, Color32, FontId, LayerId, Order},
+    EguiContexts, EguiSet, EguiSettings,
+};
+
+/// Shows each living player's name above their square. Names are painted
+/// with egui at the player's projected screen position, since the game ships
+/// no font for world-space text. Drawn once transforms are final for the
+/// frame, so tags stay on their players instead of trailing a frame behind.
+pub struct NameTagsPlugin;
+
+impl Plugin for NameTagsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(
+            draw_name_tags
+                .in_base_set(CoreSet::PostUpdate)
+                .after(TransformSystem::TransformPropagate)
+                .before(EguiSet::ProcessOutput)
+                .run_if(in_state(GameState::InGame)),
+        );
+    }
+}
+
+/// Height of the tag's bottom edge above the player's center, clear of the
+/// health bar
+const TAG_OFFSET_RF: f32 = PLAYER_WIDTH_RF * 0.95;
+const TAG_FONT_SIZE: f32 = 13.;
+
+fn draw_name_tags(
+    mut contexts: EguiContexts,
+    egui_settings: Res<EguiSettings>,
+    word_filter: Res<WordFilter>,
+    vision: Res<Vision>,
+    settings: Res<LobbySettings>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    players: Query<(&UserInfo, &Health, &Position, &GlobalTransform), With<Player>>,
+) {
+    let Some((camera, camera_transform)) = cameras.iter().find(|(camera, _)| camera.is_active)
+    else {
+        return;
+    };
+    let Some(viewport_size) = camera.logical_viewport_size() else {
+        return;
+    };
+    let scale = egui_settings.scale_factor as f32;
+    let painter = contexts
+        .ctx_mut()
+        .layer_painter(LayerId::new(Order::Background, egui::Id::new("name_tags")));
+    for (info, health, position, transform) in players.iter() {
+        if health.0 <= 0 || !vision.can_see(position.0, &settings) {
+            continue;
+        }
+        let anchor = transform.translation() + Vec3::Y * TAG_OFFSET_RF;
+        let Some(position) = camera.world_to_viewport(camera_transform, anchor) else {
+            continue;
+        };
+        // Viewport y points up, egui's down
+        let position = egui::pos2(position.x / scale, (viewport_size.y - position.y) / scale);
+        painter.text(
+            position,
+            Align2::CENTER_BOTTOM,
+            display_name(&word_filter, info),
+            FontId::proportional(TAG_FONT_SIZE),
+            Color32::WHITE,
+        );
+    }
+}
@@ -0,0 +1,226 @@
+#[cfg(debug_assertions)]
+use crate::net_sim::{DelayedMessages, SimulatedConditions};
+use crate::{
+    components::{SaveOffer, TabId},
+    lobby::StartDecision,
+    lobby_settings::LobbySettings,
+    lobby_state::LobbyStatePatch,
+    quick_play::QuickPlayMatch,
+    room::Room,
+    save::kill_game,
+    server::{connect_to_room, ConnectionStatus, ServerConfig},
+    wire_format::{decode_tagged, encode_tagged, Bincode, WireFormat},
+    GameState,
+};
+use bevy::{prelude::*, utils::HashMap};
+use bevy_matchbox::prelude::*;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// Messages between peers outside of GGRS. The room's socket is opened on
+/// entering matchmaking, and whatever arrives on its message channel is kept
+/// in [`Messages`] for the lobby, pause and other systems to pick from.
+pub struct NetPlugin;
+
+impl Plugin for NetPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Messages>()
+            .add_system(start_matchbox_socket.in_schedule(OnEnter(GameState::Matchmaking)))
+            .add_system(read_messages.before(kill_game));
+    }
+}
+
+pub fn read_messages(
+    mut messages: ResMut<Messages>,
+    mut socket: Option<ResMut<MatchboxSocket<MultipleChannels>>>,
+    #[cfg(debug_assertions)] net_conditions: Option<Res<SimulatedConditions>>,
+    #[cfg(debug_assertions)] mut delayed: ResMut<DelayedMessages>,
+) {
+    if let Some(socket) = socket.as_mut() {
+        let received = socket.channel(1).receive();
+        // Through the dev network simulator while it's open
+        #[cfg(debug_assertions)]
+        let received = match net_conditions {
+            Some(conditions) => delayed.pass(&conditions, received),
+            None => received,
+        };
+        messages.0.extend(received);
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct Messages(pub VecDeque<(PeerId, Box<[u8]>)>);
+
+/// Removes [`P2PMessage::Leaving`] messages, returning who sent them
+pub fn take_leaving_messages(messages: &mut Messages) -> Vec<PeerId> {
+    let mut leavers = Vec::new();
+    messages.0.retain(|(peer_id, packet)| {
+        let leaving = matches!(P2PMessage::decode(packet), Ok(P2PMessage::Leaving));
+        if leaving {
+            leavers.push(*peer_id);
+        }
+        !leaving
+    });
+    leavers
+}
+
+/// Set once GGRS reports that our checksum disagrees with a peer's
+#[derive(Resource)]
+pub struct DesyncDetected {
+    pub frame: i32,
+    pub local_checksum: u128,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum P2PMessage {
+    /// Kept first so builds can always read each other's version, whatever
+    /// else changed
+    Version {
+        build_hash: String,
+        protocol: u32,
+    },
+    TabId(TabId),
+    /// What changed about the sender's ready state, start choice and
+    /// `UserInfo`, see `lobby_state`
+    LobbyState(LobbyStatePatch),
+    /// Asks for a [`P2PMessage::LobbyState`] with every field, after missing
+    /// a patch
+    RequestLobbyState,
+    /// Describes our save, whose bytes peers can ask for with
+    /// [`P2PMessage::RequestSave`]
+    SaveOffer(SaveOffer),
+    NoGameSave,
+    /// Sent by the lobby host with the hash of the save everyone resumes, out
+    /// of everyone's offers
+    SaveChosen(Option<u64>),
+    /// Asks the peer that offered the save with this hash to send it
+    RequestSave(u64),
+    /// Part of a compressed [`crate::components::GameSaveData`], see `lobby::SaveTransfer`
+    SaveChunk {
+        index: u32,
+        total: u32,
+        bytes: Vec<u8>,
+    },
+    /// Sent by the lobby host only
+    Settings(LobbySettings),
+    /// Sent by the quick play queue's host to the players of each match
+    QuickPlay(QuickPlayMatch),
+    /// Tells a peer who connected mid-game to resume the running match with us
+    JoinRunningGame,
+    /// Sent when leaving a game on purpose, so the others can go on without
+    /// waiting for GGRS to time out
+    Leaving,
+    /// Sent by the host to make everyone throw away their saves, so the
+    /// group can start fresh
+    DiscardSaves,
+    /// Sent by the host to remove the player with this tab from the room
+    Kick(TabId),
+    /// Sent by the lobby host to start the game at this time, or to cancel
+    /// the countdown with `None`
+    StartAt(Option<DateTime<Utc>>),
+    /// Asks everyone to pause the running game, see `pause::PauseVote`
+    PauseRequest,
+    /// Agrees to the pending pause request
+    PauseAck,
+    /// Turns down the pending pause request
+    PauseDecline,
+    /// Agrees to resume the paused game
+    ResumeConfirm,
+    /// Tab ids and names of the others in the room, sent to newcomers, see
+    /// `peer_names::PeerNames`
+    Roster(Vec<(PeerId, TabId, String)>),
+    /// Sent by the lobby host to start without a peer that's holding up the
+    /// game
+    StopWaiting(PeerId),
+    /// Sent by the lobby host after giving up on missing saves
+    StartDecision(StartDecision),
+    /// Index into `emotes::EMOTES` of an emote the sender shows over their
+    /// character
+    Emote(u8),
+    /// Asks for a [`P2PMessage::Pong`] with the sender's clock time, see
+    /// `connection_quality::PeerPings`
+    Ping(f64),
+    /// Echoes the time of a [`P2PMessage::Ping`] back to its sender, with the
+    /// responder's wall clock in milliseconds since the Unix epoch, see
+    /// `connection_quality::PeerPings::clock_offset_ms`
+    Pong {
+        sent: f64,
+        wall_clock_ms: i64,
+    },
+}
+
+impl P2PMessage {
+    /// The message as sent on the socket, in [`crate::wire_format::P2PFormat`]
+    /// after the byte naming it. [`P2PMessage::Version`] keeps the untagged
+    /// bincode layout it had before messages were tagged, so builds from
+    /// either side of that change still read each other's version and can
+    /// tell their players which build to get.
+    pub fn encode(&self) -> Result<Vec<u8>, String> {
+        match self {
+            P2PMessage::Version { .. } => Bincode::encode(self),
+            _ => encode_tagged(self),
+        }
+    }
+
+    /// Reads a message from a peer, whichever format their build sends in
+    pub fn decode(packet: &[u8]) -> Result<Self, String> {
+        if packet.starts_with(&UNTAGGED_VERSION) {
+            Bincode::decode(packet)
+        } else {
+            decode_tagged(packet)
+        }
+    }
+}
+
+/// How an untagged [`P2PMessage::Version`] starts: its variant index as a
+/// little-endian `u32`. No tagged message starts like it, as only the
+/// bincode tag is 0 and the variant after it is never `Version`.
+const UNTAGGED_VERSION: [u8; 4] = [0; 4];
+
+fn start_matchbox_socket(
+    mut commands: Commands,
+    server: Res<ServerConfig>,
+    room: Res<Room>,
+    mut status: ResMut<ConnectionStatus>,
+) {
+    commands.insert_resource(connect_to_room(&server, &room));
+    *status = ConnectionStatus::Disconnected;
+}
+
+#[derive(Resource, Default)]
+struct HandleMapping(HashMap<PeerId, usize>);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn version_keeps_the_untagged_layout() {
+        let version = P2PMessage::Version {
+            build_hash: "abc".to_string(),
+            protocol: 42,
+        };
+        let packet = version.encode().unwrap();
+        assert_eq!(packet, bincode::serialize(&version).unwrap());
+        assert!(matches!(
+            P2PMessage::decode(&packet),
+            Ok(P2PMessage::Version { protocol: 42, .. })
+        ));
+    }
+
+    #[test]
+    fn other_messages_are_tagged() {
+        for message in [
+            P2PMessage::Leaving,
+            P2PMessage::TabId(TabId("tab".to_string())),
+        ] {
+            let packet = message.encode().unwrap();
+            assert!(!packet.starts_with(&UNTAGGED_VERSION));
+            assert_eq!(
+                format!("{:?}", P2PMessage::decode(&packet).unwrap()),
+                format!("{message:?}")
+            );
+        }
+    }
+}
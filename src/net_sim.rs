@@ -0,0 +1,222 @@
+use bevy::{prelude::*, utils::Instant};
+use bevy_egui::{
+    egui::{self, Slider},
+    EguiContexts,
+};
+use bevy_ggrs::ggrs::{Message, NonBlockingSocket};
+use bevy_matchbox::prelude::PeerId;
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// Degrades the connection on purpose so rollbacks, the waiting-on flow and
+/// reconnects can be exercised locally: GGRS packets this peer sends, see
+/// [`SimulatedSocket`], and the messages it receives, see [`DelayedMessages`].
+/// Only compiled into debug builds; toggled with F6.
+pub struct NetSimPlugin;
+
+impl Plugin for NetSimPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<NetSimWindow>()
+            .init_resource::<DelayedMessages>()
+            .add_system(toggle_net_sim_window)
+            .add_system(net_sim_ui.after(toggle_net_sim_window));
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Default, Debug)]
+pub struct NetworkConditions {
+    pub latency_ms: u32,
+    /// Up to this much extra latency, picked per packet
+    pub jitter_ms: u32,
+    pub loss_percent: f32,
+    /// Chance per packet of starting a burst where every packet is lost
+    pub burst_percent: f32,
+    pub burst_length: u32,
+    /// Drops everything, both ways, so GGRS times out the other peers and
+    /// the reconnect flow kicks in
+    pub cut: bool,
+}
+
+impl NetworkConditions {
+    /// How long a packet sent now takes to arrive, jitter included
+    fn delay(&self) -> Duration {
+        let delay_ms = self.latency_ms as f64 + random() * self.jitter_ms as f64;
+        Duration::from_secs_f64(delay_ms / 1000.)
+    }
+}
+
+/// Conditions applied to every session started while this resource exists.
+/// Shared with the running socket, so changes apply immediately.
+#[derive(Resource, Clone, Default)]
+pub struct SimulatedConditions(Arc<Mutex<NetworkConditions>>);
+
+#[derive(Resource, Default)]
+pub struct NetSimWindow {
+    pub open: bool,
+}
+
+fn toggle_net_sim_window(
+    mut commands: Commands,
+    keys: Res<Input<KeyCode>>,
+    mut window: ResMut<NetSimWindow>,
+    conditions: Option<Res<SimulatedConditions>>,
+) {
+    if keys.just_pressed(KeyCode::F6) {
+        window.open = !window.open;
+        if conditions.is_none() {
+            commands.init_resource::<SimulatedConditions>();
+        }
+    }
+}
+
+fn net_sim_ui(
+    mut contexts: EguiContexts,
+    mut window: ResMut<NetSimWindow>,
+    conditions: Option<Res<SimulatedConditions>>,
+) {
+    let Some(conditions) = conditions else {
+        return;
+    };
+    let mut conditions = conditions.0.lock().unwrap();
+    egui::Window::new("Network simulator")
+        .open(&mut window.open)
+        .show(contexts.ctx_mut(), |ui| {
+            let NetworkConditions {
+                latency_ms,
+                jitter_ms,
+                loss_percent,
+                burst_percent,
+                burst_length,
+                cut,
+            } = &mut *conditions;
+            ui.add(Slider::new(latency_ms, 0..=500).text("latency (ms)"));
+            ui.add(Slider::new(jitter_ms, 0..=200).text("jitter (ms)"));
+            ui.add(Slider::new(loss_percent, 0.0..=50.0).text("loss (%)"));
+            ui.add(Slider::new(burst_percent, 0.0..=10.0).text("burst chance (%)"));
+            ui.add(Slider::new(burst_length, 1..=120).text("burst length (packets)"));
+            ui.checkbox(cut, "Cut the connection");
+            if ui.button("Reset").clicked() {
+                *conditions = NetworkConditions::default();
+            }
+            ui.weak(
+                "Applies to game packets this peer sends in games started with the simulator \
+                 open, and to messages it receives while it's open",
+            );
+        });
+}
+
+/// Wraps a GGRS socket, holding back or dropping outgoing packets according
+/// to the shared [`NetworkConditions`], and dropping incoming ones too while
+/// the connection is cut
+pub struct SimulatedSocket<S> {
+    inner: S,
+    conditions: Arc<Mutex<NetworkConditions>>,
+    in_flight: Vec<(Instant, PeerId, Message)>,
+    burst_remaining: u32,
+}
+
+impl<S> SimulatedSocket<S> {
+    pub fn new(inner: S, conditions: &SimulatedConditions) -> Self {
+        Self {
+            inner,
+            conditions: conditions.0.clone(),
+            in_flight: Vec::new(),
+            burst_remaining: 0,
+        }
+    }
+}
+
+impl<S: NonBlockingSocket<PeerId>> SimulatedSocket<S> {
+    fn flush(&mut self) {
+        let now = Instant::now();
+        let (due, pending) = std::mem::take(&mut self.in_flight)
+            .into_iter()
+            .partition::<Vec<_>, _>(|(at, ..)| *at <= now);
+        self.in_flight = pending;
+        for (_, addr, msg) in due {
+            self.inner.send_to(&msg, &addr);
+        }
+    }
+}
+
+/// Uniform random number in `0.0..1.0`. Not the simulation RNG: dropped
+/// packets are supposed to differ between peers.
+#[cfg(not(feature = "native"))]
+fn random() -> f64 {
+    js_sys::Math::random()
+}
+
+/// Each `RandomState` is keyed differently, which is random enough here
+#[cfg(feature = "native")]
+fn random() -> f64 {
+    use std::{
+        collections::hash_map::RandomState,
+        hash::{BuildHasher, Hasher},
+    };
+    let bits = RandomState::new().build_hasher().finish() >> 11;
+    bits as f64 / (1_u64 << 53) as f64
+}
+
+impl<S: NonBlockingSocket<PeerId>> NonBlockingSocket<PeerId> for SimulatedSocket<S> {
+    fn send_to(&mut self, msg: &Message, addr: &PeerId) {
+        let conditions = *self.conditions.lock().unwrap();
+        if conditions.cut {
+            // Nothing held back either, or it would arrive after the cut
+            self.in_flight.clear();
+        } else if self.burst_remaining > 0 {
+            self.burst_remaining -= 1;
+        } else if random() * 100. < conditions.burst_percent as f64 {
+            self.burst_remaining = conditions.burst_length.saturating_sub(1);
+        } else if random() * 100. >= conditions.loss_percent as f64 {
+            let at = Instant::now() + conditions.delay();
+            self.in_flight.push((at, *addr, msg.clone()));
+        }
+        self.flush();
+    }
+
+    fn receive_all_messages(&mut self) -> Vec<(PeerId, Message)> {
+        self.flush();
+        let received = self.inner.receive_all_messages();
+        // Read anyway, so packets from during the cut don't arrive after it
+        if self.conditions.lock().unwrap().cut {
+            return Vec::new();
+        }
+        received
+    }
+}
+
+/// Messages received on the reliable channel while the simulator is open,
+/// held back by its latency and dropped while the connection is cut. Loss
+/// isn't simulated, since the channel resends lost packets, and messages
+/// stay in the order they were sent in, like the channel keeps them.
+#[derive(Resource, Default)]
+pub struct DelayedMessages(VecDeque<(Instant, PeerId, Box<[u8]>)>);
+
+impl DelayedMessages {
+    /// Takes what just arrived, returning what's due by now
+    pub fn pass(
+        &mut self,
+        conditions: &SimulatedConditions,
+        received: Vec<(PeerId, Box<[u8]>)>,
+    ) -> Vec<(PeerId, Box<[u8]>)> {
+        let conditions = *conditions.0.lock().unwrap();
+        if conditions.cut {
+            self.0.clear();
+            return Vec::new();
+        }
+        let now = Instant::now();
+        for (peer_id, packet) in received {
+            let at = now + conditions.delay();
+            let at = self.0.back().map_or(at, |(last, ..)| at.max(*last));
+            self.0.push_back((at, peer_id, packet));
+        }
+        let due = self.0.iter().take_while(|(at, ..)| *at <= now).count();
+        self.0
+            .drain(..due)
+            .map(|(_, peer_id, packet)| (peer_id, packet))
+            .collect()
+    }
+}
@@ -0,0 +1,128 @@
+use crate::{
+    components::{Player, UserInfo},
+    filter::{display_name, WordFilter},
+    launch_config::LaunchConfig,
+    GameState, GgrsConfig, LocalPlayerHandle,
+};
+use bevy::prelude::*;
+use bevy_egui::{
+    egui::{self, Grid},
+    EguiContexts,
+};
+use bevy_ggrs::{GGRSSchedule, Session};
+
+/// Per-peer connection stats for debugging stutter on bad connections.
+/// Toggled with F4, or shown from the start by launch links with
+/// `overlays=net_stats`.
+pub struct NetStatsPlugin;
+
+impl Plugin for NetStatsPlugin {
+    fn build(&self, app: &mut App) {
+        let visible = app.world.resource::<LaunchConfig>().net_stats;
+        app.insert_resource(NetStatsOverlay {
+            visible,
+            ..default()
+        })
+        .add_system(count_simulated_frame.in_schedule(GGRSSchedule))
+        .add_system(finish_frame_count.in_base_set(CoreSet::Last))
+        .add_system(toggle_net_stats)
+        .add_system(
+            net_stats_ui
+                .after(toggle_net_stats)
+                .in_set(OnUpdate(GameState::InGame)),
+        );
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct NetStatsOverlay {
+    pub visible: bool,
+    frames_this_update: u32,
+    /// Frames resimulated during the last update, i.e. every simulated frame
+    /// beyond the first
+    last_rollback_frames: u32,
+    max_rollback_frames: u32,
+}
+
+fn count_simulated_frame(mut overlay: ResMut<NetStatsOverlay>) {
+    overlay.frames_this_update += 1;
+}
+
+fn finish_frame_count(mut overlay: ResMut<NetStatsOverlay>) {
+    overlay.last_rollback_frames = overlay.frames_this_update.saturating_sub(1);
+    overlay.max_rollback_frames = overlay.max_rollback_frames.max(overlay.last_rollback_frames);
+    overlay.frames_this_update = 0;
+}
+
+fn toggle_net_stats(keys: Res<Input<KeyCode>>, mut overlay: ResMut<NetStatsOverlay>) {
+    if keys.just_pressed(KeyCode::F4) {
+        overlay.visible = !overlay.visible;
+        overlay.max_rollback_frames = 0;
+    }
+}
+
+fn net_stats_ui(
+    mut contexts: EguiContexts,
+    overlay: Res<NetStatsOverlay>,
+    session: Option<Res<Session<GgrsConfig>>>,
+    local_handle: Option<Res<LocalPlayerHandle>>,
+    players: Query<(&Player, Option<&UserInfo>)>,
+    word_filter: Res<WordFilter>,
+) {
+    if !overlay.visible {
+        return;
+    }
+    let Some(Session::P2PSession(session)) = session.as_deref() else {
+        return;
+    };
+    let mut remote_players = players
+        .iter()
+        .filter(|(player, _)| local_handle.as_ref().map_or(true, |h| h.0 != player.handle))
+        .collect::<Vec<_>>();
+    remote_players.sort_by_key(|(player, _)| player.handle);
+
+    egui::Window::new("Network")
+        .default_pos([10., 300.])
+        .show(contexts.ctx_mut(), |ui| {
+            Grid::new("net_stats_session").show(ui, |ui| {
+                ui.label("Frames ahead");
+                ui.label(session.frames_ahead().to_string());
+                ui.end_row();
+                ui.label("Rollback frames");
+                ui.label(format!(
+                    "{} (max {})",
+                    overlay.last_rollback_frames, overlay.max_rollback_frames
+                ));
+                ui.end_row();
+            });
+            ui.separator();
+            Grid::new("net_stats_peers").striped(true).show(ui, |ui| {
+                for header in ["Peer", "Ping", "Send queue", "kbps", "Behind (local/remote)"] {
+                    ui.strong(header);
+                }
+                ui.end_row();
+                for (player, info) in remote_players {
+                    let name = info.map_or("?".into(), |i| display_name(&word_filter, i));
+                    ui.label(format!("{}: {name}", player.handle));
+                    match session.network_stats(player.handle) {
+                        Ok(stats) => {
+                            ui.label(format!("{} ms", stats.ping));
+                            ui.label(stats.send_queue_len.to_string());
+                            ui.label(stats.kbps_sent.to_string());
+                            ui.label(format!(
+                                "{} / {}",
+                                stats.local_frames_behind, stats.remote_frames_behind
+                            ));
+                        }
+                        Err(_) => {
+                            ui.label("-");
+                            ui.label("-");
+                            ui.label("-");
+                            ui.label("-");
+                        }
+                    }
+                    ui.end_row();
+                }
+            });
+        });
+}
@@ -0,0 +1,77 @@
+use crate::{
+    components::UserInfo,
+    launch_config::LaunchConfig,
+    lobby::{has_stored_property, store_default_property, MAX_NAME_LENGTH},
+    storage::{KeyValueStores, StorageArea},
+    GameState,
+};
+use bevy::prelude::*;
+use bevy_egui::{
+    egui::{Align2, Button, TextEdit, Window},
+    EguiContexts,
+};
+
+/// Asks first-time visitors for a name and color before they join a room, so
+/// other players never see placeholder identities. Returning visitors reuse
+/// the profile stored in cookies, and launch links giving a name skip this
+/// with the default color.
+pub struct OnboardingPlugin;
+
+impl Plugin for OnboardingPlugin {
+    fn build(&self, app: &mut App) {
+        let stores = KeyValueStores::get();
+        let cookies = stores.area(StorageArea::Cookies);
+        if !has_stored_property::<UserInfo>(cookies) {
+            if let Some(name) = app.world.resource::<LaunchConfig>().name.clone() {
+                store_default_property(cookies, UserInfo { name, ..default() });
+            } else {
+                app.insert_resource(NeedsOnboarding {
+                    profile: UserInfo {
+                        name: String::new(),
+                        ..default()
+                    },
+                });
+            }
+        }
+        app.add_system(onboarding_ui.in_set(OnUpdate(GameState::RoomSelect)));
+    }
+}
+
+/// Present until the first-visit profile has been entered
+#[derive(Resource)]
+pub struct NeedsOnboarding {
+    profile: UserInfo,
+}
+
+fn onboarding_ui(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    onboarding: Option<ResMut<NeedsOnboarding>>,
+    stores: Res<KeyValueStores>,
+) {
+    let Some(mut onboarding) = onboarding else {
+        return;
+    };
+    Window::new("Welcome!")
+        .anchor(Align2::CENTER_CENTER, [0., 0.])
+        .collapsible(false)
+        .resizable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.label("Pick a name and color before joining.");
+            let UserInfo { name, color, .. } = &mut onboarding.profile;
+            ui.horizontal(|ui| {
+                ui.color_edit_button_srgb(color);
+                ui.add(TextEdit::singleline(name).hint_text("your name"));
+            });
+            if name.chars().count() > MAX_NAME_LENGTH {
+                *name = name.chars().take(MAX_NAME_LENGTH).collect();
+            }
+            let valid = !name.trim().is_empty();
+            if ui.add_enabled(valid, Button::new("Continue")).clicked() {
+                let mut profile = onboarding.profile.clone();
+                profile.name = profile.name.trim().to_string();
+                store_default_property(stores.area(StorageArea::Cookies), profile);
+                commands.remove_resource::<NeedsOnboarding>();
+            }
+        });
+}
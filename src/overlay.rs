@@ -0,0 +1,99 @@
+use crate::{
+    components::{Health, Player, UserInfo},
+    lobby_settings::LobbySettings,
+    rng::SimFrame,
+    rounds::RoundState,
+    GameState,
+};
+use bevy::prelude::*;
+use serde::Serialize;
+use std::cell::RefCell;
+use wasm_bindgen::prelude::*;
+
+/// Publishes non-sensitive live match data for page scripts building stream
+/// overlays around the canvas. Nothing is exposed unless the local player
+/// opts in via [`OverlaySettings`].
+pub struct OverlayPlugin;
+
+impl Plugin for OverlayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<OverlaySettings>()
+            .add_system(clear_overlay_snapshot.in_schedule(OnExit(GameState::InGame)))
+            .add_system(publish_overlay_snapshot.in_set(OnUpdate(GameState::InGame)));
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct OverlaySettings {
+    pub enabled: bool,
+}
+
+#[derive(Serialize)]
+struct OverlaySnapshot {
+    /// Zero-based, like [`RoundState::round`]
+    round: u32,
+    /// Simulated time since the round started, so it stops while the game
+    /// is paused
+    round_time_secs: f64,
+    players: Vec<OverlayPlayer>,
+}
+
+#[derive(Serialize)]
+struct OverlayPlayer {
+    handle: usize,
+    name: String,
+    /// Rounds won
+    score: u32,
+    health: i32,
+    alive: bool,
+}
+
+thread_local! {
+    static LATEST_SNAPSHOT: RefCell<Option<String>> = RefCell::new(None);
+}
+
+/// Returns the latest match data as a JSON string, or `undefined` if sharing
+/// is disabled or no match is running.
+#[wasm_bindgen(js_name = webGhostOverlaySnapshot)]
+pub fn overlay_snapshot() -> Option<String> {
+    LATEST_SNAPSHOT.with(|latest| latest.borrow().clone())
+}
+
+fn clear_overlay_snapshot() {
+    LATEST_SNAPSHOT.with(|latest| *latest.borrow_mut() = None);
+}
+
+fn publish_overlay_snapshot(
+    settings: Res<OverlaySettings>,
+    lobby_settings: Res<LobbySettings>,
+    frame: Res<SimFrame>,
+    round: Res<RoundState>,
+    players: Query<(&Player, Option<&UserInfo>, Option<&Health>)>,
+) {
+    let snapshot = if settings.enabled {
+        let mut players = players
+            .iter()
+            .map(|(player, info, health)| {
+                let health = health.map_or(0, |h| h.0);
+                OverlayPlayer {
+                    handle: player.handle,
+                    name: info.map(|i| i.name.clone()).unwrap_or_default(),
+                    score: round.scores.get(player.handle).copied().unwrap_or(0),
+                    health,
+                    alive: health > 0,
+                }
+            })
+            .collect::<Vec<_>>();
+        players.sort_by_key(|p| p.handle);
+        let snapshot = OverlaySnapshot {
+            round: round.round,
+            round_time_secs: frame.0.saturating_sub(round.start_frame) as f64
+                / lobby_settings.tick_rate as f64,
+            players,
+        };
+        serde_json::to_string(&snapshot).ok()
+    } else {
+        None
+    };
+    LATEST_SNAPSHOT.with(|latest| *latest.borrow_mut() = snapshot);
+}
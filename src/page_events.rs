@@ -0,0 +1,187 @@
+use crate::{
+    components::{Health, IsLocal, MatchBoxPeerId, Player, UserInfo},
+    lobby::MAX_NAME_LENGTH,
+    onboarding::NeedsOnboarding,
+    room::{is_valid_room_name, Room},
+    server::{connect_to_room, ConnectionStatus, ServerConfig},
+    storage::{KeyValueStores, StorageArea},
+    GameState,
+};
+use bevy::prelude::*;
+use serde::Serialize;
+use std::{cell::RefCell, collections::VecDeque};
+use wasm_bindgen::prelude::*;
+use web_sys::{window, CustomEvent, CustomEventInit};
+
+/// Lets the hosting page follow the game lifecycle through DOM events on
+/// `window` and drive it through a few exported commands.
+///
+/// Emitted events (the `detail` is a JSON string):
+/// - `webghost:lobbyjoined`
+/// - `webghost:gamestarted`
+/// - `webghost:gameended`, with the final state of every player
+/// - `webghost:matchreport`, see [`MatchReportPlugin`]
+///
+/// [`MatchReportPlugin`]: crate::match_report::MatchReportPlugin
+pub struct PageEventsPlugin;
+
+impl Plugin for PageEventsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(emit_lobby_joined.run_if(in_state(GameState::Matchmaking)))
+            .add_system(emit_game_started.in_schedule(OnEnter(GameState::InGame)))
+            .add_system(emit_game_ended.in_schedule(OnExit(GameState::InGame)))
+            .add_system(apply_page_commands);
+    }
+}
+
+enum PageCommand {
+    SetPlayerName(String),
+    JoinRoom(String),
+}
+
+thread_local! {
+    static PENDING_COMMANDS: RefCell<VecDeque<PageCommand>> = RefCell::new(VecDeque::new());
+}
+
+/// Renames the local player, as if they had typed it in the lobby.
+#[wasm_bindgen(js_name = webGhostSetPlayerName)]
+pub fn set_player_name(name: String) {
+    PENDING_COMMANDS.with(|c| c.borrow_mut().push_back(PageCommand::SetPlayerName(name)));
+}
+
+/// Joins `room`, leaving the current lobby if needed. Ignored mid-game.
+#[wasm_bindgen(js_name = webGhostJoinRoom)]
+pub fn join_room(room: String) {
+    PENDING_COMMANDS.with(|c| c.borrow_mut().push_back(PageCommand::JoinRoom(room)));
+}
+
+/// Native builds have no page to tell, so events are dropped there
+pub fn dispatch(name: &str, detail: &impl Serialize) {
+    if cfg!(feature = "native") {
+        return;
+    }
+    let Some(window) = window() else {
+        return;
+    };
+    let detail = serde_json::to_string(detail).unwrap();
+    let mut init = CustomEventInit::new();
+    init.detail(&JsValue::from_str(&detail));
+    match CustomEvent::new_with_event_init_dict(name, &init) {
+        Ok(event) => {
+            if let Err(e) = window.dispatch_event(&event) {
+                warn!("Failed to dispatch {name}: {e:?}");
+            }
+        }
+        Err(e) => warn!("Failed to create {name}: {e:?}"),
+    }
+}
+
+#[derive(Serialize)]
+struct LobbyJoined<'a> {
+    room: &'a str,
+}
+
+fn emit_lobby_joined(room: Res<Room>, local_player: Query<(), Added<IsLocal>>) {
+    if !local_player.is_empty() {
+        dispatch("webghost:lobbyjoined", &LobbyJoined { room: &room.0 });
+    }
+}
+
+#[derive(Serialize)]
+struct GameStarted {
+    players: usize,
+}
+
+fn emit_game_started(players: Query<(), With<Player>>) {
+    dispatch(
+        "webghost:gamestarted",
+        &GameStarted {
+            players: players.iter().len(),
+        },
+    );
+}
+
+#[derive(Serialize)]
+struct PlayerResult {
+    handle: usize,
+    name: String,
+    health: i32,
+    alive: bool,
+    local: bool,
+}
+
+#[derive(Serialize)]
+struct GameEnded {
+    results: Vec<PlayerResult>,
+}
+
+fn emit_game_ended(
+    players: Query<(&Player, Option<&UserInfo>, Option<&Health>, Option<&IsLocal>)>,
+) {
+    let mut results = players
+        .iter()
+        .map(|(player, info, health, local)| {
+            let health = health.map_or(0, |h| h.0);
+            PlayerResult {
+                handle: player.handle,
+                name: info.map(|i| i.name.clone()).unwrap_or_default(),
+                health,
+                alive: health > 0,
+                local: local.is_some(),
+            }
+        })
+        .collect::<Vec<_>>();
+    results.sort_by_key(|r| r.handle);
+    dispatch("webghost:gameended", &GameEnded { results });
+}
+
+fn apply_page_commands(
+    mut commands: Commands,
+    state: Res<State<GameState>>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut room: ResMut<Room>,
+    server: Res<ServerConfig>,
+    onboarding: Option<Res<NeedsOnboarding>>,
+    mut status: ResMut<ConnectionStatus>,
+    mut local_info: Query<&mut UserInfo, With<IsLocal>>,
+    peers: Query<Entity, With<MatchBoxPeerId>>,
+    stores: Res<KeyValueStores>,
+) {
+    while let Some(command) = PENDING_COMMANDS.with(|c| c.borrow_mut().pop_front()) {
+        match command {
+            PageCommand::SetPlayerName(name) => {
+                let Ok(mut info) = local_info.get_single_mut() else {
+                    warn!("Ignoring name change before joining the lobby");
+                    continue;
+                };
+                info.name = name.chars().take(MAX_NAME_LENGTH).collect();
+            }
+            PageCommand::JoinRoom(name) => {
+                if !is_valid_room_name(&name) {
+                    warn!("Ignoring request to join invalid room {name:?}");
+                    continue;
+                }
+                match state.0 {
+                    GameState::RoomSelect => {
+                        room.0 = name;
+                        room.save_to_cookie(stores.area(StorageArea::Cookies));
+                        if onboarding.is_none() {
+                            next_state.set(GameState::Matchmaking);
+                        }
+                    }
+                    GameState::Matchmaking => {
+                        info!("Switching to room {name}");
+                        room.0 = name;
+                        room.save_to_cookie(stores.area(StorageArea::Cookies));
+                        for entity in peers.iter() {
+                            commands.entity(entity).despawn();
+                        }
+                        commands.insert_resource(connect_to_room(&server, &room));
+                        *status = ConnectionStatus::Disconnected;
+                    }
+                    _ => warn!("Ignoring request to join room {name} while not in the lobby"),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,253 @@
+use crate::{
+    components::{MatchBoxPeerId, UserInfo},
+    input::pause,
+    leave::LeaveGame,
+    lobby::{PracticeMode, SocketExt, Spectating},
+    net::{read_messages, Messages, P2PMessage},
+    save::kill_game,
+    simulation::SimulationTimingSet,
+    GameState, GgrsConfig,
+};
+use bevy::{prelude::*, utils::HashSet};
+use bevy_egui::{
+    egui::{Align2, Window},
+    EguiContexts,
+};
+use bevy_ggrs::{GGRSSchedule, PlayerInputs};
+use bevy_matchbox::prelude::*;
+
+/// Pauses a running game once every peer agrees to. Escape asks the others,
+/// who each have to agree, and resuming takes everyone's confirmation too.
+/// Peers that agreed set a flag in their input, and the simulation stands
+/// still on frames where any player's input has it. That keeps the pause
+/// itself in the rollback: GGRS goes on exchanging inputs as usual, so every
+/// peer pauses and resumes on the same frame and connections don't time out.
+pub struct PausePlugin;
+
+impl Plugin for PausePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PauseVote>()
+            .edit_schedule(GGRSSchedule, |schedule| {
+                schedule.configure_set(SimulationTimingSet::Simulate.run_if(not(sim_paused)));
+            })
+            .add_systems(
+                (
+                    receive_pause_votes.after(read_messages).before(kill_game),
+                    pause_ui.after(receive_pause_votes),
+                )
+                    .in_set(OnUpdate(GameState::InGame)),
+            )
+            .add_system(reset_pause_vote.in_schedule(OnExit(GameState::InGame)));
+    }
+}
+
+/// Who voted to pause the running game, and to resume it once it's paused
+#[derive(Resource, Default)]
+pub struct PauseVote {
+    pause: HashSet<PeerId>,
+    /// Set once everyone voted to pause, until everyone voted to resume
+    paused: bool,
+    resume: HashSet<PeerId>,
+}
+
+impl PauseVote {
+    /// Whether the local peer takes part in a pause, which its input says
+    pub fn agreed(&self) -> bool {
+        self.paused
+    }
+
+    /// Pauses or resumes once the vote is unanimous
+    fn tally(&mut self, voters: &[PeerId]) {
+        let unanimous = |votes: &HashSet<PeerId>| voters.iter().all(|id| votes.contains(id));
+        if !self.paused && !self.pause.is_empty() && unanimous(&self.pause) {
+            info!("Everyone agreed to pause");
+            self.paused = true;
+        } else if self.paused && unanimous(&self.resume) {
+            info!("Everyone agreed to resume");
+            *self = default();
+        }
+    }
+}
+
+/// Run condition for the simulation
+fn sim_paused(inputs: Res<PlayerInputs<GgrsConfig>>) -> bool {
+    inputs.iter().any(|(input, _)| pause(*input))
+}
+
+/// The local peer and everyone it's connected to, in practice mode just the
+/// local peer
+fn voters(
+    socket: &MatchboxSocket<MultipleChannels>,
+    practice: bool,
+) -> Option<(PeerId, Vec<PeerId>)> {
+    let local = socket.id()?;
+    let mut voters = vec![local];
+    if !practice {
+        voters.extend(socket.connected_peers());
+    }
+    Some((local, voters))
+}
+
+fn send_to_all(socket: &mut MatchboxSocket<MultipleChannels>, message: P2PMessage) {
+    for peer_id in socket.connected_peers().collect::<Vec<_>>().iter() {
+        socket.send_p2p_message(peer_id, message.clone());
+    }
+}
+
+/// Takes the votes out of the mid-game messages, which would otherwise make
+/// [`kill_game`] restart the session
+fn receive_pause_votes(
+    mut messages: ResMut<Messages>,
+    mut vote: ResMut<PauseVote>,
+    mut socket: Option<ResMut<MatchboxSocket<MultipleChannels>>>,
+    spectating: Option<Res<Spectating>>,
+    practice: Option<Res<PracticeMode>>,
+) {
+    let Some(socket) = socket.as_mut() else {
+        return;
+    };
+    let mut received = Vec::new();
+    messages
+        .0
+        .retain(|(peer_id, packet)| match P2PMessage::decode(packet) {
+            Ok(
+                message @ (P2PMessage::PauseRequest
+                | P2PMessage::PauseAck
+                | P2PMessage::PauseDecline
+                | P2PMessage::ResumeConfirm),
+            ) => {
+                received.push((*peer_id, message));
+                false
+            }
+            _ => true,
+        });
+    let Some((local, voters)) = voters(socket, practice.is_some()) else {
+        return;
+    };
+    for (peer_id, message) in received {
+        match message {
+            P2PMessage::PauseRequest | P2PMessage::PauseAck if !vote.paused => {
+                vote.pause.insert(peer_id);
+                // Spectators have no input to pause, so they don't get a say
+                if spectating.is_some() && vote.pause.insert(local) {
+                    send_to_all(socket, P2PMessage::PauseAck);
+                }
+            }
+            P2PMessage::PauseDecline if !vote.paused => {
+                info!("{peer_id:?} turned down the pause");
+                *vote = default();
+            }
+            // Can get here before the last pause vote, if that one went to
+            // the sender first
+            P2PMessage::ResumeConfirm => {
+                vote.resume.insert(peer_id);
+                if spectating.is_some() && vote.resume.insert(local) {
+                    send_to_all(socket, P2PMessage::ResumeConfirm);
+                }
+            }
+            _ => {}
+        }
+        vote.tally(&voters);
+    }
+}
+
+fn pause_ui(
+    mut contexts: EguiContexts,
+    keys: Res<Input<KeyCode>>,
+    mut vote: ResMut<PauseVote>,
+    mut socket: Option<ResMut<MatchboxSocket<MultipleChannels>>>,
+    spectating: Option<Res<Spectating>>,
+    practice: Option<Res<PracticeMode>>,
+    peers: Query<(&MatchBoxPeerId, &UserInfo)>,
+    mut leave_events: EventWriter<LeaveGame>,
+) {
+    let Some(socket) = socket.as_mut() else {
+        return;
+    };
+    let Some((local, voters)) = voters(socket, practice.is_some()) else {
+        return;
+    };
+    let escape = keys.just_pressed(KeyCode::Escape) && spectating.is_none();
+    let pending = !vote.paused && !vote.pause.is_empty();
+    if escape && !vote.paused && vote.pause.insert(local) {
+        let message = if pending {
+            P2PMessage::PauseAck
+        } else {
+            P2PMessage::PauseRequest
+        };
+        send_to_all(socket, message);
+    } else if escape && vote.paused && vote.resume.insert(local) {
+        send_to_all(socket, P2PMessage::ResumeConfirm);
+    }
+    vote.tally(&voters);
+    if vote.pause.is_empty() {
+        return;
+    }
+
+    let name_of = |peer_id: &PeerId| {
+        peers
+            .iter()
+            .find(|(id, _)| id.0 == *peer_id)
+            .map_or("Someone".to_string(), |(_, info)| info.name.clone())
+    };
+    let votes = if vote.paused {
+        &vote.resume
+    } else {
+        &vote.pause
+    };
+    let count = voters.iter().filter(|id| votes.contains(id)).count();
+    let voted = votes.contains(&local);
+    let requester = vote.pause.iter().next().map(name_of).unwrap_or_default();
+    let title = if vote.paused { "Paused" } else { "Pause?" };
+    Window::new(title)
+        .anchor(Align2::CENTER_CENTER, [0., 0.])
+        .collapsible(false)
+        .resizable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            if !vote.paused && !voted {
+                ui.label(format!("{requester} wants to pause the game"));
+                ui.horizontal(|ui| {
+                    if ui.button("Pause").clicked() {
+                        vote.pause.insert(local);
+                        send_to_all(socket, P2PMessage::PauseAck);
+                    }
+                    if ui.button("Keep playing").clicked() {
+                        *vote = default();
+                        send_to_all(socket, P2PMessage::PauseDecline);
+                    }
+                });
+            } else if !vote.paused {
+                ui.label(format!(
+                    "Waiting for everyone to agree to pause ({count}/{})",
+                    voters.len()
+                ));
+                if ui.button("Cancel").clicked() {
+                    *vote = default();
+                    send_to_all(socket, P2PMessage::PauseDecline);
+                }
+            } else if !voted {
+                if spectating.is_some() {
+                    ui.label("The players paused the game");
+                } else if ui.button("Resume").clicked() {
+                    vote.resume.insert(local);
+                    send_to_all(socket, P2PMessage::ResumeConfirm);
+                }
+            } else {
+                ui.label(format!(
+                    "Waiting for everyone to resume ({count}/{})",
+                    voters.len()
+                ));
+            }
+            if vote.paused {
+                ui.separator();
+                if ui.button("Leave game").clicked() {
+                    leave_events.send(LeaveGame);
+                }
+            }
+        });
+    vote.tally(&voters);
+}
+
+fn reset_pause_vote(mut vote: ResMut<PauseVote>) {
+    *vote = default();
+}
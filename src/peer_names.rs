@@ -0,0 +1,116 @@
+use crate::{
+    components::{IsLocal, MatchBoxPeerId, TabId, UserInfo},
+    lobby::SocketExt,
+    net::P2PMessage,
+    persistence::{read_cookie, write_cookie},
+    placeholder::Placeholder,
+    storage::KeyValueStores,
+    GameState,
+};
+use bevy::{prelude::*, utils::HashMap};
+use bevy_matchbox::prelude::*;
+
+/// Remembers the names of players this tab has met, keyed by [`TabId`] so
+/// they survive reconnects. Newcomers are told who's already in the room with
+/// [`P2PMessage::Roster`], so a peer whose own introduction is slow to arrive
+/// can still be shown by name instead of a placeholder.
+pub struct PeerNamesPlugin;
+
+impl Plugin for PeerNamesPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(PeerNames::from_cookie())
+            .add_systems((remember_names, send_roster).in_set(OnUpdate(GameState::Matchmaking)))
+            .add_system(save_peer_names);
+    }
+}
+
+/// Names beyond this are forgotten, least recently seen first
+const MAX_REMEMBERED: usize = 64;
+
+#[derive(Resource, Default, Debug)]
+pub struct PeerNames {
+    /// Tab ids and their last known names, most recently seen last
+    names: Vec<(String, String)>,
+    /// Tab ids of peers in the room as other peers reported them. Peer ids
+    /// change with every connection, so these aren't saved.
+    reported_tabs: HashMap<PeerId, TabId>,
+}
+
+impl PeerNames {
+    const COOKIE_KEY: &'static str = "peer_names";
+
+    fn from_cookie() -> Self {
+        Self {
+            names: read_cookie(Self::COOKIE_KEY).unwrap_or_default(),
+            reported_tabs: default(),
+        }
+    }
+
+    fn remember(&mut self, tab_id: &TabId, name: &str) {
+        self.names.retain(|(tab, _)| *tab != tab_id.0);
+        if self.names.len() >= MAX_REMEMBERED {
+            self.names.remove(0);
+        }
+        self.names.push((tab_id.0.clone(), name.to_string()));
+    }
+
+    /// Takes in the tab ids and names from a [`P2PMessage::Roster`]
+    pub fn learn_roster(&mut self, roster: Vec<(PeerId, TabId, String)>) {
+        for (peer_id, tab_id, name) in roster {
+            self.remember(&tab_id, &name);
+            self.reported_tabs.insert(peer_id, tab_id);
+        }
+    }
+
+    /// The tab of a peer who hasn't told us themselves, and its last known name
+    pub fn reported(&self, peer_id: PeerId) -> Option<(&TabId, &str)> {
+        let tab_id = self.reported_tabs.get(&peer_id)?;
+        self.names
+            .iter()
+            .find(|(tab, _)| *tab == tab_id.0)
+            .map(|(_, name)| (tab_id, name.as_str()))
+    }
+}
+
+fn remember_names(
+    mut peer_names: ResMut<PeerNames>,
+    introduced: Query<
+        (&TabId, &UserInfo),
+        (
+            Or<(Changed<TabId>, Changed<UserInfo>)>,
+            Without<IsLocal>,
+            Without<Placeholder>,
+        ),
+    >,
+) {
+    for (tab_id, info) in introduced.iter() {
+        peer_names.remember(tab_id, &info.name);
+    }
+}
+
+/// Tells each newcomer who else is in the room
+fn send_roster(
+    mut socket: ResMut<MatchboxSocket<MultipleChannels>>,
+    newcomers: Query<&MatchBoxPeerId, (Added<MatchBoxPeerId>, Without<IsLocal>)>,
+    introduced: Query<
+        (&MatchBoxPeerId, &TabId, &UserInfo),
+        (Without<IsLocal>, Without<Placeholder>),
+    >,
+) {
+    for newcomer in newcomers.iter() {
+        let roster = introduced
+            .iter()
+            .filter(|(peer_id, ..)| peer_id.0 != newcomer.0)
+            .map(|(peer_id, tab_id, info)| (peer_id.0, tab_id.clone(), info.name.clone()))
+            .collect::<Vec<_>>();
+        if !roster.is_empty() {
+            socket.send_p2p_message(&newcomer.0, P2PMessage::Roster(roster));
+        }
+    }
+}
+
+fn save_peer_names(peer_names: Res<PeerNames>, stores: Res<KeyValueStores>) {
+    if peer_names.is_changed() && !peer_names.is_added() {
+        write_cookie(&stores, PeerNames::COOKIE_KEY, &peer_names.names);
+    }
+}
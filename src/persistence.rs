@@ -0,0 +1,167 @@
+use crate::{
+    storage::{KeyValueStore, KeyValueStores, StorageArea},
+    wire_format::{Ron, TextFormat},
+};
+use bevy::prelude::*;
+use bevy_egui::{
+    egui::{Align2, Window},
+    EguiContexts,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use std::cell::RefCell;
+
+/// Crash-safe storage of settings in cookies. Writes go to a journal cookie
+/// first and are only then copied over the real one, so a tab closing
+/// mid-write leaves either the old or the new value behind, never half of
+/// one. Values that fail to parse anyway are moved aside instead of crashing
+/// the game, and the player is asked what to do with them.
+pub struct PersistencePlugin;
+
+impl Plugin for PersistencePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(KeyValueStores::get())
+            .add_system(corrupted_settings_ui)
+            .add_system(storage_unavailable_ui);
+    }
+}
+
+thread_local! {
+    /// Keys quarantined this session, waiting for the player to acknowledge
+    static QUARANTINED: RefCell<Vec<String>> = RefCell::new(Vec::new());
+}
+
+/// Holds the value being written until it's safely in place
+fn journal_key(key: &str) -> String {
+    format!("{key}.new")
+}
+
+/// Where unparseable values are kept for bug reports
+fn quarantine_key(key: &str) -> String {
+    format!("{key}.corrupt")
+}
+
+/// Format of every stored value. Stores only hold strings, and a readable one
+/// can be fixed by hand when it gets quarantined.
+type StorageFormat = Ron;
+
+fn set_raw(store: &dyn KeyValueStore, key: &str, value: &str) {
+    if let Err(e) = store.set(key, value) {
+        warn!("Failed to write {key}: {e}");
+    }
+}
+
+/// Stores `value` under cookie `key`
+pub fn write_cookie<T: Serialize>(stores: &KeyValueStores, key: &str, value: &T) {
+    write_to(stores.area(StorageArea::Cookies), key, value);
+}
+
+/// Reads the value under cookie `key` while the app is built, before the
+/// stores are a resource, see [`read_from`]
+pub fn read_cookie<T: DeserializeOwned>(key: &str) -> Option<T> {
+    read_from(KeyValueStores::get().area(StorageArea::Cookies), key)
+}
+
+/// Stores `value` in [`StorageFormat`] under `key` in `store`
+pub fn write_to<T: Serialize>(store: &dyn KeyValueStore, key: &str, value: &T) {
+    let value = match StorageFormat::to_text(value) {
+        Ok(value) => value,
+        Err(e) => {
+            warn!("Failed to serialize {key}: {e}");
+            return;
+        }
+    };
+    let journal = journal_key(key);
+    set_raw(store, &journal, &value);
+    set_raw(store, key, &value);
+    store.remove(&journal);
+}
+
+/// Reads the value under `key` in `store`. Finishes a write that was cut
+/// short, and quarantines values that don't parse, returning `None` for them
+/// like for missing ones.
+pub fn read_from<T: DeserializeOwned>(store: &dyn KeyValueStore, key: &str) -> Option<T> {
+    let journal = journal_key(key);
+    if let Some(raw) = store.get(&journal) {
+        store.remove(&journal);
+        // A complete journal means the write stopped before the swap. A torn
+        // one means it stopped before touching `key`, which is still intact.
+        if let Ok(value) = StorageFormat::from_text(&raw) {
+            info!("Recovered interrupted write of {key}");
+            set_raw(store, key, &raw);
+            return Some(value);
+        }
+    }
+    let raw = store.get(key)?;
+    match StorageFormat::from_text::<T>(&raw) {
+        Ok(value) => Some(value),
+        Err(_) => {
+            warn!("Quarantining corrupt {key}");
+            set_raw(store, &quarantine_key(key), &raw);
+            store.remove(key);
+            QUARANTINED.with(|keys| keys.borrow_mut().push(key.to_string()));
+            None
+        }
+    }
+}
+
+fn corrupted_settings_ui(mut contexts: EguiContexts, stores: Res<KeyValueStores>) {
+    let keys = QUARANTINED.with(|keys| keys.borrow().clone());
+    if keys.is_empty() {
+        return;
+    }
+    Window::new("Corrupted settings")
+        .anchor(Align2::CENTER_CENTER, [0., 0.])
+        .collapsible(false)
+        .resizable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.label("Some saved settings couldn't be read and were reset to defaults:");
+            for key in keys.iter() {
+                ui.monospace(key);
+            }
+            ui.horizontal(|ui| {
+                let cookies = stores.area(StorageArea::Cookies);
+                if ui.button("Copy details").clicked() {
+                    let details = keys
+                        .iter()
+                        .map(|key| {
+                            let raw = cookies.get(&quarantine_key(key));
+                            format!("{key}: {}", raw.unwrap_or_default())
+                        })
+                        .collect::<Vec<_>>()
+                        .join("\n");
+                    ui.output_mut(|output| output.copied_text = details);
+                }
+                if ui.button("Reset corrupted settings").clicked() {
+                    for key in keys.iter() {
+                        cookies.remove(&quarantine_key(key));
+                    }
+                    QUARANTINED.with(|keys| keys.borrow_mut().clear());
+                }
+            });
+        });
+}
+
+/// Tells players once that their settings won't be remembered, e.g. with
+/// cookies blocked in private browsing
+fn storage_unavailable_ui(
+    mut contexts: EguiContexts,
+    stores: Res<KeyValueStores>,
+    mut dismissed: Local<bool>,
+) {
+    if *dismissed || stores.unavailable.is_empty() {
+        return;
+    }
+    Window::new("Storage unavailable")
+        .anchor(Align2::CENTER_BOTTOM, [0., -60.])
+        .collapsible(false)
+        .resizable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.label(
+                "Your browser doesn't let the game store data, so your name, settings and \
+                 saves will be forgotten when you close the tab.",
+            );
+            if ui.button("OK").clicked() {
+                *dismissed = true;
+            }
+        });
+}
@@ -0,0 +1,168 @@
+use bevy::prelude::*;
+use num_integer::Roots;
+
+/// Times of impact are fractions of a frame's movement, in 1/`TOI_SCALE`ths
+pub const TOI_SCALE: i64 = 1 << 12;
+
+/// When a circle moving from `from` to `to` first touches a circle at
+/// `center`, `radius` being the sum of both radii. Zero if they already
+/// overlap at `from`, `None` if they don't meet during the move. Integer only,
+/// so every peer finds the same hits.
+pub fn sweep_circle_circle(from: IVec2, to: IVec2, center: IVec2, radius: i32) -> Option<i64> {
+    let (fx, fy) = ((from.x - center.x) as i128, (from.y - center.y) as i128);
+    let (dx, dy) = ((to.x - from.x) as i128, (to.y - from.y) as i128);
+    let radius = radius as i128;
+    let c = fx * fx + fy * fy - radius * radius;
+    if c <= 0 {
+        return Some(0);
+    }
+    let a = dx * dx + dy * dy;
+    // Half of the usual b, which cancels out of the quadratic formula
+    let b = fx * dx + fy * dy;
+    if a == 0 || b >= 0 {
+        return None;
+    }
+    let discriminant = b * b - a * c;
+    if discriminant < 0 {
+        return None;
+    }
+    let t = -b - discriminant.sqrt();
+    (t <= a).then(|| (t * TOI_SCALE as i128 / a) as i64)
+}
+
+/// How far apart to push two overlapping circles at `a` and `b`, `radius`
+/// being the sum of both radii. Points from `a` to `b`, along the axis between
+/// their centers, or along x if they're on the same spot. `None` if they don't
+/// overlap.
+pub fn separate_circles(a: IVec2, b: IVec2, radius: i32) -> Option<IVec2> {
+    let (dx, dy) = ((b.x - a.x) as i64, (b.y - a.y) as i64);
+    let radius = radius as i64;
+    let distance_squared = dx * dx + dy * dy;
+    if distance_squared >= radius * radius {
+        return None;
+    }
+    if distance_squared == 0 {
+        return Some(IVec2::new(radius as i32, 0));
+    }
+    let distance = distance_squared.sqrt();
+    let overlap = radius - distance;
+    Some(IVec2::new(
+        (dx * overlap / distance) as i32,
+        (dy * overlap / distance) as i32,
+    ))
+}
+
+/// When a circle of `radius` moving from `from` to `to` first touches the box
+/// from `min` to `max`, like [`sweep_circle_circle`]. The box is grown by the
+/// radius with square corners, so circles passing right by a corner count as
+/// touching it.
+pub fn sweep_circle_aabb(
+    from: IVec2,
+    to: IVec2,
+    radius: i32,
+    min: IVec2,
+    max: IVec2,
+) -> Option<i64> {
+    let mut enter = i64::MIN;
+    let mut exit = i64::MAX;
+    for (start, end, min, max) in [(from.x, to.x, min.x, max.x), (from.y, to.y, min.y, max.y)] {
+        let (start, delta) = (start as i64, (end - start) as i64);
+        let (min, max) = ((min - radius) as i64, (max + radius) as i64);
+        if delta == 0 {
+            if start < min || start > max {
+                return None;
+            }
+            continue;
+        }
+        let (near, far) = if delta > 0 { (min, max) } else { (max, min) };
+        enter = enter.max((near - start) * TOI_SCALE / delta);
+        exit = exit.min((far - start) * TOI_SCALE / delta);
+    }
+    (enter <= exit && enter <= TOI_SCALE && exit >= 0).then_some(enter.max(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RADIUS: i32 = 5;
+    const WALL_MIN: IVec2 = IVec2::new(-2, -2);
+    const WALL_MAX: IVec2 = IVec2::new(2, 2);
+
+    #[test]
+    fn fast_circles_hit_what_they_pass_through() {
+        let (from, to) = (IVec2::new(-100, 0), IVec2::new(100, 0));
+        // Touching at x = -5, 95 of the 200 units along
+        assert_eq!(
+            sweep_circle_circle(from, to, IVec2::ZERO, RADIUS),
+            Some(95 * TOI_SCALE / 200)
+        );
+        // Touching the grown box at x = -7, 93 of the 200 units along
+        assert_eq!(
+            sweep_circle_aabb(from, to, RADIUS, WALL_MIN, WALL_MAX),
+            Some(93 * TOI_SCALE / 200)
+        );
+    }
+
+    #[test]
+    fn overlapping_starts_hit_right_away() {
+        let (from, to) = (IVec2::new(3, 0), IVec2::new(100, 0));
+        assert_eq!(sweep_circle_circle(from, to, IVec2::ZERO, RADIUS), Some(0));
+        assert_eq!(
+            sweep_circle_aabb(from, to, RADIUS, WALL_MIN, WALL_MAX),
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn parallel_moves_just_out_of_reach_miss() {
+        let (from, to) = (IVec2::new(-100, 6), IVec2::new(100, 6));
+        assert_eq!(sweep_circle_circle(from, to, IVec2::ZERO, RADIUS), None);
+        let (from, to) = (IVec2::new(-100, 8), IVec2::new(100, 8));
+        assert_eq!(
+            sweep_circle_aabb(from, to, RADIUS, WALL_MIN, WALL_MAX),
+            None
+        );
+    }
+
+    #[test]
+    fn tangent_grazes_count_as_hits() {
+        let (from, to) = (IVec2::new(-10, 5), IVec2::new(10, 5));
+        assert_eq!(
+            sweep_circle_circle(from, to, IVec2::ZERO, RADIUS),
+            Some(TOI_SCALE / 2)
+        );
+        let (from, to) = (IVec2::new(-100, 7), IVec2::new(100, 7));
+        assert_eq!(
+            sweep_circle_aabb(from, to, RADIUS, WALL_MIN, WALL_MAX),
+            Some(93 * TOI_SCALE / 200)
+        );
+    }
+
+    #[test]
+    fn moves_stopping_short_miss() {
+        let (from, to) = (IVec2::new(-100, 0), IVec2::new(-50, 0));
+        assert_eq!(sweep_circle_circle(from, to, IVec2::ZERO, RADIUS), None);
+        assert_eq!(
+            sweep_circle_aabb(from, to, RADIUS, WALL_MIN, WALL_MAX),
+            None
+        );
+    }
+
+    #[test]
+    fn walls_are_hit_before_players_behind_them() {
+        let (from, to) = (IVec2::new(-100, 0), IVec2::new(100, 0));
+        let bullet_radius = 1;
+        let wall = sweep_circle_aabb(
+            from,
+            to,
+            bullet_radius,
+            IVec2::new(-20, -50),
+            IVec2::new(-10, 50),
+        )
+        .unwrap();
+        let player =
+            sweep_circle_circle(from, to, IVec2::new(30, 0), RADIUS + bullet_radius).unwrap();
+        assert!(wall < player, "{wall} {player}");
+    }
+}
@@ -0,0 +1,193 @@
+use crate::{
+    achievements::{GameplayEvent, GameplayEvents},
+    components::{Health, Invulnerable, Player, Position, Radius},
+    lobby_settings::LobbySettings,
+    maps::CurrentMap,
+    rng::{advance_sim_frame, SimFrame, SimRng},
+    simulation::collide_players,
+    spatial::SpatialGrid,
+    IVec2Ext, F2I,
+};
+#[cfg(feature = "presentation")]
+use crate::{
+    layers::DrawLayer,
+    sprite_atlas::{GameSprite, SpriteAtlas},
+    GameState, I2F,
+};
+use bevy::prelude::*;
+use bevy_ggrs::{GGRSSchedule, Rollback, RollbackIdProvider};
+use serde::Serialize;
+
+/// Power-ups that spawn around the map at deterministic times and places
+pub struct PickupsPlugin;
+
+impl Plugin for PickupsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            (
+                spawn_pickups.after(advance_sim_frame),
+                collect_pickups.after(collide_players).after(spawn_pickups),
+                tick_pickup_effects.before(collect_pickups),
+            )
+                .in_schedule(GGRSSchedule),
+        );
+        #[cfg(feature = "presentation")]
+        app.add_system(add_pickup_sprites.in_set(OnUpdate(GameState::InGame)));
+    }
+}
+
+const PICKUP_RADIUS_SI: i32 = 3 * F2I / 10;
+const PICKUP_SPAWN_INTERVAL_FRAMES: u32 = 8 * 60;
+const MAX_PICKUPS: usize = 4;
+pub const SPEED_BOOST_FRAMES: u32 = 6 * 60;
+pub const RAPID_FIRE_FRAMES: u32 = 6 * 60;
+pub const SHIELD_FRAMES: u32 = 4 * 60;
+
+#[derive(Reflect, FromReflect, Serialize, Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PickupKind {
+    #[default]
+    SpeedBoost,
+    RapidFire,
+    Shield,
+}
+
+impl PickupKind {
+    const ALL: [Self; 3] = [Self::SpeedBoost, Self::RapidFire, Self::Shield];
+
+    #[cfg(feature = "presentation")]
+    fn color(self) -> Color {
+        match self {
+            Self::SpeedBoost => Color::rgb(1., 0.85, 0.2),
+            Self::RapidFire => Color::rgb(1., 0.35, 0.2),
+            Self::Shield => Color::rgb(0.6, 0.9, 1.),
+        }
+    }
+}
+
+#[derive(Component, Reflect, Default, Clone, Copy, Debug)]
+pub struct Pickup(pub PickupKind);
+
+/// Frames of boosted movement speed left
+#[derive(Component, Reflect, Default, Clone, Copy, Debug)]
+pub struct SpeedBoost(pub u32);
+
+/// Frames of automatic fire left
+#[derive(Component, Reflect, Default, Clone, Copy, Debug)]
+pub struct RapidFire(pub u32);
+
+pub fn spawn_pickups(
+    mut commands: Commands,
+    frame: Res<SimFrame>,
+    mut rng: ResMut<SimRng>,
+    mut rip: ResMut<RollbackIdProvider>,
+    pickups: Query<&Position, With<Pickup>>,
+    settings: Res<LobbySettings>,
+    map: Res<CurrentMap>,
+) {
+    if frame.0 % PICKUP_SPAWN_INTERVAL_FRAMES != 0 || pickups.iter().len() >= MAX_PICKUPS {
+        return;
+    }
+    let spawners = map.pickup_spawners();
+    let position = if spawners.is_empty() {
+        let limit = settings.map_size_si() / 2 - PICKUP_RADIUS_SI;
+        IVec2::new(rng.range_i32(-limit, limit), rng.range_i32(-limit, limit))
+    } else {
+        spawners[rng.range_i32(0, spawners.len() as i32) as usize]
+    };
+    let kind = PickupKind::ALL[rng.range_i32(0, PickupKind::ALL.len() as i32) as usize];
+    // Skip this spawn rather than put the pickup out of reach
+    let taken = pickups.iter().any(|existing| existing.0 == position);
+    if taken || map.blocks(position, PICKUP_RADIUS_SI) {
+        return;
+    }
+    commands.spawn((
+        Pickup(kind),
+        Rollback::new(rip.next_id()),
+        Position(position),
+        Radius(PICKUP_RADIUS_SI),
+    ));
+}
+
+/// The simulation only spawns the pickups themselves, and rollbacks may
+/// spawn them again, so their sprites are added here
+#[cfg(feature = "presentation")]
+fn add_pickup_sprites(
+    mut commands: Commands,
+    sprites: Res<SpriteAtlas>,
+    pickups: Query<(Entity, &Pickup, &Position), Added<Pickup>>,
+) {
+    for (entity, pickup, position) in pickups.iter() {
+        commands.entity(entity).insert((
+            DrawLayer::Pickups,
+            sprites.bundle(
+                GameSprite::Solid,
+                pickup.0.color(),
+                Vec2::splat(PICKUP_RADIUS_SI as f32 * I2F * 2.),
+                Transform::from_translation(position.0.i2f().extend(DrawLayer::Pickups.z())),
+            ),
+        ));
+    }
+}
+
+pub fn collect_pickups(
+    mut commands: Commands,
+    mut players: Query<
+        (
+            &Player,
+            &Position,
+            &Radius,
+            &Health,
+            &mut SpeedBoost,
+            &mut RapidFire,
+            &mut Invulnerable,
+        ),
+        Without<Pickup>,
+    >,
+    pickups: Query<(&Pickup, &Position, &Radius)>,
+    grid: Res<SpatialGrid>,
+    frame: Res<SimFrame>,
+    mut events: ResMut<GameplayEvents>,
+) {
+    // Visit players in handle order so ties resolve identically on every peer
+    let mut players = players.iter_mut().collect::<Vec<_>>();
+    players.sort_by_key(|(player, ..)| player.handle);
+    let mut collected = Vec::new();
+    for (player, position, radius, health, mut speed, mut rapid_fire, mut invulnerable) in players {
+        if health.0 <= 0 {
+            continue;
+        }
+        for entity in grid.query_circle(position.0, radius.0) {
+            let Ok((pickup, pickup_position, pickup_radius)) = pickups.get(entity) else {
+                continue;
+            };
+            if collected.contains(&entity) {
+                continue;
+            }
+            let reach = (radius.0 + pickup_radius.0) as i64;
+            if (position.0 - pickup_position.0).norm_sq_wide() >= reach * reach {
+                continue;
+            }
+            match pickup.0 {
+                PickupKind::SpeedBoost => speed.0 = SPEED_BOOST_FRAMES,
+                PickupKind::RapidFire => rapid_fire.0 = RAPID_FIRE_FRAMES,
+                PickupKind::Shield => invulnerable.0 = invulnerable.0.max(SHIELD_FRAMES),
+            }
+            events.push(
+                &frame,
+                GameplayEvent::Pickup {
+                    player: player.handle,
+                    kind: pickup.0,
+                },
+            );
+            collected.push(entity);
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+fn tick_pickup_effects(mut players: Query<(&mut SpeedBoost, &mut RapidFire)>) {
+    for (mut speed, mut rapid_fire) in players.iter_mut() {
+        speed.0 = speed.0.saturating_sub(1);
+        rapid_fire.0 = rapid_fire.0.saturating_sub(1);
+    }
+}
@@ -0,0 +1,98 @@
+use crate::{
+    components::{IsLocal, MatchBoxPeerId, UserInfo},
+    match_config::Fnv1a,
+};
+use bevy::{prelude::*, utils::HashMap};
+use bevy_matchbox::prelude::PeerId;
+
+/// Gives peers a stand-in name and color until their own [`UserInfo`]
+/// arrives, so the lobby and the first frames of a game never show blank or
+/// identical players while the handshake completes
+pub struct PlaceholderPlugin;
+
+impl Plugin for PlaceholderPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(assign_placeholders);
+    }
+}
+
+/// Marks a peer whose [`UserInfo`] is still a placeholder. Removed when their
+/// own info arrives.
+#[derive(Component)]
+pub struct Placeholder;
+
+const PLACEHOLDERS: [(&str, [u8; 3]); 12] = [
+    ("Blue", [60, 110, 230]),
+    ("Teal", [40, 170, 160]),
+    ("Green", [70, 180, 70]),
+    ("Lime", [160, 210, 50]),
+    ("Yellow", [230, 200, 40]),
+    ("Orange", [240, 140, 40]),
+    ("Red", [220, 60, 60]),
+    ("Pink", [230, 110, 180]),
+    ("Purple", [150, 80, 210]),
+    ("Indigo", [90, 80, 190]),
+    ("Brown", [140, 95, 60]),
+    ("Silver", [170, 170, 180]),
+];
+
+fn placeholder(index: usize) -> UserInfo {
+    let (color_name, color) = PLACEHOLDERS[index % PLACEHOLDERS.len()];
+    UserInfo {
+        name: format!("{color_name} Ghost"),
+        color,
+        avatar: 0,
+        cosmetics: default(),
+        class: default(),
+    }
+}
+
+/// Where `peer_id`'s placeholder starts in [`PLACEHOLDERS`], the same on
+/// every peer
+fn placeholder_index(peer_id: PeerId) -> usize {
+    let mut hasher = Fnv1a::default();
+    hasher.write(peer_id.0.as_bytes());
+    (hasher.finish() % PLACEHOLDERS.len() as u64) as usize
+}
+
+/// Picks each nameless peer's placeholder from its id. Every peer in the room
+/// gets a slot, in peer id order, moving on to the next placeholder while the
+/// one its id points at is already someone else's. That only depends on who
+/// is in the room, not on whose info has arrived, so every peer shows the
+/// same placeholders. Our own info comes from cookies instead.
+fn assign_placeholders(
+    mut commands: Commands,
+    nameless: Query<(Entity, &MatchBoxPeerId), (Without<UserInfo>, Without<IsLocal>)>,
+    peers: Query<&MatchBoxPeerId>,
+) {
+    if nameless.is_empty() {
+        return;
+    }
+    let mut peer_ids = peers.iter().map(|peer_id| peer_id.0).collect::<Vec<_>>();
+    peer_ids.sort();
+    let indices = placeholder_indices(&peer_ids);
+    for (entity, peer_id) in nameless.iter() {
+        let Some(&index) = indices.get(&peer_id.0) else {
+            continue;
+        };
+        commands
+            .entity(entity)
+            .insert((placeholder(index), Placeholder));
+    }
+}
+
+/// Index into [`PLACEHOLDERS`] for each of `peer_ids`, which are sorted
+fn placeholder_indices(peer_ids: &[PeerId]) -> HashMap<PeerId, usize> {
+    let mut taken = Vec::new();
+    let mut indices = HashMap::default();
+    for &peer_id in peer_ids {
+        let start = placeholder_index(peer_id);
+        let index = (start..start + PLACEHOLDERS.len())
+            .map(|index| index % PLACEHOLDERS.len())
+            .find(|index| !taken.contains(index))
+            .unwrap_or(start);
+        taken.push(index);
+        indices.insert(peer_id, index);
+    }
+    indices
+}
@@ -0,0 +1,145 @@
+#[cfg(feature = "presentation")]
+use crate::{
+    animation::AnimatedSprite,
+    avatar::spawn_avatar_emblem,
+    components::{Health, Invulnerable, Player, UserInfo},
+    cooldown_ring::{add_cooldown_ring, Cooldown},
+    dash::DashCooldown,
+    game::ImageAssets,
+    layers::DrawLayer,
+    pickups::SHIELD_FRAMES,
+    rounds::CROWDED_SPAWN_INVULNERABILITY_FRAMES,
+    simulation::apply_damage,
+    GameState, I2F,
+};
+use crate::{input::DIRECTION_SCALE, F2I};
+use bevy::prelude::*;
+#[cfg(feature = "presentation")]
+use bevy_ggrs::GGRSSchedule;
+
+/// How players look: their sprites and emblems, cooldown rings, and hiding
+/// them while they're dead
+#[cfg(feature = "presentation")]
+pub struct PlayerPlugin;
+
+#[cfg(feature = "presentation")]
+impl Plugin for PlayerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(add_player_sprites.in_schedule(OnEnter(GameState::InGame)))
+            .add_system(handle_death.after(apply_damage).in_schedule(GGRSSchedule));
+        add_cooldown_ring::<Invulnerable>(app, Color::rgba(0.6, 0.9, 1., 0.8), 0);
+        add_cooldown_ring::<DashCooldown>(app, Color::rgba(1., 1., 1., 0.6), 1);
+    }
+}
+
+pub const PLAYER_RADIUS_SI: i32 = 5 * F2I / 10;
+#[cfg(feature = "presentation")]
+pub const PLAYER_WIDTH_RF: f32 = PLAYER_RADIUS_SI as f32 * I2F * 2.;
+
+/// Where the player with `handle` starts each round on a map `map_size_si`
+/// wide, and which way they face
+pub fn spawn_position(handle: usize, map_size_si: i32) -> (IVec2, IVec2) {
+    let limit = map_size_si / 2 - PLAYER_RADIUS_SI;
+    // Spread in a row proportional to the map, two units apart on the
+    // default one
+    let spacing = map_size_si / 20;
+    (
+        IVec2::new(((handle as i32 - 4) * spacing).clamp(-limit, limit), 0),
+        -IVec2::new(1, 0) * DIRECTION_SCALE,
+    )
+}
+
+/// Sprites and avatar emblems for the players, which get everything the
+/// simulation needs from [`crate::simulation::insert_player_components`]
+#[cfg(feature = "presentation")]
+fn add_player_sprites(
+    mut commands: Commands,
+    images: Res<ImageAssets>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    players: Query<(Entity, Option<&UserInfo>), With<Player>>,
+) {
+    for (entity, info) in players.iter() {
+        commands.entity(entity).insert((
+            DrawLayer::Players,
+            SpriteSheetBundle {
+                transform: Transform::from_translation(Vec3::new(0., 0., DrawLayer::Players.z())),
+                sprite: TextureAtlasSprite {
+                    color: info.cloned().unwrap_or_default().sprite_color(),
+                    custom_size: Some(Vec2::new(PLAYER_WIDTH_RF, PLAYER_WIDTH_RF)),
+                    ..default()
+                },
+                texture_atlas: images.player_sheet.clone(),
+                ..default()
+            },
+            AnimatedSprite::default(),
+        ));
+        if let Some(info) = info {
+            spawn_avatar_emblem(&mut commands, &mut meshes, &mut materials, entity, info);
+        }
+    }
+}
+
+/// Longest invulnerability anything grants, which a full ring stands for
+#[cfg(feature = "presentation")]
+const MAX_INVULNERABILITY_FRAMES: u32 = if SHIELD_FRAMES > CROWDED_SPAWN_INVULNERABILITY_FRAMES {
+    SHIELD_FRAMES
+} else {
+    CROWDED_SPAWN_INVULNERABILITY_FRAMES
+};
+
+#[cfg(feature = "presentation")]
+impl Cooldown for Invulnerable {
+    fn remaining_fraction(&self) -> Option<f32> {
+        (self.0 > 0).then(|| self.0 as f32 / MAX_INVULNERABILITY_FRAMES as f32)
+    }
+}
+
+#[cfg(feature = "presentation")]
+fn handle_death(mut player_query: Query<(&Health, &mut Visibility), With<Player>>) {
+    for (health, mut visibility) in player_query.iter_mut() {
+        let target = if health.0 > 0 {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+        if *visibility != target {
+            *visibility = target;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lobby_settings::MAX_PLAYERS, MAP_SIZE_SI};
+
+    #[test]
+    fn spawn_positions_are_distinct_and_face_left() {
+        let spawns = (0..MAX_PLAYERS)
+            .map(|handle| spawn_position(handle, MAP_SIZE_SI))
+            .collect::<Vec<_>>();
+        for (handle, (position, direction)) in spawns.iter().enumerate() {
+            assert_eq!(*direction, IVec2::new(-DIRECTION_SCALE, 0));
+            assert_eq!(position.y, 0);
+            assert!(
+                spawns[..handle].iter().all(|(other, _)| other != position),
+                "player {handle} spawns on top of another"
+            );
+        }
+    }
+
+    #[test]
+    fn spawn_positions_stay_on_small_maps() {
+        for map_size_si in [F2I, 2 * F2I, 5 * F2I, MAP_SIZE_SI] {
+            let limit = map_size_si / 2 - PLAYER_RADIUS_SI;
+            for handle in 0..MAX_PLAYERS {
+                let (position, _) = spawn_position(handle, map_size_si);
+                assert!(
+                    position.x.abs() <= limit,
+                    "player {handle} spawns off a map {map_size_si} wide"
+                );
+            }
+        }
+    }
+}
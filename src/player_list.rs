@@ -0,0 +1,346 @@
+use crate::{
+    components::{StartChoice, TabId, UserInfo},
+    connection_quality::{quality_icon, PeerPings},
+    filter::{display_name, WordFilter},
+    kick::{KickPlayer, StopWaiting},
+    mute::MutedPlayers,
+    peer_names::PeerNames,
+    persistence::{read_cookie, write_cookie},
+    recent_players::{recent_players_ui, RecentPlayers},
+    storage::KeyValueStores,
+};
+use bevy::{
+    ecs::system::SystemParam,
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+use bevy_egui::egui::{Button, CollapsingHeader, ScrollArea, TextEdit, Ui};
+use bevy_matchbox::prelude::PeerId;
+use serde::{Deserialize, Serialize};
+
+/// Keeps the lobby's list of other players usable in crowded rooms: players
+/// are grouped, searchable and paged, and which groups are collapsed is
+/// remembered across visits
+pub struct PlayerListPlugin;
+
+impl Plugin for PlayerListPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(PlayerListSettings::from_cookie())
+            .add_system(save_player_list_settings);
+    }
+}
+
+/// Players shown per page of a group
+const PAGE_SIZE: usize = 10;
+/// Height a group grows to before it scrolls
+const GROUP_MAX_HEIGHT: f32 = 240.;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+enum PlayerGroup {
+    Ready,
+    NotReady,
+    Spectators,
+    /// Connected, but still introducing themselves
+    Waiting,
+}
+
+impl PlayerGroup {
+    const ALL: [PlayerGroup; 4] = [
+        PlayerGroup::Ready,
+        PlayerGroup::NotReady,
+        PlayerGroup::Spectators,
+        PlayerGroup::Waiting,
+    ];
+
+    fn title(self) -> &'static str {
+        match self {
+            PlayerGroup::Ready => "Ready",
+            PlayerGroup::NotReady => "Not ready",
+            PlayerGroup::Spectators => "Spectators",
+            PlayerGroup::Waiting => "Waiting",
+        }
+    }
+}
+
+#[derive(Resource, Default, Debug)]
+pub struct PlayerListSettings {
+    collapsed: HashSet<PlayerGroup>,
+}
+
+impl PlayerListSettings {
+    const COOKIE_KEY: &'static str = "player_list";
+
+    fn from_cookie() -> Self {
+        read_cookie(Self::COOKIE_KEY)
+            .map(|collapsed| Self { collapsed })
+            .unwrap_or_default()
+    }
+}
+
+fn save_player_list_settings(settings: Res<PlayerListSettings>, stores: Res<KeyValueStores>) {
+    if settings.is_changed() && !settings.is_added() {
+        write_cookie(&stores, PlayerListSettings::COOKIE_KEY, &settings.collapsed);
+    }
+}
+
+/// What's typed in the search box and the page each group is on. Not worth
+/// remembering past the current lobby.
+#[derive(Default)]
+pub struct PlayerListView {
+    search: String,
+    pages: HashMap<PlayerGroup, usize>,
+}
+
+/// One of the other players, as the list shows them
+pub struct PlayerRow<'a> {
+    /// Position in the unfiltered list, so players keep their number while
+    /// searching
+    pub index: usize,
+    pub peer_id: PeerId,
+    pub info: &'a UserInfo,
+    pub ready: bool,
+    pub choice: StartChoice,
+    pub tab_id: Option<&'a TabId>,
+    pub different_version: bool,
+    pub spectator: bool,
+    /// Still introducing themselves, so `info` may be a placeholder
+    pub waiting: bool,
+}
+
+impl PlayerRow<'_> {
+    fn group(&self) -> PlayerGroup {
+        if self.waiting {
+            PlayerGroup::Waiting
+        } else if self.spectator {
+            PlayerGroup::Spectators
+        } else if self.ready {
+            PlayerGroup::Ready
+        } else {
+            PlayerGroup::NotReady
+        }
+    }
+}
+
+#[derive(SystemParam)]
+pub struct PlayerList<'w, 's> {
+    settings: ResMut<'w, PlayerListSettings>,
+    view: Local<'s, PlayerListView>,
+    muted: ResMut<'w, MutedPlayers>,
+    peer_names: Res<'w, PeerNames>,
+    recent: ResMut<'w, RecentPlayers>,
+    pings: Res<'w, PeerPings>,
+    time: Res<'w, Time>,
+    kicks: EventWriter<'w, KickPlayer>,
+    stop_waiting: EventWriter<'w, StopWaiting>,
+}
+
+/// What the host asked to do with a player
+enum RowAction {
+    Kick,
+    StopWaiting,
+}
+
+impl PlayerList<'_, '_> {
+    /// Shows `rows`, labelling players with their start choice if
+    /// `show_choices` and offering to kick them or stop waiting for them if
+    /// `can_kick`
+    pub fn show(
+        &mut self,
+        ui: &mut Ui,
+        rows: &[PlayerRow],
+        word_filter: &WordFilter,
+        show_choices: bool,
+        can_kick: bool,
+    ) {
+        let Self {
+            settings,
+            view,
+            muted,
+            peer_names,
+            recent,
+            pings,
+            time,
+            kicks,
+            stop_waiting,
+        } = self;
+        ui.heading("Other Players");
+        ui.separator();
+        ui.add(TextEdit::singleline(&mut view.search).hint_text("Search players"));
+        let search = view.search.to_lowercase();
+        // Muted players can only be found by id, like their names are hidden
+        let visible = rows
+            .iter()
+            .filter(|row| {
+                search.is_empty()
+                    || row.peer_id.0.to_string().starts_with(&search)
+                    || (!is_muted(muted, row)
+                        && display_name(word_filter, row.info)
+                            .to_lowercase()
+                            .contains(&search))
+            })
+            .collect::<Vec<_>>();
+
+        for group in PlayerGroup::ALL {
+            let members = visible
+                .iter()
+                .filter(|row| row.group() == group)
+                .collect::<Vec<_>>();
+            let count = members.len();
+            if count == 0 {
+                continue;
+            }
+            let page = view.pages.entry(group).or_default();
+            let response = CollapsingHeader::new(format!("{} ({count})", group.title()))
+                .id_source(group)
+                .default_open(!settings.collapsed.contains(&group))
+                .show(ui, |ui| {
+                    page_controls(ui, page, count);
+                    ScrollArea::vertical()
+                        .id_source(group)
+                        .max_height(GROUP_MAX_HEIGHT)
+                        .show(ui, |ui| {
+                            for row in members.iter().skip(*page * PAGE_SIZE).take(PAGE_SIZE) {
+                                let action = ui
+                                    .horizontal(|ui| {
+                                        quality_icon(ui, pings, time, row.peer_id);
+                                        row_ui(
+                                            ui,
+                                            row,
+                                            muted,
+                                            peer_names,
+                                            word_filter,
+                                            show_choices,
+                                            can_kick,
+                                        )
+                                    })
+                                    .inner;
+                                match (action, row.tab_id) {
+                                    (Some(RowAction::Kick), Some(tab_id)) => {
+                                        kicks.send(KickPlayer(tab_id.clone()))
+                                    }
+                                    (Some(RowAction::StopWaiting), _) => {
+                                        stop_waiting.send(StopWaiting(row.peer_id))
+                                    }
+                                    _ => {}
+                                }
+                            }
+                        });
+                });
+            let collapsed = &mut settings.collapsed;
+            if response.header_response.clicked() && !collapsed.remove(&group) {
+                collapsed.insert(group);
+            }
+        }
+
+        if let Some((tab_id, favorite)) = recent_players_ui(ui, recent, rows, muted, word_filter) {
+            recent.set_favorite(&tab_id, favorite);
+        }
+    }
+}
+
+fn is_muted(muted: &MutedPlayers, row: &PlayerRow) -> bool {
+    row.tab_id.map_or(false, |id| muted.is_muted(id))
+}
+
+/// The name a waiting player had last time, if someone in the room told us
+/// who they are. Muted players stay nameless.
+fn reported_name<'a>(
+    peer_names: &'a PeerNames,
+    muted: &MutedPlayers,
+    row: &PlayerRow,
+) -> Option<&'a str> {
+    if !row.waiting {
+        return None;
+    }
+    peer_names
+        .reported(row.peer_id)
+        .filter(|(tab_id, _)| !muted.is_muted(tab_id))
+        .map(|(_, name)| name)
+}
+
+/// Previous/next buttons for a group of `count` players, keeping `page` in range
+fn page_controls(ui: &mut Ui, page: &mut usize, count: usize) {
+    let pages = (count + PAGE_SIZE - 1) / PAGE_SIZE;
+    *page = (*page).min(pages - 1);
+    if pages <= 1 {
+        return;
+    }
+    ui.horizontal(|ui| {
+        if ui
+            .add_enabled(*page > 0, Button::new("◀").small())
+            .clicked()
+        {
+            *page -= 1;
+        }
+        ui.label(format!("Page {}/{pages}", *page + 1));
+        if ui
+            .add_enabled(*page + 1 < pages, Button::new("▶").small())
+            .clicked()
+        {
+            *page += 1;
+        }
+    });
+}
+
+/// Returns the action the host clicked, if any
+fn row_ui(
+    ui: &mut Ui,
+    row: &PlayerRow,
+    muted: &mut MutedPlayers,
+    peer_names: &PeerNames,
+    word_filter: &WordFilter,
+    show_choice: bool,
+    can_kick: bool,
+) -> Option<RowAction> {
+    ui.horizontal(|ui| {
+        ui.label(if row.ready { "☑" } else { "☐" });
+        let index = row.index;
+        let is_muted = is_muted(muted, row);
+        if is_muted {
+            ui.weak(format!("{index}: (muted)"));
+        } else if let Some(name) = reported_name(peer_names, muted, row) {
+            ui.label(format!("{index}: {}", word_filter.censor(name)));
+        } else {
+            ui.label(format!("{index}: {}", display_name(word_filter, row.info)));
+        }
+        if let Some(tab_id) = row.tab_id {
+            let label = if is_muted { "Unmute" } else { "Mute" };
+            if ui.small_button(label).clicked() {
+                muted.set_muted(tab_id, !is_muted);
+            }
+        }
+        let mut action = None;
+        if can_kick
+            && row.tab_id.is_some()
+            && ui
+                .small_button("Kick")
+                .on_hover_text("Remove from the room until the end of your session")
+                .clicked()
+        {
+            action = Some(RowAction::Kick);
+        }
+        if can_kick
+            && row.waiting
+            && ui
+                .small_button("Stop waiting")
+                .on_hover_text("Start without them")
+                .clicked()
+        {
+            action = Some(RowAction::StopWaiting);
+        }
+        if row.spectator {
+            ui.weak("spectator");
+        }
+        if row.different_version {
+            ui.colored_label(ui.visuals().error_fg_color, "different version");
+        }
+        if show_choice {
+            ui.weak(match row.choice {
+                StartChoice::ResumeSave => "resume",
+                StartChoice::NewGame => "new game",
+            });
+        }
+        action
+    })
+    .inner
+}
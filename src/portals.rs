@@ -0,0 +1,165 @@
+use crate::{
+    components::{Health, Player, Position, Radius},
+    lobby_settings::LobbySettings,
+    maps::CurrentMap,
+    pickups::collect_pickups,
+    rounds::round_in_progress,
+    simulation::{collide_players, fire_bullets},
+    IVec2Ext, F2I,
+};
+#[cfg(feature = "presentation")]
+use crate::{layers::DrawLayer, presentation_clock::PresentationClock, GameState, I2F};
+use bevy::prelude::*;
+use bevy_ggrs::GGRSSchedule;
+use serde::Deserialize;
+
+/// Pairs of portals from the map data. Stepping onto one end puts the player
+/// on the other. Portals are constant during a game like the walls, so they
+/// live in the [`CurrentMap`] and only the players' [`PortalCooldown`] is
+/// rolled back.
+pub struct PortalsPlugin;
+
+impl Plugin for PortalsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(
+            teleport_players
+                .after(collide_players)
+                .before(fire_bullets)
+                .before(collect_pickups)
+                .run_if(round_in_progress)
+                .in_schedule(GGRSSchedule),
+        );
+        #[cfg(feature = "presentation")]
+        app.add_systems(
+            (
+                spawn_portal_sprites,
+                spin_portals.after(spawn_portal_sprites),
+            )
+                .in_set(OnUpdate(GameState::InGame)),
+        )
+        .add_system(despawn_portal_sprites.in_schedule(OnExit(GameState::InGame)));
+    }
+}
+
+pub const PORTAL_RADIUS_SI: i32 = 8 * F2I / 10;
+/// Frames after a teleport before portals work again for that player
+const PORTAL_COOLDOWN_FRAMES: u32 = 30;
+
+/// Two linked portal centers, in whole world units in the map asset
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub struct PortalPair {
+    pub a: IVec2,
+    pub b: IVec2,
+}
+
+/// Frames until portals take the player again. It doesn't run out while the
+/// player still stands on a portal, so arriving on the exit never sends them
+/// straight back.
+#[derive(Component, Reflect, Default, Clone, Copy, Debug)]
+pub struct PortalCooldown(pub u32);
+
+pub fn teleport_players(
+    settings: Res<LobbySettings>,
+    map: Res<CurrentMap>,
+    mut players: Query<(
+        &Player,
+        &Health,
+        &Radius,
+        &mut Position,
+        &mut PortalCooldown,
+    )>,
+) {
+    if map.portals().is_empty() {
+        return;
+    }
+    // Handle order, so every peer teleports players the same way
+    let mut players = players.iter_mut().collect::<Vec<_>>();
+    players.sort_by_key(|(player, ..)| player.handle);
+    for (_, health, radius, mut position, mut cooldown) in players {
+        if health.0 <= 0 {
+            continue;
+        }
+        let reach = (radius.0 + PORTAL_RADIUS_SI) as i64;
+        // Each end of each pair, with where it leads
+        let touched = map
+            .portals()
+            .iter()
+            .flat_map(|pair| [(pair.a, pair.b), (pair.b, pair.a)])
+            .find(|&(portal, _)| {
+                let portal = settings.nearest_image(portal, position.0);
+                (position.0 - portal).norm_sq_wide() < reach * reach
+            });
+        let Some((_, exit)) = touched else {
+            cooldown.0 = cooldown.0.saturating_sub(1);
+            continue;
+        };
+        if cooldown.0 > 0 {
+            cooldown.0 = (cooldown.0 - 1).max(1);
+            continue;
+        }
+        position.0 = exit;
+        cooldown.0 = PORTAL_COOLDOWN_FRAMES;
+    }
+}
+
+#[cfg(feature = "presentation")]
+const PORTAL_COLOR: Color = Color::rgba(0.55, 0.35, 1., 0.7);
+/// Turns per second of the swirl's outer square, the inner one turns the
+/// other way twice as fast
+#[cfg(feature = "presentation")]
+const PORTAL_SPIN_PER_SEC: f32 = 0.4;
+
+/// One square of a portal's swirl, spinning at this many turns per second
+#[cfg(feature = "presentation")]
+#[derive(Component)]
+struct PortalSwirl(f32);
+
+/// Spawns the swirls for the [`CurrentMap`]'s portals once it's loaded, and
+/// again whenever it changes
+#[cfg(feature = "presentation")]
+fn spawn_portal_sprites(
+    mut commands: Commands,
+    map: Res<CurrentMap>,
+    swirls: Query<Entity, With<PortalSwirl>>,
+) {
+    if !map.is_changed() && !(swirls.is_empty() && !map.portals().is_empty()) {
+        return;
+    }
+    for entity in swirls.iter() {
+        commands.entity(entity).despawn();
+    }
+    let size = PORTAL_RADIUS_SI as f32 * I2F * 2.;
+    for portal in map.portals().iter().flat_map(|pair| [pair.a, pair.b]) {
+        let translation = portal.i2f().extend(DrawLayer::Portals.z());
+        for (spin, scale) in [(PORTAL_SPIN_PER_SEC, 1.), (-2. * PORTAL_SPIN_PER_SEC, 0.6)] {
+            commands.spawn((
+                PortalSwirl(spin),
+                DrawLayer::Portals,
+                SpriteBundle {
+                    transform: Transform::from_translation(translation),
+                    sprite: Sprite {
+                        color: PORTAL_COLOR,
+                        custom_size: Some(Vec2::splat(size * scale)),
+                        ..default()
+                    },
+                    ..default()
+                },
+            ));
+        }
+    }
+}
+
+#[cfg(feature = "presentation")]
+fn spin_portals(clock: Res<PresentationClock>, mut swirls: Query<(&PortalSwirl, &mut Transform)>) {
+    let delta = clock.delta_seconds();
+    for (swirl, mut transform) in swirls.iter_mut() {
+        transform.rotate_z(swirl.0 * std::f32::consts::TAU * delta);
+    }
+}
+
+#[cfg(feature = "presentation")]
+fn despawn_portal_sprites(mut commands: Commands, swirls: Query<Entity, With<PortalSwirl>>) {
+    for entity in swirls.iter() {
+        commands.entity(entity).despawn();
+    }
+}
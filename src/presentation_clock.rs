@@ -0,0 +1,146 @@
+use crate::{
+    components::{Player, Position},
+    fixed_timestep::FixedTimestep,
+    GameState, I2F,
+};
+use bevy::{prelude::*, time::TimeSystem, transform::TransformSystem};
+
+/// Keeps presentation smooth through frame hitches. A stalled tab comes back
+/// with a huge frame delta and GGRS simulating many frames at once, so
+/// animations would skip ahead and players and the camera would teleport.
+/// Cosmetic systems read [`PresentationClock`] instead of [`Time`]: its delta
+/// is clamped, and the time lost to a hitch is paid back over the following
+/// frames. Jumps in player positions are turned into short glides the same way.
+pub struct PresentationClockPlugin;
+
+impl Plugin for PresentationClockPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PresentationClock>()
+            .add_system(
+                tick_presentation_clock
+                    .after(TimeSystem)
+                    .in_base_set(CoreSet::First),
+            )
+            .add_systems(
+                (add_smoothing, smooth_players.after(add_smoothing))
+                    .in_base_set(CoreSet::PostUpdate)
+                    .before(TransformSystem::TransformPropagate)
+                    .distributive_run_if(in_state(GameState::InGame)),
+            );
+    }
+}
+
+/// Longest delta a single presentation frame advances by
+const MAX_DELTA_SECS: f32 = 1. / 20.;
+/// Lost time beyond this is dropped rather than caught up with, e.g. after
+/// the tab was in the background
+const MAX_BACKLOG_SECS: f32 = 0.5;
+/// Share of the remaining lost time made up each frame
+const CATCH_UP_FRACTION: f32 = 0.25;
+/// Per-frame moves longer than this are glided over. Normal movement, dashes
+/// included, stays well below it.
+const JUMP_THRESHOLD_RF: f32 = 1.;
+/// Per-frame moves longer than this are respawns or round resets, and snap
+const MAX_GLIDE_RF: f32 = 8.;
+/// How quickly glides close in on the real position, per second
+const GLIDE_RATE: f32 = 15.;
+
+/// Frame time for cosmetic systems, steadied against hitches
+#[derive(Resource, Default)]
+pub struct PresentationClock {
+    delta: f32,
+    /// Time lost to hitches, still to be caught up with
+    backlog: f32,
+    paused: bool,
+    /// Real seconds left at [`PresentationClock::slow_scale`]
+    slow_secs: f32,
+    slow_scale: f32,
+}
+
+impl PresentationClock {
+    pub fn delta_seconds(&self) -> f32 {
+        self.delta
+    }
+
+    /// While paused, frames advance by nothing and no time is caught up with
+    /// afterwards
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    /// Runs cosmetic time at `scale` for the next `secs` real seconds. The
+    /// time this skips isn't caught up with afterwards. A shorter slowdown
+    /// doesn't cut a running one short.
+    pub fn slow_down(&mut self, secs: f32, scale: f32) {
+        if secs >= self.slow_secs {
+            self.slow_secs = secs;
+            self.slow_scale = scale;
+        }
+    }
+}
+
+/// Goes by real time, since [`Time`] moves in whole simulation ticks during a
+/// game, see [`crate::fixed_timestep`]
+fn tick_presentation_clock(timestep: Res<FixedTimestep>, mut clock: ResMut<PresentationClock>) {
+    if clock.paused {
+        clock.delta = 0.;
+        clock.backlog = 0.;
+        return;
+    }
+    let raw = timestep.real_delta_seconds();
+    let clamped = raw.min(MAX_DELTA_SECS);
+    let backlog = (clock.backlog + raw - clamped).min(MAX_BACKLOG_SECS);
+    let catch_up = (backlog * CATCH_UP_FRACTION).min(MAX_DELTA_SECS - clamped);
+    clock.backlog = backlog - catch_up;
+    clock.delta = clamped + catch_up;
+    if clock.slow_secs > 0. {
+        clock.slow_secs -= clamped;
+        clock.delta *= clock.slow_scale;
+    }
+}
+
+/// Follows a target point, gliding over sudden jumps instead of teleporting
+#[derive(Component, Default)]
+pub struct Smoothing {
+    last_target: Option<Vec2>,
+    /// Where the glide currently is relative to the target
+    offset: Vec2,
+}
+
+impl Smoothing {
+    /// Where to show something that is really at `target`
+    pub fn follow(&mut self, target: Vec2, clock: &PresentationClock) -> Vec2 {
+        if let Some(last) = self.last_target.replace(target) {
+            let step = (target - last).length();
+            if step > MAX_GLIDE_RF {
+                self.offset = Vec2::ZERO;
+            } else if step > JUMP_THRESHOLD_RF {
+                self.offset += last - target;
+            }
+        }
+        self.offset *= (-GLIDE_RATE * clock.delta_seconds()).exp();
+        if self.offset.length() < 0.01 {
+            self.offset = Vec2::ZERO;
+        }
+        target + self.offset
+    }
+}
+
+fn add_smoothing(
+    mut commands: Commands,
+    players: Query<Entity, (With<Player>, Without<Smoothing>)>,
+) {
+    for player in players.iter() {
+        commands.entity(player).insert(Smoothing::default());
+    }
+}
+
+fn smooth_players(
+    clock: Res<PresentationClock>,
+    mut players: Query<(&Position, &mut Smoothing, &mut Transform), With<Player>>,
+) {
+    for (position, mut smoothing, mut transform) in players.iter_mut() {
+        let shown = smoothing.follow(position.0.as_vec2() * I2F, &clock);
+        transform.translation = shown.extend(transform.translation.z);
+    }
+}
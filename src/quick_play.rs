@@ -0,0 +1,282 @@
+use crate::{
+    components::{IsLocal, IsReady, MatchBoxPeerId},
+    lobby::{SaveTransfers, SocketExt},
+    lobby_settings::{lobby_host, MAX_PLAYERS},
+    match_config::Fnv1a,
+    net::{Messages, P2PMessage},
+    room::Room,
+    server::{connect_to_room, ConnectionStatus, ServerConfig},
+    GameState,
+};
+use bevy::prelude::*;
+use bevy_egui::{
+    egui::{Align2, Window},
+    EguiContexts,
+};
+use bevy_matchbox::{
+    prelude::{MultipleChannels, PeerId},
+    MatchboxSocket,
+};
+use serde::{Deserialize, Serialize};
+
+/// Matchmaking without sharing a room name. Everyone queues in one public
+/// room; once enough players are waiting, the queue's host splits them into
+/// matches and sends each peer the name of the room its match meets in.
+pub struct QuickPlayPlugin;
+
+impl Plugin for QuickPlayPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            (
+                run_queue,
+                join_match_room.after(run_queue),
+                auto_ready.after(join_match_room),
+                quick_play_ui,
+            )
+                .in_set(OnUpdate(GameState::Matchmaking))
+                .distributive_run_if(resource_exists::<QuickPlay>()),
+        );
+    }
+}
+
+/// Room everyone queues in
+pub const QUEUE_ROOM: &str = "quick_play";
+/// Fewest queued players that start the countdown
+const MIN_PLAYERS: usize = 2;
+/// Players the queue tries to put in each match
+const MATCH_SIZE: usize = 4;
+/// Time for more players to join once there are enough for a match
+const COUNTDOWN_SECS: f64 = 10.;
+/// How long a match room waits for everyone assigned to it before starting
+/// with whoever made it
+const MATCH_ROOM_WAIT_SECS: f64 = 15.;
+/// Longest the queue's host stays in the queue for the peers it assigned to
+/// leave
+const ASSIGNMENT_SEND_WAIT_SECS: f64 = 5.;
+
+/// A match the queue's host assigned us to
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct QuickPlayMatch {
+    pub room: String,
+    pub players: u32,
+}
+
+/// Present while playing quick play instead of in a chosen room
+#[derive(Resource, Debug)]
+pub enum QuickPlay {
+    /// Waiting in [`QUEUE_ROOM`]. The countdown only runs while enough
+    /// players are queued.
+    Queued { countdown_started: Option<f64> },
+    /// Assigned to a match, but still connected to the queue. Replacing the
+    /// socket drops what it hasn't sent yet, so the queue's host stays until
+    /// the peers it sent assignments to have left, which they do once they
+    /// got them, or for at most [`ASSIGNMENT_SEND_WAIT_SECS`].
+    Assigned {
+        assignment: QuickPlayMatch,
+        /// Peers we sent their assignments to
+        notified: Vec<PeerId>,
+        assigned_at: f64,
+    },
+    /// In the match's room, waiting to ready up once the match is complete.
+    /// From then on the room works like any other.
+    Joined { players: usize, joined_at: f64 },
+}
+
+impl QuickPlay {
+    pub fn queue() -> (Self, Room) {
+        (
+            Self::Queued {
+                countdown_started: None,
+            },
+            Room(QUEUE_ROOM.to_string()),
+        )
+    }
+
+    /// Handles an assignment received from the queue's host, or made as the
+    /// host after sending `notified` theirs
+    pub fn assign(&mut self, assignment: QuickPlayMatch, notified: Vec<PeerId>, now: f64) {
+        if matches!(self, Self::Queued { .. }) {
+            info!("Assigned to quick play match in {}", assignment.room);
+            *self = Self::Assigned {
+                assignment,
+                notified,
+                assigned_at: now,
+            };
+        }
+    }
+}
+
+/// Splits queued peers into matches of about [`MATCH_SIZE`]. A remainder too
+/// small to play joins the last full match. Every peer computes the same split
+/// from the same peers.
+fn split_into_matches(mut peers: Vec<PeerId>) -> Vec<Vec<PeerId>> {
+    peers.sort();
+    let mut matches = peers
+        .chunks(MATCH_SIZE)
+        .map(|chunk| chunk.to_vec())
+        .collect::<Vec<_>>();
+    if matches.len() > 1
+        && matches
+            .last()
+            .map_or(false, |last| last.len() < MIN_PLAYERS)
+    {
+        let remainder = matches.pop().unwrap();
+        let last = matches.last_mut().unwrap();
+        if last.len() + remainder.len() <= MAX_PLAYERS {
+            last.extend(remainder);
+        }
+    }
+    matches
+}
+
+/// Names a match's room after its players, so each match gets its own room
+fn match_room_name(players: &[PeerId]) -> String {
+    let mut hasher = Fnv1a::default();
+    for player in players {
+        hasher.write(player.0.as_bytes());
+    }
+    format!("{QUEUE_ROOM}-{:016x}", hasher.finish())
+}
+
+fn run_queue(
+    mut quick_play: ResMut<QuickPlay>,
+    mut socket: ResMut<MatchboxSocket<MultipleChannels>>,
+    peers: Query<&MatchBoxPeerId>,
+    local_player: Query<&MatchBoxPeerId, With<IsLocal>>,
+    time: Res<Time>,
+) {
+    let QuickPlay::Queued { countdown_started } = &mut *quick_play else {
+        return;
+    };
+    let Ok(local_peer_id) = local_player.get_single() else {
+        return;
+    };
+    let now = time.elapsed_seconds_f64();
+    if peers.iter().len() < MIN_PLAYERS {
+        *countdown_started = None;
+        return;
+    }
+    let started = *countdown_started.get_or_insert(now);
+    // Everyone shows the countdown, but only the host acts on it
+    if now - started < COUNTDOWN_SECS || lobby_host(peers.iter()) != Some(local_peer_id.0) {
+        return;
+    }
+    let mut own_match = None;
+    let mut notified = Vec::new();
+    for players in split_into_matches(peers.iter().map(|peer| peer.0).collect()) {
+        if players.len() < MIN_PLAYERS {
+            continue;
+        }
+        let assignment = QuickPlayMatch {
+            room: match_room_name(&players),
+            players: players.len() as u32,
+        };
+        for player in players {
+            if player == local_peer_id.0 {
+                own_match = Some(assignment.clone());
+            } else {
+                socket.send_p2p_message(&player, P2PMessage::QuickPlay(assignment.clone()));
+                notified.push(player);
+            }
+        }
+    }
+    if let Some(assignment) = own_match {
+        quick_play.assign(assignment, notified, now);
+    }
+}
+
+/// Leaves the queue for the match room, starting the lobby over there
+fn join_match_room(
+    mut commands: Commands,
+    mut quick_play: ResMut<QuickPlay>,
+    peers: Query<Entity, With<MatchBoxPeerId>>,
+    socket: Res<MatchboxSocket<MultipleChannels>>,
+    server: Res<ServerConfig>,
+    mut room: ResMut<Room>,
+    mut status: ResMut<ConnectionStatus>,
+    mut messages: ResMut<Messages>,
+    mut transfers: ResMut<SaveTransfers>,
+    time: Res<Time>,
+) {
+    let QuickPlay::Assigned {
+        assignment,
+        notified,
+        assigned_at,
+    } = &*quick_play
+    else {
+        return;
+    };
+    let now = time.elapsed_seconds_f64();
+    let sending = socket
+        .connected_peers()
+        .any(|peer| notified.contains(&peer));
+    if sending && now - assigned_at < ASSIGNMENT_SEND_WAIT_SECS {
+        return;
+    }
+    room.0 = assignment.room.clone();
+    // We get a new peer id in the new room, so the local player is
+    // recreated as well
+    for entity in peers.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    messages.0.clear();
+    transfers.clear();
+    commands.insert_resource(connect_to_room(&server, &room));
+    *status = ConnectionStatus::Disconnected;
+    *quick_play = QuickPlay::Joined {
+        players: assignment.players as usize,
+        joined_at: now,
+    };
+}
+
+fn auto_ready(
+    mut commands: Commands,
+    quick_play: Res<QuickPlay>,
+    peers: Query<&MatchBoxPeerId>,
+    mut local_player: Query<&mut IsReady, With<IsLocal>>,
+    time: Res<Time>,
+) {
+    let QuickPlay::Joined { players, joined_at } = *quick_play else {
+        return;
+    };
+    let Ok(mut ready) = local_player.get_single_mut() else {
+        return;
+    };
+    let present = peers.iter().len();
+    let waited = time.elapsed_seconds_f64() - joined_at;
+    if present >= players || (waited >= MATCH_ROOM_WAIT_SECS && present >= MIN_PLAYERS) {
+        ready.0 = true;
+        commands.remove_resource::<QuickPlay>();
+    }
+}
+
+fn quick_play_ui(
+    mut contexts: EguiContexts,
+    quick_play: Res<QuickPlay>,
+    peers: Query<&MatchBoxPeerId>,
+    time: Res<Time>,
+) {
+    let present = peers.iter().len();
+    let status = match *quick_play {
+        QuickPlay::Queued {
+            countdown_started: None,
+        } => format!("Waiting for players ({present}/{MIN_PLAYERS})"),
+        QuickPlay::Queued {
+            countdown_started: Some(started),
+        } => {
+            let left = (COUNTDOWN_SECS - (time.elapsed_seconds_f64() - started)).max(0.);
+            format!("{present} players queued, match starts in {left:.0}s")
+        }
+        QuickPlay::Assigned { .. } => "Joining match...".to_string(),
+        QuickPlay::Joined { players, .. } => {
+            format!("Waiting for your match ({present}/{players})")
+        }
+    };
+    Window::new("Quick play")
+        .anchor(Align2::CENTER_TOP, [0., 10.])
+        .collapsible(false)
+        .resizable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.label(status);
+        });
+}
@@ -0,0 +1,263 @@
+use crate::{
+    components::{IsLocal, IsReady, MatchBoxPeerId, TabId, UserInfo},
+    filter::{display_name, WordFilter},
+    fixed_timestep::FixedTimestep,
+    lobby::PracticeMode,
+    mute::MutedPlayers,
+    persistence::{read_from, write_to},
+    placeholder::Placeholder,
+    player_list::PlayerRow,
+    storage::{KeyValueStore, KeyValueStores, StorageArea},
+    GameState,
+};
+use bevy::prelude::*;
+use bevy_egui::{
+    egui::{Align2, Area, CollapsingHeader, Frame, RichText, Ui},
+    EguiContexts,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Remembers the peers this tab has played with, keyed by [`TabId`], and
+/// lists them in the lobby with whether they're in the room right now.
+/// Players marked as favorites are announced when they get ready.
+pub struct RecentPlayersPlugin;
+
+impl Plugin for RecentPlayersPlugin {
+    fn build(&self, app: &mut App) {
+        let stores = KeyValueStores::get();
+        app.insert_resource(RecentPlayers::load(stores.area(StorageArea::Local)))
+            .init_resource::<FavoriteToasts>()
+            .add_system(record_recent_players.in_schedule(OnEnter(GameState::InGame)))
+            .add_systems(
+                (see_recent_players, announce_ready_favorites)
+                    .in_set(OnUpdate(GameState::Matchmaking)),
+            )
+            .add_system(favorite_toasts_ui)
+            .add_system(save_recent_players);
+    }
+}
+
+const RECENT_PLAYERS_STORAGE_KEY: &str = "recent_players";
+/// Players beyond this are forgotten, least recently seen first. Favorites
+/// are never forgotten.
+const MAX_RECENT_PLAYERS: usize = 30;
+/// Seconds a favorite's ready notification stays on screen
+const TOAST_SECS: f32 = 4.;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RecentPlayer {
+    pub tab_id: String,
+    /// Name when last seen
+    pub name: String,
+    pub last_seen: DateTime<Utc>,
+    pub favorite: bool,
+}
+
+/// Peers played with, most recently seen first
+#[derive(Resource, Default, Debug)]
+pub struct RecentPlayers(Vec<RecentPlayer>);
+
+impl RecentPlayers {
+    fn load(local: &dyn KeyValueStore) -> Self {
+        read_from(local, RECENT_PLAYERS_STORAGE_KEY)
+            .map(Self)
+            .unwrap_or_default()
+    }
+
+    fn find(&self, tab_id: &TabId) -> Option<&RecentPlayer> {
+        self.0.iter().find(|player| player.tab_id == tab_id.0)
+    }
+
+    pub fn is_favorite(&self, tab_id: &TabId) -> bool {
+        self.find(tab_id).map_or(false, |player| player.favorite)
+    }
+
+    /// Moves the player to the front with `name`, adding them if new
+    fn seen(&mut self, tab_id: &TabId, name: &str) {
+        let favorite = self.is_favorite(tab_id);
+        self.0.retain(|player| player.tab_id != tab_id.0);
+        self.0.insert(
+            0,
+            RecentPlayer {
+                tab_id: tab_id.0.clone(),
+                name: name.to_string(),
+                last_seen: Utc::now(),
+                favorite,
+            },
+        );
+        while self.0.len() > MAX_RECENT_PLAYERS {
+            let Some(oldest) = self.0.iter().rposition(|player| !player.favorite) else {
+                break;
+            };
+            self.0.remove(oldest);
+        }
+    }
+
+    pub fn set_favorite(&mut self, tab_id: &str, favorite: bool) {
+        if let Some(player) = self.0.iter_mut().find(|player| player.tab_id == tab_id) {
+            player.favorite = favorite;
+        }
+    }
+}
+
+/// Remembers everyone else in the room when an online game starts
+fn record_recent_players(
+    mut recent: ResMut<RecentPlayers>,
+    practice: Option<Res<PracticeMode>>,
+    peers: Query<
+        (&TabId, &UserInfo),
+        (With<MatchBoxPeerId>, Without<IsLocal>, Without<Placeholder>),
+    >,
+) {
+    if practice.is_some() {
+        return;
+    }
+    for (tab_id, info) in peers.iter() {
+        recent.seen(tab_id, &info.name);
+    }
+}
+
+/// Keeps the names and last seen times of recent players who join the room
+/// up to date, without adding anyone new
+fn see_recent_players(
+    mut recent: ResMut<RecentPlayers>,
+    introduced: Query<
+        (&TabId, &UserInfo),
+        (
+            Or<(Changed<TabId>, Changed<UserInfo>)>,
+            Without<IsLocal>,
+            Without<Placeholder>,
+        ),
+    >,
+) {
+    for (tab_id, info) in introduced.iter() {
+        if recent.find(tab_id).is_some() {
+            recent.seen(tab_id, &info.name);
+        }
+    }
+}
+
+/// Names of favorites who just got ready, with seconds left on screen
+#[derive(Resource, Default)]
+struct FavoriteToasts(Vec<(String, f32)>);
+
+fn announce_ready_favorites(
+    recent: Res<RecentPlayers>,
+    muted: Res<MutedPlayers>,
+    word_filter: Res<WordFilter>,
+    mut toasts: ResMut<FavoriteToasts>,
+    ready: Query<(&TabId, &UserInfo, &IsReady), (Changed<IsReady>, Without<IsLocal>)>,
+) {
+    for (tab_id, info, ready) in ready.iter() {
+        if ready.0 && recent.is_favorite(tab_id) && !muted.is_muted(tab_id) {
+            toasts
+                .0
+                .push((display_name(&word_filter, info).to_string(), TOAST_SECS));
+        }
+    }
+}
+
+fn favorite_toasts_ui(
+    mut contexts: EguiContexts,
+    timestep: Res<FixedTimestep>,
+    mut toasts: ResMut<FavoriteToasts>,
+) {
+    if toasts.0.is_empty() {
+        return;
+    }
+    let delta = timestep.real_delta_seconds();
+    toasts.0.retain_mut(|(_, secs_left)| {
+        *secs_left -= delta;
+        *secs_left > 0.
+    });
+    Area::new("favorite_toasts")
+        .anchor(Align2::RIGHT_BOTTOM, [-10., -10.])
+        .interactable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            for (name, _) in toasts.0.iter() {
+                Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.label(RichText::new("Favorite ready").small());
+                    ui.label(RichText::new(name).strong());
+                });
+            }
+        });
+}
+
+fn save_recent_players(recent: Res<RecentPlayers>, stores: Res<KeyValueStores>) {
+    if recent.is_changed() && !recent.is_added() {
+        write_to(
+            stores.area(StorageArea::Local),
+            RECENT_PLAYERS_STORAGE_KEY,
+            &recent.0,
+        );
+    }
+}
+
+/// The lobby's "Recent players" section. Players are online while one of
+/// `rows` has their tab id, and favorites who are ready stand out. Returns
+/// the tab id of a player whose favorite star was clicked, with its new state.
+pub fn recent_players_ui(
+    ui: &mut Ui,
+    recent: &RecentPlayers,
+    rows: &[PlayerRow],
+    muted: &MutedPlayers,
+    word_filter: &WordFilter,
+) -> Option<(String, bool)> {
+    if recent.0.is_empty() {
+        return None;
+    }
+    let mut toggled = None;
+    let online = recent
+        .0
+        .iter()
+        .filter(|player| row_of(rows, player).is_some())
+        .count();
+    CollapsingHeader::new(format!("Recent players ({online} online)"))
+        .id_source("recent_players")
+        .show(ui, |ui| {
+            // Favorites first, each part still most recently seen first
+            let favorites = recent.0.iter().filter(|player| player.favorite);
+            let others = recent.0.iter().filter(|player| !player.favorite);
+            for player in favorites.chain(others) {
+                let row = row_of(rows, player);
+                ui.horizontal(|ui| {
+                    let star = if player.favorite { "★" } else { "☆" };
+                    if ui
+                        .small_button(star)
+                        .on_hover_text("Favorites are announced when they get ready")
+                        .clicked()
+                    {
+                        toggled = Some((player.tab_id.clone(), !player.favorite));
+                    }
+                    if row.is_some() {
+                        ui.label("●").on_hover_text("In this room");
+                    } else {
+                        ui.weak("○").on_hover_text("Not in this room");
+                    }
+                    let name = if muted.is_muted(&TabId(player.tab_id.clone())) {
+                        RichText::new("(muted)").weak()
+                    } else {
+                        RichText::new(word_filter.censor(&player.name))
+                    };
+                    let ready = row.map_or(false, |row| row.ready);
+                    if player.favorite && ready {
+                        ui.label(
+                            name.strong()
+                                .background_color(ui.visuals().selection.bg_fill),
+                        );
+                        ui.label("ready");
+                    } else {
+                        ui.label(name);
+                        ui.weak(player.last_seen.format("%Y-%m-%d").to_string());
+                    }
+                });
+            }
+        });
+    toggled
+}
+
+fn row_of<'r, 'a>(rows: &'r [PlayerRow<'a>], player: &RecentPlayer) -> Option<&'r PlayerRow<'a>> {
+    rows.iter()
+        .find(|row| row.tab_id.map_or(false, |tab_id| tab_id.0 == player.tab_id))
+}
@@ -0,0 +1,277 @@
+use crate::{
+    components::{IsLocal, SaveOffer, TabId, UserInfo},
+    filter::WordFilter,
+    lobby::{AutoResume, PracticeMode, Spectating},
+    placeholder::Placeholder,
+    room::Room,
+    storage::{KeyValueStore, KeyValueStores, StorageArea},
+    wire_format::{Ron, TextFormat},
+    GameState,
+};
+use bevy::prelude::*;
+use bevy_egui::{
+    egui::{Align2, Window},
+    EguiContexts,
+};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Brings a player who refreshed mid-game back into it. The running game is
+/// noted in session storage, which survives a refresh of the same tab. On
+/// startup a recent note skips room selection, and the lobby waits for the
+/// other players and readies up to resume, like after a peer dropped out. If
+/// they don't show up or there's nothing to resume, the normal lobby takes
+/// over.
+pub struct ReconnectPlugin;
+
+impl Plugin for ReconnectPlugin {
+    fn build(&self, app: &mut App) {
+        let stores = KeyValueStores::get();
+        let session = stores.area(StorageArea::Session);
+        if let Some(game) = ActiveGame::load(session).filter(ActiveGame::is_recent) {
+            info!("Found a running game in room {}", game.room);
+            app.insert_resource(Reconnecting {
+                game,
+                started_at: None,
+                resuming: false,
+            });
+        }
+        app.add_system(rejoin_room.in_schedule(OnEnter(GameState::RoomSelect)))
+            .add_system(remember_game.in_schedule(OnEnter(GameState::InGame)))
+            .add_system(refresh_game.in_set(OnUpdate(GameState::InGame)))
+            .add_systems(
+                (resume_when_back, reconnect_ui.after(resume_when_back))
+                    .in_set(OnUpdate(GameState::Matchmaking))
+                    .distributive_run_if(resource_exists::<Reconnecting>()),
+            )
+            .add_system(finish_reconnect.in_schedule(OnExit(GameState::Matchmaking)))
+            .add_system(
+                reconnect_failed_ui
+                    .in_set(OnUpdate(GameState::Matchmaking))
+                    .run_if(resource_exists::<ReconnectFailed>()),
+            );
+    }
+}
+
+const STORAGE_KEY: &str = "active_game";
+/// How often the note of a running game is refreshed
+const REFRESH_SECS: f64 = 10.;
+/// Notes older than this are from games that are likely over
+const MAX_AGE_SECS: i64 = 120;
+/// How long to wait for everyone to come back before giving up
+const RECONNECT_TIMEOUT_SECS: f64 = 30.;
+
+/// The game this tab is playing, kept across refreshes
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct ActiveGame {
+    room: String,
+    /// Tab ids and names of the other players
+    players: Vec<(String, String)>,
+    updated_at: DateTime<Utc>,
+}
+
+impl ActiveGame {
+    fn load(session: &dyn KeyValueStore) -> Option<Self> {
+        let value = session.get(STORAGE_KEY)?;
+        Ron::from_text(&value).ok()
+    }
+
+    fn store(&self, session: &dyn KeyValueStore) {
+        if let Ok(value) = Ron::to_text(self) {
+            let _ = session.set(STORAGE_KEY, &value);
+        }
+    }
+
+    fn forget(session: &dyn KeyValueStore) {
+        session.remove(STORAGE_KEY);
+    }
+
+    fn is_recent(&self) -> bool {
+        Utc::now() - self.updated_at < Duration::seconds(MAX_AGE_SECS)
+    }
+
+    /// "Alice", "Alice and Bob", "Alice, Bob and Carol", censored by
+    /// `word_filter`
+    fn player_names(&self, word_filter: &WordFilter) -> String {
+        let names = self
+            .players
+            .iter()
+            .map(|(_, name)| word_filter.censor(name))
+            .collect::<Vec<_>>();
+        match names.split_last() {
+            None => "the others".to_string(),
+            Some((last, [])) => last.to_string(),
+            Some((last, rest)) => format!("{} and {last}", rest.join(", ")),
+        }
+    }
+}
+
+/// Present from startup until the game we refreshed out of is resumed or
+/// given up on
+#[derive(Resource)]
+struct Reconnecting {
+    game: ActiveGame,
+    /// When we started waiting in the lobby
+    started_at: Option<f64>,
+    resuming: bool,
+}
+
+/// Why we ended up in the normal lobby instead of back in the game
+#[derive(Resource)]
+struct ReconnectFailed(String);
+
+/// Goes straight back to the room of the interrupted game. Any later visit to
+/// room selection means the player left on purpose.
+fn rejoin_room(
+    mut commands: Commands,
+    reconnecting: Option<Res<Reconnecting>>,
+    mut room: ResMut<Room>,
+    stores: Res<KeyValueStores>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    match reconnecting {
+        Some(reconnecting) if reconnecting.started_at.is_none() => {
+            room.0 = reconnecting.game.room.clone();
+            next_state.set(GameState::Matchmaking);
+        }
+        _ => {
+            commands.remove_resource::<Reconnecting>();
+            ActiveGame::forget(stores.area(StorageArea::Session));
+        }
+    }
+}
+
+fn remember_game(
+    room: Res<Room>,
+    practice: Option<Res<PracticeMode>>,
+    spectating: Option<Res<Spectating>>,
+    others: Query<(&TabId, &UserInfo), Without<IsLocal>>,
+    stores: Res<KeyValueStores>,
+) {
+    let session = stores.area(StorageArea::Session);
+    if practice.is_some() || spectating.is_some() {
+        ActiveGame::forget(session);
+        return;
+    }
+    ActiveGame {
+        room: room.0.clone(),
+        players: others
+            .iter()
+            .map(|(tab_id, info)| (tab_id.0.clone(), info.name.clone()))
+            .collect(),
+        updated_at: Utc::now(),
+    }
+    .store(session);
+}
+
+fn refresh_game(time: Res<Time>, stores: Res<KeyValueStores>, mut last_refresh: Local<f64>) {
+    let now = time.elapsed_seconds_f64();
+    if now - *last_refresh < REFRESH_SECS {
+        return;
+    }
+    *last_refresh = now;
+    let session = stores.area(StorageArea::Session);
+    if let Some(mut game) = ActiveGame::load(session) {
+        game.updated_at = Utc::now();
+        game.store(session);
+    }
+}
+
+/// Readies up to resume once everyone from the interrupted game is back, or
+/// falls back to the normal lobby
+fn resume_when_back(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut reconnecting: ResMut<Reconnecting>,
+    // Peers offer their save before sending their user info, so once that
+    // arrived we know about any save they have
+    peers: Query<&TabId, (With<UserInfo>, Without<Placeholder>, Without<IsLocal>)>,
+    offers: Query<&SaveOffer>,
+    stores: Res<KeyValueStores>,
+    word_filter: Res<WordFilter>,
+) {
+    if reconnecting.resuming {
+        return;
+    }
+    let now = time.elapsed_seconds_f64();
+    let started_at = *reconnecting.started_at.get_or_insert(now);
+    let all_back = reconnecting
+        .game
+        .players
+        .iter()
+        .all(|(tab_id, _)| peers.iter().any(|peer| peer.0 == *tab_id));
+    let failure = if all_back && offers.is_empty() {
+        Some("Nobody has a save of your last game.".to_string())
+    } else if !all_back && now - started_at > RECONNECT_TIMEOUT_SECS {
+        Some(format!(
+            "{} didn't come back in time.",
+            reconnecting.game.player_names(&word_filter)
+        ))
+    } else {
+        None
+    };
+    if let Some(reason) = failure {
+        info!("Giving up on reconnecting: {reason}");
+        commands.remove_resource::<Reconnecting>();
+        commands.insert_resource(ReconnectFailed(reason));
+        ActiveGame::forget(stores.area(StorageArea::Session));
+    } else if all_back {
+        info!("Everyone is back, resuming");
+        reconnecting.resuming = true;
+        commands.init_resource::<AutoResume>();
+    }
+}
+
+fn finish_reconnect(mut commands: Commands) {
+    commands.remove_resource::<Reconnecting>();
+    commands.remove_resource::<ReconnectFailed>();
+}
+
+fn reconnect_ui(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    reconnecting: Res<Reconnecting>,
+    peers: Query<&TabId, Without<IsLocal>>,
+    stores: Res<KeyValueStores>,
+    word_filter: Res<WordFilter>,
+) {
+    Window::new("Reconnecting")
+        .anchor(Align2::CENTER_CENTER, [0., 0.])
+        .collapsible(false)
+        .resizable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.label(format!(
+                "Reconnecting to your game with {}…",
+                reconnecting.game.player_names(&word_filter)
+            ));
+            for (tab_id, name) in reconnecting.game.players.iter() {
+                let back = peers.iter().any(|peer| peer.0 == *tab_id);
+                let name = word_filter.censor(name);
+                ui.label(format!("{} {name}", if back { "✔" } else { "…" }));
+            }
+            if reconnecting.resuming {
+                ui.label("Everyone is back, resuming the game.");
+            } else if ui.button("Go to the lobby instead").clicked() {
+                commands.remove_resource::<Reconnecting>();
+                ActiveGame::forget(stores.area(StorageArea::Session));
+            }
+        });
+}
+
+fn reconnect_failed_ui(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    failed: Res<ReconnectFailed>,
+) {
+    Window::new("Couldn't resume your game")
+        .anchor(Align2::CENTER_CENTER, [0., 0.])
+        .collapsible(false)
+        .resizable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.label(&failed.0);
+            ui.label("You're in the lobby of the room instead.");
+            if ui.button("OK").clicked() {
+                commands.remove_resource::<ReconnectFailed>();
+            }
+        });
+}
@@ -0,0 +1,51 @@
+use bevy::prelude::*;
+
+/// Deterministic random number generator for the simulation. It's a rollback
+/// resource, so every peer draws the same numbers on the same frame.
+#[derive(Resource, Reflect, Clone, Copy, Debug)]
+#[reflect(Resource)]
+pub struct SimRng {
+    state: u64,
+}
+
+impl Default for SimRng {
+    fn default() -> Self {
+        Self::from_seed(0x2545_f491_4f6c_dd1d)
+    }
+}
+
+impl SimRng {
+    pub fn from_seed(seed: u64) -> Self {
+        // xorshift gets stuck on zero
+        Self { state: seed.max(1) }
+    }
+
+    /// Raw state, for comparing peers' simulations
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    /// xorshift64*
+    pub fn next_u32(&mut self) -> u32 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        (self.state.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 32) as u32
+    }
+
+    /// Uniform in `min..max`
+    pub fn range_i32(&mut self, min: i32, max: i32) -> i32 {
+        debug_assert!(min < max);
+        let span = (max as i64 - min as i64) as u64;
+        (min as i64 + (self.next_u32() as u64 % span) as i64) as i32
+    }
+}
+
+/// Number of simulation frames advanced so far, rolled back with the world
+#[derive(Resource, Reflect, Default, Clone, Copy, Debug)]
+#[reflect(Resource)]
+pub struct SimFrame(pub u32);
+
+pub fn advance_sim_frame(mut frame: ResMut<SimFrame>) {
+    frame.0 = frame.0.wrapping_add(1);
+}
@@ -0,0 +1,136 @@
+use crate::{
+    kick::Kicked,
+    launch_config::LaunchConfig,
+    onboarding::NeedsOnboarding,
+    quick_play::QuickPlay,
+    storage::{KeyValueStore, KeyValueStores, StorageArea},
+    GameState,
+};
+use bevy::prelude::*;
+use bevy_egui::{
+    egui::{Align2, Button, Key, TextEdit, Window},
+    EguiContexts,
+};
+
+/// Pre-lobby screen where the player picks which matchbox room to join, so
+/// friends can meet in a private room instead of the public one
+pub struct RoomSelectPlugin;
+
+impl Plugin for RoomSelectPlugin {
+    fn build(&self, app: &mut App) {
+        let stores = KeyValueStores::get();
+        let room = Room::from_cookie(stores.area(StorageArea::Cookies));
+        app.insert_resource(room.unwrap_or_default()).add_systems(
+            (join_launch_room, room_select_ui.after(join_launch_room))
+                .in_set(OnUpdate(GameState::RoomSelect)),
+        );
+    }
+}
+
+pub const MAX_ROOM_NAME_LENGTH: usize = 32;
+
+/// Name of the matchbox room to join
+#[derive(Resource, Clone, Debug, PartialEq)]
+pub struct Room(pub String);
+
+impl Default for Room {
+    fn default() -> Self {
+        Self("web_ghost".to_string())
+    }
+}
+
+impl Room {
+    const COOKIE_KEY: &'static str = "room";
+
+    fn from_cookie(cookies: &dyn KeyValueStore) -> Option<Self> {
+        let name = cookies.get(Self::COOKIE_KEY)?;
+        is_valid_room_name(&name).then_some(Self(name))
+    }
+
+    /// Remembers this room as the one to offer next time
+    pub fn save_to_cookie(&self, cookies: &dyn KeyValueStore) {
+        let _ = cookies.set(Self::COOKIE_KEY, &self.0);
+    }
+}
+
+/// Room names end up in the socket URL path, so keep them to URL-safe characters
+pub fn is_valid_room_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_ROOM_NAME_LENGTH
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Skips the room select screen for launch links naming a room or asking for
+/// a sync test, without remembering the room for next time
+fn join_launch_room(
+    mut launch: ResMut<LaunchConfig>,
+    mut room: ResMut<Room>,
+    mut next_state: ResMut<NextState<GameState>>,
+    onboarding: Option<Res<NeedsOnboarding>>,
+    kicked: Option<Res<Kicked>>,
+    stores: Res<KeyValueStores>,
+) {
+    if onboarding.is_some() || kicked.is_some() {
+        return;
+    }
+    if let Some(launch_room) = launch.room.take() {
+        *room = launch_room;
+    } else if !launch.synctest {
+        return;
+    }
+    next_state.set(GameState::Matchmaking);
+}
+
+fn room_select_ui(
+    mut contexts: EguiContexts,
+    mut room: ResMut<Room>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut working_name: Local<Option<String>>,
+    mut commands: Commands,
+    onboarding: Option<Res<NeedsOnboarding>>,
+    kicked: Option<Res<Kicked>>,
+) {
+    if onboarding.is_some() || kicked.is_some() {
+        return;
+    }
+    let name = working_name.get_or_insert_with(|| room.0.clone());
+    Window::new("Join a room")
+        .anchor(Align2::CENTER_CENTER, [0., 0.])
+        .collapsible(false)
+        .resizable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.label("Share the room name with friends to play together.");
+            let response = ui.add(TextEdit::singleline(name).hint_text("room name"));
+            let valid = is_valid_room_name(name);
+            if !valid {
+                ui.label("Use letters, digits, '-' or '_' only.");
+            }
+            ui.horizontal(|ui| {
+                let submitted = response.lost_focus() && ui.input(|i| i.key_pressed(Key::Enter));
+                if ui.add_enabled(valid, Button::new("Join")).clicked() || (valid && submitted) {
+                    commands.remove_resource::<QuickPlay>();
+                    room.0 = name.clone();
+                    room.save_to_cookie(stores.area(StorageArea::Cookies));
+                    next_state.set(GameState::Matchmaking);
+                }
+                if ui.button("Public room").clicked() {
+                    commands.remove_resource::<QuickPlay>();
+                    *room = Room::default();
+                    room.save_to_cookie(stores.area(StorageArea::Cookies));
+                    next_state.set(GameState::Matchmaking);
+                }
+                if ui
+                    .button("Quick play")
+                    .on_hover_text("Get matched with other players automatically")
+                    .clicked()
+                {
+                    let (quick_play, queue_room) = QuickPlay::queue();
+                    commands.insert_resource(quick_play);
+                    *room = queue_room;
+                    next_state.set(GameState::Matchmaking);
+                }
+            });
+        });
+}
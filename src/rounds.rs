@@ -0,0 +1,281 @@
+#[cfg(feature = "presentation")]
+use crate::components::UserInfo;
+use crate::{
+    achievements::{GameplayEvent, GameplayEvents},
+    classes::PlayerClass,
+    components::{Active, Bullet, Health, Invulnerable, MoveDir, Player, Position},
+    game_modes::Rule,
+    hill::HillState,
+    lobby_settings::LobbySettings,
+    maps::CurrentMap,
+    rng::SimFrame,
+    simulation::{apply_damage, expire_bullets},
+    spawns::pick_spawn,
+    weapons::{Ammo, Weapon},
+    GameState, SPAWN_INVULNERABILITY_FRAMES,
+};
+use bevy::prelude::*;
+#[cfg(feature = "presentation")]
+use bevy_egui::{
+    egui::{Align2, Area, Grid, RichText},
+    EguiContexts,
+};
+use bevy_ggrs::GGRSSchedule;
+
+/// Splits a match into rounds won by the last player standing, or by
+/// capturing the hill in modes that have one, until the mode's score limit or
+/// round count ends the match. Everything that affects
+/// the simulation runs in the GGRS schedule so peers stay in sync.
+pub struct RoundsPlugin;
+
+impl Plugin for RoundsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RoundState>()
+            .add_system(
+                update_round
+                    .after(apply_damage)
+                    .after(expire_bullets)
+                    .in_schedule(GGRSSchedule),
+            )
+            .add_system(reset_rounds.in_schedule(OnExit(GameState::InGame)));
+        #[cfg(feature = "presentation")]
+        app.add_system(round_ui.in_set(OnUpdate(GameState::InGame)));
+    }
+}
+
+/// Spawn protection for players who couldn't be placed away from threats
+pub const CROWDED_SPAWN_INVULNERABILITY_FRAMES: u32 = 2 * SPAWN_INVULNERABILITY_FRAMES;
+
+#[derive(Resource, Reflect, Default, Clone, Debug)]
+#[reflect(Resource)]
+pub struct RoundState {
+    /// Zero-based index of the current round
+    pub round: u32,
+    /// Frames left in the between-round countdown, zero while a round is on
+    pub intermission_frames: u32,
+    /// [`SimFrame`] the current round started on
+    pub start_frame: u32,
+    /// Rounds won, indexed by player handle
+    pub scores: Vec<u32>,
+    /// Handles of the players still alive at the end of the last round
+    pub survivors: Vec<usize>,
+    /// Set once a rule ended the match. Rounds stop for good.
+    pub match_over: bool,
+}
+
+impl RoundState {
+    pub fn in_progress(&self) -> bool {
+        self.intermission_frames == 0 && !self.match_over
+    }
+
+    /// Handle of the player with the most round wins, if there is just one
+    pub fn leader(&self) -> Option<usize> {
+        let best = *self.scores.iter().max()?;
+        let mut leaders = self.scores.iter().enumerate().filter(|(_, s)| **s == best);
+        match (leaders.next(), leaders.next()) {
+            (Some((handle, _)), None) => Some(handle),
+            _ => None,
+        }
+    }
+
+    /// Whether the round that just ended was the last one under `settings`
+    fn is_last_round(&self, settings: &LobbySettings) -> bool {
+        let limit_reached = |rule, value: u32| {
+            settings
+                .rule(rule)
+                .map_or(false, |limit| limit > 0 && value >= limit as u32)
+        };
+        let top_score = self.scores.iter().copied().max().unwrap_or(0);
+        limit_reached(Rule::ScoreLimit, top_score)
+            || limit_reached(Rule::RoundCount, self.round + 1)
+    }
+}
+
+/// Frames between the end of a round and the next one starting
+fn respawn_delay_frames(settings: &LobbySettings) -> u32 {
+    let seconds = settings.rule(Rule::RespawnDelay).unwrap_or(3).max(1) as u32;
+    seconds * settings.tick_rate
+}
+
+/// Run condition for systems that should pause between rounds
+pub fn round_in_progress(round: Res<RoundState>) -> bool {
+    round.in_progress()
+}
+
+pub fn update_round(
+    mut round: ResMut<RoundState>,
+    mut players: Query<(
+        &Player,
+        &mut Health,
+        &mut Position,
+        &mut MoveDir,
+        &mut Invulnerable,
+        &PlayerClass,
+        &Weapon,
+        &mut Ammo,
+    )>,
+    bullets: Query<(&Active, &Position), With<Bullet>>,
+    settings: Res<LobbySettings>,
+    map: Res<CurrentMap>,
+    frame: Res<SimFrame>,
+    hill: Res<HillState>,
+    mut events: ResMut<GameplayEvents>,
+) {
+    let player_count = players.iter().len();
+    if round.scores.len() < player_count {
+        round.scores.resize(player_count, 0);
+    }
+
+    if round.in_progress() {
+        let mut alive = players
+            .iter()
+            .filter(|(_, health, ..)| health.0 > 0)
+            .map(|(player, ..)| player.handle)
+            .collect::<Vec<_>>();
+        // Solo games end when the only player dies
+        let last_standing = if player_count > 1 { 1 } else { 0 };
+        let capturer = hill.capturer(&settings);
+        if alive.len() <= last_standing || capturer.is_some() {
+            alive.sort();
+            // Capturing the hill wins the round even with others still alive
+            let survivors = capturer.map_or(alive, |capturer| vec![capturer]);
+            if let [winner] = survivors[..] {
+                round.scores[winner] += 1;
+            }
+            info!("Round {} over, survivors: {survivors:?}", round.round);
+            round.survivors = survivors;
+            round.intermission_frames = respawn_delay_frames(&settings);
+            if round.is_last_round(&settings) {
+                info!("Match over after round {}", round.round);
+                round.match_over = true;
+            }
+            let winner = match round.survivors[..] {
+                [winner] => Some(winner),
+                _ => None,
+            };
+            let flawless = players.iter().any(|(player, health, .., class, _, _)| {
+                Some(player.handle) == winner && health.0 == class.stats().max_health
+            });
+            events.push(
+                &frame,
+                GameplayEvent::RoundEnded {
+                    winner,
+                    flawless,
+                    match_winner: round.match_over.then(|| round.leader()).flatten(),
+                },
+            );
+        }
+        return;
+    }
+    if round.match_over {
+        return;
+    }
+
+    round.intermission_frames -= 1;
+    if round.intermission_frames > 0 {
+        return;
+    }
+
+    round.round += 1;
+    round.start_frame = frame.0;
+    // Place players in handle order, each avoiding the ones placed before it
+    // and the bullets still flying from the last round
+    let bullets = bullets
+        .iter()
+        .filter(|(active, _)| active.0)
+        .map(|(_, position)| position.0)
+        .collect::<Vec<_>>();
+    let mut players = players.iter_mut().collect::<Vec<_>>();
+    players.sort_by_key(|(player, ..)| player.handle);
+    let mut placed = Vec::with_capacity(players.len());
+    for (
+        player,
+        mut health,
+        mut position,
+        mut move_dir,
+        mut invulnerable,
+        class,
+        weapon,
+        mut ammo,
+    ) in players
+    {
+        let spawn = pick_spawn(
+            player.handle,
+            &placed,
+            &bullets,
+            &map,
+            settings.map_size_si(),
+        );
+        position.0 = spawn.position;
+        move_dir.0 = spawn.direction;
+        health.0 = class.stats().max_health;
+        *ammo = Ammo::full(*weapon);
+        invulnerable.0 = if spawn.safe {
+            SPAWN_INVULNERABILITY_FRAMES
+        } else {
+            CROWDED_SPAWN_INVULNERABILITY_FRAMES
+        };
+        placed.push(spawn.position);
+    }
+}
+
+#[cfg(feature = "presentation")]
+fn round_ui(
+    mut contexts: EguiContexts,
+    round: Res<RoundState>,
+    settings: Res<LobbySettings>,
+    players: Query<(&Player, Option<&UserInfo>)>,
+) {
+    if round.in_progress() {
+        return;
+    }
+    let mut players = players.iter().collect::<Vec<_>>();
+    players.sort_by_key(|(player, _)| player.handle);
+    let name = |handle: usize| {
+        players
+            .iter()
+            .find(|(player, _)| player.handle == handle)
+            .and_then(|(_, info)| info.map(|i| i.name.clone()))
+            .unwrap_or_else(|| format!("Player {handle}"))
+    };
+    Area::new("round_overlay")
+        .anchor(Align2::CENTER_CENTER, [0., 0.])
+        .show(contexts.ctx_mut(), |ui| {
+            let headline = match round.survivors[..] {
+                [winner] => format!("{} wins round {}!", name(winner), round.round + 1),
+                _ => format!("Round {} is a draw", round.round + 1),
+            };
+            ui.label(RichText::new(headline).heading());
+            if round.match_over {
+                ui.label(match round.leader() {
+                    Some(winner) => format!("{} wins the match!", name(winner)),
+                    None => "The match is a draw".to_string(),
+                });
+            } else {
+                let tick_rate = settings.tick_rate;
+                ui.label(format!(
+                    "Next round in {}",
+                    (round.intermission_frames + tick_rate - 1) / tick_rate
+                ));
+            }
+            ui.separator();
+            Grid::new("round_scores").show(ui, |ui| {
+                for (player, _) in players.iter() {
+                    ui.label(name(player.handle));
+                    ui.label(
+                        round
+                            .scores
+                            .get(player.handle)
+                            .copied()
+                            .unwrap_or(0)
+                            .to_string(),
+                    );
+                    ui.end_row();
+                }
+            });
+        });
+}
+
+pub fn reset_rounds(mut round: ResMut<RoundState>) {
+    *round = RoundState::default();
+}
@@ -0,0 +1,236 @@
+use crate::{
+    components::{GameSaveData, IsLocal, TabId},
+    filter::WordFilter,
+    lobby::PracticeMode,
+    room::Room,
+    save_storage,
+    storage::{KeyValueStores, StorageArea},
+    GameState,
+};
+use bevy::prelude::*;
+use bevy_egui::{
+    egui::{self, Grid, ScrollArea},
+    EguiContexts,
+};
+use ron::Value;
+
+/// Lists the room's autosaves from local storage and loads any of them into
+/// a local practice session, to inspect a game as it was saved. The save's
+/// own player is played from this tab and the others stand still, or are
+/// played by bots. Only added in debug builds; toggled with F8 in the lobby.
+pub struct SandboxPlugin;
+
+impl Plugin for SandboxPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TimeTravel>()
+            .add_systems(
+                (toggle_time_travel, time_travel_ui.after(toggle_time_travel))
+                    .in_set(OnUpdate(GameState::Matchmaking)),
+            )
+            .add_system(leave_sandbox.in_schedule(OnExit(GameState::InGame)));
+    }
+}
+
+/// Present while a practice session plays a loaded save. Read by the session
+/// setup and the snapshot loading, which are shared with normal games.
+#[derive(Resource)]
+pub struct Sandbox {
+    /// The save, with its own player's tab id and this tab's swapped
+    pub save: GameSaveData,
+    /// Tab ids of the save's players, one per seat
+    pub tab_ids: Vec<String>,
+    /// This tab's id, which the save's own player was given
+    pub own_tab_id: String,
+    pub bots: bool,
+}
+
+#[derive(Resource, Default)]
+struct TimeTravel {
+    open: bool,
+    /// Saves by the tab that stored them, with their player counts, read
+    /// when the window opens
+    saves: Vec<(String, GameSaveData, usize)>,
+    bots: bool,
+}
+
+fn toggle_time_travel(
+    keys: Res<Input<KeyCode>>,
+    room: Res<Room>,
+    stores: Res<KeyValueStores>,
+    mut time_travel: ResMut<TimeTravel>,
+) {
+    if keys.just_pressed(KeyCode::F8) {
+        time_travel.open = !time_travel.open;
+        if time_travel.open {
+            time_travel.saves = save_storage::list(stores.area(StorageArea::Local), &room.0)
+                .into_iter()
+                .map(|(tab_id, save)| {
+                    let players = saved_tab_ids(&save.snapshot).len();
+                    (tab_id, save, players)
+                })
+                .collect();
+        }
+    }
+}
+
+fn time_travel_ui(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    room: Res<Room>,
+    word_filter: Res<WordFilter>,
+    local: Query<&TabId, With<IsLocal>>,
+    mut time_travel: ResMut<TimeTravel>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if !time_travel.open {
+        return;
+    }
+    let Ok(own_tab) = local.get_single() else {
+        return;
+    };
+    let mut open = true;
+    let mut load = None;
+    let TimeTravel { saves, bots, .. } = &mut *time_travel;
+    egui::Window::new("Time travel")
+        .open(&mut open)
+        .default_width(360.)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.label(format!(
+                "Autosaves for room {}",
+                word_filter.censor(&room.0)
+            ));
+            ui.checkbox(bots, "Bots play the other players");
+            ui.separator();
+            if saves.is_empty() {
+                ui.weak("No saves stored for this room");
+            }
+            ScrollArea::vertical().show(ui, |ui| {
+                Grid::new("autosaves").striped(true).show(ui, |ui| {
+                    for (index, (tab_id, save, players)) in saves.iter().enumerate() {
+                        ui.label(save.timestamp.format("%Y-%m-%d %H:%M:%S").to_string());
+                        ui.label(format!("{players} players"));
+                        ui.label(save.config.mode.as_str());
+                        if *tab_id == own_tab.0 {
+                            ui.label("this tab");
+                        } else {
+                            ui.weak(short_id(tab_id));
+                        }
+                        if ui.button("Load into sandbox").clicked() {
+                            load = Some(index);
+                        }
+                        ui.end_row();
+                    }
+                });
+            });
+        });
+    time_travel.open = open;
+
+    let Some((tab_id, mut save, _)) = load.map(|index| time_travel.saves[index].clone()) else {
+        return;
+    };
+    // The save's player becomes this tab's, so the loaded state goes to the
+    // local player like when resuming
+    save.snapshot = swap_quoted(&save.snapshot, &tab_id, &own_tab.0);
+    let tab_ids = saved_tab_ids(&save.snapshot);
+    if !tab_ids.contains(&own_tab.0) {
+        warn!("Save of {tab_id} has no player of its own, not loading it");
+        return;
+    }
+    info!(
+        "Loading save of {tab_id} from {} into a sandbox",
+        save.timestamp
+    );
+    commands.insert_resource(Sandbox {
+        save,
+        tab_ids,
+        own_tab_id: own_tab.0.clone(),
+        bots: time_travel.bots,
+    });
+    commands.insert_resource(PracticeMode);
+    time_travel.open = false;
+    next_state.set(GameState::InGame);
+}
+
+fn leave_sandbox(mut commands: Commands) {
+    commands.remove_resource::<Sandbox>();
+}
+
+/// Tab ids of the players in a serialized snapshot, sorted so seats are the
+/// same on every load
+pub fn saved_tab_ids(snapshot: &str) -> Vec<String> {
+    fn collect(value: &Value, in_tab_id: bool, tab_ids: &mut Vec<String>) {
+        match value {
+            Value::String(tab_id) if in_tab_id => tab_ids.push(tab_id.clone()),
+            Value::Map(map) => {
+                for (key, value) in map.iter() {
+                    let is_tab_id = matches!(key, Value::String(name) if name.ends_with("::TabId"));
+                    collect(value, in_tab_id || is_tab_id, tab_ids);
+                }
+            }
+            Value::Seq(values) => {
+                for value in values {
+                    collect(value, in_tab_id, tab_ids);
+                }
+            }
+            Value::Option(Some(value)) => collect(value, in_tab_id, tab_ids),
+            _ => {}
+        }
+    }
+    let mut tab_ids = Vec::new();
+    match ron::from_str::<Value>(snapshot) {
+        Ok(value) => collect(&value, false, &mut tab_ids),
+        Err(error) => warn!("Failed to parse saved snapshot: {error}"),
+    }
+    tab_ids.sort();
+    tab_ids.dedup();
+    tab_ids
+}
+
+/// `snapshot` with the quoted strings `a` and `b` swapped for each other, so
+/// a player the save already had with this tab's id keeps an id of its own
+fn swap_quoted(snapshot: &str, a: &str, b: &str) -> String {
+    let (a, b) = (format!("\"{a}\""), format!("\"{b}\""));
+    let mut swapped = String::with_capacity(snapshot.len());
+    let mut rest = snapshot;
+    while let Some(quote) = rest.find('"') {
+        swapped.push_str(&rest[..quote]);
+        rest = &rest[quote..];
+        let (replacement, len) = if rest.starts_with(&a) {
+            (b.as_str(), a.len())
+        } else if rest.starts_with(&b) {
+            (a.as_str(), b.len())
+        } else {
+            ("\"", 1)
+        };
+        swapped.push_str(replacement);
+        rest = &rest[len..];
+    }
+    swapped.push_str(rest);
+    swapped
+}
+
+fn short_id(tab_id: &str) -> &str {
+    &tab_id[..tab_id.len().min(8)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tab_ids_are_swapped_both_ways() {
+        let snapshot = r#"{"TabId": ("saver"), "TabId": ("mine"), "name": "saver2"}"#;
+        assert_eq!(
+            swap_quoted(snapshot, "saver", "mine"),
+            r#"{"TabId": ("mine"), "TabId": ("saver"), "name": "saver2"}"#
+        );
+        assert_eq!(swap_quoted(snapshot, "saver", "saver"), snapshot);
+    }
+
+    #[test]
+    fn swapped_saves_keep_every_player() {
+        let snapshot = r#"{"a::TabId": "saver", "b::TabId": "mine"}"#;
+        let swapped = swap_quoted(snapshot, "saver", "mine");
+        assert_eq!(saved_tab_ids(&swapped), ["mine", "saver"]);
+    }
+}
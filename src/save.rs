@@ -0,0 +1,468 @@
+use crate::{
+    budget::BULLET_POOL_SIZE,
+    classes::PlayerClass,
+    components::{
+        Active, Bullet, GameSaveData, Health, Invulnerable, IsLocal, IsReady, MoveDir, Player,
+        Position, TabId,
+    },
+    hazards::spawn_hazards,
+    lobby::{AutoResume, GameStartConfig, PracticeMode},
+    match_config::MatchConfig,
+    net::{take_leaving_messages, Messages},
+    rng::SimFrame,
+    room::Room,
+    rounds::RoundState,
+    sandbox::Sandbox,
+    save_migration::{self, component_key, value_end, SAVE_VERSION},
+    save_storage,
+    session_events::{session_events, SessionDisconnected},
+    simulation::{
+        insert_player_components, pooled_bullet, seed_rng, spawn_bullet_pool, SimulationTimingSet,
+    },
+    status::StatusEvent,
+    storage::{KeyValueStores, StorageArea},
+    weapons::Ammo,
+    GameState, GgrsConfig,
+};
+use bevy::prelude::*;
+use bevy_ggrs::{ggrs_stage::GGRSStage, GGRSSchedule, RollbackIdProvider};
+use chrono::Utc;
+use std::{any::type_name, ops::Range};
+
+/// Saves a snapshot of the running game when it's cut short, so the same
+/// players can resume it, and loads that snapshot back when they do.
+/// Serializing the world takes long enough to hitch a frame, so it's done
+/// every [`SNAPSHOT_INTERVAL_FRAMES`] frames along the way, and a game cut
+/// short saves the latest of those snapshots, going back at most that many
+/// frames. The world only holds the latest predicted frame, so a snapshot is
+/// kept once its frame is confirmed without having been rolled back.
+pub struct SavePlugin;
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            (
+                load_snapshot
+                    .after(insert_player_components)
+                    .after(spawn_bullet_pool)
+                    .after(spawn_hazards)
+                    .after(seed_rng),
+                apply_loaded_components
+                    .after(insert_player_components)
+                    .after(load_snapshot),
+                refill_bullet_pool.after(load_snapshot),
+            )
+                .in_schedule(OnEnter(GameState::InGame)),
+        )
+        .add_systems(
+            (
+                take_snapshot.before(kill_game),
+                kill_game.after(session_events),
+            )
+                .in_set(OnUpdate(GameState::InGame)),
+        )
+        .add_system(
+            notice_rollbacks
+                .in_schedule(GGRSSchedule)
+                .in_base_set(SimulationTimingSet::Start),
+        )
+        .add_system(forget_snapshot.in_schedule(OnExit(GameState::InGame)));
+    }
+}
+
+/// GGRS frames between the snapshots kept for saving
+const SNAPSHOT_INTERVAL_FRAMES: i32 = 60;
+
+/// The latest snapshot of a confirmed frame of the running game
+#[derive(Resource)]
+struct LatestSnapshot {
+    /// GGRS frame whose state it holds
+    frame: i32,
+    save: GameSaveData,
+}
+
+/// A snapshot of the world as last simulated, usually on predicted inputs,
+/// waiting for its frame to be confirmed
+#[derive(Resource)]
+struct PendingSnapshot {
+    /// GGRS frame whose state it holds
+    frame: i32,
+    /// [`SimFrame`] at the time, to notice rollbacks to before it
+    sim_frame: u32,
+    /// Set when GGRS rolled back past the frame, i.e. some of the inputs it
+    /// was simulated with were mispredicted
+    rolled_back: bool,
+    save: GameSaveData,
+}
+
+/// Snapshots the game once the frame has moved far enough past the last
+/// snapshot kept, and keeps the snapshot once its frame is confirmed. Only
+/// P2P sessions confirm frames; the rest are snapshotted when they end.
+fn take_snapshot(world: &mut World) {
+    let (current_frame, confirmed_frame) =
+        match world.get_resource::<bevy_ggrs::Session<GgrsConfig>>() {
+            Some(bevy_ggrs::Session::P2PSession(session)) => {
+                (session.current_frame(), session.confirmed_frame())
+            }
+            _ => return,
+        };
+    if let Some(pending) = world.get_resource::<PendingSnapshot>() {
+        // The state of a frame follows from the inputs of the ones before it
+        let confirmed = confirmed_frame >= pending.frame - 1;
+        if pending.rolled_back || confirmed {
+            let pending = world.remove_resource::<PendingSnapshot>().unwrap();
+            if !pending.rolled_back {
+                world.insert_resource(LatestSnapshot {
+                    frame: pending.frame,
+                    save: pending.save,
+                });
+            }
+        }
+        return;
+    }
+    let due = world
+        .get_resource::<LatestSnapshot>()
+        .map_or(true, |latest| {
+            current_frame >= latest.frame + SNAPSHOT_INTERVAL_FRAMES
+        });
+    if !due {
+        return;
+    }
+    let save = serialize_game(world);
+    world.insert_resource(PendingSnapshot {
+        frame: current_frame,
+        sim_frame: world.resource::<SimFrame>().0,
+        rolled_back: false,
+        save,
+    });
+}
+
+/// Runs before each simulated frame, so a rollback shows as starting from
+/// an earlier [`SimFrame`] than the pending snapshot's. Rolling back to its
+/// own frame leaves its state as it was.
+fn notice_rollbacks(frame: Res<SimFrame>, pending: Option<ResMut<PendingSnapshot>>) {
+    if let Some(mut pending) = pending {
+        if frame.0 < pending.sim_frame {
+            pending.rolled_back = true;
+        }
+    }
+}
+
+fn forget_snapshot(mut commands: Commands) {
+    commands.remove_resource::<LatestSnapshot>();
+    commands.remove_resource::<PendingSnapshot>();
+}
+
+pub fn serialize_game(world: &World) -> GameSaveData {
+    let snapshot = world
+        .resource::<GGRSStage<GgrsConfig>>()
+        .get_serialized_snapshot(world);
+    GameSaveData {
+        snapshot: drop_inactive_bullets(&snapshot),
+        timestamp: Utc::now(),
+        config: world.resource::<MatchConfig>().clone(),
+        save_version: SAVE_VERSION,
+    }
+}
+
+/// Ends the game on a disconnect reported by [`session_events`], or when
+/// lobby messages arrive mid-game, saving the latest snapshot to resume from
+pub fn kill_game(world: &mut World) {
+    if matches!(
+        world.get_resource::<bevy_ggrs::Session<GgrsConfig>>(),
+        Some(bevy_ggrs::Session::SyncTestSession(_))
+    ) {
+        return;
+    }
+    // A finished match moves on to its summary instead of being saved
+    if world.resource::<RoundState>().match_over {
+        return;
+    }
+    let disconnected = world.contains_resource::<SessionDisconnected>();
+    if !disconnected && world.get_resource::<Messages>().unwrap().0.is_empty() {
+        return;
+    }
+
+    let leavers = take_leaving_messages(&mut world.resource_mut::<Messages>());
+    if disconnected {
+        info!("Ending the game after a disconnect");
+    } else if !leavers.is_empty() {
+        info!("{leavers:?} left, restarting the session without them");
+        world.init_resource::<AutoResume>();
+    } else {
+        // Lobby messages only arrive mid-game from peers who just connected
+        info!("New peer connected, restarting the session to let them in");
+        world.init_resource::<AutoResume>();
+    }
+    world
+        .get_resource_mut::<NextState<GameState>>()
+        .unwrap()
+        .set(GameState::Matchmaking);
+
+    if let Ok(mut ready) = world
+        .query_filtered::<&mut IsReady, With<IsLocal>>()
+        .get_single_mut(world)
+    {
+        ready.0 = false;
+    }
+
+    let save = match world.remove_resource::<LatestSnapshot>() {
+        Some(latest) => latest.save,
+        None => serialize_game(world),
+    };
+    store_save(world, &save);
+    world.insert_resource(save);
+}
+
+/// Keeps `save` in local storage as the local player's save for this room,
+/// telling the player if it can't be
+pub fn store_save(world: &mut World, save: &GameSaveData) {
+    info!("Saving world snapshot: {}", save.snapshot);
+    let room = world.resource::<Room>().0.clone();
+    let Ok(tab_id) = world
+        .query_filtered::<&TabId, With<IsLocal>>()
+        .get_single(world)
+    else {
+        return;
+    };
+    let stores = world.resource::<KeyValueStores>();
+    if let Err(e) = save_storage::store(stores.area(StorageArea::Local), &room, &tab_id.0, save) {
+        world.send_event(StatusEvent::warning(format!(
+            "Couldn't store the game to resume later: {e}"
+        )));
+    }
+}
+
+pub fn load_snapshot(world: &mut World) {
+    let sandboxed = world
+        .get_resource::<Sandbox>()
+        .map(|sandbox| sandbox.save.clone());
+    if sandboxed.is_none()
+        && (world.contains_resource::<PracticeMode>()
+            || world
+                .get_resource::<GameStartConfig>()
+                .map_or(false, |config| !config.resume_save))
+    {
+        return;
+    }
+    let save = sandboxed.or_else(|| {
+        world
+            .query_filtered::<Option<&GameSaveData>, With<IsLocal>>()
+            .get_single(world)
+            .ok()
+            .flatten()
+            .cloned()
+    });
+    let Some(mut snapshot) = save else {
+        return;
+    };
+    if let Err(e) = save_migration::migrate(&mut snapshot) {
+        world.send_event(StatusEvent::warning(format!(
+            "Couldn't load the save, starting a new game instead: {e}"
+        )));
+        return;
+    }
+    info!(
+        "Loading world snapshot from {}: {}",
+        snapshot.timestamp, snapshot.snapshot
+    );
+    world.resource_scope(|world, stage: Mut<GGRSStage<GgrsConfig>>| {
+        stage.load_serialized_snapshot(world, &snapshot.snapshot);
+    });
+}
+
+/// `snapshot` without the bullets of the pool that aren't in flight. Most of
+/// the pool is idle most of the time, and its [`BULLET_POOL_SIZE`] entities
+/// would otherwise make up the bulk of every save. An entity is dropped when
+/// the components it holds include [`Bullet`] and an inactive [`Active`],
+/// along with the comma that separates it from the next one.
+pub fn drop_inactive_bullets(snapshot: &str) -> String {
+    /// An open bracket and what was found directly inside it so far
+    #[derive(Default)]
+    struct Group {
+        start: usize,
+        bullet: bool,
+        inactive: bool,
+        /// Set on an entity once its components turned out to be an
+        /// inactive bullet's
+        drop: bool,
+    }
+    let bullet_key = component_key(type_name::<Bullet>());
+    let active_key = component_key(type_name::<Active>());
+    let mut groups: Vec<Group> = Vec::new();
+    let mut dropped: Vec<Range<usize>> = Vec::new();
+    let mut in_string = false;
+    let mut escaped = false;
+    for (index, c) in snapshot.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => {
+                in_string = true;
+                let rest = &snapshot[index..];
+                let Some(group) = groups.last_mut() else {
+                    continue;
+                };
+                if rest.starts_with(&bullet_key) {
+                    group.bullet = true;
+                } else if let Some(value) = rest.strip_prefix(&active_key) {
+                    let value = &value[..value_end(value).unwrap_or(0)];
+                    let value = value.trim().trim_start_matches('(').trim_end_matches(')');
+                    group.inactive = value.trim() == "false";
+                }
+            }
+            '(' | '[' | '{' => groups.push(Group {
+                start: index,
+                ..default()
+            }),
+            ')' | ']' | '}' => {
+                let Some(group) = groups.pop() else {
+                    continue;
+                };
+                if group.drop {
+                    dropped.push(group.start..index + 1);
+                } else if group.bullet && group.inactive {
+                    if let Some(entity) = groups.last_mut() {
+                        entity.drop = true;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    let mut kept = String::with_capacity(snapshot.len());
+    let mut from = 0;
+    for range in dropped {
+        kept.push_str(&snapshot[from..range.start]);
+        let rest = &snapshot[range.end..];
+        from = match rest.trim_start().strip_prefix(',') {
+            Some(after) => snapshot.len() - after.trim_start().len(),
+            None => range.end,
+        };
+    }
+    kept.push_str(&snapshot[from..]);
+    kept
+}
+
+/// Saves leave out the bullets that weren't in flight, see
+/// [`drop_inactive_bullets`], so the pool is brought back up to
+/// [`BULLET_POOL_SIZE`] once one is loaded. Every peer loads the same save,
+/// so the new bullets get the same rollback ids everywhere.
+fn refill_bullet_pool(
+    mut commands: Commands,
+    mut rip: ResMut<RollbackIdProvider>,
+    bullets: Query<(), With<Bullet>>,
+) {
+    for _ in bullets.iter().len()..BULLET_POOL_SIZE {
+        commands.spawn(pooled_bullet(&mut rip));
+    }
+}
+
+fn apply_loaded_components(
+    mut commands: Commands,
+    new_players: Query<(Entity, &TabId), With<Player>>,
+    loaded_players: Query<
+        (
+            Entity,
+            &TabId,
+            &Position,
+            &MoveDir,
+            &Ammo,
+            &Health,
+            &Invulnerable,
+            &PlayerClass,
+        ),
+        Without<Player>,
+    >,
+) {
+    for (new_entity, new_id) in new_players.iter() {
+        for (
+            _loaded_entity,
+            loaded_id,
+            loaded_transform,
+            move_dir,
+            ammo,
+            health,
+            invulnerable,
+            class,
+        ) in loaded_players.iter()
+        {
+            if new_id.0 == loaded_id.0 {
+                commands.entity(new_entity).insert((
+                    *loaded_transform,
+                    *move_dir,
+                    *ammo,
+                    *health,
+                    *invulnerable,
+                    *class,
+                ));
+                break;
+            }
+        }
+    }
+    for (entity, ..) in loaded_players.iter() {
+        commands.entity(entity).despawn();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entity(rollback_id: u32, components: &[(&str, &str)]) -> String {
+        let components = components
+            .iter()
+            .map(|(name, value)| format!("{}{value}", component_key(name)))
+            .collect::<Vec<_>>()
+            .join(",");
+        format!("(rollback_id:{rollback_id},components:{{{components}}})")
+    }
+
+    fn bullet(rollback_id: u32, active: bool) -> String {
+        entity(
+            rollback_id,
+            &[
+                (type_name::<Bullet>(), "()"),
+                (type_name::<Active>(), &format!("({active})")),
+                (type_name::<Position>(), "((x:1,y:2))"),
+            ],
+        )
+    }
+
+    #[test]
+    fn only_inactive_bullets_are_dropped() {
+        let player = entity(0, &[(type_name::<Active>(), "(false)")]);
+        let snapshot = format!(
+            "(entities:[{player},{},{},{}],resources:{{}})",
+            bullet(1, false),
+            bullet(2, true),
+            bullet(3, false)
+        );
+        assert_eq!(
+            drop_inactive_bullets(&snapshot),
+            format!("(entities:[{player},{},],resources:{{}})", bullet(2, true))
+        );
+    }
+
+    #[test]
+    fn idle_pools_make_up_most_of_a_save() {
+        let mut entities = vec![entity(0, &[(type_name::<Player>(), "(handle:0)")])];
+        entities.extend((1..=BULLET_POOL_SIZE as u32).map(|id| bullet(id, id == 1)));
+        let snapshot = format!("(entities:[{}],resources:{{}})", entities.join(","));
+        let saved = drop_inactive_bullets(&snapshot);
+        assert!(ron::from_str::<ron::Value>(&saved).is_ok());
+        assert!(
+            saved.len() * 10 < snapshot.len(),
+            "{} of {} bytes",
+            saved.len(),
+            snapshot.len()
+        );
+    }
+}
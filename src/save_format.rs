@@ -0,0 +1,232 @@
+use crate::{
+    components::GameSaveData,
+    match_config::MatchConfig,
+    wire_format::{Bincode, Compact, Ron, WireFormat},
+};
+use chrono::{DateTime, Utc};
+use miniz_oxide::{deflate::compress_to_vec, inflate::decompress_to_vec};
+use serde::Deserialize;
+
+/// Starts every encoded save, followed by an [`Encoding`] byte, so saves in
+/// other formats are recognized instead of misread
+const MAGIC: [u8; 2] = *b"WG";
+
+/// Ways a save can be encoded. Decoding supports all of them, encoding uses
+/// [`PREFERRED_ENCODING`]. How they compare depends on what's in the
+/// snapshot, so compare them on a real game's save with the debug console's
+/// `save_formats` command, in the browser or a `native` build.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Encoding {
+    /// The whole save as RON text, like the reflect-serialized snapshot in it
+    Ron = 0,
+    Bincode = 1,
+    /// Bincode compressed with deflate
+    BincodeDeflate = 2,
+    /// [`Compact`]'s variable-length integers compressed with deflate
+    CompactDeflate = 3,
+}
+
+impl Encoding {
+    fn from_byte(byte: u8) -> Option<Self> {
+        [
+            Self::Ron,
+            Self::Bincode,
+            Self::BincodeDeflate,
+            Self::CompactDeflate,
+        ]
+        .into_iter()
+        .find(|encoding| *encoding as u8 == byte)
+    }
+}
+
+/// The snapshot in a save is reflect-serialized text full of repeated type
+/// names, which deflate shrinks without a format that has to track every
+/// rollback component
+const PREFERRED_ENCODING: Encoding = Encoding::BincodeDeflate;
+const COMPRESSION_LEVEL: u8 = 6;
+
+/// `save` with a header naming its encoding, for save transfers and storage
+pub fn encode(save: &GameSaveData) -> Vec<u8> {
+    encode_as(save, PREFERRED_ENCODING, COMPRESSION_LEVEL)
+}
+
+fn encode_as(save: &GameSaveData, encoding: Encoding, level: u8) -> Vec<u8> {
+    let body = match encoding {
+        Encoding::Ron => Ron::encode(save),
+        Encoding::Bincode => Bincode::encode(save),
+        Encoding::BincodeDeflate => Bincode::encode(save).map(|body| compress_to_vec(&body, level)),
+        Encoding::CompactDeflate => Compact::encode(save).map(|body| compress_to_vec(&body, level)),
+    }
+    .expect("saves are always serializable");
+    let mut bytes = Vec::with_capacity(MAGIC.len() + 1 + body.len());
+    bytes.extend_from_slice(&MAGIC);
+    bytes.push(encoding as u8);
+    bytes.extend(body);
+    bytes
+}
+
+/// [`GameSaveData`] from before it kept its save version
+#[derive(Deserialize)]
+struct UnversionedSave {
+    snapshot: String,
+    timestamp: DateTime<Utc>,
+    config: MatchConfig,
+}
+
+impl From<UnversionedSave> for GameSaveData {
+    /// The version is left at 0 for whoever knows it to fill in
+    fn from(save: UnversionedSave) -> Self {
+        Self {
+            snapshot: save.snapshot,
+            timestamp: save.timestamp,
+            config: save.config,
+            save_version: 0,
+        }
+    }
+}
+
+/// Reads a save written by [`encode`] in any supported encoding
+pub fn decode(bytes: &[u8]) -> Option<GameSaveData> {
+    let body = bytes.strip_prefix(&MAGIC)?;
+    let (encoding, body) = body.split_first()?;
+    match Encoding::from_byte(*encoding)? {
+        Encoding::Ron => decode_as::<Ron>(body),
+        Encoding::Bincode => decode_as::<Bincode>(body),
+        Encoding::BincodeDeflate => decode_as::<Bincode>(&decompress_to_vec(body).ok()?),
+        Encoding::CompactDeflate => decode_as::<Compact>(&decompress_to_vec(body).ok()?),
+    }
+}
+
+/// Formats without field names can't tell a save without the version apart,
+/// except as one that ends early, so it's tried as both
+fn decode_as<F: WireFormat>(body: &[u8]) -> Option<GameSaveData> {
+    F::decode(body)
+        .or_else(|_| F::decode::<UnversionedSave>(body).map(Into::into))
+        .ok()
+}
+
+/// Size and speed of one candidate format on a particular save
+#[cfg(debug_assertions)]
+pub struct FormatReport {
+    pub name: String,
+    pub bytes: usize,
+    pub encode_ms: f64,
+    pub decode_ms: f64,
+}
+
+/// Encodes and decodes `save` in every candidate format, timing each
+#[cfg(debug_assertions)]
+pub fn compare_formats(save: &GameSaveData) -> Vec<FormatReport> {
+    use bevy::utils::Instant;
+
+    let candidates = [
+        ("RON".to_string(), Encoding::Ron, 0),
+        ("bincode".to_string(), Encoding::Bincode, 0),
+    ]
+    .into_iter()
+    .chain([1, 6, 9].map(|level| {
+        (
+            format!("bincode + deflate {level}"),
+            Encoding::BincodeDeflate,
+            level,
+        )
+    }))
+    .chain([(
+        "compact + deflate 6".to_string(),
+        Encoding::CompactDeflate,
+        6,
+    )]);
+    candidates
+        .map(|(name, encoding, level)| {
+            let started = Instant::now();
+            let bytes = encode_as(save, encoding, level);
+            let encoded = Instant::now();
+            decode(&bytes).expect("failed to decode a save just encoded");
+            FormatReport {
+                name,
+                bytes: bytes.len(),
+                encode_ms: (encoded - started).as_secs_f64() * 1000.,
+                decode_ms: encoded.elapsed().as_secs_f64() * 1000.,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{game_modes::DEFAULT_MODE, match_config::balance_hash};
+    use serde::Serialize;
+
+    fn save() -> GameSaveData {
+        GameSaveData {
+            snapshot: "(entities: [(entity: 0, components: {\"Position\": ((1, 2))})])".repeat(20),
+            timestamp: Utc::now(),
+            config: MatchConfig {
+                seed: 7,
+                mode: DEFAULT_MODE.to_string(),
+                balance_hash: balance_hash(),
+            },
+            save_version: 5,
+        }
+    }
+
+    fn assert_same(a: &GameSaveData, b: &GameSaveData) {
+        assert_eq!(a.snapshot, b.snapshot);
+        assert_eq!(a.timestamp, b.timestamp);
+        assert_eq!(a.config, b.config);
+        assert_eq!(a.save_version, b.save_version);
+    }
+
+    #[test]
+    fn every_encoding_round_trips() {
+        let save = save();
+        for encoding in [
+            Encoding::Ron,
+            Encoding::Bincode,
+            Encoding::BincodeDeflate,
+            Encoding::CompactDeflate,
+        ] {
+            let decoded = decode(&encode_as(&save, encoding, COMPRESSION_LEVEL))
+                .unwrap_or_else(|| panic!("{encoding:?} didn't decode"));
+            assert_same(&decoded, &save);
+        }
+    }
+
+    #[test]
+    fn compression_pays_off_on_repetitive_snapshots() {
+        let save = save();
+        let plain = encode_as(&save, Encoding::Bincode, 0).len();
+        assert!(encode(&save).len() < plain / 2);
+    }
+
+    #[test]
+    fn saves_from_before_the_version_decode_as_version_0() {
+        #[derive(Serialize)]
+        struct Unversioned<'a> {
+            snapshot: &'a str,
+            timestamp: DateTime<Utc>,
+            config: &'a MatchConfig,
+        }
+        let save = save();
+        let body = Bincode::encode(&Unversioned {
+            snapshot: &save.snapshot,
+            timestamp: save.timestamp,
+            config: &save.config,
+        })
+        .unwrap();
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(Encoding::BincodeDeflate as u8);
+        bytes.extend(compress_to_vec(&body, COMPRESSION_LEVEL));
+        let decoded = decode(&bytes).unwrap();
+        assert_eq!(decoded.save_version, 0);
+        assert_eq!(decoded.snapshot, save.snapshot);
+    }
+
+    #[test]
+    fn other_data_is_not_misread() {
+        assert!(decode(b"").is_none());
+        assert!(decode(b"{\"snapshot\": \"\"}").is_none());
+        assert!(decode(&[MAGIC[0], MAGIC[1], 9, 0]).is_none());
+    }
+}
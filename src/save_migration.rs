@@ -0,0 +1,236 @@
+use crate::{
+    components::{GameSaveData, Player},
+    hill::HillState,
+    lobby_settings::LobbySettings,
+    rounds::RoundState,
+    zone::ZoneState,
+};
+use bevy::prelude::*;
+use std::any::type_name;
+
+/// Bump whenever the snapshot or [`GameSaveData`] format changes, adding a
+/// migration from the previous version to [`MIGRATIONS`] if older saves can
+/// be brought up to date
+pub const SAVE_VERSION: u32 = 23;
+
+/// Upgrades the snapshot of a save written by version `from` to `from + 1`
+struct Migration {
+    from: u32,
+    apply: fn(&mut String) -> Result<(), String>,
+}
+
+/// Consecutive migrations, oldest first. Saves older than the first one are
+/// dropped.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        from: 15,
+        apply: bullet_ready_to_ammo,
+    },
+    // Only the save gained its version, the snapshot is the same
+    Migration {
+        from: 16,
+        apply: |_| Ok(()),
+    },
+    Migration {
+        from: 17,
+        apply: add_hill_state,
+    },
+    Migration {
+        from: 18,
+        apply: add_move_spread,
+    },
+    Migration {
+        from: 19,
+        apply: add_map_seed,
+    },
+    Migration {
+        from: 20,
+        apply: add_bullet_collisions,
+    },
+    Migration {
+        from: 21,
+        apply: add_sweep_start,
+    },
+    Migration {
+        from: 22,
+        apply: add_round_start_frame,
+    },
+];
+
+/// Whether a save written by `version` can be loaded, directly or after
+/// migrating it
+pub fn is_compatible(version: u32) -> bool {
+    version == SAVE_VERSION
+        || MIGRATIONS
+            .first()
+            .map_or(false, |first| (first.from..SAVE_VERSION).contains(&version))
+}
+
+/// Brings `save` up to [`SAVE_VERSION`], one migration at a time. Saves from
+/// newer versions, or too old to migrate, are rejected rather than loaded into
+/// components they don't match.
+pub fn migrate(save: &mut GameSaveData) -> Result<(), String> {
+    if save.save_version > SAVE_VERSION {
+        return Err(format!(
+            "save version {} is newer than {SAVE_VERSION}",
+            save.save_version
+        ));
+    }
+    if !is_compatible(save.save_version) {
+        return Err(format!(
+            "save version {} is too old to migrate",
+            save.save_version
+        ));
+    }
+    for migration in MIGRATIONS
+        .iter()
+        .skip_while(|migration| migration.from < save.save_version)
+    {
+        (migration.apply)(&mut save.snapshot)
+            .map_err(|e| format!("migrating from version {}: {e}", migration.from))?;
+        save.save_version = migration.from + 1;
+        info!("Migrated save to version {}", save.save_version);
+    }
+    Ok(())
+}
+
+/// Version 16 replaced the `BulletReady` flag with [`Ammo`]. Players get an
+/// empty clip that's reloaded on the first frame, which fills it to the size
+/// of whatever weapon they're holding.
+///
+/// [`Ammo`]: crate::weapons::Ammo
+fn bullet_ready_to_ammo(snapshot: &mut String) -> Result<(), String> {
+    let ammo = format!(
+        "(clip: 0, reserve: {}, reload_frames: 1, trigger_ready: true)",
+        crate::weapons::MAX_RESERVE_AMMO
+    );
+    remove_component(snapshot, "web_ghost::simulation::BulletReady")?;
+    insert_beside::<Player>(snapshot, type_name::<crate::weapons::Ammo>(), &ammo);
+    Ok(())
+}
+
+/// Version 18 added the [`HillState`] resource, which starts out without a
+/// hill until the next round places one
+fn add_hill_state(snapshot: &mut String) -> Result<(), String> {
+    let hill = "(placed: false, center: (x: 0, y: 0), moves_in: 0, progress: [])";
+    insert_beside::<ZoneState>(snapshot, type_name::<HillState>(), hill);
+    Ok(())
+}
+
+/// Version 19 added [`MoveSpread`], which starts players out standing still
+///
+/// [`MoveSpread`]: crate::weapons::MoveSpread
+fn add_move_spread(snapshot: &mut String) -> Result<(), String> {
+    insert_beside::<Player>(snapshot, type_name::<crate::weapons::MoveSpread>(), "(0)");
+    Ok(())
+}
+
+/// Version 20 added [`LobbySettings::map_seed`]. Saved games were never on a
+/// generated map, so any seed will do.
+fn add_map_seed(snapshot: &mut String) -> Result<(), String> {
+    add_field::<LobbySettings>(snapshot, "map_seed: 0")
+}
+
+/// Version 21 added [`LobbySettings::bullet_collisions`], off as before
+fn add_bullet_collisions(snapshot: &mut String) -> Result<(), String> {
+    add_field::<LobbySettings>(snapshot, "bullet_collisions: false")
+}
+
+/// Version 22 added [`SweepStart`] to bullets. Every frame's move sets it
+/// before anything sweeps, so where it starts out doesn't matter.
+///
+/// [`SweepStart`]: crate::components::SweepStart
+fn add_sweep_start(snapshot: &mut String) -> Result<(), String> {
+    insert_beside::<crate::components::Bullet>(
+        snapshot,
+        type_name::<crate::components::SweepStart>(),
+        "((x: 0, y: 0))",
+    );
+    Ok(())
+}
+
+/// Version 23 added [`RoundState::start_frame`]. When the saved round started
+/// is lost, so its time counts from the start of the game.
+fn add_round_start_frame(snapshot: &mut String) -> Result<(), String> {
+    add_field::<RoundState>(snapshot, "start_frame: 0")
+}
+
+/// Adds `field` to the struct every entry for `T` holds
+fn add_field<T>(snapshot: &mut String, field: &str) -> Result<(), String> {
+    let key = component_key(type_name::<T>());
+    let entry = format!("{field}, ");
+    let mut from = 0;
+    while let Some(found) = snapshot[from..].find(&key) {
+        let value_start = from + found + key.len();
+        let open = snapshot[value_start..]
+            .find('(')
+            .ok_or_else(|| format!("no struct for {}", type_name::<T>()))?;
+        let at = value_start + open + 1;
+        snapshot.insert_str(at, &entry);
+        from = at + entry.len();
+    }
+    Ok(())
+}
+
+/// The reflect-serialized snapshot keys each entity's components, and the
+/// resources, by type name, so an entry can be added next to every entry for
+/// `T` by inserting it in front of that
+fn insert_beside<T>(snapshot: &mut String, component: &str, value: &str) {
+    let key = component_key(type_name::<T>());
+    let entry = format!("{}: {value}, ", component_key(component));
+    let mut from = 0;
+    while let Some(found) = snapshot[from..].find(&key) {
+        let at = from + found;
+        snapshot.insert_str(at, &entry);
+        from = at + entry.len() + key.len();
+    }
+}
+
+/// Removes every entry of `component`, with its value and trailing comma
+fn remove_component(snapshot: &mut String, component: &str) -> Result<(), String> {
+    let key = component_key(component);
+    while let Some(start) = snapshot.find(&key) {
+        let value_start = start + key.len();
+        let value_len = value_end(&snapshot[value_start..])
+            .ok_or_else(|| format!("unterminated value for {component}"))?;
+        let mut end = value_start + value_len;
+        let rest = &snapshot[end..];
+        if let Some(comma) = rest.trim_start().strip_prefix(',') {
+            end = snapshot.len() - comma.trim_start().len();
+        }
+        snapshot.replace_range(start..end, "");
+    }
+    Ok(())
+}
+
+pub fn component_key(component: &str) -> String {
+    format!("\"{component}\":")
+}
+
+/// Length of the value at the start of `text`, up to the comma or closing
+/// bracket that ends it
+pub fn value_end(text: &str) -> Option<usize> {
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (index, c) in text.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' if depth == 0 => return Some(index),
+            ')' | ']' | '}' => depth -= 1,
+            ',' if depth == 0 => return Some(index),
+            _ => {}
+        }
+    }
+    None
+}
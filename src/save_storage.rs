@@ -0,0 +1,213 @@
+use crate::{
+    components::GameSaveData,
+    save_format,
+    save_migration::{self, SAVE_VERSION},
+    storage::KeyValueStore,
+    wire_format::{Ron, TextFormat},
+};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+struct StoredSave {
+    version: u32,
+    /// The save as encoded by [`save_format::encode`], in base64 since local
+    /// storage only holds strings
+    data: String,
+}
+
+/// Just the version, so incompatible saves can be detected without parsing
+/// the rest
+#[derive(Deserialize)]
+struct StoredVersion {
+    version: u32,
+}
+
+fn storage_key(room: &str, tab_id: &str) -> String {
+    format!("game_save:{room}:{tab_id}")
+}
+
+/// Tabs with a save stored for `room`, since storage can't list its keys
+fn index_key(room: &str) -> String {
+    format!("game_save_index:{room}")
+}
+
+fn read_index(store: &dyn KeyValueStore, room: &str) -> Vec<String> {
+    store
+        .get(&index_key(room))
+        .and_then(|value| Ron::from_text(&value).ok())
+        .unwrap_or_default()
+}
+
+fn write_index(store: &dyn KeyValueStore, room: &str, tab_ids: &[String]) {
+    let key = index_key(room);
+    if tab_ids.is_empty() {
+        store.remove(&key);
+    } else if let Err(e) = Ron::to_text(&tab_ids).and_then(|value| store.set(&key, &value)) {
+        warn!("Failed to store game save index: {e}");
+    }
+}
+
+/// Keeps `save` in `store`, local storage in the game, so it survives every
+/// tab refreshing at once
+pub fn store(
+    store: &dyn KeyValueStore,
+    room: &str,
+    tab_id: &str,
+    save: &GameSaveData,
+) -> Result<(), String> {
+    let data = to_base64(&save_format::encode(save)).ok_or("couldn't encode it")?;
+    let stored = StoredSave {
+        version: SAVE_VERSION,
+        data,
+    };
+    let value = Ron::to_text(&stored)?;
+    store.set(&storage_key(room, tab_id), &value)?;
+    let mut index = read_index(store, room);
+    if !index.iter().any(|indexed| indexed == tab_id) {
+        index.push(tab_id.to_string());
+        write_index(store, room, &index);
+    }
+    Ok(())
+}
+
+/// Forgets the save stored for this room and tab
+pub fn remove(store: &dyn KeyValueStore, room: &str, tab_id: &str) {
+    store.remove(&storage_key(room, tab_id));
+    let mut index = read_index(store, room);
+    index.retain(|indexed| indexed != tab_id);
+    write_index(store, room, &index);
+}
+
+/// Every tab's save for `room`, by tab id, newest first. Only saves stored
+/// since the index was added are listed.
+pub fn list(store: &dyn KeyValueStore, room: &str) -> Vec<(String, GameSaveData)> {
+    let index = read_index(store, room);
+    let mut saves = index
+        .iter()
+        .filter_map(|tab_id| Some((tab_id.clone(), load(store, room, tab_id)?)))
+        .collect::<Vec<_>>();
+    if saves.len() != index.len() {
+        let kept = saves.iter().map(|(tab_id, _)| tab_id.clone());
+        write_index(store, room, &kept.collect::<Vec<_>>());
+    }
+    saves.sort_by(|(_, a), (_, b)| b.timestamp.cmp(&a.timestamp));
+    saves
+}
+
+/// Loads the save stored for this room and tab, migrating it if it was
+/// written by an older version and discarding it if it can't be
+pub fn load(store: &dyn KeyValueStore, room: &str, tab_id: &str) -> Option<GameSaveData> {
+    let key = storage_key(room, tab_id);
+    let value = store.get(&key)?;
+    let save = Ron::from_text::<StoredVersion>(&value)
+        .ok()
+        .filter(|stored| save_migration::is_compatible(stored.version))
+        .and_then(|_| Ron::from_text::<StoredSave>(&value).ok())
+        .and_then(|stored| {
+            let mut save = save_format::decode(&from_base64(&stored.data)?)?;
+            // Saves from before the version was kept in the save itself
+            if save.save_version == 0 {
+                save.save_version = stored.version;
+            }
+            save_migration::migrate(&mut save)
+                .map_err(|e| warn!("Failed to migrate game save {key}: {e}"))
+                .ok()?;
+            Some(save)
+        });
+    if save.is_none() {
+        info!("Discarding incompatible game save {key}");
+        store.remove(&key);
+    }
+    save
+}
+
+/// Base64 through the browser's `btoa`, which takes one char per byte
+#[cfg(not(feature = "native"))]
+fn to_base64(bytes: &[u8]) -> Option<String> {
+    let binary = bytes.iter().map(|byte| *byte as char).collect::<String>();
+    web_sys::window()?.btoa(&binary).ok()
+}
+
+#[cfg(not(feature = "native"))]
+fn from_base64(data: &str) -> Option<Vec<u8>> {
+    let binary = web_sys::window()?.atob(data).ok()?;
+    binary.chars().map(|c| u8::try_from(c).ok()).collect()
+}
+
+/// Same alphabet as `btoa`, so saves read the same on either platform
+#[cfg(feature = "native")]
+fn to_base64(bytes: &[u8]) -> Option<String> {
+    use base64::Engine;
+    Some(base64::engine::general_purpose::STANDARD.encode(bytes))
+}
+
+#[cfg(feature = "native")]
+fn from_base64(data: &str) -> Option<Vec<u8>> {
+    use base64::Engine;
+    base64::engine::general_purpose::STANDARD.decode(data).ok()
+}
+
+/// Run in a browser by the `wasm-tests` CI job, see .github/workflows/tests.yml
+#[cfg(all(test, target_arch = "wasm32"))]
+mod tests {
+    use super::*;
+    use crate::{
+        game_modes::DEFAULT_MODE,
+        match_config::{balance_hash, MatchConfig},
+        storage::{KeyValueStores, StorageArea},
+    };
+    use chrono::{Duration, Utc};
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    fn save(snapshot: &str, age_secs: i64) -> GameSaveData {
+        GameSaveData {
+            snapshot: snapshot.to_string(),
+            timestamp: Utc::now() - Duration::seconds(age_secs),
+            config: MatchConfig {
+                seed: 3,
+                mode: DEFAULT_MODE.to_string(),
+                balance_hash: balance_hash(),
+            },
+            save_version: SAVE_VERSION,
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn base64_keeps_every_byte() {
+        let bytes = (0..=u8::MAX).collect::<Vec<_>>();
+        assert_eq!(from_base64(&to_base64(&bytes).unwrap()), Some(bytes));
+    }
+
+    #[wasm_bindgen_test]
+    fn saves_survive_local_storage() {
+        let stores = KeyValueStores::get();
+        let local = stores.area(StorageArea::Local);
+        let room = "save_storage_test";
+        store(local, room, "tab_a", &save("(older)", 60)).unwrap();
+        store(local, room, "tab_b", &save("(newer)", 0)).unwrap();
+        let loaded = load(local, room, "tab_a").unwrap();
+        assert_eq!(loaded.snapshot, "(older)");
+        assert_eq!(loaded.config, save("", 0).config);
+
+        let listed = list(local, room);
+        let tab_ids = listed.iter().map(|(tab_id, _)| tab_id.as_str());
+        assert_eq!(tab_ids.collect::<Vec<_>>(), ["tab_b", "tab_a"]);
+
+        remove(local, room, "tab_a");
+        remove(local, room, "tab_b");
+        assert!(load(local, room, "tab_a").is_none());
+        assert!(list(local, room).is_empty());
+        assert_eq!(local.get(&index_key(room)), None);
+    }
+
+    #[wasm_bindgen_test]
+    fn unreadable_saves_are_discarded() {
+        let stores = KeyValueStores::get();
+        let local = stores.area(StorageArea::Local);
+        let key = storage_key("save_storage_test", "corrupt");
+        local.set(&key, "(version: 1, data: \"\")").unwrap();
+        assert!(load(local, "save_storage_test", "corrupt").is_none());
+        assert_eq!(local.get(&key), None);
+    }
+}
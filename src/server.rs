@@ -0,0 +1,249 @@
+use crate::{
+    components::{IsLocal, MatchBoxPeerId},
+    launch_config::LaunchConfig,
+    lobby::forget_peers,
+    room::Room,
+    GameState,
+};
+use bevy::prelude::*;
+use bevy_egui::{
+    egui::{Align2, TextEdit, Window},
+    EguiContexts,
+};
+use bevy_matchbox::prelude::*;
+
+/// Picks the matchbox signaling server, keeps retrying while it can't be
+/// reached, and tells the player about it instead of silently hanging. A
+/// socket the server closes on us is replaced, backing off between attempts,
+/// and the local player keeps their lobby state through it.
+pub struct ServerPlugin;
+
+impl Plugin for ServerPlugin {
+    fn build(&self, app: &mut App) {
+        let config = match &app.world.resource::<LaunchConfig>().server {
+            Some(url) => ServerConfig { url: url.clone() },
+            None => ServerConfig::from_page_url(),
+        };
+        app.insert_resource(config)
+            .init_resource::<ConnectionStatus>()
+            .add_systems(
+                (monitor_connection, connection_status_ui.after(monitor_connection))
+                    .in_set(OnUpdate(GameState::Matchmaking)),
+            );
+    }
+}
+
+const PRODUCTION_SERVER_URL: &str = "wss://areyougoingserver.solve.social";
+const LOCAL_SERVER_URL: &str = "ws://127.0.0.1:3536";
+#[cfg(feature = "native")]
+const SERVER_URL_VAR: &str = "WEB_GHOST_SERVER";
+/// How long to wait for the server to assign us an id before reconnecting
+const CONNECT_TIMEOUT_SECS: f64 = 10.;
+/// Wait before replacing a closed socket, doubled with every failed attempt
+const RETRY_BACKOFF_SECS: f64 = 1.;
+const MAX_RETRY_BACKOFF_SECS: f64 = 30.;
+
+/// Seconds to wait after the start of `attempt` before replacing its socket
+/// once it closed
+fn retry_backoff_secs(attempt: u32) -> f64 {
+    (RETRY_BACKOFF_SECS * 2_f64.powi(attempt.saturating_sub(1).min(16) as i32))
+        .min(MAX_RETRY_BACKOFF_SECS)
+}
+
+#[derive(Resource, Clone, Debug, PartialEq)]
+pub struct ServerConfig {
+    pub url: String,
+}
+
+impl ServerConfig {
+    /// Used unless the launch link names a server. Pages served from
+    /// localhost talk to a local `matchbox_server`, and everything else uses
+    /// the production server.
+    #[cfg(not(feature = "native"))]
+    fn from_page_url() -> Self {
+        use web_sys::window;
+
+        let is_local = window()
+            .and_then(|w| w.location().hostname().ok())
+            .map_or(false, |host| host == "localhost" || host == "127.0.0.1");
+        let url = if is_local {
+            LOCAL_SERVER_URL
+        } else {
+            PRODUCTION_SERVER_URL
+        };
+        Self {
+            url: url.to_string(),
+        }
+    }
+
+    /// Native builds have no page, so they take the server from the
+    /// environment and default to a local one
+    #[cfg(feature = "native")]
+    fn from_page_url() -> Self {
+        let url = std::env::var(SERVER_URL_VAR).unwrap_or_else(|_| LOCAL_SERVER_URL.to_string());
+        Self {
+            url: url.trim_end_matches('/').to_string(),
+        }
+    }
+}
+
+#[derive(Resource, Default, Debug)]
+pub enum ConnectionStatus {
+    #[default]
+    Disconnected,
+    Connecting {
+        started_at: f64,
+        attempt: u32,
+        last_error: Option<String>,
+        /// Set when we were connected before and the server dropped us
+        reconnecting: bool,
+    },
+    Connected,
+}
+
+pub fn connect_to_room(server: &ServerConfig, room: &Room) -> MatchboxSocket<MultipleChannels> {
+    let room_url = format!("{}/{}", server.url, room.0);
+    info!("connecting to matchbox server: {:?}", room_url);
+    MatchboxSocket::from(
+        WebRtcSocketBuilder::new(room_url)
+            .add_channel(ChannelConfig::ggrs())
+            .add_reliable_channel()
+            .build(),
+    )
+}
+
+fn monitor_connection(
+    mut commands: Commands,
+    time: Res<Time>,
+    server: Res<ServerConfig>,
+    room: Res<Room>,
+    socket: Option<Res<MatchboxSocket<MultipleChannels>>>,
+    mut status: ResMut<ConnectionStatus>,
+    mut local_player: Query<&mut MatchBoxPeerId, With<IsLocal>>,
+    remote_peers: Query<Entity, (With<MatchBoxPeerId>, Without<IsLocal>)>,
+) {
+    let now = time.elapsed_seconds_f64();
+    let closed = socket.as_ref().map_or(false, |s| s.any_closed());
+    if let Some(id) = socket.as_ref().and_then(|s| s.id()).filter(|_| !closed) {
+        if !matches!(*status, ConnectionStatus::Connected) {
+            info!("Connected to matchbox server");
+            *status = ConnectionStatus::Connected;
+        }
+        // A new socket comes with a new id, but the local player stays who
+        // they were
+        if let Ok(mut local_peer_id) = local_player.get_single_mut() {
+            if local_peer_id.0 != id {
+                local_peer_id.0 = id;
+            }
+        }
+        return;
+    }
+    match &mut *status {
+        ConnectionStatus::Connecting {
+            started_at,
+            attempt,
+            last_error,
+            ..
+        } => {
+            let (timed_out, error) = if closed {
+                (
+                    now - *started_at > retry_backoff_secs(*attempt),
+                    format!("Connection to {} closed", server.url),
+                )
+            } else {
+                (
+                    now - *started_at > CONNECT_TIMEOUT_SECS,
+                    format!("No response from {}", server.url),
+                )
+            };
+            if timed_out {
+                warn!("{error}, retrying");
+                *last_error = Some(error);
+                *attempt += 1;
+                *started_at = now;
+                commands.insert_resource(connect_to_room(&server, &room));
+            }
+        }
+        ConnectionStatus::Connected => {
+            warn!("Lost the connection to the matchbox server, reconnecting");
+            // The others see us leave, and are met again once reconnected
+            for entity in remote_peers.iter() {
+                commands.entity(entity).despawn();
+            }
+            forget_peers(&mut commands);
+            commands.insert_resource(connect_to_room(&server, &room));
+            *status = ConnectionStatus::Connecting {
+                started_at: now,
+                attempt: 1,
+                last_error: Some(format!("Lost the connection to {}", server.url)),
+                reconnecting: true,
+            };
+        }
+        ConnectionStatus::Disconnected => {
+            *status = ConnectionStatus::Connecting {
+                started_at: now,
+                attempt: 1,
+                last_error: None,
+                reconnecting: false,
+            };
+            if socket.is_none() {
+                commands.insert_resource(connect_to_room(&server, &room));
+            }
+        }
+    }
+}
+
+fn connection_status_ui(
+    mut contexts: EguiContexts,
+    mut commands: Commands,
+    time: Res<Time>,
+    mut server: ResMut<ServerConfig>,
+    room: Res<Room>,
+    mut status: ResMut<ConnectionStatus>,
+    mut working_url: Local<Option<String>>,
+) {
+    let ConnectionStatus::Connecting {
+        attempt,
+        last_error,
+        reconnecting,
+        ..
+    } = &*status
+    else {
+        return;
+    };
+    let (attempt, last_error, reconnecting) = (*attempt, last_error.clone(), *reconnecting);
+    let url = working_url.get_or_insert_with(|| server.url.clone());
+    let title = if reconnecting {
+        "Reconnecting"
+    } else {
+        "Connecting"
+    };
+    Window::new(title)
+        .anchor(Align2::CENTER_CENTER, [0., 0.])
+        .collapsible(false)
+        .resizable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.label(format!("Connecting to {} (attempt {attempt})...", server.url));
+            if let Some(error) = last_error {
+                ui.colored_label(ui.visuals().warn_fg_color, error);
+            }
+            if reconnecting {
+                ui.weak("You keep your name, readiness and save while reconnecting");
+            }
+            ui.separator();
+            ui.horizontal(|ui| {
+                ui.label("Server:");
+                ui.add(TextEdit::singleline(url));
+            });
+            if ui.button("Reconnect").clicked() {
+                server.url = url.trim_end_matches('/').to_string();
+                commands.insert_resource(connect_to_room(&server, &room));
+                *status = ConnectionStatus::Connecting {
+                    started_at: time.elapsed_seconds_f64(),
+                    attempt: 1,
+                    last_error: None,
+                    reconnecting,
+                };
+            }
+        });
+}
@@ -0,0 +1,162 @@
+use crate::{
+    components::{MatchBoxPeerId, UserInfo},
+    net::DesyncDetected,
+    presentation_clock::PresentationClock,
+    GameState, GgrsConfig,
+};
+use bevy::{prelude::*, utils::HashMap};
+use bevy_egui::{
+    egui::{Align2, Area, Frame, RichText},
+    EguiContexts,
+};
+use bevy_ggrs::{ggrs::GGRSEvent, Session};
+use bevy_matchbox::prelude::PeerId;
+
+/// Handles everything GGRS reports about the session. Reading events from the
+/// session consumes them, so they're drained once per frame and passed on as
+/// [`GgrsSessionEvent`]s for any system to read. Short interruptions show a
+/// banner and pause presentation until the peer is back, and only a real
+/// disconnect ends the game, through [`crate::save::kill_game`]. Wait
+/// recommendations are left to [`crate::frame_advantage`].
+pub struct SessionEventsPlugin;
+
+impl Plugin for SessionEventsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ConnectionInterrupted>()
+            .add_event::<GgrsSessionEvent>()
+            .add_system(
+                drain_session_events
+                    .before(session_events)
+                    .run_if(resource_exists::<Session<GgrsConfig>>()),
+            )
+            .add_systems(
+                (
+                    session_events,
+                    pause_presentation.after(session_events),
+                    interrupted_ui,
+                )
+                    .in_set(OnUpdate(GameState::InGame)),
+            )
+            .add_system(reset_session_events.in_schedule(OnExit(GameState::InGame)));
+    }
+}
+
+/// Something GGRS reported about the running session
+#[derive(Clone, Debug)]
+pub struct GgrsSessionEvent(pub GGRSEvent<GgrsConfig>);
+
+/// Peers that stopped sending, with the time at which GGRS gives up on them
+#[derive(Resource, Default)]
+struct ConnectionInterrupted(HashMap<PeerId, f64>);
+
+/// Set when a peer disconnected for good, for [`crate::save::kill_game`] to end the
+/// game
+#[derive(Resource)]
+pub struct SessionDisconnected;
+
+fn drain_session_events(
+    mut session: ResMut<Session<GgrsConfig>>,
+    mut events: EventWriter<GgrsSessionEvent>,
+) {
+    let drained = match &mut *session {
+        Session::P2PSession(session) => session.events().collect::<Vec<_>>(),
+        Session::SpectatorSession(session) => session.events().collect(),
+        Session::SyncTestSession(_) => return,
+    };
+    events.send_batch(drained.into_iter().map(GgrsSessionEvent));
+}
+
+pub fn session_events(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut events: EventReader<GgrsSessionEvent>,
+    mut interrupted: ResMut<ConnectionInterrupted>,
+) {
+    let now = time.elapsed_seconds_f64();
+    for GgrsSessionEvent(event) in events.iter() {
+        match *event {
+            GGRSEvent::DesyncDetected {
+                frame,
+                local_checksum,
+                remote_checksum,
+                addr,
+            } => {
+                error!(
+                    "Desync detected at frame {frame}: local checksum {local_checksum:#x}, \
+                     remote checksum {remote_checksum:#x} from {addr:?}"
+                );
+                commands.insert_resource(DesyncDetected {
+                    frame,
+                    local_checksum,
+                });
+            }
+            GGRSEvent::NetworkInterrupted {
+                addr,
+                disconnect_timeout,
+            } => {
+                warn!("Connection to {addr:?} interrupted");
+                let deadline = now + disconnect_timeout as f64 / 1000.;
+                interrupted.0.insert(addr, deadline);
+            }
+            GGRSEvent::NetworkResumed { addr } => {
+                info!("Connection to {addr:?} resumed");
+                interrupted.0.remove(&addr);
+            }
+            // Handled by the frame advantage throttle
+            GGRSEvent::WaitRecommendation { .. } => {}
+            GGRSEvent::Disconnected { addr } => {
+                info!("GGRS Disconnect event detected for {addr:?}");
+                interrupted.0.remove(&addr);
+                commands.insert_resource(SessionDisconnected);
+            }
+            ref other => trace!("{other:?}"),
+        }
+    }
+}
+
+/// The simulation stops once it can't predict any further, so cosmetic
+/// motion stops with it instead of running on ahead
+fn pause_presentation(
+    interrupted: Res<ConnectionInterrupted>,
+    mut clock: ResMut<PresentationClock>,
+) {
+    clock.set_paused(!interrupted.0.is_empty());
+}
+
+fn interrupted_ui(
+    mut contexts: EguiContexts,
+    time: Res<Time>,
+    interrupted: Res<ConnectionInterrupted>,
+    peers: Query<(&MatchBoxPeerId, &UserInfo)>,
+) {
+    if interrupted.0.is_empty() {
+        return;
+    }
+    let now = time.elapsed_seconds_f64();
+    Area::new("connection_interrupted")
+        .anchor(Align2::CENTER_TOP, [0., 40.])
+        .interactable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            Frame::popup(ui.style()).show(ui, |ui| {
+                ui.label(RichText::new("Connection interrupted").strong());
+                for (peer_id, deadline) in interrupted.0.iter() {
+                    let name = peers
+                        .iter()
+                        .find(|(id, _)| id.0 == *peer_id)
+                        .map_or("A player", |(_, info)| info.name.as_str());
+                    let secs_left = (deadline - now).max(0.).ceil();
+                    ui.label(format!("Waiting for {name}, dropping them in {secs_left}s"));
+                }
+            });
+        });
+}
+
+fn reset_session_events(
+    mut commands: Commands,
+    mut interrupted: ResMut<ConnectionInterrupted>,
+    mut clock: ResMut<PresentationClock>,
+) {
+    interrupted.0.clear();
+    clock.set_paused(false);
+    commands.remove_resource::<SessionDisconnected>();
+}
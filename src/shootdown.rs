@@ -0,0 +1,136 @@
+use crate::{
+    components::{Active, Bullet, MoveDir, Position, Radius, Shooter, SweepStart},
+    lobby_settings::LobbySettings,
+    physics::{sweep_circle_circle, TOI_SCALE},
+    rng::SimFrame,
+    simulation::{apply_damage, expire_bullets, move_bullet},
+    spatial::{index_bullets, SpatialGrid},
+    vfx::{Effect, VfxQueue},
+};
+use bevy::{prelude::*, utils::HashMap};
+use bevy_ggrs::{GGRSSchedule, Rollback};
+
+/// Bullets from different shooters that meet destroy each other, with the
+/// lobby's `bullet_collisions` setting, so incoming shots can be shot down.
+/// Runs before [`apply_damage`], so a bullet shot down never lands. Bullets
+/// are only tested against others the [`SpatialGrid`] finds near their move,
+/// which keeps the cost in line with the number of bullets rather than its
+/// square.
+pub struct ShootdownPlugin;
+
+impl Plugin for ShootdownPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(
+            shoot_down_bullets
+                .after(move_bullet)
+                .after(index_bullets)
+                .before(apply_damage)
+                .before(expire_bullets)
+                .in_schedule(GGRSSchedule),
+        );
+    }
+}
+
+/// A bullet's move this frame
+struct Sweep {
+    id: u32,
+    entity: Entity,
+    from: IVec2,
+    to: IVec2,
+    radius: i32,
+    shooter: usize,
+    dir: IVec2,
+}
+
+fn shoot_down_bullets(
+    settings: Res<LobbySettings>,
+    frame: Res<SimFrame>,
+    grid: Res<SpatialGrid>,
+    mut vfx: ResMut<VfxQueue>,
+    mut bullets: Query<
+        (
+            Entity,
+            &Rollback,
+            &mut Active,
+            &Position,
+            &Radius,
+            &Shooter,
+            &MoveDir,
+            &SweepStart,
+        ),
+        With<Bullet>,
+    >,
+) {
+    if !settings.bullet_collisions {
+        return;
+    }
+    let mut sweeps = bullets
+        .iter()
+        .filter(|(_, _, active, ..)| active.0)
+        .map(
+            |(entity, rollback, _, position, radius, shooter, dir, sweep_start)| Sweep {
+                id: rollback.id(),
+                entity,
+                from: sweep_start.0,
+                to: position.0,
+                radius: radius.0,
+                shooter: shooter.0,
+                dir: dir.0,
+            },
+        )
+        .collect::<Vec<_>>();
+    // Query order may differ between peers, rollback ids don't
+    sweeps.sort_by_key(|sweep| sweep.id);
+    let indices = sweeps
+        .iter()
+        .enumerate()
+        .map(|(index, sweep)| (sweep.entity, index))
+        .collect::<HashMap<_, _>>();
+
+    let mut pairs = Vec::new();
+    for (a, sweep) in sweeps.iter().enumerate() {
+        for entity in grid.query_sweep(sweep.from, sweep.to, sweep.radius) {
+            match indices.get(&entity) {
+                Some(&b) if b > a && sweeps[b].shooter != sweep.shooter => pairs.push((a, b)),
+                _ => {}
+            }
+        }
+    }
+
+    // Each bullet is only seen along its own move, so the test runs on their
+    // move relative to each other
+    let mut hits = pairs
+        .into_iter()
+        .filter_map(|(a, b)| {
+            let (a_sweep, b_sweep) = (&sweeps[a], &sweeps[b]);
+            let b_from = settings.nearest_image(b_sweep.from, a_sweep.from);
+            let from = a_sweep.from - b_from;
+            let to = from + (a_sweep.to - a_sweep.from) - (b_sweep.to - b_sweep.from);
+            sweep_circle_circle(from, to, IVec2::ZERO, a_sweep.radius + b_sweep.radius)
+                .map(|toi| (toi, a, b))
+        })
+        .collect::<Vec<_>>();
+    // Earliest first, so a bullet meeting two others takes out the first
+    hits.sort_unstable();
+
+    let mut destroyed = vec![false; sweeps.len()];
+    for (toi, a, b) in hits {
+        if destroyed[a] || destroyed[b] {
+            continue;
+        }
+        destroyed[a] = true;
+        destroyed[b] = true;
+        let sweep = &sweeps[a];
+        let along = |from: i32, to: i32| from + ((to - from) as i64 * toi / TOI_SCALE) as i32;
+        let at = IVec2::new(
+            along(sweep.from.x, sweep.to.x),
+            along(sweep.from.y, sweep.to.y),
+        );
+        vfx.push(&frame, Effect::Shootdown, sweep.shooter, at, sweep.dir);
+        for index in [a, b] {
+            if let Ok((_, _, mut active, ..)) = bullets.get_mut(sweeps[index].entity) {
+                active.0 = false;
+            }
+        }
+    }
+}
@@ -0,0 +1,871 @@
+use crate::{
+    achievements::{forget_resimulated_events, GameplayEvent, GameplayEvents},
+    audio::{Sound, SoundQueue},
+    budget::{bullet_budget, expiry_order, BudgetedBullet, BULLET_POOL_SIZE},
+    classes::PlayerClass,
+    components::*,
+    dash::{DashCooldown, DashPlugin, DASH_SPEED_PERCENT},
+    debug_assert_headroom, fixed_point,
+    hazards::{touch_hazards, Hazard, HazardsPlugin},
+    hill::{HillPlugin, HillState},
+    input::{aim_direction, direction, fire, reload, DIRECTION_SCALE},
+    lobby_settings::{LobbySettings, MAX_BOUNCES},
+    maps::CurrentMap,
+    match_config::MatchConfig,
+    physics::{separate_circles, sweep_circle_circle},
+    pickups::{
+        collect_pickups, spawn_pickups, Pickup, PickupKind, PickupsPlugin, RapidFire, SpeedBoost,
+    },
+    player::PLAYER_RADIUS_SI,
+    portals::{PortalCooldown, PortalsPlugin},
+    rng::{advance_sim_frame, SimFrame, SimRng},
+    rounds::{round_in_progress, RoundState, RoundsPlugin},
+    shootdown::ShootdownPlugin,
+    spatial::{SpatialGrid, SpatialPlugin},
+    vfx::{Effect, VfxQueue},
+    weapons::{
+        switch_weapons, Ammo, BulletSpeed, MoveSpread, SwitchReady, Weapon, WeaponCooldown,
+        MOVE_SPREAD_FRAMES,
+    },
+    zone::{ZonePlugin, ZoneState},
+    GameState, GgrsConfig, F2I,
+};
+use bevy::{prelude::*, utils::HashMap};
+use bevy_ggrs::{GGRSPlugin, GGRSSchedule, PlayerInputs, Rollback, RollbackIdProvider};
+
+/// GGRS with everything the simulation rolls back registered. Callers add
+/// their input system and build it.
+pub fn ggrs_plugin() -> GGRSPlugin<GgrsConfig> {
+    GGRSPlugin::<GgrsConfig>::new()
+        .register_rollback_component::<Position>()
+        .register_rollback_component::<Ammo>()
+        .register_rollback_component::<MoveDir>()
+        .register_rollback_component::<Active>()
+        .register_rollback_component::<Radius>()
+        .register_rollback_component::<TabId>()
+        .register_rollback_component::<Lifetime>()
+        .register_rollback_component::<Bounces>()
+        .register_rollback_component::<SweepStart>()
+        .register_rollback_component::<Health>()
+        .register_rollback_component::<Damage>()
+        .register_rollback_component::<Invulnerable>()
+        .register_rollback_component::<Pickup>()
+        .register_rollback_component::<SpeedBoost>()
+        .register_rollback_component::<RapidFire>()
+        .register_rollback_component::<Shooter>()
+        .register_rollback_component::<Velocity>()
+        .register_rollback_component::<FireSlowdown>()
+        .register_rollback_component::<Weapon>()
+        .register_rollback_component::<WeaponCooldown>()
+        .register_rollback_component::<SwitchReady>()
+        .register_rollback_component::<BulletSpeed>()
+        .register_rollback_component::<LastHit>()
+        .register_rollback_component::<DashCooldown>()
+        .register_rollback_component::<PlayerClass>()
+        .register_rollback_component::<PortalCooldown>()
+        .register_rollback_component::<Hazard>()
+        .register_rollback_component::<MoveSpread>()
+        .register_rollback_resource::<SimRng>()
+        .register_rollback_resource::<SimFrame>()
+        .register_rollback_resource::<RoundState>()
+        .register_rollback_resource::<LobbySettings>()
+        .register_rollback_resource::<ZoneState>()
+        .register_rollback_resource::<HillState>()
+        .register_type_dependency::<bool>()
+        .register_type_dependency::<String>()
+        .register_type_dependency::<IVec2>()
+        .register_type_dependency::<i32>()
+        .register_type_dependency::<u8>()
+        .register_type_dependency::<u32>()
+        .register_type_dependency::<u64>()
+        .register_type_dependency::<usize>()
+        .register_type_dependency::<Vec<i32>>()
+        .register_type_dependency::<Vec<u32>>()
+        .register_type_dependency::<Vec<usize>>()
+        .register_type_dependency::<PickupKind>()
+}
+
+/// The deterministic part of the game: movement, portals, hazards, combat,
+/// bullets shooting each other down, pickups, rounds, the hill and the
+/// shrinking zone, run in the GGRS schedule. It touches neither the window
+/// nor the renderer, so it builds without the `presentation` feature. What
+/// the simulation wants shown or heard goes through the [`SoundQueue`],
+/// [`VfxQueue`] and [`GameplayEvents`], and sprites are added by
+/// presentation systems.
+pub struct SimulationPlugin;
+
+impl Plugin for SimulationPlugin {
+    fn build(&self, app: &mut App) {
+        app.edit_schedule(GGRSSchedule, |schedule| {
+            schedule
+                .set_default_base_set(SimulationTimingSet::Simulate)
+                .configure_sets(
+                    (
+                        SimulationTimingSet::Start,
+                        SimulationTimingSet::Simulate,
+                        SimulationTimingSet::End,
+                    )
+                        .chain(),
+                );
+        })
+        .init_resource::<SimRng>()
+        .init_resource::<SimFrame>()
+        .init_resource::<LobbySettings>()
+        .init_resource::<CurrentMap>()
+        .init_resource::<GameplayEvents>()
+        .init_resource::<SoundQueue>()
+        .init_resource::<VfxQueue>()
+        .add_systems(
+            (
+                insert_player_components,
+                spawn_bullet_pool.after(insert_player_components),
+                seed_rng,
+            )
+                .in_schedule(OnEnter(GameState::InGame)),
+        )
+        .add_systems(
+            (
+                advance_sim_frame,
+                forget_resimulated_events
+                    .after(advance_sim_frame)
+                    .before(touch_hazards)
+                    .before(collect_pickups)
+                    .before(ring_out)
+                    .before(apply_damage),
+                ring_out
+                    .after(advance_sim_frame)
+                    .before(move_players)
+                    .run_if(round_in_progress),
+                move_players
+                    .after(advance_sim_frame)
+                    .run_if(round_in_progress),
+                collide_players
+                    .after(move_players)
+                    .run_if(round_in_progress),
+                reload_weapons.after(switch_weapons),
+                switch_weapons.after(advance_sim_frame),
+                fire_bullets
+                    .after(collide_players)
+                    .after(spawn_pickups)
+                    .after(reload_weapons)
+                    .after(switch_weapons)
+                    .run_if(round_in_progress),
+                move_bullet.after(move_players).after(fire_bullets),
+                apply_damage
+                    .after(move_bullet)
+                    .after(move_players)
+                    .run_if(round_in_progress),
+                expire_bullets.after(move_bullet).after(apply_damage),
+            )
+                .in_schedule(GGRSSchedule),
+        )
+        .add_plugin(PickupsPlugin)
+        .add_plugin(DashPlugin)
+        .add_plugin(RoundsPlugin)
+        .add_plugin(ZonePlugin)
+        .add_plugin(PortalsPlugin)
+        .add_plugin(HazardsPlugin)
+        .add_plugin(HillPlugin)
+        .add_plugin(ShootdownPlugin)
+        .add_plugin(SpatialPlugin);
+    }
+}
+
+/// Brackets every other system in the rollback schedule, for timing it and
+/// for looking at the state each frame ends with
+#[derive(SystemSet, Debug, Hash, PartialEq, Eq, Clone, Copy)]
+#[system_set(base)]
+pub enum SimulationTimingSet {
+    Start,
+    Simulate,
+    End,
+}
+
+pub const PLAYER_MAX_HEALTH: i32 = 100;
+pub const SPAWN_INVULNERABILITY_FRAMES: u32 = 2 * 60;
+pub const PLAYER_MOVE_SPEED_SI: i32 = (13 * F2I) / 100;
+/// Fraction of knockback velocity kept each frame
+const VELOCITY_RETAINED_PERCENT: i32 = 80;
+/// Knockback from a bullet hit at 100% of the lobby's knockback. Velocity
+/// decays, so this carries the player about five times as far in total.
+const HIT_KNOCKBACK_SI: i32 = 25 * F2I / 100;
+/// How recently a player must have been hit for their ring out to count as
+/// the shooter's kill
+const RING_OUT_CREDIT_FRAMES: u32 = 3 * 60;
+pub const BULLET_SPEED_SI: i32 = (35 * F2I) / 100;
+/// Longest interval between shots while rapid fire is active, which also
+/// makes every weapon automatic
+const RAPID_FIRE_INTERVAL_FRAMES: u32 = 8;
+
+/// Fresh games start from the agreed seed; resumed ones get their RNG state
+/// back from the snapshot
+pub fn seed_rng(config: Res<MatchConfig>, mut rng: ResMut<SimRng>, mut frame: ResMut<SimFrame>) {
+    *rng = config.rng();
+    *frame = SimFrame::default();
+}
+
+/// Gives every player the components the simulation needs. Their sprites are
+/// up to the presentation.
+pub fn insert_player_components(
+    mut commands: Commands,
+    mut rip: ResMut<RollbackIdProvider>,
+    settings: Res<LobbySettings>,
+    map: Res<CurrentMap>,
+    players: Query<(Entity, &Player, Option<&UserInfo>)>, // This won't find any if loaded from gamestate
+) {
+    for (entity, player, info) in players.iter() {
+        let (spawn_pos, spawn_dir) = map.spawn_position(player.handle, settings.map_size_si());
+        let class = info.map_or_else(PlayerClass::default, |info| info.class);
+        commands.entity(entity).insert((
+            Rollback::new(rip.next_id()),
+            MoveDir(spawn_dir),
+            Position(spawn_pos),
+            Radius(PLAYER_RADIUS_SI),
+            Health(class.stats().max_health),
+            Invulnerable(SPAWN_INVULNERABILITY_FRAMES),
+            SpeedBoost(0),
+            RapidFire(0),
+            Velocity::default(),
+            FireSlowdown(0),
+            Weapon::default(),
+            WeaponCooldown::default(),
+            SwitchReady::default(),
+            LastHit::default(),
+        ));
+        // Bundles top out at 15 components
+        commands.entity(entity).insert((
+            class,
+            DashCooldown::default(),
+            PortalCooldown::default(),
+            Ammo::default(),
+            MoveSpread::default(),
+        ));
+    }
+}
+
+/// Spawns every bullet the game can have in flight, inactive, so shots reuse
+/// them instead of adding and removing rollback entities. After the players,
+/// so rollback ids come out the same on every peer.
+pub fn spawn_bullet_pool(mut commands: Commands, mut rip: ResMut<RollbackIdProvider>) {
+    for _ in 0..BULLET_POOL_SIZE {
+        commands.spawn(pooled_bullet(&mut rip));
+    }
+}
+
+/// A bullet of the pool, waiting to be fired
+pub fn pooled_bullet(rip: &mut RollbackIdProvider) -> impl Bundle {
+    (
+        Bullet,
+        Active(false),
+        Rollback::new(rip.next_id()),
+        MoveDir::default(),
+        Position::default(),
+        Radius::default(),
+        Lifetime::default(),
+        Bounces::default(),
+        SweepStart::default(),
+        Damage::default(),
+        BulletSpeed::default(),
+        Shooter::default(),
+    )
+}
+
+pub fn move_players(
+    inputs: Res<PlayerInputs<GgrsConfig>>,
+    settings: Res<LobbySettings>,
+    map: Res<CurrentMap>,
+    mut player_query: Query<(
+        &mut Position,
+        &mut MoveDir,
+        &Player,
+        &Health,
+        &SpeedBoost,
+        &mut Velocity,
+        &mut FireSlowdown,
+        &Weapon,
+        &DashCooldown,
+        &PlayerClass,
+        &mut MoveSpread,
+    )>,
+) {
+    for (
+        mut position,
+        mut move_dir,
+        player,
+        health,
+        speed_boost,
+        mut velocity,
+        mut slowdown,
+        weapon,
+        dash,
+        class,
+        mut move_spread,
+    ) in player_query.iter_mut()
+    {
+        if health.0 <= 0 {
+            continue;
+        }
+        let (input, _) = inputs[player.handle];
+        let direction = direction(input);
+        move_spread.0 = if direction != IVec2::ZERO {
+            MOVE_SPREAD_FRAMES
+        } else {
+            move_spread.0.saturating_sub(1)
+        };
+
+        let base_speed = settings.player_speed_si() * class.stats().speed_percent / 100;
+        let mut speed = if speed_boost.0 > 0 {
+            base_speed * 3 / 2
+        } else {
+            base_speed
+        };
+        if slowdown.0 > 0 {
+            slowdown.0 -= 1;
+            speed = speed * weapon.stats().slowdown_speed_percent / 100;
+        }
+        if dash.is_dashing() {
+            speed = speed * DASH_SPEED_PERCENT / 100;
+        }
+        let knockback = velocity.0;
+        velocity.0 = velocity.0 * VELOCITY_RETAINED_PERCENT / 100;
+
+        if direction == IVec2::ZERO && knockback == IVec2::ZERO {
+            continue;
+        }
+        if direction != IVec2::ZERO {
+            move_dir.0 = direction;
+        }
+        let move_delta = (direction * speed) / DIRECTION_SCALE + knockback;
+
+        let new_pos = confine_player(&settings, &map, position.0 + move_delta);
+        debug_assert_headroom(new_pos, "player");
+
+        position.0.x = new_pos.x;
+        position.0.y = new_pos.y;
+    }
+}
+
+/// Keeps a player that moved on the map and out of walls
+fn confine_player(settings: &LobbySettings, map: &CurrentMap, position: IVec2) -> IVec2 {
+    let position = if settings.wrap_around {
+        settings.wrap_position(position)
+    } else {
+        let limit = IVec2::splat(settings.half_map_size_si());
+        position.clamp(-limit, limit)
+    };
+    map.push_out_of_walls(position, PLAYER_RADIUS_SI)
+}
+
+/// Takes players out of the round when knockback carries them past the edge
+/// of the map, under the lobby's ring out rule. Recoil counts too, so firing
+/// with your back to the edge is a risk. The last player to hit them within
+/// [`RING_OUT_CREDIT_FRAMES`] gets the kill.
+pub fn ring_out(
+    settings: Res<LobbySettings>,
+    frame: Res<SimFrame>,
+    mut players: Query<(&Player, &Position, &Velocity, &LastHit, &mut Health)>,
+    mut sounds: ResMut<SoundQueue>,
+    mut vfx: ResMut<VfxQueue>,
+    mut events: ResMut<GameplayEvents>,
+) {
+    if !settings.ring_out() {
+        return;
+    }
+    let limit = settings.half_map_size_si();
+    // Handle order, so sounds and effects queue identically on every peer
+    let mut players = players.iter_mut().collect::<Vec<_>>();
+    players.sort_by_key(|(player, ..)| player.handle);
+    for (player, position, velocity, last_hit, mut health) in players {
+        let pushed_to = position.0 + velocity.0;
+        if health.0 <= 0 || velocity.0 == IVec2::ZERO || pushed_to.abs().max_element() <= limit {
+            continue;
+        }
+        health.0 = 0;
+        let credited = last_hit.frame > 0
+            && last_hit.shooter != player.handle
+            && frame.0.saturating_sub(last_hit.frame) <= RING_OUT_CREDIT_FRAMES;
+        let event = if credited {
+            GameplayEvent::Kill {
+                shooter: last_hit.shooter,
+                victim: player.handle,
+            }
+        } else {
+            GameplayEvent::Death {
+                victim: player.handle,
+            }
+        };
+        events.push(&frame, event);
+        sounds.push(&frame, Sound::Death, player.handle);
+        vfx.push(
+            &frame,
+            Effect::DeathBurst,
+            player.handle,
+            position.0,
+            IVec2::ZERO,
+        );
+    }
+}
+
+/// Pushes overlapping players apart with the lobby's body blocking. Pairs
+/// are resolved in handle order, so every peer untangles crowds the same way.
+pub fn collide_players(
+    settings: Res<LobbySettings>,
+    map: Res<CurrentMap>,
+    mut players: Query<(&Player, &Health, &Radius, &mut Position)>,
+) {
+    if !settings.body_blocking {
+        return;
+    }
+    let mut bodies = players
+        .iter_mut()
+        .filter(|(_, health, ..)| health.0 > 0)
+        .map(|(player, _, radius, position)| (player.handle, radius.0, position))
+        .collect::<Vec<_>>();
+    bodies.sort_by_key(|(handle, ..)| *handle);
+    for i in 0..bodies.len() {
+        for j in i + 1..bodies.len() {
+            let (a, b) = (bodies[i].2 .0, bodies[j].2 .0);
+            let radius = bodies[i].1 + bodies[j].1;
+            let Some(push) = separate_circles(a, settings.nearest_image(b, a), radius) else {
+                continue;
+            };
+            bodies[i].2 .0 = a - push / 2;
+            bodies[j].2 .0 = b + (push - push / 2);
+        }
+    }
+    for (.., position) in bodies.iter_mut() {
+        position.0 = confine_player(&settings, &map, position.0);
+    }
+}
+
+pub fn fire_bullets(
+    inputs: Res<PlayerInputs<GgrsConfig>>,
+    mut player_query: Query<
+        (
+            &Position,
+            &Player,
+            &mut Ammo,
+            &MoveDir,
+            &Radius,
+            &Health,
+            &Weapon,
+            &mut WeaponCooldown,
+            &RapidFire,
+            &mut Velocity,
+            &mut FireSlowdown,
+            &PlayerClass,
+            &MoveSpread,
+        ),
+        Without<Bullet>,
+    >,
+    mut bullets: Query<
+        (
+            Entity,
+            &Rollback,
+            &mut Active,
+            &mut Position,
+            &mut MoveDir,
+            &mut Radius,
+            &mut Lifetime,
+            &mut Bounces,
+            &mut Damage,
+            &mut BulletSpeed,
+            &mut Shooter,
+        ),
+        With<Bullet>,
+    >,
+    others: Query<(), (With<Rollback>, Without<Bullet>)>,
+    frame: Res<SimFrame>,
+    mut sounds: ResMut<SoundQueue>,
+    mut vfx: ResMut<VfxQueue>,
+    mut events: ResMut<GameplayEvents>,
+    mut rng: ResMut<SimRng>,
+) {
+    let mut players = player_query.iter_mut().collect::<Vec<_>>();
+    // Fire in handle order so the same players hit the budget on every peer
+    players.sort_by_key(|(_, player, ..)| player.handle);
+    let mut bullet_count = bullets.iter().filter(|(_, _, active, ..)| active.0).count();
+    let budget = bullet_budget(players.len(), others.iter().len());
+    let mut expiring = expiry_order(bullets.iter().filter(|(_, _, active, ..)| active.0).map(
+        |(entity, _, _, position, _, _, lifetime, _, _, _, shooter)| BudgetedBullet {
+            entity,
+            lifetime: lifetime.0,
+            shooter: shooter.0,
+            position: position.0,
+        },
+    ))
+    .into_iter();
+    for (
+        player_transform,
+        player,
+        mut ammo,
+        player_move_dir,
+        player_radius,
+        health,
+        weapon,
+        mut cooldown,
+        rapid_fire,
+        mut velocity,
+        mut slowdown,
+        class,
+        move_spread,
+    ) in players
+    {
+        let (input, _) = inputs[player.handle];
+        let stats = weapon.stats();
+        let bullet_radius = class.stats().bullet_radius_si;
+        let shot_size = stats.bullets_per_shot as usize;
+        if !fire(input) || !ammo.can_fire() || cooldown.0 > 0 || health.0 <= 0 || shot_size > budget
+        {
+            continue;
+        }
+        // Over budget, the oldest bullets go back to the pool to make way
+        while bullet_count + shot_size > budget {
+            let Some(entity) = expiring.next() else {
+                break;
+            };
+            if let Ok((_, _, mut active, ..)) = bullets.get_mut(entity) {
+                active.0 = false;
+            }
+            bullet_count -= 1;
+        }
+        // Only when others' shots this frame already filled the budget
+        if bullet_count + shot_size > budget {
+            continue;
+        }
+        bullet_count += shot_size;
+        let aim = aim_direction(input).unwrap_or(player_move_dir.0);
+        // The whole shot is thrown off at once. Standing still draws nothing
+        // from the RNG.
+        let max_angle = move_spread.max_angle();
+        let aim = if max_angle > 0 {
+            fixed_point::rotate(aim, rng.range_i32(-max_angle, max_angle + 1))
+        } else {
+            aim
+        };
+        for bullet_dir in weapon.shot_directions(aim) {
+            // The free bullet with the lowest rollback id, so every peer and
+            // every resimulation picks the same one
+            let Some((_, entity)) = bullets
+                .iter()
+                .filter(|(_, _, active, ..)| !active.0)
+                .map(|(entity, rollback, ..)| (rollback.id(), entity))
+                .min()
+            else {
+                break;
+            };
+            let Ok((
+                _,
+                _,
+                mut active,
+                mut position,
+                mut dir,
+                mut radius,
+                mut lifetime,
+                mut bounces,
+                mut damage,
+                mut speed,
+                mut shooter,
+            )) = bullets.get_mut(entity)
+            else {
+                continue;
+            };
+            active.0 = true;
+            position.0 = player_transform.0
+                + (bullet_dir * (bullet_radius + player_radius.0)) / DIRECTION_SCALE;
+            dir.0 = bullet_dir;
+            radius.0 = bullet_radius;
+            lifetime.0 = stats.lifetime_frames;
+            bounces.0 = 0;
+            damage.0 = stats.damage;
+            speed.0 = stats.bullet_speed_percent;
+            shooter.0 = player.handle;
+        }
+        ammo.trigger_ready = false;
+        ammo.clip -= 1;
+        cooldown.0 = if rapid_fire.0 > 0 {
+            stats.fire_interval_frames.min(RAPID_FIRE_INTERVAL_FRAMES)
+        } else {
+            stats.fire_interval_frames
+        };
+        sounds.push(&frame, Sound::Fire, player.handle);
+        events.push(
+            &frame,
+            GameplayEvent::Shot {
+                shooter: player.handle,
+            },
+        );
+        let muzzle = player_transform.0 + (aim * player_radius.0) / DIRECTION_SCALE;
+        vfx.push(&frame, Effect::MuzzleFlash, player.handle, muzzle, aim);
+        // Recoil, picked up by move_players from the next frame on
+        velocity.0 -= aim * stats.recoil_si / DIRECTION_SCALE;
+        slowdown.0 = stats.slowdown_frames;
+    }
+}
+
+pub fn move_bullet(
+    settings: Res<LobbySettings>,
+    mut query: Query<
+        (
+            &Active,
+            &mut Position,
+            &mut MoveDir,
+            &mut Bounces,
+            &mut SweepStart,
+            &BulletSpeed,
+            &Shooter,
+        ),
+        With<Bullet>,
+    >,
+    frame: Res<SimFrame>,
+    mut vfx: ResMut<VfxQueue>,
+) {
+    for (active, mut position, mut dir, mut bounces, mut sweep_start, speed, shooter) in
+        query.iter_mut()
+    {
+        if !active.0 {
+            continue;
+        }
+        vfx.push(&frame, Effect::BulletTrail, shooter.0, position.0, dir.0);
+        let step = bullet_step(dir.0, *speed, &settings);
+        let start = position.0;
+        position.0 += step;
+        sweep_start.0 = start;
+        if settings.wrap_around {
+            position.0 = settings.wrap_position(position.0);
+            // Swept up to the wrapped position, across the edge it came in by
+            sweep_start.0 = position.0 - step;
+        } else if settings.ricochet && bounces.0 < MAX_BOUNCES {
+            let half = settings.half_map_size_si();
+            let crossing = edge_crossing(start, position.0, half);
+            let mut bounced = false;
+            for axis in 0..2 {
+                let overshoot = position.0[axis].abs() - half;
+                if overshoot > 0 {
+                    // Mirrored back in, as if it had bounced right at the edge
+                    position.0[axis] -= position.0[axis].signum() * overshoot * 2;
+                    dir.0[axis] = -dir.0[axis];
+                    bounced = true;
+                }
+            }
+            if bounced {
+                bounces.0 += 1;
+                // The rest of the move is swept from where it bounced
+                sweep_start.0 = crossing;
+            }
+        }
+        debug_assert_headroom(position.0, "bullet");
+    }
+}
+
+pub fn bullet_step(dir: IVec2, speed: BulletSpeed, settings: &LobbySettings) -> IVec2 {
+    (dir * (settings.bullet_speed_si() * speed.0 / 100)) / DIRECTION_SCALE
+}
+
+/// Where the move from `from` to `to` first crosses the edge of a map that
+/// reaches `half` from the middle on each axis
+fn edge_crossing(from: IVec2, to: IVec2, half: i32) -> IVec2 {
+    let delta = to - from;
+    // Share of the move made before each axis that ends up outside crosses,
+    // as a fraction
+    let (num, den) = (0..2)
+        .filter(|&axis| to[axis].abs() > half && delta[axis] != 0)
+        .map(|axis| {
+            let num = (to[axis].signum() * half - from[axis]) as i64;
+            let den = delta[axis] as i64;
+            (num * den.signum(), den.abs())
+        })
+        .min_by(|(a_num, a_den), (b_num, b_den)| (a_num * b_den).cmp(&(b_num * a_den)))
+        .unwrap_or((0, 1));
+    let num = num.clamp(0, den);
+    from + IVec2::new(
+        (delta.x as i64 * num / den) as i32,
+        (delta.y as i64 * num / den) as i32,
+    )
+}
+
+/// Returns bullets to the pool once they run out of time, leave the map or
+/// hit a wall
+pub fn expire_bullets(
+    settings: Res<LobbySettings>,
+    map: Res<CurrentMap>,
+    mut query: Query<(&mut Active, &mut Lifetime, &Position, &SweepStart, &Radius), With<Bullet>>,
+) {
+    for (mut active, mut lifetime, position, sweep_start, radius) in query.iter_mut() {
+        if !active.0 {
+            continue;
+        }
+        let limit = settings.half_map_size_si() + radius.0;
+        lifetime.0 = lifetime.0.saturating_sub(1);
+        let out_of_bounds =
+            !settings.wrap_around && (position.0.x.abs() > limit || position.0.y.abs() > limit);
+        // Swept, so fast bullets can't skip over thin walls between frames
+        let hit_wall = map.sweep(sweep_start.0, position.0, radius.0).is_some();
+        if lifetime.0 == 0 || out_of_bounds || hit_wall {
+            active.0 = false;
+        }
+    }
+}
+
+/// Counts down the fire cooldown and reloads, and starts a reload on the
+/// reload input or once the clip runs dry
+fn reload_weapons(
+    inputs: Res<PlayerInputs<GgrsConfig>>,
+    mut query: Query<(
+        &mut Ammo,
+        &mut WeaponCooldown,
+        &Player,
+        &Health,
+        &Weapon,
+        &RapidFire,
+    )>,
+) {
+    for (mut ammo, mut cooldown, player, health, weapon, rapid_fire) in query.iter_mut() {
+        let (input, _) = inputs[player.handle];
+        cooldown.0 = cooldown.0.saturating_sub(1);
+        let automatic = weapon.stats().automatic || rapid_fire.0 > 0;
+        if !fire(input) || automatic {
+            ammo.trigger_ready = true;
+        }
+        if ammo.is_reloading() {
+            ammo.tick_reload(*weapon);
+        } else if health.0 > 0 && (ammo.clip == 0 || reload(input)) {
+            ammo.start_reload(*weapon);
+        }
+    }
+}
+
+pub fn apply_damage(
+    mut player_query: Query<
+        (
+            Entity,
+            &Player,
+            &Position,
+            &Radius,
+            &mut Health,
+            &mut Invulnerable,
+            &mut LastHit,
+            &mut Velocity,
+        ),
+        Without<Bullet>,
+    >,
+    mut bullet_query: Query<
+        (
+            &Rollback,
+            &mut Active,
+            &Position,
+            &Radius,
+            &Damage,
+            &Shooter,
+            &MoveDir,
+            &SweepStart,
+        ),
+        With<Bullet>,
+    >,
+    settings: Res<LobbySettings>,
+    map: Res<CurrentMap>,
+    grid: Res<SpatialGrid>,
+    frame: Res<SimFrame>,
+    mut sounds: ResMut<SoundQueue>,
+    mut events: ResMut<GameplayEvents>,
+    mut vfx: ResMut<VfxQueue>,
+) {
+    // Players are kept in handle order so that two players hit at the same
+    // moment resolve identically on every peer
+    let mut players = player_query.iter_mut().collect::<Vec<_>>();
+    players.sort_by_key(|(_, player, ..)| player.handle);
+    let mut targets = Vec::new();
+    let mut indices = HashMap::default();
+    for (entity, player, position, radius, health, mut invulnerable, last_hit, velocity) in players
+    {
+        if invulnerable.0 > 0 {
+            invulnerable.0 -= 1;
+        } else if health.0 > 0 {
+            indices.insert(entity, targets.len());
+            targets.push((player, position, radius, health, last_hit, velocity));
+        }
+    }
+    let knockback_si = HIT_KNOCKBACK_SI * settings.knockback_percent() / 100;
+
+    let mut bullets = bullet_query
+        .iter_mut()
+        .filter(|(_, active, ..)| active.0)
+        .collect::<Vec<_>>();
+    // Query order may differ between peers, rollback ids don't. Two bullets
+    // reaching the same player resolve in the same order everywhere.
+    bullets.sort_by_key(|(rollback, ..)| rollback.id());
+    for (_, mut active, bullet_position, bullet_radius, damage, shooter, dir, sweep_start) in
+        bullets
+    {
+        // Swept over the bullet's whole move this frame, so fast bullets
+        // can't tunnel through players, and players behind a wall are safe
+        let from = sweep_start.0;
+        let wall_hit = map.sweep(from, bullet_position.0, bullet_radius.0);
+        let first_hit = grid
+            .query_sweep(from, bullet_position.0, bullet_radius.0)
+            .into_iter()
+            .filter_map(|entity| indices.get(&entity).map(|&index| (index, &targets[index])))
+            .filter(|(_, (player, _, _, health, ..))| {
+                health.0 > 0 && (settings.friendly_fire() || shooter.0 != player.handle)
+            })
+            .filter_map(|(index, (_, position, radius, ..))| {
+                sweep_circle_circle(
+                    from,
+                    bullet_position.0,
+                    settings.nearest_image(position.0, bullet_position.0),
+                    radius.0 + bullet_radius.0,
+                )
+                .map(|toi| (toi, index))
+            })
+            .filter(|(toi, _)| wall_hit.map_or(true, |wall_toi| *toi <= wall_toi))
+            .min();
+        let Some((_, index)) = first_hit else {
+            continue;
+        };
+        let (player, position, _, health, last_hit, velocity) = &mut targets[index];
+        health.0 = (health.0 - damage.0).max(0);
+        // Along the bullet's path, picked up by move_players from the next
+        // frame on
+        velocity.0 += dir.0 * knockback_si / DIRECTION_SCALE;
+        vfx.push(
+            &frame,
+            Effect::Hit(damage.0),
+            player.handle,
+            bullet_position.0,
+            dir.0,
+        );
+        events.push(
+            &frame,
+            GameplayEvent::Hit {
+                shooter: shooter.0,
+                victim: player.handle,
+                damage: damage.0,
+            },
+        );
+        **last_hit = LastHit {
+            shooter: shooter.0,
+            frame: frame.0,
+        };
+        active.0 = false;
+        let sound = if health.0 == 0 {
+            events.push(
+                &frame,
+                GameplayEvent::Kill {
+                    shooter: shooter.0,
+                    victim: player.handle,
+                },
+            );
+            vfx.push(
+                &frame,
+                Effect::DeathBurst,
+                player.handle,
+                position.0,
+                IVec2::ZERO,
+            );
+            Sound::Death
+        } else {
+            Sound::Hit
+        };
+        sounds.push(&frame, sound, player.handle);
+    }
+}
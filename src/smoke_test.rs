@@ -0,0 +1,140 @@
+use crate::{
+    components::{Health, IsLocal, IsReady, MatchBoxPeerId, Player, Position},
+    onboarding::NeedsOnboarding,
+    rng::{SimFrame, SimRng},
+    room::Room,
+    simulation::SimulationTimingSet,
+    GameState, MAX_PREDICTION_FRAMES,
+};
+use bevy::{app::AppExit, prelude::*};
+use bevy_ggrs::GGRSSchedule;
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+};
+
+/// Plays a short scripted game for `scripts/smoke_test.sh`, which runs two
+/// native peers against a local matchbox server. Each peer joins the room
+/// named by `WEB_GHOST_SMOKE_TEST`, readies up once the other is there, holds
+/// a fixed pattern of keys, and prints a hash of the simulation state at
+/// [`CHECKSUM_FRAME`] before quitting. Peers that stayed in sync print the
+/// same hash, so this covers matchmaking, the lobby handshake and the
+/// rollback session together.
+pub struct SmokeTestPlugin {
+    room: String,
+}
+
+impl SmokeTestPlugin {
+    const ROOM_VAR: &'static str = "WEB_GHOST_SMOKE_TEST";
+
+    pub fn from_env() -> Option<Self> {
+        let room = std::env::var(Self::ROOM_VAR).ok()?;
+        Some(Self { room })
+    }
+}
+
+impl Plugin for SmokeTestPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(SmokeTest {
+            room: Room(self.room.clone()),
+            checksum: None,
+        })
+        .add_system(join_room.in_set(OnUpdate(GameState::RoomSelect)))
+        .add_system(ready_up.in_set(OnUpdate(GameState::Matchmaking)))
+        .add_system(
+            press_keys
+                .in_base_set(CoreSet::First)
+                .run_if(in_state(GameState::InGame)),
+        )
+        .add_system(
+            record_checksum
+                .in_schedule(GGRSSchedule)
+                .in_base_set(SimulationTimingSet::End),
+        )
+        .add_system(report_checksum.in_set(OnUpdate(GameState::InGame)));
+    }
+}
+
+/// Peers taking part, so nobody starts before the other one is in the room
+const PEERS: usize = 2;
+/// Frame the peers compare their state at
+const CHECKSUM_FRAME: u32 = 300;
+/// Frames each key of the input pattern is held for
+const FRAMES_PER_KEY: u32 = 20;
+const KEY_PATTERN: [KeyCode; 4] = [KeyCode::D, KeyCode::W, KeyCode::A, KeyCode::S];
+
+#[derive(Resource)]
+struct SmokeTest {
+    room: Room,
+    /// State hash of [`CHECKSUM_FRAME`], from its latest simulation
+    checksum: Option<u64>,
+}
+
+fn join_room(
+    mut commands: Commands,
+    test: Res<SmokeTest>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    commands.remove_resource::<NeedsOnboarding>();
+    commands.insert_resource(test.room.clone());
+    next_state.set(GameState::Matchmaking);
+}
+
+fn ready_up(peers: Query<&MatchBoxPeerId>, mut local: Query<&mut IsReady, With<IsLocal>>) {
+    if peers.iter().count() < PEERS {
+        return;
+    }
+    for mut ready in local.iter_mut() {
+        if !ready.0 {
+            info!("Smoke test: everyone's here, readying up");
+            ready.0 = true;
+        }
+    }
+}
+
+/// Walks in a square and fires every other stretch
+fn press_keys(mut keys: ResMut<Input<KeyCode>>, mut frame: Local<u32>) {
+    let step = *frame / FRAMES_PER_KEY;
+    *frame += 1;
+    keys.release_all();
+    keys.press(KEY_PATTERN[step as usize % KEY_PATTERN.len()]);
+    if step % 2 == 0 {
+        keys.press(KeyCode::Space);
+    }
+}
+
+/// Rollbacks simulate the frame again, so the last hash recorded is the one
+/// of the confirmed frame
+fn record_checksum(
+    frame: Res<SimFrame>,
+    rng: Res<SimRng>,
+    players: Query<(&Player, &Position, &Health)>,
+    mut test: ResMut<SmokeTest>,
+) {
+    if frame.0 != CHECKSUM_FRAME {
+        return;
+    }
+    let mut players = players
+        .iter()
+        .map(|(player, position, health)| (player.handle, position.0.to_array(), health.0))
+        .collect::<Vec<_>>();
+    players.sort();
+    let mut hasher = DefaultHasher::new();
+    players.hash(&mut hasher);
+    rng.state().hash(&mut hasher);
+    test.checksum = Some(hasher.finish());
+}
+
+fn report_checksum(frame: Res<SimFrame>, test: Res<SmokeTest>, mut exit: EventWriter<AppExit>) {
+    // Past the prediction window, the checksum frame can't be rolled back
+    if frame.0 <= CHECKSUM_FRAME + MAX_PREDICTION_FRAMES {
+        return;
+    }
+    let Some(checksum) = test.checksum else {
+        error!("Smoke test: frame {CHECKSUM_FRAME} was never simulated");
+        std::process::exit(1);
+    };
+    // Read by the script, so printed rather than logged
+    println!("SMOKE_TEST_CHECKSUM {CHECKSUM_FRAME} {checksum:016x}");
+    exit.send(AppExit);
+}
@@ -0,0 +1,235 @@
+use crate::{rng::SimFrame, GameState, GgrsConfig};
+use bevy::{prelude::*, utils::HashMap};
+use bevy_egui::{
+    egui::{self, Grid, ScrollArea, Slider},
+    EguiContexts,
+};
+use bevy_ggrs::GGRSStage;
+use ron::Value;
+
+/// Serializes the rollback snapshot every few simulation frames and lists
+/// what changed since the last one, to track down state that drifts apart
+/// between peers. Changes are logged too, so logs from two peers can be
+/// compared. Only added in debug builds; toggled with F7.
+pub struct SnapshotDiffPlugin;
+
+impl Plugin for SnapshotDiffPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SnapshotDiff>()
+            .add_system(toggle_snapshot_diff)
+            .add_system(
+                diff_snapshots
+                    .after(toggle_snapshot_diff)
+                    .in_set(OnUpdate(GameState::InGame)),
+            )
+            .add_system(snapshot_diff_ui.after(diff_snapshots))
+            .add_system(reset_snapshot_diff.in_schedule(OnExit(GameState::InGame)));
+    }
+}
+
+/// Changes kept for display from the latest diff
+const MAX_LISTED_CHANGES: usize = 200;
+/// Longest value shown before it's cut off
+const MAX_VALUE_CHARS: usize = 60;
+
+#[derive(Resource)]
+pub struct SnapshotDiff {
+    pub open: bool,
+    /// Simulation frames between snapshots
+    interval_frames: u32,
+    previous: Option<(u32, Value)>,
+    /// Frames the latest diff was taken between
+    compared: Option<(u32, u32)>,
+    /// Changed values per component type, most first
+    summary: Vec<(String, usize)>,
+    changes: Vec<String>,
+}
+
+impl Default for SnapshotDiff {
+    fn default() -> Self {
+        Self {
+            open: false,
+            interval_frames: 60,
+            previous: None,
+            compared: None,
+            summary: Vec::new(),
+            changes: Vec::new(),
+        }
+    }
+}
+
+fn toggle_snapshot_diff(keys: Res<Input<KeyCode>>, mut diff: ResMut<SnapshotDiff>) {
+    if keys.just_pressed(KeyCode::F7) {
+        diff.open = !diff.open;
+    }
+}
+
+fn reset_snapshot_diff(mut diff: ResMut<SnapshotDiff>) {
+    diff.previous = None;
+}
+
+fn diff_snapshots(world: &mut World) {
+    let frame = world.resource::<SimFrame>().0;
+    let diff = world.resource::<SnapshotDiff>();
+    if !diff.open {
+        return;
+    }
+    // Frames go backwards when a new session starts
+    let due = diff.previous.as_ref().map_or(true, |(previous, _)| {
+        frame < *previous || frame >= previous + diff.interval_frames
+    });
+    if !due {
+        return;
+    }
+    let Some(stage) = world.get_resource::<GGRSStage<GgrsConfig>>() else {
+        return;
+    };
+    let serialized = stage.get_serialized_snapshot(world);
+    let snapshot = match ron::from_str::<Value>(&serialized) {
+        Ok(snapshot) => snapshot,
+        Err(error) => {
+            warn!("Failed to parse snapshot at frame {frame}: {error}");
+            return;
+        }
+    };
+
+    let mut diff = world.resource_mut::<SnapshotDiff>();
+    let Some((previous_frame, previous)) = diff.previous.replace((frame, snapshot)) else {
+        return;
+    };
+    if frame < previous_frame {
+        return;
+    }
+    let mut changes = Vec::new();
+    let current = &diff.previous.as_ref().unwrap().1;
+    diff_values("", &previous, current, &mut changes);
+
+    let mut counts = HashMap::<String, usize>::default();
+    for (path, _) in changes.iter() {
+        *counts.entry(component_of(path)).or_default() += 1;
+    }
+    let mut summary = counts.into_iter().collect::<Vec<_>>();
+    summary.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+    info!(
+        "Snapshot diff {previous_frame}..{frame}: {} changes",
+        changes.len()
+    );
+    let changes = changes
+        .into_iter()
+        .map(|(path, change)| {
+            let line = format!("{path}: {change}");
+            info!("  {line}");
+            line
+        })
+        .take(MAX_LISTED_CHANGES)
+        .collect();
+    diff.compared = Some((previous_frame, frame));
+    diff.summary = summary;
+    diff.changes = changes;
+}
+
+/// Collects `(path, description)` for every leaf that differs between
+/// `before` and `after`, including entries only one of them has
+fn diff_values(path: &str, before: &Value, after: &Value, changes: &mut Vec<(String, String)>) {
+    match (before, after) {
+        (Value::Map(before), Value::Map(after)) => {
+            for (key, old) in before.iter() {
+                let path = child_path(path, key);
+                match lookup(after, key) {
+                    Some(new) => diff_values(&path, old, new, changes),
+                    None => changes.push((path, format!("removed {}", show(old)))),
+                }
+            }
+            for (key, new) in after.iter() {
+                if lookup(before, key).is_none() {
+                    changes.push((child_path(path, key), format!("added {}", show(new))));
+                }
+            }
+        }
+        (Value::Seq(before), Value::Seq(after)) => {
+            for (index, (old, new)) in before.iter().zip(after.iter()).enumerate() {
+                diff_values(&format!("{path}/{index}"), old, new, changes);
+            }
+            if before.len() != after.len() {
+                changes.push((
+                    format!("{path}/len"),
+                    format!("{} -> {}", before.len(), after.len()),
+                ));
+            }
+        }
+        (Value::Option(Some(before)), Value::Option(Some(after))) => {
+            diff_values(path, before, after, changes)
+        }
+        _ if before != after => changes.push((
+            path.to_string(),
+            format!("{} -> {}", show(before), show(after)),
+        )),
+        _ => {}
+    }
+}
+
+/// `ron::Map` can't look values up by key
+fn lookup<'a>(map: &'a ron::Map, key: &Value) -> Option<&'a Value> {
+    map.iter()
+        .find(|(candidate, _)| *candidate == key)
+        .map(|(_, value)| value)
+}
+
+fn child_path(path: &str, key: &Value) -> String {
+    match key {
+        Value::String(key) => format!("{path}/{key}"),
+        key => format!("{path}/{}", show(key)),
+    }
+}
+
+/// The short name of the first type in `path`, which for entity data is the
+/// component the change belongs to
+fn component_of(path: &str) -> String {
+    path.split('/')
+        .find(|segment| segment.contains("::"))
+        .map(|name| name.rsplit("::").next().unwrap_or(name))
+        .unwrap_or(path)
+        .to_string()
+}
+
+fn show(value: &Value) -> String {
+    let mut text = ron::to_string(value).unwrap_or_else(|_| format!("{value:?}"));
+    if text.chars().count() > MAX_VALUE_CHARS {
+        text = text.chars().take(MAX_VALUE_CHARS).collect::<String>() + "…";
+    }
+    text
+}
+
+fn snapshot_diff_ui(mut contexts: EguiContexts, mut diff: ResMut<SnapshotDiff>) {
+    let diff = &mut *diff;
+    egui::Window::new("Snapshot diff")
+        .open(&mut diff.open)
+        .default_width(420.)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.add(Slider::new(&mut diff.interval_frames, 1..=600).text("every (frames)"));
+            let Some((from, to)) = diff.compared else {
+                ui.label("Waiting for two snapshots…");
+                return;
+            };
+            ui.label(format!(
+                "Frames {from}..{to}: {} changes",
+                diff.summary.iter().map(|(_, count)| count).sum::<usize>()
+            ));
+            Grid::new("snapshot_diff_summary")
+                .striped(true)
+                .show(ui, |ui| {
+                    for (component, count) in diff.summary.iter() {
+                        ui.label(component);
+                        ui.label(count.to_string());
+                        ui.end_row();
+                    }
+                });
+            ui.separator();
+            ScrollArea::vertical().max_height(300.).show(ui, |ui| {
+                for change in diff.changes.iter() {
+                    ui.monospace(change);
+                }
+            });
+        });
+}
@@ -0,0 +1,251 @@
+use crate::{
+    components::{Active, Bullet, Position, Radius, SweepStart},
+    hazards::{move_hazards, touch_hazards},
+    lobby_settings::LobbySettings,
+    pickups::{collect_pickups, spawn_pickups},
+    portals::teleport_players,
+    simulation::{apply_damage, collide_players, move_bullet},
+    F2I,
+};
+use bevy::{prelude::*, utils::HashMap};
+use bevy_ggrs::{GGRSSchedule, Rollback};
+
+/// Keeps a [`SpatialGrid`] of everything that collides, so collision systems
+/// only test what's nearby instead of every pair.
+pub struct SpatialPlugin;
+
+impl Plugin for SpatialPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SpatialGrid>().add_systems(
+            (
+                index_bodies
+                    .after(collide_players)
+                    .after(teleport_players)
+                    .after(move_hazards)
+                    .after(spawn_pickups)
+                    .before(touch_hazards)
+                    .before(collect_pickups),
+                index_bullets
+                    .after(index_bodies)
+                    .after(move_bullet)
+                    .before(apply_damage),
+            )
+                .in_schedule(GGRSSchedule),
+        );
+    }
+}
+
+/// Side of a grid cell, about what a bullet moves in a frame and a few
+/// player widths, so most queries look at one to four cells
+const CELL_SI: i32 = 2 * F2I;
+
+/// Which cells of a fixed grid every player, pickup, hazard and active
+/// bullet covers this frame, keyed by integer cell coordinates. It's rebuilt
+/// from [`Position`] in each GGRS frame before anything reads it, so it holds
+/// nothing a rollback would have to restore and is never saved.
+///
+/// Players pushing each other apart is the one collision that doesn't use
+/// it: that happens while players are still moving, before the grid is built.
+#[derive(Resource, Default)]
+pub struct SpatialGrid {
+    half: i32,
+    cells_across: i32,
+    wrap_around: bool,
+    cells: HashMap<IVec2, Vec<(u32, Entity)>>,
+}
+
+impl SpatialGrid {
+    fn reset(&mut self, settings: &LobbySettings) {
+        self.half = settings.half_map_size_si();
+        self.cells_across = (2 * self.half / CELL_SI).max(1);
+        self.wrap_around = settings.wrap_around;
+        // Keeps the cells' buffers for the next frame
+        for entities in self.cells.values_mut() {
+            entities.clear();
+        }
+    }
+
+    /// Cell containing `position`. On wrapping maps the map is split into a
+    /// whole number of cells, so a position and its images share a cell.
+    fn cell_of(&self, position: IVec2) -> IVec2 {
+        let size = 2 * self.half.max(1) as i64;
+        let cell = |v: i32| ((v + self.half) as i64 * self.cells_across as i64).div_euclid(size);
+        IVec2::new(cell(position.x) as i32, cell(position.y) as i32)
+    }
+
+    /// Cells overlapping the box from `min` to `max`, each once
+    fn cells_between(&self, min: IVec2, max: IVec2) -> impl Iterator<Item = IVec2> {
+        let (min, mut max) = (self.cell_of(min), self.cell_of(max));
+        let (wrap_around, cells_across) = (self.wrap_around, self.cells_across);
+        if wrap_around {
+            // Past the width of the map, cells would come round again
+            max = max.min(min + cells_across - 1);
+        }
+        let wrap = move |cell: i32| {
+            if wrap_around {
+                cell.rem_euclid(cells_across)
+            } else {
+                cell
+            }
+        };
+        (min.y..=max.y)
+            .flat_map(move |y| (min.x..=max.x).map(move |x| IVec2::new(wrap(x), wrap(y))))
+    }
+
+    fn insert(&mut self, rollback: &Rollback, entity: Entity, min: IVec2, max: IVec2) {
+        for cell in self.cells_between(min, max).collect::<Vec<_>>() {
+            self.cells
+                .entry(cell)
+                .or_default()
+                .push((rollback.id(), entity));
+        }
+    }
+
+    /// Entities that may overlap the circle at `center`, which may be outside
+    /// a wrapping map. Everything that does is included, and some that turn
+    /// out not to, so callers still test each. Each entity is listed once, in
+    /// rollback id order, which is the same on every peer.
+    pub fn query_circle(&self, center: IVec2, radius: i32) -> Vec<Entity> {
+        let mut found = self
+            .cells_between(center - radius, center + radius)
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten()
+            .copied()
+            .collect::<Vec<_>>();
+        found.sort_unstable_by_key(|(id, _)| *id);
+        found.dedup_by_key(|(id, _)| *id);
+        found.into_iter().map(|(_, entity)| entity).collect()
+    }
+
+    /// Entities that may touch a circle of `radius` moving from `from` to
+    /// `to`, see [`Self::query_circle`]
+    pub fn query_sweep(&self, from: IVec2, to: IVec2, radius: i32) -> Vec<Entity> {
+        // Half the move's length is never more than its longer side
+        let reach = (to - from).abs().max_element();
+        self.query_circle((from + to) / 2, radius + reach)
+    }
+}
+
+/// Starts the frame's grid with players, pickups and hazards, once they've
+/// all moved
+pub fn index_bodies(
+    settings: Res<LobbySettings>,
+    mut grid: ResMut<SpatialGrid>,
+    bodies: Query<(Entity, &Rollback, &Position, &Radius), Without<Bullet>>,
+) {
+    grid.reset(&settings);
+    for (entity, rollback, position, radius) in bodies.iter() {
+        grid.insert(
+            rollback,
+            entity,
+            position.0 - radius.0,
+            position.0 + radius.0,
+        );
+    }
+}
+
+/// Adds active bullets over the whole of their move this frame, so sweeps
+/// against them find them wherever along it they're hit
+pub fn index_bullets(
+    mut grid: ResMut<SpatialGrid>,
+    bullets: Query<(Entity, &Rollback, &Active, &Position, &Radius, &SweepStart), With<Bullet>>,
+) {
+    for (entity, rollback, active, position, radius, sweep_start) in bullets.iter() {
+        if !active.0 {
+            continue;
+        }
+        let from = sweep_start.0;
+        let min = from.min(position.0) - radius.0;
+        let max = from.max(position.0) + radius.0;
+        grid.insert(rollback, entity, min, max);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn empty_grid(wrap_around: bool) -> (SpatialGrid, i32) {
+        let settings = LobbySettings {
+            wrap_around,
+            ..default()
+        };
+        let mut grid = SpatialGrid::default();
+        grid.reset(&settings);
+        (grid, settings.half_map_size_si())
+    }
+
+    /// Adds a body like [`index_bodies`] does. Entities are numbered against
+    /// their rollback ids, so results sorted by entity would come out reversed.
+    fn add(grid: &mut SpatialGrid, id: u32, position: IVec2, radius: i32) -> Entity {
+        let entity = Entity::from_raw(10 - id);
+        grid.insert(
+            &Rollback::new(id),
+            entity,
+            position - radius,
+            position + radius,
+        );
+        entity
+    }
+
+    #[test]
+    fn circles_find_bodies_across_the_seam() {
+        for wrap_around in [false, true] {
+            let (mut grid, half) = empty_grid(wrap_around);
+            let body = add(&mut grid, 1, IVec2::new(half - F2I / 4, 0), F2I / 4);
+            // Left of the map, where the right edge comes round on wrapping maps
+            let found = grid.query_circle(IVec2::new(-half - F2I / 2, 0), F2I / 10);
+            assert_eq!(found.contains(&body), wrap_around, "{wrap_around}");
+            let found = grid.query_circle(IVec2::new(-half + F2I / 10, 0), F2I / 10);
+            assert_eq!(found.contains(&body), wrap_around, "{wrap_around}");
+        }
+    }
+
+    #[test]
+    fn sweeps_find_bodies_across_the_seam() {
+        let (mut grid, half) = empty_grid(true);
+        let body = add(&mut grid, 1, IVec2::new(-half + F2I / 2, 0), F2I / 4);
+        let (from, to) = (IVec2::new(half - F2I, 0), IVec2::new(half + F2I, 0));
+        assert_eq!(grid.query_sweep(from, to, F2I / 10), [body]);
+    }
+
+    #[test]
+    fn bodies_at_either_half_are_found_from_both() {
+        let (mut grid, half) = empty_grid(true);
+        let right = add(&mut grid, 1, IVec2::new(half, 0), 1);
+        let left = add(&mut grid, 2, IVec2::new(-half, 0), 1);
+        for x in [half, -half] {
+            assert_eq!(grid.query_circle(IVec2::new(x, 0), 1), [right, left], "{x}");
+        }
+
+        let (mut grid, half) = empty_grid(false);
+        let right = add(&mut grid, 1, IVec2::new(half, 0), 1);
+        let left = add(&mut grid, 2, IVec2::new(-half, 0), 1);
+        assert_eq!(grid.query_circle(IVec2::new(half, 0), 1), [right]);
+        assert_eq!(grid.query_circle(IVec2::new(-half, 0), 1), [left]);
+    }
+
+    #[test]
+    fn each_body_is_listed_once() {
+        for wrap_around in [false, true] {
+            let (mut grid, half) = empty_grid(wrap_around);
+            // Covers several cells, and both sides of the seam when wrapping
+            let large = add(&mut grid, 1, IVec2::new(half, 0), 3 * F2I);
+            let small = add(&mut grid, 2, IVec2::ZERO, 0);
+            let found = grid.query_circle(IVec2::ZERO, 2 * half);
+            assert_eq!(found, [large, small], "{wrap_around}");
+            let found = grid.query_sweep(IVec2::new(-half, 0), IVec2::new(half, 0), F2I);
+            assert_eq!(found, [large, small], "{wrap_around}");
+        }
+    }
+
+    #[test]
+    fn results_come_in_rollback_id_order() {
+        let (mut grid, _) = empty_grid(false);
+        let mut bodies = [3, 1, 2].map(|id| add(&mut grid, id, IVec2::ZERO, F2I));
+        bodies.sort_by_key(|entity| 10 - entity.index());
+        assert_eq!(grid.query_circle(IVec2::ZERO, F2I), bodies);
+        let found = grid.query_sweep(IVec2::new(-F2I, 0), IVec2::new(F2I, 0), 0);
+        assert_eq!(found, bodies);
+    }
+}
@@ -0,0 +1,157 @@
+use crate::{maps::CurrentMap, player::PLAYER_RADIUS_SI, IVec2Ext, F2I};
+use bevy::prelude::*;
+
+/// Closest a player may spawn to a living enemy or bullet
+const SAFE_SPAWN_DISTANCE_SI: i32 = 2 * F2I;
+/// Spacing of the fallback spawn points tried when the usual one is unsafe
+const SPAWN_GRID_STEP_SI: i32 = 4 * F2I;
+
+/// Where a respawning player ends up, and whether the spot is clear of threats
+pub struct Spawn {
+    pub position: IVec2,
+    pub direction: IVec2,
+    pub safe: bool,
+}
+
+/// Picks a spawn for `handle` at least [`SAFE_SPAWN_DISTANCE_SI`] away from
+/// the positions of every enemy and active bullet on `map`, which is
+/// `map_size_si` wide. The handle's usual spawn is preferred, then points on
+/// a grid over the map clear of walls, starting at an offset per handle so
+/// crowded players spread out. Only depends on its inputs, so every peer
+/// picks the same spot.
+pub fn pick_spawn(
+    handle: usize,
+    enemies: &[IVec2],
+    bullets: &[IVec2],
+    map: &CurrentMap,
+    map_size_si: i32,
+) -> Spawn {
+    let (default_position, direction) = map.spawn_position(handle, map_size_si);
+    let is_safe = |position: IVec2| {
+        enemies.iter().chain(bullets).all(|threat| {
+            (*threat - position).norm_sq_wide()
+                >= SAFE_SPAWN_DISTANCE_SI as i64 * SAFE_SPAWN_DISTANCE_SI as i64
+        })
+    };
+    if is_safe(default_position) {
+        return Spawn {
+            position: default_position,
+            direction,
+            safe: true,
+        };
+    }
+
+    let limit = map_size_si / 2 - PLAYER_RADIUS_SI;
+    let steps = (2 * limit / SPAWN_GRID_STEP_SI) + 1;
+    let candidates = (0..steps)
+        .flat_map(|y| (0..steps).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            IVec2::new(
+                -limit + x * SPAWN_GRID_STEP_SI,
+                -limit + y * SPAWN_GRID_STEP_SI,
+            )
+        })
+        .filter(|position| !map.blocks(*position, PLAYER_RADIUS_SI))
+        .collect::<Vec<_>>();
+    if candidates.is_empty() {
+        return Spawn {
+            position: default_position,
+            direction,
+            safe: false,
+        };
+    }
+    let start = handle * 7 % candidates.len();
+    candidates
+        .iter()
+        .cycle()
+        .skip(start)
+        .take(candidates.len())
+        .find(|position| is_safe(**position))
+        .map(|position| Spawn {
+            position: *position,
+            direction,
+            safe: true,
+        })
+        .unwrap_or(Spawn {
+            position: default_position,
+            direction,
+            safe: false,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MAP_SIZE_SI;
+
+    fn is_clear(spawn: &Spawn, threats: &[IVec2]) -> bool {
+        threats.iter().all(|threat| {
+            (*threat - spawn.position).norm_sq_wide()
+                >= SAFE_SPAWN_DISTANCE_SI as i64 * SAFE_SPAWN_DISTANCE_SI as i64
+        })
+    }
+
+    #[test]
+    fn usual_spawn_when_nothing_is_near() {
+        let map = CurrentMap::default();
+        let far = [IVec2::new(0, 10 * F2I)];
+        let spawn = pick_spawn(2, &far, &far, &map, MAP_SIZE_SI);
+        assert!(spawn.safe);
+        assert_eq!(spawn.position, map.spawn_position(2, MAP_SIZE_SI).0);
+    }
+
+    #[test]
+    fn spawns_avoid_enemies_and_bullets() {
+        let map = CurrentMap::default();
+        let (usual, _) = map.spawn_position(0, MAP_SIZE_SI);
+        let near = [usual + IVec2::new(F2I / 2, 0)];
+        for (enemies, bullets) in [(&near[..], &[][..]), (&[][..], &near[..])] {
+            let spawn = pick_spawn(0, enemies, bullets, &map, MAP_SIZE_SI);
+            assert!(spawn.safe);
+            assert_ne!(spawn.position, usual);
+            assert!(is_clear(&spawn, &near));
+        }
+    }
+
+    #[test]
+    fn crowded_players_spread_out() {
+        let map = CurrentMap::default();
+        // Bullets all over the row players usually line up in
+        let bullets = (-20..=20)
+            .map(|x| IVec2::new(x * F2I, 0))
+            .collect::<Vec<_>>();
+        let mut placed = Vec::new();
+        for handle in 0..8 {
+            let spawn = pick_spawn(handle, &placed, &bullets, &map, MAP_SIZE_SI);
+            assert!(spawn.safe, "player {handle} found no safe spot");
+            assert!(is_clear(&spawn, &placed) && is_clear(&spawn, &bullets));
+            placed.push(spawn.position);
+        }
+    }
+
+    #[test]
+    fn usual_spawn_when_nowhere_is_safe() {
+        let map = CurrentMap::default();
+        let map_size_si = 5 * F2I;
+        // Covers the usual spawn and the four grid points of a map this small
+        let corner = map_size_si / 2 - PLAYER_RADIUS_SI;
+        let bullets = [
+            IVec2::new(-corner, -corner),
+            IVec2::new(corner, -corner),
+            IVec2::new(-corner, corner),
+            IVec2::new(corner, corner),
+        ];
+        let (usual, _) = map.spawn_position(0, map_size_si);
+        let spawn = pick_spawn(0, &[usual], &bullets, &map, map_size_si);
+        assert!(!spawn.safe);
+        assert_eq!(spawn.position, usual);
+    }
+
+    #[test]
+    fn same_threats_give_the_same_spawn() {
+        let map = CurrentMap::default();
+        let enemies = [map.spawn_position(1, MAP_SIZE_SI).0];
+        let [a, b] = [(); 2].map(|_| pick_spawn(1, &enemies, &[], &map, MAP_SIZE_SI).position);
+        assert_eq!(a, b);
+    }
+}
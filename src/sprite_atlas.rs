@@ -0,0 +1,136 @@
+use crate::{game::ImageAssets, layers::DrawLayer, GameState};
+use bevy::{
+    prelude::*,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+};
+
+/// Packs the textures of sprites spawned mid-game into one atlas when assets
+/// finish loading, and draws each of them once, invisibly, right away. WASM
+/// builds otherwise hitch the first time a bullet or pickup appears, while
+/// the texture is uploaded and the sprite pipeline is specialized for it.
+pub struct SpriteAtlasPlugin;
+
+impl Plugin for SpriteAtlasPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(build_sprite_atlas.in_schedule(OnExit(GameState::AssetLoading)))
+            .add_system(finish_warm_up);
+    }
+}
+
+/// Frames the warm-up sprites are drawn for
+const WARM_UP_FRAMES: u32 = 3;
+/// Width of the plain white texture tinted for solid-colored sprites
+const SOLID_SIZE: u32 = 8;
+
+/// Sprites drawn from the [`SpriteAtlas`]
+#[derive(Clone, Copy, Debug)]
+pub enum GameSprite {
+    Bullet,
+    /// Plain white, for sprites that are just a tinted square
+    Solid,
+}
+
+impl GameSprite {
+    const ALL: [GameSprite; 2] = [GameSprite::Bullet, GameSprite::Solid];
+}
+
+#[derive(Resource)]
+pub struct SpriteAtlas {
+    atlas: Handle<TextureAtlas>,
+    /// Atlas index of each [`GameSprite`], in declaration order
+    indices: [usize; 2],
+}
+
+impl SpriteAtlas {
+    /// Bundle for `sprite` tinted `color` and scaled to `size` world units,
+    /// to spawn gameplay sprites with instead of separate textures
+    pub fn bundle(
+        &self,
+        sprite: GameSprite,
+        color: Color,
+        size: Vec2,
+        transform: Transform,
+    ) -> SpriteSheetBundle {
+        SpriteSheetBundle {
+            sprite: TextureAtlasSprite {
+                index: self.indices[sprite as usize],
+                color,
+                custom_size: Some(size),
+                ..default()
+            },
+            texture_atlas: self.atlas.clone(),
+            transform,
+            ..default()
+        }
+    }
+}
+
+#[derive(Component)]
+struct WarmUp(u32);
+
+fn build_sprite_atlas(
+    mut commands: Commands,
+    images: Res<ImageAssets>,
+    mut textures: ResMut<Assets<Image>>,
+    mut atlases: ResMut<Assets<TextureAtlas>>,
+) {
+    let solid = textures.add(Image::new_fill(
+        Extent3d {
+            width: SOLID_SIZE,
+            height: SOLID_SIZE,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &[255; 4],
+        TextureFormat::Rgba8UnormSrgb,
+    ));
+    let handles = GameSprite::ALL.map(|sprite| match sprite {
+        GameSprite::Bullet => images.bullet.clone(),
+        GameSprite::Solid => solid.clone(),
+    });
+    let mut builder = TextureAtlasBuilder::default();
+    for handle in handles.iter() {
+        let image = textures.get(handle).expect("gameplay textures are loaded");
+        builder.add_texture(handle.clone(), image);
+    }
+    let atlas = builder
+        .finish(&mut textures)
+        .expect("gameplay textures fit in one atlas");
+    let indices = handles.map(|handle| atlas.get_texture_index(&handle).unwrap());
+    let atlas = SpriteAtlas {
+        atlas: atlases.add(atlas),
+        indices,
+    };
+
+    // Transparent and behind everything, but still drawn
+    let hidden = Color::rgba(1., 1., 1., 0.);
+    let transform = Transform::from_translation(Vec3::new(0., 0., DrawLayer::Background.z()));
+    for sprite in GameSprite::ALL {
+        commands.spawn((
+            WarmUp(WARM_UP_FRAMES),
+            atlas.bundle(sprite, hidden, Vec2::ONE, transform),
+        ));
+    }
+    commands.spawn((
+        WarmUp(WARM_UP_FRAMES),
+        SpriteSheetBundle {
+            sprite: TextureAtlasSprite {
+                color: hidden,
+                ..default()
+            },
+            texture_atlas: images.player_sheet.clone(),
+            transform,
+            ..default()
+        },
+    ));
+    commands.insert_resource(atlas);
+}
+
+fn finish_warm_up(mut commands: Commands, mut sprites: Query<(Entity, &mut WarmUp)>) {
+    for (entity, mut warm_up) in sprites.iter_mut() {
+        warm_up.0 = warm_up.0.saturating_sub(1);
+        if warm_up.0 == 0 {
+            commands.entity(entity).despawn();
+        }
+    }
+}
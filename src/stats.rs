@@ -0,0 +1,104 @@
+use crate::{
+    achievements::{confirm_gameplay_events, ConfirmedGameplayEvent, GameplayEvent},
+    components::{IsLocal, PlayerStats},
+    lobby::{PracticeMode, Spectating},
+    GameState, LocalPlayerHandle,
+};
+use bevy::prelude::*;
+use bevy_egui::egui::{CollapsingHeader, Grid, Ui};
+
+/// Counts the local player's kills, deaths, games and wins across sessions.
+/// The totals live in a [`PlayerStats`] component on the local player, which
+/// the lobby loads from and saves to cookies. Practice games don't count.
+pub struct StatsPlugin;
+
+impl Plugin for StatsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RoundTally>()
+            .add_system(
+                count_game_played
+                    .run_if(not(resource_exists::<PracticeMode>()))
+                    .run_if(not(resource_exists::<Spectating>()))
+                    .in_schedule(OnEnter(GameState::InGame)),
+            )
+            .add_system(reset_round_tally.in_schedule(OnEnter(GameState::InGame)))
+            .add_system(
+                record_round_stats
+                    .after(confirm_gameplay_events)
+                    .run_if(not(resource_exists::<PracticeMode>()))
+                    .in_set(OnUpdate(GameState::InGame)),
+            );
+    }
+}
+
+fn count_game_played(mut stats: Query<&mut PlayerStats, With<IsLocal>>) {
+    for mut stats in stats.iter_mut() {
+        stats.games_played += 1;
+    }
+}
+
+/// The local player's kills and deaths in the round being played
+#[derive(Resource, Default)]
+struct RoundTally {
+    kills: u32,
+    deaths: u32,
+}
+
+fn reset_round_tally(mut tally: ResMut<RoundTally>) {
+    *tally = RoundTally::default();
+}
+
+/// Adds the round to the local stats once it ends. Counts from
+/// [`ConfirmedGameplayEvent`]s, so only round ends that are still there after
+/// rollbacks count, and each of them once.
+fn record_round_stats(
+    mut events: EventReader<ConfirmedGameplayEvent>,
+    local_handle: Option<Res<LocalPlayerHandle>>,
+    mut stats: Query<&mut PlayerStats, With<IsLocal>>,
+    mut tally: ResMut<RoundTally>,
+) {
+    let (Some(local_handle), Ok(mut stats)) = (local_handle, stats.get_single_mut()) else {
+        events.clear();
+        return;
+    };
+    let local_handle = local_handle.0;
+    for confirmed in events.iter() {
+        match confirmed.event {
+            GameplayEvent::Kill { shooter, victim } => {
+                if victim == local_handle {
+                    tally.deaths += 1;
+                } else if shooter == local_handle {
+                    tally.kills += 1;
+                }
+            }
+            GameplayEvent::Death { victim } if victim == local_handle => tally.deaths += 1,
+            GameplayEvent::RoundEnded { winner, .. } => {
+                stats.kills += tally.kills;
+                stats.deaths += tally.deaths;
+                if winner == Some(local_handle) {
+                    stats.wins += 1;
+                }
+                *tally = RoundTally::default();
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The lobby's "Stats" section
+pub fn stats_ui(ui: &mut Ui, stats: &PlayerStats) {
+    CollapsingHeader::new("Stats").show(ui, |ui| {
+        Grid::new("player_stats").show(ui, |ui| {
+            for (label, value) in [
+                ("Games played", stats.games_played),
+                ("Rounds won", stats.wins),
+                ("Kills", stats.kills),
+                ("Deaths", stats.deaths),
+            ] {
+                ui.label(label);
+                ui.label(value.to_string());
+                ui.end_row();
+            }
+        });
+    });
+}
@@ -0,0 +1,133 @@
+use crate::fixed_timestep::FixedTimestep;
+use bevy::prelude::*;
+use bevy_egui::{
+    egui::{Align2, Area, Frame, RichText},
+    EguiContexts,
+};
+
+/// Tells the player about things going wrong that used to only end up in the
+/// console, or crash the game. Any system can send a [`StatusEvent`]; each is
+/// logged and shown as a banner in the bottom right corner. Info and warnings
+/// fade after a while, errors stay until dismissed.
+pub struct StatusPlugin;
+
+impl Plugin for StatusPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<StatusEvent>()
+            .init_resource::<StatusBanners>()
+            .add_system(collect_status_events)
+            .add_system(status_banners_ui.after(collect_status_events));
+    }
+}
+
+/// How long info and warnings are shown for
+const BANNER_SECS: f32 = 6.;
+/// Oldest banners are dropped beyond this many
+const MAX_BANNERS: usize = 5;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StatusLevel {
+    Info,
+    Warning,
+    Error,
+}
+
+/// Something the player should know about, e.g. a save that couldn't be
+/// stored or a session that couldn't start
+#[derive(Clone, Debug)]
+pub struct StatusEvent {
+    pub level: StatusLevel,
+    pub message: String,
+}
+
+impl StatusEvent {
+    pub fn info(message: impl Into<String>) -> Self {
+        Self {
+            level: StatusLevel::Info,
+            message: message.into(),
+        }
+    }
+
+    pub fn warning(message: impl Into<String>) -> Self {
+        Self {
+            level: StatusLevel::Warning,
+            message: message.into(),
+        }
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Self {
+            level: StatusLevel::Error,
+            message: message.into(),
+        }
+    }
+}
+
+struct Banner {
+    event: StatusEvent,
+    /// `None` for errors, which stay until dismissed
+    secs_left: Option<f32>,
+}
+
+#[derive(Resource, Default)]
+struct StatusBanners(Vec<Banner>);
+
+fn collect_status_events(mut events: EventReader<StatusEvent>, mut banners: ResMut<StatusBanners>) {
+    for event in events.iter() {
+        match event.level {
+            StatusLevel::Info => info!("{}", event.message),
+            StatusLevel::Warning => warn!("{}", event.message),
+            StatusLevel::Error => error!("{}", event.message),
+        }
+        // The same failure repeating only needs one banner
+        banners
+            .0
+            .retain(|banner| banner.event.message != event.message);
+        banners.0.push(Banner {
+            event: event.clone(),
+            secs_left: (event.level != StatusLevel::Error).then_some(BANNER_SECS),
+        });
+    }
+    let excess = banners.0.len().saturating_sub(MAX_BANNERS);
+    banners.0.drain(..excess);
+}
+
+fn status_banners_ui(
+    mut contexts: EguiContexts,
+    timestep: Res<FixedTimestep>,
+    mut banners: ResMut<StatusBanners>,
+) {
+    if banners.0.is_empty() {
+        return;
+    }
+    let delta = timestep.real_delta_seconds();
+    banners.0.retain_mut(|banner| {
+        banner.secs_left = banner.secs_left.map(|secs| secs - delta);
+        banner.secs_left.map_or(true, |secs| secs > 0.)
+    });
+    let mut dismissed = None;
+    Area::new("status_banners")
+        .anchor(Align2::RIGHT_BOTTOM, [-10., -10.])
+        .show(contexts.ctx_mut(), |ui| {
+            for (index, banner) in banners.0.iter().enumerate() {
+                Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.set_max_width(300.);
+                    ui.horizontal(|ui| {
+                        let (title, color) = match banner.event.level {
+                            StatusLevel::Info => ("Info", ui.visuals().text_color()),
+                            StatusLevel::Warning => ("Warning", ui.visuals().warn_fg_color),
+                            StatusLevel::Error => ("Error", ui.visuals().error_fg_color),
+                        };
+                        ui.label(RichText::new(title).strong().color(color));
+                        if banner.secs_left.is_none() && ui.small_button("Dismiss").clicked() {
+                            dismissed = Some(index);
+                        }
+                    });
+                    ui.label(&banner.event.message);
+                });
+            }
+        });
+    if let Some(index) = dismissed {
+        banners.0.remove(index);
+    }
+}
@@ -0,0 +1,271 @@
+use bevy::{prelude::*, utils::HashMap};
+use std::sync::{Arc, Mutex};
+
+/// A string key-value store the game keeps data in between runs. In the
+/// browser these are cookies and web storage; native builds (the `native`
+/// feature) keep the persistent areas in files instead, so the same code runs
+/// as a desktop game or as a bot peer for testing. Areas that turn out to be
+/// unavailable, like cookies in some private browsing modes, are replaced by
+/// a [`MemoryStore`] so the game still runs, just without remembering.
+pub trait KeyValueStore: Send + Sync {
+    fn get(&self, key: &str) -> Option<String>;
+    fn set(&self, key: &str, value: &str) -> Result<(), String>;
+    fn remove(&self, key: &str);
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StorageArea {
+    /// Small settings, shared by every tab
+    Cookies,
+    /// Larger data such as saves and history, shared by every tab
+    Local,
+    /// Data of this tab that should survive a refresh, but not a new tab
+    Session,
+}
+
+impl StorageArea {
+    const ALL: [StorageArea; 3] = [
+        StorageArea::Cookies,
+        StorageArea::Local,
+        StorageArea::Session,
+    ];
+}
+
+/// Keeps values for as long as the game runs
+#[derive(Default)]
+pub struct MemoryStore(Mutex<HashMap<String, String>>);
+
+impl KeyValueStore for MemoryStore {
+    fn get(&self, key: &str) -> Option<String> {
+        self.0.lock().unwrap().get(key).cloned()
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<(), String> {
+        self.0.lock().unwrap().insert(key.into(), value.into());
+        Ok(())
+    }
+
+    fn remove(&self, key: &str) {
+        self.0.lock().unwrap().remove(key);
+    }
+}
+
+/// The store of every [`StorageArea`], picked once at startup. Systems take
+/// it as a resource, so an app can be given other stores, like
+/// [`KeyValueStores::in_memory`] in tests. Code running while the app is
+/// built reads the same stores through [`KeyValueStores::get`].
+#[derive(Resource, Clone)]
+pub struct KeyValueStores {
+    stores: [Arc<dyn KeyValueStore>; 3],
+    /// Areas that fell back to memory
+    pub unavailable: Vec<StorageArea>,
+}
+
+impl KeyValueStores {
+    pub fn get() -> Self {
+        static STORES: Mutex<Option<KeyValueStores>> = Mutex::new(None);
+        STORES
+            .lock()
+            .unwrap()
+            .get_or_insert_with(Self::detect)
+            .clone()
+    }
+
+    fn detect() -> Self {
+        let mut unavailable = Vec::new();
+        let stores = StorageArea::ALL.map(|area| match platform::store(area) {
+            Some(store) if works(store.as_ref()) => store,
+            _ => {
+                warn!("{area:?} storage is unavailable, nothing stored there will be kept");
+                unavailable.push(area);
+                Arc::new(MemoryStore::default()) as Arc<dyn KeyValueStore>
+            }
+        });
+        Self {
+            stores,
+            unavailable,
+        }
+    }
+
+    /// Stores that keep nothing past the app, for tests
+    #[cfg(test)]
+    pub fn in_memory() -> Self {
+        Self {
+            stores: StorageArea::ALL
+                .map(|_| Arc::new(MemoryStore::default()) as Arc<dyn KeyValueStore>),
+            unavailable: Vec::new(),
+        }
+    }
+
+    pub fn area(&self, area: StorageArea) -> &dyn KeyValueStore {
+        self.stores[area as usize].as_ref()
+    }
+}
+
+/// Whether a value written to `store` can be read back
+fn works(store: &dyn KeyValueStore) -> bool {
+    const PROBE_KEY: &str = "storage_probe";
+    let works = store.set(PROBE_KEY, "1").is_ok() && store.get(PROBE_KEY).as_deref() == Some("1");
+    store.remove(PROBE_KEY);
+    works
+}
+
+#[cfg(not(feature = "native"))]
+mod platform {
+    use super::{KeyValueStore, StorageArea};
+    use std::sync::Arc;
+    use wasm_cookies::CookieOptions;
+
+    struct CookieStore;
+
+    impl KeyValueStore for CookieStore {
+        /// Cookies that aren't valid UTF-8 read as empty, so they fail to
+        /// parse like other corrupt values
+        fn get(&self, key: &str) -> Option<String> {
+            wasm_cookies::get(key).map(Result::unwrap_or_default)
+        }
+
+        fn set(&self, key: &str, value: &str) -> Result<(), String> {
+            wasm_cookies::set(key, value, &CookieOptions::default());
+            Ok(())
+        }
+
+        fn remove(&self, key: &str) {
+            wasm_cookies::delete(key);
+        }
+    }
+
+    /// Local or session storage. Looked up on every access, since the
+    /// browser's handle can't be shared between threads.
+    struct WebStore(StorageArea);
+
+    impl WebStore {
+        fn storage(&self) -> Option<web_sys::Storage> {
+            let window = web_sys::window()?;
+            match self.0 {
+                StorageArea::Session => window.session_storage(),
+                _ => window.local_storage(),
+            }
+            .ok()
+            .flatten()
+        }
+    }
+
+    impl KeyValueStore for WebStore {
+        fn get(&self, key: &str) -> Option<String> {
+            self.storage()?.get_item(key).ok().flatten()
+        }
+
+        fn set(&self, key: &str, value: &str) -> Result<(), String> {
+            let storage = self.storage().ok_or("storage unavailable")?;
+            storage.set_item(key, value).map_err(|e| format!("{e:?}"))
+        }
+
+        fn remove(&self, key: &str) {
+            if let Some(storage) = self.storage() {
+                let _ = storage.remove_item(key);
+            }
+        }
+    }
+
+    pub fn store(area: StorageArea) -> Option<Arc<dyn KeyValueStore>> {
+        Some(match area {
+            StorageArea::Cookies => Arc::new(CookieStore),
+            area => Arc::new(WebStore(area)),
+        })
+    }
+}
+
+#[cfg(feature = "native")]
+mod platform {
+    use super::{KeyValueStore, MemoryStore, StorageArea};
+    use std::{fs, path::PathBuf, sync::Arc};
+
+    /// Overrides where persistent data is kept, e.g. to give each bot peer
+    /// its own profile
+    const DATA_DIR_VAR: &str = "WEB_GHOST_DATA_DIR";
+    const DEFAULT_DATA_DIR: &str = "web_ghost_data";
+
+    /// One file per key in a directory
+    struct FileStore(PathBuf);
+
+    impl FileStore {
+        /// Keys contain characters like `:` that aren't safe in file names
+        fn path(&self, key: &str) -> PathBuf {
+            let name = key
+                .bytes()
+                .map(|byte| match byte {
+                    b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' | b'.' => {
+                        (byte as char).to_string()
+                    }
+                    byte => format!("%{byte:02x}"),
+                })
+                .collect::<String>();
+            self.0.join(name)
+        }
+    }
+
+    impl KeyValueStore for FileStore {
+        fn get(&self, key: &str) -> Option<String> {
+            fs::read(self.path(key))
+                .ok()
+                .map(|bytes| String::from_utf8(bytes).unwrap_or_default())
+        }
+
+        fn set(&self, key: &str, value: &str) -> Result<(), String> {
+            fs::create_dir_all(&self.0).map_err(|e| e.to_string())?;
+            fs::write(self.path(key), value).map_err(|e| e.to_string())
+        }
+
+        fn remove(&self, key: &str) {
+            let _ = fs::remove_file(self.path(key));
+        }
+    }
+
+    pub fn store(area: StorageArea) -> Option<Arc<dyn KeyValueStore>> {
+        let root = std::env::var(DATA_DIR_VAR).unwrap_or_else(|_| DEFAULT_DATA_DIR.to_string());
+        let root = PathBuf::from(root);
+        Some(match area {
+            StorageArea::Cookies => Arc::new(FileStore(root.join("cookies"))),
+            StorageArea::Local => Arc::new(FileStore(root.join("local"))),
+            // A session lasts as long as the process
+            StorageArea::Session => Arc::new(MemoryStore::default()),
+        })
+    }
+}
+
+/// Run in a browser by the `wasm-tests` CI job, see .github/workflows/tests.yml
+#[cfg(all(test, target_arch = "wasm32"))]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    #[wasm_bindgen_test]
+    fn every_area_is_available_in_the_browser() {
+        assert_eq!(KeyValueStores::get().unavailable, Vec::new());
+    }
+
+    #[wasm_bindgen_test]
+    fn every_area_keeps_values() {
+        let stores = KeyValueStores::get();
+        for area in StorageArea::ALL {
+            let store = stores.area(area);
+            let key = format!("storage_test:{area:?}");
+            assert_eq!(store.get(&key), None);
+            store.set(&key, "(value: \"a; b=c\")").unwrap();
+            assert_eq!(store.get(&key).as_deref(), Some("(value: \"a; b=c\")"));
+            store.remove(&key);
+            assert_eq!(store.get(&key), None);
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn areas_are_separate() {
+        let stores = KeyValueStores::get();
+        let key = "storage_test:separate";
+        stores.area(StorageArea::Local).set(key, "local").unwrap();
+        assert_eq!(stores.area(StorageArea::Session).get(key), None);
+        assert_eq!(stores.area(StorageArea::Cookies).get(key), None);
+        stores.area(StorageArea::Local).remove(key);
+    }
+}
@@ -0,0 +1,147 @@
+use crate::{frame_advantage::throttle, GameState, GgrsConfig};
+use bevy::prelude::*;
+use bevy_egui::{
+    egui::{Align2, Area, Frame, RichText},
+    EguiContexts,
+};
+use bevy_ggrs::Session;
+#[cfg(not(feature = "native"))]
+use std::cell::RefCell;
+
+/// Gets the game back in step after its tab was in the background. Browsers
+/// stop or throttle animation frames in hidden tabs, so the simulation stalls
+/// while the other peers play on. When the tab becomes visible again and the
+/// local simulation turns out to be far behind, it runs at a multiple of the
+/// tick rate until it has caught up, under a "reconnecting to match" overlay
+/// instead of a jumpy game.
+pub struct TabVisibilityPlugin;
+
+impl Plugin for TabVisibilityPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TabVisibility>()
+            .add_systems(
+                (detect_frame_deficit.before(throttle), catch_up_ui)
+                    .in_set(OnUpdate(GameState::InGame)),
+            )
+            .add_system(stop_catching_up.in_schedule(OnExit(GameState::InGame)));
+        #[cfg(not(feature = "native"))]
+        app.add_startup_system(listen_for_visibility_changes)
+            .add_system(track_visibility.in_base_set(CoreSet::PreUpdate));
+    }
+}
+
+/// Frames behind the peers from which the simulation speeds up to catch up
+const CATCH_UP_THRESHOLD_FRAMES: i32 = 10;
+/// Catching up stops once the simulation is at most this far behind
+const CAUGHT_UP_FRAMES: i32 = 2;
+/// Simulation rate while catching up, in percent of the tick rate. This caps
+/// the extra GGRS ticks per render frame, so a long absence is made up over
+/// a few seconds instead of freezing the tab in one huge frame.
+pub const CATCH_UP_RATE_PERCENT: u32 = 300;
+/// Longest a catch-up may take before it's given up on, leaving the rest to
+/// GGRS's own time sync
+const MAX_CATCH_UP_SECS: f64 = 10.;
+
+#[cfg(not(feature = "native"))]
+thread_local! {
+    /// Whether the tab was hidden, for each visibility change since the last
+    /// frame
+    static VISIBILITY_CHANGES: RefCell<Vec<bool>> = RefCell::new(Vec::new());
+}
+
+#[derive(Resource, Default)]
+pub struct TabVisibility {
+    pub hidden: bool,
+    /// Set when the tab comes back, until the frame deficit was looked at
+    returned: bool,
+    /// When the current catch-up started, if the simulation is catching up
+    catching_up_since: Option<f64>,
+}
+
+impl TabVisibility {
+    pub fn is_catching_up(&self) -> bool {
+        self.catching_up_since.is_some()
+    }
+}
+
+#[cfg(not(feature = "native"))]
+fn listen_for_visibility_changes() {
+    use wasm_bindgen::{closure::Closure, JsCast};
+
+    let Some(document) = web_sys::window().and_then(|window| window.document()) else {
+        return;
+    };
+    let listener = Closure::<dyn FnMut()>::new(|| {
+        let hidden = web_sys::window()
+            .and_then(|window| window.document())
+            .map_or(false, |document| document.hidden());
+        VISIBILITY_CHANGES.with(|changes| changes.borrow_mut().push(hidden));
+    });
+    if let Err(e) = document
+        .add_event_listener_with_callback("visibilitychange", listener.as_ref().unchecked_ref())
+    {
+        warn!("Couldn't listen for tab visibility changes: {e:?}");
+    }
+    // Listens for as long as the page is open
+    listener.forget();
+}
+
+#[cfg(not(feature = "native"))]
+fn track_visibility(mut visibility: ResMut<TabVisibility>) {
+    for hidden in VISIBILITY_CHANGES.with(|changes| changes.take()) {
+        if hidden == visibility.hidden {
+            continue;
+        }
+        info!("Tab {}", if hidden { "hidden" } else { "visible again" });
+        visibility.hidden = hidden;
+        visibility.returned |= !hidden;
+    }
+}
+
+/// Starts catching up when the tab comes back far behind the peers, and
+/// stops once the deficit is made up
+fn detect_frame_deficit(
+    time: Res<Time>,
+    session: Option<Res<Session<GgrsConfig>>>,
+    mut visibility: ResMut<TabVisibility>,
+) {
+    let Some(Session::P2PSession(session)) = session.as_deref() else {
+        return;
+    };
+    let now = time.elapsed_seconds_f64();
+    let behind = -session.frames_ahead();
+    if std::mem::take(&mut visibility.returned) && behind >= CATCH_UP_THRESHOLD_FRAMES {
+        info!("Back {behind} frames behind the peers, catching up");
+        visibility.catching_up_since = Some(now);
+    }
+    let Some(since) = visibility.catching_up_since else {
+        return;
+    };
+    if behind <= CAUGHT_UP_FRAMES {
+        info!("Caught up after {:.1}s", now - since);
+        visibility.catching_up_since = None;
+    } else if now - since > MAX_CATCH_UP_SECS {
+        warn!("Still {behind} frames behind after catching up, giving up");
+        visibility.catching_up_since = None;
+    }
+}
+
+fn catch_up_ui(mut contexts: EguiContexts, visibility: Res<TabVisibility>) {
+    if !visibility.is_catching_up() {
+        return;
+    }
+    Area::new("catching_up")
+        .anchor(Align2::CENTER_TOP, [0., 40.])
+        .interactable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            Frame::popup(ui.style()).show(ui, |ui| {
+                ui.label(RichText::new("Reconnecting to match…").strong());
+                ui.weak("Catching up with what happened while the tab was hidden");
+            });
+        });
+}
+
+fn stop_catching_up(mut visibility: ResMut<TabVisibility>) {
+    visibility.returned = false;
+    visibility.catching_up_since = None;
+}
@@ -0,0 +1,73 @@
+use crate::{maps::WallRect, F2I};
+use bevy::{
+    prelude::*,
+    render::mesh::{Indices, PrimitiveTopology},
+    utils::HashSet,
+};
+
+/// One mesh of axis-aligned quads, each given by its min and max corner, so
+/// a whole layer of the map is a single draw call instead of a sprite per
+/// piece
+pub fn quad_mesh(quads: impl IntoIterator<Item = (Vec2, Vec2)>) -> Mesh {
+    let mut positions = Vec::new();
+    let mut indices = Vec::new();
+    for (min, max) in quads {
+        let first = positions.len() as u32;
+        positions.extend([
+            [min.x, min.y, 0.],
+            [max.x, min.y, 0.],
+            [max.x, max.y, 0.],
+            [min.x, max.y, 0.],
+        ]);
+        indices.extend([first, first + 1, first + 2, first, first + 2, first + 3]);
+    }
+    let normals = vec![[0., 0., 1.]; positions.len()];
+    let uvs = vec![[0., 0.]; positions.len()];
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+    mesh.set_indices(Some(Indices::U32(indices)));
+    mesh
+}
+
+/// Unit tiles covered by `walls`, by their lower left corner in world units.
+/// Map walls sit on whole units, so they tile exactly.
+fn wall_tiles(walls: &[WallRect]) -> HashSet<IVec2> {
+    let mut tiles = HashSet::default();
+    for wall in walls {
+        let (min, max) = (wall.min / F2I, wall.max / F2I);
+        for x in min.x..max.x {
+            for y in min.y..max.y {
+                tiles.insert(IVec2::new(x, y));
+            }
+        }
+    }
+    tiles
+}
+
+/// The walls' fill, and their edges, autotiled: each tile only gets an edge
+/// on the sides that don't border another wall tile, so touching and
+/// overlapping walls read as one solid block
+pub fn wall_meshes(walls: &[WallRect], edge_width: f32) -> (Mesh, Mesh) {
+    let tiles = wall_tiles(walls);
+    let fill = quad_mesh(tiles.iter().map(|tile| {
+        let min = tile.as_vec2();
+        (min, min + Vec2::ONE)
+    }));
+    let sides = [
+        (IVec2::NEG_X, Vec2::ZERO, Vec2::new(edge_width, 1.)),
+        (IVec2::X, Vec2::new(1. - edge_width, 0.), Vec2::ONE),
+        (IVec2::NEG_Y, Vec2::ZERO, Vec2::new(1., edge_width)),
+        (IVec2::Y, Vec2::new(0., 1. - edge_width), Vec2::ONE),
+    ];
+    let edges = quad_mesh(tiles.iter().flat_map(|tile| {
+        let min = tile.as_vec2();
+        sides
+            .iter()
+            .filter(|(neighbor, ..)| !tiles.contains(&(*tile + *neighbor)))
+            .map(move |(_, from, to)| (min + *from, min + *to))
+            .collect::<Vec<_>>()
+    }));
+    (fill, edges)
+}
@@ -0,0 +1,97 @@
+use crate::GameState;
+use bevy::{input::touch::Touches, prelude::*, window::PrimaryWindow};
+use bevy_egui::{
+    egui::{self, Color32, Stroke},
+    EguiContexts,
+};
+
+/// On-screen virtual joystick (left half of the screen) and fire button
+/// (right half), switched on the first time a touch is seen
+pub struct TouchPlugin;
+
+impl Plugin for TouchPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TouchControls>().add_systems(
+            (update_touch_controls, draw_touch_controls.after(update_touch_controls))
+                .in_set(OnUpdate(GameState::InGame)),
+        );
+    }
+}
+
+/// Radius in logical pixels the joystick knob can travel from its base
+const JOYSTICK_RADIUS: f32 = 60.;
+const FIRE_BUTTON_RADIUS: f32 = 45.;
+
+#[derive(Resource, Default)]
+pub struct TouchControls {
+    pub enabled: bool,
+    /// Joystick deflection in screen space (y down), with length in `0..=1`
+    pub direction: Vec2,
+    pub fire: bool,
+    joystick: Option<(Vec2, Vec2)>,
+}
+
+fn update_touch_controls(
+    touches: Res<Touches>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut controls: ResMut<TouchControls>,
+) {
+    let Ok(window) = windows.get_single() else {
+        return;
+    };
+    if !controls.enabled && touches.iter().next().is_none() {
+        return;
+    }
+    controls.enabled = true;
+
+    let half_width = window.width() / 2.;
+    let mut joystick = None;
+    let mut fire = false;
+    for touch in touches.iter() {
+        if touch.start_position().x < half_width {
+            joystick.get_or_insert((touch.start_position(), touch.position()));
+        } else {
+            fire = true;
+        }
+    }
+    controls.joystick = joystick;
+    controls.direction = joystick
+        .map(|(base, knob)| ((knob - base) / JOYSTICK_RADIUS).clamp_length_max(1.))
+        .unwrap_or_default();
+    controls.fire = fire;
+}
+
+fn draw_touch_controls(
+    mut contexts: EguiContexts,
+    controls: Res<TouchControls>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+) {
+    let Ok(window) = windows.get_single() else {
+        return;
+    };
+    if !controls.enabled {
+        return;
+    }
+    let painter = contexts.ctx_mut().layer_painter(egui::LayerId::new(
+        egui::Order::Foreground,
+        egui::Id::new("touch_controls"),
+    ));
+    let outline = Stroke::new(2., Color32::from_white_alpha(120));
+    let fill = Color32::from_white_alpha(60);
+
+    if let Some((base, _)) = controls.joystick {
+        let base = egui::pos2(base.x, base.y);
+        let knob = base + egui::vec2(controls.direction.x, controls.direction.y) * JOYSTICK_RADIUS;
+        painter.circle_stroke(base, JOYSTICK_RADIUS, outline);
+        painter.circle_filled(knob, JOYSTICK_RADIUS / 3., fill);
+    }
+
+    let fire_center = egui::pos2(
+        window.width() - FIRE_BUTTON_RADIUS * 2.,
+        window.height() - FIRE_BUTTON_RADIUS * 3.,
+    );
+    if controls.fire {
+        painter.circle_filled(fire_center, FIRE_BUTTON_RADIUS, fill);
+    }
+    painter.circle_stroke(fire_center, FIRE_BUTTON_RADIUS, outline);
+}
@@ -0,0 +1,173 @@
+use crate::{
+    classes::PlayerClass,
+    components::{Health, HealthBar, IsLocal, MatchBoxPeerId, Player, TabId, UserInfo},
+    connection_quality::{quality_icon, PeerPings},
+    cooldown_ring::CooldownRingSettings,
+    dash::DashCooldown,
+    filter::{display_name, WordFilter},
+    layers::DrawLayer,
+    leave::LeaveGame,
+    lobby::{PracticeMode, Spectating},
+    lobby_settings::{ConnectionSettings, LobbySettings},
+    net::DesyncDetected,
+    player::PLAYER_WIDTH_RF,
+    weapons::{Ammo, Weapon},
+    GameState,
+};
+use bevy::prelude::*;
+use bevy_egui::{
+    egui::{Align, Layout, ProgressBar, TopBottomPanel},
+    EguiContexts,
+};
+
+/// The in-game HUD: the bottom bar with the local player's status, and the
+/// health bars over every player
+pub struct UiPlugin;
+
+impl Plugin for UiPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems((bottom_bar_ui, update_health_bars).in_set(OnUpdate(GameState::InGame)));
+    }
+}
+
+fn bottom_bar_ui(
+    mut contexts: EguiContexts,
+    mut players: Query<
+        (
+            &TabId,
+            &UserInfo,
+            Option<&DashCooldown>,
+            Option<&Weapon>,
+            Option<&Ammo>,
+        ),
+        With<IsLocal>,
+    >,
+    mut ring_settings: ResMut<CooldownRingSettings>,
+    desync: Option<Res<DesyncDetected>>,
+    practice: Option<Res<PracticeMode>>,
+    spectating: Option<Res<Spectating>>,
+    settings: Res<LobbySettings>,
+    connection: Res<ConnectionSettings>,
+    others: Query<(&Player, &MatchBoxPeerId, &UserInfo), Without<IsLocal>>,
+    (pings, time, word_filter): (Res<PeerPings>, Res<Time>, Res<WordFilter>),
+    mut next_state: ResMut<NextState<GameState>>,
+    mut leave_events: EventWriter<LeaveGame>,
+) {
+    let (TabId(tab_id), UserInfo { name, .. }, dash, weapon, ammo) = players.single_mut();
+    TopBottomPanel::bottom("bottom_panel").show(contexts.ctx_mut(), |ui| {
+        ui.horizontal(|ui| {
+            ui.label(format!("Name: {name}"));
+            ui.weak(format!(
+                "{} Hz, input delay {}",
+                settings.tick_rate, connection.input_delay
+            ));
+            if spectating.is_some() {
+                ui.colored_label(ui.visuals().warn_fg_color, "Spectating (room full)");
+            }
+            if let Some(weapon) = weapon {
+                ui.label(format!("Weapon: {} (Q to switch)", weapon.name()));
+            }
+            if let (Some(weapon), Some(ammo)) = (weapon, ammo) {
+                if let Some(remaining) = ammo.reload_remaining(*weapon) {
+                    ui.add(
+                        ProgressBar::new(1. - remaining)
+                            .desired_width(80.)
+                            .text("Reloading"),
+                    );
+                } else {
+                    ui.label(format!(
+                        "Ammo: {}/{} +{} (R to reload)",
+                        ammo.clip,
+                        weapon.stats().clip_size,
+                        ammo.reserve
+                    ));
+                }
+            }
+            if let Some(remaining) = dash.and_then(DashCooldown::remaining_fraction) {
+                ui.add(
+                    ProgressBar::new(1. - remaining)
+                        .desired_width(80.)
+                        .text("Dash"),
+                );
+            } else if dash.is_some() {
+                ui.label("Dash ready");
+            }
+            ui.checkbox(&mut ring_settings.enabled, "Cooldown rings");
+            let mut others = others.iter().collect::<Vec<_>>();
+            others.sort_by_key(|(player, ..)| player.handle);
+            for (_, peer_id, info) in others {
+                quality_icon(ui, &pings, &time, peer_id.0);
+                ui.weak(&*display_name(&word_filter, info));
+            }
+            if practice.is_some() {
+                if ui.button("Leave practice").clicked() {
+                    next_state.set(GameState::Matchmaking);
+                }
+            } else if ui.button("Leave game").clicked() {
+                leave_events.send(LeaveGame);
+            }
+            if let Some(desync) = desync {
+                ui.colored_label(
+                    ui.visuals().error_fg_color,
+                    format!(
+                        "Desync at frame {} (local checksum {:#x})",
+                        desync.frame, desync.local_checksum
+                    ),
+                );
+            }
+            ui.with_layout(Layout::right_to_left(Align::Max), |ui| {
+                ui.label(format!("ID: {tab_id}"));
+            });
+        });
+    });
+}
+
+const HEALTH_BAR_WIDTH_RF: f32 = PLAYER_WIDTH_RF;
+const HEALTH_BAR_HEIGHT_RF: f32 = 0.08;
+
+fn update_health_bars(
+    mut commands: Commands,
+    players: Query<(Entity, &Transform, &Health, &PlayerClass), (With<Player>, Without<HealthBar>)>,
+    mut bars: Query<(
+        Entity,
+        &HealthBar,
+        &mut Transform,
+        &mut Sprite,
+        &mut Visibility,
+    )>,
+) {
+    for (bar_entity, HealthBar(owner), mut transform, mut sprite, mut visibility) in bars.iter_mut()
+    {
+        let Ok((_, player_transform, health, class)) = players.get(*owner) else {
+            commands.entity(bar_entity).despawn();
+            continue;
+        };
+        let fraction = (health.0 as f32 / class.stats().max_health as f32).clamp(0., 1.);
+        let width = HEALTH_BAR_WIDTH_RF * fraction;
+        transform.translation.x =
+            player_transform.translation.x + (width - HEALTH_BAR_WIDTH_RF) / 2.;
+        transform.translation.y = player_transform.translation.y + PLAYER_WIDTH_RF * 0.75;
+        sprite.custom_size = Some(Vec2::new(width, HEALTH_BAR_HEIGHT_RF));
+        sprite.color = Color::rgb(1. - fraction, fraction, 0.);
+        *visibility = if health.0 > 0 {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+    }
+    for (player, ..) in players.iter() {
+        if !bars.iter().any(|(_, owner, ..)| owner.0 == player) {
+            commands.spawn((
+                HealthBar(player),
+                DrawLayer::UiWorld,
+                SpriteBundle {
+                    sprite: Sprite {
+                        custom_size: Some(Vec2::new(HEALTH_BAR_WIDTH_RF, HEALTH_BAR_HEIGHT_RF)),
+                        ..default()
+                    },
+                    ..default()
+                },
+            ));
+        }
+    }
+}
@@ -0,0 +1,376 @@
+use crate::rng::SimFrame;
+#[cfg(feature = "presentation")]
+use crate::{
+    budget::{MAX_PARTICLES, PARTICLE_DEGRADE_THRESHOLD},
+    components::{Player, UserInfo},
+    layers::DrawLayer,
+    lobby_settings::LobbySettings,
+    low_power::LowPowerMode,
+    presentation_clock::PresentationClock,
+    vision::Vision,
+    GameState, IVec2Ext, MAX_PREDICTION_FRAMES,
+};
+use bevy::prelude::*;
+#[cfg(feature = "presentation")]
+use bevy::{transform::TransformSystem, utils::HashSet};
+#[cfg(feature = "presentation")]
+use bevy_egui::{
+    egui::{self, Align2, Color32, FontId, LayerId, Order},
+    EguiContexts, EguiSet, EguiSettings,
+};
+#[cfg(feature = "presentation")]
+use std::f32::consts::TAU;
+
+/// Short-lived particles for muzzle flashes, bullet trails, hits, deaths and
+/// bullets shot down, and floating damage numbers where bullets connect.
+/// Like sounds, effects are queued by the simulation as [`VfxEvent`]s and
+/// each is spawned once, however often its frame is resimulated. Particles
+/// are plain entities outside the rollback and are skipped in low power mode,
+/// and for effects out of the local player's [`Vision`] in the fog of war.
+/// Nearing [`MAX_PARTICLES`], effects lose their details, and past it the
+/// least important ones are dropped. Damage numbers aren't particles: they're
+/// painted with egui like the name tags, and shown in low power mode too.
+#[cfg(feature = "presentation")]
+pub struct VfxPlugin;
+
+#[cfg(feature = "presentation")]
+impl Plugin for VfxPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<VfxQueue>()
+            .add_event::<EffectShown>()
+            .add_systems(
+                (
+                    spawn_queued_effects,
+                    update_particles.after(spawn_queued_effects),
+                    update_damage_numbers.after(spawn_queued_effects),
+                )
+                    .in_set(OnUpdate(GameState::InGame)),
+            )
+            .add_system(
+                draw_damage_numbers
+                    .in_base_set(CoreSet::PostUpdate)
+                    .after(TransformSystem::TransformPropagate)
+                    .before(EguiSet::ProcessOutput)
+                    .run_if(in_state(GameState::InGame)),
+            )
+            .add_system(clear_vfx.in_schedule(OnExit(GameState::InGame)));
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Effect {
+    MuzzleFlash,
+    BulletTrail,
+    /// A bullet connected, for this much damage
+    Hit(i32),
+    DeathBurst,
+    /// Two bullets destroyed each other
+    Shootdown,
+}
+
+#[cfg(feature = "presentation")]
+impl Effect {
+    /// Effects spawn in this order, so the least important are the ones
+    /// dropped once the particle budget runs out
+    fn importance(self) -> u8 {
+        match self {
+            Self::DeathBurst | Self::Hit(_) | Self::Shootdown => 2,
+            Self::MuzzleFlash => 1,
+            Self::BulletTrail => 0,
+        }
+    }
+}
+
+/// An effect caused by the simulation. Resimulating a frame queues an equal
+/// event, which is how replays are recognized.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct VfxEvent {
+    pub frame: u32,
+    pub effect: Effect,
+    /// Handle of the player the effect belongs to
+    pub source: usize,
+    pub position: IVec2,
+    /// Scaled like [`crate::components::MoveDir`]
+    pub direction: IVec2,
+}
+
+/// Sent once for every effect as it's spawned, after replays are filtered
+/// out, for other presentation systems that react to effects
+#[cfg(feature = "presentation")]
+pub struct EffectShown(pub VfxEvent);
+
+#[derive(Resource, Default)]
+pub struct VfxQueue {
+    queued: Vec<VfxEvent>,
+    /// Events already spawned that could still be simulated again
+    #[cfg(feature = "presentation")]
+    spawned: HashSet<VfxEvent>,
+}
+
+impl VfxQueue {
+    pub fn push(
+        &mut self,
+        frame: &SimFrame,
+        effect: Effect,
+        source: usize,
+        position: IVec2,
+        direction: IVec2,
+    ) {
+        self.queued.push(VfxEvent {
+            frame: frame.0,
+            effect,
+            source,
+            position,
+            direction,
+        });
+    }
+}
+
+#[cfg(feature = "presentation")]
+#[derive(Component)]
+struct Particle {
+    /// World units per second
+    velocity: Vec2,
+    secs_left: f32,
+    lifetime: f32,
+    size: f32,
+    alpha: f32,
+}
+
+/// Damage dealt by a hit, floating up from where the bullet connected
+#[cfg(feature = "presentation")]
+#[derive(Component)]
+struct DamageNumber {
+    amount: i32,
+    /// Where the hit was in the simulation, to hide numbers in the fog
+    at: IVec2,
+    position: Vec2,
+    secs_left: f32,
+}
+
+#[cfg(feature = "presentation")]
+const FLASH_COLOR: Color = Color::rgb(1., 0.9, 0.5);
+#[cfg(feature = "presentation")]
+const HIT_COLOR: Color = Color::rgba(1., 1., 1., 0.9);
+#[cfg(feature = "presentation")]
+const TRAIL_COLOR: Color = Color::rgba(1., 1., 1., 0.4);
+#[cfg(feature = "presentation")]
+const DEATH_PARTICLES: usize = 12;
+#[cfg(feature = "presentation")]
+const DAMAGE_NUMBER_SECS: f32 = 0.8;
+/// World units per second damage numbers float up by
+#[cfg(feature = "presentation")]
+const DAMAGE_NUMBER_RISE_RF: f32 = 1.2;
+#[cfg(feature = "presentation")]
+const DAMAGE_NUMBER_FONT_SIZE: f32 = 16.;
+#[cfg(feature = "presentation")]
+const DAMAGE_NUMBER_COLOR: Color32 = Color32::from_rgb(255, 220, 90);
+
+#[cfg(feature = "presentation")]
+fn spawn_queued_effects(
+    mut commands: Commands,
+    frame: Res<SimFrame>,
+    low_power: Res<LowPowerMode>,
+    vision: Res<Vision>,
+    settings: Res<LobbySettings>,
+    mut queue: ResMut<VfxQueue>,
+    mut shown: EventWriter<EffectShown>,
+    players: Query<(&Player, Option<&UserInfo>)>,
+    particles: Query<(), With<Particle>>,
+) {
+    if queue.queued.is_empty() {
+        return;
+    }
+    let mut room = MAX_PARTICLES.saturating_sub(particles.iter().len());
+    let VfxQueue { queued, spawned } = &mut *queue;
+    queued.sort_by_key(|event| std::cmp::Reverse(event.effect.importance()));
+    for event in queued.drain(..) {
+        if !spawned.insert(event) {
+            continue;
+        }
+        shown.send(EffectShown(event));
+        if let Effect::Hit(amount) = event.effect {
+            commands.spawn(DamageNumber {
+                amount,
+                at: event.position,
+                position: event.position.i2f(),
+                secs_left: DAMAGE_NUMBER_SECS,
+            });
+        }
+        if low_power.0 || !vision.can_see(event.position, &settings) {
+            continue;
+        }
+        let degraded = room < PARTICLE_DEGRADE_THRESHOLD;
+        if degraded && event.effect == Effect::BulletTrail {
+            continue;
+        }
+        let position = event.position.i2f();
+        let direction = event.direction.i2f().normalize_or_zero();
+        let mut spawn = |color: Color, velocity: Vec2, lifetime: f32, size: f32| {
+            if room == 0 {
+                return;
+            }
+            room -= 1;
+            commands.spawn((
+                Particle {
+                    velocity,
+                    secs_left: lifetime,
+                    lifetime,
+                    size,
+                    alpha: color.a(),
+                },
+                DrawLayer::Vfx,
+                SpriteBundle {
+                    sprite: Sprite {
+                        color,
+                        custom_size: Some(Vec2::splat(size)),
+                        ..default()
+                    },
+                    transform: Transform::from_translation(position.extend(DrawLayer::Vfx.z())),
+                    ..default()
+                },
+            ));
+        };
+        match event.effect {
+            Effect::MuzzleFlash => {
+                spawn(FLASH_COLOR, Vec2::ZERO, 0.06, 0.35);
+                if degraded {
+                    continue;
+                }
+                for angle in [-0.4, 0., 0.4] {
+                    let spark = Vec2::from_angle(angle).rotate(direction) * 4.;
+                    spawn(FLASH_COLOR, spark, 0.12, 0.06);
+                }
+            }
+            Effect::BulletTrail => spawn(TRAIL_COLOR, Vec2::ZERO, 0.15, 0.08),
+            Effect::Hit(_) => spawn(HIT_COLOR, Vec2::ZERO, 0.1, 0.3),
+            Effect::Shootdown => {
+                spawn(FLASH_COLOR, Vec2::ZERO, 0.08, 0.3);
+                if degraded {
+                    continue;
+                }
+                // Sparks fly off to the sides of the bullets' paths
+                for side in [-1., 1.] {
+                    spawn(FLASH_COLOR, direction.perp() * side * 3., 0.15, 0.06);
+                }
+            }
+            Effect::DeathBurst => {
+                let color = players
+                    .iter()
+                    .find(|(player, _)| player.handle == event.source)
+                    .and_then(|(_, info)| info.map(UserInfo::sprite_color))
+                    .unwrap_or(Color::WHITE);
+                // Turned by the frame so bursts don't all look the same
+                let count = if degraded {
+                    DEATH_PARTICLES / 2
+                } else {
+                    DEATH_PARTICLES
+                };
+                let offset = (event.frame % 7) as f32 / 7. * TAU / count as f32;
+                for i in 0..count {
+                    let angle = offset + i as f32 / count as f32 * TAU;
+                    let speed = if i % 2 == 0 { 3. } else { 5. };
+                    spawn(color, Vec2::from_angle(angle) * speed, 0.5, 0.12);
+                }
+            }
+        }
+    }
+    // Frames older than the prediction window are never simulated again
+    let oldest = frame.0.saturating_sub(MAX_PREDICTION_FRAMES);
+    spawned.retain(|event| event.frame >= oldest);
+}
+
+#[cfg(feature = "presentation")]
+fn update_particles(
+    mut commands: Commands,
+    clock: Res<PresentationClock>,
+    mut particles: Query<(Entity, &mut Particle, &mut Transform, &mut Sprite)>,
+) {
+    let delta = clock.delta_seconds();
+    for (entity, mut particle, mut transform, mut sprite) in particles.iter_mut() {
+        particle.secs_left -= delta;
+        if particle.secs_left <= 0. {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        let left = particle.secs_left / particle.lifetime;
+        transform.translation += (particle.velocity * delta).extend(0.);
+        sprite.custom_size = Some(Vec2::splat(particle.size * (0.5 + left / 2.)));
+        sprite.color.set_a(particle.alpha * left);
+    }
+}
+
+#[cfg(feature = "presentation")]
+fn update_damage_numbers(
+    mut commands: Commands,
+    clock: Res<PresentationClock>,
+    mut numbers: Query<(Entity, &mut DamageNumber)>,
+) {
+    let delta = clock.delta_seconds();
+    for (entity, mut number) in numbers.iter_mut() {
+        number.secs_left -= delta;
+        if number.secs_left <= 0. {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        number.position.y += DAMAGE_NUMBER_RISE_RF * delta;
+    }
+}
+
+/// Painted at the numbers' projected screen positions, like the name tags
+#[cfg(feature = "presentation")]
+fn draw_damage_numbers(
+    mut contexts: EguiContexts,
+    egui_settings: Res<EguiSettings>,
+    vision: Res<Vision>,
+    settings: Res<LobbySettings>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    numbers: Query<&DamageNumber>,
+) {
+    if numbers.is_empty() {
+        return;
+    }
+    let Some((camera, camera_transform)) = cameras.iter().find(|(camera, _)| camera.is_active)
+    else {
+        return;
+    };
+    let Some(viewport_size) = camera.logical_viewport_size() else {
+        return;
+    };
+    let scale = egui_settings.scale_factor as f32;
+    let painter = contexts.ctx_mut().layer_painter(LayerId::new(
+        Order::Background,
+        egui::Id::new("damage_numbers"),
+    ));
+    for number in numbers.iter() {
+        if !vision.can_see(number.at, &settings) {
+            continue;
+        }
+        let Some(position) = camera.world_to_viewport(camera_transform, number.position.extend(0.))
+        else {
+            continue;
+        };
+        // Viewport y points up, egui's down
+        let position = egui::pos2(position.x / scale, (viewport_size.y - position.y) / scale);
+        let alpha = (number.secs_left / DAMAGE_NUMBER_SECS * 2.).min(1.);
+        painter.text(
+            position,
+            Align2::CENTER_BOTTOM,
+            number.amount.to_string(),
+            FontId::proportional(DAMAGE_NUMBER_FONT_SIZE),
+            DAMAGE_NUMBER_COLOR.linear_multiply(alpha),
+        );
+    }
+}
+
+#[cfg(feature = "presentation")]
+fn clear_vfx(
+    mut commands: Commands,
+    mut queue: ResMut<VfxQueue>,
+    effects: Query<Entity, Or<(With<Particle>, With<DamageNumber>)>>,
+) {
+    *queue = VfxQueue::default();
+    for entity in effects.iter() {
+        commands.entity(entity).despawn();
+    }
+}
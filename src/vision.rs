@@ -0,0 +1,170 @@
+use crate::{
+    components::{Active, Bullet, Health, HealthBar, Player, Position},
+    hot_seat::HotSeat,
+    lobby::Spectating,
+    lobby_settings::LobbySettings,
+    GameState, LocalPlayerHandle, F2I, I2F,
+};
+use bevy::{prelude::*, transform::TransformSystem};
+use bevy_egui::{
+    egui::{self, Color32, LayerId, Order, Stroke},
+    EguiContexts, EguiSet, EguiSettings,
+};
+
+/// Fog of war for the lobby's `fog_of_war` setting: living players only see
+/// others, and bullets, within [`VISION_RADIUS_SI`] of themselves, and the
+/// rest of the screen is darkened. Sight is judged on rollback positions, so
+/// it agrees with the simulation rather than with smoothed sprites. It only
+/// hides things locally; the dead, like spectators, see everything, and so
+/// do hot-seat players, who share a screen.
+pub struct VisionPlugin;
+
+impl Plugin for VisionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Vision>()
+            .add_system(update_vision.in_set(OnUpdate(GameState::InGame)))
+            .add_system(
+                hide_out_of_sight
+                    .in_base_set(CoreSet::PostUpdate)
+                    .before(TransformSystem::TransformPropagate)
+                    .run_if(in_state(GameState::InGame)),
+            )
+            .add_system(
+                draw_fog
+                    .in_base_set(CoreSet::PostUpdate)
+                    .after(TransformSystem::TransformPropagate)
+                    .before(EguiSet::ProcessOutput)
+                    .run_if(in_state(GameState::InGame)),
+            )
+            .add_system(reset_vision.in_schedule(OnExit(GameState::InGame)));
+    }
+}
+
+pub const VISION_RADIUS_SI: i32 = 5 * F2I;
+/// Darkness of the fog, out of 255
+const FOG_ALPHA: u8 = 200;
+
+/// Where the local player sees from, if their sight is limited
+#[derive(Resource, Default)]
+pub struct Vision {
+    center: Option<IVec2>,
+}
+
+impl Vision {
+    pub fn can_see(&self, position: IVec2, settings: &LobbySettings) -> bool {
+        let Some(center) = self.center else {
+            return true;
+        };
+        let offset = settings.nearest_image(position, center) - center;
+        let (x, y) = (offset.x as i64, offset.y as i64);
+        x * x + y * y <= (VISION_RADIUS_SI as i64).pow(2)
+    }
+}
+
+fn update_vision(
+    settings: Res<LobbySettings>,
+    local_handle: Option<Res<LocalPlayerHandle>>,
+    spectating: Option<Res<Spectating>>,
+    hot_seat: Option<Res<HotSeat>>,
+    players: Query<(&Player, &Position, &Health)>,
+    mut vision: ResMut<Vision>,
+) {
+    let limited = settings.fog_of_war && spectating.is_none() && hot_seat.is_none();
+    let center = match (&local_handle, limited) {
+        (Some(local), true) => players
+            .iter()
+            .find(|(player, _, health)| player.handle == local.0 && health.0 > 0)
+            .map(|(_, position, _)| position.0),
+        _ => None,
+    };
+    if vision.center != center {
+        vision.center = center;
+    }
+}
+
+/// Runs after everything else that sets these entities' visibility this frame
+fn hide_out_of_sight(
+    settings: Res<LobbySettings>,
+    vision: Res<Vision>,
+    local_handle: Option<Res<LocalPlayerHandle>>,
+    mut players: Query<(&Player, &Position, &Health, &mut Visibility)>,
+    mut bullets: Query<(&Active, &Position, &mut Visibility), (With<Bullet>, Without<Player>)>,
+    mut health_bars: Query<(&HealthBar, &mut Visibility), (Without<Player>, Without<Bullet>)>,
+) {
+    if vision.center.is_none() {
+        return;
+    }
+    let is_local = |player: &Player| {
+        local_handle
+            .as_ref()
+            .map_or(false, |l| l.0 == player.handle)
+    };
+    for (player, position, health, mut visibility) in players.iter_mut() {
+        // The dead are hidden by the simulation
+        if health.0 > 0 && !is_local(player) {
+            *visibility = if vision.can_see(position.0, &settings) {
+                Visibility::Inherited
+            } else {
+                Visibility::Hidden
+            };
+        }
+    }
+    // Pooled bullets not in flight stay hidden
+    for (_, position, mut visibility) in bullets.iter_mut().filter(|(active, ..)| active.0) {
+        *visibility = if vision.can_see(position.0, &settings) {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+    }
+    for (HealthBar(owner), mut visibility) in health_bars.iter_mut() {
+        if let Ok((_, position, ..)) = players.get(*owner) {
+            if !vision.can_see(position.0, &settings) {
+                *visibility = Visibility::Hidden;
+            }
+        }
+    }
+}
+
+/// Darkens the screen outside the vision radius, with a stroke around the
+/// visible circle wide enough to cover the rest of the screen
+fn draw_fog(
+    mut contexts: EguiContexts,
+    egui_settings: Res<EguiSettings>,
+    vision: Res<Vision>,
+    cameras: Query<(&Camera, &GlobalTransform, &OrthographicProjection)>,
+) {
+    let Some(center) = vision.center else {
+        return;
+    };
+    let Some((camera, camera_transform, projection)) =
+        cameras.iter().find(|(camera, ..)| camera.is_active)
+    else {
+        return;
+    };
+    let Some(viewport_size) = camera.logical_viewport_size() else {
+        return;
+    };
+    let world_center = (center.as_vec2() * I2F).extend(0.);
+    let Some(position) = camera.world_to_viewport(camera_transform, world_center) else {
+        return;
+    };
+    let scale = egui_settings.scale_factor as f32;
+    let points_per_unit = viewport_size.y / projection.area.height() / scale;
+    let radius = VISION_RADIUS_SI as f32 * I2F * points_per_unit;
+    // Viewport y points up, egui's down
+    let position = egui::pos2(position.x / scale, (viewport_size.y - position.y) / scale);
+    let cover = viewport_size.length() / scale * 2.;
+    contexts
+        .ctx_mut()
+        .layer_painter(LayerId::new(Order::Background, egui::Id::new("fog")))
+        .circle_stroke(
+            position,
+            radius + cover / 2.,
+            Stroke::new(cover, Color32::from_black_alpha(FOG_ALPHA)),
+        );
+}
+
+fn reset_vision(mut vision: ResMut<Vision>) {
+    *vision = default();
+}
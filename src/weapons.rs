@@ -0,0 +1,260 @@
+use crate::{
+    components::{Health, Player},
+    fixed_point,
+    input::switch_weapon,
+    GgrsConfig, F2I,
+};
+use bevy::prelude::*;
+use bevy_ggrs::PlayerInputs;
+
+/// The gun a player fires with
+#[derive(Component, Reflect, FromReflect, Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Weapon {
+    #[default]
+    Blaster,
+    Shotgun,
+    Repeater,
+}
+
+/// Everything firing a [`Weapon`] depends on, so one firing pipeline serves
+/// them all
+pub struct WeaponStats {
+    /// Knockback applied against the aim direction per shot
+    pub recoil_si: i32,
+    /// Frames the shooter moves slower after a shot
+    pub slowdown_frames: u32,
+    /// Movement speed while slowed, as a percentage of normal
+    pub slowdown_speed_percent: i32,
+    /// Fewest frames between two shots
+    pub fire_interval_frames: u32,
+    /// Whether holding fire keeps shooting, instead of taking a press per shot
+    pub automatic: bool,
+    /// Bullets per shot, fanned out evenly around the aim direction
+    pub bullets_per_shot: u32,
+    /// Angle between neighbouring bullets of a shot, in 1/256ths of a turn
+    pub spread: u8,
+    /// Percentage of the lobby's bullet speed
+    pub bullet_speed_percent: i32,
+    pub damage: i32,
+    pub lifetime_frames: u32,
+    /// Shots per clip
+    pub clip_size: u8,
+    /// Frames a reload takes
+    pub reload_frames: u8,
+}
+
+impl Weapon {
+    pub const ALL: [Weapon; 3] = [Weapon::Blaster, Weapon::Shotgun, Weapon::Repeater];
+
+    pub fn stats(self) -> &'static WeaponStats {
+        match self {
+            Weapon::Blaster => &WeaponStats {
+                recoil_si: 8 * F2I / 100,
+                slowdown_frames: 12,
+                slowdown_speed_percent: 60,
+                fire_interval_frames: 8,
+                automatic: false,
+                bullets_per_shot: 1,
+                spread: 0,
+                bullet_speed_percent: 100,
+                damage: 25,
+                lifetime_frames: 3 * 60,
+                clip_size: 12,
+                reload_frames: 60,
+            },
+            Weapon::Shotgun => &WeaponStats {
+                recoil_si: 20 * F2I / 100,
+                slowdown_frames: 20,
+                slowdown_speed_percent: 50,
+                fire_interval_frames: 40,
+                automatic: false,
+                bullets_per_shot: 5,
+                spread: 6,
+                bullet_speed_percent: 90,
+                damage: 12,
+                lifetime_frames: 40,
+                clip_size: 6,
+                reload_frames: 90,
+            },
+            Weapon::Repeater => &WeaponStats {
+                recoil_si: 3 * F2I / 100,
+                slowdown_frames: 6,
+                slowdown_speed_percent: 70,
+                fire_interval_frames: 6,
+                automatic: true,
+                bullets_per_shot: 1,
+                spread: 0,
+                bullet_speed_percent: 120,
+                damage: 10,
+                lifetime_frames: 2 * 60,
+                clip_size: 30,
+                reload_frames: 75,
+            },
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Weapon::Blaster => "Blaster",
+            Weapon::Shotgun => "Shotgun",
+            Weapon::Repeater => "Repeater",
+        }
+    }
+
+    fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|weapon| *weapon == self).unwrap();
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    /// Directions of the bullets of one shot aimed along `aim`
+    pub fn shot_directions(self, aim: IVec2) -> impl Iterator<Item = IVec2> {
+        let stats = self.stats();
+        let count = stats.bullets_per_shot as i32;
+        // Halved since offsets are doubled
+        let spread = stats.spread as i32 * fixed_point::AIM_STEP / 2;
+        (0..count).map(move |i| {
+            // Twice the offset from the middle bullet, to stay integral for
+            // even counts
+            let offset = 2 * i - (count - 1);
+            if offset == 0 {
+                return aim;
+            }
+            fixed_point::rotate(aim, offset * spread)
+        })
+    }
+}
+
+/// Frames left until the player's weapon can fire again
+#[derive(Component, Reflect, Default, Clone, Copy, Debug)]
+pub struct WeaponCooldown(pub u32);
+
+/// Most rounds a player carries besides the loaded clip, and what they start
+/// every round with
+pub const MAX_RESERVE_AMMO: u8 = 120;
+
+/// Rounds in the loaded clip and in reserve. A reload moves rounds from the
+/// reserve into the clip once it's done counting down.
+#[derive(Component, Reflect, Clone, Copy, Debug)]
+pub struct Ammo {
+    pub clip: u8,
+    pub reserve: u8,
+    /// Frames until the reload in progress is done, 0 while not reloading
+    pub reload_frames: u8,
+    /// Whether fire was released since the last shot, for weapons that take
+    /// a press per shot
+    pub trigger_ready: bool,
+}
+
+impl Default for Ammo {
+    fn default() -> Self {
+        Self::full(Weapon::default())
+    }
+}
+
+impl Ammo {
+    /// A loaded `weapon` and a full reserve
+    pub fn full(weapon: Weapon) -> Self {
+        Self {
+            clip: weapon.stats().clip_size,
+            reserve: MAX_RESERVE_AMMO,
+            reload_frames: 0,
+            trigger_ready: true,
+        }
+    }
+
+    pub fn is_reloading(&self) -> bool {
+        self.reload_frames > 0
+    }
+
+    pub fn can_fire(&self) -> bool {
+        self.clip > 0 && !self.is_reloading() && self.trigger_ready
+    }
+
+    /// Starts reloading `weapon`, unless the clip is full or the reserve empty
+    pub fn start_reload(&mut self, weapon: Weapon) {
+        if !self.is_reloading() && self.clip < weapon.stats().clip_size && self.reserve > 0 {
+            self.reload_frames = weapon.stats().reload_frames;
+        }
+    }
+
+    /// Counts a reload in progress down, filling the clip when it's done
+    pub fn tick_reload(&mut self, weapon: Weapon) {
+        if !self.is_reloading() {
+            return;
+        }
+        self.reload_frames -= 1;
+        if self.reload_frames == 0 {
+            let loaded = weapon
+                .stats()
+                .clip_size
+                .saturating_sub(self.clip)
+                .min(self.reserve);
+            self.clip += loaded;
+            self.reserve -= loaded;
+        }
+    }
+
+    /// Fraction of the reload in progress that's left, if there's one
+    #[cfg(feature = "presentation")]
+    pub fn reload_remaining(&self, weapon: Weapon) -> Option<f32> {
+        self.is_reloading()
+            .then(|| self.reload_frames as f32 / weapon.stats().reload_frames as f32)
+    }
+}
+
+/// Frames after moving that shots are still thrown off, see [`MoveSpread`]
+pub const MOVE_SPREAD_FRAMES: u32 = 12;
+/// Widest random turn of a shot fired while moving, in steps of
+/// [`fixed_point::TURN`]
+const MAX_MOVE_SPREAD: i32 = 12;
+
+/// How recently the player moved, counting down from [`MOVE_SPREAD_FRAMES`]
+/// once they stand still. Shots are turned by a random angle of up to a
+/// share of [`MAX_MOVE_SPREAD`] this size, so standing still gives perfect
+/// aim.
+#[derive(Component, Reflect, Default, Clone, Copy, Debug)]
+pub struct MoveSpread(pub u32);
+
+impl MoveSpread {
+    /// Widest angle a shot may be turned by right now
+    pub fn max_angle(self) -> i32 {
+        MAX_MOVE_SPREAD * self.0 as i32 / MOVE_SPREAD_FRAMES as i32
+    }
+
+    /// How much of the full spread applies, for the crosshair
+    #[cfg(feature = "presentation")]
+    pub fn fraction(self) -> f32 {
+        self.0 as f32 / MOVE_SPREAD_FRAMES as f32
+    }
+}
+
+/// Whether the switch button was released since the last switch, so holding
+/// it switches only once
+#[derive(Component, Reflect, Default, Clone, Copy, Debug)]
+pub struct SwitchReady(pub bool);
+
+/// Speed of a bullet as a percentage of the lobby's bullet speed, from the
+/// weapon that fired it
+#[derive(Component, Reflect, Default, Clone, Copy, Debug)]
+pub struct BulletSpeed(pub i32);
+
+/// Cycles to the next weapon on each press of the switch button. The loaded
+/// rounds go back into the reserve and the new weapon is reloaded.
+pub fn switch_weapons(
+    inputs: Res<PlayerInputs<GgrsConfig>>,
+    mut players: Query<(&Player, &Health, &mut Weapon, &mut SwitchReady, &mut Ammo)>,
+) {
+    for (player, health, mut weapon, mut switch_ready, mut ammo) in players.iter_mut() {
+        let (input, _) = inputs[player.handle];
+        if !switch_weapon(input) {
+            switch_ready.0 = true;
+        } else if switch_ready.0 && health.0 > 0 {
+            switch_ready.0 = false;
+            *weapon = weapon.next();
+            ammo.reserve = ammo.reserve.saturating_add(ammo.clip).min(MAX_RESERVE_AMMO);
+            ammo.clip = 0;
+            ammo.reload_frames = 0;
+            ammo.start_reload(*weapon);
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use bincode::Options;
+use serde::{de::DeserializeOwned, Serialize};
+
+/// A way of turning serde values into bytes and back. P2P messages, stored
+/// settings and saves go through one of these instead of calling a serde
+/// format directly, so the format can change without touching every caller.
+pub trait WireFormat {
+    /// Names the format in front of data that could be in any of several,
+    /// see [`encode_tagged`]
+    const TAG: u8;
+
+    fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>, String>;
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, String>;
+}
+
+/// A [`WireFormat`] whose output is text, for storage that only holds strings
+pub trait TextFormat: WireFormat {
+    fn to_text<T: Serialize>(value: &T) -> Result<String, String>;
+    fn from_text<T: DeserializeOwned>(text: &str) -> Result<T, String>;
+}
+
+/// Fixed-width little-endian integers, what P2P messages have always used
+pub struct Bincode;
+
+/// Readable text with field names, what cookies and local storage hold
+pub struct Ron;
+
+/// Variable-length integers in the style of postcard: most numbers sent are
+/// small and take a single byte. No field names, like [`Bincode`].
+pub struct Compact;
+
+impl WireFormat for Bincode {
+    const TAG: u8 = 0;
+
+    fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>, String> {
+        bincode::serialize(value).map_err(|e| e.to_string())
+    }
+
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, String> {
+        bincode::deserialize(bytes).map_err(|e| e.to_string())
+    }
+}
+
+impl WireFormat for Ron {
+    const TAG: u8 = 1;
+
+    fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>, String> {
+        Self::to_text(value).map(String::into_bytes)
+    }
+
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, String> {
+        Self::from_text(std::str::from_utf8(bytes).map_err(|e| e.to_string())?)
+    }
+}
+
+impl TextFormat for Ron {
+    fn to_text<T: Serialize>(value: &T) -> Result<String, String> {
+        ron::to_string(value).map_err(|e| e.to_string())
+    }
+
+    fn from_text<T: DeserializeOwned>(text: &str) -> Result<T, String> {
+        ron::from_str(text).map_err(|e| e.to_string())
+    }
+}
+
+impl WireFormat for Compact {
+    const TAG: u8 = 2;
+
+    fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>, String> {
+        bincode::DefaultOptions::new()
+            .serialize(value)
+            .map_err(|e| e.to_string())
+    }
+
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, String> {
+        bincode::DefaultOptions::new()
+            .deserialize(bytes)
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// Format P2P messages are sent in, picked with the `wire-compact` and
+/// `wire-ron` features. Every format is read whatever the choice, so builds
+/// that picked differently still understand each other.
+#[cfg(feature = "wire-compact")]
+pub type P2PFormat = Compact;
+#[cfg(all(feature = "wire-ron", not(feature = "wire-compact")))]
+pub type P2PFormat = Ron;
+#[cfg(not(any(feature = "wire-compact", feature = "wire-ron")))]
+pub type P2PFormat = Bincode;
+
+/// `value` in [`P2PFormat`], after the byte naming it
+pub fn encode_tagged<T: Serialize>(value: &T) -> Result<Vec<u8>, String> {
+    let mut bytes = vec![P2PFormat::TAG];
+    bytes.extend(P2PFormat::encode(value)?);
+    Ok(bytes)
+}
+
+/// Reads data written by [`encode_tagged`], in whichever format it names
+pub fn decode_tagged<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, String> {
+    let (tag, body) = bytes.split_first().ok_or("no data")?;
+    match *tag {
+        Bincode::TAG => Bincode::decode(body),
+        Ron::TAG => Ron::decode(body),
+        Compact::TAG => Compact::decode(body),
+        tag => Err(format!("unknown wire format {tag}")),
+    }
+}
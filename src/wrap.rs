@@ -0,0 +1,165 @@
+use crate::{
+    components::{Bullet, Player},
+    lobby_settings::LobbySettings,
+    GameState, I2F,
+};
+use bevy::{prelude::*, render::view::VisibilitySystems, transform::TransformSystem};
+
+/// Shows players and bullets on both sides of an edge while they cross it on
+/// a map that wraps around (see [`LobbySettings::wrap_around`]). Everything
+/// that can cross gets a copy of its sprite one map width away in each
+/// direction, and copies are shown wherever they land inside the view of the
+/// map. Copies are plain entities following their source once its transform
+/// is final for the frame, and go away with it.
+pub struct WrapPlugin;
+
+impl Plugin for WrapPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(
+            add_mirrors
+                .run_if(wraps_around)
+                .in_set(OnUpdate(GameState::InGame)),
+        )
+        .add_system(
+            follow_sources
+                .in_base_set(CoreSet::PostUpdate)
+                .after(TransformSystem::TransformPropagate)
+                .before(VisibilitySystems::VisibilityPropagate)
+                .before(VisibilitySystems::CheckVisibility)
+                .run_if(in_state(GameState::InGame)),
+        )
+        .add_system(remove_mirrors.in_schedule(OnExit(GameState::InGame)));
+    }
+}
+
+/// How far past the map's edge copies are still shown, a bit beyond what the
+/// camera can see
+const VISIBLE_MARGIN_RF: f32 = 1.5;
+
+/// A copy of `source`'s sprite, offset by `offset` map widths
+#[derive(Component)]
+struct WrapMirror {
+    source: Entity,
+    offset: Vec2,
+}
+
+/// Set on entities once their mirrors are spawned
+#[derive(Component)]
+struct Mirrored;
+
+fn wraps_around(settings: Res<LobbySettings>) -> bool {
+    settings.wrap_around
+}
+
+fn add_mirrors(
+    mut commands: Commands,
+    sources: Query<
+        (
+            Entity,
+            Option<&Sprite>,
+            Option<&Handle<Image>>,
+            Option<&TextureAtlasSprite>,
+            Option<&Handle<TextureAtlas>>,
+        ),
+        (Or<(With<Player>, With<Bullet>)>, Without<Mirrored>),
+    >,
+) {
+    for (source, sprite, image, atlas_sprite, atlas) in sources.iter() {
+        commands.entity(source).insert(Mirrored);
+        for x in -1..=1 {
+            for y in -1..=1 {
+                if x == 0 && y == 0 {
+                    continue;
+                }
+                let mirror = WrapMirror {
+                    source,
+                    offset: Vec2::new(x as f32, y as f32),
+                };
+                match (sprite, image, atlas_sprite, atlas) {
+                    (Some(sprite), Some(image), ..) => {
+                        commands.spawn((
+                            mirror,
+                            SpriteBundle {
+                                sprite: sprite.clone(),
+                                texture: image.clone(),
+                                ..default()
+                            },
+                        ));
+                    }
+                    (_, _, Some(sprite), Some(atlas)) => {
+                        commands.spawn((
+                            mirror,
+                            SpriteSheetBundle {
+                                sprite: sprite.clone(),
+                                texture_atlas: atlas.clone(),
+                                ..default()
+                            },
+                        ));
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+/// Copies the sources' final transforms, sprites and visibility, and removes
+/// mirrors of despawned sources
+fn follow_sources(
+    mut commands: Commands,
+    settings: Res<LobbySettings>,
+    sources: Query<
+        (
+            &GlobalTransform,
+            &Visibility,
+            Option<&Sprite>,
+            Option<&TextureAtlasSprite>,
+        ),
+        Without<WrapMirror>,
+    >,
+    mut mirrors: Query<(
+        Entity,
+        &WrapMirror,
+        &mut Transform,
+        &mut GlobalTransform,
+        &mut Visibility,
+        Option<&mut Sprite>,
+        Option<&mut TextureAtlasSprite>,
+    )>,
+) {
+    let map_size = settings.map_size_si() as f32 * I2F;
+    let limit = map_size / 2. + VISIBLE_MARGIN_RF;
+    for (entity, mirror, mut transform, mut global, mut visibility, sprite, atlas_sprite) in
+        mirrors.iter_mut()
+    {
+        let Ok((source_global, source_visibility, source_sprite, source_atlas_sprite)) =
+            sources.get(mirror.source)
+        else {
+            commands.entity(entity).despawn();
+            continue;
+        };
+        *transform = source_global.compute_transform();
+        transform.translation += (mirror.offset * map_size).extend(0.);
+        // Transforms were already propagated for this frame
+        *global = GlobalTransform::from(*transform);
+        let position = transform.translation.truncate();
+        let in_view = position.x.abs() <= limit && position.y.abs() <= limit;
+        *visibility = if in_view {
+            *source_visibility
+        } else {
+            Visibility::Hidden
+        };
+        if let (Some(mut sprite), Some(source)) = (sprite, source_sprite) {
+            *sprite = source.clone();
+        }
+        if let (Some(mut sprite), Some(source)) = (atlas_sprite, source_atlas_sprite) {
+            *sprite = source.clone();
+        }
+    }
+}
+
+fn remove_mirrors(mut commands: Commands, mirrors: Query<Entity, With<WrapMirror>>) {
+    for entity in mirrors.iter() {
+        commands.entity(entity).despawn();
+    }
+}
@@ -0,0 +1,199 @@
+use crate::{
+    achievements::{GameplayEvent, GameplayEvents},
+    audio::{Sound, SoundQueue},
+    components::{Health, Invulnerable, Player, Position},
+    game_modes::Rule,
+    lobby_settings::LobbySettings,
+    pickups::collect_pickups,
+    rng::SimFrame,
+    rounds::{update_round, RoundState},
+    simulation::apply_damage,
+    GameState, F2I,
+};
+#[cfg(feature = "presentation")]
+use crate::{layers::DrawLayer, I2F};
+use bevy::prelude::*;
+use bevy_ggrs::GGRSSchedule;
+
+/// Sudden death: once a round has gone on for the mode's zone delay, the safe
+/// area closes in on the middle of the map and players left outside it lose
+/// health until they come back in or die. The zone is a rollback resource, so
+/// it's resimulated and saved along with everything else.
+pub struct ZonePlugin;
+
+impl Plugin for ZonePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ZoneState>()
+            .add_system(
+                update_zone
+                    .after(apply_damage)
+                    .after(collect_pickups)
+                    .before(update_round)
+                    .in_schedule(GGRSSchedule),
+            )
+            .add_system(reset_zone.in_schedule(OnExit(GameState::InGame)));
+        #[cfg(feature = "presentation")]
+        app.add_system(draw_zone_edges.in_set(OnUpdate(GameState::InGame)))
+            .add_system(despawn_zone_edges.in_schedule(OnExit(GameState::InGame)));
+    }
+}
+
+/// Half the width of the zone once it's done shrinking
+const ZONE_MIN_HALF_SIZE_SI: i32 = 3 * F2I;
+/// Seconds the zone takes to close from the map edges to its smallest size
+const ZONE_SHRINK_SECS: u32 = 45;
+/// Health lost by a player outside the zone each time it bites
+const ZONE_DAMAGE: i32 = 5;
+/// Bites per second while outside the zone
+const ZONE_DAMAGE_PER_SEC: u32 = 2;
+
+#[derive(Resource, Reflect, Default, Clone, Debug)]
+#[reflect(Resource)]
+pub struct ZoneState {
+    /// Frames the current round has been going on for
+    pub round_frames: u32,
+    /// Half the width of the square around the map center that's safe, or 0
+    /// while the zone hasn't started closing
+    pub half_size: i32,
+}
+
+impl ZoneState {
+    pub fn is_closing(&self) -> bool {
+        self.half_size > 0
+    }
+
+    pub fn contains(&self, position: IVec2) -> bool {
+        !self.is_closing()
+            || (position.x.abs() <= self.half_size && position.y.abs() <= self.half_size)
+    }
+}
+
+/// Frames into a round before the zone starts closing, if it ever does
+fn zone_delay_frames(settings: &LobbySettings) -> Option<u32> {
+    let seconds = settings.rule(Rule::ZoneDelay).unwrap_or(0);
+    (seconds > 0).then(|| seconds as u32 * settings.tick_rate)
+}
+
+pub fn update_zone(
+    mut zone: ResMut<ZoneState>,
+    round: Res<RoundState>,
+    settings: Res<LobbySettings>,
+    frame: Res<SimFrame>,
+    mut players: Query<(&Player, &Position, &mut Health, &Invulnerable)>,
+    mut sounds: ResMut<SoundQueue>,
+    mut events: ResMut<GameplayEvents>,
+) {
+    if !round.in_progress() {
+        if zone.round_frames > 0 {
+            *zone = ZoneState::default();
+        }
+        return;
+    }
+    zone.round_frames += 1;
+    let Some(delay) = zone_delay_frames(&settings) else {
+        return;
+    };
+    let Some(closing_frames) = zone.round_frames.checked_sub(delay) else {
+        return;
+    };
+
+    // Integer math from the frame count alone, so every peer and every
+    // resimulation lands on the same size
+    let full = settings.half_map_size_si();
+    let min = ZONE_MIN_HALF_SIZE_SI.min(full);
+    let shrink_frames = (ZONE_SHRINK_SECS * settings.tick_rate).max(1);
+    let shrunk =
+        (full - min) as i64 * closing_frames.min(shrink_frames) as i64 / shrink_frames as i64;
+    zone.half_size = full - shrunk as i32;
+
+    let interval = (settings.tick_rate / ZONE_DAMAGE_PER_SEC).max(1);
+    if closing_frames == 0 || closing_frames % interval != 0 {
+        return;
+    }
+    // Handle order, so sounds queue identically on every peer
+    let mut players = players.iter_mut().collect::<Vec<_>>();
+    players.sort_by_key(|(player, ..)| player.handle);
+    for (player, position, mut health, invulnerable) in players {
+        if health.0 <= 0 || invulnerable.0 > 0 || zone.contains(position.0) {
+            continue;
+        }
+        health.0 = (health.0 - ZONE_DAMAGE).max(0);
+        let sound = if health.0 == 0 {
+            events.push(
+                &frame,
+                GameplayEvent::Death {
+                    victim: player.handle,
+                },
+            );
+            Sound::Death
+        } else {
+            Sound::Hit
+        };
+        sounds.push(&frame, sound, player.handle);
+    }
+}
+
+fn reset_zone(mut zone: ResMut<ZoneState>) {
+    *zone = ZoneState::default();
+}
+
+#[cfg(feature = "presentation")]
+const ZONE_EDGE_WIDTH_RF: f32 = 0.25;
+#[cfg(feature = "presentation")]
+const ZONE_EDGE_COLOR: Color = Color::rgba(1., 0.25, 0.3, 0.8);
+
+/// One side of the zone's square outline, top, bottom, left and right by
+/// index
+#[cfg(feature = "presentation")]
+#[derive(Component)]
+struct ZoneEdge(usize);
+
+#[cfg(feature = "presentation")]
+fn draw_zone_edges(
+    mut commands: Commands,
+    zone: Res<ZoneState>,
+    mut edges: Query<(&ZoneEdge, &mut Transform, &mut Sprite, &mut Visibility)>,
+) {
+    if edges.is_empty() {
+        if zone.is_closing() {
+            for side in 0..4 {
+                commands.spawn((
+                    ZoneEdge(side),
+                    DrawLayer::Zone,
+                    SpriteBundle {
+                        sprite: Sprite {
+                            color: ZONE_EDGE_COLOR,
+                            ..default()
+                        },
+                        ..default()
+                    },
+                ));
+            }
+        }
+        return;
+    }
+    let half = zone.half_size as f32 * I2F;
+    let length = 2. * half + ZONE_EDGE_WIDTH_RF;
+    for (edge, mut transform, mut sprite, mut visibility) in edges.iter_mut() {
+        *visibility = if zone.is_closing() {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+        let (offset, size) = match edge.0 {
+            0 => (Vec2::new(0., half), Vec2::new(length, ZONE_EDGE_WIDTH_RF)),
+            1 => (Vec2::new(0., -half), Vec2::new(length, ZONE_EDGE_WIDTH_RF)),
+            2 => (Vec2::new(-half, 0.), Vec2::new(ZONE_EDGE_WIDTH_RF, length)),
+            _ => (Vec2::new(half, 0.), Vec2::new(ZONE_EDGE_WIDTH_RF, length)),
+        };
+        transform.translation = offset.extend(DrawLayer::Zone.z());
+        sprite.custom_size = Some(size);
+    }
+}
+
+#[cfg(feature = "presentation")]
+fn despawn_zone_edges(mut commands: Commands, edges: Query<Entity, With<ZoneEdge>>) {
+    for entity in edges.iter() {
+        commands.entity(entity).despawn();
+    }
+}